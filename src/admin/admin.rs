@@ -2,10 +2,11 @@ use clap::Parser;
 use conduwuit::Result;
 
 use crate::{
-	appservice, appservice::AppserviceCommand, check, check::CheckCommand, command::Command,
-	debug, debug::DebugCommand, federation, federation::FederationCommand, media,
-	media::MediaCommand, query, query::QueryCommand, room, room::RoomCommand, server,
-	server::ServerCommand, user, user::UserCommand,
+	appservice, appservice::AppserviceCommand, check, check::CheckCommand, command::Command, db,
+	db::DbCommand, debug, debug::DebugCommand, federation, federation::FederationCommand, ip,
+	ip::IpCommand, media, media::MediaCommand, pusher, pusher::PusherCommand, query,
+	query::QueryCommand, room, room::RoomCommand, server, server::ServerCommand, token,
+	token::TokenCommand, user, user::UserCommand,
 };
 
 #[derive(Debug, Parser)]
@@ -19,6 +20,10 @@ pub(super) enum AdminCommand {
 	/// - Commands for managing local users
 	Users(UserCommand),
 
+	#[command(subcommand)]
+	/// - Commands for managing registration tokens
+	Tokens(TokenCommand),
+
 	#[command(subcommand)]
 	/// - Commands for managing rooms
 	Rooms(RoomCommand),
@@ -31,10 +36,22 @@ pub(super) enum AdminCommand {
 	/// - Commands for managing the server
 	Server(ServerCommand),
 
+	#[command(subcommand)]
+	/// - Commands for banning IP addresses
+	Ip(IpCommand),
+
 	#[command(subcommand)]
 	/// - Commands for managing media
 	Media(MediaCommand),
 
+	#[command(subcommand)]
+	/// - Commands for inspecting pushers
+	Pushers(PusherCommand),
+
+	#[command(subcommand)]
+	/// - Commands for managing the database
+	Db(DbCommand),
+
 	#[command(subcommand)]
 	/// - Commands for checking integrity
 	Check(CheckCommand),
@@ -55,10 +72,14 @@ pub(super) async fn process(command: AdminCommand, context: &Command<'_>) -> Res
 	match command {
 		| Appservices(command) => appservice::process(command, context).await?,
 		| Media(command) => media::process(command, context).await?,
+		| Pushers(command) => pusher::process(command, context).await?,
+		| Db(command) => db::process(command, context).await?,
 		| Users(command) => user::process(command, context).await?,
+		| Tokens(command) => token::process(command, context).await?,
 		| Rooms(command) => room::process(command, context).await?,
 		| Federation(command) => federation::process(command, context).await?,
 		| Server(command) => server::process(command, context).await?,
+		| Ip(command) => ip::process(command, context).await?,
 		| Debug(command) => debug::process(command, context).await?,
 		| Query(command) => query::process(command, context).await?,
 		| Check(command) => check::process(command, context).await?,
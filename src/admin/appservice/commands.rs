@@ -1,9 +1,22 @@
-use ruma::{api::appservice::Registration, events::room::message::RoomMessageEventContent};
+use std::{
+	fmt::Write,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use api::client::{full_user_deactivate, leave_all_rooms, update_avatar_url, update_displayname};
+use conduwuit::{info, utils::time};
+use futures::StreamExt;
+use ruma::{
+	api::appservice::{Namespace, Registration},
+	events::room::message::RoomMessageEventContent,
+	OwnedRoomId, UserId,
+};
+use service::{sending::Destination, Services};
 
 use crate::{admin_command, Result};
 
 #[admin_command]
-pub(super) async fn register(&self) -> Result<RoomMessageEventContent> {
+pub(super) async fn register(&self, force: bool) -> Result<RoomMessageEventContent> {
 	if self.body.len() < 2
 		|| !self.body[0].trim().starts_with("```")
 		|| self.body.last().unwrap_or(&"").trim() != "```"
@@ -15,23 +28,52 @@ pub(super) async fn register(&self) -> Result<RoomMessageEventContent> {
 
 	let appservice_config_body = self.body[1..self.body.len().checked_sub(1).unwrap()].join("\n");
 	let parsed_config = serde_yaml::from_str::<Registration>(&appservice_config_body);
-	match parsed_config {
-		| Ok(registration) => match self
-			.services
-			.appservice
-			.register_appservice(&registration, &appservice_config_body)
-			.await
-		{
-			| Ok(()) => Ok(RoomMessageEventContent::text_plain(format!(
-				"Appservice registered with ID: {}",
-				registration.id
+	let registration = match parsed_config {
+		| Ok(registration) => registration,
+		| Err(e) =>
+			return Ok(RoomMessageEventContent::text_plain(format!(
+				"Could not parse appservice config as YAML: {e}"
 			))),
-			| Err(e) => Ok(RoomMessageEventContent::text_plain(format!(
-				"Failed to register appservice: {e}"
+	};
+
+	let report = match self
+		.services
+		.appservice
+		.check_namespaces(&registration)
+		.await
+	{
+		| Ok(report) => report,
+		| Err(e) =>
+			return Ok(RoomMessageEventContent::text_plain(format!(
+				"Invalid appservice registration: {e}"
 			))),
-		},
+	};
+
+	if !report.conflicts.is_empty() && !force {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Refusing to register appservice {}, its namespaces conflict with other \
+			 appservices. Fix the registration, or pass --force to register it \
+			 anyway.\n\n{report}",
+			registration.id
+		)));
+	}
+
+	match self
+		.services
+		.appservice
+		.register_appservice(&registration, &appservice_config_body)
+		.await
+	{
+		| Ok(()) if report.is_empty() => Ok(RoomMessageEventContent::text_plain(format!(
+			"Appservice registered with ID: {}",
+			registration.id
+		))),
+		| Ok(()) => Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Appservice registered with ID: {}\n\n{report}",
+			registration.id
+		))),
 		| Err(e) => Ok(RoomMessageEventContent::text_plain(format!(
-			"Could not parse appservice config as YAML: {e}"
+			"Failed to register appservice: {e}"
 		))),
 	}
 }
@@ -82,3 +124,299 @@ pub(super) async fn list_registered(&self) -> Result<RoomMessageEventContent> {
 	let output = format!("Appservices ({}): {}", appservices.len(), appservices.join(", "));
 	Ok(RoomMessageEventContent::text_plain(output))
 }
+
+#[admin_command]
+pub(super) async fn reload(&self) -> Result<RoomMessageEventContent> {
+	let summary = self.services.appservice.reload().await?;
+	if summary.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Reloaded appservice registrations, nothing changed.",
+		));
+	}
+
+	let list = |ids: &[String]| {
+		if ids.is_empty() {
+			"none".to_owned()
+		} else {
+			ids.join(", ")
+		}
+	};
+	let output = format!(
+		"Reloaded appservice registrations.\n\nAdded: {}\nRemoved: {}\nChanged: {}",
+		list(&summary.added),
+		list(&summary.removed),
+		list(&summary.changed),
+	);
+
+	Ok(RoomMessageEventContent::text_plain(output))
+}
+
+#[admin_command]
+pub(super) async fn queue(
+	&self,
+	appservice_identifier: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let ids = match appservice_identifier {
+		| Some(id) => vec![id],
+		| None => self.services.appservice.iter_ids().await,
+	};
+
+	if ids.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No appservices are registered."));
+	}
+
+	let mut msg = String::from("Appservice transaction queues:\n");
+	for id in ids {
+		let dest = Destination::Appservice(id.clone());
+		let sending = &self.services.sending;
+		let queued = sending.db.queued_requests(&dest).count().await;
+		let in_flight = sending.db.active_requests_for(&dest).count().await;
+
+		write!(msg, "- {id}: {queued} queued, {in_flight} in the current transaction")?;
+		match sending.db.get_appservice_failures(&id).await {
+			| Some(failures) => {
+				let last_failure = UNIX_EPOCH
+					.checked_add(Duration::from_millis(failures.last_failure))
+					.unwrap_or(UNIX_EPOCH);
+
+				writeln!(
+					msg,
+					", {} failed in a row, last at {}: {}",
+					failures.tries,
+					time::format(last_failure, "%+"),
+					failures.last_error,
+				)?;
+			},
+			| None => writeln!(msg)?,
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn status(
+	&self,
+	appservice_identifier: String,
+) -> Result<RoomMessageEventContent> {
+	let Some(info) = self
+		.services
+		.appservice
+		.get_registration_info(&appservice_identifier)
+		.await
+	else {
+		return Ok(RoomMessageEventContent::text_plain("Appservice does not exist."));
+	};
+
+	let id = &appservice_identifier;
+	let format_millis = |millis: u64| {
+		let at = UNIX_EPOCH
+			.checked_add(Duration::from_millis(millis))
+			.unwrap_or(UNIX_EPOCH);
+
+		time::format(at, "%+")
+	};
+
+	let sending = &self.services.sending;
+	let dest = Destination::Appservice(id.clone());
+	let queued = sending.db.queued_requests(&dest).count().await;
+	let in_flight = sending.db.active_requests_for(&dest).count().await;
+	let last_success = sending
+		.db
+		.get_appservice_last_success(id)
+		.await
+		.map_or_else(|| "never".to_owned(), format_millis);
+
+	let registration = &info.registration;
+	let mut msg = format!("Appservice {id}:\n\n");
+	writeln!(msg, "URL: {}", registration.url.as_deref().unwrap_or("none"))?;
+	writeln!(msg, "Last successful transaction: {last_success}")?;
+	writeln!(msg, "Pending events: {queued} queued, {in_flight} in the current transaction")?;
+	match sending.db.get_appservice_failures(id).await {
+		| Some(failures) => writeln!(
+			msg,
+			"Consecutive failures: {}, last at {}: {}",
+			failures.tries,
+			format_millis(failures.last_failure),
+			failures.last_error,
+		)?,
+		| None => writeln!(msg, "Consecutive failures: 0")?,
+	}
+
+	let namespaces = [
+		("Users", &registration.namespaces.users),
+		("Aliases", &registration.namespaces.aliases),
+		("Rooms", &registration.namespaces.rooms),
+	];
+
+	writeln!(msg, "\nNamespaces:")?;
+	for (kind, namespaces) in namespaces {
+		write!(msg, "- {kind}:")?;
+		if namespaces.is_empty() {
+			writeln!(msg, " none")?;
+			continue;
+		}
+
+		writeln!(msg)?;
+		for Namespace { exclusive, regex, .. } in namespaces {
+			let exclusive = if *exclusive { " (exclusive)" } else { "" };
+			writeln!(msg, "  - `{regex}`{exclusive}")?;
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn list_users(
+	&self,
+	appservice_identifier: String,
+) -> Result<RoomMessageEventContent> {
+	let users = self
+		.services
+		.appservice
+		.exclusive_users(&appservice_identifier)
+		.await?;
+
+	let mut msg =
+		format!("Users of appservice {appservice_identifier} ({}):\n```\n", users.len());
+	for user_id in &users {
+		let deactivated = self
+			.services
+			.users
+			.is_deactivated(user_id)
+			.await
+			.unwrap_or(false);
+
+		let suffix = if deactivated { " (deactivated)" } else { "" };
+		writeln!(msg, "{user_id}{suffix}")?;
+	}
+	msg.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn deactivate_users(
+	&self,
+	appservice_identifier: String,
+	no_leave_rooms: bool,
+) -> Result<RoomMessageEventContent> {
+	let users = self
+		.services
+		.appservice
+		.exclusive_users(&appservice_identifier)
+		.await?;
+
+	self.services
+		.admin
+		.send_message(RoomMessageEventContent::text_plain(format!(
+			"Deactivating {} users of appservice {appservice_identifier}...",
+			users.len()
+		)))
+		.await
+		.ok();
+
+	let mut deactivated: usize = 0;
+	for user_id in &users {
+		match deactivate_user(self.services, user_id, !no_leave_rooms).await {
+			| Ok(true) => deactivated = deactivated.saturating_add(1),
+			| Ok(false) => {},
+			| Err(e) => {
+				self.services
+					.admin
+					.send_message(RoomMessageEventContent::text_plain(format!(
+						"Failed deactivating {user_id}: {e}"
+					)))
+					.await
+					.ok();
+			},
+		}
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Deactivated {deactivated} users of appservice {appservice_identifier}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn purge_users(
+	&self,
+	appservice_identifier: String,
+) -> Result<RoomMessageEventContent> {
+	let users = self
+		.services
+		.appservice
+		.exclusive_users(&appservice_identifier)
+		.await?;
+
+	self.services
+		.admin
+		.send_message(RoomMessageEventContent::text_plain(format!(
+			"Purging {} users of appservice {appservice_identifier}...",
+			users.len()
+		)))
+		.await
+		.ok();
+
+	let mut deactivated: usize = 0;
+	let mut deleted_media: usize = 0;
+	for user_id in &users {
+		let result = async {
+			if deactivate_user(self.services, user_id, true).await? {
+				deactivated = deactivated.saturating_add(1);
+			}
+
+			let deleted = self.services.media.delete_from_user(user_id).await?;
+			deleted_media = deleted_media.saturating_add(deleted);
+
+			Result::<()>::Ok(())
+		};
+
+		if let Err(e) = result.await {
+			self.services
+				.admin
+				.send_message(RoomMessageEventContent::text_plain(format!(
+					"Failed purging {user_id}: {e}"
+				)))
+				.await
+				.ok();
+		}
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Deactivated {deactivated} users of appservice {appservice_identifier} and deleted \
+		 {deleted_media} of their files."
+	)))
+}
+
+/// Deactivates the user unless already deactivated, and makes them leave all
+/// their rooms. Returns whether the user was deactivated now.
+async fn deactivate_user(
+	services: &Services,
+	user_id: &UserId,
+	leave_rooms: bool,
+) -> Result<bool> {
+	if services.users.is_deactivated(user_id).await? {
+		return Ok(false);
+	}
+
+	services.users.deactivate_account(user_id).await?;
+	if leave_rooms {
+		info!("Forcing appservice user {user_id} to leave all rooms");
+		let all_joined_rooms: Vec<OwnedRoomId> = services
+			.rooms
+			.state_cache
+			.rooms_joined(user_id)
+			.map(Into::into)
+			.collect()
+			.await;
+
+		full_user_deactivate(services, user_id, &all_joined_rooms, false).await?;
+		update_displayname(services, user_id, None, &all_joined_rooms).await;
+		update_avatar_url(services, user_id, None, None, &all_joined_rooms).await;
+		leave_all_rooms(services, user_id).await;
+	}
+
+	Ok(true)
+}
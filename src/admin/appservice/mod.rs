@@ -15,7 +15,15 @@ pub(super) enum AppserviceCommand {
 	///
 	/// Registering a new bridge using the ID of an existing bridge will replace
 	/// the old one.
-	Register,
+	///
+	/// The namespaces are checked against the other appservices and the
+	/// existing local users and aliases first. Registrations which conflict
+	/// with another appservice are refused unless forced.
+	Register {
+		/// Register even when the namespaces conflict with another appservice
+		#[arg(long)]
+		force: bool,
+	},
 
 	/// - Unregister an appservice using its ID
 	///
@@ -37,4 +45,59 @@ pub(super) enum AppserviceCommand {
 	/// - List all the currently registered appservices
 	#[clap(alias("list"))]
 	ListRegistered,
+
+	/// - Show the transaction queue of each appservice
+	///
+	/// Lists how many events are waiting to be sent to each appservice, how
+	/// many are in the transaction being sent, and its failures in a row.
+	Queue {
+		/// Only show this appservice
+		appservice_identifier: Option<String>,
+	},
+
+	/// - Show the health of an appservice
+	///
+	/// Shows when a transaction was last delivered to it, how many events are
+	/// waiting to be sent, its failures in a row with the last error, and the
+	/// namespaces it registered.
+	Status {
+		/// The appservice to show
+		appservice_identifier: String,
+	},
+
+	/// - List the local users in an appservice's exclusive namespace
+	ListUsers {
+		/// The appservice whose users to list
+		appservice_identifier: String,
+	},
+
+	/// - Deactivate all the users in an appservice's exclusive namespace
+	///
+	/// Useful after decommissioning a bridge. Users will be removed from all
+	/// rooms by default. Use --no-leave-rooms to not leave all rooms.
+	DeactivateUsers {
+		/// The appservice whose users to deactivate
+		appservice_identifier: String,
+
+		/// Does not leave any rooms the users are in on deactivation
+		#[arg(short, long)]
+		no_leave_rooms: bool,
+	},
+
+	/// - Deactivate all the users in an appservice's exclusive namespace and
+	///   delete their media
+	///
+	/// The users leave all rooms, and every file they uploaded is deleted.
+	/// Users which are already deactivated only have their media deleted.
+	PurgeUsers {
+		/// The appservice whose users to purge
+		appservice_identifier: String,
+	},
+
+	/// - Reload the appservice registrations
+	///
+	/// Re-reads the registrations in the database and in the configured
+	/// `appservice_registration_dir`, applying added, removed and changed
+	/// registrations without a restart.
+	Reload,
 }
@@ -0,0 +1,73 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Subcommand;
+use conduwuit::Result;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum DbBackupCommand {
+	/// - Create a backup of the database while the server is running
+	///
+	/// Backups beyond `database_backups_to_keep` are removed, oldest first.
+	Create,
+
+	/// - List the database backups
+	List,
+
+	/// - Restore a backup into an empty directory
+	///
+	/// The live database is not touched. Stop the server and move the
+	/// restored directory to `database_path` to use it.
+	Restore {
+		/// ID of the backup, as shown by `db backup list`
+		backup_id: u32,
+
+		/// Empty directory to restore the database into
+		target: PathBuf,
+	},
+}
+
+#[admin_command]
+async fn create(&self) -> Result<RoomMessageEventContent> {
+	let globals = Arc::clone(&self.services.globals);
+	self.services
+		.server
+		.runtime()
+		.spawn_blocking(move || globals.db.backup())
+		.await??;
+
+	let result = self.services.globals.db.backup_list()?;
+
+	Ok(RoomMessageEventContent::notice_markdown(result))
+}
+
+#[admin_command]
+async fn list(&self) -> Result<RoomMessageEventContent> {
+	let result = self.services.globals.db.backup_list()?;
+
+	if result.is_empty() {
+		Ok(RoomMessageEventContent::text_plain("No backups found."))
+	} else {
+		Ok(RoomMessageEventContent::text_plain(result))
+	}
+}
+
+#[admin_command]
+async fn restore(&self, backup_id: u32, target: PathBuf) -> Result<RoomMessageEventContent> {
+	let globals = Arc::clone(&self.services.globals);
+	let path = target.clone();
+	self.services
+		.server
+		.runtime()
+		.spawn_blocking(move || globals.db.backup_restore(backup_id, &path))
+		.await??;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Restored backup #{backup_id} to `{}`. Stop the server and move it to `database_path` \
+		 to use it.",
+		target.display()
+	)))
+}
@@ -0,0 +1,38 @@
+use std::fmt::Write;
+
+use conduwuit::{utils::bytes::pretty, Result};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn recompress_state(
+	&self,
+	room_id: Option<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	let state_compressor = &self.services.rooms.state_compressor;
+	let rooms = match room_id {
+		| Some(room_id) => {
+			let recompression = state_compressor.recompress_room(&room_id).await?;
+			vec![(room_id, recompression)]
+		},
+		| None => state_compressor.recompress_stale_rooms().await?,
+	};
+
+	if rooms.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No rooms needed recompressing."));
+	}
+
+	let mut msg = format!("Recompressed the state of {} room(s):\n", rooms.len());
+	for (room_id, recompression) in &rooms {
+		writeln!(
+			msg,
+			"- {room_id}: {} states, {} -> {}",
+			recompression.states,
+			pretty(recompression.bytes_before),
+			pretty(recompression.bytes_after),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
@@ -0,0 +1,47 @@
+mod backup;
+mod commands;
+mod stats;
+
+use clap::Subcommand;
+use conduwuit::Result;
+use ruma::OwnedRoomId;
+
+use self::backup::DbBackupCommand;
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum DbCommand {
+	#[command(subcommand)]
+	/// - Manage online database backups in `database_backup_path`
+	Backup(DbBackupCommand),
+
+	/// - Rebuild the state compression layers of a room, or of every room whose
+	///   layers have grown deep
+	///
+	/// This reclaims space and speeds up loading the state of old, busy
+	/// rooms. It can also be run on `state_recompression_schedule`.
+	RecompressState {
+		/// Room to recompress even if its layers are not deep
+		room_id: Option<OwnedRoomId>,
+	},
+
+	/// - Show where the database's disk space goes
+	///
+	/// Without flags only the totals are shown. Room sizes are estimated from
+	/// their events, the state snapshots those events point at and the media
+	/// they refer to, and can take a while to compute.
+	Stats {
+		/// Show each column's on-disk size and estimated number of keys
+		#[arg(long)]
+		by_column: bool,
+
+		/// Show the rooms taking up the most space
+		#[arg(long)]
+		by_room: bool,
+
+		/// How many rooms to show with `--by-room`
+		#[arg(long, default_value("20"))]
+		limit: usize,
+	},
+}
@@ -0,0 +1,175 @@
+use std::{cmp::Reverse, collections::HashSet, ffi::CStr, fmt::Write};
+
+use conduwuit::{
+	utils::{bytes::pretty, stream::TryIgnore},
+	PduEvent, Result,
+};
+use futures::StreamExt;
+use ruma::{events::room::message::RoomMessageEventContent, Mxc, OwnedRoomId, RoomId};
+use service::{media::content_mxcs, Services};
+
+use crate::admin_command;
+
+/// Estimated storage taken up by a room.
+#[derive(Default)]
+struct RoomStorage {
+	events: usize,
+	event_bytes: usize,
+	states: usize,
+	state_bytes: usize,
+	media: usize,
+	media_bytes: usize,
+}
+
+impl RoomStorage {
+	fn total(&self) -> usize {
+		self.event_bytes
+			.saturating_add(self.state_bytes)
+			.saturating_add(self.media_bytes)
+	}
+}
+
+#[admin_command]
+pub(super) async fn stats(
+	&self,
+	by_column: bool,
+	by_room: bool,
+	limit: usize,
+) -> Result<RoomMessageEventContent> {
+	let mut columns: Vec<(&str, u64, u64)> = self
+		.services
+		.db
+		.iter()
+		.map(|(&name, map)| {
+			let property = |name: &CStr| map.property_integer(name).unwrap_or(0);
+			let size = property(c"rocksdb.total-sst-files-size");
+			let keys = property(c"rocksdb.estimate-num-keys");
+			(name, size, keys)
+		})
+		.collect();
+
+	columns.sort_by_key(|&(name, size, _)| (Reverse(size), name));
+
+	let (size, keys) = columns
+		.iter()
+		.fold((0_u64, 0_u64), |(size, keys), (_, s, k)| {
+			(size.saturating_add(*s), keys.saturating_add(*k))
+		});
+
+	let mut out = format!(
+		"Database: {} on disk in {} columns, about {keys} keys\n",
+		pretty(size.try_into()?),
+		columns.len()
+	);
+
+	if by_column {
+		writeln!(out, "\n| Column | Size | Keys |\n| --- | ---: | ---: |")?;
+		for (name, size, keys) in &columns {
+			writeln!(out, "| {name} | {} | {keys} |", pretty((*size).try_into()?))?;
+		}
+	}
+
+	if by_room {
+		let room_ids: Vec<OwnedRoomId> = self
+			.services
+			.rooms
+			.metadata
+			.iter_ids()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let mut rooms = Vec::with_capacity(room_ids.len());
+		for room_id in room_ids {
+			let storage = room_storage(self.services, &room_id).await?;
+			rooms.push((room_id, storage));
+		}
+
+		rooms.sort_by_key(|(_, storage)| Reverse(storage.total()));
+
+		writeln!(
+			out,
+			"\n{} largest of {} rooms (estimated):\n\n| Room | Total | Events | State | Media \
+			 |\n| --- | ---: | ---: | ---: | ---: |",
+			limit.min(rooms.len()),
+			rooms.len(),
+		)?;
+
+		for (room_id, storage) in rooms.iter().take(limit) {
+			writeln!(
+				out,
+				"| {room_id} | {} | {} ({}) | {} ({}) | {} ({}) |",
+				pretty(storage.total()),
+				pretty(storage.event_bytes),
+				storage.events,
+				pretty(storage.state_bytes),
+				storage.states,
+				pretty(storage.media_bytes),
+				storage.media,
+			)?;
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+/// Adds up the room's timeline events, the state snapshots they point at and
+/// the files they refer to. Snapshots and files shared with other rooms are
+/// counted for each of them.
+async fn room_storage(services: &Services, room_id: &RoomId) -> Result<RoomStorage> {
+	let mut storage = RoomStorage::default();
+	let Ok(shortroomid) = services.rooms.short.get_shortroomid(room_id).await else {
+		return Ok(storage);
+	};
+
+	let pduid_pdu = services.db.get("pduid_pdu")?;
+	let statediff = services.db.get("shortstatehash_statediff")?;
+	let prefix = shortroomid.to_be_bytes();
+
+	let mut events = pduid_pdu.raw_stream_prefix(&prefix).ignore_err().boxed();
+	let mut states = HashSet::new();
+	let mut mxcs = HashSet::new();
+	while let Some((key, value)) = events.next().await {
+		storage.events = storage.events.saturating_add(1);
+		storage.event_bytes = storage
+			.event_bytes
+			.saturating_add(key.len())
+			.saturating_add(value.len());
+
+		let Ok(pdu) = serde_json::from_slice::<PduEvent>(value) else {
+			continue;
+		};
+
+		mxcs.extend(content_mxcs(&pdu.get_content_as_value()));
+		if let Ok(shortstatehash) = services
+			.rooms
+			.state_accessor
+			.pdu_shortstatehash(&pdu.event_id)
+			.await
+		{
+			states.insert(shortstatehash);
+		}
+	}
+
+	for shortstatehash in &states {
+		if let Ok(diff) = statediff.get(&shortstatehash.to_be_bytes()).await {
+			storage.states = storage.states.saturating_add(1);
+			storage.state_bytes = storage.state_bytes.saturating_add(diff.len());
+		}
+	}
+
+	for mxc in &mxcs {
+		let Ok(mxc) = Mxc::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		if let Ok(stat) = services.media.get_file_stat(&mxc).await {
+			storage.media = storage.media.saturating_add(1);
+			storage.media_bytes = storage
+				.media_bytes
+				.saturating_add(stat.len().try_into().unwrap_or(usize::MAX));
+		}
+	}
+
+	Ok(storage)
+}
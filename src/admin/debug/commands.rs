@@ -13,8 +13,8 @@ use futures::{FutureExt, StreamExt, TryStreamExt};
 use ruma::{
 	api::{client::error::ErrorKind, federation::event::get_room_state},
 	events::room::message::RoomMessageEventContent,
-	CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomOrAliasId, RoomId, RoomVersionId,
-	ServerName,
+	CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, RoomId,
+	RoomVersionId, ServerName,
 };
 use service::rooms::{
 	short::{ShortEventId, ShortRoomId},
@@ -64,6 +64,25 @@ pub(super) async fn get_auth_chain(
 	)))
 }
 
+#[admin_command]
+pub(super) async fn rebuild_auth_chains(
+	&self,
+	room_id: Option<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	let start = Instant::now();
+	let count = self
+		.services
+		.rooms
+		.auth_chain
+		.rebuild(room_id.as_deref())
+		.await?;
+
+	let elapsed = start.elapsed();
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Rebuilt the auth chains of {count} events in {elapsed:?}"
+	)))
+}
+
 #[admin_command]
 pub(super) async fn parse_pdu(&self) -> Result<RoomMessageEventContent> {
 	if self.body.len() < 2
@@ -713,6 +732,7 @@ pub(super) async fn force_set_room_state_from_server(
 		.await?;
 
 	info!("Forcing new room state");
+	let state_lock = self.services.rooms.state.mutex.lock(&*room_id).await;
 	let HashSetCompressStateEvent {
 		shortstatehash: short_state_hash,
 		added,
@@ -721,10 +741,9 @@ pub(super) async fn force_set_room_state_from_server(
 		.services
 		.rooms
 		.state_compressor
-		.save_state(room_id.clone().as_ref(), new_room_state)
+		.save_state(room_id.clone().as_ref(), new_room_state, &state_lock)
 		.await?;
 
-	let state_lock = self.services.rooms.state.mutex.lock(&*room_id).await;
 	self.services
 		.rooms
 		.state
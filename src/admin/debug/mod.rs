@@ -3,7 +3,7 @@ pub(crate) mod tester;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{EventId, OwnedRoomOrAliasId, RoomId, ServerName};
+use ruma::{EventId, OwnedRoomId, OwnedRoomOrAliasId, RoomId, ServerName};
 use service::rooms::short::{ShortEventId, ShortRoomId};
 
 use self::tester::TesterCommand;
@@ -23,6 +23,12 @@ pub(super) enum DebugCommand {
 		event_id: Box<EventId>,
 	},
 
+	/// - Recompute the stored auth chains of a room's events, or of every event
+	///   after discarding all stored auth chains
+	RebuildAuthChains {
+		room_id: Option<OwnedRoomId>,
+	},
+
 	/// - Parse and print a PDU from a JSON
 	///
 	/// The PDU event is only checked for validity and is not added to the
@@ -1,9 +1,14 @@
-use std::fmt::Write;
+use std::{
+	fmt::Write,
+	sync::atomic::Ordering,
+	time::{Duration, SystemTime},
+};
 
-use conduwuit::Result;
+use conduwuit::{info, utils, utils::IterStream, Result};
 use futures::StreamExt;
 use ruma::{
-	events::room::message::RoomMessageEventContent, OwnedRoomId, RoomId, ServerName, UserId,
+	events::{room::message::RoomMessageEventContent, StateEventType},
+	OwnedRoomId, OwnedRoomOrAliasId, RoomId, ServerName, UserId,
 };
 
 use crate::{admin_command, get_room_info};
@@ -20,6 +25,98 @@ pub(super) async fn enable_room(&self, room_id: Box<RoomId>) -> Result<RoomMessa
 	Ok(RoomMessageEventContent::text_plain("Room enabled."))
 }
 
+#[admin_command]
+pub(super) async fn unfederate_room(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+
+	let Ok(create_event) = self
+		.services
+		.rooms
+		.state_accessor
+		.room_state_get(&room_id, &StateEventType::RoomCreate, "")
+		.await
+	else {
+		return Ok(RoomMessageEventContent::text_plain("Room does not exist on this server."));
+	};
+
+	if !self.services.globals.user_is_local(&create_event.sender) {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Only rooms created on this server can stop federating.",
+		));
+	}
+
+	self.services.rooms.metadata.unfederate_room(&room_id, true);
+
+	info!("Stopped federation of room {room_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Room {room_id} no longer federates. Other servers keep the events they already have."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn refederate_room(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+
+	self.services
+		.rooms
+		.metadata
+		.unfederate_room(&room_id, false);
+
+	Ok(RoomMessageEventContent::text_plain(format!("Room {room_id} federates again.")))
+}
+
+#[admin_command]
+pub(super) async fn list_unfederated_rooms(
+	&self,
+	no_details: bool,
+) -> Result<RoomMessageEventContent> {
+	let room_ids: Vec<OwnedRoomId> = self
+		.services
+		.rooms
+		.metadata
+		.list_unfederated_rooms()
+		.map(Into::into)
+		.collect()
+		.await;
+
+	if room_ids.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No rooms have stopped federating."));
+	}
+
+	let mut rooms = room_ids
+		.iter()
+		.stream()
+		.then(|room_id| get_room_info(self.services, room_id))
+		.collect::<Vec<_>>()
+		.await;
+
+	rooms.sort_by_key(|r| r.1);
+	rooms.reverse();
+
+	let output_plain = format!(
+		"Rooms Not Federating ({}):\n```\n{}\n```",
+		rooms.len(),
+		rooms
+			.iter()
+			.map(|(id, members, name)| if no_details {
+				format!("{id}")
+			} else {
+				format!("{id}\tMembers: {members}\tName: {name}")
+			})
+			.collect::<Vec<_>>()
+			.join("\n")
+	);
+
+	Ok(RoomMessageEventContent::notice_markdown(output_plain))
+}
+
 #[admin_command]
 pub(super) async fn incoming_federation(&self) -> Result<RoomMessageEventContent> {
 	let map = self
@@ -133,3 +230,90 @@ pub(super) async fn remote_user_in_rooms(
 
 	Ok(RoomMessageEventContent::text_markdown(output))
 }
+
+#[admin_command]
+pub(super) async fn destination_stats(
+	&self,
+	server_name: Option<Box<ServerName>>,
+) -> Result<RoomMessageEventContent> {
+	let mut destinations = self.services.federation.destinations();
+	destinations.retain(|(dest, _)| {
+		server_name
+			.as_deref()
+			.is_none_or(|server| **dest == *server)
+	});
+	destinations.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+	if destinations.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No requests made to these servers."));
+	}
+
+	let now = utils::time::now_millis();
+	let mut msg = String::from(
+		"| Server | In flight | Queued | Max | Requests | Failures | Last request |\n| --- | \
+		 --- | --- | --- | --- | --- | --- |\n",
+	);
+
+	for (dest, destination) in destinations {
+		let last = now.saturating_sub(destination.last_request.load(Ordering::Relaxed));
+		let max = destination
+			.max_connections
+			.map_or_else(|| "-".to_owned(), |max| max.to_string());
+
+		writeln!(
+			msg,
+			"| {dest} | {} | {} | {max} | {} | {} | {} ago |",
+			destination.in_flight.load(Ordering::Relaxed),
+			destination.queued.load(Ordering::Relaxed),
+			destination.requests.load(Ordering::Relaxed),
+			destination.failures.load(Ordering::Relaxed),
+			utils::time::pretty(Duration::from_millis(last)),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::text_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn health(
+	&self,
+	server_name: Option<Box<ServerName>>,
+	degraded: bool,
+) -> Result<RoomMessageEventContent> {
+	let mut health = self.services.sending.federation_health();
+	health.retain(|(dest, health)| {
+		server_name
+			.as_deref()
+			.is_none_or(|server| **dest == *server)
+			&& (!degraded || health.consecutive_failures > 0)
+	});
+
+	if health.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No transactions sent to these servers."));
+	}
+
+	let ago = |time: Option<SystemTime>| {
+		time.and_then(|time| time.elapsed().ok())
+			.map_or_else(|| "never".to_owned(), |ago| format!("{} ago", utils::time::pretty(ago)))
+	};
+
+	let mut msg = String::from(
+		"| Server | Transactions | Success | Median latency | Consecutive failures | Last \
+		 success | Last failure |\n| --- | --- | --- | --- | --- | --- | --- |\n",
+	);
+
+	for (dest, health) in health {
+		writeln!(
+			msg,
+			"| {dest} | {} | {:.1}% | {:?} | {} | {} | {} |",
+			health.transactions,
+			health.success_rate * 100.0,
+			health.median_latency,
+			health.consecutive_failures,
+			ago(health.last_success),
+			ago(health.last_failure),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::text_markdown(msg))
+}
@@ -2,7 +2,7 @@ mod commands;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{RoomId, ServerName, UserId};
+use ruma::{OwnedRoomOrAliasId, RoomId, ServerName, UserId};
 
 use crate::admin_command_dispatch;
 
@@ -22,6 +22,29 @@ pub(super) enum FederationCommand {
 		room_id: Box<RoomId>,
 	},
 
+	/// - Stops a room created on this server from federating from now on
+	///
+	/// Its events are no longer sent to other servers, which are refused any
+	/// federation requests for it, and it is left out of the room directory
+	/// other servers see. For rooms that should have been created with
+	/// `m.federate: false`; other servers keep what they already have.
+	UnfederateRoom {
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - Lets a room cut off with `unfederate-room` federate again
+	RefederateRoom {
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - List the rooms cut off with `unfederate-room`
+	ListUnfederatedRooms {
+		#[arg(long)]
+		/// Whether to only output room IDs without supplementary room
+		/// information
+		no_details: bool,
+	},
+
 	/// - Fetch `/.well-known/matrix/support` from the specified server
 	///
 	/// Despite the name, this is not a federation endpoint and does not go
@@ -39,4 +62,28 @@ pub(super) enum FederationCommand {
 	RemoteUserInRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - Show outgoing request statistics per remote server
+	///
+	/// Lists requests in flight, waiting on a configured "max_connections",
+	/// completed and failed, along with when the server was last contacted.
+	/// reqwest does not expose the state of its connection pools, so open and
+	/// idle connections can't be shown.
+	DestinationStats {
+		/// Only show this server
+		server_name: Option<Box<ServerName>>,
+	},
+
+	/// - Show the health of transactions sent to each remote server
+	///
+	/// Success rate and median latency cover the most recent transactions.
+	/// Servers with the most consecutive failures are listed first.
+	Health {
+		/// Only show this server
+		server_name: Option<Box<ServerName>>,
+
+		/// Only show servers whose last transaction failed
+		#[arg(long)]
+		degraded: bool,
+	},
 }
@@ -0,0 +1,67 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use conduwuit::{
+	utils::{time, time::parse_duration},
+	Result,
+};
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn ban(
+	&self,
+	cidr: String,
+	duration: Option<String>,
+	reason: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let duration = duration.as_deref().map(parse_duration).transpose()?;
+	self.services.ipban.ban(&cidr, duration, reason, false)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(match duration {
+		| Some(duration) => format!("Banned `{cidr}` for {}.", time::pretty(duration)),
+		| None => format!("Banned `{cidr}` until unbanned."),
+	}))
+}
+
+#[admin_command]
+pub(super) async fn unban(&self, cidr: String) -> Result<RoomMessageEventContent> {
+	if !self.services.ipban.unban(&cidr) {
+		return Ok(RoomMessageEventContent::notice_markdown(format!("`{cidr}` is not banned.")));
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("Unbanned `{cidr}`.")))
+}
+
+#[admin_command]
+pub(super) async fn list(&self) -> Result<RoomMessageEventContent> {
+	let bans = self.services.ipban.list();
+	if bans.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No IP addresses are banned."));
+	}
+
+	let bans: Vec<_> = bans
+		.into_iter()
+		.map(|(cidr, ban)| {
+			let expiry = ban.expires_at.map_or_else(
+				|| "never".to_owned(),
+				|expiry| {
+					UNIX_EPOCH
+						.checked_add(Duration::from_millis(expiry))
+						.map_or_else(String::new, |expiry| time::format(expiry, "%+"))
+				},
+			);
+
+			let automatic = if ban.automatic { " (automatic)" } else { "" };
+			let reason = ban.reason.as_deref().unwrap_or("no reason given");
+
+			format!("{cidr}{automatic}: {reason}, expires: {expiry}")
+		})
+		.collect();
+
+	let mut plain_msg = format!("Found {} IP ban(s):\n```\n", bans.len());
+	plain_msg += bans.join("\n").as_str();
+	plain_msg += "\n```";
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
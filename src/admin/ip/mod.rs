@@ -0,0 +1,34 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum IpCommand {
+	/// - Ban an IP address or CIDR range from client endpoints
+	Ban {
+		/// The IP address or CIDR range, e.g. 192.0.2.1 or 2001:db8::/32
+		cidr: String,
+
+		/// - The relative time (e.g. 30m, 1d) after which the ban expires;
+		///   permanent if unspecified
+		#[arg(long, short)]
+		duration: Option<String>,
+
+		/// Why the range is banned
+		#[arg(long, short)]
+		reason: Option<String>,
+	},
+
+	/// - Lift the ban of an IP address or CIDR range, as given when banned
+	Unban {
+		/// The IP address or CIDR range
+		cidr: String,
+	},
+
+	/// - List banned IP addresses and CIDR ranges
+	List,
+}
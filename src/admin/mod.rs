@@ -11,12 +11,16 @@ pub(crate) mod utils;
 
 pub(crate) mod appservice;
 pub(crate) mod check;
+pub(crate) mod db;
 pub(crate) mod debug;
 pub(crate) mod federation;
+pub(crate) mod ip;
 pub(crate) mod media;
+pub(crate) mod pusher;
 pub(crate) mod query;
 pub(crate) mod room;
 pub(crate) mod server;
+pub(crate) mod token;
 pub(crate) mod user;
 
 extern crate conduwuit_api as api;
@@ -0,0 +1,134 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Write,
+};
+
+use conduwuit::{utils::time, Result};
+use futures::{future::ready, StreamExt};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedUserId};
+use service::pusher::pusher_gateway;
+
+use crate::{admin_command, utils::parse_local_user_id};
+
+#[admin_command]
+pub(super) async fn list(&self, user: Option<String>) -> Result<RoomMessageEventContent> {
+	let pushers: Vec<(OwnedUserId, _)> = match user {
+		| Some(user) => {
+			let user_id = parse_local_user_id(self.services, &user)?;
+			self.services
+				.pusher
+				.get_pushers(&user_id)
+				.await
+				.into_iter()
+				.map(|pusher| (user_id.clone(), pusher))
+				.collect()
+		},
+		| None => self.services.pusher.all_pushers().collect().await,
+	};
+
+	if pushers.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No pushers found."));
+	}
+
+	let mut msg = format!("Found {} pusher(s):\n", pushers.len());
+	for (user_id, pusher) in &pushers {
+		let pushkey = pusher.ids.pushkey.as_str();
+		let state = self
+			.services
+			.pusher
+			.get_pusher_state(user_id, pushkey)
+			.await;
+
+		let enabled = if state.enabled { "" } else { " (disabled)" };
+		let device = state
+			.device_id
+			.map(|device_id| format!(", device {device_id}"))
+			.unwrap_or_default();
+
+		writeln!(
+			msg,
+			"- {user_id}: {} on \"{}\"{enabled} via {}{device}",
+			pusher.ids.app_id,
+			pusher.device_display_name,
+			pusher_gateway(pusher),
+		)?;
+
+		if let Some((_, failures)) = self
+			.services
+			.pusher
+			.get_push_failures(user_id)
+			.into_iter()
+			.find(|(failing, _)| failing == pushkey)
+		{
+			writeln!(
+				msg,
+				"  - {} failed deliveries in a row, last at {}: {}",
+				failures.count,
+				time::format(failures.last_failure, "%+"),
+				failures.last_error,
+			)?;
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn stats(&self) -> Result<RoomMessageEventContent> {
+	let mut pushers = BTreeMap::<String, usize>::new();
+	self.services
+		.pusher
+		.all_pushers()
+		.for_each(|(_, pusher)| {
+			let count = pushers.entry(pusher_gateway(&pusher)).or_default();
+			*count = count.saturating_add(1);
+			ready(())
+		})
+		.await;
+
+	let deliveries: BTreeMap<_, _> = self
+		.services
+		.pusher
+		.get_gateway_stats()
+		.into_iter()
+		.collect();
+
+	if pushers.is_empty() && deliveries.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No pushers found."));
+	}
+
+	let total: usize = pushers.values().sum();
+	let mut msg = format!("{total} pusher(s), deliveries since startup:\n");
+	let gateways = pushers.keys().chain(deliveries.keys());
+	for gateway in gateways.collect::<BTreeSet<_>>() {
+		let count = pushers.get(gateway).copied().unwrap_or(0);
+		write!(msg, "- {gateway}: {count} pusher(s)")?;
+
+		let Some(stats) = deliveries.get(gateway) else {
+			writeln!(msg, ", no deliveries")?;
+			continue;
+		};
+
+		let attempts = stats.delivered.saturating_add(stats.failed);
+		#[allow(clippy::cast_precision_loss)]
+		let success_rate = if attempts > 0 {
+			stats.delivered as f64 * 100.0 / attempts as f64
+		} else {
+			100.0
+		};
+
+		write!(
+			msg,
+			", {} delivered, {} failed ({success_rate:.1}% success)",
+			stats.delivered, stats.failed
+		)?;
+
+		if let Some((at, error)) = &stats.last_failure {
+			write!(msg, ", last failure at {}: {error}", time::format(*at, "%+"))?;
+		}
+
+		writeln!(msg)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
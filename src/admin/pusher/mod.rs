@@ -0,0 +1,22 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum PusherCommand {
+	/// - List pushers, with where they deliver to and whether deliveries are
+	///   failing
+	List {
+		/// Only list the pushers of this local user
+		#[arg(long)]
+		user: Option<String>,
+	},
+
+	/// - Show how many pushers deliver to each push gateway, and how deliveries
+	///   to each went since startup
+	Stats,
+}
@@ -11,6 +11,12 @@ pub(crate) enum PusherCommand {
 		/// Full user ID
 		user_id: Box<UserId>,
 	},
+
+	/// - Returns the user's pushers whose deliveries are currently failing.
+	GetPushFailures {
+		/// Full user ID
+		user_id: Box<UserId>,
+	},
 }
 
 pub(super) async fn process(subcommand: PusherCommand, context: &Command<'_>) -> Result {
@@ -22,6 +28,13 @@ pub(super) async fn process(subcommand: PusherCommand, context: &Command<'_>) ->
 			let results = services.pusher.get_pushers(&user_id).await;
 			let query_time = timer.elapsed();
 
+			write!(context, "Query completed in {query_time:?}:\n\n```rs\n{results:#?}\n```")
+		},
+		| PusherCommand::GetPushFailures { user_id } => {
+			let timer = tokio::time::Instant::now();
+			let results = services.pusher.get_push_failures(&user_id);
+			let query_time = timer.elapsed();
+
 			write!(context, "Query completed in {query_time:?}:\n\n```rs\n{results:#?}\n```")
 		},
 	}
@@ -1,10 +1,14 @@
 use std::fmt::Write;
 
 use clap::Subcommand;
-use conduwuit::Result;
+use conduwuit::{
+	utils::{string, ReadyExt},
+	Result,
+};
 use futures::StreamExt;
 use ruma::{
-	events::room::message::RoomMessageEventContent, OwnedRoomAliasId, OwnedRoomId, RoomId,
+	events::room::message::RoomMessageEventContent, OwnedRoomAliasId, OwnedRoomId, RoomAliasId,
+	RoomId,
 };
 
 use crate::{escape_html, Command};
@@ -12,6 +16,8 @@ use crate::{escape_html, Command};
 #[derive(Debug, Subcommand)]
 pub(crate) enum RoomAliasCommand {
 	/// - Make an alias point to a room.
+	///
+	/// Aliases matching `forbidden_alias_names` are refused.
 	Set {
 		#[arg(short, long)]
 		/// Set the alias even if a room is already using it
@@ -24,6 +30,18 @@ pub(crate) enum RoomAliasCommand {
 		room_alias_localpart: String,
 	},
 
+	/// - Point an existing local alias at another room
+	///
+	/// E.g. to move an alias left on an old room to its replacement after an
+	/// upgrade. Aliases matching `forbidden_alias_names` are refused.
+	Move {
+		/// The alias localpart to move (`alias`, not `#alias:servername.tld`)
+		room_alias_localpart: String,
+
+		/// The room id the alias should point to
+		new_room_id: Box<RoomId>,
+	},
+
 	/// - Remove a local alias
 	Remove {
 		/// The alias localpart to remove (`alias`, not `#alias:servername.tld`)
@@ -40,7 +58,30 @@ pub(crate) enum RoomAliasCommand {
 	/// - List aliases currently being used
 	List {
 		/// If set, only list the aliases for this room
+		#[arg(long = "room")]
+		room_id: Option<Box<RoomId>>,
+	},
+
+	/// - Remove all the local aliases whose localpart matches a pattern
+	///
+	/// The pattern is a glob (`*` and `?`) matched against the localpart,
+	/// e.g. `spam*`. With --forbidden, the aliases matching
+	/// `forbidden_alias_names` are removed instead.
+	RemoveMatching {
+		/// The glob to match alias localparts against
+		pattern: Option<String>,
+
+		/// Remove the aliases matching `forbidden_alias_names`
+		#[arg(long)]
+		forbidden: bool,
+
+		/// Only remove the matching aliases of this room
+		#[arg(long = "room")]
 		room_id: Option<Box<RoomId>>,
+
+		/// Only list the aliases which would be removed
+		#[arg(long)]
+		dry_run: bool,
 	},
 }
 
@@ -60,6 +101,7 @@ pub(super) async fn reprocess(
 
 	match command {
 		| RoomAliasCommand::Set { ref room_alias_localpart, .. }
+		| RoomAliasCommand::Move { ref room_alias_localpart, .. }
 		| RoomAliasCommand::Remove { ref room_alias_localpart }
 		| RoomAliasCommand::Which { ref room_alias_localpart } => {
 			let room_alias_str =
@@ -71,7 +113,16 @@ pub(super) async fn reprocess(
 						"Failed to parse alias: {err}"
 					))),
 			};
+			let forbidden = services
+				.globals
+				.forbidden_alias_names()
+				.is_match(room_alias.alias());
+
 			match command {
+				| RoomAliasCommand::Set { .. } | RoomAliasCommand::Move { .. } if forbidden =>
+					Ok(RoomMessageEventContent::text_plain(
+						"Refusing to use an alias matching forbidden_alias_names.",
+					)),
 				| RoomAliasCommand::Set { force, room_id, .. } =>
 					match (force, services.rooms.alias.resolve_local_alias(&room_alias).await) {
 						| (true, Ok(id)) => {
@@ -107,6 +158,8 @@ pub(super) async fn reprocess(
 							}
 						},
 					},
+				| RoomAliasCommand::Move { new_room_id, .. } =>
+					move_alias(context, &room_alias, &new_room_id).await,
 				| RoomAliasCommand::Remove { .. } =>
 					match services.rooms.alias.resolve_local_alias(&room_alias).await {
 						| Ok(id) => match services
@@ -133,9 +186,13 @@ pub(super) async fn reprocess(
 						| Err(_) =>
 							Ok(RoomMessageEventContent::text_plain("Alias isn't in use.")),
 					},
-				| RoomAliasCommand::List { .. } => unreachable!(),
+				| RoomAliasCommand::List { .. } | RoomAliasCommand::RemoveMatching { .. } =>
+					unreachable!(),
 			}
 		},
+		| RoomAliasCommand::RemoveMatching { pattern, forbidden, room_id, dry_run } =>
+			remove_matching(context, pattern.as_deref(), forbidden, room_id.as_deref(), dry_run)
+				.await,
 		| RoomAliasCommand::List { room_id } =>
 			if let Some(room_id) = room_id {
 				let aliases: Vec<OwnedRoomAliasId> = services
@@ -199,3 +256,109 @@ pub(super) async fn reprocess(
 			},
 	}
 }
+
+async fn move_alias(
+	context: &Command<'_>,
+	room_alias: &RoomAliasId,
+	new_room_id: &RoomId,
+) -> Result<RoomMessageEventContent> {
+	let services = context.services;
+	let server_user = &services.globals.server_user;
+
+	let Ok(old_room_id) = services.rooms.alias.resolve_local_alias(room_alias).await else {
+		return Ok(RoomMessageEventContent::text_plain("Alias isn't in use."));
+	};
+
+	if old_room_id == new_room_id {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Alias already points to {new_room_id}"
+		)));
+	}
+
+	if !services.rooms.metadata.exists(new_room_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Room {new_room_id} is not known to this server."
+		)));
+	}
+
+	services
+		.rooms
+		.alias
+		.remove_alias(room_alias, server_user)
+		.await?;
+
+	services
+		.rooms
+		.alias
+		.set_alias(room_alias, new_room_id, server_user)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Moved {room_alias} from {old_room_id} to {new_room_id}"
+	)))
+}
+
+async fn remove_matching(
+	context: &Command<'_>,
+	pattern: Option<&str>,
+	forbidden: bool,
+	room_id: Option<&RoomId>,
+	dry_run: bool,
+) -> Result<RoomMessageEventContent> {
+	let services = context.services;
+	let server_user = &services.globals.server_user;
+	let server_name = services.globals.server_name();
+
+	if pattern.is_none() && !forbidden {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Give a pattern or --forbidden to choose which aliases to remove.",
+		));
+	}
+
+	let forbidden_names = services.globals.forbidden_alias_names();
+	let matches = |localpart: &str| {
+		pattern.is_none_or(|pattern| string::glob_match(pattern, localpart))
+			&& (!forbidden || forbidden_names.is_match(localpart))
+	};
+
+	let aliases: Vec<(OwnedRoomId, OwnedRoomAliasId)> = services
+		.rooms
+		.alias
+		.all_local_aliases()
+		.ready_filter(|(alias_room_id, localpart)| {
+			room_id.is_none_or(|room_id| room_id == *alias_room_id) && matches(localpart)
+		})
+		.ready_filter_map(|(alias_room_id, localpart)| {
+			OwnedRoomAliasId::parse(format!("#{localpart}:{server_name}"))
+				.ok()
+				.map(|alias| (alias_room_id.to_owned(), alias))
+		})
+		.collect()
+		.await;
+
+	if aliases.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No aliases match."));
+	}
+
+	let mut removed: usize = 0;
+	let mut plain_list = String::new();
+	for (alias_room_id, alias) in &aliases {
+		if !dry_run {
+			if let Err(e) = services.rooms.alias.remove_alias(alias, server_user).await {
+				writeln!(plain_list, "- {alias} ({alias_room_id}): failed to remove: {e}")?;
+				continue;
+			}
+
+			removed = removed.saturating_add(1);
+		}
+
+		writeln!(plain_list, "- {alias} ({alias_room_id})")?;
+	}
+
+	let plain = if dry_run {
+		format!("Would remove {} aliases:\n{plain_list}", aliases.len())
+	} else {
+		format!("Removed {removed} aliases:\n{plain_list}")
+	};
+
+	Ok(RoomMessageEventContent::text_plain(plain))
+}
@@ -1,7 +1,15 @@
-use conduwuit::Result;
+use std::{sync::atomic::Ordering, time::Duration};
+
+use conduwuit::{
+	info,
+	utils::{self, time::pretty},
+	Result,
+};
 use futures::StreamExt;
-use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedUserId};
+use service::export::{RoomExportFilter, RoomExportFormat};
 
+use super::ExportFormat;
 use crate::{admin_command, get_room_info, PAGE_SIZE};
 
 #[admin_command]
@@ -67,3 +75,197 @@ pub(super) async fn exists(&self, room_id: OwnedRoomId) -> Result<RoomMessageEve
 
 	Ok(RoomMessageEventContent::notice_markdown(format!("{result}")))
 }
+
+#[admin_command]
+pub(super) async fn purge(
+	&self,
+	room_id: OwnedRoomId,
+	yes_i_want_to_do_this: bool,
+) -> Result<RoomMessageEventContent> {
+	let services = self.services;
+
+	if !yes_i_want_to_do_this {
+		return Ok(RoomMessageEventContent::notice_markdown(
+			"You must pass the --yes-i-want-to-do-this flag to ensure you really want to purge \
+			 everything stored about this room. This cannot be undone.",
+		));
+	}
+
+	if services
+		.rooms
+		.short
+		.get_shortroomid(&room_id)
+		.await
+		.is_err()
+	{
+		return Ok(RoomMessageEventContent::text_plain("We don't know about this room."));
+	}
+
+	if !services.rooms.metadata.is_banned(&room_id).await {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Ban the room with `rooms moderation ban-room` before purging it.",
+		));
+	}
+
+	let local_users = services
+		.rooms
+		.state_cache
+		.local_users_in_room(&room_id)
+		.count()
+		.await;
+
+	if local_users > 0 {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{local_users} local user(s) are still in the room. Evict them with `rooms \
+			 moderation ban-room --force` before purging it."
+		)));
+	}
+
+	let purge = &services.rooms.purge;
+	writeln!(self, "Purging {room_id}...").await?;
+
+	let aliases = purge.purge_aliases(&room_id).await;
+	writeln!(self, "Removed {aliases} alias(es) and the directory entry.").await?;
+
+	let media = purge.purge_remote_media(&room_id).await;
+	writeln!(self, "Deleted {media} cached remote media file(s).").await?;
+
+	let state = purge.purge_state(&room_id).await?;
+	writeln!(self, "Removed {state} state group(s).").await?;
+
+	let members = purge.purge_members(&room_id).await;
+	writeln!(self, "Removed the memberships and room data of {members} user(s).").await?;
+
+	let events = purge.purge_events(&room_id).await?;
+	writeln!(self, "Removed {events} event(s).").await?;
+
+	services.clear_cache().await;
+	info!("Purged room {room_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!("Finished purging {room_id}.")))
+}
+
+#[admin_command]
+pub(super) async fn purge_empty(&self, dry_run: bool) -> Result<RoomMessageEventContent> {
+	let abandoned = self.services.rooms.cleanup.cleanup(dry_run).await?;
+	if abandoned.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No abandoned rooms found."));
+	}
+
+	if !dry_run && abandoned.iter().any(|room| room.expired) {
+		self.services.clear_cache().await;
+	}
+
+	let now = utils::millis_since_unix_epoch();
+	let rooms = abandoned
+		.iter()
+		.map(|room| {
+			let empty_for = Duration::from_millis(now.saturating_sub(room.empty_since));
+			let status = match (room.expired, dry_run) {
+				| (true, true) => "would be purged",
+				| (true, false) => "purged",
+				| (false, _) => "within grace period",
+			};
+
+			format!("{} (empty for {}): {status}", room.room_id, pretty(empty_for))
+		})
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Abandoned rooms ({}):\n```\n{rooms}\n```",
+		abandoned.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn export(
+	&self,
+	room_id: OwnedRoomId,
+	format: ExportFormat,
+	since: Option<u64>,
+	until: Option<u64>,
+	sender: Vec<OwnedUserId>,
+	event_type: Vec<String>,
+	state: bool,
+) -> Result<RoomMessageEventContent> {
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Ok(RoomMessageEventContent::text_plain("We don't know about this room."));
+	}
+
+	let filter = RoomExportFilter {
+		since,
+		until,
+		senders: sender,
+		types: event_type,
+		state,
+	};
+
+	let format = match format {
+		| ExportFormat::Ndjson => RoomExportFormat::Ndjson,
+		| ExportFormat::Json => RoomExportFormat::Json,
+	};
+
+	let export = self
+		.services
+		.export
+		.export_room(&room_id, &filter, format)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Exported {} events ({} bytes) to `{}`",
+		export.events,
+		export.size,
+		export.path.display()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn retention(
+	&self,
+	room_id: Option<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	let retention = &self.services.rooms.retention;
+	let stats = retention.stats();
+	let mut msg = format!(
+		"Retention purges since startup: {}\nEvents purged: {}\nMedia purged: {}\nLast run: {}",
+		stats.runs.load(Ordering::Relaxed),
+		stats.events.load(Ordering::Relaxed),
+		stats.media.load(Ordering::Relaxed),
+		stats.last_run.load(Ordering::Relaxed),
+	);
+
+	if !self.services.server.config.retention_enabled {
+		msg.push_str("\n\nMessage retention is disabled.");
+	}
+
+	if let Some(room_id) = room_id {
+		let lifetime = match retention.max_lifetime(&room_id).await {
+			| _ if self.services.admin.is_admin_room(&room_id).await =>
+				"forever (admin room)".to_owned(),
+			| Some(lifetime) => format!("for {}", pretty(lifetime)),
+			| None => "forever".to_owned(),
+		};
+
+		msg.push_str(&format!("\n\nEvents in {room_id} are kept {lifetime}"));
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("```\n{msg}\n```")))
+}
+
+#[admin_command]
+pub(super) async fn recount_notifications(
+	&self,
+	room_id: OwnedRoomId,
+) -> Result<RoomMessageEventContent> {
+	let users = self
+		.services
+		.rooms
+		.timeline
+		.recount_room_notifications(&room_id)
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Recounted the notifications of {users} local users in {room_id}."
+	)))
+}
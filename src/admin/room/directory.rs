@@ -1,5 +1,7 @@
+use std::time::{Duration, UNIX_EPOCH};
+
 use clap::Subcommand;
-use conduwuit::Result;
+use conduwuit::{utils::time, Result};
 use futures::StreamExt;
 use ruma::{events::room::message::RoomMessageEventContent, RoomId};
 
@@ -23,6 +25,21 @@ pub(crate) enum RoomDirectoryCommand {
 	List {
 		page: Option<usize>,
 	},
+
+	/// - List requests to publish rooms awaiting review
+	Pending,
+
+	/// - Approve a request to publish a room, publishing it
+	Approve {
+		/// The room id of the room to publish
+		room_id: Box<RoomId>,
+	},
+
+	/// - Reject a request to publish a room
+	Reject {
+		/// The room id of the room not to publish
+		room_id: Box<RoomId>,
+	},
 }
 
 pub(super) async fn process(command: RoomDirectoryCommand, context: &Command<'_>) -> Result {
@@ -81,5 +98,64 @@ pub(super) async fn reprocess(
 			);
 			Ok(RoomMessageEventContent::text_markdown(output))
 		},
+		| RoomDirectoryCommand::Pending => {
+			let requests: Vec<_> = services
+				.rooms
+				.directory
+				.publish_requests()
+				.then(|(room_id, request)| async move {
+					let (id, members, name) = get_room_info(services, &room_id).await;
+					let requested_at = UNIX_EPOCH
+						.checked_add(Duration::from_millis(request.requested_at))
+						.map_or_else(String::new, |at| time::format(at, "%+"));
+
+					format!(
+						"{id} | Members: {members} | Name: {name} | Requested by {} at \
+						 {requested_at}",
+						request.user_id
+					)
+				})
+				.collect()
+				.await;
+
+			if requests.is_empty() {
+				return Ok(RoomMessageEventContent::text_plain("No rooms are awaiting review."));
+			}
+
+			let output = format!("Rooms awaiting review:\n```\n{}\n```", requests.join("\n"));
+			Ok(RoomMessageEventContent::text_markdown(output))
+		},
+		| RoomDirectoryCommand::Approve { room_id } => {
+			if services
+				.rooms
+				.directory
+				.publish_request(&room_id)
+				.await
+				.is_err()
+			{
+				return Ok(RoomMessageEventContent::text_plain(
+					"There is no request to publish this room.",
+				));
+			}
+
+			services.rooms.directory.set_public(&room_id);
+			Ok(RoomMessageEventContent::notice_plain("Room published"))
+		},
+		| RoomDirectoryCommand::Reject { room_id } => {
+			if services
+				.rooms
+				.directory
+				.publish_request(&room_id)
+				.await
+				.is_err()
+			{
+				return Ok(RoomMessageEventContent::text_plain(
+					"There is no request to publish this room.",
+				));
+			}
+
+			services.rooms.directory.reject_publish(&room_id);
+			Ok(RoomMessageEventContent::notice_plain("Request to publish room rejected"))
+		},
 	}
 }
@@ -4,9 +4,9 @@ mod directory;
 mod info;
 mod moderation;
 
-use clap::Subcommand;
+use clap::{Subcommand, ValueEnum};
 use conduwuit::Result;
-use ruma::OwnedRoomId;
+use ruma::{OwnedRoomId, OwnedUserId};
 
 use self::{
 	alias::RoomAliasCommand, directory::RoomDirectoryCommand, info::RoomInfoCommand,
@@ -56,4 +56,84 @@ pub(super) enum RoomCommand {
 	Exists {
 		room_id: OwnedRoomId,
 	},
+
+	/// - Purge a room, removing its events, state, memberships, aliases,
+	///   directory entry, account data and cached remote media
+	///
+	/// The room has to be banned with `rooms moderation ban-room --force`
+	/// first, so no local users are left in it and it can't be rejoined while
+	/// it is purged. The ban is kept afterwards.
+	///
+	/// Requires the `--yes-i-want-to-do-this` flag.
+	Purge {
+		room_id: OwnedRoomId,
+
+		#[arg(long)]
+		yes_i_want_to_do_this: bool,
+	},
+
+	/// - Purge rooms which no local user is in and every local user who left
+	///   has forgotten, once they have been empty for `empty_room_grace_period`
+	///
+	/// This runs the same pass as the `empty_room_cleanup` background job.
+	/// With `--dry-run` the rooms are only listed.
+	PurgeEmpty {
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Export a room's events to a file in `export_path`, for compliance or
+	///   archival
+	///
+	/// Events are written as full PDUs in timeline order. Timestamps are
+	/// milliseconds since the unix epoch; `--until` is exclusive.
+	Export {
+		room_id: OwnedRoomId,
+
+		#[arg(long, value_enum, default_value_t)]
+		format: ExportFormat,
+
+		/// Only export events sent at or after this time
+		#[arg(long)]
+		since: Option<u64>,
+
+		/// Only export events sent before this time
+		#[arg(long)]
+		until: Option<u64>,
+
+		/// Only export events from these senders (can be repeated)
+		#[arg(long)]
+		sender: Vec<OwnedUserId>,
+
+		/// Only export events of these types (can be repeated)
+		#[arg(long)]
+		event_type: Vec<String>,
+
+		/// Also export the full room state at the first and last exported
+		/// events
+		#[arg(long)]
+		state: bool,
+	},
+
+	/// - Recompute the unread notification and highlight counts of every local
+	///   user in a room
+	RecountNotifications {
+		room_id: OwnedRoomId,
+	},
+
+	/// - Show what the message retention purger has deleted since startup, and
+	///   how long a room's events are kept
+	Retention {
+		room_id: Option<OwnedRoomId>,
+	},
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub(super) enum ExportFormat {
+	/// One JSON object per line
+	#[default]
+	Ndjson,
+
+	/// A single JSON array
+	Json,
 }
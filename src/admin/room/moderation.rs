@@ -1,15 +1,22 @@
+use std::{collections::BTreeSet, fmt::Write};
+
 use api::client::leave_room;
 use clap::Subcommand;
 use conduwuit::{
 	debug, error, info,
-	utils::{IterStream, ReadyExt},
+	utils::{
+		stream::TryIgnore,
+		time::{parse_duration, pretty},
+		IterStream, ReadyExt,
+	},
 	warn, Result,
 };
 use futures::StreamExt;
 use ruma::{
-	events::room::message::RoomMessageEventContent, OwnedRoomId, RoomAliasId, RoomId,
-	RoomOrAliasId,
+	events::room::message::RoomMessageEventContent, Mxc, OwnedMxcUri, OwnedRoomId,
+	OwnedRoomOrAliasId, OwnedUserId, RoomAliasId, RoomId, RoomOrAliasId,
 };
+use service::media::content_mxcs;
 
 use crate::{admin_command, admin_command_dispatch, get_room_info};
 
@@ -74,6 +81,74 @@ pub(crate) enum RoomModerationCommand {
 		/// information
 		no_details: bool,
 	},
+
+	/// - Quarantines a room while it is investigated, without deleting anything
+	///
+	/// Local users other than admins can't join the room, it is hidden from
+	/// the room directory, and the media shared into it (now or later) can't
+	/// be downloaded. Users already in the room stay in it.
+	QuarantineRoom {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - Lifts the quarantine of a room and its media
+	UnquarantineRoom {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - List of all rooms we have quarantined
+	ListQuarantinedRooms {
+		#[arg(long)]
+		/// Whether to only output room IDs without supplementary room
+		/// information
+		no_details: bool,
+	},
+
+	/// - Show the invites counted against `invites_per_sender_per_hour` and
+	///   `invites_per_room_per_hour` within the last hour
+	InviteCounters,
+
+	/// - Reset the invites counted against a sender or room, or all of them
+	///   when neither is given
+	ResetInviteCounters {
+		#[arg(long)]
+		/// The local user whose invites to forget
+		user: Option<OwnedUserId>,
+
+		#[arg(long)]
+		/// The room whose invites to forget
+		room: Option<OwnedRoomId>,
+	},
+
+	/// - Puts a room in raid mode, under which local and remote users need
+	///   approval with `approve-join` to join it, or takes it out with
+	///   --disable
+	RaidMode {
+		room: OwnedRoomId,
+
+		#[arg(long)]
+		/// How long raid mode lasts, e.g. "30m" or "2h"; until disabled if
+		/// not given
+		duration: Option<String>,
+
+		#[arg(long)]
+		/// Take the room out of raid mode
+		disable: bool,
+	},
+
+	/// - List rooms in raid mode with the users waiting for approval
+	ListRaidModes,
+
+	/// - Allows a user to join a room in raid mode
+	ApproveJoin {
+		room: OwnedRoomId,
+
+		user: OwnedUserId,
+	},
 }
 
 #[admin_command]
@@ -637,3 +712,211 @@ async fn list_banned_rooms(&self, no_details: bool) -> Result<RoomMessageEventCo
 
 	Ok(RoomMessageEventContent::notice_markdown(output_plain))
 }
+
+#[admin_command]
+async fn quarantine_room(&self, room: OwnedRoomOrAliasId) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+
+	if self
+		.services
+		.admin
+		.get_admin_room()
+		.await
+		.is_ok_and(|admin_room_id| admin_room_id == room_id)
+	{
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to quarantine the admin room.",
+		));
+	}
+
+	self.services.rooms.metadata.quarantine_room(&room_id, true);
+	let media = quarantine_room_media(self.services, &room_id, true).await;
+
+	info!("Quarantined room {room_id} and {media} of its media");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Room {room_id} quarantined along with {media} media file(s)."
+	)))
+}
+
+#[admin_command]
+async fn unquarantine_room(&self, room: OwnedRoomOrAliasId) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+
+	self.services
+		.rooms
+		.metadata
+		.quarantine_room(&room_id, false);
+	let media = quarantine_room_media(self.services, &room_id, false).await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Lifted the quarantine of room {room_id} and {media} media file(s)."
+	)))
+}
+
+#[admin_command]
+async fn list_quarantined_rooms(&self, no_details: bool) -> Result<RoomMessageEventContent> {
+	let room_ids: Vec<OwnedRoomId> = self
+		.services
+		.rooms
+		.metadata
+		.list_quarantined_rooms()
+		.map(Into::into)
+		.collect()
+		.await;
+
+	if room_ids.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No rooms are quarantined."));
+	}
+
+	let mut rooms = room_ids
+		.iter()
+		.stream()
+		.then(|room_id| get_room_info(self.services, room_id))
+		.collect::<Vec<_>>()
+		.await;
+
+	rooms.sort_by_key(|r| r.1);
+	rooms.reverse();
+
+	let output_plain = format!(
+		"Rooms Quarantined ({}):\n```\n{}\n```",
+		rooms.len(),
+		rooms
+			.iter()
+			.map(|(id, members, name)| if no_details {
+				format!("{id}")
+			} else {
+				format!("{id}\tMembers: {members}\tName: {name}")
+			})
+			.collect::<Vec<_>>()
+			.join("\n")
+	);
+
+	Ok(RoomMessageEventContent::notice_markdown(output_plain))
+}
+
+#[admin_command]
+async fn invite_counters(&self) -> Result<RoomMessageEventContent> {
+	let (senders, rooms) = self.services.ratelimit.invite_counters();
+	if senders.is_empty() && rooms.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain(
+			"No invites sent within the last hour.",
+		));
+	}
+
+	let mut msg = String::from("| Sender | Invites |\n| --- | --- |\n");
+	for (sender, count) in senders {
+		writeln!(msg, "| {sender} | {count} |")?;
+	}
+
+	msg.push_str("\n| Room | Invites |\n| --- | --- |\n");
+	for (room_id, count) in rooms {
+		writeln!(msg, "| {room_id} | {count} |")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+async fn reset_invite_counters(
+	&self,
+	user: Option<OwnedUserId>,
+	room: Option<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	self.services
+		.ratelimit
+		.reset_invite_counters(user.as_deref(), room.as_deref());
+
+	Ok(RoomMessageEventContent::notice_plain("Invite counters reset."))
+}
+
+#[admin_command]
+async fn raid_mode(
+	&self,
+	room: OwnedRoomId,
+	duration: Option<String>,
+	disable: bool,
+) -> Result<RoomMessageEventContent> {
+	if disable {
+		return Ok(RoomMessageEventContent::notice_plain(
+			if self.services.ratelimit.disable_raid_mode(&room) {
+				format!("Raid mode disabled for {room}.")
+			} else {
+				format!("{room} is not in raid mode.")
+			},
+		));
+	}
+
+	let duration = duration.as_deref().map(parse_duration).transpose()?;
+	self.services.ratelimit.enable_raid_mode(&room, duration);
+
+	Ok(RoomMessageEventContent::notice_plain(match duration {
+		| Some(duration) => format!("Raid mode enabled for {room} for {}.", pretty(duration)),
+		| None => format!("Raid mode enabled for {room} until disabled."),
+	}))
+}
+
+#[admin_command]
+async fn list_raid_modes(&self) -> Result<RoomMessageEventContent> {
+	let modes = self.services.ratelimit.raid_modes();
+	if modes.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No rooms are in raid mode."));
+	}
+
+	let mut msg =
+		String::from("| Room | Remaining | Pending | Approved |\n| --- | --- | --- | --- |\n");
+	for mode in modes {
+		let remaining = mode
+			.remaining
+			.map_or_else(|| "until disabled".to_owned(), pretty);
+
+		let pending: Vec<_> = mode.pending.iter().map(ToString::to_string).collect();
+		let approved: Vec<_> = mode.approved.iter().map(ToString::to_string).collect();
+		writeln!(
+			msg,
+			"| {} | {remaining} | {} | {} |",
+			mode.room_id,
+			pending.join(", "),
+			approved.join(", "),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+async fn approve_join(
+	&self,
+	room: OwnedRoomId,
+	user: OwnedUserId,
+) -> Result<RoomMessageEventContent> {
+	if !self.services.ratelimit.approve_join(&room, &user) {
+		return Ok(RoomMessageEventContent::notice_plain(format!("{room} is not in raid mode.")));
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(format!("{user} may now join {room}.")))
+}
+
+/// Quarantines, or lifts the quarantine of, the media referenced by the
+/// room's events. Returns the number of files.
+async fn quarantine_room_media(
+	services: &service::Services,
+	room_id: &RoomId,
+	quarantined: bool,
+) -> usize {
+	let mxcs: BTreeSet<OwnedMxcUri> = services
+		.rooms
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err()
+		.map(|(_, pdu)| content_mxcs(&pdu.get_content_as_value()).stream())
+		.flatten()
+		.collect()
+		.await;
+
+	mxcs.iter()
+		.filter_map(|mxc| Mxc::try_from(mxc.as_str()).ok())
+		.inspect(|mxc| services.media.quarantine(mxc, quarantined))
+		.count()
+}
@@ -1,7 +1,19 @@
-use std::{fmt::Write, path::PathBuf, sync::Arc};
-
-use conduwuit::{info, utils::time, warn, Err, Result};
-use ruma::events::room::message::RoomMessageEventContent;
+use std::{
+	cmp::Reverse,
+	fmt::Write,
+	path::PathBuf,
+	sync::{atomic::Ordering, Arc},
+	time::{Duration, UNIX_EPOCH},
+};
+
+use conduwuit::{
+	info,
+	metrics::route::Route,
+	utils::{self, bytes::pretty, time},
+	warn, Err, Result,
+};
+use futures::StreamExt;
+use ruma::{events::room::message::RoomMessageEventContent, OwnedUserId};
 
 use crate::admin_command;
 
@@ -71,6 +83,43 @@ pub(super) async fn list_features(
 	Ok(RoomMessageEventContent::text_markdown(features))
 }
 
+#[admin_command]
+pub(super) async fn route_stats(&self, limit: usize) -> Result<RoomMessageEventContent> {
+	let mut routes = self.services.server.metrics.routes.snapshot();
+	if routes.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No requests handled yet."));
+	}
+
+	routes.sort_by_key(|(_, route)| Reverse(route.requests.load(Ordering::Relaxed)));
+
+	let quantile = |route: &Route, quantile| {
+		route
+			.quantile(quantile)
+			.map_or_else(|| "-".to_owned(), |latency| format!("{latency:?}"))
+	};
+
+	let mut msg = String::from(
+		"| Route | In flight | Requests | 4xx | 5xx | p50 | p95 | p99 |\n| --- | --- | --- | \
+		 --- | --- | --- | --- | --- |\n",
+	);
+
+	for (path, route) in routes.iter().take(limit) {
+		writeln!(
+			msg,
+			"| `{path}` | {} | {} | {} | {} | {} | {} | {} |",
+			route.in_flight.load(Ordering::Relaxed),
+			route.requests.load(Ordering::Relaxed),
+			route.client_errors.load(Ordering::Relaxed),
+			route.server_errors.load(Ordering::Relaxed),
+			quantile(route, 0.5),
+			quantile(route, 0.95),
+			quantile(route, 0.99),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::text_markdown(msg))
+}
+
 #[admin_command]
 pub(super) async fn memory_usage(&self) -> Result<RoomMessageEventContent> {
 	let services_usage = self.services.memory_usage().await?;
@@ -78,8 +127,21 @@ pub(super) async fn memory_usage(&self) -> Result<RoomMessageEventContent> {
 	let allocator_usage =
 		conduwuit::alloc::memory_usage().map_or(String::new(), |s| format!("\nAllocator:\n{s}"));
 
+	let metrics = &self.services.server.metrics;
+	let mut process_usage = String::new();
+	if let Some(resident) = utils::sys::resident_memory() {
+		writeln!(process_usage, "resident: {}", pretty(resident))?;
+	}
+
+	writeln!(process_usage, "runtime workers: {}", metrics.num_workers())?;
+	if let Some(runtime) = metrics.runtime_metrics() {
+		writeln!(process_usage, "runtime alive tasks: {}", runtime.num_alive_tasks())?;
+		writeln!(process_usage, "runtime global queue: {}", runtime.global_queue_depth())?;
+	}
+
 	Ok(RoomMessageEventContent::text_plain(format!(
-		"Services:\n{services_usage}\nDatabase:\n{database_usage}{allocator_usage}",
+		"Process:\n{process_usage}\nServices:\n{services_usage}\nDatabase:\\
+		 n{database_usage}{allocator_usage}",
 	)))
 }
 
@@ -128,6 +190,78 @@ pub(super) async fn list_database_files(&self) -> Result<RoomMessageEventContent
 	Ok(RoomMessageEventContent::notice_markdown(result))
 }
 
+#[admin_command]
+pub(super) async fn train_compression_dictionary(
+	&self,
+	samples: usize,
+	dict_bytes: usize,
+	apply: bool,
+) -> Result<RoomMessageEventContent> {
+	use conduwuit_database::dictionary::Options;
+
+	const EVENT_MAPS: [&str; 2] = ["pduid_pdu", "eventid_outlierpdu"];
+
+	if self.services.server.config.rocksdb_compression_algo != "zstd" {
+		return Err!("Dictionary compression requires rocksdb_compression_algo to be \"zstd\".");
+	}
+
+	let options = Options {
+		samples,
+		dict_bytes,
+		..Default::default()
+	};
+	let map = self.services.db.get(EVENT_MAPS[0])?.clone();
+	let report = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || map.train_dictionary_blocking(&options))
+		.await??;
+
+	let percent =
+		|bytes: usize, of: usize| bytes.saturating_mul(100).checked_div(of).unwrap_or(0);
+
+	let savings = report
+		.plain_bytes
+		.saturating_sub(report.dict_compressed_bytes);
+
+	let mut out = format!(
+		"Trained a {} dictionary from {} events and measured it against {} others totalling \
+		 {}:\n\n- without dictionary: {} ({}% of original)\n- with dictionary: {} ({}% of \
+		 original)\n\nDictionary compression is expected to reduce the size of compressed event \
+		 data by about {}%.",
+		pretty(report.dict_bytes),
+		report.trained,
+		report.measured,
+		pretty(report.raw_bytes),
+		pretty(report.plain_bytes),
+		percent(report.plain_bytes, report.raw_bytes),
+		pretty(report.dict_compressed_bytes),
+		percent(report.dict_compressed_bytes, report.raw_bytes),
+		percent(savings, report.plain_bytes),
+	);
+
+	if apply {
+		for name in EVENT_MAPS {
+			self.services
+				.db
+				.get(name)?
+				.set_compression_dictionary(report.dict_bytes)?;
+		}
+
+		writeln!(
+			out,
+			"\n\nRocksDB now compresses new event data with dictionaries of up to {} trained \
+			 from each file it writes, until restart. Set `rocksdb_compression_dictionary_bytes \
+			 = {}` in your config to keep it.",
+			pretty(report.dict_bytes),
+			report.dict_bytes
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
 #[admin_command]
 pub(super) async fn admin_notice(&self, message: Vec<String>) -> Result<RoomMessageEventContent> {
 	let message = message.join(" ");
@@ -136,6 +270,111 @@ pub(super) async fn admin_notice(&self, message: Vec<String>) -> Result<RoomMess
 	Ok(RoomMessageEventContent::notice_plain("Notice was sent to #admins"))
 }
 
+#[admin_command]
+pub(super) async fn notice(
+	&self,
+	all: bool,
+	user: Vec<OwnedUserId>,
+	message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let message = message.join(" ");
+	if message.is_empty() {
+		return Err!("Notice message cannot be empty.");
+	}
+
+	if all == !user.is_empty() {
+		return Err!("Pass either --all or at least one --user.");
+	}
+
+	let server_notices = &self.services.server_notices;
+	if all {
+		let notice = server_notices.broadcast(&message).await?;
+
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Notice `{}` reached {} users ({} failed)",
+			notice.id, notice.reached, notice.failed
+		)));
+	}
+
+	let content = RoomMessageEventContent::notice_markdown(&message);
+	for user_id in &user {
+		if !self.services.globals.user_is_local(user_id) {
+			return Err!("{user_id} is not a local user.");
+		}
+
+		server_notices.send(user_id, content.clone()).await?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Notice sent to {} users",
+		user.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn notice_status(
+	&self,
+	notice_id: String,
+	list_users: bool,
+) -> Result<RoomMessageEventContent> {
+	let server_notices = &self.services.server_notices;
+	let Ok(notice) = server_notices.get_notice(&notice_id).await else {
+		return Err!("No notice with this ID.");
+	};
+
+	let mut msg = format!(
+		"Notice `{}` sent at {}: reached {} users, {} failed",
+		notice.id,
+		time::format(UNIX_EPOCH + Duration::from_millis(notice.sent_at), "%+"),
+		notice.reached,
+		notice.failed
+	);
+
+	if list_users {
+		let users: Vec<_> = server_notices
+			.reached_users(&notice_id)
+			.map(ToString::to_string)
+			.collect()
+			.await;
+
+		write!(msg, "\n```\n{}\n```", users.join("\n"))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn scheduled_tasks(&self) -> Result<RoomMessageEventContent> {
+	let tasks = self.services.scheduler.tasks();
+	if tasks.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No scheduled tasks are configured."));
+	}
+
+	let mut msg = String::new();
+	for (i, task) in tasks.iter().enumerate() {
+		let next_run = task
+			.next_run
+			.map_or_else(|| "never".to_owned(), |next_run| time::format(next_run, "%+"));
+
+		writeln!(msg, "#{i} `{}` `{}`", task.schedule, task.admin_command)?;
+		writeln!(msg, "- next run: {next_run}")?;
+		match &task.last_run {
+			| None => writeln!(msg, "- last run: never")?,
+			| Some(last_run) => {
+				writeln!(
+					msg,
+					"- last run: {} ({}, took {:?})",
+					time::format(last_run.started, "%+"),
+					if last_run.success { "succeeded" } else { "failed" },
+					last_run.elapsed
+				)?;
+			},
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
 #[admin_command]
 pub(super) async fn reload_mods(&self) -> Result<RoomMessageEventContent> {
 	self.services.server.reload()?;
@@ -4,6 +4,7 @@ use std::path::PathBuf;
 
 use clap::Subcommand;
 use conduwuit::Result;
+use ruma::OwnedUserId;
 
 use crate::admin_command_dispatch;
 
@@ -49,11 +50,75 @@ pub(super) enum ServerCommand {
 	/// - List database files
 	ListDatabaseFiles,
 
+	/// - Train a zstd dictionary on a sample of stored events and report the
+	///   expected disk savings of dictionary compression
+	///
+	/// The dictionary trained here is only used for the estimate and then
+	/// discarded; RocksDB trains its own dictionary from each file it writes.
+	/// With --apply, RocksDB dictionary compression of the same size is enabled
+	/// for new event data until the server restarts; set
+	/// `rocksdb_compression_dictionary_bytes` to keep it enabled.
+	TrainCompressionDictionary {
+		/// Number of events to sample
+		#[arg(long, default_value("16384"))]
+		samples: usize,
+
+		/// Maximum dictionary size in bytes
+		#[arg(long, default_value("65536"))]
+		dict_bytes: usize,
+
+		/// Have RocksDB compress new event data with dictionaries of this size
+		#[arg(long)]
+		apply: bool,
+	},
+
 	/// - Send a message to the admin room.
 	AdminNotice {
 		message: Vec<String>,
 	},
 
+	/// - Send a notice to local users in their server notice rooms
+	///
+	/// Each user gets a room with the server user, tagged `m.server_notice`,
+	/// which is reused for later notices unless they leave it. Notices sent
+	/// with `--all` are tracked; see `notice-status`.
+	Notice {
+		/// Send to every local user
+		#[arg(long)]
+		all: bool,
+
+		/// Send to this user (can be repeated)
+		#[arg(long)]
+		user: Vec<OwnedUserId>,
+
+		/// Markdown message to send
+		message: Vec<String>,
+	},
+
+	/// - Show the scheduled tasks from `scheduled_tasks`,
+	///   `database_backup_schedule` and `state_recompression_schedule` with
+	///   their next and last runs
+	ScheduledTasks,
+
+	/// - Show how many users a notice sent with `notice --all` reached
+	NoticeStatus {
+		notice_id: String,
+
+		/// Also list the users it was delivered to
+		#[arg(long)]
+		list_users: bool,
+	},
+
+	/// - Show request statistics per route since startup
+	///
+	/// Latency percentiles are estimated from a histogram, and shown as the
+	/// upper bound of the bucket they fall in.
+	RouteStats {
+		/// Number of routes to show, most requested first
+		#[arg(long, default_value("50"))]
+		limit: usize,
+	},
+
 	/// - Hot-reload the server
 	#[clap(alias = "reload")]
 	ReloadMods,
@@ -0,0 +1,87 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use conduwuit::{
+	utils,
+	utils::{
+		time,
+		time::{parse_duration, timepoint_from_now},
+	},
+	Result,
+};
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn create(
+	&self,
+	token: Option<String>,
+	uses_allowed: Option<u64>,
+	expires_in: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let expiry_time = expires_in
+		.as_deref()
+		.map(|expires_in| -> Result<u64> {
+			let expiry = timepoint_from_now(parse_duration(expires_in)?)?;
+			let expiry = expiry.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+			Ok(expiry.as_millis().try_into()?)
+		})
+		.transpose()?;
+
+	let token = self
+		.services
+		.registration_tokens
+		.create(token, uses_allowed, expiry_time)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Created registration token `{token}`."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list(&self) -> Result<RoomMessageEventContent> {
+	let now = utils::millis_since_unix_epoch();
+	let tokens: Vec<_> = self
+		.services
+		.registration_tokens
+		.list()
+		.map(|(token, info)| {
+			let uses_allowed = info
+				.uses_allowed
+				.map_or_else(|| "unlimited".to_owned(), |allowed| allowed.to_string());
+
+			let expiry = info.expiry_time.map_or_else(
+				|| "never".to_owned(),
+				|expiry| {
+					UNIX_EPOCH
+						.checked_add(Duration::from_millis(expiry))
+						.map_or_else(String::new, |expiry| time::format(expiry, "%+"))
+				},
+			);
+
+			let valid = if info.is_usable(now) { "" } else { " (expired)" };
+
+			format!(
+				"{token}{valid}: {} completed, {uses_allowed} allowed, expires: {expiry}",
+				info.completed
+			)
+		})
+		.collect()
+		.await;
+
+	let mut plain_msg = format!("Found {} registration token(s):\n```\n", tokens.len());
+	plain_msg += tokens.join("\n").as_str();
+	plain_msg += "\n```";
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
+
+#[admin_command]
+pub(super) async fn revoke(&self, token: String) -> Result<RoomMessageEventContent> {
+	self.services.registration_tokens.revoke(&token).await?;
+
+	Ok(RoomMessageEventContent::text_plain("Registration token revoked."))
+}
@@ -0,0 +1,33 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum TokenCommand {
+	/// - Create a new registration token
+	Create {
+		/// The token, if unspecified one is generated
+		token: Option<String>,
+
+		/// Maximum number of times the token can be used to register
+		#[arg(long, short)]
+		uses_allowed: Option<u64>,
+
+		/// - The relative time (e.g. 30s, 5m, 7d) after which the token expires
+		#[arg(long, short)]
+		expires_in: Option<String>,
+	},
+
+	/// - List all registration tokens and their usage
+	List,
+
+	/// - Revoke a registration token
+	Revoke {
+		/// The token to revoke
+		token: String,
+	},
+}
@@ -1,9 +1,17 @@
-use std::{collections::BTreeMap, fmt::Write as _};
+use std::{
+	collections::BTreeMap,
+	fmt::Write as _,
+	net::IpAddr,
+	path::PathBuf,
+	time::{Duration, UNIX_EPOCH},
+};
 
-use api::client::{full_user_deactivate, join_room_by_id_helper, leave_room};
+use api::client::{
+	force_join_room_by_id_helper, full_user_deactivate, join_room_by_id_helper, leave_room,
+};
 use conduwuit::{
 	debug_warn, error, info, is_equal_to,
-	utils::{self, ReadyExt},
+	utils::{self, string, time, ReadyExt},
 	warn, PduBuilder, Result,
 };
 use conduwuit_api::client::{leave_all_rooms, update_avatar_url, update_displayname};
@@ -20,34 +28,168 @@ use ruma::{
 	},
 	EventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomId, UserId,
 };
+use service::login_attempts::Target;
 
+use super::UserSort;
 use crate::{
 	admin_command, get_room_info,
-	utils::{parse_active_local_user_id, parse_local_user_id},
+	utils::{parse_active_local_user_id, parse_local_user_id, parse_user_id},
+	PAGE_SIZE,
 };
 
 const AUTO_GEN_PASSWORD_LENGTH: usize = 25;
 const BULK_JOIN_REASON: &str = "Bulk force joining this room as initiated by the server admin.";
 
 #[admin_command]
-pub(super) async fn list_users(&self) -> Result<RoomMessageEventContent> {
-	let users: Vec<_> = self
-		.services
-		.users
-		.list_local_users()
-		.map(ToString::to_string)
-		.collect()
-		.await;
+pub(super) async fn list_users(
+	&self,
+	filter: Option<String>,
+	deactivated: bool,
+	admin: bool,
+	page: Option<usize>,
+	sort: UserSort,
+	json: bool,
+) -> Result<RoomMessageEventContent> {
+	let services = self.services;
+	let page = page.unwrap_or(1).max(1);
+	let filter = filter.map(|filter| {
+		if filter.starts_with(['@', '*', '?']) {
+			filter
+		} else {
+			format!("@{filter}")
+		}
+	});
+
+	// Admins are found through the admin room's members and everyone else
+	// through the user index, narrowed to the filter's literal prefix.
+	let candidates: Vec<OwnedUserId> = if admin {
+		let Ok(admin_room) = services.admin.get_admin_room().await else {
+			return Ok(RoomMessageEventContent::text_plain("There is no admin room."));
+		};
+
+		services
+			.rooms
+			.state_cache
+			.room_members(&admin_room)
+			.ready_filter(|user_id| services.globals.user_is_local(user_id))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await
+	} else {
+		let prefix = filter.as_deref().map_or("", string::glob_prefix);
+		services
+			.users
+			.stream_prefix(prefix)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await
+	};
+
+	let mut users = Vec::with_capacity(candidates.len());
+	for user_id in candidates {
+		if filter
+			.as_deref()
+			.is_some_and(|filter| !string::glob_match(filter, user_id.as_str()))
+		{
+			continue;
+		}
+
+		let is_active = services.users.is_active(&user_id).await;
+		if !is_active && !deactivated {
+			continue;
+		}
+
+		users.push(UserRow {
+			created_at: services.users.created_at(&user_id).await,
+			last_seen: services.users.last_seen(&user_id).await,
+			devices: services.users.all_device_ids(&user_id).count().await,
+			admin: admin || services.users.is_admin(&user_id).await,
+			deactivated: !is_active,
+			user_id,
+		});
+	}
+
+	match sort {
+		| UserSort::Name => users.sort_by(|a, b| a.user_id.cmp(&b.user_id)),
+		| UserSort::Creation => users.sort_by_key(|user| user.created_at),
+		| UserSort::LastSeen => users.sort_by(|a, b| b.last_seen.cmp(&a.last_seen)),
+		| UserSort::Devices => users.sort_by(|a, b| b.devices.cmp(&a.devices)),
+	}
+
+	let total = users.len();
+	let users: Vec<_> = users
+		.into_iter()
+		.skip(page.saturating_sub(1).saturating_mul(PAGE_SIZE))
+		.take(PAGE_SIZE)
+		.collect();
+
+	if json {
+		let users: Vec<_> = users
+			.iter()
+			.map(|user| {
+				serde_json::json!({
+					"user_id": user.user_id,
+					"created_at": user.created_at,
+					"last_seen": user.last_seen,
+					"devices": user.devices,
+					"admin": user.admin,
+					"deactivated": user.deactivated,
+				})
+			})
+			.collect();
+
+		let output = serde_json::to_string_pretty(&serde_json::json!({
+			"total": total,
+			"page": page,
+			"users": users,
+		}))?;
 
-	let mut plain_msg = format!("Found {} local user account(s):\n```\n", users.len());
-	plain_msg += users.join("\n").as_str();
-	plain_msg += "\n```";
+		return Ok(RoomMessageEventContent::notice_markdown(format!("```json\n{output}\n```")));
+	}
+
+	let format_ms = |ms: Option<u64>| {
+		ms.map_or_else(
+			|| "-".to_owned(),
+			|ms| time::format(UNIX_EPOCH + Duration::from_millis(ms), "%Y-%m-%d %H:%M"),
+		)
+	};
+
+	let mut plain_msg =
+		format!("Found {total} local user account(s), showing page {page}:\n```\n");
+	plain_msg += "USER ID\tCREATED\tLAST SEEN\tDEVICES\tFLAGS\n";
+	for user in &users {
+		let flags = [(user.admin, "admin"), (user.deactivated, "deactivated")]
+			.into_iter()
+			.filter_map(|(set, flag)| set.then_some(flag))
+			.collect::<Vec<_>>()
+			.join(",");
+
+		writeln!(
+			plain_msg,
+			"{}\t{}\t{}\t{}\t{flags}",
+			user.user_id,
+			format_ms(user.created_at),
+			format_ms(user.last_seen),
+			user.devices,
+		)?;
+	}
+	plain_msg += "```";
 
 	self.write_str(plain_msg.as_str()).await?;
 
 	Ok(RoomMessageEventContent::text_plain(""))
 }
 
+/// A row of the `users list` output.
+struct UserRow {
+	user_id: OwnedUserId,
+	created_at: Option<u64>,
+	last_seen: Option<u64>,
+	devices: usize,
+	admin: bool,
+	deactivated: bool,
+}
+
 #[admin_command]
 pub(super) async fn create_user(
 	&self,
@@ -75,6 +217,7 @@ pub(super) async fn create_user(
 	self.services
 		.users
 		.create(&user_id, Some(password.as_str()))?;
+	self.services.account_validity.start(&user_id).await;
 
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
@@ -107,68 +250,61 @@ pub(super) async fn create_user(
 				.into(),
 			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
 				content: ruma::events::push_rules::PushRulesEventContent {
-					global: ruma::push::Ruleset::server_default(&user_id),
+					global: services.pusher.server_default_rules(&user_id),
 				},
 			})
 			.expect("to json value always works"),
 		)
 		.await?;
 
-	if !self.services.server.config.auto_join_rooms.is_empty() {
-		for room in &self.services.server.config.auto_join_rooms {
-			let Ok(room_id) = self.services.rooms.alias.resolve(room).await else {
-				error!(%user_id, "Failed to resolve room alias to room ID when attempting to auto join {room}, skipping");
-				continue;
-			};
-
-			if !self
-				.services
-				.rooms
-				.state_cache
-				.server_in_room(self.services.globals.server_name(), &room_id)
-				.await
-			{
-				warn!(
-					"Skipping room {room} to automatically join as we have never joined before."
-				);
-				continue;
-			}
+	for room in &self.services.server.config.auto_join_rooms {
+		let Ok((room_id, resolved_servers)) = self
+			.services
+			.rooms
+			.alias
+			.resolve_with_servers(room, None)
+			.await
+		else {
+			error!(%user_id, "Failed to resolve room alias to room ID when attempting to auto join {room}, skipping");
+			continue;
+		};
 
-			if let Some(room_server_name) = room.server_name() {
-				match join_room_by_id_helper(
-					self.services,
-					&user_id,
-					&room_id,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[
-						self.services.globals.server_name().to_owned(),
-						room_server_name.to_owned(),
-					],
-					None,
-					&None,
-				)
-				.await
-				{
-					| Ok(_response) => {
-						info!("Automatically joined room {room} for user {user_id}");
-					},
-					| Err(e) => {
-						self.services
-							.admin
-							.send_message(RoomMessageEventContent::text_plain(format!(
-								"Failed to automatically join room {room} for user {user_id}: \
-								 {e}"
-							)))
-							.await
-							.ok();
-						// don't return this error so we don't fail registrations
-						error!(
-							"Failed to automatically join room {room} for user {user_id}: {e}"
-						);
-					},
-				};
+		let mut servers = vec![self.services.globals.server_name().to_owned()];
+		for server in resolved_servers
+			.into_iter()
+			.chain(room.server_name().map(ToOwned::to_owned))
+		{
+			if !servers.contains(&server) {
+				servers.push(server);
 			}
 		}
+
+		match join_room_by_id_helper(
+			self.services,
+			&user_id,
+			&room_id,
+			Some("Automatically joining this room upon registration".to_owned()),
+			&servers,
+			None,
+			&None,
+		)
+		.await
+		{
+			| Ok(_response) => {
+				info!("Automatically joined room {room} for user {user_id}");
+			},
+			| Err(e) => {
+				self.services
+					.admin
+					.send_message(RoomMessageEventContent::text_plain(format!(
+						"Failed to automatically join room {room} for user {user_id}: {e}"
+					)))
+					.await
+					.ok();
+				// don't return this error so we don't fail registrations
+				error!("Failed to automatically join room {room} for user {user_id}: {e}");
+			},
+		};
 	}
 
 	// we dont add a device since we're not the user, just the creator
@@ -200,6 +336,7 @@ pub(super) async fn create_user(
 pub(super) async fn deactivate(
 	&self,
 	no_leave_rooms: bool,
+	erase: bool,
 	user_id: String,
 ) -> Result<RoomMessageEventContent> {
 	// Validate user id
@@ -232,17 +369,91 @@ pub(super) async fn deactivate(
 			.collect()
 			.await;
 
-		full_user_deactivate(self.services, &user_id, &all_joined_rooms).await?;
+		full_user_deactivate(self.services, &user_id, &all_joined_rooms, erase).await?;
 		update_displayname(self.services, &user_id, None, &all_joined_rooms).await;
 		update_avatar_url(self.services, &user_id, None, None, &all_joined_rooms).await;
 		leave_all_rooms(self.services, &user_id).await;
 	}
 
+	if erase {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} has been deactivated and erased"
+		)));
+	}
+
 	Ok(RoomMessageEventContent::text_plain(format!(
 		"User {user_id} has been deactivated"
 	)))
 }
 
+#[admin_command]
+pub(super) async fn export_account(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let export = self.services.export.export_account(&user_id).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Exported {user_id} with {} media files ({} bytes) to `{}`",
+		export.media,
+		export.size,
+		export.path.display()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn import_account(
+	&self,
+	path: PathBuf,
+	username: String,
+	password: Option<String>,
+	join_rooms: bool,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &username)?;
+	let import = self.services.export.import_account(&path, &user_id).await?;
+
+	let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+	self.services
+		.users
+		.set_password(&user_id, Some(password.as_str()))?;
+
+	let mut msg = format!(
+		"Imported {user_id} with {} media files and {} backed up room keys, password: \
+		 `{password}`",
+		import.media, import.backup_keys
+	);
+
+	if join_rooms {
+		let mut joined: usize = 0;
+		for room_id in &import.rooms {
+			let servers: Vec<_> = [self.services.globals.server_name()]
+				.into_iter()
+				.chain(room_id.server_name())
+				.map(ToOwned::to_owned)
+				.collect();
+
+			match join_room_by_id_helper(
+				self.services,
+				&user_id,
+				room_id,
+				None,
+				&servers,
+				None,
+				&None,
+			)
+			.await
+			{
+				| Ok(_) => joined = joined.saturating_add(1),
+				| Err(e) => warn!(%user_id, %room_id, "Failed to rejoin room: {e}"),
+			}
+		}
+
+		write!(msg, "\nRejoined {joined} of {} rooms", import.rooms.len())?;
+	} else if !import.rooms.is_empty() {
+		write!(msg, "\nThe user was in {} rooms they have to rejoin", import.rooms.len())?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
 #[admin_command]
 pub(super) async fn reset_password(
 	&self,
@@ -258,6 +469,12 @@ pub(super) async fn reset_password(
 		));
 	}
 
+	if self.services.users.is_erased(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to set the password for an erased user.",
+		));
+	}
+
 	let new_password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 
 	match self
@@ -274,6 +491,225 @@ pub(super) async fn reset_password(
 	}
 }
 
+#[admin_command]
+pub(super) async fn suspend_user(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to suspend the server service account.",
+		));
+	}
+
+	if self.services.users.is_admin(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to suspend an admin account. Remove its admin status first.",
+		));
+	}
+
+	if self.services.users.is_suspended(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is already suspended"
+		)));
+	}
+
+	self.services.users.suspend(&user_id);
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been suspended"
+	)))
+}
+
+#[admin_command]
+pub(super) async fn renew_account(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.account_validity.is_enabled() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Account expiry is disabled; set account_validity_period to enable it.",
+		));
+	}
+
+	let expires_at = self.services.account_validity.renew(&user_id).await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Account of {user_id} renewed until {}",
+		time::format(UNIX_EPOCH + Duration::from_millis(expires_at), "%+")
+	)))
+}
+
+#[admin_command]
+pub(super) async fn set_account_expiry(
+	&self,
+	user_id: String,
+	expires_in: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	let Some(expires_in) = expires_in
+		.as_deref()
+		.map(time::parse_duration)
+		.transpose()?
+	else {
+		self.services
+			.account_validity
+			.set_expires_at(&user_id, None)
+			.await;
+
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Account of {user_id} will never expire"
+		)));
+	};
+
+	let expires_at = utils::millis_since_unix_epoch()
+		.saturating_add(expires_in.as_millis().try_into().unwrap_or(u64::MAX));
+
+	self.services
+		.account_validity
+		.set_expires_at(&user_id, Some(expires_at))
+		.await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Account of {user_id} will expire in {}",
+		time::pretty(expires_in)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unsuspend_user(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.unsuspend(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is not suspended"
+		)));
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} is no longer suspended"
+	)))
+}
+
+#[admin_command]
+pub(super) async fn login_as(
+	&self,
+	user_id: String,
+	valid_for: Option<u64>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to log in as the server service account.",
+		));
+	}
+
+	// The reply would keep the token in the admin room's history for anyone who
+	// can read it, long after it was needed.
+	if self.reply_id.is_some() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Access tokens are not posted to the admin room. Run this command from the server \
+			 console, or use the `POST /_synapse/admin/v1/users/{userId}/login` admin API.",
+		));
+	}
+
+	let issued_by = "the server console";
+
+	let lifetime = Duration::from_secs(
+		valid_for.unwrap_or(self.services.server.config.admin_login_token_lifetime),
+	);
+
+	let (device_id, token) = self
+		.services
+		.users
+		.create_admin_login(&user_id, issued_by, lifetime)
+		.await?;
+
+	info!("{issued_by} logged in as {user_id} on device {device_id} through an admin command");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Logged in as {user_id} on device {device_id} for {}: `{token}`",
+		time::pretty(lifetime)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn recount_notifications(
+	&self,
+	user_id: String,
+	room_id: Option<OwnedRoomOrAliasId>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	let rooms: Vec<OwnedRoomId> = match room_id {
+		| Some(room_id) => vec![self.services.rooms.alias.resolve(&room_id).await?],
+		| None =>
+			self.services
+				.rooms
+				.state_cache
+				.rooms_joined(&user_id)
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+	};
+
+	let mut changed = String::new();
+	for room_id in &rooms {
+		let before = (
+			self.services
+				.rooms
+				.user
+				.notification_count(&user_id, room_id)
+				.await,
+			self.services
+				.rooms
+				.user
+				.highlight_count(&user_id, room_id)
+				.await,
+		);
+
+		let after = self
+			.services
+			.rooms
+			.timeline
+			.recount_notifications(&user_id, room_id)
+			.await?;
+
+		if before != after {
+			writeln!(
+				changed,
+				"- {room_id}: {} notifications, {} highlights (was {}, {})",
+				after.0, after.1, before.0, before.1
+			)?;
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Recounted notifications of {user_id} in {} rooms.\n{changed}",
+		rooms.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn allow_cross_signing_reset(
+	&self,
+	user_id: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.exists(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("User {user_id} does not exist")));
+	}
+
+	let expires_at = self.services.users.allow_cross_signing_reset(&user_id);
+	let expires_at = time::rfc2822_from_seconds((expires_at / 1000).try_into()?);
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} may set up or reset their cross-signing keys without authenticating \
+		 until {expires_at}"
+	)))
+}
+
 #[admin_command]
 pub(super) async fn deactivate_all(
 	&self,
@@ -357,7 +793,8 @@ pub(super) async fn deactivate_all(
 						.collect()
 						.await;
 
-					full_user_deactivate(self.services, &user_id, &all_joined_rooms).await?;
+					full_user_deactivate(self.services, &user_id, &all_joined_rooms, false)
+						.await?;
 					update_displayname(self.services, &user_id, None, &all_joined_rooms).await;
 					update_avatar_url(self.services, &user_id, None, None, &all_joined_rooms)
 						.await;
@@ -532,14 +969,12 @@ pub(super) async fn force_join_list_of_local_users(
 	let mut successful_joins: usize = 0;
 
 	for user_id in user_ids {
-		match join_room_by_id_helper(
+		match force_join_room_by_id_helper(
 			self.services,
 			&user_id,
 			&room_id,
 			Some(String::from(BULK_JOIN_REASON)),
 			&servers,
-			None,
-			&None,
 		)
 		.await
 		{
@@ -628,14 +1063,12 @@ pub(super) async fn force_join_all_local_users(
 		.collect::<Vec<_>>()
 		.await
 	{
-		match join_room_by_id_helper(
+		match force_join_room_by_id_helper(
 			self.services,
 			user_id,
 			&room_id,
 			Some(String::from(BULK_JOIN_REASON)),
 			&servers,
-			None,
-			&None,
 		)
 		.await
 		{
@@ -673,8 +1106,7 @@ pub(super) async fn force_join_room(
 		self.services.globals.user_is_local(&user_id),
 		"Parsed user_id must be a local user"
 	);
-	join_room_by_id_helper(self.services, &user_id, &room_id, None, &servers, None, &None)
-		.await?;
+	force_join_room_by_id_helper(self.services, &user_id, &room_id, None, &servers).await?;
 
 	Ok(RoomMessageEventContent::notice_markdown(format!(
 		"{user_id} has been joined to {room_id}.",
@@ -784,6 +1216,54 @@ pub(super) async fn make_user_admin(&self, user_id: String) -> Result<RoomMessag
 	)))
 }
 
+#[admin_command]
+pub(super) async fn list_login_lockouts(&self) -> Result<RoomMessageEventContent> {
+	let mut lockouts = self.services.login_attempts.lockouts();
+	lockouts.sort_by_key(|(_, remaining)| *remaining);
+
+	let mut plain_msg = format!("Found {} login lockout(s):\n```\n", lockouts.len());
+	for (target, remaining) in lockouts {
+		writeln!(plain_msg, "{target}: {} remaining", time::pretty(remaining))?;
+	}
+	plain_msg += "```";
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
+
+#[admin_command]
+pub(super) async fn clear_login_lockout(
+	&self,
+	target: Option<String>,
+	all: bool,
+) -> Result<RoomMessageEventContent> {
+	if all {
+		let lifted = self.services.login_attempts.clear_all();
+
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Lifted {lifted} login lockout(s) and forgot all failed attempts."
+		)));
+	}
+
+	let Some(target) = target else {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Specify a user ID or IP address, or --all.",
+		));
+	};
+
+	let target = match target.parse::<IpAddr>() {
+		| Ok(ip) => Target::Ip(ip),
+		| Err(_) => Target::User(parse_user_id(self.services, &target)?),
+	};
+
+	let message = if self.services.login_attempts.clear(&target) {
+		format!("Lifted the login lockout of {target}.")
+	} else {
+		format!("{target} was not locked out; forgot its failed attempts.")
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(message))
+}
+
 #[admin_command]
 pub(super) async fn put_room_tag(
 	&self,
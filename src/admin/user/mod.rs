@@ -1,6 +1,8 @@
 mod commands;
 
-use clap::Subcommand;
+use std::path::PathBuf;
+
+use clap::{Subcommand, ValueEnum};
 use conduwuit::Result;
 use ruma::{EventId, OwnedRoomOrAliasId, RoomId};
 
@@ -18,6 +20,36 @@ pub(super) enum UserCommand {
 		password: Option<String>,
 	},
 
+	/// - Export a local user's account to a tar archive in `export_path`
+	///
+	/// The archive holds their profile, devices, account data, latest key
+	/// backup, room memberships and uploaded media, and can be imported on
+	/// another conduwuit server with `import-account`.
+	ExportAccount {
+		user_id: String,
+	},
+
+	/// - Create a local user from an archive made by `export-account`
+	///
+	/// Media is re-uploaded under new MXC URIs and the user gets a new
+	/// password. Devices are not restored. With --join-rooms, the user
+	/// rejoins the rooms they were in where they still can.
+	ImportAccount {
+		/// Path to the archive on the server
+		path: PathBuf,
+
+		/// Username of the new user
+		username: String,
+
+		/// Password of the new user, if unspecified one is generated
+		#[arg(long)]
+		password: Option<String>,
+
+		/// Rejoin the rooms the user was joined to
+		#[arg(long)]
+		join_rooms: bool,
+	},
+
 	/// - Reset user password
 	ResetPassword {
 		/// Username of the user for whom the password should be reset
@@ -30,9 +62,16 @@ pub(super) enum UserCommand {
 	///
 	/// User will be removed from all rooms by default.
 	/// Use --no-leave-rooms to not leave all rooms by default.
+	///
+	/// Use --erase to also erase the user's data (GDPR): the messages they sent
+	/// in the rooms they are joined to are redacted, and their media, profile
+	/// and account data deleted. State events they sent are kept. Erased users
+	/// cannot be reactivated.
 	Deactivate {
 		#[arg(short, long)]
 		no_leave_rooms: bool,
+		#[arg(long, conflicts_with = "no_leave_rooms")]
+		erase: bool,
 		user_id: String,
 	},
 
@@ -59,9 +98,104 @@ pub(super) enum UserCommand {
 		force: bool,
 	},
 
+	/// - Suspend a local user (MSC3823)
+	///
+	/// Suspended users keep their account and rooms, but cannot log in, join
+	/// rooms, invite or send events other than leaving rooms and redactions
+	/// until unsuspended.
+	SuspendUser {
+		user_id: String,
+	},
+
+	/// - Lift the suspension of a local user
+	UnsuspendUser {
+		user_id: String,
+	},
+
+	/// - Issue an access token to act as a local user
+	///
+	/// The token belongs to a new device, which the user can see among their
+	/// devices, and expires after --valid-for seconds
+	/// (`admin_login_token_lifetime` by default); the device is removed soon
+	/// after. Useful to debug user-specific issues or repair a bridge without
+	/// the user's password.
+	///
+	/// Only available from the server console, as the token would otherwise
+	/// stay in the admin room's history; admins can use the admin API instead.
+	LoginAs {
+		user_id: String,
+		#[arg(long)]
+		valid_for: Option<u64>,
+	},
+
+	/// - Renew a local user's account for another `account_validity_period`
+	///
+	/// Expired accounts may be used again right away.
+	RenewAccount {
+		user_id: String,
+	},
+
+	/// - Set when a local user's account expires
+	///
+	/// The duration is counted from now, e.g. "30d". Without one, the account
+	/// never expires.
+	SetAccountExpiry {
+		user_id: String,
+		expires_in: Option<String>,
+	},
+
+	/// - Allow a local user to reset their cross-signing keys
+	///
+	/// For the next 10 minutes the user may set up or replace their
+	/// cross-signing keys without authenticating, e.g. after losing all their
+	/// devices or when they cannot complete password authentication. Their
+	/// other sessions are told of the new keys and the reset is noted in the
+	/// admin room.
+	AllowCrossSigningReset {
+		user_id: String,
+	},
+
+	/// - Recompute a local user's unread notification and highlight counts
+	///
+	/// Counts are recounted from the events after their latest read receipt,
+	/// in one room or in every room they are joined to, to fix stuck badges.
+	RecountNotifications {
+		user_id: String,
+
+		room_id: Option<OwnedRoomOrAliasId>,
+	},
+
 	/// - List local users in the database
+	///
+	/// Users are listed 100 to a page, and can be filtered by a glob pattern
+	/// on their user ID (e.g. `@bot_*`) and sorted by creation time, when they
+	/// were last seen or how many devices they have.
 	#[clap(alias = "list")]
-	ListUsers,
+	ListUsers {
+		/// Only list users whose user ID matches this glob pattern
+		#[arg(long)]
+		filter: Option<String>,
+
+		/// Also list deactivated users
+		#[arg(long)]
+		deactivated: bool,
+
+		/// Only list server admins
+		#[arg(long)]
+		admin: bool,
+
+		#[arg(long)]
+		page: Option<usize>,
+
+		/// Sort by user ID, by creation time (oldest first), or by last seen
+		/// time or device count (highest first)
+		#[arg(long, value_enum, default_value_t)]
+		sort: UserSort,
+
+		/// Output the users as JSON for scripting
+		#[arg(long)]
+		json: bool,
+	},
 
 	/// - Lists all the rooms (local and remote) that the specified user is
 	///   joined in
@@ -70,6 +204,9 @@ pub(super) enum UserCommand {
 	},
 
 	/// - Manually join a local user to a room.
+	///
+	/// If the room isn't public, one of our users in the room who may invite
+	/// them invites them first.
 	ForceJoinRoom {
 		user_id: String,
 		room_id: OwnedRoomOrAliasId,
@@ -93,6 +230,21 @@ pub(super) enum UserCommand {
 		user_id: String,
 	},
 
+	/// - List accounts and IP addresses locked out after too many failed
+	///   password attempts.
+	ListLoginLockouts,
+
+	/// - Lift the lockout of an account or IP address after too many failed
+	///   password attempts, and forget its failed attempts.
+	ClearLoginLockout {
+		/// User ID, localpart or IP address to lift the lockout of
+		target: Option<String>,
+
+		/// Lift all lockouts
+		#[arg(long)]
+		all: bool,
+	},
+
 	/// - Puts a room tag for the specified user and room ID.
 	///
 	/// This is primarily useful if you'd like to set your admin room
@@ -154,3 +306,12 @@ pub(super) enum UserCommand {
 		yes_i_want_to_do_this: bool,
 	},
 }
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub(super) enum UserSort {
+	#[default]
+	Name,
+	Creation,
+	LastSeen,
+	Devices,
+}
@@ -0,0 +1,238 @@
+use std::time::UNIX_EPOCH;
+
+use axum::extract::State;
+use conduwuit::{info, utils::stream::TryIgnore, Err, Result};
+use futures::{stream, StreamExt};
+use ruma::{Mxc, OwnedMxcUri};
+use serde::{Deserialize, Serialize};
+use service::media::content_mxcs;
+
+use super::check_admin;
+use crate::Ruma;
+
+/// # `GET /_synapse/admin/v1/users/{user_id}/media`
+///
+/// Lists the media uploaded by a local user.
+pub(crate) async fn list_user_media_route(
+	State(services): State<crate::State>,
+	body: Ruma<list_user_media::Request>,
+) -> Result<list_user_media::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	if !services.globals.user_is_local(&body.user_id) {
+		return Err!(Request(InvalidParam("Can only look up local users.")));
+	}
+
+	let from = body.from.unwrap_or(0);
+	let limit = body.limit.unwrap_or(100);
+
+	let mxcs = services.media.get_all_user_mxcs(&body.user_id).await;
+	let total = mxcs.len();
+
+	let mut media = Vec::with_capacity(limit.min(total));
+	for mxc in mxcs.iter().skip(from).take(limit) {
+		let Ok(mxc) = Mxc::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		let meta = services.media.get_metadata(&mxc).await;
+		let stat = services.media.get_file_stat(&mxc).await.ok();
+		media.push(MediaInfo {
+			media_id: mxc.media_id.to_owned(),
+			media_type: meta.as_ref().and_then(|meta| meta.content_type.clone()),
+			upload_name: meta
+				.and_then(|meta| meta.content_disposition)
+				.and_then(|disposition| disposition.filename),
+			media_length: stat.as_ref().map(std::fs::Metadata::len),
+			created_ts: stat
+				.and_then(|stat| stat.modified().ok())
+				.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+				.and_then(|since| since.as_millis().try_into().ok()),
+		});
+	}
+
+	let next = from.saturating_add(limit);
+
+	Ok(list_user_media::Response {
+		media,
+		next_token: (next < total).then_some(next),
+		total,
+	})
+}
+
+/// # `GET /_synapse/admin/v1/room/{room_id}/media`
+///
+/// Lists the media referenced by the events of a room, split into media
+/// stored by this server and by other servers.
+pub(crate) async fn list_room_media_route(
+	State(services): State<crate::State>,
+	body: Ruma<list_room_media::Request>,
+) -> Result<list_room_media::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	if !services.rooms.metadata.exists(&body.room_id).await {
+		return Err!(Request(NotFound("Room not found.")));
+	}
+
+	let mut mxcs: Vec<OwnedMxcUri> = services
+		.rooms
+		.timeline
+		.pdus(None, &body.room_id, None)
+		.ignore_err()
+		.flat_map(|(_, pdu)| stream::iter(content_mxcs(&pdu.get_content_as_value())))
+		.collect()
+		.await;
+
+	mxcs.sort_unstable();
+	mxcs.dedup();
+
+	let (local, remote) = mxcs.into_iter().partition(|mxc| {
+		mxc.server_name()
+			.is_ok_and(|server_name| services.globals.server_is_ours(server_name))
+	});
+
+	Ok(list_room_media::Response { local, remote })
+}
+
+/// # `DELETE /_synapse/admin/v1/media/{server_name}/{media_id}`
+///
+/// Deletes a file from the media store.
+pub(crate) async fn delete_media_route(
+	State(services): State<crate::State>,
+	body: Ruma<delete_media::Request>,
+) -> Result<delete_media::Response> {
+	let sender_user = body.sender_user();
+	check_admin(&services, sender_user).await?;
+
+	let mxc = Mxc {
+		server_name: &body.server_name,
+		media_id: &body.media_id,
+	};
+
+	services.media.delete(&mxc).await?;
+
+	info!("{mxc} deleted by {sender_user} through the admin API");
+
+	Ok(delete_media::Response {
+		deleted_media: vec![body.media_id.clone()],
+		total: 1,
+	})
+}
+
+/// Media details as the Synapse admin API presents them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct MediaInfo {
+	media_id: String,
+	media_type: Option<String>,
+	upload_name: Option<String>,
+
+	/// Size of the file in bytes.
+	media_length: Option<u64>,
+
+	/// When the file was stored, in milliseconds since the unix epoch.
+	created_ts: Option<u64>,
+}
+
+pub(crate) mod list_user_media {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedUserId,
+	};
+
+	use super::MediaInfo;
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/users/:user_id/media",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) user_id: OwnedUserId,
+
+		/// Offset to continue listing at, from `next_token`.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) from: Option<usize>,
+
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) limit: Option<usize>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) media: Vec<MediaInfo>,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) next_token: Option<usize>,
+
+		pub(crate) total: usize,
+	}
+}
+
+pub(crate) mod list_room_media {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedMxcUri, OwnedRoomId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/room/:room_id/media",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) room_id: OwnedRoomId,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) local: Vec<OwnedMxcUri>,
+
+		pub(crate) remote: Vec<OwnedMxcUri>,
+	}
+}
+
+pub(crate) mod delete_media {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedServerName,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: DELETE,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/media/:server_name/:media_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) server_name: OwnedServerName,
+
+		#[ruma_api(path)]
+		pub(crate) media_id: String,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) deleted_media: Vec<String>,
+
+		pub(crate) total: usize,
+	}
+}
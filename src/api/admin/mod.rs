@@ -0,0 +1,25 @@
+//! Subset of the Synapse admin API (`/_synapse/admin`), so existing admin
+//! tools such as synapse-admin can manage this server. Every endpoint requires
+//! the access token of a server admin.
+
+pub(super) mod media;
+pub(super) mod rooms;
+pub(super) mod users;
+pub(super) mod version;
+
+use conduwuit::{Err, Result};
+pub(super) use media::*;
+pub(super) use rooms::*;
+use ruma::UserId;
+use service::Services;
+pub(super) use users::*;
+pub(super) use version::*;
+
+/// Rejects requests from users who are not server admins.
+async fn check_admin(services: &Services, sender_user: &UserId) -> Result {
+	if !services.users.is_admin(sender_user).await {
+		return Err!(Request(Forbidden("You are not a server admin.")));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,467 @@
+use axum::extract::State;
+use conduwuit::{info, utils::ReadyExt, warn, Err, Result};
+use futures::StreamExt;
+use ruma::{
+	events::StateEventType, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonObject, Value as JsonValue};
+use service::Services;
+
+use super::check_admin;
+use crate::{
+	client::{force_join_room_by_id_helper, leave_room},
+	Ruma,
+};
+
+/// # `GET /_synapse/admin/v1/rooms`
+///
+/// Lists the rooms known to the server, optionally filtered by room ID, name
+/// or canonical alias and sorted by one of their attributes.
+pub(crate) async fn list_rooms_route(
+	State(services): State<crate::State>,
+	body: Ruma<list_rooms::Request>,
+) -> Result<list_rooms::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let from = body.from.unwrap_or(0);
+	let limit = body.limit.unwrap_or(100);
+	let search_term = body.search_term.as_deref().map(str::to_lowercase);
+
+	let mut rooms: Vec<RoomInfo> = services
+		.rooms
+		.metadata
+		.iter_ids()
+		.map(ToOwned::to_owned)
+		.then(|room_id| room_info(&services, room_id))
+		.ready_filter(|room| {
+			search_term.as_deref().is_none_or(|term| {
+				[
+					Some(room.room_id.as_str()),
+					room.name.as_deref(),
+					room.canonical_alias.as_deref(),
+				]
+				.into_iter()
+				.flatten()
+				.any(|value| value.to_lowercase().contains(term))
+			})
+		})
+		.collect()
+		.await;
+
+	match body.order_by.as_deref().unwrap_or("name") {
+		| "canonical_alias" => rooms.sort_by(|a, b| a.canonical_alias.cmp(&b.canonical_alias)),
+		| "size" | "joined_members" => rooms.sort_by_key(|room| room.joined_members),
+		| "joined_local_members" => rooms.sort_by_key(|room| room.joined_local_members),
+		| "version" => rooms.sort_by(|a, b| a.version.cmp(&b.version)),
+		| "creator" => rooms.sort_by(|a, b| a.creator.cmp(&b.creator)),
+		| "encryption" => rooms.sort_by(|a, b| a.encryption.cmp(&b.encryption)),
+		| "federatable" => rooms.sort_by_key(|room| room.federatable),
+		| "public" => rooms.sort_by_key(|room| room.public),
+		| "join_rules" => rooms.sort_by(|a, b| a.join_rules.cmp(&b.join_rules)),
+		| "guest_access" => rooms.sort_by(|a, b| a.guest_access.cmp(&b.guest_access)),
+		| "history_visibility" =>
+			rooms.sort_by(|a, b| a.history_visibility.cmp(&b.history_visibility)),
+		| _ => rooms.sort_by(|a, b| a.name.cmp(&b.name)),
+	}
+
+	if body.dir.as_deref() == Some("b") {
+		rooms.reverse();
+	}
+
+	let total_rooms = rooms.len();
+	let rooms: Vec<_> = rooms.into_iter().skip(from).take(limit).collect();
+	let next = from.saturating_add(rooms.len());
+
+	Ok(list_rooms::Response {
+		offset: from,
+		total_rooms,
+		next_batch: (next < total_rooms).then_some(next),
+		prev_batch: (from > 0).then(|| from.saturating_sub(limit)),
+		rooms,
+	})
+}
+
+/// # `GET /_synapse/admin/v1/rooms/{room_id}`
+///
+/// Gets the details of a room known to the server.
+pub(crate) async fn get_room_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_room::Request>,
+) -> Result<get_room::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	if !services.rooms.metadata.exists(&body.room_id).await {
+		return Err!(Request(NotFound("Room not found.")));
+	}
+
+	let topic = services
+		.rooms
+		.state_accessor
+		.get_room_topic(&body.room_id)
+		.await
+		.ok();
+
+	Ok(get_room::Response {
+		room: room_info(&services, body.room_id.clone()).await,
+		topic,
+	})
+}
+
+/// # `DELETE /_synapse/admin/v1/rooms/{room_id}`
+///
+/// Removes all local users from a room, its local aliases and its room
+/// directory entry. If `block` is set, local users may not join it again and
+/// federation with it is disabled. The room's history stays in the database.
+pub(crate) async fn delete_room_route(
+	State(services): State<crate::State>,
+	body: Ruma<delete_room::Request>,
+) -> Result<delete_room::Response> {
+	let sender_user = body.sender_user();
+	check_admin(&services, sender_user).await?;
+
+	let room_id = &body.room_id;
+	if services
+		.admin
+		.get_admin_room()
+		.await
+		.is_ok_and(|admin_room_id| admin_room_id == *room_id)
+	{
+		return Err!(Request(Forbidden("Not allowed to delete the admin room.")));
+	}
+
+	if body.block {
+		services.rooms.metadata.ban_room(room_id, true);
+		services.rooms.metadata.disable_room(room_id, true);
+	}
+
+	let local_users: Vec<OwnedUserId> = services
+		.rooms
+		.state_cache
+		.local_users_in_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut kicked_users = Vec::with_capacity(local_users.len());
+	let mut failed_to_kick_users = Vec::new();
+	for user_id in local_users {
+		match leave_room(&services, &user_id, room_id, None).await {
+			| Ok(()) => kicked_users.push(user_id),
+			| Err(e) => {
+				warn!(%user_id, %room_id, "Failed to remove user from room: {e}");
+				failed_to_kick_users.push(user_id);
+			},
+		}
+	}
+
+	let local_aliases: Vec<OwnedRoomAliasId> = services
+		.rooms
+		.alias
+		.local_aliases_for_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for alias in &local_aliases {
+		if let Err(e) = services
+			.rooms
+			.alias
+			.remove_alias(alias, &services.globals.server_user)
+			.await
+		{
+			warn!(%alias, "Failed to remove room alias: {e}");
+		}
+	}
+
+	services.rooms.directory.set_not_public(room_id);
+
+	info!("{room_id} deleted by {sender_user} through the admin API");
+
+	Ok(delete_room::Response {
+		kicked_users,
+		failed_to_kick_users,
+		local_aliases,
+		new_room_id: None,
+	})
+}
+
+/// # `POST /_synapse/admin/v1/join/{room_id_or_alias}`
+///
+/// Joins a local user to a room without their client. Rooms that aren't
+/// public are joined through an invite from one of our users in the room.
+pub(crate) async fn join_room_route(
+	State(services): State<crate::State>,
+	body: Ruma<join_room::Request>,
+) -> Result<join_room::Response> {
+	let sender_user = body.sender_user();
+	check_admin(&services, sender_user).await?;
+
+	let user_id = &body.user_id;
+	if !services.globals.user_is_local(user_id) || !services.users.is_active(user_id).await {
+		return Err!(Request(InvalidParam("Can only join active local users to rooms.")));
+	}
+
+	let (room_id, servers) = services
+		.rooms
+		.alias
+		.resolve_with_servers(&body.room_id_or_alias, None)
+		.await?;
+
+	force_join_room_by_id_helper(&services, user_id, &room_id, None, &servers).await?;
+
+	info!("{user_id} joined to {room_id} by {sender_user} through the admin API");
+
+	Ok(join_room::Response { room_id })
+}
+
+async fn room_info(services: &Services, room_id: OwnedRoomId) -> RoomInfo {
+	let state_accessor = &services.rooms.state_accessor;
+	let create = state_accessor
+		.room_state_get(&room_id, &StateEventType::RoomCreate, "")
+		.await
+		.ok();
+
+	let create_content = create
+		.as_ref()
+		.and_then(|pdu| pdu.get_content::<JsonObject<String, JsonValue>>().ok());
+
+	RoomInfo {
+		name: state_accessor.get_name(&room_id).await.ok(),
+		canonical_alias: state_accessor.get_canonical_alias(&room_id).await.ok(),
+		joined_members: services
+			.rooms
+			.state_cache
+			.room_joined_count(&room_id)
+			.await
+			.unwrap_or(0),
+		joined_local_members: services
+			.rooms
+			.state_cache
+			.local_users_in_room(&room_id)
+			.count()
+			.await,
+		version: services.rooms.state.get_room_version(&room_id).await.ok(),
+		creator: create.map(|pdu| pdu.sender),
+		encryption: state_field(services, &room_id, StateEventType::RoomEncryption, "algorithm")
+			.await,
+		federatable: create_content
+			.as_ref()
+			.and_then(|content| content.get("m.federate"))
+			.and_then(JsonValue::as_bool)
+			.unwrap_or(true),
+		public: services.rooms.directory.is_public_room(&room_id).await,
+		join_rules: state_field(services, &room_id, StateEventType::RoomJoinRules, "join_rule")
+			.await,
+		guest_access: state_field(
+			services,
+			&room_id,
+			StateEventType::RoomGuestAccess,
+			"guest_access",
+		)
+		.await,
+		history_visibility: state_field(
+			services,
+			&room_id,
+			StateEventType::RoomHistoryVisibility,
+			"history_visibility",
+		)
+		.await,
+		room_type: create_content
+			.as_ref()
+			.and_then(|content| content.get("type"))
+			.and_then(JsonValue::as_str)
+			.map(ToOwned::to_owned),
+		room_id,
+	}
+}
+
+/// A string field of a room's state event with an empty state key.
+async fn state_field(
+	services: &Services,
+	room_id: &RoomId,
+	event_type: StateEventType,
+	field: &str,
+) -> Option<String> {
+	services
+		.rooms
+		.state_accessor
+		.room_state_get_content::<JsonObject<String, JsonValue>>(room_id, &event_type, "")
+		.await
+		.ok()
+		.and_then(|content| content.get(field)?.as_str().map(ToOwned::to_owned))
+}
+
+/// Room details as the Synapse admin API presents them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct RoomInfo {
+	room_id: OwnedRoomId,
+	name: Option<String>,
+	canonical_alias: Option<OwnedRoomAliasId>,
+	joined_members: u64,
+	joined_local_members: usize,
+	version: Option<RoomVersionId>,
+	creator: Option<OwnedUserId>,
+	encryption: Option<String>,
+	federatable: bool,
+	public: bool,
+	join_rules: Option<String>,
+	guest_access: Option<String>,
+	history_visibility: Option<String>,
+	room_type: Option<String>,
+}
+
+pub(crate) mod list_rooms {
+	use ruma::api::{metadata, request, response, Metadata};
+
+	use super::RoomInfo;
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/rooms",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		/// Offset to continue listing at, from `next_batch`.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) from: Option<usize>,
+
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) limit: Option<usize>,
+
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) order_by: Option<String>,
+
+		/// `f` for ascending or `b` for descending order.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) dir: Option<String>,
+
+		/// Only rooms whose ID, name or canonical alias contains this.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) search_term: Option<String>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) rooms: Vec<RoomInfo>,
+
+		pub(crate) offset: usize,
+
+		pub(crate) total_rooms: usize,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) next_batch: Option<usize>,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) prev_batch: Option<usize>,
+	}
+}
+
+pub(crate) mod get_room {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedRoomId,
+	};
+
+	use super::RoomInfo;
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/rooms/:room_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) room_id: OwnedRoomId,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		#[serde(flatten)]
+		pub(crate) room: RoomInfo,
+
+		pub(crate) topic: Option<String>,
+	}
+}
+
+pub(crate) mod delete_room {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedRoomAliasId, OwnedRoomId, OwnedUserId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: DELETE,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/rooms/:room_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) room_id: OwnedRoomId,
+
+		/// Whether to prevent local users from joining the room again.
+		#[serde(default)]
+		pub(crate) block: bool,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) kicked_users: Vec<OwnedUserId>,
+
+		pub(crate) failed_to_kick_users: Vec<OwnedUserId>,
+
+		pub(crate) local_aliases: Vec<OwnedRoomAliasId>,
+
+		/// Always null, as no replacement room is created.
+		pub(crate) new_room_id: Option<OwnedRoomId>,
+	}
+}
+
+pub(crate) mod join_room {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/join/:room_id_or_alias",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) room_id_or_alias: OwnedRoomOrAliasId,
+
+		pub(crate) user_id: OwnedUserId,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) room_id: OwnedRoomId,
+	}
+}
@@ -0,0 +1,566 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::extract::State;
+use conduwuit::{
+	err, info,
+	utils::{self, ReadyExt},
+	Err, Result,
+};
+use futures::StreamExt;
+use ruma::{
+	events::room::message::RoomMessageEventContent, OwnedMxcUri, OwnedRoomId, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use service::Services;
+
+use super::check_admin;
+use crate::{
+	client::{full_user_deactivate, leave_all_rooms},
+	Ruma,
+};
+
+/// # `GET /_synapse/admin/v2/users`
+///
+/// Lists the local users, optionally filtered by user ID or display name and
+/// sorted by one of their attributes.
+pub(crate) async fn list_users_route(
+	State(services): State<crate::State>,
+	body: Ruma<list_users::Request>,
+) -> Result<list_users::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let from = body.from.unwrap_or(0);
+	let limit = body.limit.unwrap_or(100);
+	let id_filter = body.user_id.as_deref().map(str::to_lowercase);
+	let name_filter = body.name.as_deref().map(str::to_lowercase);
+
+	let mut users: Vec<UserInfo> = services
+		.users
+		.stream()
+		.map(ToOwned::to_owned)
+		.then(|user_id| user_info(&services, user_id))
+		.ready_filter(|user| body.deactivated.unwrap_or(false) || !user.deactivated)
+		.ready_filter(|user| body.admins.is_none_or(|admins| user.admin == admins))
+		.ready_filter(|user| {
+			id_filter
+				.as_deref()
+				.is_none_or(|filter| user.name.as_str().to_lowercase().contains(filter))
+		})
+		.ready_filter(|user| {
+			name_filter.as_deref().is_none_or(|filter| {
+				user.name.as_str().to_lowercase().contains(filter)
+					|| user
+						.displayname
+						.as_deref()
+						.is_some_and(|displayname| displayname.to_lowercase().contains(filter))
+			})
+		})
+		.collect()
+		.await;
+
+	match body.order_by.as_deref().unwrap_or("name") {
+		| "displayname" => users.sort_by(|a, b| a.displayname.cmp(&b.displayname)),
+		| "admin" => users.sort_by_key(|user| user.admin),
+		| "deactivated" => users.sort_by_key(|user| user.deactivated),
+		| "suspended" => users.sort_by_key(|user| user.suspended),
+		| "creation_ts" => users.sort_by_key(|user| user.creation_ts),
+		| "last_seen_ts" => users.sort_by_key(|user| user.last_seen_ts),
+		| _ => users.sort_by(|a, b| a.name.cmp(&b.name)),
+	}
+
+	if body.dir.as_deref() == Some("b") {
+		users.reverse();
+	}
+
+	let total = users.len();
+	let users: Vec<_> = users.into_iter().skip(from).take(limit).collect();
+	let next = from.saturating_add(users.len());
+	let next_token = (next < total).then(|| next.to_string());
+
+	Ok(list_users::Response { users, next_token, total })
+}
+
+/// # `GET /_synapse/admin/v2/users/{user_id}`
+///
+/// Gets the account details of a local user.
+pub(crate) async fn get_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_user::Request>,
+) -> Result<get_user::Response> {
+	check_admin(&services, body.sender_user()).await?;
+	check_local_user(&services, &body.user_id).await?;
+
+	let threepids = services
+		.threepid
+		.get_threepids(&body.user_id)
+		.map(|threepid| Threepid {
+			medium: threepid.medium.to_string(),
+			address: threepid.address,
+			added_at: threepid.added_at.get().into(),
+			validated_at: threepid.validated_at.get().into(),
+		})
+		.collect()
+		.await;
+
+	Ok(get_user::Response {
+		user: user_info(&services, body.user_id.clone()).await,
+		threepids,
+	})
+}
+
+/// # `POST /_synapse/admin/v1/deactivate/{user_id}`
+///
+/// Deactivates a local user, removing them from all their rooms and clearing
+/// their profile. With `erase`, their data is erased as well.
+pub(crate) async fn deactivate_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<deactivate_user::Request>,
+) -> Result<deactivate_user::Response> {
+	let sender_user = body.sender_user();
+	check_admin(&services, sender_user).await?;
+	check_local_user(&services, &body.user_id).await?;
+
+	if body.user_id == services.globals.server_user {
+		return Err!(Request(Forbidden("Not allowed to deactivate the server service account.")));
+	}
+
+	let all_joined_rooms: Vec<OwnedRoomId> = services
+		.rooms
+		.state_cache
+		.rooms_joined(&body.user_id)
+		.map(Into::into)
+		.collect()
+		.await;
+
+	full_user_deactivate(&services, &body.user_id, &all_joined_rooms, body.erase).await?;
+	leave_all_rooms(&services, &body.user_id).await;
+
+	info!("{} deactivated by {sender_user} through the admin API", body.user_id);
+
+	Ok(deactivate_user::Response {
+		id_server_unbind_result: "success".to_owned(),
+	})
+}
+
+/// # `GET /_synapse/admin/v1/whois/{user_id}`
+///
+/// Gets the devices of a local user with the IP address each was last seen
+/// at.
+pub(crate) async fn whois_route(
+	State(services): State<crate::State>,
+	body: Ruma<whois::Request>,
+) -> Result<whois::Response> {
+	check_admin(&services, body.sender_user()).await?;
+	check_local_user(&services, &body.user_id).await?;
+
+	let devices = services
+		.users
+		.all_devices_metadata(&body.user_id)
+		.map(|device| {
+			let connection = Connection {
+				ip: device.last_seen_ip,
+				last_seen: device.last_seen_ts.map(|ts| ts.get().into()),
+				user_agent: None,
+			};
+
+			(device.device_id.to_string(), Sessions {
+				sessions: vec![Session { connections: vec![connection] }],
+			})
+		})
+		.collect()
+		.await;
+
+	Ok(whois::Response { user_id: body.user_id.clone(), devices })
+}
+
+/// # `PUT /_synapse/admin/v1/suspend/{user_id}`
+///
+/// Suspends or unsuspends a local user (MSC3823).
+pub(crate) async fn suspend_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<suspend_user::Request>,
+) -> Result<suspend_user::Response> {
+	let sender_user = body.sender_user();
+	check_admin(&services, sender_user).await?;
+	check_local_user(&services, &body.user_id).await?;
+
+	if body.suspend {
+		if services.users.is_admin(&body.user_id).await {
+			return Err!(Request(Forbidden("Not allowed to suspend an admin account.")));
+		}
+
+		services.users.suspend(&body.user_id);
+		info!("{} suspended by {sender_user} through the admin API", body.user_id);
+	} else {
+		services.users.unsuspend(&body.user_id).await;
+		info!("{} unsuspended by {sender_user} through the admin API", body.user_id);
+	}
+
+	Ok(suspend_user::Response {
+		result: BTreeMap::from([(format!("user_{}_suspended", body.user_id), body.suspend)]),
+	})
+}
+
+/// # `POST /_synapse/admin/v1/users/{user_id}/login`
+///
+/// Issues an access token to act as a local user, on a new device named after
+/// the admin. It expires at `valid_until_ms`, or after
+/// `admin_login_token_lifetime`, and cannot be refreshed.
+pub(crate) async fn login_as_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<login_as_user::Request>,
+) -> Result<login_as_user::Response> {
+	let sender_user = body.sender_user();
+	check_admin(&services, sender_user).await?;
+	check_local_user(&services, &body.user_id).await?;
+
+	if body.user_id == sender_user {
+		return Err!(Request(Forbidden("Cannot use the admin API to log in as yourself.")));
+	}
+
+	if body.user_id == services.globals.server_user {
+		return Err!(Request(Forbidden("Not allowed to log in as the server service account.")));
+	}
+
+	if !services.users.is_active(&body.user_id).await {
+		return Err!(Request(Forbidden("User is deactivated.")));
+	}
+
+	let lifetime = match body.valid_until_ms {
+		| Some(valid_until_ms) => valid_until_ms
+			.checked_sub(utils::millis_since_unix_epoch())
+			.filter(|millis| *millis > 0)
+			.map(Duration::from_millis)
+			.ok_or_else(|| err!(Request(InvalidParam("valid_until_ms is in the past."))))?,
+		| None => Duration::from_secs(services.server.config.admin_login_token_lifetime),
+	};
+
+	let (device_id, access_token) = services
+		.users
+		.create_admin_login(&body.user_id, sender_user.as_str(), lifetime)
+		.await?;
+
+	info!(
+		"{sender_user} logged in as {} on device {device_id} through the admin API",
+		body.user_id
+	);
+
+	services
+		.admin
+		.send_message(RoomMessageEventContent::notice_plain(format!(
+			"{sender_user} logged in as {} on device {device_id} through the admin API, for {}.",
+			body.user_id,
+			utils::time::pretty(lifetime)
+		)))
+		.await
+		.ok();
+
+	Ok(login_as_user::Response { access_token })
+}
+
+async fn check_local_user(services: &Services, user_id: &UserId) -> Result {
+	if !services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Can only look up local users.")));
+	}
+
+	if !services.users.exists(user_id).await {
+		return Err!(Request(NotFound("User not found.")));
+	}
+
+	Ok(())
+}
+
+async fn user_info(services: &Services, user_id: OwnedUserId) -> UserInfo {
+	UserInfo {
+		displayname: services.users.displayname(&user_id).await.ok(),
+		avatar_url: services.users.avatar_url(&user_id).await.ok(),
+		admin: services.users.is_admin(&user_id).await,
+		deactivated: !services.users.is_active(&user_id).await,
+		suspended: services.users.is_suspended(&user_id).await,
+		is_guest: false,
+		creation_ts: services.users.created_at(&user_id).await,
+		last_seen_ts: services.users.last_seen(&user_id).await,
+		name: user_id,
+	}
+}
+
+/// Account details as the Synapse admin API presents them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct UserInfo {
+	name: OwnedUserId,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	displayname: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	avatar_url: Option<OwnedMxcUri>,
+
+	admin: bool,
+
+	deactivated: bool,
+
+	suspended: bool,
+
+	is_guest: bool,
+
+	/// When the account was created, in milliseconds since the unix epoch.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	creation_ts: Option<u64>,
+
+	/// When any of the account's devices was last seen, in milliseconds since
+	/// the unix epoch.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	last_seen_ts: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Threepid {
+	medium: String,
+	address: String,
+	added_at: u64,
+	validated_at: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Sessions {
+	sessions: Vec<Session>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Session {
+	connections: Vec<Connection>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Connection {
+	ip: Option<String>,
+
+	/// When the device was last seen, in milliseconds since the unix epoch.
+	last_seen: Option<u64>,
+
+	user_agent: Option<String>,
+}
+
+pub(crate) mod list_users {
+	use ruma::api::{metadata, request, response, Metadata};
+
+	use super::UserInfo;
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v2/users",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		/// Offset to continue listing at, from `next_token`.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) from: Option<usize>,
+
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) limit: Option<usize>,
+
+		/// Only users whose user ID contains this.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) user_id: Option<String>,
+
+		/// Only users whose user ID or display name contains this.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) name: Option<String>,
+
+		/// Whether to include deactivated users.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) deactivated: Option<bool>,
+
+		/// Only server admins if true, or only other users if false.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) admins: Option<bool>,
+
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) order_by: Option<String>,
+
+		/// `f` for ascending or `b` for descending order.
+		#[ruma_api(query)]
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) dir: Option<String>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) users: Vec<UserInfo>,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) next_token: Option<String>,
+
+		pub(crate) total: usize,
+	}
+}
+
+pub(crate) mod get_user {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedUserId,
+	};
+
+	use super::{Threepid, UserInfo};
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v2/users/:user_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) user_id: OwnedUserId,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		#[serde(flatten)]
+		pub(crate) user: UserInfo,
+
+		pub(crate) threepids: Vec<Threepid>,
+	}
+}
+
+pub(crate) mod deactivate_user {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedUserId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/deactivate/:user_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) user_id: OwnedUserId,
+
+		#[serde(default)]
+		pub(crate) erase: bool,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) id_server_unbind_result: String,
+	}
+}
+
+pub(crate) mod whois {
+	use std::collections::BTreeMap;
+
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedUserId,
+	};
+
+	use super::Sessions;
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/whois/:user_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) user_id: OwnedUserId,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) user_id: OwnedUserId,
+
+		/// Sessions by device ID.
+		pub(crate) devices: BTreeMap<String, Sessions>,
+	}
+}
+
+pub(crate) mod suspend_user {
+	use std::collections::BTreeMap;
+
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedUserId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: PUT,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/suspend/:user_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) user_id: OwnedUserId,
+
+		pub(crate) suspend: bool,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		#[ruma_api(body)]
+		pub(crate) result: BTreeMap<String, bool>,
+	}
+}
+
+pub(crate) mod login_as_user {
+	use ruma::{
+		api::{metadata, request, response, Metadata},
+		OwnedUserId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/users/:user_id/login",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {
+		#[ruma_api(path)]
+		pub(crate) user_id: OwnedUserId,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub(crate) valid_until_ms: Option<u64>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) access_token: String,
+	}
+}
@@ -0,0 +1,44 @@
+use axum::extract::State;
+use conduwuit::Result;
+
+use super::check_admin;
+use crate::Ruma;
+
+/// # `GET /_synapse/admin/v1/server_version`
+///
+/// Gets the name and version of the server software.
+pub(crate) async fn get_admin_server_version_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_server_version::Request>,
+) -> Result<get_server_version::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	Ok(get_server_version::Response {
+		server_version: format!(
+			"{} {}",
+			conduwuit::version::name(),
+			conduwuit::version::version()
+		),
+	})
+}
+
+pub(crate) mod get_server_version {
+	use ruma::api::{metadata, request, response, Metadata};
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_synapse/admin/v1/server_version",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) server_version: String,
+	}
+}
@@ -1,36 +1,51 @@
-use std::fmt::Write;
+use std::{fmt::Write, time::Duration};
 
-use axum::extract::State;
+use axum::{
+	extract::{Query, State},
+	response::{IntoResponse, Redirect, Response},
+	Json,
+};
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
-	debug_info, error, info, is_equal_to, utils, utils::ReadyExt, warn, Error, PduBuilder, Result,
+	debug_info, debug_warn, err, error, info, is_equal_to, utils,
+	utils::{stream::TryIgnore, ReadyExt},
+	warn, Err, Error, PduBuilder, Result,
 };
 use futures::{FutureExt, StreamExt};
 use register::RegistrationKind;
+use reqwest::Url;
 use ruma::{
 	api::client::{
 		account::{
-			change_password, check_registration_token_validity, deactivate, get_3pids,
-			get_username_availability,
+			add_3pid, bind_3pid, change_password, check_registration_token_validity, deactivate,
+			delete_3pid, get_3pids, get_username_availability,
 			register::{self, LoginType},
 			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
-			whoami, ThirdPartyIdRemovalStatus,
+			request_registration_token_via_email, unbind_3pid, whoami, ThirdPartyIdRemovalStatus,
 		},
 		error::ErrorKind,
-		uiaa::{AuthFlow, AuthType, UiaaInfo},
+		uiaa::{AuthFlow, AuthType, ThirdpartyIdCredentials, UiaaInfo},
 	},
 	events::{
 		room::{
 			message::RoomMessageEventContent,
 			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+			redaction::RoomRedactionEventContent,
 		},
-		GlobalAccountDataEventType, StateEventType,
+		GlobalAccountDataEventType, StateEventType, TimelineEventType,
 	},
-	push, OwnedRoomId, UserId,
+	thirdparty::Medium,
+	OwnedClientSecret, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedSessionId,
+	OwnedUserId, RoomId, RoomOrAliasId, UserId,
 };
-use service::Services;
+use serde::Deserialize;
+use service::{threepid, Services};
+use tokio::time::sleep;
 
-use super::{join_room_by_id_helper, DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
+use super::{
+	check_legacy_auth, client_base_url, issue_refresh_token, join_room_by_id_helper,
+	DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH,
+};
 use crate::Ruma;
 
 const RANDOM_USER_ID_LENGTH: usize = 10;
@@ -52,6 +67,8 @@ pub(crate) async fn get_register_available_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<get_username_availability::v3::Request>,
 ) -> Result<get_username_availability::v3::Response> {
+	check_legacy_auth(&services)?;
+
 	// workaround for https://github.com/matrix-org/matrix-appservice-irc/issues/1780 due to inactivity of fixing the issue
 	let is_matrix_appservice_irc = body.appservice_info.as_ref().is_some_and(|appservice| {
 		appservice.registration.id == "irc"
@@ -105,7 +122,8 @@ pub(crate) async fn get_register_available_route(
 /// - Only works if registration is enabled
 /// - If type is guest: ignores all parameters except
 ///   initial_device_display_name
-/// - If sender is not appservice: Requires UIAA (but we only use a dummy stage)
+/// - If sender is not appservice: Requires UIAA (a registration token, email
+///   verification and/or a CAPTCHA if configured, otherwise only a dummy stage)
 /// - If type is not guest and no username is given: Always fails after UIAA
 ///   check
 /// - Creates a new account and populates it with default account data
@@ -118,6 +136,10 @@ pub(crate) async fn register_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<register::v3::Request>,
 ) -> Result<register::v3::Response> {
+	if body.appservice_info.is_none() {
+		check_legacy_auth(&services)?;
+	}
+
 	if !services.globals.allow_registration() && body.appservice_info.is_none() {
 		info!(
 			"Registration disabled and request not from known appservice, rejecting \
@@ -128,11 +150,15 @@ pub(crate) async fn register_route(
 	}
 
 	let is_guest = body.kind == RegistrationKind::Guest;
+	let config = &services.server.config;
+	let open_registration = !services.registration_tokens.has_static_token()
+		&& !config.require_email_for_registration
+		&& !config.captcha.is_enabled()
+		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse;
 
 	if is_guest
 		&& (!services.globals.allow_guest_registration()
-			|| (services.globals.allow_registration()
-				&& services.globals.registration_token.is_some()))
+			|| (services.globals.allow_registration() && !open_registration))
 	{
 		info!(
 			"Guest registration disabled / registration enabled with token configured, \
@@ -230,31 +256,62 @@ pub(crate) async fn register_route(
 	}
 
 	// UIAA
-	let mut uiaainfo;
-	let skip_auth = if services.globals.registration_token.is_some() {
+	let require_email = config.require_email_for_registration && body.appservice_info.is_none();
+	let require_captcha = config.captcha.is_enabled() && body.appservice_info.is_none();
+
+	// Tokens created by the admin are required unless registration is gated
+	// by another stage or open registration was explicitly allowed.
+	let require_token = body.appservice_info.is_none()
+		&& (services.registration_tokens.has_static_token()
+			|| (!open_registration && !require_email && !require_captcha));
+
+	let mut stages = Vec::new();
+	if require_token {
 		// Registration token required
-		uiaainfo = UiaaInfo {
-			flows: vec![AuthFlow {
-				stages: vec![AuthType::RegistrationToken],
-			}],
-			completed: Vec::new(),
-			params: Box::default(),
-			session: None,
-			auth_error: None,
-		};
-		body.appservice_info.is_some()
-	} else {
+		stages.push(AuthType::RegistrationToken);
+	}
+
+	if require_email {
+		stages.push(AuthType::EmailIdentity);
+	}
+
+	if require_captcha {
+		stages.push(AuthType::ReCaptcha);
+	}
+
+	let skip_auth = if stages.is_empty() {
 		// No registration token necessary, but clients must still go through the flow
-		uiaainfo = UiaaInfo {
-			flows: vec![AuthFlow { stages: vec![AuthType::Dummy] }],
-			completed: Vec::new(),
-			params: Box::default(),
-			session: None,
-			auth_error: None,
-		};
+		stages.push(AuthType::Dummy);
 		body.appservice_info.is_some() || is_guest
+	} else {
+		body.appservice_info.is_some()
+	};
+
+	services
+		.spam_checker
+		.check_registration(
+			user_id.localpart(),
+			client,
+			body.appservice_info
+				.as_ref()
+				.map(|info| info.registration.id.as_str()),
+		)
+		.await?;
+
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow { stages }],
+		completed: Vec::new(),
+		params: if require_captcha {
+			services.uiaa.captcha_params()?
+		} else {
+			Box::default()
+		},
+		session: None,
+		auth_error: None,
 	};
 
+	let mut email = None;
+	let mut registration_token = None;
 	if !skip_auth {
 		if let Some(auth) = &body.auth {
 			let (worked, uiaainfo) = services
@@ -270,7 +327,26 @@ pub(crate) async fn register_route(
 			if !worked {
 				return Err(Error::Uiaa(uiaainfo));
 			}
-		// Success!
+			// Success!
+			if require_email {
+				email = Some(registration_email(&services, &uiaainfo).await?);
+			}
+
+			if require_token {
+				let token = services
+					.uiaa
+					.take_registration_token(
+						&UserId::parse_with_server_name("", services.globals.server_name())
+							.expect("we know this is valid"),
+						"".into(),
+						uiaainfo.session.as_deref().expect("session is always set"),
+					)
+					.ok_or_else(|| {
+						err!(Request(Forbidden("Registration token stage expired.")))
+					})?;
+
+				registration_token = Some(token);
+			}
 		} else if let Some(json) = body.json_body {
 			uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 			services.uiaa.create(
@@ -286,10 +362,44 @@ pub(crate) async fn register_route(
 		}
 	}
 
+	// Claim the email address first, so a registration which lost a race for it
+	// fails before the account exists.
+	if let Some((_, session)) = &email {
+		services.threepid.add(&user_id, session).await?;
+	}
+
 	let password = if is_guest { None } else { body.password.as_deref() };
 
 	// Create user
-	services.users.create(&user_id, password)?;
+	let created = match registration_token {
+		| Some(token) => services.registration_tokens.complete(&token).await,
+		| None => Ok(()),
+	}
+	.and_then(|()| services.users.create(&user_id, password));
+
+	if let Err(e) = created {
+		if let Some((_, session)) = &email {
+			if let Err(e) = services
+				.threepid
+				.remove(&user_id, &session.medium, &session.address)
+				.await
+			{
+				warn!(%user_id, "Failed to release email address of failed registration: {e}");
+			}
+		}
+
+		return Err(e);
+	}
+
+	if let Some((creds, _)) = email {
+		services
+			.threepid
+			.remove_session(&creds.client_secret, &creds.sid);
+	}
+
+	if body.appservice_info.is_none() {
+		services.account_validity.start(&user_id).await;
+	}
 
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
@@ -316,7 +426,7 @@ pub(crate) async fn register_route(
 			GlobalAccountDataEventType::PushRules.to_string().into(),
 			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
 				content: ruma::events::push_rules::PushRulesEventContent {
-					global: push::Ruleset::server_default(&user_id),
+					global: services.pusher.server_default_rules(&user_id),
 				},
 			})
 			.expect("to json always works"),
@@ -423,74 +533,259 @@ pub(crate) async fn register_route(
 	// If this is the first real user, grant them admin privileges except for guest
 	// users Note: the server user, @conduit:servername, is generated first
 	if !is_guest {
-		if let Ok(admin_room) = services.admin.get_admin_room().await {
-			if services
-				.rooms
-				.state_cache
-				.room_joined_count(&admin_room)
-				.await
-				.is_ok_and(is_equal_to!(1))
-			{
-				services.admin.make_user_admin(&user_id).await?;
-				warn!("Granting {user_id} admin privileges as the first user");
-			}
-		}
+		grant_first_user_admin(&services, &user_id).await?;
 	}
 
 	if body.appservice_info.is_none()
-		&& !services.server.config.auto_join_rooms.is_empty()
 		&& (services.globals.allow_guests_auto_join_rooms() || !is_guest)
 	{
-		for room in &services.server.config.auto_join_rooms {
-			let Ok(room_id) = services.rooms.alias.resolve(room).await else {
-				error!(
-					"Failed to resolve room alias to room ID when attempting to auto join \
-					 {room}, skipping"
-				);
-				continue;
-			};
+		auto_join_rooms(services, &user_id).await;
+	}
 
-			if !services
-				.rooms
-				.state_cache
-				.server_in_room(services.globals.server_name(), &room_id)
-				.await
-			{
+	let (refresh_token, expires_in) =
+		issue_refresh_token(&services, &user_id, &device_id, body.refresh_token);
+
+	Ok(register::v3::Response {
+		access_token: Some(token),
+		user_id,
+		device_id: Some(device_id),
+		refresh_token,
+		expires_in,
+	})
+}
+
+/// Creates an account for a user authenticated by an external provider, such as
+/// SSO or LDAP, with no local password.
+pub(super) async fn create_external_user(
+	services: crate::State,
+	user_id: &UserId,
+	displayname: Option<String>,
+	client: &str,
+	provider: &str,
+) -> Result {
+	if services
+		.globals
+		.forbidden_usernames()
+		.is_match(user_id.localpart())
+		|| services.appservice.is_exclusive_user_id(user_id).await
+	{
+		return Err!(Request(Exclusive("This username is reserved.")));
+	}
+
+	services.users.create_external(user_id);
+	services.account_validity.start(user_id).await;
+
+	let displayname = displayname.unwrap_or_else(|| {
+		let mut displayname = user_id.localpart().to_owned();
+		if !services.globals.new_user_displayname_suffix().is_empty() {
+			write!(displayname, " {}", services.server.config.new_user_displayname_suffix)
+				.expect("should be able to write to string buffer");
+		}
+
+		displayname
+	});
+
+	services.users.set_displayname(user_id, Some(displayname));
+
+	// Initial account data
+	services
+		.account_data
+		.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
+				content: ruma::events::push_rules::PushRulesEventContent {
+					global: services.pusher.server_default_rules(user_id),
+				},
+			})
+			.expect("to json always works"),
+		)
+		.await?;
+
+	info!("New user \"{user_id}\" registered on this server with {provider}.");
+
+	if services.server.config.admin_room_notices {
+		services
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(format!(
+				"New user \"{user_id}\" registered on this server with {provider} from IP \
+				 {client}"
+			)))
+			.await
+			.ok();
+	}
+
+	grant_first_user_admin(&services, user_id).await?;
+	auto_join_rooms(services, user_id).await;
+
+	Ok(())
+}
+
+/// Grants admin privileges to a newly registered user if they are the first
+/// real user; the server user is always generated first.
+pub(super) async fn grant_first_user_admin(services: &Services, user_id: &UserId) -> Result {
+	if let Ok(admin_room) = services.admin.get_admin_room().await {
+		if services
+			.rooms
+			.state_cache
+			.room_joined_count(&admin_room)
+			.await
+			.is_ok_and(is_equal_to!(1))
+		{
+			services.admin.make_user_admin(user_id).await?;
+			warn!("Granting {user_id} admin privileges as the first user");
+		}
+	}
+
+	Ok(())
+}
+
+/// Joins a newly registered user to the configured `auto_join_rooms`.
+/// Failed joins are retried in the background rather than failing the
+/// registration.
+pub(super) async fn auto_join_rooms(services: crate::State, user_id: &UserId) {
+	for room in &services.server.config.auto_join_rooms {
+		match auto_join_room(&services, user_id, room).await {
+			| Ok(()) => info!("Automatically joined room {room} for user {user_id}"),
+			| Err(e) if services.server.config.auto_join_rooms_retries > 0 => {
 				warn!(
-					"Skipping room {room} to automatically join as we have never joined before."
+					"Failed to automatically join room {room} for user {user_id}, retrying \
+					 later: {e}"
 				);
-				continue;
-			}
 
-			if let Some(room_server_name) = room.server_name() {
-				if let Err(e) = join_room_by_id_helper(
-					&services,
-					&user_id,
-					&room_id,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[services.globals.server_name().to_owned(), room_server_name.to_owned()],
-					None,
-					&body.appservice_info,
-				)
-				.boxed()
-				.await
-				{
-					// don't return this error so we don't fail registrations
-					error!("Failed to automatically join room {room} for user {user_id}: {e}");
-				} else {
-					info!("Automatically joined room {room} for user {user_id}");
-				};
-			}
+				services.server.runtime().spawn(retry_auto_join_room(
+					services,
+					user_id.to_owned(),
+					room.clone(),
+				));
+			},
+			// don't return this error so we don't fail registrations
+			| Err(e) =>
+				error!("Failed to automatically join room {room} for user {user_id}: {e}"),
 		}
 	}
+}
 
-	Ok(register::v3::Response {
-		access_token: Some(token),
+async fn retry_auto_join_room(
+	services: crate::State,
+	user_id: OwnedUserId,
+	room: OwnedRoomOrAliasId,
+) {
+	const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+	let config = &services.server.config;
+	let backoff = Duration::from_secs(config.auto_join_rooms_retry_backoff);
+	for tries in 1..=config.auto_join_rooms_retries {
+		let delay = backoff
+			.saturating_mul(tries.saturating_mul(tries))
+			.min(MAX_BACKOFF);
+
+		tokio::select! {
+			() = sleep(delay) => {},
+			() = services.server.until_shutdown() => return,
+		}
+
+		if !services.users.is_active_local(&user_id).await {
+			return;
+		}
+
+		match auto_join_room(&services, &user_id, &room).await {
+			| Ok(()) => {
+				info!(
+					"Automatically joined room {room} for user {user_id} after {tries} retries"
+				);
+				return;
+			},
+			| Err(e) if tries < config.auto_join_rooms_retries => {
+				debug_warn!(
+					"Retry {tries} to automatically join room {room} for user {user_id} failed: \
+					 {e}"
+				);
+			},
+			| Err(e) => {
+				error!("Giving up automatically joining room {room} for user {user_id}: {e}");
+			},
+		}
+	}
+}
+
+/// Joins the user to a room of `auto_join_rooms`, resolving it if it is an
+/// alias and joining over federation if this server isn't in the room.
+async fn auto_join_room(services: &Services, user_id: &UserId, room: &RoomOrAliasId) -> Result {
+	let (room_id, resolved_servers) = services
+		.rooms
+		.alias
+		.resolve_with_servers(room, None)
+		.await
+		.map_err(|e| err!("Failed to resolve {room}: {e}"))?;
+
+	if services
+		.rooms
+		.state_cache
+		.is_joined(user_id, &room_id)
+		.await
+	{
+		return Ok(());
+	}
+
+	let mut servers = vec![services.globals.server_name().to_owned()];
+	for server in resolved_servers
+		.into_iter()
+		.chain(room.server_name().map(ToOwned::to_owned))
+	{
+		if !servers.contains(&server) {
+			servers.push(server);
+		}
+	}
+
+	join_room_by_id_helper(
+		services,
 		user_id,
-		device_id: Some(device_id),
-		refresh_token: None,
-		expires_in: None,
-	})
+		&room_id,
+		Some("Automatically joining this room upon registration".to_owned()),
+		&servers,
+		None,
+		&None,
+	)
+	.boxed()
+	.await
+	.map(|_| ())
+}
+
+/// Gets the email address validated to complete the `m.login.email.identity`
+/// stage of a registration UIAA session.
+async fn registration_email(
+	services: &Services,
+	uiaainfo: &UiaaInfo,
+) -> Result<(ThirdpartyIdCredentials, threepid::Session)> {
+	let creds = services
+		.uiaa
+		.take_threepid_creds(
+			&UserId::parse_with_server_name("", services.globals.server_name())
+				.expect("we know this is valid"),
+			"".into(),
+			uiaainfo.session.as_deref().expect("session is always set"),
+		)
+		.ok_or_else(|| {
+			err!(Request(ThreepidAuthFailed("Email address has not been validated.")))
+		})?;
+
+	let session = services
+		.threepid
+		.get_validated_session(&creds.client_secret, &creds.sid)
+		.await?;
+
+	if services
+		.threepid
+		.find_user(&session.medium, &session.address)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("Email address is already in use.")));
+	}
+
+	Ok((creds, session))
 }
 
 /// # `POST /_matrix/client/r0/account/password`
@@ -516,6 +811,8 @@ pub(crate) async fn change_password_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<change_password::v3::Request>,
 ) -> Result<change_password::v3::Response> {
+	check_legacy_auth(&services)?;
+
 	// Authentication for this endpoint was made optional, but we need
 	// authentication currently
 	let sender_user = body
@@ -620,6 +917,8 @@ pub(crate) async fn deactivate_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<deactivate::v3::Request>,
 ) -> Result<deactivate::v3::Response> {
+	check_legacy_auth(&services)?;
+
 	// Authentication for this endpoint was made optional, but we need
 	// authentication currently
 	let sender_user = body
@@ -669,7 +968,7 @@ pub(crate) async fn deactivate_route(
 	super::update_displayname(&services, sender_user, None, &all_joined_rooms).await;
 	super::update_avatar_url(&services, sender_user, None, None, &all_joined_rooms).await;
 
-	full_user_deactivate(&services, sender_user, &all_joined_rooms).await?;
+	full_user_deactivate(&services, sender_user, &all_joined_rooms, body.erase).await?;
 
 	info!("User {sender_user} deactivated their account.");
 
@@ -691,14 +990,65 @@ pub(crate) async fn deactivate_route(
 /// # `GET _matrix/client/v3/account/3pid`
 ///
 /// Get a list of third party identifiers associated with this account.
-///
-/// - Currently always returns empty list
 pub(crate) async fn third_party_route(
+	State(services): State<crate::State>,
 	body: Ruma<get_3pids::v3::Request>,
 ) -> Result<get_3pids::v3::Response> {
-	let _sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let threepids = services.threepid.get_threepids(sender_user).collect().await;
 
-	Ok(get_3pids::v3::Response::new(Vec::new()))
+	Ok(get_3pids::v3::Response::new(threepids))
+}
+
+/// # `POST /_matrix/client/v3/register/email/requestToken`
+///
+/// Requests a validation token be emailed to an address so it can be used to
+/// complete the `m.login.email.identity` stage of registration.
+///
+/// - 403 signals that the server does not require email verification for
+///   registration
+/// - 400 signals that the email address is already in use
+#[tracing::instrument(skip_all, fields(%client), name = "register_email")]
+pub(crate) async fn request_registration_token_via_email_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<request_registration_token_via_email::v3::Request>,
+) -> Result<request_registration_token_via_email::v3::Response> {
+	check_legacy_auth(&services)?;
+
+	if !services.globals.allow_registration()
+		|| !services.server.config.require_email_for_registration
+	{
+		return Err!(Request(ThreepidDenied(
+			"This server does not use email addresses for registration."
+		)));
+	}
+
+	let address = threepid::normalize_email(&body.email)?;
+	if services
+		.threepid
+		.find_user(&Medium::Email, &address)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("Email address is already in use.")));
+	}
+
+	let sid = services
+		.threepid
+		.request_email_token(
+			&body.client_secret,
+			&address,
+			body.send_attempt,
+			body.next_link.clone(),
+		)
+		.await?;
+
+	Ok(request_registration_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid_submit_url(&services)),
+	})
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
@@ -706,15 +1056,198 @@ pub(crate) async fn third_party_route(
 /// "This API should be used to request validation tokens when adding an email
 /// address to an account"
 ///
+/// - Emails a validation token to the address if an SMTP server is configured
 /// - 403 signals that The homeserver does not allow the third party identifier
 ///   as a contact option.
+#[tracing::instrument(skip_all, fields(%client), name = "3pid_email_token")]
 pub(crate) async fn request_3pid_management_token_via_email_route(
-	_body: Ruma<request_3pid_management_token_via_email::v3::Request>,
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<request_3pid_management_token_via_email::v3::Request>,
 ) -> Result<request_3pid_management_token_via_email::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
+	let address = threepid::normalize_email(&body.email)?;
+	if services
+		.threepid
+		.find_user(&Medium::Email, &address)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("Email address is already in use.")));
+	}
+
+	let sid = services
+		.threepid
+		.request_email_token(
+			&body.client_secret,
+			&address,
+			body.send_attempt,
+			body.next_link.clone(),
+		)
+		.await?;
+
+	Ok(request_3pid_management_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid_submit_url(&services)),
+	})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/add`
+///
+/// Adds a validated third party identifier to this account.
+///
+/// - Requires UIAA to verify user password
+#[tracing::instrument(skip_all, fields(%client), name = "add_3pid")]
+pub(crate) async fn add_3pid_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<add_3pid::v3::Request>,
+) -> Result<add_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow { stages: vec![AuthType::Password] }],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services
+			.uiaa
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
+
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+	// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services
+			.uiaa
+			.create(sender_user, sender_device, &uiaainfo, &json);
+
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	let session = services
+		.threepid
+		.get_validated_session(&body.client_secret, &body.sid)
+		.await?;
+
+	services.threepid.add(sender_user, &session).await?;
+	services
+		.threepid
+		.remove_session(&body.client_secret, &body.sid);
+
+	info!("User {sender_user} added a third party identifier to their account.");
+
+	Ok(add_3pid::v3::Response {})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/delete`
+///
+/// Removes a third party identifier from this account.
+///
+/// - Identity servers are not supported, so nothing is unbound from them
+pub(crate) async fn delete_3pid_route(
+	State(services): State<crate::State>,
+	body: Ruma<delete_3pid::v3::Request>,
+) -> Result<delete_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	services
+		.threepid
+		.remove(sender_user, &body.medium, &body.address)
+		.await?;
+
+	info!("User {sender_user} removed a third party identifier from their account.");
+
+	Ok(delete_3pid::v3::Response {
+		id_server_unbind_result: ThirdPartyIdRemovalStatus::NoSupport,
+	})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/bind`
+///
+/// Binding third party identifiers on identity servers is not supported.
+pub(crate) async fn bind_3pid_route(
+	_body: Ruma<bind_3pid::v3::Request>,
+) -> Result<bind_3pid::v3::Response> {
+	Err!(Request(ThreepidDenied("Identity servers are not supported by this server.")))
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/unbind`
+///
+/// Unbinding third party identifiers from identity servers is not supported.
+pub(crate) async fn unbind_3pid_route(
+	_body: Ruma<unbind_3pid::v3::Request>,
+) -> Result<unbind_3pid::v3::Response> {
+	Ok(unbind_3pid::v3::Response {
+		id_server_unbind_result: ThirdPartyIdRemovalStatus::NoSupport,
+	})
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SubmitThreepidToken {
+	sid: OwnedSessionId,
+	client_secret: OwnedClientSecret,
+	token: String,
+}
+
+/// # `GET /_conduwuit/client/3pid/email/submit_token`
+///
+/// conduwuit-specific API visited from the link in validation emails. Marks the
+/// validation session as validated, redirecting to the `next_link` the client
+/// provided if any.
+pub(crate) async fn submit_3pid_token_link_route(
+	State(services): State<crate::State>,
+	Query(body): Query<SubmitThreepidToken>,
+) -> Result<Response> {
+	let session = services
+		.threepid
+		.submit_token(&body.client_secret, &body.sid, &body.token)
+		.await?;
+
+	if let Some(next_link) = session
+		.next_link
+		.as_deref()
+		.and_then(|link| Url::parse(link).ok())
+		.filter(|link| matches!(link.scheme(), "https" | "http"))
+	{
+		return Ok(Redirect::to(next_link.as_str()).into_response());
+	}
+
+	Ok(
+		"Your email address has been verified. You may now return to your client."
+			.into_response(),
+	)
+}
+
+/// # `POST /_conduwuit/client/3pid/email/submit_token`
+///
+/// conduwuit-specific API advertised as the `submit_url` for validation
+/// sessions, allowing clients to submit the token on the user's behalf.
+pub(crate) async fn submit_3pid_token_route(
+	State(services): State<crate::State>,
+	Json(body): Json<SubmitThreepidToken>,
+) -> Result<impl IntoResponse> {
+	services
+		.threepid
+		.submit_token(&body.client_secret, &body.sid, &body.token)
+		.await?;
+
+	Ok(Json(serde_json::json!({
+		"success": true
+	})))
+}
+
+fn threepid_submit_url(services: &Services) -> String {
+	format!("{}{}", client_base_url(services), threepid::SUBMIT_TOKEN_PATH)
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
@@ -737,20 +1270,23 @@ pub(crate) async fn request_3pid_management_token_via_msisdn_route(
 ///
 /// Checks if the provided registration token is valid at the time of checking
 ///
-/// Currently does not have any ratelimiting, and this isn't very practical as
-/// there is only one registration token allowed.
+/// Currently does not have any ratelimiting.
 pub(crate) async fn check_registration_token_validity(
 	State(services): State<crate::State>,
 	body: Ruma<check_registration_token_validity::v1::Request>,
 ) -> Result<check_registration_token_validity::v1::Response> {
-	let Some(reg_token) = services.globals.registration_token.clone() else {
+	check_legacy_auth(&services)?;
+
+	if !services.globals.allow_registration() {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
 			"Server does not allow token registration.",
 		));
-	};
+	}
 
-	Ok(check_registration_token_validity::v1::Response { valid: reg_token == body.token })
+	let valid = services.registration_tokens.is_valid(&body.token).await;
+
+	Ok(check_registration_token_validity::v1::Response { valid })
 }
 
 /// Runs through all the deactivation steps:
@@ -759,11 +1295,13 @@ pub(crate) async fn check_registration_token_validity(
 /// - Removing display name
 /// - Removing avatar URL and blurhash
 /// - Removing all profile data
+/// - Erasing the user's data if `erase` is set, see [`erase_user`]
 /// - Leaving all rooms (and forgets all of them)
 pub async fn full_user_deactivate(
 	services: &Services,
 	user_id: &UserId,
 	all_joined_rooms: &[OwnedRoomId],
+	erase: bool,
 ) -> Result<()> {
 	services.users.deactivate_account(user_id).await.ok();
 	super::update_displayname(services, user_id, None, all_joined_rooms).await;
@@ -777,6 +1315,10 @@ pub async fn full_user_deactivate(
 		})
 		.await;
 
+	if erase {
+		erase_user(services, user_id, all_joined_rooms).await;
+	}
+
 	for room_id in all_joined_rooms {
 		let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
@@ -831,3 +1373,99 @@ pub async fn full_user_deactivate(
 
 	Ok(())
 }
+
+/// Erases a deactivated user's data (GDPR):
+///
+/// - Redacts the events they sent in the rooms they are still joined to, the
+///   only rooms we can send redactions in on their behalf
+/// - Deletes their media, third-party identifiers and account data
+/// - Marks them as erased, so no further data about them is accepted
+async fn erase_user(services: &Services, user_id: &UserId, all_joined_rooms: &[OwnedRoomId]) {
+	services.users.mark_erased(user_id);
+
+	for room_id in all_joined_rooms {
+		redact_user_events(services, user_id, room_id).await;
+	}
+
+	match services.media.delete_from_user(user_id).await {
+		| Ok(count) => info!("Deleted {count} media files of {user_id} as part of erasure"),
+		| Err(e) => warn!(%user_id, "Failed to delete media as part of erasure: {e}"),
+	}
+
+	let threepids: Vec<_> = services.threepid.get_threepids(user_id).collect().await;
+	for threepid in threepids {
+		if let Err(e) = services
+			.threepid
+			.remove(user_id, &threepid.medium, &threepid.address)
+			.await
+		{
+			warn!(%user_id, "Failed to remove third-party identifier as part of erasure: {e}");
+		}
+	}
+
+	let left_rooms: Vec<OwnedRoomId> = services
+		.rooms
+		.state_cache
+		.rooms_left(user_id)
+		.map(|(room_id, _)| room_id)
+		.collect()
+		.await;
+
+	services.account_data.delete_all(None, user_id).await;
+	for room_id in all_joined_rooms.iter().chain(&left_rooms) {
+		services
+			.account_data
+			.delete_all(Some(room_id), user_id)
+			.await;
+	}
+
+	info!("Erased {user_id} as part of account deactivation");
+}
+
+/// Redacts the messages the user sent to the room. State events are left
+/// alone, since redacting e.g. power levels or join rules the user set could
+/// break the room for everyone else.
+async fn redact_user_events(services: &Services, user_id: &UserId, room_id: &RoomId) {
+	let event_ids: Vec<OwnedEventId> = services
+		.rooms
+		.timeline
+		.pdus(Some(user_id), room_id, None)
+		.ignore_err()
+		.ready_filter(|(_, pdu)| pdu.sender == user_id && !pdu.is_redacted())
+		.ready_filter(|(_, pdu)| {
+			pdu.state_key.is_none() && pdu.kind != TimelineEventType::RoomRedaction
+		})
+		.map(|(_, pdu)| pdu.event_id)
+		.collect()
+		.await;
+
+	let reason = format!(
+		"The account of this user on {} has been erased.",
+		services.globals.server_name()
+	);
+
+	for event_id in event_ids {
+		let state_lock = services.rooms.state.mutex.lock(room_id).await;
+
+		// ignore errors so erasure doesn't stop at one event
+		if let Err(e) = services
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					redacts: Some(event_id.clone()),
+					..PduBuilder::timeline(&RoomRedactionEventContent {
+						redacts: Some(event_id.clone()),
+						reason: Some(reason.clone()),
+					})
+				},
+				user_id,
+				room_id,
+				&state_lock,
+			)
+			.await
+		{
+			warn!(%room_id, %event_id, %user_id, "Failed to redact event as part of erasure: {e}");
+		}
+	}
+}
@@ -15,7 +15,10 @@ use ruma::{
 use serde::Deserialize;
 use serde_json::{json, value::RawValue as RawJsonValue};
 
-use crate::{service::Services, Result, Ruma};
+use crate::{
+	service::{presence::PRESENCE_OVERRIDE_EVENT_TYPE, Services},
+	Result, Ruma,
+};
 
 /// # `PUT /_matrix/client/r0/user/{userId}/account_data/{type}`
 ///
@@ -39,6 +42,18 @@ pub(crate) async fn set_global_account_data_route(
 	)
 	.await?;
 
+	// Appear offline or away right away rather than on the next sync.
+	if body.event_type.to_string() == PRESENCE_OVERRIDE_EVENT_TYPE
+		&& services.globals.allow_local_presence()
+	{
+		if let Some(presence) = services.presence.presence_override(&body.user_id).await {
+			services
+				.presence
+				.ping_presence(&body.user_id, &presence)
+				.await?;
+		}
+	}
+
 	Ok(set_global_account_data::v3::Response {})
 }
 
@@ -0,0 +1,118 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use axum::{
+	extract::{Query, State},
+	response::{Html, IntoResponse},
+	Form,
+};
+use conduwuit::{
+	info,
+	utils::{time, HtmlEscape},
+	Err, Result,
+};
+use http::header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY};
+use ruma::UserId;
+use serde::Deserialize;
+use service::account_validity::RENEW_PATH;
+
+use crate::Ruma;
+
+#[derive(Deserialize)]
+pub(crate) struct RenewAccount {
+	token: String,
+}
+
+/// Lets the confirmation page submit its form to this server, which the
+/// default policy forbids.
+const CONFIRMATION_PAGE_CSP: &str =
+	"default-src 'none'; form-action 'self'; frame-ancestors 'none'; base-uri 'none'";
+
+/// # `POST /_conduwuit/client/v1/account_validity/send_mail`
+///
+/// conduwuit-specific API emailing the user a new link to renew their
+/// account. Expired accounts may still use it.
+pub(crate) async fn send_renewal_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<send_renewal_email::Request>,
+) -> Result<send_renewal_email::Response> {
+	let sender_user = body.sender_user();
+	if !services.account_validity.is_enabled() {
+		return Err!(Request(NotFound("Accounts on this server do not expire.")));
+	}
+
+	services
+		.account_validity
+		.send_renewal_email(sender_user)
+		.await?;
+
+	Ok(send_renewal_email::Response {})
+}
+
+/// # `GET /_conduwuit/client/account_validity/renew`
+///
+/// conduwuit-specific API visited from the link in renewal emails. Shows a
+/// page asking to confirm the renewal, so that merely fetching the link (e.g.
+/// by a mail scanner) does not renew the account.
+pub(crate) async fn renew_account_link_route(
+	State(services): State<crate::State>,
+	Query(body): Query<RenewAccount>,
+) -> Result<impl IntoResponse> {
+	let user_id = services.account_validity.renewal_user(&body.token).await?;
+
+	Ok((
+		[(CONTENT_SECURITY_POLICY, CONFIRMATION_PAGE_CSP), (CACHE_CONTROL, "no-store")],
+		Html(confirmation_page(&user_id, &body.token)),
+	))
+}
+
+/// # `POST /_conduwuit/client/account_validity/renew`
+///
+/// conduwuit-specific API submitted from the renewal confirmation page.
+/// Renews the account the link was sent for.
+pub(crate) async fn renew_account_route(
+	State(services): State<crate::State>,
+	Form(body): Form<RenewAccount>,
+) -> Result<impl IntoResponse> {
+	let (user_id, expires_at) = services
+		.account_validity
+		.renew_with_token(&body.token)
+		.await?;
+
+	info!("{user_id} renewed their account through a renewal link");
+
+	Ok(format!(
+		"Your account {user_id} has been renewed until {}. You may now return to your client.",
+		time::format(UNIX_EPOCH + Duration::from_millis(expires_at), "%+")
+	))
+}
+
+fn confirmation_page(user_id: &UserId, token: &str) -> String {
+	let user_id = HtmlEscape(user_id.as_str());
+	let token = HtmlEscape(token);
+
+	format!(
+		"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Renew your \
+		 account</title></head><body><p>Renew your account {user_id}?</p><form method=\"post\" \
+		 action=\"{RENEW_PATH}\"><input type=\"hidden\" name=\"token\" \
+		 value=\"{token}\"><button type=\"submit\">Renew</button></form></body></html>\n"
+	)
+}
+
+pub(crate) mod send_renewal_email {
+	use ruma::api::{metadata, request, response, Metadata};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: true,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_conduwuit/client/v1/account_validity/send_mail",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {}
+}
@@ -42,5 +42,12 @@ pub(crate) async fn get_capabilities_route(
 		.set("uk.tcpip.msc4133.profile_fields", json!({"enabled": true}))
 		.expect("this is valid JSON we created");
 
+	// MSC4174 capability
+	if let Some(vapid) = services.pusher.web_push_public_key() {
+		capabilities
+			.set("m.webpush", json!({"enabled": true, "vapid": vapid}))
+			.expect("this is valid JSON we created");
+	}
+
 	Ok(get_capabilities::v3::Response { capabilities })
 }
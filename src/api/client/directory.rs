@@ -24,7 +24,7 @@ use ruma::{
 	},
 	uint, OwnedRoomId, RoomId, ServerName, UInt, UserId,
 };
-use service::Services;
+use service::{rooms::directory::RemotePage, Services};
 
 use crate::Ruma;
 
@@ -40,19 +40,19 @@ pub(crate) async fn get_public_rooms_filtered_route(
 	body: Ruma<get_public_rooms_filtered::v3::Request>,
 ) -> Result<get_public_rooms_filtered::v3::Response> {
 	if let Some(server) = &body.server {
-		if services
-			.server
-			.config
-			.forbidden_remote_room_directory_server_names
-			.contains(server)
+		if !services.globals.server_is_ours(server)
+			&& !services.rooms.directory.remote_directory_allowed(server)
 		{
-			return Err!(Request(Forbidden("Server is banned on this homeserver.")));
+			return Err!(Request(Forbidden(
+				"This server's room directory is not allowed on this homeserver."
+			)));
 		}
 	}
 
 	let response = get_public_rooms_filtered_helper(
 		&services,
 		body.server.as_deref(),
+		None,
 		body.limit,
 		body.since.as_deref(),
 		&body.filter,
@@ -82,19 +82,19 @@ pub(crate) async fn get_public_rooms_route(
 	body: Ruma<get_public_rooms::v3::Request>,
 ) -> Result<get_public_rooms::v3::Response> {
 	if let Some(server) = &body.server {
-		if services
-			.server
-			.config
-			.forbidden_remote_room_directory_server_names
-			.contains(server)
+		if !services.globals.server_is_ours(server)
+			&& !services.rooms.directory.remote_directory_allowed(server)
 		{
-			return Err!(Request(Forbidden("Server is banned on this homeserver.")));
+			return Err!(Request(Forbidden(
+				"This server's room directory is not allowed on this homeserver."
+			)));
 		}
 	}
 
 	let response = get_public_rooms_filtered_helper(
 		&services,
 		body.server.as_deref(),
+		None,
 		body.limit,
 		body.since.as_deref(),
 		&Filter::default(),
@@ -152,6 +152,33 @@ pub(crate) async fn set_room_visibility_route(
 
 	match &body.visibility {
 		| room::Visibility::Public => {
+			if services.server.config.room_directory_review
+				&& !services.users.is_admin(sender_user).await
+				&& body.appservice_info.is_none()
+			{
+				services
+					.rooms
+					.directory
+					.request_publish(&body.room_id, sender_user);
+
+				if services.server.config.admin_room_notices {
+					services
+						.admin
+						.send_text(&format!(
+							"{sender_user} requested to publish {} to the room directory; \
+							 review it with `!admin rooms directory pending`",
+							body.room_id
+						))
+						.await;
+				}
+				info!(
+					"{sender_user} requested to publish {0} to the room directory",
+					body.room_id
+				);
+
+				return Ok(set_room_visibility::v3::Response {});
+			}
+
 			if services.server.config.lockdown_public_room_directory
 				&& !services.users.is_admin(sender_user).await
 				&& body.appservice_info.is_none()
@@ -225,9 +252,12 @@ pub(crate) async fn get_room_visibility_route(
 	})
 }
 
+/// `origin` is the server asking for this server's rooms over federation, if
+/// any.
 pub(crate) async fn get_public_rooms_filtered_helper(
 	services: &Services,
 	server: Option<&ServerName>,
+	origin: Option<&ServerName>,
 	limit: Option<UInt>,
 	since: Option<&str>,
 	filter: &Filter,
@@ -236,6 +266,17 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 	if let Some(other_server) =
 		server.filter(|server_name| !services.globals.server_is_ours(server_name))
 	{
+		let page = RemotePage {
+			server: other_server.to_owned(),
+			limit,
+			since: since.map(ToOwned::to_owned),
+			filter: serde_json::to_string(filter)?,
+		};
+
+		if let Some(response) = services.rooms.directory.cached_remote_page(&page) {
+			return Ok(response);
+		}
+
 		let response = services
 			.sending
 			.send_federation_request(
@@ -252,12 +293,16 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 			)
 			.await?;
 
-		return Ok(get_public_rooms_filtered::v3::Response {
+		let response = get_public_rooms_filtered::v3::Response {
 			chunk: response.chunk,
 			prev_batch: response.prev_batch,
 			next_batch: response.next_batch,
 			total_room_count_estimate: response.total_room_count_estimate,
-		});
+		};
+
+		services.rooms.directory.cache_remote_page(page, &response);
+
+		return Ok(response);
 	}
 
 	// Use limit or else 10, with maximum 100
@@ -287,6 +332,11 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 		.rooms
 		.directory
 		.public_rooms()
+		.filter(|room_id| async move { !services.rooms.metadata.is_quarantined(room_id).await })
+		.filter(|room_id| async move {
+			// Rooms whose federation was cut off aren't listed to other servers
+			origin.is_none() || !services.rooms.metadata.is_unfederated(room_id).await
+		})
 		.map(ToOwned::to_owned)
 		.then(|room_id| public_rooms_chunk(services, room_id))
 		.filter_map(|chunk| async move {
@@ -0,0 +1,192 @@
+use axum::{
+	body::Body,
+	extract::{Path, State},
+	response::IntoResponse,
+};
+use bytes::Bytes;
+use conduwuit::{Err, Result};
+use futures::{stream, Stream};
+use http::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use service::{
+	export::{Export, Status},
+	Services,
+};
+use tokio::{fs::File, io::AsyncReadExt};
+
+use super::client_base_url;
+use crate::Ruma;
+
+/// Path downloads of completed exports are served from.
+const DOWNLOAD_PATH: &str = "/_conduwuit/client/v1/export/download";
+
+/// Size of the chunks exports are streamed in.
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// # `POST /_conduwuit/client/v1/export`
+///
+/// conduwuit-specific API requesting an export of the user's own data. The
+/// export is prepared in the background; its progress can be polled with `GET`
+/// on the same endpoint. Users may only request an export once per
+/// `user_data_export_interval`.
+pub(crate) async fn request_export_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_export::Request>,
+) -> Result<request_export::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	if body.appservice_info.is_some() || services.users.is_deactivated(sender_user).await? {
+		return Err!(Request(Forbidden("Only active users can export their data.")));
+	}
+
+	let export = services.export.request(sender_user).await?;
+
+	Ok(request_export::Response {
+		status: export_status(&services, &export),
+	})
+}
+
+/// # `GET /_conduwuit/client/v1/export`
+///
+/// conduwuit-specific API returning the status of the user's latest data
+/// export, with a URL to download it from once complete.
+pub(crate) async fn get_export_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_export::Request>,
+) -> Result<get_export::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let Ok(export) = services.export.get(sender_user).await else {
+		return Err!(Request(NotFound("No data export has been requested.")));
+	};
+
+	Ok(get_export::Response {
+		status: export_status(&services, &export),
+	})
+}
+
+/// # `GET /_conduwuit/client/v1/export/download/{id}`
+///
+/// conduwuit-specific API serving a completed data export. The export ID in
+/// the URL is the secret authorizing the download, which is only available
+/// until the export expires. The file is streamed rather than read into memory.
+pub(crate) async fn download_export_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+	let file = services.export.download(&id).await?;
+	let len = file.metadata().await?.len();
+
+	Ok((
+		[
+			(CONTENT_TYPE, "application/x-ndjson".to_owned()),
+			(CONTENT_DISPOSITION, r#"attachment; filename="export.ndjson""#.to_owned()),
+			(CONTENT_LENGTH, len.to_string()),
+		],
+		Body::from_stream(read_chunks(file)),
+	))
+}
+
+fn read_chunks(file: File) -> impl Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+	stream::try_unfold(file, |mut file| async move {
+		let mut chunk = vec![0_u8; DOWNLOAD_CHUNK_SIZE];
+		let read = file.read(&mut chunk).await?;
+		if read == 0 {
+			return Ok(None);
+		}
+
+		chunk.truncate(read);
+		Ok(Some((Bytes::from(chunk), file)))
+	})
+}
+
+fn export_status(services: &Services, export: &Export) -> ExportStatus {
+	let status = export.status();
+	let download_url = (status == Status::Complete)
+		.then(|| format!("{}{DOWNLOAD_PATH}/{}", client_base_url(services), export.id));
+
+	ExportStatus {
+		status: match status {
+			| Status::Pending => "pending",
+			| Status::Complete => "complete",
+			| Status::Failed => "failed",
+			| Status::Expired => "expired",
+		}
+		.to_owned(),
+		requested_at: export.requested_at,
+		completed_at: export.completed_at,
+		expires_at: export.expires_at,
+		size: (status == Status::Complete).then_some(export.size),
+		download_url,
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct ExportStatus {
+	/// One of `pending`, `complete`, `failed` or `expired`.
+	status: String,
+
+	/// When the export was requested, in milliseconds since the unix epoch.
+	requested_at: u64,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	completed_at: Option<u64>,
+
+	/// When the download stops being available, in milliseconds since the
+	/// unix epoch.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	expires_at: Option<u64>,
+
+	/// Size of the export in bytes.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	size: Option<u64>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	download_url: Option<String>,
+}
+
+pub(crate) mod request_export {
+	use ruma::api::{metadata, request, response, Metadata};
+
+	use super::ExportStatus;
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: true,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_conduwuit/client/v1/export",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		#[ruma_api(body)]
+		pub(crate) status: ExportStatus,
+	}
+}
+
+pub(crate) mod get_export {
+	use ruma::api::{metadata, request, response, Metadata};
+
+	use super::ExportStatus;
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_conduwuit/client/v1/export",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		#[ruma_api(body)]
+		pub(crate) status: ExportStatus,
+	}
+}
@@ -1,7 +1,7 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 
 use axum::extract::State;
-use conduwuit::{err, utils, Error, Result};
+use conduwuit::{err, info, result::LogErr, utils, Error, Result};
 use futures::{stream::FuturesUnordered, StreamExt};
 use ruma::{
 	api::{
@@ -15,6 +15,7 @@ use ruma::{
 		},
 		federation,
 	},
+	events::room::message::RoomMessageEventContent,
 	serde::Raw,
 	OneTimeKeyAlgorithm, OwnedDeviceId, OwnedUserId, UserId,
 };
@@ -108,7 +109,8 @@ pub(crate) async fn claim_keys_route(
 ///
 /// Uploads end-to-end key information for the sender user.
 ///
-/// - Requires UIAA to verify password
+/// - Requires UIAA to verify password, unless an admin has allowed the user to
+///   reset their keys
 pub(crate) async fn upload_signing_keys_route(
 	State(services): State<crate::State>,
 	body: Ruma<upload_signing_keys::v3::Request>,
@@ -116,7 +118,54 @@ pub(crate) async fn upload_signing_keys_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
-	// UIAA
+	// UIAA, unless an admin has allowed a reset
+	let reset_allowed = services
+		.users
+		.cross_signing_reset_allowed(sender_user)
+		.await;
+
+	if !reset_allowed {
+		check_cross_signing_uiaa(&services, &body).await?;
+	}
+
+	if let Some(master_key) = &body.master_key {
+		services
+			.users
+			.add_cross_signing_keys(
+				sender_user,
+				master_key,
+				&body.self_signing_key,
+				&body.user_signing_key,
+				true, // notify so that other users see the new keys
+			)
+			.await?;
+
+		if reset_allowed {
+			// The key update also tells the user's other sessions their identity changed
+			info!("{sender_user} reset their cross-signing keys as allowed by an admin");
+			services
+				.admin
+				.send_message(RoomMessageEventContent::notice_plain(format!(
+					"{sender_user} reset their cross-signing keys from device {sender_device} \
+					 as allowed by an admin."
+				)))
+				.await
+				.ok();
+		}
+	}
+
+	Ok(upload_signing_keys::v3::Response {})
+}
+
+/// Requires the user to authenticate with their password before uploading
+/// their cross-signing keys.
+async fn check_cross_signing_uiaa(
+	services: &Services,
+	body: &Ruma<upload_signing_keys::v3::Request>,
+) -> Result {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
 	let mut uiaainfo = UiaaInfo {
 		flows: vec![AuthFlow { stages: vec![AuthType::Password] }],
 		completed: Vec::new(),
@@ -135,31 +184,18 @@ pub(crate) async fn upload_signing_keys_route(
 			return Err(Error::Uiaa(uiaainfo));
 		}
 	// Success!
-	} else if let Some(json) = body.json_body {
+	} else if let Some(json) = &body.json_body {
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, &json);
+			.create(sender_user, sender_device, &uiaainfo, json);
 
 		return Err(Error::Uiaa(uiaainfo));
 	} else {
 		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
 	}
 
-	if let Some(master_key) = &body.master_key {
-		services
-			.users
-			.add_cross_signing_keys(
-				sender_user,
-				master_key,
-				&body.self_signing_key,
-				&body.user_signing_key,
-				true, // notify so that other users see the new keys
-			)
-			.await?;
-	}
-
-	Ok(upload_signing_keys::v3::Response {})
+	Ok(())
 }
 
 /// # `POST /_matrix/client/r0/keys/signatures/upload`
@@ -460,6 +496,18 @@ pub(crate) async fn claim_keys_helper(
 
 	let mut get_over_federation = BTreeMap::new();
 
+	let local_input: BTreeMap<_, _> = one_time_keys_input
+		.iter()
+		.filter(|(user_id, _)| services.globals.user_is_local(user_id))
+		.map(|(user_id, map)| (user_id.clone(), map.clone()))
+		.collect();
+
+	// Appservices serving their users' keys are asked first (MSC3983)
+	let mut claimed_from_appservices = services
+		.appservice
+		.claim_one_time_keys(&local_input)
+		.await;
+
 	for (user_id, map) in one_time_keys_input {
 		if !services.globals.user_is_local(user_id) {
 			get_over_federation
@@ -468,8 +516,17 @@ pub(crate) async fn claim_keys_helper(
 				.push((user_id, map));
 		}
 
-		let mut container = BTreeMap::new();
+		let mut container = claimed_from_appservices
+			.remove(user_id)
+			.unwrap_or_default();
+
+		container.retain(|_, keys| !keys.is_empty());
+		let mut claimed_local = false;
 		for (device_id, key_algorithm) in map {
+			if container.contains_key(device_id) {
+				continue;
+			}
+
 			if let Ok(one_time_keys) = services
 				.users
 				.take_one_time_key(user_id, device_id, key_algorithm)
@@ -478,8 +535,20 @@ pub(crate) async fn claim_keys_helper(
 				let mut c = BTreeMap::new();
 				c.insert(one_time_keys.0, one_time_keys.1);
 				container.insert(device_id.clone(), c);
+				claimed_local = true;
 			}
 		}
+
+		// Appservices receiving device keys learn the new counts (MSC3202)
+		if claimed_local {
+			services
+				.sending
+				.appservice_keys_claimed(user_id)
+				.await
+				.log_err()
+				.ok();
+		}
+
 		one_time_keys.insert(user_id.clone(), container);
 	}
 
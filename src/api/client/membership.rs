@@ -22,8 +22,12 @@ use ruma::{
 			error::ErrorKind,
 			knock::knock_room,
 			membership::{
-				ban_user, forget_room, get_member_events, invite_user, join_room_by_id,
-				join_room_by_id_or_alias,
+				ban_user, forget_room, get_member_events,
+				invite_user::{
+					self,
+					v3::{InvitationRecipient, Invite3pid},
+				},
+				join_room_by_id, join_room_by_id_or_alias,
 				joined_members::{self, v3::RoomMember},
 				joined_rooms, kick_user, leave_room, unban_user, ThirdPartySigned,
 			},
@@ -34,8 +38,9 @@ use ruma::{
 	events::{
 		room::{
 			join_rules::{AllowRule, JoinRule, RoomJoinRulesEventContent},
-			member::{MembershipState, RoomMemberEventContent},
+			member::{MembershipState, RoomMemberEventContent, ThirdPartyInvite},
 			message::RoomMessageEventContent,
+			third_party_invite::RoomThirdPartyInviteEventContent,
 		},
 		StateEventType,
 	},
@@ -46,6 +51,7 @@ use service::{
 	appservice::RegistrationInfo,
 	pdu::gen_event_id,
 	rooms::{state::RoomMutexGuard, state_compressor::HashSetCompressStateEvent},
+	threepid::InviteDetails,
 	Services,
 };
 
@@ -105,7 +111,7 @@ async fn banned_room_check(
 					.collect()
 					.await;
 
-				full_user_deactivate(services, user_id, &all_joined_rooms).await?;
+				full_user_deactivate(services, user_id, &all_joined_rooms, false).await?;
 			}
 
 			return Err!(Request(Forbidden("This room is banned on this homeserver.")));
@@ -146,7 +152,7 @@ async fn banned_room_check(
 					.collect()
 					.await;
 
-				full_user_deactivate(services, user_id, &all_joined_rooms).await?;
+				full_user_deactivate(services, user_id, &all_joined_rooms, false).await?;
 			}
 
 			return Err!(Request(Forbidden("This remote server is banned on this homeserver.")));
@@ -503,44 +509,191 @@ pub(crate) async fn invite_user_route(
 	)
 	.await?;
 
-	if let invite_user::v3::InvitationRecipient::UserId { user_id } = &body.recipient {
-		let sender_ignored_recipient = services.users.user_is_ignored(sender_user, user_id);
-		let recipient_ignored_by_sender = services.users.user_is_ignored(user_id, sender_user);
+	let user_id = match &body.recipient {
+		| InvitationRecipient::UserId { user_id } => user_id.clone(),
+		| InvitationRecipient::ThirdPartyId(invite) => {
+			let Some(user_id) = find_third_party_invitee(&services, invite).await? else {
+				invite_third_party_helper(&services, sender_user, &body.room_id, invite)
+					.boxed()
+					.await?;
 
-		let (sender_ignored_recipient, recipient_ignored_by_sender) =
-			join!(sender_ignored_recipient, recipient_ignored_by_sender);
+				return Ok(invite_user::v3::Response {});
+			};
 
-		if sender_ignored_recipient {
-			return Err!(Request(Forbidden(
-				"You cannot invite users you have ignored to rooms."
-			)));
+			user_id
+		},
+	};
+
+	let user_id = &user_id;
+	let sender_ignored_recipient = services.users.user_is_ignored(sender_user, user_id);
+	let recipient_ignored_by_sender = services.users.user_is_ignored(user_id, sender_user);
+
+	let (sender_ignored_recipient, recipient_ignored_by_sender) =
+		join!(sender_ignored_recipient, recipient_ignored_by_sender);
+
+	if sender_ignored_recipient {
+		return Err!(Request(Forbidden("You cannot invite users you have ignored to rooms.")));
+	}
+
+	if let Ok(target_user_membership) = services
+		.rooms
+		.state_accessor
+		.get_member(&body.room_id, user_id)
+		.await
+	{
+		if target_user_membership.membership == MembershipState::Ban {
+			return Err!(Request(Forbidden("User is banned from this room.")));
 		}
+	}
+
+	if recipient_ignored_by_sender {
+		// silently drop the invite to the recipient if they've been ignored by the
+		// sender, pretend it worked
+		return Ok(invite_user::v3::Response {});
+	}
 
-		if let Ok(target_user_membership) = services
+	invite_helper(
+		&services,
+		sender_user,
+		user_id,
+		&body.room_id,
+		body.reason.clone(),
+		false,
+		None,
+	)
+	.boxed()
+	.await?;
+
+	Ok(invite_user::v3::Response {})
+}
+
+/// Finds the user a third-party identifier being invited is bound to, either
+/// on this server or at the identity server the client named.
+async fn find_third_party_invitee(
+	services: &Services,
+	invite: &Invite3pid,
+) -> Result<Option<OwnedUserId>> {
+	if !services
+		.threepid
+		.is_trusted_identity_server(&invite.id_server)
+	{
+		return Err!(Request(Forbidden(
+			"Identity server {} is not trusted by this server.",
+			invite.id_server
+		)));
+	}
+
+	if let Ok(user_id) = services
+		.threepid
+		.find_user(&invite.medium, &invite.address)
+		.await
+	{
+		return Ok(Some(user_id));
+	}
+
+	services
+		.threepid
+		.lookup_threepid(
+			&invite.id_server,
+			&invite.id_access_token,
+			&invite.medium,
+			&invite.address,
+		)
+		.await
+}
+
+/// Invites a third-party identifier not bound to any user yet: the identity
+/// server stores the invite and notifies the identifier, and an
+/// `m.room.third_party_invite` event is sent into the room which the invitee
+/// redeems once they bind the identifier to their account.
+async fn invite_third_party_helper(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	invite: &Invite3pid,
+) -> Result {
+	if !services.users.is_admin(sender_user).await {
+		services.ratelimit.check_invite(sender_user, room_id)?;
+	}
+
+	if !services
+		.rooms
+		.state_cache
+		.is_joined(sender_user, room_id)
+		.await
+	{
+		return Err!(Request(Forbidden(
+			"You must be joined in the room you are trying to invite from."
+		)));
+	}
+
+	let details = InviteDetails {
+		medium: invite.medium.clone(),
+		address: invite.address.clone(),
+		room_id: room_id.to_owned(),
+		sender: sender_user.to_owned(),
+		room_alias: services
 			.rooms
 			.state_accessor
-			.get_member(&body.room_id, user_id)
+			.get_canonical_alias(room_id)
 			.await
-		{
-			if target_user_membership.membership == MembershipState::Ban {
-				return Err!(Request(Forbidden("User is banned from this room.")));
-			}
-		}
+			.ok(),
+		room_avatar_url: services
+			.rooms
+			.state_accessor
+			.get_avatar(room_id)
+			.await
+			.into_option()
+			.and_then(|avatar| avatar.url),
+		room_join_rules: services
+			.rooms
+			.state_accessor
+			.get_join_rule(room_id)
+			.await
+			.ok()
+			.map(|(join_rule, _)| join_rule.as_str().to_owned()),
+		room_name: services.rooms.state_accessor.get_name(room_id).await.ok(),
+		sender_display_name: services.users.displayname(sender_user).await.ok(),
+		sender_avatar_url: services.users.avatar_url(sender_user).await.ok(),
+	};
 
-		if recipient_ignored_by_sender {
-			// silently drop the invite to the recipient if they've been ignored by the
-			// sender, pretend it worked
-			return Ok(invite_user::v3::Response {});
-		}
+	let stored = services
+		.threepid
+		.store_invite(&invite.id_server, &invite.id_access_token, &details)
+		.await?;
 
-		invite_helper(&services, sender_user, user_id, &body.room_id, body.reason.clone(), false)
-			.boxed()
-			.await?;
+	let public_key = stored
+		.public_keys
+		.first()
+		.expect("identity server returned at least one public key");
 
-		Ok(invite_user::v3::Response {})
-	} else {
-		Err!(Request(NotFound("User not found.")))
-	}
+	let key_validity_url = public_key.key_validity_url.clone().unwrap_or_else(|| {
+		format!("https://{}/_matrix/identity/v2/pubkey/isvalid", invite.id_server)
+	});
+
+	let content = RoomThirdPartyInviteEventContent {
+		display_name: stored.display_name,
+		key_validity_url,
+		public_key: public_key.public_key.clone(),
+		public_keys: Some(stored.public_keys.clone()),
+	};
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+
+	services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(stored.token, &content),
+			sender_user,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	drop(state_lock);
+
+	Ok(())
 }
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/kick`
@@ -821,6 +974,10 @@ pub async fn join_room_by_id_helper(
 	third_party_signed: Option<&ThirdPartySigned>,
 	appservice_info: &Option<RegistrationInfo>,
 ) -> Result<join_room_by_id::v3::Response> {
+	if services.users.is_suspended(sender_user).await {
+		return Err!(Request(UserSuspended("Your account has been suspended.")));
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
 	let user_is_guest = services
@@ -844,6 +1001,15 @@ pub async fn join_room_by_id_helper(
 		return Ok(join_room_by_id::v3::Response { room_id: room_id.into() });
 	}
 
+	let is_admin = services.users.is_admin(sender_user).await;
+	if !is_admin && services.rooms.metadata.is_quarantined(room_id).await {
+		return Err!(Request(Forbidden("This room is quarantined on this homeserver.")));
+	}
+
+	if !is_admin {
+		services.ratelimit.check_raid_join(room_id, sender_user)?;
+	}
+
 	if let Ok(membership) = services
 		.rooms
 		.state_accessor
@@ -896,6 +1062,91 @@ pub async fn join_room_by_id_helper(
 	Ok(join_room_by_id::v3::Response::new(room_id.to_owned()))
 }
 
+/// Joins a local user to a room on a server admin's behalf, without their
+/// client. When this server is in the room and its join rules would turn the
+/// user away, a local member allowed to invite them does so first.
+pub async fn force_join_room_by_id_helper(
+	services: &Services,
+	user_id: &UserId,
+	room_id: &RoomId,
+	reason: Option<String>,
+	servers: &[OwnedServerName],
+) -> Result<join_room_by_id::v3::Response> {
+	let server_in_room = services
+		.rooms
+		.state_cache
+		.server_in_room(services.globals.server_name(), room_id)
+		.await;
+
+	let is_public = services
+		.rooms
+		.state_accessor
+		.room_state_get_content(room_id, &StateEventType::RoomJoinRules, "")
+		.await
+		.is_ok_and(|content: RoomJoinRulesEventContent| content.join_rule == JoinRule::Public);
+
+	if server_in_room
+		&& !is_public
+		&& !services.rooms.state_cache.is_joined(user_id, room_id).await
+		&& !services
+			.rooms
+			.state_cache
+			.is_invited(user_id, room_id)
+			.await
+	{
+		let state_lock = services.rooms.state.mutex.lock(room_id).await;
+
+		let members: Vec<OwnedUserId> = services
+			.rooms
+			.state_cache
+			.local_users_in_room(room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let mut inviter = None;
+		for member in members {
+			if services
+				.rooms
+				.state_accessor
+				.user_can_invite(room_id, &member, user_id, &state_lock)
+				.await
+			{
+				inviter = Some(member);
+				break;
+			}
+		}
+
+		let Some(inviter) = inviter else {
+			return Err!(Request(Forbidden(
+				"The room is not public and none of our users in it may invite {user_id}."
+			)));
+		};
+
+		let content = RoomMemberEventContent {
+			displayname: services.users.displayname(user_id).await.ok(),
+			avatar_url: services.users.avatar_url(user_id).await.ok(),
+			blurhash: services.users.blurhash(user_id).await.ok(),
+			..RoomMemberEventContent::new(MembershipState::Invite)
+		};
+
+		services
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder::state(user_id.to_string(), &content),
+				&inviter,
+				room_id,
+				&state_lock,
+			)
+			.await?;
+
+		info!("{inviter} invited {user_id} to {room_id} for a forced join");
+	}
+
+	join_room_by_id_helper(services, user_id, room_id, reason, servers, None, &None).await
+}
+
 #[tracing::instrument(skip_all, fields(%sender_user, %room_id), name = "join_remote")]
 async fn join_room_by_id_helper_remote(
 	services: &Services,
@@ -971,6 +1222,12 @@ async fn join_room_by_id_helper_remote(
 		.expect("event is valid, we just created it"),
 	);
 
+	services
+		.rooms
+		.timeline
+		.strip_member_profile(&mut join_event_stub, room_id, &remote_server)
+		.await;
+
 	// We keep the "event_id" in the pdu only in v1 or
 	// v2 rooms
 	match room_version_id {
@@ -1183,7 +1440,7 @@ async fn join_room_by_id_helper_remote(
 	} = services
 		.rooms
 		.state_compressor
-		.save_state(room_id, Arc::new(compressed))
+		.save_state(room_id, Arc::new(compressed), &state_lock)
 		.await?;
 
 	debug!("Forcing state for new room");
@@ -1391,6 +1648,12 @@ async fn join_room_by_id_helper_local(
 		.expect("event is valid, we just created it"),
 	);
 
+	services
+		.rooms
+		.timeline
+		.strip_member_profile(&mut join_event_stub, room_id, &remote_server)
+		.await;
+
 	// We keep the "event_id" in the pdu only in v1 or
 	// v2 rooms
 	match room_version_id {
@@ -1539,8 +1802,10 @@ pub(crate) async fn invite_helper(
 	room_id: &RoomId,
 	reason: Option<String>,
 	is_direct: bool,
+	third_party_invite: Option<ThirdPartyInvite>,
 ) -> Result {
-	if !services.users.is_admin(sender_user).await && services.globals.block_non_admin_invites() {
+	let is_admin = services.users.is_admin(sender_user).await;
+	if !is_admin && services.globals.block_non_admin_invites() {
 		info!(
 			"User {sender_user} is not an admin and attempted to send an invite to room \
 			 {room_id}"
@@ -1548,7 +1813,24 @@ pub(crate) async fn invite_helper(
 		return Err!(Request(Forbidden("Invites are not allowed on this server.")));
 	}
 
+	if !is_admin {
+		services.ratelimit.check_invite(sender_user, room_id)?;
+	}
+
+	services
+		.spam_checker
+		.user_may_invite(sender_user, user_id, room_id)
+		.await?;
+
+	if services.users.is_erased(user_id).await {
+		return Err!(Request(Forbidden("This user's account has been erased.")));
+	}
+
 	if !services.globals.user_is_local(user_id) {
+		if services.rooms.metadata.is_unfederated(room_id).await {
+			return Err!(Request(Forbidden("This room does not federate with other servers.")));
+		}
+
 		let (pdu, pdu_json, invite_room_state) = {
 			let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
@@ -1556,6 +1838,7 @@ pub(crate) async fn invite_helper(
 				avatar_url: services.users.avatar_url(user_id).await.ok(),
 				is_direct: Some(is_direct),
 				reason,
+				third_party_invite,
 				..RoomMemberEventContent::new(MembershipState::Invite)
 			};
 
@@ -1656,6 +1939,7 @@ pub(crate) async fn invite_helper(
 		blurhash: services.users.blurhash(user_id).await.ok(),
 		is_direct: Some(is_direct),
 		reason,
+		third_party_invite,
 		..RoomMemberEventContent::new(MembershipState::Invite)
 	};
 
@@ -1960,6 +2244,10 @@ async fn knock_room_by_id_helper(
 	reason: Option<String>,
 	servers: &[OwnedServerName],
 ) -> Result<knock_room::v3::Response> {
+	if services.users.is_suspended(sender_user).await {
+		return Err!(Request(UserSuspended("Your account has been suspended.")));
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
 	if services
@@ -2127,6 +2415,12 @@ async fn knock_room_helper_local(
 		.expect("event is valid, we just created it"),
 	);
 
+	services
+		.rooms
+		.timeline
+		.strip_member_profile(&mut knock_event_stub, room_id, &remote_server)
+		.await;
+
 	// In order to create a compatible ref hash (EventID) the `hashes` field needs
 	// to be present
 	services
@@ -2255,6 +2549,12 @@ async fn knock_room_helper_remote(
 		.expect("event is valid, we just created it"),
 	);
 
+	services
+		.rooms
+		.timeline
+		.strip_member_profile(&mut knock_event_stub, room_id, &remote_server)
+		.await;
+
 	// In order to create a compatible ref hash (EventID) the `hashes` field needs
 	// to be present
 	services
@@ -2354,7 +2654,7 @@ async fn knock_room_helper_remote(
 	} = services
 		.rooms
 		.state_compressor
-		.save_state(room_id, Arc::new(compressed))
+		.save_state(room_id, Arc::new(compressed), &state_lock)
 		.await?;
 
 	debug!("Forcing state for new room");
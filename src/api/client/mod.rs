@@ -1,5 +1,6 @@
 pub(super) mod account;
 pub(super) mod account_data;
+pub(super) mod account_validity;
 pub(super) mod alias;
 pub(super) mod appservice;
 pub(super) mod backup;
@@ -7,6 +8,7 @@ pub(super) mod capabilities;
 pub(super) mod context;
 pub(super) mod device;
 pub(super) mod directory;
+pub(super) mod export;
 pub(super) mod filter;
 pub(super) mod keys;
 pub(super) mod media;
@@ -20,12 +22,14 @@ pub(super) mod push;
 pub(super) mod read_marker;
 pub(super) mod redact;
 pub(super) mod relations;
+pub(super) mod rendezvous;
 pub(super) mod report;
 pub(super) mod room;
 pub(super) mod search;
 pub(super) mod send;
 pub(super) mod session;
 pub(super) mod space;
+pub(super) mod sso;
 pub(super) mod state;
 pub(super) mod sync;
 pub(super) mod tag;
@@ -42,19 +46,24 @@ pub(super) mod well_known;
 pub use account::full_user_deactivate;
 pub(super) use account::*;
 pub(super) use account_data::*;
+pub(super) use account_validity::*;
 pub(super) use alias::*;
 pub(super) use appservice::*;
 pub(super) use backup::*;
 pub(super) use capabilities::*;
+use conduwuit::{Err, Result};
 pub(super) use context::*;
 pub(super) use device::*;
 pub(super) use directory::*;
+pub(super) use export::*;
 pub(super) use filter::*;
 pub(super) use keys::*;
 pub(super) use media::*;
 pub(super) use media_legacy::*;
 pub(super) use membership::*;
-pub use membership::{join_room_by_id_helper, leave_all_rooms, leave_room};
+pub use membership::{
+	force_join_room_by_id_helper, join_room_by_id_helper, leave_all_rooms, leave_room,
+};
 pub(super) use message::*;
 pub(super) use openid::*;
 pub(super) use presence::*;
@@ -64,12 +73,14 @@ pub(super) use push::*;
 pub(super) use read_marker::*;
 pub(super) use redact::*;
 pub(super) use relations::*;
+pub(super) use rendezvous::*;
 pub(super) use report::*;
 pub(super) use room::*;
 pub(super) use search::*;
 pub(super) use send::*;
 pub(super) use session::*;
 pub(super) use space::*;
+pub(super) use sso::*;
 pub(super) use state::*;
 pub(super) use sync::*;
 pub(super) use tag::*;
@@ -91,3 +102,30 @@ const TOKEN_LENGTH: usize = 32;
 
 /// generated user session ID length
 const SESSION_ID_LENGTH: usize = service::uiaa::SESSION_ID_LENGTH;
+
+/// Rejects conduwuit's own authentication endpoints when authentication is
+/// delegated to an OpenID Connect provider (MSC3861).
+fn check_legacy_auth(services: &service::Services) -> Result {
+	if services.delegated_auth.is_enabled() {
+		return Err!(Request(Unrecognized(
+			"Authentication is delegated to an OpenID Connect provider on this server."
+		)));
+	}
+
+	Ok(())
+}
+
+/// Base URL for links to this server's conduwuit-specific client APIs, without
+/// a trailing slash.
+fn client_base_url(services: &service::Services) -> String {
+	services
+		.server
+		.config
+		.well_known
+		.client
+		.as_ref()
+		.map_or_else(
+			|| format!("https://{}", services.globals.server_name()),
+			|url| url.as_str().trim_end_matches('/').to_owned(),
+		)
+}
@@ -4,9 +4,9 @@ use ruma::{
 	api::client::{
 		error::ErrorKind,
 		push::{
-			delete_pushrule, get_pushers, get_pushrule, get_pushrule_actions,
-			get_pushrule_enabled, get_pushrules_all, get_pushrules_global_scope, set_pusher,
-			set_pushrule, set_pushrule_actions, set_pushrule_enabled,
+			delete_pushrule, get_pushrule, get_pushrule_actions, get_pushrule_enabled,
+			get_pushrules_all, get_pushrules_global_scope, set_pusher, set_pushrule,
+			set_pushrule_actions, set_pushrule_enabled,
 		},
 	},
 	events::{
@@ -15,11 +15,11 @@ use ruma::{
 	},
 	push::{
 		InsertPushRuleError, PredefinedContentRuleId, PredefinedOverrideRuleId,
-		RemovePushRuleError, Ruleset,
+		RemovePushRuleError,
 	},
 	CanonicalJsonObject, CanonicalJsonValue,
 };
-use service::Services;
+use service::{pusher::PusherState, Services};
 
 use crate::{Error, Result, Ruma};
 
@@ -77,7 +77,8 @@ pub(crate) async fn get_pushrules_all_route(
 				.remove(Content, PredefinedContentRuleId::ContainsUserName)
 				.ok();
 
-			global_ruleset.update_with_server_default(Ruleset::server_default(sender_user));
+			global_ruleset
+				.update_with_server_default(services.pusher.server_default_rules(sender_user));
 
 			services
 				.account_data
@@ -126,7 +127,7 @@ pub(crate) async fn get_pushrules_global_route(
 				GlobalAccountDataEventType::PushRules.to_string().into(),
 				&serde_json::to_value(PushRulesEvent {
 					content: PushRulesEventContent {
-						global: Ruleset::server_default(sender_user),
+						global: services.pusher.server_default_rules(sender_user),
 					},
 				})
 				.expect("to json always works"),
@@ -134,7 +135,7 @@ pub(crate) async fn get_pushrules_global_route(
 			.await?;
 
 		return Ok(get_pushrules_global_scope::v3::Response {
-			global: Ruleset::server_default(sender_user),
+			global: services.pusher.server_default_rules(sender_user),
 		});
 	};
 
@@ -170,7 +171,8 @@ pub(crate) async fn get_pushrules_global_route(
 				.remove(Content, PredefinedContentRuleId::ContainsUserName)
 				.ok();
 
-			global_ruleset.update_with_server_default(Ruleset::server_default(sender_user));
+			global_ruleset
+				.update_with_server_default(services.pusher.server_default_rules(sender_user));
 
 			services
 				.account_data
@@ -478,32 +480,81 @@ pub(crate) async fn delete_pushrule_route(
 
 /// # `GET /_matrix/client/r0/pushers`
 ///
-/// Gets all currently active pushers for the sender user.
+/// Gets all currently active pushers for the sender user, including whether
+/// each is enabled and which device created it (MSC3881).
 pub(crate) async fn get_pushers_route(
 	State(services): State<crate::State>,
-	body: Ruma<get_pushers::v3::Request>,
-) -> Result<get_pushers::v3::Response> {
-	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	body: Ruma<get_pushers::Request>,
+) -> Result<get_pushers::Response> {
+	let sender_user = body.sender_user();
 
-	Ok(get_pushers::v3::Response {
-		pushers: services.pusher.get_pushers(sender_user).await,
-	})
+	let mut pushers = Vec::new();
+	for pusher in services.pusher.get_pushers(sender_user).await {
+		let state = services
+			.pusher
+			.get_pusher_state(sender_user, pusher.ids.pushkey.as_str())
+			.await;
+
+		pushers.push(get_pushers::PusherWithState {
+			pusher,
+			enabled: state.enabled,
+			device_id: state.device_id,
+		});
+	}
+
+	Ok(get_pushers::Response { pushers })
 }
 
 /// # `POST /_matrix/client/r0/pushers/set`
 ///
-/// Adds a pusher for the sender user.
+/// Adds a pusher for the sender user. Accepts `org.matrix.msc3881.enabled`
+/// so a client can disable the pushers of the user's other devices.
 ///
 /// - TODO: Handle `append`
 pub(crate) async fn set_pushers_route(
 	State(services): State<crate::State>,
 	body: Ruma<set_pusher::v3::Request>,
 ) -> Result<set_pusher::v3::Response> {
-	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_user = body.sender_user();
+
+	let enabled = match body
+		.json_body
+		.as_ref()
+		.and_then(|body| body.as_object())
+		.and_then(|body| body.get("org.matrix.msc3881.enabled"))
+	{
+		| None => true,
+		| Some(CanonicalJsonValue::Bool(enabled)) => *enabled,
+		| Some(_) => {
+			return Err!(Request(InvalidParam("org.matrix.msc3881.enabled must be a boolean")));
+		},
+	};
+
+	let mut state = PusherState {
+		enabled,
+		device_id: body.sender_device.clone(),
+	};
+
+	// Toggling another device's pusher keeps it associated with that device
+	if let set_pusher::v3::PusherAction::Post(data) = &body.action {
+		let pushkey = data.pusher.ids.pushkey.as_str();
+		if services
+			.pusher
+			.get_pusher(sender_user, pushkey)
+			.await
+			.is_ok()
+		{
+			let existing = services.pusher.get_pusher_state(sender_user, pushkey).await;
+
+			if existing.device_id.is_some() {
+				state.device_id = existing.device_id;
+			}
+		}
+	}
 
 	services
 		.pusher
-		.set_pusher(sender_user, &body.action)
+		.set_pusher(sender_user, &body.action, &state)
 		.await?;
 
 	Ok(set_pusher::v3::Response::new())
@@ -523,7 +574,7 @@ async fn recreate_push_rules_and_return(
 			GlobalAccountDataEventType::PushRules.to_string().into(),
 			&serde_json::to_value(PushRulesEvent {
 				content: PushRulesEventContent {
-					global: Ruleset::server_default(sender_user),
+					global: services.pusher.server_default_rules(sender_user),
 				},
 			})
 			.expect("to json always works"),
@@ -531,6 +582,48 @@ async fn recreate_push_rules_and_return(
 		.await?;
 
 	Ok(get_pushrules_all::v3::Response {
-		global: Ruleset::server_default(sender_user),
+		global: services.pusher.server_default_rules(sender_user),
 	})
 }
+
+pub(crate) mod get_pushers {
+	use ruma::{
+		api::{client::push::Pusher, metadata, request, response, Metadata},
+		OwnedDeviceId,
+	};
+	use serde::{Deserialize, Serialize};
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			1.0 => "/_matrix/client/r0/pushers",
+			1.1 => "/_matrix/client/v3/pushers",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub(crate) struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	pub(crate) struct Response {
+		pub(crate) pushers: Vec<PusherWithState>,
+	}
+
+	/// A pusher with its MSC3881 fields.
+	#[derive(Clone, Debug, Deserialize, Serialize)]
+	pub(crate) struct PusherWithState {
+		#[serde(flatten)]
+		pub(crate) pusher: Pusher,
+
+		#[serde(rename = "org.matrix.msc3881.enabled")]
+		pub(crate) enabled: bool,
+
+		#[serde(
+			rename = "org.matrix.msc3881.device_id",
+			skip_serializing_if = "Option::is_none"
+		)]
+		pub(crate) device_id: Option<OwnedDeviceId>,
+	}
+}
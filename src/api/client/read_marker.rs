@@ -46,7 +46,8 @@ pub(crate) async fn set_read_marker_route(
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_notification_counts(sender_user, &body.room_id)
+			.await;
 	}
 
 	// ping presence
@@ -111,6 +112,12 @@ pub(crate) async fn create_receipt_route(
 ) -> Result<create_receipt::v3::Response> {
 	let sender_user = body.sender_user();
 
+	if matches!(&body.receipt_type, create_receipt::v3::ReceiptType::FullyRead)
+		&& body.thread != ReceiptThread::Unthreaded
+	{
+		return Err!(Request(InvalidParam("Fully read markers cannot be threaded.")));
+	}
+
 	if matches!(
 		&body.receipt_type,
 		create_receipt::v3::ReceiptType::Read | create_receipt::v3::ReceiptType::ReadPrivate
@@ -118,7 +125,8 @@ pub(crate) async fn create_receipt_route(
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_notification_counts_for(sender_user, &body.room_id, &body.thread)
+			.await;
 	}
 
 	// ping presence
@@ -155,7 +163,7 @@ pub(crate) async fn create_receipt_route(
 						sender_user.to_owned(),
 						ruma::events::receipt::Receipt {
 							ts: Some(MilliSecondsSinceUnixEpoch::now()),
-							thread: ReceiptThread::Unthreaded,
+							thread: body.thread.clone(),
 						},
 					)]),
 				)]),
@@ -175,6 +183,12 @@ pub(crate) async fn create_receipt_route(
 				.await;
 		},
 		| create_receipt::v3::ReceiptType::ReadPrivate => {
+			// The private read marker tracks the room as a whole; a receipt for a single
+			// thread only clears that thread's notifications.
+			if matches!(body.thread, ReceiptThread::Thread(_)) {
+				return Ok(create_receipt::v3::Response {});
+			}
+
 			let count = services
 				.rooms
 				.timeline
@@ -0,0 +1,121 @@
+use axum::{
+	extract::{Path, State},
+	response::{IntoResponse, Response},
+};
+use axum_extra::{
+	headers::{CacheControl, Expires, LastModified},
+	TypedHeader,
+};
+use conduwuit::{err, Err, Result};
+use http::{
+	header::{CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, PRAGMA},
+	HeaderMap, HeaderName, StatusCode,
+};
+use reqwest::Url;
+use ruma::api::client::rendezvous::create_rendezvous_session;
+use service::rendezvous::Session;
+
+use super::client_base_url;
+use crate::Ruma;
+
+/// Path rendezvous sessions are served under.
+const RENDEZVOUS_PATH: &str = "/_matrix/client/unstable/org.matrix.msc4108/rendezvous";
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc4108/rendezvous`
+///
+/// Creates a rendezvous session for signing in with a QR code (MSC4108),
+/// returning the URL the other device reads and writes it at. Sessions expire
+/// after a minute.
+pub(crate) async fn create_rendezvous_session_route(
+	State(services): State<crate::State>,
+	body: Ruma<create_rendezvous_session::unstable::Request>,
+) -> Result<create_rendezvous_session::unstable::Response> {
+	let (id, session) = services.rendezvous.create(body.body.content)?;
+	let url = Url::parse(&format!("{}{RENDEZVOUS_PATH}/{id}", client_base_url(&services)))
+		.map_err(|e| err!(Config("well_known.client", "Invalid client URL: {e}")))?;
+
+	Ok(create_rendezvous_session::unstable::Response {
+		url,
+		etag: session.etag,
+		expires: session.expires,
+		last_modified: session.last_modified,
+	})
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Gets the current payload of a rendezvous session. Responds with `304 Not
+/// Modified` if it still matches the `If-None-Match` header.
+pub(crate) async fn get_rendezvous_session_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+	headers: HeaderMap,
+) -> Result<Response> {
+	let session = services.rendezvous.get(&id)?;
+	let unchanged = headers
+		.get(IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.is_some_and(|etag| etag == session.etag);
+
+	if unchanged {
+		return Ok((StatusCode::NOT_MODIFIED, session_headers(&session)).into_response());
+	}
+
+	Ok((
+		StatusCode::OK,
+		[(CONTENT_TYPE, "text/plain")],
+		session_headers(&session),
+		session.content,
+	)
+		.into_response())
+}
+
+/// # `PUT /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Replaces the payload of a rendezvous session. The `If-Match` header must
+/// carry the ETag of the payload last read, so a device cannot overwrite a
+/// message it has not seen.
+pub(crate) async fn update_rendezvous_session_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+	headers: HeaderMap,
+	content: String,
+) -> Result<Response> {
+	let Some(etag) = headers.get(IF_MATCH).and_then(|value| value.to_str().ok()) else {
+		return Err!(Request(MissingParam("Missing If-Match header.")));
+	};
+
+	let session = services.rendezvous.update(&id, etag, content)?;
+
+	Ok((StatusCode::ACCEPTED, session_headers(&session)).into_response())
+}
+
+/// # `DELETE /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Ends a rendezvous session once the devices are done with it.
+pub(crate) async fn delete_rendezvous_session_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+) -> Result<StatusCode> {
+	services.rendezvous.delete(&id)?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+/// Headers describing the session's payload, which must not be cached by
+/// intermediaries.
+fn session_headers(
+	session: &Session,
+) -> (
+	[(HeaderName, String); 2],
+	TypedHeader<Expires>,
+	TypedHeader<LastModified>,
+	TypedHeader<CacheControl>,
+) {
+	(
+		[(ETAG, session.etag.clone()), (PRAGMA, "no-cache".to_owned())],
+		TypedHeader(Expires::from(session.expires)),
+		TypedHeader(LastModified::from(session.last_modified)),
+		TypedHeader(CacheControl::new().with_no_store()),
+	)
+}
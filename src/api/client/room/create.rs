@@ -2,7 +2,10 @@ use std::collections::BTreeMap;
 
 use axum::extract::State;
 use conduwuit::{
-	debug_info, debug_warn, err, error, info, pdu::PduBuilder, warn, Err, Error, Result,
+	config::{DefaultJoinRule, RoomPresetConfig, RoomStateTemplate},
+	debug_info, debug_warn, err, error, info,
+	pdu::PduBuilder,
+	warn, Err, Error, Result,
 };
 use futures::FutureExt;
 use ruma::{
@@ -14,6 +17,7 @@ use ruma::{
 		room::{
 			canonical_alias::RoomCanonicalAliasEventContent,
 			create::RoomCreateEventContent,
+			encryption::RoomEncryptionEventContent,
 			guest_access::{GuestAccess, RoomGuestAccessEventContent},
 			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
 			join_rules::{JoinRule, RoomJoinRulesEventContent},
@@ -26,13 +30,16 @@ use ruma::{
 	},
 	int,
 	serde::{JsonObject, Raw},
-	CanonicalJsonObject, Int, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+	CanonicalJsonObject, Int, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomVersionId,
 };
 use serde_json::{json, value::to_raw_value};
 use service::{appservice::RegistrationInfo, Services};
 
 use crate::{client::invite_helper, Ruma};
 
+/// Key of `creation_content` naming one of the server's `room_presets`.
+const NAMED_PRESET_KEY: &str = "org.conduwuit.preset";
+
 /// # `POST /_matrix/client/v3/createRoom`
 ///
 /// Creates a new room.
@@ -46,7 +53,7 @@ use crate::{client::invite_helper, Ruma};
 /// - Send join rules
 /// - Send history visibility
 /// - Send guest access
-/// - Send events listed in initial state
+/// - Send events listed in initial state, and the server's default state
 /// - Send events implied by `name` and `topic`
 /// - Send invite events
 #[allow(clippy::large_stack_frames)]
@@ -68,22 +75,41 @@ pub(crate) async fn create_room_route(
 		));
 	}
 
+	services
+		.spam_checker
+		.user_may_create_room(sender_user)
+		.await?;
+
+	// Whose choices take precedence over the server's room defaults
+	let privileged = body.appservice_info.is_some() || services.users.is_admin(sender_user).await;
+
+	let named_preset = named_preset(&services, &body)?;
+
 	let room_id: OwnedRoomId = if let Some(custom_room_id) = &body.room_id {
-		custom_room_id_check(&services, custom_room_id)?
+		custom_room_id_check(&services, custom_room_id, body.appservice_info.as_ref())?
 	} else {
-		RoomId::new(&services.server.name)
+		services.rooms.metadata.generate_room_id().await?
 	};
 
-	// check if room ID doesn't already exist instead of erroring on auth check
-	if services.rooms.short.get_shortroomid(&room_id).await.is_ok() {
+	// check if room ID doesn't already exist instead of erroring on auth check, and
+	// that it isn't the ID of a banned room we never knew
+	if services.rooms.short.get_shortroomid(&room_id).await.is_ok()
+		|| services.rooms.metadata.is_banned(&room_id).await
+	{
 		return Err(Error::BadRequest(
 			ErrorKind::RoomInUse,
 			"Room with that custom room ID already exists",
 		));
 	}
 
+	let publish_needs_review = body.visibility == room::Visibility::Public
+		&& services.server.config.room_directory_review
+		&& body.appservice_info.is_none()
+		&& !services.users.is_admin(sender_user).await;
+
 	if body.visibility == room::Visibility::Public
 		&& services.server.config.lockdown_public_room_directory
+		&& !publish_needs_review
 		&& !services.users.is_admin(sender_user).await
 		&& body.appservice_info.is_none()
 	{
@@ -143,6 +169,7 @@ pub(crate) async fn create_room_route(
 					error!("Failed to deserialise content as canonical JSON: {}", e);
 					Error::bad_database("Failed to deserialise content as canonical JSON.")
 				})?;
+			content.remove(NAMED_PRESET_KEY);
 			match room_version {
 				| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 => {
 					content.insert(
@@ -229,11 +256,15 @@ pub(crate) async fn create_room_route(
 
 	// 3. Power levels
 
-	// Figure out preset. We need it for preset specific events
-	let preset = body.preset.clone().unwrap_or(match &body.visibility {
-		| room::Visibility::Public => RoomPreset::PublicChat,
-		| _ => RoomPreset::PrivateChat, // Room visibility should not be custom
-	});
+	// Figure out preset. We need it for preset specific events. Named presets
+	// build on one of the spec's.
+	let preset = match named_preset {
+		| Some(named_preset) => named_preset.base.clone().unwrap_or(RoomPreset::PrivateChat),
+		| None => body.preset.clone().unwrap_or(match &body.visibility {
+			| room::Visibility::Public => RoomPreset::PublicChat,
+			| _ => RoomPreset::PrivateChat, // Room visibility should not be custom
+		}),
+	};
 
 	let mut users = BTreeMap::from_iter([(sender_user.clone(), int!(100))]);
 
@@ -254,9 +285,12 @@ pub(crate) async fn create_room_route(
 	}
 
 	let power_levels_content = default_power_levels_content(
+		&services,
+		named_preset.map(|named_preset| &named_preset.power_levels),
 		body.power_level_content_override.as_ref(),
 		&body.visibility,
 		users,
+		privileged,
 	)?;
 
 	services
@@ -298,18 +332,23 @@ pub(crate) async fn create_room_route(
 	// 5. Events set by preset
 
 	// 5.1 Join Rules
+	let chose_preset = body.preset.is_some() || named_preset.is_some();
+	let preset_join_rule = named_preset.and_then(|named_preset| named_preset.join_rule);
+	let join_rule = match (services.server.config.default_room_join_rule, preset_join_rule) {
+		| (Some(join_rule), _) if !(privileged && chose_preset) => to_join_rule(join_rule),
+		| (_, Some(join_rule)) => to_join_rule(join_rule),
+		| _ => match preset {
+			| RoomPreset::PublicChat => JoinRule::Public,
+			// according to spec "invite" is the default
+			| _ => JoinRule::Invite,
+		},
+	};
+
 	services
 		.rooms
 		.timeline
 		.build_and_append_pdu(
-			PduBuilder::state(
-				String::new(),
-				&RoomJoinRulesEventContent::new(match preset {
-					| RoomPreset::PublicChat => JoinRule::Public,
-					// according to spec "invite" is the default
-					| _ => JoinRule::Invite,
-				}),
-			),
+			PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(join_rule)),
 			sender_user,
 			&room_id,
 			&state_lock,
@@ -352,7 +391,9 @@ pub(crate) async fn create_room_route(
 		.boxed()
 		.await?;
 
-	// 6. Events listed in initial_state
+	// 6. Events listed in initial_state and by the named preset, and the server's
+	// default state
+	let mut initial_state = Vec::with_capacity(body.initial_state.len());
 	for event in &body.initial_state {
 		let mut pdu_builder = event.deserialize_as::<PduBuilder>().map_err(|e| {
 			warn!("Invalid initial state event: {:?}", e);
@@ -373,6 +414,41 @@ pub(crate) async fn create_room_route(
 		// Implicit state key defaults to ""
 		pdu_builder.state_key.get_or_insert_with(String::new);
 
+		initial_state.push(pdu_builder);
+	}
+
+	let same_state = |a: &PduBuilder, b: &PduBuilder| {
+		a.event_type == b.event_type && a.state_key == b.state_key
+	};
+
+	if let Some(named_preset) = named_preset {
+		let mut preset_state = state_from_templates(&named_preset.initial_state)?;
+		if named_preset.encryption {
+			preset_state.push(PduBuilder::state(
+				String::new(),
+				&RoomEncryptionEventContent::with_recommended_defaults(),
+			));
+		}
+
+		preset_state.retain(|state| !initial_state.iter().any(|own| same_state(own, state)));
+		initial_state = preset_state.into_iter().chain(initial_state).collect();
+	}
+
+	// The events of whoever doesn't take precedence are left out where the other
+	// sets the same state
+	let server_state = default_room_state(&services, &preset)?;
+	let (overridden, kept) = if privileged {
+		(server_state, initial_state)
+	} else {
+		(initial_state, server_state)
+	};
+
+	let overridden: Vec<_> = overridden
+		.into_iter()
+		.filter(|pdu_builder| !kept.iter().any(|kept| same_state(kept, pdu_builder)))
+		.collect();
+
+	for pdu_builder in overridden.into_iter().chain(kept) {
 		// Silently skip encryption events if they are not allowed
 		if pdu_builder.event_type == TimelineEventType::RoomEncryption
 			&& !services.globals.allow_encryption()
@@ -431,7 +507,7 @@ pub(crate) async fn create_room_route(
 		}
 
 		if let Err(e) =
-			invite_helper(&services, sender_user, user_id, &room_id, None, body.is_direct)
+			invite_helper(&services, sender_user, user_id, &room_id, None, body.is_direct, None)
 				.boxed()
 				.await
 		{
@@ -447,7 +523,24 @@ pub(crate) async fn create_room_route(
 			.set_alias(&alias, &room_id, sender_user)?;
 	}
 
-	if body.visibility == room::Visibility::Public {
+	if publish_needs_review {
+		services
+			.rooms
+			.directory
+			.request_publish(&room_id, sender_user);
+
+		if services.server.config.admin_room_notices {
+			services
+				.admin
+				.send_text(&format!(
+					"{sender_user} requested to publish {} to the room directory; review it \
+					 with `!admin rooms directory pending`",
+					&room_id
+				))
+				.await;
+		}
+		info!("{sender_user} requested to publish {0} to the room directory", &room_id);
+	} else if body.visibility == room::Visibility::Public {
 		services.rooms.directory.set_public(&room_id);
 
 		if services.server.config.admin_room_notices {
@@ -467,11 +560,97 @@ pub(crate) async fn create_room_route(
 	Ok(create_room::v3::Response::new(room_id))
 }
 
+/// The state the server adds to every new room: `default_room_initial_state`
+/// and the encryption event of `default_room_encryption`.
+fn default_room_state(
+	services: &Services,
+	preset: &create_room::v3::RoomPreset,
+) -> Result<Vec<PduBuilder>> {
+	let config = &services.server.config;
+	let mut state = Vec::with_capacity(config.default_room_initial_state.len().saturating_add(1));
+
+	if config.default_room_encryption && *preset != create_room::v3::RoomPreset::PublicChat {
+		state.push(PduBuilder::state(
+			String::new(),
+			&RoomEncryptionEventContent::with_recommended_defaults(),
+		));
+	}
+
+	state.extend(state_from_templates(&config.default_room_initial_state)?);
+
+	Ok(state)
+}
+
+fn state_from_templates(templates: &[RoomStateTemplate]) -> Result<Vec<PduBuilder>> {
+	templates
+		.iter()
+		.map(|template| {
+			Ok(PduBuilder {
+				event_type: template.event_type.clone().into(),
+				content: to_raw_value(&template.content)?,
+				state_key: Some(template.state_key.clone()),
+				..Default::default()
+			})
+		})
+		.collect()
+}
+
+/// The server's named preset chosen as the request's `preset`, or in its
+/// `creation_content`.
+fn named_preset<'a>(
+	services: &'a Services,
+	body: &create_room::v3::Request,
+) -> Result<Option<&'a RoomPresetConfig>> {
+	use create_room::v3::RoomPreset;
+
+	let from_content = body
+		.creation_content
+		.as_ref()
+		.map(|content| content.get_field::<String>(NAMED_PRESET_KEY))
+		.transpose()
+		.map_err(|e| err!(Request(BadJson("Invalid {NAMED_PRESET_KEY}: {e}"))))?
+		.flatten();
+
+	let from_preset = body
+		.preset
+		.as_ref()
+		.filter(|preset| {
+			!matches!(
+				preset,
+				RoomPreset::PrivateChat | RoomPreset::PublicChat | RoomPreset::TrustedPrivateChat
+			)
+		})
+		.map(|preset| preset.as_str().to_owned());
+
+	let Some(name) = from_content.or(from_preset) else {
+		return Ok(None);
+	};
+
+	services
+		.server
+		.config
+		.room_presets
+		.get(&name)
+		.map(Some)
+		.ok_or_else(|| err!(Request(InvalidParam("Unknown room preset {name:?}"))))
+}
+
+fn to_join_rule(join_rule: DefaultJoinRule) -> JoinRule {
+	match join_rule {
+		| DefaultJoinRule::Public => JoinRule::Public,
+		| DefaultJoinRule::Invite => JoinRule::Invite,
+		| DefaultJoinRule::Knock => JoinRule::Knock,
+	}
+}
+
 /// creates the power_levels_content for the PDU builder
 fn default_power_levels_content(
+	services: &Services,
+	preset_power_levels: Option<&BTreeMap<String, serde_json::Value>>,
 	power_level_content_override: Option<&Raw<RoomPowerLevelsEventContent>>,
 	visibility: &room::Visibility,
 	users: BTreeMap<OwnedUserId, Int>,
+	privileged: bool,
 ) -> Result<serde_json::Value> {
 	let mut power_levels_content =
 		serde_json::to_value(RoomPowerLevelsEventContent { users, ..Default::default() })
@@ -512,6 +691,17 @@ fn default_power_levels_content(
 			serde_json::to_value(50).expect("50 is valid Value");
 	}
 
+	// The server's power levels apply before the creator's override if the creator
+	// is privileged, otherwise after it
+	let server_power_levels = &services.server.config.default_room_power_levels;
+	if privileged {
+		merge_power_levels(&mut power_levels_content, server_power_levels);
+	}
+
+	if let Some(preset_power_levels) = preset_power_levels {
+		merge_power_levels(&mut power_levels_content, preset_power_levels);
+	}
+
 	if let Some(power_level_content_override) = power_level_content_override {
 		let json: JsonObject = serde_json::from_str(power_level_content_override.json().get())
 			.map_err(|_| {
@@ -523,9 +713,31 @@ fn default_power_levels_content(
 		}
 	}
 
+	if !privileged {
+		merge_power_levels(&mut power_levels_content, server_power_levels);
+	}
+
 	Ok(power_levels_content)
 }
 
+/// Merges power levels from the config into the power levels content; the maps
+/// of users, events and notifications are merged, other keys are replaced.
+fn merge_power_levels(
+	power_levels_content: &mut serde_json::Value,
+	power_levels: &BTreeMap<String, serde_json::Value>,
+) {
+	for (key, value) in power_levels {
+		match (power_levels_content.get_mut(key), value) {
+			| (Some(serde_json::Value::Object(existing)), serde_json::Value::Object(value))
+				if matches!(key.as_str(), "users" | "events" | "notifications") =>
+			{
+				existing.extend(value.clone());
+			},
+			| _ => power_levels_content[key] = value.clone(),
+		}
+	}
+}
+
 /// if a room is being created with a room alias, run our checks
 async fn room_alias_check(
 	services: &Services,
@@ -599,7 +811,11 @@ async fn room_alias_check(
 }
 
 /// if a room is being created with a custom room ID, run our checks against it
-fn custom_room_id_check(services: &Services, custom_room_id: &str) -> Result<OwnedRoomId> {
+fn custom_room_id_check(
+	services: &Services,
+	custom_room_id: &str,
+	appservice_info: Option<&RegistrationInfo>,
+) -> Result<OwnedRoomId> {
 	// apply forbidden room alias checks to custom room IDs too
 	if services
 		.globals
@@ -609,6 +825,19 @@ fn custom_room_id_check(services: &Services, custom_room_id: &str) -> Result<Own
 		return Err(Error::BadRequest(ErrorKind::Unknown, "Custom room ID is forbidden."));
 	}
 
+	let patterns = &services.server.config.custom_room_id_patterns;
+	if !patterns.is_empty() && !patterns.is_match(custom_room_id) {
+		return Err!(Request(InvalidParam(
+			"Custom room ID does not match the patterns allowed on this server."
+		)));
+	}
+
+	if let Some(appservice_id) = services.rooms.metadata.room_id_reserved_for(custom_room_id) {
+		if appservice_info.is_none_or(|info| info.registration.id != appservice_id) {
+			return Err!(Request(Exclusive("Custom room ID is reserved for an appservice.")));
+		}
+	}
+
 	if custom_room_id.contains(':') {
 		return Err(Error::BadRequest(
 			ErrorKind::InvalidParam,
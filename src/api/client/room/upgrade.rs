@@ -1,8 +1,8 @@
 use std::cmp::max;
 
 use axum::extract::State;
-use conduwuit::{err, info, pdu::PduBuilder, Error, Result};
-use futures::StreamExt;
+use conduwuit::{err, info, pdu::PduBuilder, utils::ReadyExt, Error, Result};
+use futures::{StreamExt, TryStreamExt};
 use ruma::{
 	api::client::{error::ErrorKind, room::upgrade_room},
 	events::{
@@ -13,25 +13,38 @@ use ruma::{
 		},
 		StateEventType, TimelineEventType,
 	},
-	int, CanonicalJsonObject, RoomId, RoomVersionId,
+	int, CanonicalJsonObject, OwnedUserId, RoomVersionId, UserId,
 };
+use serde::Deserialize;
 use serde_json::{json, value::to_raw_value};
 
 use crate::Ruma;
 
 /// Recommended transferable state events list from the spec
-const TRANSFERABLE_STATE_EVENTS: &[StateEventType; 9] = &[
+const TRANSFERABLE_STATE_EVENTS: &[StateEventType; 10] = &[
 	StateEventType::RoomAvatar,
 	StateEventType::RoomEncryption,
 	StateEventType::RoomGuestAccess,
 	StateEventType::RoomHistoryVisibility,
 	StateEventType::RoomJoinRules,
 	StateEventType::RoomName,
+	StateEventType::RoomPinnedEvents,
 	StateEventType::RoomPowerLevels,
 	StateEventType::RoomServerAcl,
 	StateEventType::RoomTopic,
 ];
 
+/// Overrides of the server's `room_upgrade_*` defaults, accepted next to
+/// `new_version` in the request body.
+#[derive(Default, Deserialize)]
+struct UpgradeOptions {
+	#[serde(rename = "org.conduwuit.transfer_bans")]
+	transfer_bans: Option<bool>,
+
+	#[serde(rename = "org.conduwuit.invite_members")]
+	invite_members: Option<bool>,
+}
+
 /// # `POST /_matrix/client/r0/rooms/{roomId}/upgrade`
 ///
 /// Upgrades the room.
@@ -39,7 +52,10 @@ const TRANSFERABLE_STATE_EVENTS: &[StateEventType; 9] = &[
 /// - Creates a replacement room
 /// - Sends a tombstone event into the current room
 /// - Sender user joins the room
-/// - Transfers some state events
+/// - Transfers some state events, including the power levels of users who
+///   aren't in the room, server ACLs and pinned events
+/// - Bans the users banned in the old room, unless disabled
+/// - Invites the local members of the old room, if enabled
 /// - Moves local aliases
 /// - Modifies old room power levels to prevent users from speaking
 pub(crate) async fn upgrade_room_route(
@@ -59,8 +75,24 @@ pub(crate) async fn upgrade_room_route(
 		));
 	}
 
+	let options: UpgradeOptions = body
+		.json_body
+		.as_ref()
+		.map(|json| serde_json::to_value(json).and_then(serde_json::from_value))
+		.transpose()
+		.map_err(|e| err!(Request(BadJson("Invalid room upgrade options: {e}"))))?
+		.unwrap_or_default();
+
+	let config = &services.server.config;
+	let transfer_bans = options
+		.transfer_bans
+		.unwrap_or(config.room_upgrade_transfer_bans);
+	let invite_members = options
+		.invite_members
+		.unwrap_or(config.room_upgrade_invite_local_members);
+
 	// Create a replacement room
-	let replacement_room = RoomId::new(services.globals.server_name());
+	let replacement_room = services.rooms.metadata.generate_room_id().await?;
 
 	let _short_id = services
 		.rooms
@@ -68,6 +100,9 @@ pub(crate) async fn upgrade_room_route(
 		.get_or_create_shortroomid(&replacement_room)
 		.await;
 
+	// Locked before the tombstone is sent, so users following it only see the
+	// replacement room once it's set up
+	let replacement_lock = services.rooms.state.mutex.lock(&replacement_room).await;
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	// Send a m.room.tombstone event to the old room to indicate that it is not
@@ -89,7 +124,7 @@ pub(crate) async fn upgrade_room_route(
 
 	// Change lock to replacement room
 	drop(state_lock);
-	let state_lock = services.rooms.state.mutex.lock(&replacement_room).await;
+	let state_lock = replacement_lock;
 
 	// Get the old room creation event
 	let mut create_event_content: CanonicalJsonObject = services
@@ -227,6 +262,82 @@ pub(crate) async fn upgrade_room_route(
 			.await?;
 	}
 
+	// Ban the users who were banned in the old room
+	if transfer_bans {
+		let banned: Vec<(OwnedUserId, RoomMemberEventContent)> = services
+			.rooms
+			.state_accessor
+			.room_state_full_pdus(&body.room_id)
+			.try_filter_map(|pdu| async move {
+				if pdu.kind != TimelineEventType::RoomMember {
+					return Ok(None);
+				}
+
+				let Some(user_id) = pdu
+					.state_key
+					.as_deref()
+					.and_then(|state_key| UserId::parse(state_key).ok())
+				else {
+					return Ok(None);
+				};
+
+				let content: RoomMemberEventContent = pdu.get_content()?;
+				Ok((content.membership == MembershipState::Ban).then_some((user_id, content)))
+			})
+			.try_collect()
+			.await?;
+
+		for (user_id, content) in banned {
+			services
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
+						membership: MembershipState::Ban,
+						reason: content.reason,
+						displayname: None,
+						avatar_url: None,
+						is_direct: None,
+						join_authorized_via_users_server: None,
+						third_party_invite: None,
+						blurhash: None,
+					}),
+					sender_user,
+					&replacement_room,
+					&state_lock,
+				)
+				.await?;
+		}
+	}
+
+	// Invite the local members of the old room
+	if invite_members {
+		let members: Vec<OwnedUserId> = services
+			.rooms
+			.state_cache
+			.active_local_users_in_room(&body.room_id)
+			.ready_filter(|&user_id| user_id != *sender_user)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for user_id in members {
+			services
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(
+						user_id.to_string(),
+						&RoomMemberEventContent::new(MembershipState::Invite),
+					),
+					sender_user,
+					&replacement_room,
+					&state_lock,
+				)
+				.await?;
+		}
+	}
+
 	// Moves any local aliases to the new room
 	let mut local_aliases = services
 		.rooms
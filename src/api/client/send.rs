@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use axum::extract::State;
 use conduwuit::{err, Err};
 use ruma::{api::client::message::send_message_event, events::MessageLikeEventType};
-use serde_json::from_str;
+use serde_json::{from_str, json};
 
 use crate::{service::pdu::PduBuilder, utils, Result, Ruma};
 
@@ -31,6 +31,16 @@ pub(crate) async fn send_message_event_route(
 		return Err!(Request(Forbidden("Encryption has been disabled")));
 	}
 
+	services
+		.spam_checker
+		.check_event_for_spam(&json!({
+			"room_id": body.room_id,
+			"sender": sender_user,
+			"type": body.event_type,
+			"content": body.body.body.json(),
+		}))
+		.await?;
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	if body.event_type == MessageLikeEventType::CallInvite
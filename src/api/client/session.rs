@@ -11,22 +11,32 @@ use ruma::{
 			get_login_token,
 			get_login_types::{
 				self,
-				v3::{ApplicationServiceLoginType, PasswordLoginType, TokenLoginType},
+				v3::{
+					ApplicationServiceLoginType, IdentityProvider, LoginType, PasswordLoginType,
+					SsoLoginType, TokenLoginType,
+				},
 			},
 			login::{
 				self,
 				v3::{DiscoveryInfo, HomeserverInfo},
 			},
-			logout, logout_all,
+			logout, logout_all, refresh_token,
 		},
 		uiaa,
 	},
-	OwnedUserId, UserId,
+	serde::JsonObject,
+	thirdparty::Medium,
+	CanonicalJsonValue, DeviceId, OwnedRoomId, OwnedUserId, UserId,
 };
-use service::uiaa::SESSION_ID_LENGTH;
+use service::{ldap, threepid, uiaa::SESSION_ID_LENGTH, Services};
 
-use super::{DEVICE_ID_LENGTH, TOKEN_LENGTH};
-use crate::{utils, utils::hash, Error, Result, Ruma};
+use super::{
+	check_legacy_auth, create_external_user, update_displayname, DEVICE_ID_LENGTH, TOKEN_LENGTH,
+};
+use crate::{utils, utils::hash, ClientIp, Error, Result, Ruma};
+
+/// Login type for JSON Web Tokens, as used by Synapse.
+const JWT_LOGIN_TYPE: &str = "org.matrix.login.jwt";
 
 /// # `GET /_matrix/client/v3/login`
 ///
@@ -38,13 +48,38 @@ pub(crate) async fn get_login_types_route(
 	InsecureClientIp(client): InsecureClientIp,
 	_body: Ruma<get_login_types::v3::Request>,
 ) -> Result<get_login_types::v3::Response> {
-	Ok(get_login_types::v3::Response::new(vec![
-		get_login_types::v3::LoginType::Password(PasswordLoginType::default()),
-		get_login_types::v3::LoginType::ApplicationService(ApplicationServiceLoginType::default()),
-		get_login_types::v3::LoginType::Token(TokenLoginType {
-			get_login_token: services.server.config.login_via_existing_session,
-		}),
-	]))
+	check_legacy_auth(&services)?;
+
+	let config = &services.server.config;
+	let mut login_types = Vec::with_capacity(5);
+
+	if config.oidc.password_login_enabled() {
+		login_types.push(LoginType::Password(PasswordLoginType::default()));
+	}
+
+	login_types.push(LoginType::ApplicationService(ApplicationServiceLoginType::default()));
+
+	if services.oidc.is_enabled() {
+		login_types.push(LoginType::Sso(SsoLoginType {
+			identity_providers: vec![IdentityProvider {
+				id: config.oidc.idp_id.clone(),
+				name: config.oidc.idp_name.clone(),
+				icon: None,
+				brand: None,
+			}],
+		}));
+	}
+
+	login_types.push(LoginType::Token(TokenLoginType {
+		get_login_token: config.login_via_existing_session,
+	}));
+
+	if services.jwt.is_enabled() {
+		login_types
+			.push(LoginType::new(JWT_LOGIN_TYPE, JsonObject::new()).expect("custom login type"));
+	}
+
+	Ok(get_login_types::v3::Response::new(login_types))
 }
 
 /// # `POST /_matrix/client/v3/login`
@@ -64,9 +99,11 @@ pub(crate) async fn get_login_types_route(
 #[tracing::instrument(skip_all, fields(%client), name = "login")]
 pub(crate) async fn login_route(
 	State(services): State<crate::State>,
-	InsecureClientIp(client): InsecureClientIp,
+	ClientIp(client): ClientIp,
 	body: Ruma<login::v3::Request>,
 ) -> Result<login::v3::Response> {
+	check_legacy_auth(&services)?;
+
 	// Validate login method
 	// TODO: Other login methods
 	let user_id = match &body.login_info {
@@ -78,6 +115,12 @@ pub(crate) async fn login_route(
 			..
 		}) => {
 			debug!("Got password login type");
+			if !services.server.config.oidc.password_login_enabled() {
+				return Err!(Request(Unknown(
+					"Password login is disabled; log in with SSO instead."
+				)));
+			}
+
 			let user_id = if let Some(uiaa::UserIdentifier::UserIdOrLocalpart(user_id)) =
 				identifier
 			{
@@ -93,25 +136,26 @@ pub(crate) async fn login_route(
 			}
 			.map_err(|_| Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
 
-			let hash = services
-				.users
-				.password_hash(&user_id)
-				.await
-				.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
+			services
+				.login_attempts
+				.check(Some(&user_id), Some(client))?;
+			if !check_password(services, &user_id, password, &client.to_string()).await? {
+				services
+					.login_attempts
+					.record_failure(Some(&user_id), Some(client));
+				services.ipban.record_auth_failure(client);
 
-			if hash.is_empty() {
-				return Err!(Request(UserDeactivated("The user has been deactivated")));
-			}
-
-			if hash::verify_password(password, &hash).is_err() {
 				return Err!(Request(Forbidden("Wrong username or password.")));
 			}
 
+			services.login_attempts.record_success(&user_id);
+
 			user_id
 		},
 		| login::v3::LoginInfo::Token(login::v3::Token { token }) => {
 			debug!("Got token login type");
-			if !services.server.config.login_via_existing_session {
+			// Login tokens are also issued at the end of SSO logins
+			if !services.server.config.login_via_existing_session && !services.oidc.is_enabled() {
 				return Err!(Request(Unknown("Token login is not enabled.")));
 			}
 			services.users.find_from_login_token(token).await?
@@ -155,6 +199,10 @@ pub(crate) async fn login_route(
 
 			user_id
 		},
+		| _ if login_type(body.json_body.as_ref()) == Some(JWT_LOGIN_TYPE) => {
+			debug!("Got JWT login type");
+			jwt_login(services, body.json_body.as_ref(), &client.to_string()).await?
+		},
 		| _ => {
 			warn!("Unsupported or unknown login type: {:?}", &body.login_info);
 			debug!("JSON body: {:?}", &body.json_body);
@@ -165,6 +213,10 @@ pub(crate) async fn login_route(
 		},
 	};
 
+	if services.users.is_suspended(&user_id).await {
+		return Err!(Request(UserSuspended("Your account has been suspended.")));
+	}
+
 	// Generate new device id if the user didn't specify one
 	let device_id = body
 		.device_id
@@ -203,6 +255,9 @@ pub(crate) async fn login_route(
 			.await?;
 	}
 
+	let (refresh_token, expires_in) =
+		issue_refresh_token(&services, &user_id, &device_id, body.refresh_token);
+
 	// send client well-known if specified so the client knows to reconfigure itself
 	let client_discovery_info: Option<DiscoveryInfo> = services
 		.server
@@ -223,12 +278,221 @@ pub(crate) async fn login_route(
 		access_token: token,
 		device_id,
 		well_known: client_discovery_info,
-		expires_in: None,
+		expires_in,
 		home_server: Some(services.globals.server_name().to_owned()),
-		refresh_token: None,
+		refresh_token,
+	})
+}
+
+/// Logs in with a JSON Web Token, creating the account on first login if
+/// enabled.
+async fn jwt_login(
+	services: crate::State,
+	json_body: Option<&CanonicalJsonValue>,
+	client: &str,
+) -> Result<OwnedUserId> {
+	if !services.jwt.is_enabled() {
+		return Err!(Request(Unknown("JWT login is not enabled.")));
+	}
+
+	let Some(token) = json_body
+		.and_then(CanonicalJsonValue::as_object)
+		.and_then(|body| body.get("token"))
+		.and_then(CanonicalJsonValue::as_str)
+	else {
+		return Err!(Request(MissingParam("Missing JWT login token.")));
+	};
+
+	let claims = services.jwt.validate(token).await?;
+	let user_id = UserId::parse_with_server_name(
+		claims.localpart.to_lowercase(),
+		services.globals.server_name(),
+	)
+	.ok()
+	.filter(|user_id| services.globals.user_is_local(user_id))
+	.ok_or_else(|| err!(Request(InvalidUsername("Username is invalid."))))?;
+
+	if !services.users.exists(&user_id).await {
+		if !services.server.config.jwt.register_users {
+			return Err!(Request(Forbidden("This account is not registered on this server.")));
+		}
+
+		if user_id.is_historical() {
+			return Err!(Request(InvalidUsername("Username is invalid.")));
+		}
+
+		create_external_user(services, &user_id, claims.displayname, client, "JWT").await?;
+	} else if services.users.is_deactivated(&user_id).await? {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	Ok(user_id)
+}
+
+fn login_type(json_body: Option<&CanonicalJsonValue>) -> Option<&str> {
+	json_body
+		.and_then(CanonicalJsonValue::as_object)
+		.and_then(|body| body.get("type"))
+		.and_then(CanonicalJsonValue::as_str)
+}
+
+/// Checks the password of a login, against the LDAP server if enabled and
+/// otherwise the account's local password. Returns false if it was wrong.
+async fn check_password(
+	services: crate::State,
+	user_id: &UserId,
+	password: &str,
+	client: &str,
+) -> Result<bool> {
+	if let Some(accepted) = ldap_login(services, user_id, password, client).await? {
+		return Ok(accepted);
+	}
+
+	let Ok(hash) = services.users.password_hash(user_id).await else {
+		return Ok(false);
+	};
+
+	if hash.is_empty() {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	Ok(hash::verify_password(password, &hash).is_ok())
+}
+
+/// Checks a password login against the LDAP server if enabled, creating the
+/// account on first login and syncing its profile from the directory. Returns
+/// whether LDAP accepted the password, or None if the local password should be
+/// checked instead.
+async fn ldap_login(
+	services: crate::State,
+	user_id: &UserId,
+	password: &str,
+	client: &str,
+) -> Result<Option<bool>> {
+	let config = &services.server.config.ldap;
+	if !services.ldap.is_enabled() || !services.globals.user_is_local(user_id) {
+		return Ok(None);
+	}
+
+	let Some(entry) = services
+		.ldap
+		.authenticate(user_id.localpart(), password)
+		.await?
+	else {
+		return Ok(ldap::password_outcome(false, config.allow_local_password));
+	};
+
+	if !services.users.exists(user_id).await {
+		if !config.register_users {
+			return Err!(Request(Forbidden("This account is not registered on this server.")));
+		}
+
+		create_external_user(services, user_id, entry.displayname.clone(), client, "LDAP")
+			.await?;
+	} else if services.users.is_deactivated(user_id).await? {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	if entry.displayname.is_some()
+		&& services.users.displayname(user_id).await.ok() != entry.displayname
+	{
+		let all_joined_rooms: Vec<OwnedRoomId> = services
+			.rooms
+			.state_cache
+			.rooms_joined(user_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		update_displayname(&services, user_id, entry.displayname, &all_joined_rooms).await;
+	}
+
+	if let Some(email) = entry.email.as_deref() {
+		let added = async {
+			let email = threepid::normalize_email(email)?;
+			if services
+				.threepid
+				.find_user(&Medium::Email, &email)
+				.await
+				.ok()
+				.as_deref() != Some(user_id)
+			{
+				let now = utils::millis_since_unix_epoch();
+				services
+					.threepid
+					.add_validated(user_id, &Medium::Email, &email, now)
+					.await?;
+			}
+
+			Ok::<_, Error>(())
+		};
+
+		if let Err(e) = added.await {
+			warn!(%user_id, "Failed to sync email address from LDAP: {e}");
+		}
+	}
+
+	debug!(%user_id, dn = %entry.dn, "Authenticated with LDAP");
+
+	Ok(Some(true))
+}
+
+/// # `POST /_matrix/client/v3/refresh`
+///
+/// Replaces the access token of the device a refresh token was issued to, and
+/// the refresh token itself. The previous tokens stop working immediately.
+#[tracing::instrument(skip_all, fields(%client), name = "refresh")]
+pub(crate) async fn refresh_token_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<refresh_token::v3::Request>,
+) -> Result<refresh_token::v3::Response> {
+	check_legacy_auth(&services)?;
+
+	let (user_id, device_id) = services
+		.users
+		.find_from_refresh_token(&body.refresh_token)
+		.await?;
+
+	let access_token = utils::random_string(TOKEN_LENGTH);
+	services
+		.users
+		.set_token(&user_id, &device_id, &access_token)
+		.await?;
+
+	let (refresh_token, expires_in_ms) =
+		issue_refresh_token(&services, &user_id, &device_id, true);
+
+	debug!(%user_id, %device_id, "Refreshed access token");
+
+	Ok(refresh_token::v3::Response {
+		access_token,
+		refresh_token,
+		expires_in_ms,
 	})
 }
 
+/// Issues a refresh token for a device alongside the access token it was just
+/// given, if the client asked for one and they are enabled. Returns the
+/// refresh token and the access token's lifetime.
+pub(super) fn issue_refresh_token(
+	services: &Services,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	requested: bool,
+) -> (Option<String>, Option<Duration>) {
+	if !requested || !services.server.config.allow_refresh_tokens {
+		return (None, None);
+	}
+
+	let refresh_token = utils::random_string(TOKEN_LENGTH);
+	let expires_in = services
+		.users
+		.set_refresh_token(user_id, device_id, &refresh_token);
+
+	(Some(refresh_token), Some(expires_in))
+}
+
 /// # `POST /_matrix/client/v1/login/get_token`
 ///
 /// Allows a logged-in user to get a short-lived token which can be used
@@ -241,6 +505,8 @@ pub(crate) async fn login_token_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<get_login_token::v1::Request>,
 ) -> Result<get_login_token::v1::Response> {
+	check_legacy_auth(&services)?;
+
 	if !services.server.config.login_via_existing_session {
 		return Err!(Request(Forbidden("Login via an existing session is not enabled")));
 	}
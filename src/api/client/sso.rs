@@ -0,0 +1,219 @@
+use axum::{
+	extract::{Query, State},
+	response::{AppendHeaders, Html, IntoResponse, Response},
+};
+use axum_client_ip::InsecureClientIp;
+use conduwuit::{
+	err, info,
+	utils::{self, HtmlEscape},
+	warn, Err, Result,
+};
+use http::{
+	header::{CACHE_CONTROL, COOKIE, SET_COOKIE},
+	HeaderMap,
+};
+use reqwest::Url;
+use ruma::{
+	api::client::session::{sso_login, sso_login_with_provider},
+	OwnedUserId, UserId,
+};
+use serde::Deserialize;
+use service::{oidc::Identity, Services};
+
+use super::{check_legacy_auth, client_base_url, create_external_user, TOKEN_LENGTH};
+use crate::Ruma;
+
+/// Path the OpenID Connect provider redirects back to.
+const CALLBACK_PATH: &str = "/_conduwuit/client/oidc/callback";
+
+/// Cookie binding an SSO login to the browser which started it.
+const STATE_COOKIE: &str = "conduwuit_oidc_state";
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CallbackQuery {
+	state: String,
+	code: Option<String>,
+	error: Option<String>,
+	error_description: Option<String>,
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect`
+///
+/// Redirects the user's browser to the configured OpenID Connect provider to
+/// log in.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_login_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<sso_login::v3::Request>,
+) -> Result<sso_login::v3::Response> {
+	check_legacy_auth(&services)?;
+
+	let (location, cookie) = sso_redirect(&services, &body.redirect_url).await?;
+
+	Ok(sso_login::v3::Response { location, cookie: Some(cookie) })
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect/{idpId}`
+///
+/// Redirects the user's browser to the OpenID Connect provider with the given
+/// identity provider ID to log in.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_login_with_provider_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<sso_login_with_provider::v3::Request>,
+) -> Result<sso_login_with_provider::v3::Response> {
+	check_legacy_auth(&services)?;
+
+	if !services.oidc.is_enabled() || body.idp_id != services.server.config.oidc.idp_id {
+		return Err!(Request(NotFound("Unknown identity provider.")));
+	}
+
+	let (location, cookie) = sso_redirect(&services, &body.redirect_url).await?;
+
+	Ok(sso_login_with_provider::v3::Response { location, cookie: Some(cookie) })
+}
+
+/// # `GET /_conduwuit/client/oidc/callback`
+///
+/// Completes an SSO login when the OpenID Connect provider redirects back,
+/// provisioning an account on first login if enabled. The user is asked to
+/// confirm continuing to the client's `redirectUrl`, which gets a `loginToken`
+/// to log in with.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_callback_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	headers: HeaderMap,
+	Query(query): Query<CallbackQuery>,
+) -> Result<Response> {
+	check_legacy_auth(&services)?;
+
+	let cookie_state = headers
+		.get_all(COOKIE)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(';'))
+		.filter_map(|cookie| cookie.trim().split_once('='))
+		.find_map(|(name, value)| (name == STATE_COOKIE).then_some(value));
+
+	if cookie_state != Some(query.state.as_str()) {
+		return Err!(Request(Forbidden("SSO login was not started by this browser.")));
+	}
+
+	if let Some(error) = &query.error {
+		let description = query.error_description.as_deref().unwrap_or_default();
+		warn!(%error, %description, "OpenID Connect provider returned an error");
+		return Err!(Request(Forbidden("SSO login was cancelled or denied by the provider.")));
+	}
+
+	let Some(code) = &query.code else {
+		return Err!(Request(MissingParam("Missing authorization code.")));
+	};
+
+	let identity = services.oidc.complete(&query.state, code).await?;
+	let user_id = match services.oidc.find_user(&identity.subject).await {
+		| Ok(user_id) => user_id,
+		| Err(_) => provision_user(services, &identity, &client.to_string()).await?,
+	};
+
+	if services.users.is_deactivated(&user_id).await? {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	let mut redirect_url = Url::parse(&identity.redirect_url)
+		.map_err(|_| err!(Request(InvalidParam("Invalid redirectUrl."))))?;
+
+	let token = utils::random_string(TOKEN_LENGTH);
+	services.users.create_login_token(&user_id, &token);
+	redirect_url
+		.query_pairs_mut()
+		.append_pair("loginToken", &token);
+
+	info!(%user_id, "Logged in with SSO");
+
+	Ok((
+		AppendHeaders([
+			(SET_COOKIE, state_cookie("", 0)),
+			(CACHE_CONTROL, "no-store".to_owned()),
+		]),
+		Html(confirmation_page(&user_id, &redirect_url)),
+	)
+		.into_response())
+}
+
+/// Page asking the user to confirm handing their login to the client. Any site
+/// can start an SSO login with its own `redirectUrl`, which the provider may
+/// complete without asking, so the login token is never sent on unattended.
+fn confirmation_page(user_id: &UserId, redirect_url: &Url) -> String {
+	let client = redirect_url.host_str().unwrap_or(redirect_url.as_str());
+	let client = HtmlEscape(client);
+	let user_id = HtmlEscape(user_id.as_str());
+	let redirect_url = HtmlEscape(redirect_url.as_str());
+
+	format!(
+		"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Continue to \
+		 {client}</title></head><body><p>You are about to log in to <b>{client}</b> as \
+		 {user_id}.</p><p>Only continue if you started this login from {client}; otherwise \
+		 close this page.</p><p><a href=\"{redirect_url}\">Continue to \
+		 {client}</a></p></body></html>\n"
+	)
+}
+
+/// Starts a login at the provider, returning the location to redirect to and
+/// the state cookie to set.
+async fn sso_redirect(services: &Services, redirect_url: &str) -> Result<(String, String)> {
+	if Url::parse(redirect_url).is_err() {
+		return Err!(Request(InvalidParam("Invalid redirectUrl.")));
+	}
+
+	let callback_url = Url::parse(&format!("{}{CALLBACK_PATH}", client_base_url(services)))
+		.map_err(|e| err!(Config("well_known.client", "Invalid client URL: {e}")))?;
+
+	let (state, location) = services
+		.oidc
+		.authorization_url(redirect_url, callback_url)
+		.await?;
+
+	Ok((location.into(), state_cookie(&state, 600)))
+}
+
+/// Creates an account for a user logging in with SSO for the first time.
+async fn provision_user(
+	services: crate::State,
+	identity: &Identity,
+	client: &str,
+) -> Result<OwnedUserId> {
+	if !services.server.config.oidc.register_users {
+		return Err!(Request(Forbidden("This account is not registered on this server.")));
+	}
+
+	let Some(localpart) = &identity.localpart else {
+		return Err!(Request(InvalidUsername(
+			"The identity provider did not supply a usable username."
+		)));
+	};
+
+	let user_id =
+		UserId::parse_with_server_name(localpart.as_str(), services.globals.server_name())
+			.ok()
+			.filter(|user_id| !user_id.is_historical())
+			.ok_or_else(|| err!(Request(InvalidUsername("Username is invalid."))))?;
+
+	if services.users.exists(&user_id).await {
+		return Err!(Request(UserInUse("An account with this username already exists.")));
+	}
+
+	create_external_user(services, &user_id, identity.displayname.clone(), client, "SSO").await?;
+	services.oidc.link_user(&identity.subject, &user_id)?;
+
+	Ok(user_id)
+}
+
+fn state_cookie(state: &str, max_age: u64) -> String {
+	format!(
+		"{STATE_COOKIE}={state}; Path={CALLBACK_PATH}; Max-Age={max_age}; HttpOnly; Secure; \
+		 SameSite=Lax"
+	)
+}
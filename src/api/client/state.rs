@@ -15,6 +15,7 @@ use ruma::{
 	serde::Raw,
 	OwnedEventId, RoomId, UserId,
 };
+use serde_json::json;
 use service::Services;
 
 use crate::{Ruma, RumaResponse};
@@ -164,6 +165,17 @@ async fn send_state_event_for_key_helper(
 	timestamp: Option<ruma::MilliSecondsSinceUnixEpoch>,
 ) -> Result<OwnedEventId> {
 	allowed_to_send_state_event(services, room_id, event_type, state_key, json).await?;
+	services
+		.spam_checker
+		.check_event_for_spam(&json!({
+			"room_id": room_id,
+			"sender": sender,
+			"type": event_type,
+			"state_key": state_key,
+			"content": json.json(),
+		}))
+		.await?;
+
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 	let event_id = services
 		.rooms
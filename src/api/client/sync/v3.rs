@@ -230,7 +230,7 @@ pub(crate) async fn build_sync_events(
 	let invited_rooms = services
 		.rooms
 		.state_cache
-		.rooms_invited(sender_user)
+		.rooms_invited_unignored(sender_user)
 		.fold_default(|mut invited_rooms: BTreeMap<_, _>, (room_id, invite_state)| async move {
 			let invite_count = services
 				.rooms
@@ -383,6 +383,12 @@ async fn process_presence_updates(
 				.state_cache
 				.user_sees_user(syncing_user, user_id)
 		})
+		.filter(|(user_id, ..)| {
+			services
+				.users
+				.user_is_ignored(user_id, syncing_user)
+				.map(|ignored| !ignored)
+		})
 		.filter_map(|(user_id, _, presence_bytes)| {
 			services
 				.presence
@@ -1037,28 +1043,24 @@ async fn calculate_state_incremental(
 	let mut delta_state_events = Vec::new();
 
 	if since_shortstatehash != current_shortstatehash {
-		let current_state_ids = services
-			.rooms
-			.state_accessor
-			.state_full_ids(current_shortstatehash)
-			.collect();
-
-		let since_state_ids = services
-			.rooms
-			.state_accessor
-			.state_full_ids(since_shortstatehash)
-			.collect();
-
-		let (current_state_ids, since_state_ids): (
-			HashMap<_, OwnedEventId>,
-			HashMap<_, OwnedEventId>,
-		) = join(current_state_ids, since_state_ids).await;
+		let state_ids = if full_state {
+			services
+				.rooms
+				.state_accessor
+				.state_full_ids(current_shortstatehash)
+				.boxed()
+		} else {
+			services
+				.rooms
+				.state_accessor
+				.state_added_ids(since_shortstatehash, current_shortstatehash)
+				.boxed()
+		};
 
-		current_state_ids
-			.iter()
-			.stream()
-			.ready_filter(|(key, id)| full_state || since_state_ids.get(key) != Some(id))
-			.wide_filter_map(|(_, id)| services.rooms.timeline.get_pdu(id).ok())
+		state_ids
+			.wide_filter_map(|(_, id): (_, OwnedEventId)| async move {
+				services.rooms.timeline.get_pdu(&id).await.ok()
+			})
 			.ready_for_each(|pdu| delta_state_events.push(pdu))
 			.await;
 	}
@@ -1,6 +1,6 @@
 use std::{
 	cmp::{self, Ordering},
-	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashSet},
 	time::Duration,
 };
 
@@ -110,7 +110,7 @@ pub(crate) async fn sync_events_v5_route(
 	let all_invited_rooms: Vec<_> = services
 		.rooms
 		.state_cache
-		.rooms_invited(sender_user)
+		.rooms_invited_unignored(sender_user)
 		.map(|r| r.0)
 		.collect()
 		.await;
@@ -744,59 +744,51 @@ async fn collect_e2ee<'a>(
 			let new_encrypted_room = encrypted_room && since_encryption.is_err();
 
 			if encrypted_room {
-				let current_state_ids: HashMap<_, OwnedEventId> = services
+				let changed_state_ids: Vec<OwnedEventId> = services
 					.rooms
 					.state_accessor
-					.state_full_ids(current_shortstatehash)
+					.state_added_ids(since_shortstatehash, current_shortstatehash)
+					.map(|(_, id)| id)
 					.collect()
 					.await;
 
-				let since_state_ids: HashMap<_, _> = services
-					.rooms
-					.state_accessor
-					.state_full_ids(since_shortstatehash)
-					.collect()
-					.await;
+				for id in changed_state_ids {
+					let Ok(pdu) = services.rooms.timeline.get_pdu(&id).await else {
+						error!("Pdu in state not found: {id}");
+						continue;
+					};
+					if pdu.kind == TimelineEventType::RoomMember {
+						if let Some(state_key) = &pdu.state_key {
+							let user_id =
+								OwnedUserId::parse(state_key.clone()).map_err(|_| {
+									Error::bad_database("Invalid UserId in member PDU.")
+								})?;
+
+							if user_id == *sender_user {
+								continue;
+							}
 
-				for (key, id) in current_state_ids {
-					if since_state_ids.get(&key) != Some(&id) {
-						let Ok(pdu) = services.rooms.timeline.get_pdu(&id).await else {
-							error!("Pdu in state not found: {id}");
-							continue;
-						};
-						if pdu.kind == TimelineEventType::RoomMember {
-							if let Some(state_key) = &pdu.state_key {
-								let user_id =
-									OwnedUserId::parse(state_key.clone()).map_err(|_| {
-										Error::bad_database("Invalid UserId in member PDU.")
-									})?;
-
-								if user_id == *sender_user {
-									continue;
-								}
-
-								let content: RoomMemberEventContent = pdu.get_content()?;
-								match content.membership {
-									| MembershipState::Join => {
-										// A new user joined an encrypted room
-										if !share_encrypted_room(
-											&services,
-											sender_user,
-											&user_id,
-											Some(room_id),
-										)
-										.await
-										{
-											device_list_changes.insert(user_id);
-										}
-									},
-									| MembershipState::Leave => {
-										// Write down users that have left encrypted rooms we
-										// are in
-										left_encrypted_users.insert(user_id);
-									},
-									| _ => {},
-								}
+							let content: RoomMemberEventContent = pdu.get_content()?;
+							match content.membership {
+								| MembershipState::Join => {
+									// A new user joined an encrypted room
+									if !share_encrypted_room(
+										&services,
+										sender_user,
+										&user_id,
+										Some(room_id),
+									)
+									.await
+									{
+										device_list_changes.insert(user_id);
+									}
+								},
+								| MembershipState::Leave => {
+									// Write down users that have left encrypted rooms we
+									// are in
+									left_encrypted_users.insert(user_id);
+								},
+								| _ => {},
 							}
 						}
 					}
@@ -62,6 +62,14 @@ pub(crate) async fn send_event_to_device_route(
 				continue;
 			}
 
+			if services
+				.users
+				.user_is_ignored(sender_user, target_user_id)
+				.await
+			{
+				continue;
+			}
+
 			let event_type = &body.event_type.to_string();
 
 			let event = event
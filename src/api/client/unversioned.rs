@@ -1,8 +1,12 @@
 use std::collections::BTreeMap;
 
 use axum::{extract::State, response::IntoResponse, Json};
+use conduwuit::Err;
 use futures::StreamExt;
+use http::StatusCode;
 use ruma::api::client::discovery::get_supported_versions;
+use serde_json::json;
+use service::Services;
 
 use crate::{Result, Ruma};
 
@@ -19,6 +23,7 @@ use crate::{Result, Ruma};
 /// Note: Unstable features are used while developing new features. Clients
 /// should avoid using unstable features in their stable releases
 pub(crate) async fn get_supported_versions_route(
+	State(services): State<crate::State>,
 	_body: Ruma<get_supported_versions::Request>,
 ) -> Result<get_supported_versions::Response> {
 	let resp = get_supported_versions::Response {
@@ -47,12 +52,16 @@ pub(crate) async fn get_supported_versions_route(
 			("org.matrix.msc3026.busy_presence".to_owned(), true), /* busy presence status (https://github.com/matrix-org/matrix-spec-proposals/pull/3026) */
 			("org.matrix.msc3827".to_owned(), true), /* filtering of /publicRooms by room type (https://github.com/matrix-org/matrix-spec-proposals/pull/3827) */
 			("org.matrix.msc3952_intentional_mentions".to_owned(), true), /* intentional mentions (https://github.com/matrix-org/matrix-spec-proposals/pull/3952) */
+			("org.matrix.msc3881".to_owned(), true), /* remotely toggling push notifications (https://github.com/matrix-org/matrix-spec-proposals/pull/3881) */
+			("org.matrix.msc4174".to_owned(), services.pusher.web_push_public_key().is_some()), /* Web Push pushers (https://github.com/matrix-org/matrix-spec-proposals/pull/4174) */
 			("org.matrix.msc3575".to_owned(), true), /* sliding sync (https://github.com/matrix-org/matrix-spec-proposals/pull/3575/files#r1588877046) */
 			("org.matrix.msc3916.stable".to_owned(), true), /* authenticated media (https://github.com/matrix-org/matrix-spec-proposals/pull/3916) */
 			("org.matrix.msc4180".to_owned(), true), /* stable flag for 3916 (https://github.com/matrix-org/matrix-spec-proposals/pull/4180) */
 			("uk.tcpip.msc4133".to_owned(), true), /* Extending User Profile API with Key:Value Pairs (https://github.com/matrix-org/matrix-spec-proposals/pull/4133) */
 			("us.cloke.msc4175".to_owned(), true), /* Profile field for user time zone (https://github.com/matrix-org/matrix-spec-proposals/pull/4175) */
 			("org.matrix.simplified_msc3575".to_owned(), true), /* Simplified Sliding sync (https://github.com/matrix-org/matrix-spec-proposals/pull/4186) */
+			("org.matrix.msc2965".to_owned(), services.delegated_auth.is_enabled()), /* OIDC-aware clients discovering delegated auth (https://github.com/matrix-org/matrix-spec-proposals/pull/2965) */
+			("org.matrix.msc4108".to_owned(), services.rendezvous.is_enabled()), /* QR code login (https://github.com/matrix-org/matrix-spec-proposals/pull/4108) */
 		]),
 	};
 
@@ -84,3 +93,97 @@ pub(crate) async fn conduwuit_local_user_count(
 		"count": user_count
 	})))
 }
+
+/// # `GET /_conduwuit/health/live`
+///
+/// conduwuit-specific liveness probe. Fails when the server is shutting down
+/// or any of the federation sender workers has stopped.
+pub(crate) async fn conduwuit_health_live(
+	State(services): State<crate::State>,
+) -> impl IntoResponse {
+	health_response([
+		("server", server_health(&services)),
+		("senders", senders_health(&services)),
+	])
+}
+
+/// # `GET /_conduwuit/health/ready`
+///
+/// conduwuit-specific readiness probe. In addition to the liveness checks,
+/// reads from the database without writing to it and, when
+/// `health_check_dns_name` is configured, resolves it.
+pub(crate) async fn conduwuit_health_ready(
+	State(services): State<crate::State>,
+) -> impl IntoResponse {
+	let database = services.globals.db.health_check().await;
+	let dns = match services.server.config.health_check_dns_name.as_deref() {
+		| Some(hostname) => Some(services.resolver.health_check(hostname).await),
+		| None => None,
+	};
+
+	let mut components = vec![
+		("server", server_health(&services)),
+		("senders", senders_health(&services)),
+		("database", component_status(database.map(|()| json!({})))),
+	];
+
+	if let Some(dns) = dns {
+		components.push(("dns", component_status(dns.map(|()| json!({})))));
+	}
+
+	health_response(components)
+}
+
+fn server_health(services: &Services) -> (bool, serde_json::Value) {
+	component_status(services.server.check_running().map(|()| json!({})))
+}
+
+fn senders_health(services: &Services) -> (bool, serde_json::Value) {
+	let (running, expected) = services.sending.senders_alive();
+	let result = if running < expected {
+		Err!("{running} of {expected} sender workers are running")
+	} else {
+		Ok(json!({}))
+	};
+
+	let (ok, mut status) = component_status(result);
+	status["running"] = json!(running);
+	status["expected"] = json!(expected);
+
+	(ok, status)
+}
+
+fn component_status(result: conduwuit::Result<serde_json::Value>) -> (bool, serde_json::Value) {
+	match result {
+		| Ok(mut details) => {
+			details["status"] = json!("ok");
+			(true, details)
+		},
+		| Err(e) => (false, json!({ "status": "error", "error": e.to_string() })),
+	}
+}
+
+fn health_response<I>(components: I) -> (StatusCode, Json<serde_json::Value>)
+where
+	I: IntoIterator<Item = (&'static str, (bool, serde_json::Value))>,
+{
+	let mut healthy = true;
+	let components: serde_json::Map<_, _> = components
+		.into_iter()
+		.inspect(|(_, (ok, _))| healthy &= *ok)
+		.map(|(name, (_, status))| (name.to_owned(), status))
+		.collect();
+
+	let status = if healthy {
+		StatusCode::OK
+	} else {
+		StatusCode::SERVICE_UNAVAILABLE
+	};
+
+	let body = json!({
+		"status": if healthy { "ok" } else { "error" },
+		"components": components,
+	});
+
+	(status, Json(body))
+}
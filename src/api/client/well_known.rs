@@ -1,11 +1,15 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use ruma::api::client::{
 	discovery::{
-		discover_homeserver::{self, HomeserverInfo, SlidingSyncProxyInfo},
+		discover_homeserver::{
+			self, AuthenticationServerInfo, HomeserverInfo, SlidingSyncProxyInfo,
+		},
 		discover_support::{self, Contact},
+		get_authentication_issuer,
 	},
 	error::ErrorKind,
 };
+use service::Services;
 
 use crate::{Error, Result, Ruma};
 
@@ -26,9 +30,25 @@ pub(crate) async fn well_known_client(
 		identity_server: None,
 		sliding_sync_proxy: Some(SlidingSyncProxyInfo { url: client_url }),
 		tile_server: None,
+		authentication: authentication_server_info(&services),
 	})
 }
 
+/// # `GET /_matrix/client/unstable/org.matrix.msc2965/auth_issuer`
+///
+/// Returns the OpenID Connect provider authentication is delegated to
+/// (MSC2965), otherwise returns 404.
+pub(crate) async fn auth_issuer_route(
+	State(services): State<crate::State>,
+	_body: Ruma<get_authentication_issuer::msc2965::Request>,
+) -> Result<get_authentication_issuer::msc2965::Response> {
+	let Some(info) = authentication_server_info(&services) else {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Not found."));
+	};
+
+	Ok(get_authentication_issuer::msc2965::Response { issuer: info.issuer })
+}
+
 /// # `GET /.well-known/matrix/support`
 ///
 /// Server support contact and support page of a homeserver's domain.
@@ -96,3 +116,18 @@ pub(crate) async fn syncv3_client_server_json(
 		"version": conduwuit::version(),
 	})))
 }
+
+fn authentication_server_info(services: &Services) -> Option<AuthenticationServerInfo> {
+	let config = &services.server.config.delegated_auth;
+	if !config.is_enabled() {
+		return None;
+	}
+
+	Some(AuthenticationServerInfo {
+		issuer: config.issuer.as_ref()?.to_string(),
+		account: config
+			.account_management_url
+			.as_ref()
+			.map(ToString::to_string),
+	})
+}
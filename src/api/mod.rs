@@ -1,5 +1,6 @@
 #![allow(clippy::toplevel_ref_arg)]
 
+pub mod admin;
 pub mod client;
 pub mod router;
 pub mod server;
@@ -9,7 +10,7 @@ extern crate conduwuit_service as service;
 
 pub(crate) use conduwuit::{debug_info, pdu::PduEvent, utils, Error, Result};
 
-pub(crate) use self::router::{Ruma, RumaResponse, State};
+pub(crate) use self::router::{ClientIp, Ruma, RumaResponse, State};
 
 conduwuit::mod_ctor! {}
 conduwuit::mod_dtor! {}
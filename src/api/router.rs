@@ -1,5 +1,6 @@
 mod args;
 mod auth;
+mod client_ip;
 mod handler;
 mod request;
 mod response;
@@ -16,8 +17,10 @@ use conduwuit::{err, Server};
 use http::{uri, Uri};
 
 use self::handler::RouterExt;
-pub(super) use self::{args::Args as Ruma, response::RumaResponse, state::State};
-use crate::{client, server};
+pub(super) use self::{
+	args::Args as Ruma, client_ip::ClientIp, response::RumaResponse, state::State,
+};
+use crate::{admin, client, server};
 
 pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 	let config = &server.config;
@@ -35,15 +38,46 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_login_types_route)
 		.ruma_route(&client::login_route)
 		.ruma_route(&client::login_token_route)
+		.ruma_route(&client::refresh_token_route)
+		.ruma_route(&client::sso_login_route)
+		.ruma_route(&client::sso_login_with_provider_route)
+		.route("/_conduwuit/client/oidc/callback", get(client::sso_callback_route))
+		.ruma_route(&client::create_rendezvous_session_route)
+		.route(
+			"/_matrix/client/unstable/org.matrix.msc4108/rendezvous/:id",
+			get(client::get_rendezvous_session_route)
+				.put(client::update_rendezvous_session_route)
+				.delete(client::delete_rendezvous_session_route),
+		)
 		.ruma_route(&client::whoami_route)
 		.ruma_route(&client::logout_route)
 		.ruma_route(&client::logout_all_route)
 		.ruma_route(&client::change_password_route)
 		.ruma_route(&client::deactivate_route)
 		.ruma_route(&client::third_party_route)
+		.ruma_route(&client::request_registration_token_via_email_route)
 		.ruma_route(&client::request_3pid_management_token_via_email_route)
 		.ruma_route(&client::request_3pid_management_token_via_msisdn_route)
+		.ruma_route(&client::add_3pid_route)
+		.ruma_route(&client::delete_3pid_route)
+		.ruma_route(&client::bind_3pid_route)
+		.ruma_route(&client::unbind_3pid_route)
+		.route(
+			"/_conduwuit/client/3pid/email/submit_token",
+			get(client::submit_3pid_token_link_route).post(client::submit_3pid_token_route),
+		)
+		.ruma_route(&client::send_renewal_email_route)
+		.route(
+			"/_conduwuit/client/account_validity/renew",
+			get(client::renew_account_link_route).post(client::renew_account_route),
+		)
 		.ruma_route(&client::check_registration_token_validity)
+		.ruma_route(&client::request_export_route)
+		.ruma_route(&client::get_export_route)
+		.route(
+			"/_conduwuit/client/v1/export/download/:id",
+			get(client::download_export_route),
+		)
 		.ruma_route(&client::get_capabilities_route)
 		.ruma_route(&client::get_pushrules_all_route)
 		.ruma_route(&client::get_pushrules_global_route)
@@ -185,8 +219,25 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		)
 		.ruma_route(&client::well_known_support)
 		.ruma_route(&client::well_known_client)
+		.ruma_route(&client::auth_issuer_route)
 		.route("/_conduwuit/server_version", get(client::conduwuit_server_version))
+		.route("/_conduwuit/health/live", get(client::conduwuit_health_live))
+		.route("/_conduwuit/health/ready", get(client::conduwuit_health_ready))
 		.ruma_route(&client::room_initial_sync_route)
+		.ruma_route(&admin::get_admin_server_version_route)
+		.ruma_route(&admin::list_users_route)
+		.ruma_route(&admin::get_user_route)
+		.ruma_route(&admin::deactivate_user_route)
+		.ruma_route(&admin::whois_route)
+		.ruma_route(&admin::suspend_user_route)
+		.ruma_route(&admin::login_as_user_route)
+		.ruma_route(&admin::list_rooms_route)
+		.ruma_route(&admin::get_room_route)
+		.ruma_route(&admin::delete_room_route)
+		.ruma_route(&admin::join_room_route)
+		.ruma_route(&admin::list_user_media_route)
+		.ruma_route(&admin::list_room_media_route)
+		.ruma_route(&admin::delete_media_route)
 		.route("/client/server.json", get(client::syncv3_client_server_json));
 
 	if config.allow_federation {
@@ -215,6 +266,8 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			.ruma_route(&server::create_join_event_v1_route)
 			.ruma_route(&server::create_join_event_v2_route)
 			.ruma_route(&server::create_invite_route)
+			.ruma_route(&server::exchange_third_party_invite_route)
+			.ruma_route(&server::third_party_invite_bind_route)
 			.ruma_route(&server::get_devices_route)
 			.ruma_route(&server::get_room_information_route)
 			.ruma_route(&server::get_profile_information_route)
@@ -1,8 +1,8 @@
-use std::{mem, ops::Deref};
+use std::{mem, ops::Deref, time::Instant};
 
 use axum::{async_trait, body::Body, extract::FromRequest};
 use bytes::{BufMut, Bytes, BytesMut};
-use conduwuit::{debug, debug_warn, err, trace, utils::string::EMPTY, Error, Result};
+use conduwuit::{debug, debug_warn, err, metrics, trace, utils::string::EMPTY, Error, Result};
 use ruma::{
 	api::IncomingRequest, CanonicalJsonObject, CanonicalJsonValue, DeviceId, OwnedDeviceId,
 	OwnedServerName, OwnedUserId, ServerName, UserId,
@@ -107,7 +107,22 @@ where
 			);
 			json_body = Some(CanonicalJsonValue::Object(CanonicalJsonObject::new()));
 		}
+		let timer = Instant::now();
 		let auth = auth::auth(services, &mut request, json_body.as_ref(), &T::METADATA).await?;
+		metrics::request::add_auth(timer.elapsed());
+		metrics::request::set_sender(|| {
+			auth.sender_user
+				.as_deref()
+				.map(ToString::to_string)
+				.or_else(|| auth.origin.as_deref().map(ToString::to_string))
+				.or_else(|| {
+					auth.appservice_info
+						.as_ref()
+						.map(|info| info.registration.id.clone())
+				})
+				.unwrap_or_default()
+		});
+
 		Ok(Self {
 			body: make_body::<T>(services, &mut request, json_body.as_mut(), &auth)?,
 			origin: auth.origin,
@@ -13,6 +13,7 @@ use ruma::{
 			profile::{
 				get_avatar_url, get_display_name, get_profile, get_profile_key, get_timezone_key,
 			},
+			session::{logout, logout_all, refresh_token},
 			voip::get_turn_server_info,
 		},
 		federation::openid::get_openid_userinfo,
@@ -27,7 +28,7 @@ use service::{
 };
 
 use super::request::Request;
-use crate::service::appservice::RegistrationInfo;
+use crate::{client::send_renewal_email, service::appservice::RegistrationInfo};
 
 enum Token {
 	Appservice(Box<RegistrationInfo>),
@@ -59,7 +60,40 @@ pub(super) async fn auth(
 		if let Some(reg_info) = services.appservice.find_from_token(token).await {
 			Token::Appservice(Box::new(reg_info))
 		} else if let Ok((user_id, device_id)) = services.users.find_from_token(token).await {
+			// Clients may still send their expired token when refreshing it
+			if metadata != &refresh_token::v3::Request::METADATA
+				&& services
+					.users
+					.access_token_expired(&user_id, &device_id)
+					.await
+			{
+				return Err(Error::BadRequest(
+					ErrorKind::UnknownToken { soft_logout: true },
+					"Access token has expired.",
+				));
+			}
+
+			// Expired accounts may only log out or ask for a renewal link
+			if !matches!(
+				metadata,
+				&logout::v3::Request::METADATA
+					| &logout_all::v3::Request::METADATA
+					| &send_renewal_email::Request::METADATA
+			) && services.account_validity.is_expired(&user_id).await
+			{
+				return Err!(Request(Forbidden(
+					"Your account has expired. Use the link emailed to you to renew it, or \
+					 contact the server administrator."
+				)));
+			}
+
 			Token::User((user_id, device_id))
+		} else if services.delegated_auth.is_enabled() {
+			// Tokens issued by the provider are validated with it (MSC3861)
+			match services.delegated_auth.introspect(token).await? {
+				| Some((user_id, device_id)) => Token::User((user_id, device_id)),
+				| None => Token::Invalid,
+			}
 		} else {
 			Token::Invalid
 		}
@@ -221,10 +255,26 @@ async fn auth_appservice(
 		return Err!(Request(Exclusive("User is not in namespace.")));
 	}
 
+	if services.users.is_erased(&user_id).await {
+		return Err!(Request(Forbidden("This user's account has been erased.")));
+	}
+
+	let device_id = request.query.device_id.clone();
+	if let Some(device_id) = &device_id {
+		if services
+			.users
+			.get_device_metadata(&user_id, device_id)
+			.await
+			.is_err()
+		{
+			return Err!(Request(Forbidden("Appservice user does not have this device.")));
+		}
+	}
+
 	Ok(Auth {
 		origin: None,
 		sender_user: Some(user_id),
-		sender_device: None,
+		sender_device: device_id,
 		appservice_info: Some(*info),
 	})
 }
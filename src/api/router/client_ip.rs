@@ -0,0 +1,33 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+	async_trait,
+	extract::{ConnectInfo, FromRequestParts},
+};
+use conduwuit::{err, Error, Result};
+use http::request::Parts;
+
+use super::State;
+
+/// Extractor for the address of the client making a request: the peer, or the
+/// address it forwarded the request for if it is one of `trusted_proxies`.
+/// Unlike `InsecureClientIp` clients can't forge it, so anything counted or
+/// enforced per address must use this.
+pub(crate) struct ClientIp(pub(crate) IpAddr);
+
+#[async_trait]
+impl FromRequestParts<State> for ClientIp {
+	type Rejection = Error;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &State,
+	) -> Result<Self, Self::Rejection> {
+		let ConnectInfo(peer) = parts
+			.extensions
+			.get::<ConnectInfo<SocketAddr>>()
+			.ok_or_else(|| err!("Missing connection info of the request."))?;
+
+		Ok(Self(services.ratelimit.client_ip(peer.ip(), &parts.headers)))
+	}
+}
@@ -4,6 +4,7 @@ use axum::{extract::Path, RequestExt, RequestPartsExt};
 use bytes::Bytes;
 use conduwuit::{err, Result};
 use http::request::Parts;
+use ruma::OwnedDeviceId;
 use serde::Deserialize;
 use service::Services;
 
@@ -11,6 +12,9 @@ use service::Services;
 pub(super) struct QueryParams {
 	pub(super) access_token: Option<String>,
 	pub(super) user_id: Option<String>,
+	/// The device of the user an appservice acts as (MSC3202)
+	#[serde(alias = "org.matrix.msc3202.device_id")]
+	pub(super) device_id: Option<OwnedDeviceId>,
 }
 
 pub(super) struct Request {
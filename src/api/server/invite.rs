@@ -74,6 +74,10 @@ pub(crate) async fn create_invite_route(
 		return Err!(Request(InvalidParam("User does not belong to this homeserver.")));
 	}
 
+	if services.users.is_erased(&invited_user).await {
+		return Err!(Request(Forbidden("This user's account has been erased.")));
+	}
+
 	// Make sure we're not ACL'ed from their room.
 	services
 		.rooms
@@ -108,6 +112,11 @@ pub(crate) async fn create_invite_route(
 		return Err!(Request(Forbidden("This server does not allow room invites.")));
 	}
 
+	services
+		.spam_checker
+		.user_may_invite(sender, &invited_user, &body.room_id)
+		.await?;
+
 	let mut invite_state = body.invite_room_state.clone();
 
 	let mut event: JsonObject = serde_json::from_str(body.event.get())
@@ -120,15 +129,20 @@ pub(crate) async fn create_invite_route(
 
 	invite_state.push(pdu.to_stripped_state_event());
 
+	// Invites from users the invitee has ignored are silently dropped; the
+	// remote server is told it worked, as with local invites.
+	let sender_ignored = services.users.user_is_ignored(sender, &invited_user).await;
+
 	// If we are active in the room, the remote server will notify us about the
 	// join/invite through /send. If we are not in the room, we need to manually
 	// record the invited state for client /sync through update_membership(), and
 	// send the invite PDU to the relevant appservices.
-	if !services
-		.rooms
-		.state_cache
-		.server_in_room(services.globals.server_name(), &body.room_id)
-		.await
+	if !sender_ignored
+		&& !services
+			.rooms
+			.state_cache
+			.server_in_room(services.globals.server_name(), &body.room_id)
+			.await
 	{
 		services
 			.rooms
@@ -70,6 +70,10 @@ pub(crate) async fn create_join_event_template_route(
 		}
 	}
 
+	services
+		.ratelimit
+		.check_raid_join(&body.room_id, &body.user_id)?;
+
 	let room_version_id = services.rooms.state.get_room_version(&body.room_id).await?;
 	if !body.ver.contains(&room_version_id) {
 		return Err(Error::BadRequest(
@@ -30,6 +30,7 @@ pub(crate) async fn get_public_rooms_filtered_route(
 	let response = crate::client::get_public_rooms_filtered_helper(
 		&services,
 		None,
+		Some(body.origin()),
 		body.limit,
 		body.since.as_deref(),
 		&body.filter,
@@ -67,6 +68,7 @@ pub(crate) async fn get_public_rooms_route(
 	let response = crate::client::get_public_rooms_filtered_helper(
 		&services,
 		None,
+		Some(body.origin()),
 		body.limit,
 		body.since.as_deref(),
 		&Filter::default(),
@@ -82,6 +82,8 @@ pub(crate) async fn send_transaction_message_route(
 		)));
 	}
 
+	services.sending.note_inbound(body.origin());
+
 	let txn_start_time = Instant::now();
 	trace!(
 		pdus = body.pdus.len(),
@@ -175,6 +177,12 @@ async fn handle(
 	Ok(results)
 }
 
+#[tracing::instrument(
+	name = "room",
+	level = "debug",
+	skip_all,
+	fields(room_id = %room_id, %origin),
+)]
 async fn handle_room(
 	services: &Services,
 	_client: &IpAddr,
@@ -190,6 +198,9 @@ async fn handle_room(
 		.lock(&room_id)
 		.await;
 
+	// Coalesce the WAL flushes of every PDU for this room into one.
+	let _cork = services.db.cork_and_flush();
+
 	let room_id = &room_id;
 	pdus.try_stream()
 		.and_then(|(_, event_id, value)| async move {
@@ -515,6 +526,14 @@ async fn handle_edu_direct_to_device_user<Event: Send + Sync>(
 	ev_type: &str,
 	map: BTreeMap<DeviceIdOrAllDevices, Raw<Event>>,
 ) {
+	if services
+		.users
+		.user_is_ignored(sender, &target_user_id)
+		.await
+	{
+		return;
+	}
+
 	for (target_device_id_maybe, event) in map {
 		let Ok(event) = event
 			.deserialize_as()
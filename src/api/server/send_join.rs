@@ -124,6 +124,9 @@ async fn create_join_event(
 		return Err!(Request(Forbidden("Not allowed to join on behalf of another server.")));
 	}
 
+	services.ratelimit.check_raid_join(room_id, &sender)?;
+	services.ratelimit.check_remote_join(room_id)?;
+
 	let state_key: OwnedUserId = serde_json::from_value(
 		value
 			.get("state_key")
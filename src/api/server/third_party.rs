@@ -0,0 +1,149 @@
+use axum::extract::State;
+use conduwuit::{err, Err, Result};
+use futures::FutureExt;
+use ruma::{
+	api::federation::third_party::{bind_callback, exchange_invite},
+	events::{
+		room::{
+			member::{MembershipState, RoomMemberEventContent, ThirdPartyInvite},
+			third_party_invite::RoomThirdPartyInviteEventContent,
+		},
+		StateEventType,
+	},
+	RoomId, UserId,
+};
+use service::{threepid::verify_third_party_signed, Services};
+
+use crate::{client::invite_helper, Ruma};
+
+/// # `PUT /_matrix/federation/v1/exchange_third_party_invite/{roomId}`
+///
+/// Turns a third-party invite sent by one of our users into an invite of the
+/// user who bound the third-party identifier.
+pub(crate) async fn exchange_third_party_invite_route(
+	State(services): State<crate::State>,
+	body: Ruma<exchange_invite::v1::Request>,
+) -> Result<exchange_invite::v1::Response> {
+	if body.kind != StateEventType::RoomMember {
+		return Err!(Request(InvalidParam("Only m.room.member events can be exchanged.")));
+	}
+
+	if !services.rooms.metadata.exists(&body.room_id).await {
+		return Err!(Request(NotFound("Room is unknown to this server.")));
+	}
+
+	services
+		.rooms
+		.event_handler
+		.acl_check(body.origin(), &body.room_id)
+		.await?;
+
+	let third_party_invite = body
+		.content
+		.third_party_invite
+		.clone()
+		.ok_or_else(|| err!(Request(BadJson("Missing third_party_invite in content."))))?;
+
+	exchange_third_party_invite(
+		&services,
+		&body.sender,
+		&body.room_id,
+		&body.state_key,
+		third_party_invite,
+	)
+	.boxed()
+	.await?;
+
+	Ok(exchange_invite::v1::Response {})
+}
+
+/// # `PUT /_matrix/federation/v1/3pid/onbind`
+///
+/// Called by identity servers when a third-party identifier with pending
+/// invites is bound to a user. Invites sent by our users are exchanged here,
+/// others are passed to the server of their sender.
+pub(crate) async fn third_party_invite_bind_route(
+	State(services): State<crate::State>,
+	body: Ruma<bind_callback::v1::Request>,
+) -> Result<bind_callback::v1::Response> {
+	for invite in &body.invites {
+		if invite.mxid != body.mxid {
+			return Err!(Request(InvalidParam("Invite is for a different user.")));
+		}
+
+		let third_party_invite = ThirdPartyInvite {
+			display_name: invite.address.clone(),
+			signed: invite.signed.clone(),
+		};
+
+		if services.globals.user_is_local(&invite.sender) {
+			exchange_third_party_invite(
+				&services,
+				&invite.sender,
+				&invite.room_id,
+				&invite.mxid,
+				third_party_invite,
+			)
+			.boxed()
+			.await?;
+
+			continue;
+		}
+
+		services
+			.sending
+			.send_federation_request(invite.sender.server_name(), exchange_invite::v1::Request {
+				room_id: invite.room_id.clone(),
+				kind: StateEventType::RoomMember,
+				sender: invite.sender.clone(),
+				state_key: invite.mxid.clone(),
+				content: RoomMemberEventContent {
+					third_party_invite: Some(third_party_invite),
+					..RoomMemberEventContent::new(MembershipState::Invite)
+				},
+			})
+			.await?;
+	}
+
+	Ok(bind_callback::v1::Response {})
+}
+
+/// Invites `user_id` on behalf of our user who sent the room's
+/// `m.room.third_party_invite` event, once the identity server's signature of
+/// the invite is verified.
+async fn exchange_third_party_invite(
+	services: &Services,
+	sender: &UserId,
+	room_id: &RoomId,
+	user_id: &UserId,
+	mut third_party_invite: ThirdPartyInvite,
+) -> Result {
+	if !services.globals.user_is_local(sender) {
+		return Err!(Request(Forbidden("Sender is not a user of this server.")));
+	}
+
+	let signed = &third_party_invite.signed;
+	if signed.mxid != user_id {
+		return Err!(Request(Forbidden("Third-party invite was signed for a different user.")));
+	}
+
+	let invite_event = services
+		.rooms
+		.state_accessor
+		.room_state_get(room_id, &StateEventType::RoomThirdPartyInvite, &signed.token)
+		.await
+		.map_err(|_| {
+			err!(Request(NotFound("No third-party invite in the room for this token.")))
+		})?;
+
+	if invite_event.sender != sender {
+		return Err!(Request(Forbidden("Third-party invite was sent by a different user.")));
+	}
+
+	let invite_content: RoomThirdPartyInviteEventContent = invite_event.get_content()?;
+	verify_third_party_signed(signed, &invite_content)?;
+
+	third_party_invite.display_name = invite_content.display_name;
+
+	invite_helper(services, sender, user_id, room_id, None, false, Some(third_party_invite)).await
+}
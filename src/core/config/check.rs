@@ -1,10 +1,30 @@
-use std::env::consts::OS;
+use std::{env::consts::OS, net::IpAddr};
 
 use either::Either;
 use figment::Figment;
 
-use super::DEPRECATED_KEYS;
-use crate::{debug, debug_info, debug_warn, error, warn, Config, Err, Result, Server};
+use super::{DEPRECATED_KEYS, RATE_LIMIT_CLASSES};
+use crate::{
+	debug, debug_info, debug_warn, error,
+	utils::{sys, time::cron::Schedule},
+	warn, Config, Err, Error, Result, Server,
+};
+
+/// A potentially dangerous combination of config options found by lint().
+struct Lint {
+	severity: Severity,
+	item: &'static str,
+	message: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Severity {
+	/// Logged at startup; the server continues.
+	Warning,
+
+	/// Logged at startup; the server refuses to start.
+	Error,
+}
 
 /// Performs check() with additional checks specific to reloading old config
 /// with new config.
@@ -38,6 +58,223 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	if cfg!(feature = "perf_measurements")
+		&& config.allow_jaeger
+		&& config.otlp_endpoint.is_some()
+	{
+		return Err!(Config(
+			"otlp_endpoint",
+			"The Jaeger and OTLP exporters cannot be enabled at the same time"
+		));
+	}
+
+	for (class, rule) in &config.rate_limits {
+		if !RATE_LIMIT_CLASSES.contains(&class.as_str()) {
+			return Err!(Config(
+				"rate_limits",
+				"Unknown rate limit class {class:?}; expected one of {RATE_LIMIT_CLASSES:?}"
+			));
+		}
+
+		if !rule.per_second.is_finite() || rule.per_second <= 0.0 || rule.burst == 0 {
+			return Err!(Config(
+				"rate_limits",
+				"Rate limit for {class:?} needs a positive per_second and burst"
+			));
+		}
+	}
+
+	if config.appservice_transaction_limit == 0 {
+		return Err!(Config(
+			"appservice_transaction_limit",
+			"appservice_transaction_limit must be at least 1"
+		));
+	}
+
+	for (id, rule) in &config.appservice_rate_limits {
+		if !rule.per_second.is_finite() || rule.per_second <= 0.0 || rule.burst == 0 {
+			return Err!(Config(
+				"appservice_rate_limits",
+				"Rate limit for appservice {id:?} needs a positive per_second and burst"
+			));
+		}
+	}
+
+	if !config.remote_join_rate_per_room.is_finite() || config.remote_join_rate_per_room < 0.0 {
+		return Err!(Config(
+			"remote_join_rate_per_room",
+			"remote_join_rate_per_room must be 0 or a positive number"
+		));
+	}
+
+	if !matches!(config.content_filter_action.as_str(), "reject" | "redact") {
+		return Err!(Config(
+			"content_filter_action",
+			"content_filter_action must be \"reject\" or \"redact\""
+		));
+	}
+
+	let push_fields = [
+		"type",
+		"content",
+		"sender",
+		"sender_display_name",
+		"room_name",
+		"room_alias",
+		"user_is_target",
+	];
+	if let Some(field) = config
+		.push_omit_fields
+		.iter()
+		.find(|field| !push_fields.contains(&field.as_str()))
+	{
+		return Err!(Config(
+			"push_omit_fields",
+			"Unknown push notification field {field:?}. Available fields are {push_fields:?}."
+		));
+	}
+
+	if config.smtp.connection_uri.is_some() && config.smtp.sender.is_none() {
+		return Err!(Config(
+			"smtp.sender",
+			"An SMTP sender address is required when an SMTP connection_uri is set."
+		));
+	}
+
+	if !matches!(config.captcha.provider.as_str(), "" | "recaptcha" | "hcaptcha" | "turnstile") {
+		return Err!(Config(
+			"captcha.provider",
+			"Unknown CAPTCHA provider {:?}. Available options are \"recaptcha\", \"hcaptcha\" \
+			 and \"turnstile\".",
+			config.captcha.provider
+		));
+	}
+
+	if config.captcha.site_key.is_some() != config.captcha.secret_key.is_some() {
+		return Err!(Config(
+			"captcha.secret_key",
+			"Both a CAPTCHA site_key and secret_key must be set to require a CAPTCHA for \
+			 registration."
+		));
+	}
+
+	let oidc = &config.oidc;
+	if (oidc.issuer.is_some() || oidc.client_id.is_some() || oidc.client_secret.is_some())
+		&& !oidc.is_enabled()
+	{
+		return Err!(Config(
+			"oidc.issuer",
+			"An OpenID Connect issuer, client_id and client_secret must all be set to enable \
+			 SSO login."
+		));
+	}
+
+	let delegated_auth = &config.delegated_auth;
+	if (delegated_auth.issuer.is_some()
+		|| delegated_auth.client_id.is_some()
+		|| delegated_auth.client_secret.is_some())
+		&& !delegated_auth.is_enabled()
+	{
+		return Err!(Config(
+			"delegated_auth.issuer",
+			"An issuer, client_id and client_secret must all be set to delegate authentication."
+		));
+	}
+
+	if delegated_auth.is_enabled() && oidc.is_enabled() {
+		return Err!(Config(
+			"oidc.issuer",
+			"SSO login cannot be used while authentication is delegated; configure the provider \
+			 in your authentication service instead."
+		));
+	}
+
+	if config.allow_refresh_tokens && config.refreshable_access_token_lifetime == 0 {
+		return Err!(Config(
+			"refreshable_access_token_lifetime",
+			"Access tokens issued with a refresh token must have a lifetime of at least one \
+			 second."
+		));
+	}
+
+	if config.allow_qr_code_login && !delegated_auth.is_enabled() {
+		return Err!(Config(
+			"allow_qr_code_login",
+			"QR code login requires authentication to be delegated with [global.delegated_auth]."
+		));
+	}
+
+	let ldap = &config.ldap;
+	if ldap.uri.is_some() && ldap.bind_dn_template.is_none() && ldap.base_dn.is_none() {
+		return Err!(Config(
+			"ldap.base_dn",
+			"Either an LDAP bind_dn_template or a base_dn to search for users in must be set."
+		));
+	}
+
+	if ldap
+		.uri
+		.as_ref()
+		.is_some_and(|uri| !matches!(uri.scheme(), "ldap" | "ldaps"))
+	{
+		return Err!(Config("ldap.uri", "The LDAP URI scheme must be \"ldap\" or \"ldaps\"."));
+	}
+
+	let jwt = &config.jwt;
+	let jwt_keys = [jwt.secret.is_some(), jwt.key_file.is_some(), jwt.jwks_url.is_some()];
+	if jwt_keys.iter().filter(|&&set| set).count() > 1 {
+		return Err!(Config(
+			"jwt.secret",
+			"Only one of a JWT secret, key_file or jwks_url may be set."
+		));
+	}
+
+	if jwt.is_enabled() {
+		match jwt.algorithm.as_str() {
+			| "HS256" if jwt.secret.is_none() => {
+				return Err!(Config("jwt.secret", "A secret is required for HS256 JWT login."));
+			},
+			| "RS256" | "ES256" if jwt.secret.is_some() => {
+				return Err!(Config(
+					"jwt.key_file",
+					"A key_file or jwks_url is required for {} JWT login.",
+					jwt.algorithm
+				));
+			},
+			| "HS256" | "RS256" | "ES256" => {},
+			| _ => {
+				return Err!(Config(
+					"jwt.algorithm",
+					"The JWT algorithm must be \"HS256\", \"RS256\" or \"ES256\"."
+				));
+			},
+		}
+	}
+
+	if oidc.is_enabled() && !oidc.scopes.iter().any(|scope| scope == "openid") {
+		return Err!(Config("oidc.scopes", "The \"openid\" scope is required for SSO login."));
+	}
+
+	if oidc.is_enabled()
+		&& !oidc
+			.idp_id
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || "._~-".contains(c))
+	{
+		return Err!(Config(
+			"oidc.idp_id",
+			"The identity provider ID may only contain characters from [A-Za-z0-9._~-]."
+		));
+	}
+
+	if config.require_email_for_registration && config.smtp.connection_uri.is_none() {
+		return Err!(Config(
+			"require_email_for_registration",
+			"Requiring email verification for registration requires [global.smtp] to be \
+			 configured."
+		));
+	}
+
 	if cfg!(all(feature = "hardened_malloc", feature = "jemalloc")) {
 		debug_warn!(
 			"hardened_malloc and jemalloc compile-time features are both enabled, this causes \
@@ -126,6 +363,74 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	match config.database_backend.as_str() {
+		| "rocksdb" => (),
+		| "memory" => {
+			if config.rocksdb_read_only || config.rocksdb_secondary {
+				return Err!(Config(
+					"database_backend",
+					"An in-memory database cannot be opened read-only or as a secondary."
+				));
+			}
+
+			if config.database_backup_path.is_some() {
+				return Err!(Config(
+					"database_backup_path",
+					"An in-memory database cannot be backed up."
+				));
+			}
+		},
+		| backend => {
+			warn!(
+				"Unknown database_backend {backend:?}; expected \"rocksdb\" or \"memory\". \
+				 Falling back to \"rocksdb\"."
+			);
+		},
+	}
+
+	if config.rocksdb_read_only && config.rocksdb_secondary {
+		return Err!(Config(
+			"rocksdb_secondary",
+			"rocksdb_read_only and rocksdb_secondary cannot both be enabled."
+		));
+	}
+
+	if let Some(schedule) = &config.database_backup_schedule {
+		if let Err(e) = schedule.parse::<Schedule>() {
+			return Err!(Config("database_backup_schedule", "{e}"));
+		}
+
+		if config.database_backup_path.is_none() {
+			return Err!(Config(
+				"database_backup_schedule",
+				"A backup schedule requires database_backup_path to be set."
+			));
+		}
+	}
+
+	if config
+		.export_path
+		.as_ref()
+		.is_some_and(|path| path.starts_with(&config.database_path))
+	{
+		return Err!(Config(
+			"export_path",
+			"Exports cannot be written inside database_path; choose a separate directory."
+		));
+	}
+
+	if let Some(schedule) = &config.state_recompression_schedule {
+		if let Err(e) = schedule.parse::<Schedule>() {
+			return Err!(Config("state_recompression_schedule", "{e}"));
+		}
+	}
+
+	for task in &config.scheduled_tasks {
+		if let Err(e) = task.schedule.parse::<Schedule>() {
+			return Err!(Config("scheduled_tasks", "{e}"));
+		}
+	}
+
 	// check if the user specified a registration token as `""`
 	if config.registration_token == Some(String::new()) {
 		return Err!(Config(
@@ -168,20 +473,49 @@ pub fn check(config: &Config) -> Result {
 		}
 	}
 
-	if config.allow_registration
-		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
-		&& config.registration_token.is_none()
-		&& config.registration_token_file.is_none()
+	if let Some((appservice_id, _)) = config
+		.appservice_room_id_prefixes
+		.iter()
+		.find(|(_, prefixes)| prefixes.iter().any(String::is_empty))
 	{
 		return Err!(Config(
-			"registration_token",
-			"!! You have `allow_registration` enabled without a token configured in your config \
-			 which means you are allowing ANYONE to register on your conduwuit instance without \
-			 any 2nd-step (e.g. registration token). If this is not the intended behaviour, \
-			 please set a registration token. For security and safety reasons, conduwuit will \
-			 shut down. If you are extra sure this is the desired behaviour you want, please \
-			 set the following config option to true:
-`yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse`"
+			"appservice_room_id_prefixes",
+			"Appservice {appservice_id:?} has an empty prefix, which would reserve every room \
+			 ID."
+		));
+	}
+
+	for cidr in &config.trusted_proxies {
+		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
+			return Err!(Config(
+				"trusted_proxies",
+				"Parsing IP CIDR range {cidr:?} failed: {e}."
+			));
+		}
+	}
+
+	for cidr in &config.registration_rate_limit_allowlist {
+		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
+			return Err!(Config(
+				"registration_rate_limit_allowlist",
+				"Parsing IP CIDR range {cidr:?} failed: {e}."
+			));
+		}
+	}
+
+	if config.ip_autoban_failures > 0
+		&& (config.ip_autoban_window == 0 || config.ip_autoban_duration == 0)
+	{
+		return Err!(Config(
+			"ip_autoban_window",
+			"ip_autoban_window and ip_autoban_duration must be at least one second"
+		));
+	}
+
+	if config.registration_rate_limit_window == 0 || config.register_available_window == 0 {
+		return Err!(Config(
+			"registration_rate_limit_window",
+			"Registration rate limit windows must be at least one second"
 		));
 	}
 
@@ -189,6 +523,8 @@ pub fn check(config: &Config) -> Result {
 		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.require_email_for_registration
+		&& !config.captcha.is_enabled()
 	{
 		warn!(
 			"Open registration is enabled via setting \
@@ -259,7 +595,126 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
-	Ok(())
+	lint(config)
+}
+
+/// Checks for combinations of config options which are individually valid but
+/// dangerous or broken together. Everything found is logged as a group so all
+/// problems can be fixed at once; if any have error severity, the first is
+/// returned.
+fn lint(config: &Config) -> Result {
+	let lints = lints(config);
+
+	let warnings: Vec<_> = lints
+		.iter()
+		.filter(|lint| lint.severity == Severity::Warning)
+		.collect();
+
+	if !warnings.is_empty() {
+		warn!("Found {} questionable config combination(s):", warnings.len());
+		for lint in warnings {
+			warn!(config = %lint.item, "{}", lint.message);
+		}
+	}
+
+	let errors: Vec<_> = lints
+		.into_iter()
+		.filter(|lint| lint.severity == Severity::Error)
+		.collect();
+
+	if !errors.is_empty() {
+		error!("Found {} dangerous config combination(s):", errors.len());
+		for lint in &errors {
+			error!(config = %lint.item, "{}", lint.message);
+		}
+	}
+
+	match errors.into_iter().next() {
+		| Some(lint) => Err(Error::Config(lint.item, lint.message.into())),
+		| None => Ok(()),
+	}
+}
+
+fn lints(config: &Config) -> Vec<Lint> {
+	let mut lints = Vec::new();
+
+	if config.allow_registration
+		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
+		&& config.registration_token.is_none()
+		&& config.registration_token_file.is_none()
+		&& !config.require_email_for_registration
+		&& !config.captcha.is_enabled()
+		&& !config.lockdown_public_room_directory
+		&& !config.room_directory_review
+	{
+		lints.push(Lint {
+			severity: Severity::Warning,
+			item: "lockdown_public_room_directory",
+			message: "Open registration is enabled without a registration token and any user \
+			          can publish rooms to the public room directory. Spammers can register \
+			          accounts and advertise rooms on your server. Consider setting \
+			          `lockdown_public_room_directory` to true."
+				.to_owned(),
+		});
+	}
+
+	let host = config.server_name.host();
+	if config.allow_federation
+		&& (host == "localhost"
+			|| host.ends_with(".localhost")
+			|| host
+				.trim_start_matches('[')
+				.trim_end_matches(']')
+				.parse::<IpAddr>()
+				.is_ok_and(|ip| ip.is_loopback()))
+	{
+		lints.push(Lint {
+			// local testing with debug builds commonly uses localhost
+			severity: if cfg!(debug_assertions) {
+				Severity::Warning
+			} else {
+				Severity::Error
+			},
+			item: "allow_federation",
+			message: format!(
+				"Federation is enabled but server_name {:?} is a loopback address, which remote \
+				 servers can never reach. Set a publicly resolvable server_name or set \
+				 `allow_federation` to false.",
+				config.server_name
+			),
+		});
+	}
+
+	if config.rocksdb_direct_io {
+		if let Ok(fstype) = sys::storage::fstype_from_path(&config.database_path) {
+			if fstype.starts_with("fuse") || fstype == "zfs" {
+				lints.push(Lint {
+					severity: Severity::Warning,
+					item: "rocksdb_direct_io",
+					message: format!(
+						"The database path resides on a {fstype} filesystem, which is known to \
+						 have problems with direct-io. If RocksDB fails to open the database, \
+						 set `rocksdb_direct_io` to false."
+					),
+				});
+			}
+		}
+	}
+
+	if (!config.turn_secret.is_empty() || config.turn_secret_file.is_some())
+		&& (!config.turn_username.is_empty() || !config.turn_password.is_empty())
+	{
+		lints.push(Lint {
+			severity: Severity::Warning,
+			item: "turn_secret",
+			message: "Both a TURN shared secret and static TURN credentials are configured. The \
+			          shared secret takes priority and `turn_username`/`turn_password` are \
+			          ignored; remove whichever you did not intend to use."
+				.to_owned(),
+		});
+	}
+
+	lints
 }
 
 /// Iterates over all the keys in the config file and warns if there is a
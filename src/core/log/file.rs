@@ -0,0 +1,153 @@
+//! Log file output with size and time based rotation.
+
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::{err, utils::time::now_millis, Config, Result};
+
+/// Writer appending to a log file which is rotated once it exceeds a size or
+/// age limit. Rotated files are renamed with a millisecond timestamp suffix
+/// and only the most recent are retained.
+#[derive(Clone)]
+pub struct RollingFile {
+	inner: Arc<Mutex<Inner>>,
+}
+
+pub struct Guard<'a>(std::sync::MutexGuard<'a, Inner>);
+
+struct Inner {
+	path: PathBuf,
+	file: File,
+	written: u64,
+	opened: SystemTime,
+	max_size: u64,
+	max_age: Duration,
+	retain: usize,
+}
+
+impl RollingFile {
+	pub fn new(config: &Config, path: &Path) -> Result<Self> {
+		let (file, written) =
+			open(path).map_err(|e| err!(Config("log_file", "{path:?}: {e}")))?;
+
+		let inner = Inner {
+			path: path.to_owned(),
+			file,
+			written,
+			opened: SystemTime::now(),
+			max_size: config.log_file_max_size,
+			max_age: Duration::from_secs(config.log_file_rotate_interval_s),
+			retain: config.log_file_retain,
+		};
+
+		Ok(Self { inner: Arc::new(Mutex::new(inner)) })
+	}
+}
+
+impl<'a> MakeWriter<'a> for RollingFile {
+	type Writer = Guard<'a>;
+
+	fn make_writer(&'a self) -> Self::Writer {
+		Guard(
+			self.inner
+				.lock()
+				.unwrap_or_else(std::sync::PoisonError::into_inner),
+		)
+	}
+}
+
+impl Write for Guard<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let inner = &mut self.0;
+		if inner.due(buf.len()) {
+			// A failed rotation must not lose the event; keep appending to the
+			// current file and try again on the next write.
+			if let Err(e) = inner.rotate() {
+				eprintln!("Failed to rotate log file {:?}: {e}", inner.path);
+			}
+		}
+
+		let len = inner.file.write(buf)?;
+		inner.written = inner
+			.written
+			.saturating_add(len.try_into().unwrap_or(u64::MAX));
+
+		Ok(len)
+	}
+
+	fn flush(&mut self) -> io::Result<()> { self.0.file.flush() }
+}
+
+impl Inner {
+	fn due(&self, len: usize) -> bool {
+		let size_due = self.max_size > 0
+			&& self.written > 0
+			&& self
+				.written
+				.saturating_add(len.try_into().unwrap_or(u64::MAX))
+				> self.max_size;
+
+		let age_due = !self.max_age.is_zero()
+			&& self
+				.opened
+				.elapsed()
+				.is_ok_and(|elapsed| elapsed >= self.max_age);
+
+		size_due || age_due
+	}
+
+	fn rotate(&mut self) -> io::Result<()> {
+		self.file.flush()?;
+
+		let mut rotated = self.path.clone().into_os_string();
+		rotated.push(format!(".{}", now_millis()));
+		fs::rename(&self.path, &rotated)?;
+
+		(self.file, self.written) = open(&self.path)?;
+		self.opened = SystemTime::now();
+
+		self.prune()
+	}
+
+	fn prune(&self) -> io::Result<()> {
+		let (Some(dir), Some(name)) = (self.path.parent(), self.path.file_name()) else {
+			return Ok(());
+		};
+
+		let dir = if dir.as_os_str().is_empty() {
+			Path::new(".")
+		} else {
+			dir
+		};
+		let prefix = format!("{}.", name.to_string_lossy());
+		let mut rotated: Vec<(u64, PathBuf)> = fs::read_dir(dir)?
+			.filter_map(|entry| {
+				let entry = entry.ok()?;
+				let name = entry.file_name();
+				let stamp = name.to_str()?.strip_prefix(&prefix)?.parse().ok()?;
+				Some((stamp, entry.path()))
+			})
+			.collect();
+
+		rotated.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+		for (_, path) in rotated.iter().skip(self.retain) {
+			fs::remove_file(path)?;
+		}
+
+		Ok(())
+	}
+}
+
+fn open(path: &Path) -> io::Result<(File, u64)> {
+	let file = OpenOptions::new().create(true).append(true).open(path)?;
+	let written = file.metadata()?.len();
+
+	Ok((file, written))
+}
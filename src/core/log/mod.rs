@@ -3,14 +3,18 @@
 pub mod capture;
 pub mod color;
 mod console;
+mod file;
 pub mod fmt;
 pub mod fmt_span;
 mod reload;
+mod sample;
 mod suppress;
 
 pub use capture::Capture;
 pub use console::ConsoleFormat;
+pub use file::RollingFile;
 pub use reload::{LogLevelReloadHandles, ReloadHandle};
+pub use sample::Sampler;
 pub use suppress::Suppress;
 pub use tracing::Level;
 pub use tracing_core::{Event, Metadata};
@@ -0,0 +1,94 @@
+use tracing::subscriber::Interest;
+use tracing_core::{Metadata, Subscriber};
+use tracing_subscriber::{
+	layer::{Context, Filter},
+	registry::LookupSpan,
+};
+
+use crate::{err, Err, Result};
+
+/// Per-layer filter recording a random sample of root spans at a rate
+/// configured by target. Spans beneath one which is not recorded are not
+/// recorded either, so each trace is kept or discarded whole.
+#[derive(Clone, Debug, Default)]
+pub struct Sampler {
+	/// Target prefixes and their sampling rate, longest prefix first. An empty
+	/// prefix matches every target.
+	rules: Vec<(String, f64)>,
+}
+
+impl Sampler {
+	/// Parse directives of the form `target=rate`, or a bare `rate` for all
+	/// targets, where the rate is between 0.0 and 1.0.
+	pub fn new<I, S>(directives: I) -> Result<Self>
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		let mut rules = directives
+			.into_iter()
+			.map(|directive| parse(directive.as_ref()))
+			.collect::<Result<Vec<_>>>()?;
+
+		rules.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+
+		Ok(Self { rules })
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.rules.is_empty() }
+
+	fn rate(&self, target: &str) -> f64 {
+		self.rules
+			.iter()
+			.find(|(prefix, _)| matches(target, prefix))
+			.map_or(1.0, |(_, rate)| *rate)
+	}
+}
+
+impl<S> Filter<S> for Sampler
+where
+	S: Subscriber + for<'a> LookupSpan<'a>,
+{
+	fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+		if !meta.is_span() {
+			return true;
+		}
+
+		// The current span is unfiltered while the lookup is filtered; when the
+		// parent was discarded its descendants follow.
+		if let Some(parent) = cx.current_span().id() {
+			return cx.span(parent).is_some();
+		}
+
+		let rate = self.rate(meta.target());
+		rate >= 1.0 || (rate > 0.0 && rand::random::<f64>() < rate)
+	}
+
+	fn callsite_enabled(&self, _meta: &'static Metadata<'static>) -> Interest {
+		Interest::sometimes()
+	}
+}
+
+fn parse(directive: &str) -> Result<(String, f64)> {
+	let (target, rate) = directive.rsplit_once('=').unwrap_or(("", directive));
+
+	let rate: f64 = rate
+		.trim()
+		.parse()
+		.map_err(|e| err!("Invalid sampling rate in {directive:?}: {e}"))?;
+
+	if !(0.0..=1.0).contains(&rate) {
+		return Err!("Sampling rate in {directive:?} must be between 0.0 and 1.0");
+	}
+
+	Ok((target.trim().to_owned(), rate))
+}
+
+fn matches(target: &str, prefix: &str) -> bool {
+	prefix.is_empty()
+		|| target
+			.strip_prefix(prefix)
+			.is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
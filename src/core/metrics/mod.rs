@@ -1,3 +1,6 @@
+pub mod request;
+pub mod route;
+
 use std::sync::atomic::AtomicU32;
 
 use tokio::runtime;
@@ -24,6 +27,8 @@ pub struct Metrics {
 	pub requests_handle_active: AtomicU32,
 	pub requests_handle_finished: AtomicU32,
 	pub requests_panic: AtomicU32,
+
+	pub routes: route::Routes,
 }
 
 impl Metrics {
@@ -53,6 +58,8 @@ impl Metrics {
 			requests_handle_active: AtomicU32::new(0),
 			requests_handle_finished: AtomicU32::new(0),
 			requests_panic: AtomicU32::new(0),
+
+			routes: route::Routes::default(),
 		}
 	}
 
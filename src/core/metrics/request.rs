@@ -0,0 +1,67 @@
+//! Per-request timing breakdown, collected for slow request logging.
+
+use std::{
+	future::Future,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, OnceLock,
+	},
+	time::Duration,
+};
+
+#[derive(Debug, Default)]
+pub struct Timing {
+	/// Time spent authenticating the request, in microseconds.
+	auth: AtomicU64,
+
+	/// Time spent waiting on database queries, in microseconds.
+	db: AtomicU64,
+
+	/// Authenticated user, appservice or origin server making the request.
+	sender: OnceLock<String>,
+}
+
+tokio::task_local! {
+	static TIMING: Arc<Timing>;
+}
+
+impl Timing {
+	/// Run the future with this timing collecting the breakdown of the work it
+	/// performs on the current task.
+	pub async fn scope<F: Future>(self: &Arc<Self>, fut: F) -> F::Output {
+		TIMING.scope(self.clone(), fut).await
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn auth(&self) -> Duration { Duration::from_micros(self.auth.load(Ordering::Relaxed)) }
+
+	#[inline]
+	#[must_use]
+	pub fn db(&self) -> Duration { Duration::from_micros(self.db.load(Ordering::Relaxed)) }
+
+	#[inline]
+	#[must_use]
+	pub fn sender(&self) -> Option<&str> { self.sender.get().map(String::as_str) }
+}
+
+/// Account time spent authenticating the current request.
+#[inline]
+pub fn add_auth(elapsed: Duration) { add(elapsed, |timing| &timing.auth); }
+
+/// Account time spent waiting on the database for the current request.
+#[inline]
+pub fn add_db(elapsed: Duration) { add(elapsed, |timing| &timing.db); }
+
+/// Record who made the current request.
+#[inline]
+pub fn set_sender(sender: impl FnOnce() -> String) {
+	_ = TIMING.try_with(|timing| {
+		timing.sender.get_or_init(sender);
+	});
+}
+
+fn add(elapsed: Duration, field: impl FnOnce(&Timing) -> &AtomicU64) {
+	let micros = elapsed.as_micros().try_into().unwrap_or(u64::MAX);
+	_ = TIMING.try_with(|timing| field(timing).fetch_add(micros, Ordering::Relaxed));
+}
@@ -0,0 +1,139 @@
+//! Per-route request counts and latency histograms since startup.
+
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in milliseconds. Latencies
+/// above the last bound are counted in a final overflow bucket.
+const BUCKETS_MS: [u64; 15] =
+	[1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10_000, 30_000, 60_000];
+
+#[derive(Default)]
+pub struct Routes {
+	routes: Mutex<HashMap<String, Arc<Route>>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Route {
+	/// Requests currently being handled.
+	pub in_flight: AtomicU64,
+
+	/// Requests completed.
+	pub requests: AtomicU64,
+
+	/// Requests completed with a 4xx status.
+	pub client_errors: AtomicU64,
+
+	/// Requests completed with a 5xx status.
+	pub server_errors: AtomicU64,
+
+	buckets: [AtomicU64; BUCKETS_MS.len() + 1],
+}
+
+/// Held while a request is handled; records it against its route when done.
+pub struct Pending {
+	route: Arc<Route>,
+	done: bool,
+}
+
+impl Routes {
+	/// Count a request to `path` as in flight.
+	pub fn begin(&self, path: &str) -> Pending {
+		let mut routes = self.routes.lock().expect("locked");
+		let route = routes
+			.get(path)
+			.cloned()
+			.unwrap_or_else(|| routes.entry(path.to_owned()).or_default().clone());
+
+		drop(routes);
+		route.in_flight.fetch_add(1, Ordering::Relaxed);
+		Pending { route, done: false }
+	}
+
+	/// Statistics for every route requested since startup.
+	#[must_use]
+	pub fn snapshot(&self) -> Vec<(String, Arc<Route>)> {
+		self.routes
+			.lock()
+			.expect("locked")
+			.iter()
+			.map(|(path, route)| (path.clone(), route.clone()))
+			.collect()
+	}
+}
+
+impl Pending {
+	/// Record the request as completed with `status` after `elapsed`.
+	pub fn finish(mut self, status: u16, elapsed: Duration) {
+		let route = &self.route;
+		route.requests.fetch_add(1, Ordering::Relaxed);
+		match status {
+			| 400..=499 => _ = route.client_errors.fetch_add(1, Ordering::Relaxed),
+			| 500..=599 => _ = route.server_errors.fetch_add(1, Ordering::Relaxed),
+			| _ => {},
+		}
+
+		let elapsed_ms = elapsed.as_millis();
+		let bucket = BUCKETS_MS
+			.iter()
+			.position(|&bound| elapsed_ms <= bound.into())
+			.unwrap_or(BUCKETS_MS.len());
+
+		route.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+		self.done = true;
+	}
+}
+
+impl Drop for Pending {
+	fn drop(&mut self) {
+		self.route.in_flight.fetch_sub(1, Ordering::Relaxed);
+		if !self.done {
+			// The request was abandoned, e.g. the client disconnected.
+			self.route.requests.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+}
+
+impl Route {
+	/// Estimated latency below which `quantile` of requests completed. The
+	/// estimate is the upper bound of the histogram bucket the quantile falls
+	/// in; `None` when no requests completed or it falls in the overflow
+	/// bucket.
+	#[must_use]
+	#[allow(
+		clippy::as_conversions,
+		clippy::cast_possible_truncation,
+		clippy::cast_precision_loss,
+		clippy::cast_sign_loss
+	)]
+	pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+		let counts: Vec<u64> = self
+			.buckets
+			.iter()
+			.map(|count| count.load(Ordering::Relaxed))
+			.collect();
+
+		let total: u64 = counts.iter().sum();
+		if total == 0 {
+			return None;
+		}
+
+		let target = ((total as f64) * quantile).ceil().max(1.0) as u64;
+		let mut seen: u64 = 0;
+		counts
+			.iter()
+			.position(|&count| {
+				seen = seen.saturating_add(count);
+				seen >= target
+			})
+			.and_then(|bucket| BUCKETS_MS.get(bucket))
+			.copied()
+			.map(Duration::from_millis)
+	}
+}
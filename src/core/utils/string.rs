@@ -102,6 +102,53 @@ pub fn common_prefix<'a>(choice: &'a [&str]) -> &'a str {
 	})
 }
 
+/// Matches a string against a glob pattern, where `*` matches any run of
+/// characters and `?` matches any single character.
+/// ```
+/// use conduwuit_core::utils::string::glob_match;
+/// assert!(glob_match("@alice*:example.com", "@alice2:example.com"));
+/// ```
+#[must_use]
+pub fn glob_match(pattern: &str, input: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let input: Vec<char> = input.chars().collect();
+
+	// Where the last `*` was seen and how much input it had consumed, to
+	// backtrack to when the rest fails to match
+	let (mut p, mut i) = (0, 0);
+	let mut star: Option<(usize, usize)> = None;
+	while i < input.len() {
+		match pattern.get(p) {
+			| Some('*') => {
+				star = Some((p, i));
+				p = p.saturating_add(1);
+			},
+			| Some(&c) if c == '?' || c == input[i] => {
+				p = p.saturating_add(1);
+				i = i.saturating_add(1);
+			},
+			| _ => match star {
+				| Some((star_p, star_i)) => {
+					p = star_p.saturating_add(1);
+					i = star_i.saturating_add(1);
+					star = Some((star_p, i));
+				},
+				| None => return false,
+			},
+		}
+	}
+
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The literal prefix of a glob pattern, before its first wildcard.
+#[must_use]
+pub fn glob_prefix(pattern: &str) -> &str {
+	pattern
+		.find(['*', '?'])
+		.map_or(pattern, |end| pattern.split_at(end).0)
+}
+
 /// Parses the bytes into a string.
 pub fn string_from_bytes(bytes: &[u8]) -> Result<String> {
 	let str: &str = str_from_bytes(bytes)?;
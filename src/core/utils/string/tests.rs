@@ -21,6 +21,32 @@ fn common_prefix_none() {
 	assert_eq!(output, "");
 }
 
+#[test]
+fn glob_match() {
+	assert!(super::glob_match("@alice:example.com", "@alice:example.com"));
+	assert!(super::glob_match("@alice*", "@alice:example.com"));
+	assert!(super::glob_match("*:example.com", "@bob:example.com"));
+	assert!(super::glob_match("@b?b:*", "@bob:example.com"));
+	assert!(super::glob_match("*a*a*", "banana"));
+	assert!(super::glob_match("*", ""));
+}
+
+#[test]
+fn glob_match_none() {
+	assert!(!super::glob_match("@alice*", "@bob:example.com"));
+	assert!(!super::glob_match("@b?b", "@bob:example.com"));
+	assert!(!super::glob_match("*a*a*a*a", "banana"));
+	assert!(!super::glob_match("?", ""));
+}
+
+#[test]
+fn glob_prefix() {
+	assert_eq!(super::glob_prefix("@alice*:example.com"), "@alice");
+	assert_eq!(super::glob_prefix("@b?b"), "@b");
+	assert_eq!(super::glob_prefix("*"), "");
+	assert_eq!(super::glob_prefix("@carol"), "@carol");
+}
+
 #[test]
 fn camel_to_snake_case_0() {
 	let res = super::camel_to_snake_string("CamelToSnakeCase");
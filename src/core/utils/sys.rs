@@ -1,5 +1,6 @@
 pub mod compute;
 pub mod storage;
+pub mod systemd;
 
 use std::path::PathBuf;
 
@@ -54,3 +55,20 @@ pub fn current_exe_deleted() -> bool {
 	std::env::current_exe()
 		.is_ok_and(|exe| exe.to_str().is_some_and(|exe| exe.ends_with(" (deleted)")))
 }
+
+/// Resident set size of the process in bytes, read from procfs. Only
+/// available on Linux; an allocator-independent view of memory usage.
+#[must_use]
+pub fn resident_memory() -> Option<usize> {
+	let status = std::fs::read_to_string("/proc/self/status").ok()?;
+	let kib: usize = status
+		.lines()
+		.find_map(|line| line.strip_prefix("VmRSS:"))?
+		.trim()
+		.strip_suffix("kB")?
+		.trim()
+		.parse()
+		.ok()?;
+
+	kib.checked_mul(1024)
+}
@@ -134,3 +134,28 @@ pub fn dev_from_path(path: &Path) -> Result<(dev_t, dev_t)> {
 fn block_path((major, minor): (dev_t, dev_t)) -> PathBuf {
 	format!("/sys/dev/block/{major}:{minor}/").into()
 }
+
+/// Get the filesystem type (e.g. "ext4", "zfs", "fuse.sshfs") on which Path
+/// resides, according to the mount table of the current process. The deepest
+/// existing ancestor is used when Path does not exist yet.
+pub fn fstype_from_path(path: &Path) -> Result<String> {
+	use std::io::{Error, ErrorKind::NotFound};
+
+	let path = path
+		.ancestors()
+		.find_map(|path| path.canonicalize().ok())
+		.ok_or_else(|| Error::new(NotFound, "Path has no existing ancestor."))?;
+
+	read_to_string("/proc/self/mountinfo")?
+		.lines()
+		.filter_map(|line| {
+			let (mount, fs) = line.split_once(" - ")?;
+			let mount_point = mount.split(' ').nth(4)?;
+			let fstype = fs.split(' ').next()?;
+			path.starts_with(mount_point)
+				.then_some((mount_point, fstype))
+		})
+		.max_by_key(|(mount_point, _)| mount_point.len())
+		.map(|(_, fstype)| fstype.to_owned())
+		.ok_or_else(|| Error::new(NotFound, "Mount point not found.").into())
+}
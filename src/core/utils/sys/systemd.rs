@@ -0,0 +1,71 @@
+//! Service manager notifications, see sd_notify(3). These are no-ops unless
+//! built with the `systemd` feature and running as a systemd service.
+
+use std::time::Duration;
+
+/// Notify that startup is complete and the server is ready.
+#[inline]
+pub fn ready() {
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	notify(&[sd_notify::NotifyState::Ready, sd_notify::NotifyState::Status("Running")]);
+}
+
+/// Notify that the server is shutting down.
+#[inline]
+pub fn stopping() {
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	notify(&[sd_notify::NotifyState::Stopping, sd_notify::NotifyState::Status("Stopping")]);
+}
+
+/// Describe what the server is currently doing, e.g. which migration is being
+/// run. Shown by `systemctl status`.
+#[inline]
+pub fn status(status: &str) {
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	notify(&[sd_notify::NotifyState::Status(status)]);
+
+	#[cfg(not(all(feature = "systemd", target_os = "linux")))]
+	_ = status;
+}
+
+/// Ask the service manager to allow `timeout` more time from now before
+/// considering the current startup or shutdown to have hung.
+#[inline]
+pub fn extend_timeout(timeout: Duration) {
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	notify(&[sd_notify::NotifyState::ExtendTimeoutUsec(
+		timeout.as_micros().try_into().unwrap_or(u32::MAX),
+	)]);
+
+	#[cfg(not(all(feature = "systemd", target_os = "linux")))]
+	_ = timeout;
+}
+
+/// Keep-alive ping for the service manager's watchdog.
+#[inline]
+pub fn watchdog() {
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	notify(&[sd_notify::NotifyState::Watchdog]);
+}
+
+/// The interval at which watchdog() must be called, if the service manager
+/// has the watchdog enabled for this process (`WatchdogSec=`).
+#[must_use]
+pub fn watchdog_interval() -> Option<Duration> {
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	{
+		let mut usec = 0;
+		sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec))
+	}
+
+	#[cfg(not(all(feature = "systemd", target_os = "linux")))]
+	None
+}
+
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+fn notify(state: &[sd_notify::NotifyState<'_>]) {
+	// NOTIFY_SOCKET must remain set for later notifications.
+	if let Err(e) = sd_notify::notify(false, state) {
+		crate::debug_warn!(?state, "Failed to notify systemd: {e}");
+	}
+}
@@ -276,3 +276,43 @@ async fn set_intersection_sorted_stream2() {
 		.await;
 	assert!(r.eq(&["ccc", "ggg", "iii"]));
 }
+
+#[test]
+fn cron_schedule_next() {
+	use std::time::{Duration, UNIX_EPOCH};
+
+	use utils::time::cron::Schedule;
+
+	let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+
+	// 2025-01-01 00:00:00 UTC, a Wednesday
+	let start = 1_735_689_600;
+
+	let daily: Schedule = "0 4 * * *".parse().unwrap();
+	assert_eq!(daily.next_after(at(start)), Some(at(start + 4 * 3600)));
+	assert_eq!(daily.next_after(at(start + 4 * 3600)), Some(at(start + 28 * 3600)));
+
+	let quarterly: Schedule = "*/15 * * * *".parse().unwrap();
+	assert_eq!(quarterly.next_after(at(start + 60)), Some(at(start + 15 * 60)));
+
+	// Sunday 2025-01-05, written as 7
+	let weekly: Schedule = "30 2 * * 7".parse().unwrap();
+	assert_eq!(weekly.next_after(at(start)), Some(at(start + 4 * 86400 + 2 * 3600 + 1800)));
+
+	let monthly: Schedule = "@monthly".parse().unwrap();
+	assert_eq!(monthly.next_after(at(start)), Some(at(start + 31 * 86400)));
+
+	let never: Schedule = "0 0 30 2 *".parse().unwrap();
+	assert_eq!(never.next_after(at(start)), None);
+}
+
+#[test]
+fn cron_schedule_invalid() {
+	use utils::time::cron::Schedule;
+
+	assert!("* * * *".parse::<Schedule>().is_err());
+	assert!("60 * * * *".parse::<Schedule>().is_err());
+	assert!("*/0 * * * *".parse::<Schedule>().is_err());
+	assert!("5-1 * * * *".parse::<Schedule>().is_err());
+	assert!("0 4 * * mon".parse::<Schedule>().is_err());
+}
@@ -1,3 +1,4 @@
+pub mod cron;
 pub mod exponential_backoff;
 
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
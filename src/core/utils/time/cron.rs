@@ -0,0 +1,156 @@
+use std::{str::FromStr, time::SystemTime};
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Timelike, Utc};
+
+use crate::{err, Error, Result};
+
+/// A five-field cron schedule (minute, hour, day of month, month, day of
+/// week) evaluated in UTC. Fields take `*`, numbers, ranges, lists and steps
+/// such as `*/15` or `1-5`; `@hourly`, `@daily`, `@weekly`, `@monthly` and
+/// `@yearly` are accepted as shorthands.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Schedule {
+	minutes: u64,
+	hours: u64,
+	days: u64,
+	months: u64,
+	weekdays: u64,
+	any_day: bool,
+	any_weekday: bool,
+}
+
+/// How far ahead to look for the next run before deciding a schedule never
+/// fires, e.g. for the 30th of February.
+const SEARCH_DAYS: i64 = 366 * 4;
+
+impl Schedule {
+	/// Returns the first time strictly after `after` at which the schedule
+	/// fires, if any.
+	#[must_use]
+	pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+		let after: DateTime<Utc> = after.into();
+		let mut t = after
+			.with_second(0)?
+			.with_nanosecond(0)?
+			.checked_add_signed(TimeDelta::minutes(1))?;
+
+		let limit = t.checked_add_signed(TimeDelta::days(SEARCH_DAYS))?;
+		while t < limit {
+			if !has(self.months, t.month()) {
+				let (year, month) = match t.month() {
+					| 12 => (t.year().checked_add(1)?, 1),
+					| month => (t.year(), month.saturating_add(1)),
+				};
+
+				t = NaiveDate::from_ymd_opt(year, month, 1)?
+					.and_hms_opt(0, 0, 0)?
+					.and_utc();
+			} else if !self.day_matches(&t) {
+				t = t.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?.and_utc();
+			} else if !has(self.hours, t.hour()) {
+				t = t.with_minute(0)?.checked_add_signed(TimeDelta::hours(1))?;
+			} else if !has(self.minutes, t.minute()) {
+				t = t.checked_add_signed(TimeDelta::minutes(1))?;
+			} else {
+				return Some(t.into());
+			}
+		}
+
+		None
+	}
+
+	/// Like cron, a day matches either restricted day field when both are
+	/// restricted.
+	fn day_matches(&self, t: &DateTime<Utc>) -> bool {
+		let day = has(self.days, t.day());
+		let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+		match (self.any_day, self.any_weekday) {
+			| (true, true) => true,
+			| (true, false) => weekday,
+			| (false, true) => day,
+			| (false, false) => day || weekday,
+		}
+	}
+}
+
+impl FromStr for Schedule {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		let s = match s.trim() {
+			| "@hourly" => "0 * * * *",
+			| "@daily" | "@midnight" => "0 0 * * *",
+			| "@weekly" => "0 0 * * 0",
+			| "@monthly" => "0 0 1 * *",
+			| "@yearly" | "@annually" => "0 0 1 1 *",
+			| s => s,
+		};
+
+		let fields: Vec<&str> = s.split_whitespace().collect();
+		let [minutes, hours, days, months, weekdays] = fields[..] else {
+			return Err(err!("Cron schedule {s:?} must have five fields"));
+		};
+
+		// Sunday may be written as 0 or 7.
+		let mut weekdays_mask = field(weekdays, 0, 7)?;
+		if has(weekdays_mask, 7) {
+			weekdays_mask = (weekdays_mask & !bit(7)) | bit(0);
+		}
+
+		Ok(Self {
+			minutes: field(minutes, 0, 59)?,
+			hours: field(hours, 0, 23)?,
+			days: field(days, 1, 31)?,
+			months: field(months, 1, 12)?,
+			weekdays: weekdays_mask,
+			any_day: days.starts_with('*'),
+			any_weekday: weekdays.starts_with('*'),
+		})
+	}
+}
+
+/// Parses one cron field into a bitmask of the values it matches.
+fn field(s: &str, min: u32, max: u32) -> Result<u64> {
+	let invalid = || err!("Invalid cron field {s:?}, expected values from {min} to {max}");
+	let number = |n: &str| {
+		n.parse::<u32>()
+			.ok()
+			.filter(|n| (min..=max).contains(n))
+			.ok_or_else(invalid)
+	};
+
+	let mut mask = 0_u64;
+	for part in s.split(',') {
+		let (range, step) = match part.split_once('/') {
+			| Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+			| None => (part, 1),
+		};
+
+		if step == 0 {
+			return Err(invalid());
+		}
+
+		let (start, end) = match range.split_once('-') {
+			| _ if range == "*" => (min, max),
+			| Some((start, end)) => (number(start)?, number(end)?),
+			| None if step > 1 => (number(range)?, max),
+			| None => (number(range)?, number(range)?),
+		};
+
+		if start > end {
+			return Err(invalid());
+		}
+
+		mask = (start..=end)
+			.step_by(step.try_into()?)
+			.fold(mask, |mask, n| mask | bit(n));
+	}
+
+	Ok(mask)
+}
+
+#[inline]
+fn has(mask: u64, n: u32) -> bool { mask & bit(n) != 0 }
+
+#[inline]
+fn bit(n: u32) -> u64 { 1_u64.checked_shl(n).unwrap_or(0) }
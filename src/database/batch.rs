@@ -0,0 +1,99 @@
+use std::{fmt::Debug, sync::Arc};
+
+use conduwuit::{Err, Result};
+use rocksdb::{WriteBatchWithTransaction, WriteOptions};
+
+use crate::{map::write_options_default, util::or_else, Database, Engine, Map};
+
+/// Accumulates writes to any number of columns which are then committed
+/// atomically in a single RocksDB write batch.
+///
+/// Writes are not visible to readers (including the writer) until the batch
+/// is committed; only group writes which nothing in between depends on.
+/// Dropping an uncommitted batch discards its writes.
+///
+/// Used for the writes which must land together: a pdu entering the timeline,
+/// and a new state snapshot with its diff and the event it belongs to. The
+/// rest of a pdu's handling (the outlier, state cache, search and push
+/// updates) still writes directly, so a pdu as a whole isn't atomic. A whole
+/// federation transaction can't be one batch either, as each pdu is handled
+/// against what the ones before it wrote; its WAL flushes are corked instead.
+pub struct Batch<'a> {
+	db: Arc<Engine>,
+	batch: WriteBatchWithTransaction<false>,
+	write_options: WriteOptions,
+	wakes: Vec<(&'a Map, Vec<u8>)>,
+}
+
+impl Database {
+	#[inline]
+	#[must_use]
+	pub fn batch(&self) -> Batch<'_> { Batch::new(&self.db) }
+}
+
+impl<'a> Batch<'a> {
+	pub(super) fn new(db: &Arc<Engine>) -> Self {
+		Self {
+			db: db.clone(),
+			batch: WriteBatchWithTransaction::default(),
+			write_options: write_options_default(db),
+			wakes: Vec::new(),
+		}
+	}
+
+	/// Queue an insertion of Key/Value into the map; both are raw.
+	#[tracing::instrument(skip(self, map, val), fields(%map), level = "trace")]
+	pub fn insert<K, V>(&mut self, map: &'a Map, key: &K, val: V)
+	where
+		K: AsRef<[u8]> + ?Sized + Debug,
+		V: AsRef<[u8]>,
+	{
+		self.batch.put_cf(&map.cf(), key, val);
+		self.wakes.push((map, key.as_ref().to_vec()));
+	}
+
+	/// Queue a removal of the raw Key from the map.
+	#[tracing::instrument(skip(self, map), fields(%map), level = "trace")]
+	pub fn remove<K>(&mut self, map: &'a Map, key: &K)
+	where
+		K: AsRef<[u8]> + ?Sized + Debug,
+	{
+		self.batch.delete_cf(&map.cf(), key);
+	}
+
+	/// Write every queued mutation at once. Watchers are woken only after the
+	/// batch is durable in the memtable. Fails on a read-only database.
+	#[tracing::instrument(skip(self), fields(len = self.len()), level = "trace")]
+	pub fn commit(self) -> Result {
+		if self.is_empty() {
+			return Ok(());
+		}
+
+		if self.db.is_read_only() {
+			return Err!(Database("Cannot write to a read-only database."));
+		}
+
+		self.db
+			.db
+			.write_opt(self.batch, &self.write_options)
+			.or_else(or_else)?;
+
+		if !self.db.corked() {
+			self.db.flush()?;
+		}
+
+		for (map, key) in &self.wakes {
+			map.wake(key);
+		}
+
+		Ok(())
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn len(&self) -> usize { self.batch.len() }
+
+	#[inline]
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.batch.is_empty() }
+}
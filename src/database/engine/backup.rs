@@ -1,7 +1,7 @@
-use std::fmt::Write;
+use std::{fmt::Write, path::Path};
 
-use conduwuit::{error, implement, info, utils::time::rfc2822_from_seconds, warn, Result};
-use rocksdb::backup::{BackupEngine, BackupEngineOptions};
+use conduwuit::{error, implement, info, utils::time::rfc2822_from_seconds, warn, Err, Result};
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
 
 use super::Engine;
 use crate::{or_else, util::map_err};
@@ -71,3 +71,44 @@ pub fn backup_list(&self) -> Result<String> {
 
 	Ok(res)
 }
+
+/// Restores a backup into an empty directory. The live database can't be
+/// restored over; the restored copy is swapped in while the server is
+/// stopped.
+#[implement(Engine)]
+#[tracing::instrument(skip(self))]
+pub fn backup_restore(&self, backup_id: u32, target: &Path) -> Result {
+	let server = &self.ctx.server;
+	let config = &server.config;
+	let path = config.database_backup_path.as_ref();
+	if path.is_none() || path.is_some_and(|path| path.as_os_str().is_empty()) {
+		return Err!(Config(
+			"database_backup_path",
+			"Configure database_backup_path to enable backups"
+		));
+	}
+
+	if target == config.database_path
+		|| target.canonicalize().ok() == config.database_path.canonicalize().ok()
+	{
+		return Err!("Refusing to restore over the live database at {target:?}");
+	}
+
+	if target
+		.read_dir()
+		.is_ok_and(|mut entries| entries.next().is_some())
+	{
+		return Err!("Directory {target:?} is not empty");
+	}
+
+	let options =
+		BackupEngineOptions::new(path.expect("valid database backup path")).map_err(map_err)?;
+	let mut engine = BackupEngine::open(&options, &*self.ctx.env.lock()?).map_err(map_err)?;
+	engine
+		.restore_from_backup(target, target, &RestoreOptions::default(), backup_id)
+		.map_err(map_err)?;
+
+	info!("Restored database backup #{backup_id} to {target:?}");
+
+	Ok(())
+}
@@ -1,4 +1,9 @@
-use conduwuit::{err, utils::math::Expected, Config, Result};
+use conduwuit::{
+	config::RocksDbColumnOptions,
+	err,
+	utils::math::{usize_from_f64, Expected},
+	Config, Result,
+};
 use rocksdb::{
 	BlockBasedIndexType, BlockBasedOptions, BlockBasedPinningTier, Cache,
 	DBCompressionType as CompressionType, DataBlockIndexType, LruCacheOptions, Options,
@@ -6,7 +11,7 @@ use rocksdb::{
 };
 
 use super::descriptor::{CacheDisp, Descriptor};
-use crate::{util::map_err, Context};
+use crate::{map::dictionary::TRAIN_RATIO, util::map_err, Context};
 
 pub(super) const SENTINEL_COMPRESSION_LEVEL: i32 = 32767;
 
@@ -14,9 +19,32 @@ pub(super) const SENTINEL_COMPRESSION_LEVEL: i32 = 32767;
 /// db_options() as the argument to this function and use the return value in
 /// the arguments to open the specific column.
 pub(crate) fn cf_options(ctx: &Context, opts: Options, desc: &Descriptor) -> Result<Options> {
-	let cache = get_cache(ctx, desc);
 	let config = &ctx.server.config;
-	descriptor_cf_options(opts, desc.clone(), config, cache.as_ref())
+	let mut desc = desc.clone();
+	if let Some(column) = config.rocksdb_column_options.get(desc.name) {
+		set_column_options(&mut desc, column)?;
+	}
+
+	let cache = get_cache(ctx, &desc);
+	descriptor_cf_options(opts, desc, config, cache.as_ref())
+}
+
+/// Applies the overrides configured for the column in `rocksdb_column_options`
+/// which don't depend on the rest of the config. Compression is overridden in
+/// set_compression().
+fn set_column_options(desc: &mut Descriptor, column: &RocksDbColumnOptions) -> Result {
+	if let Some(capacity_mb) = column.cache_capacity_mb {
+		desc.cache_disp = CacheDisp::Unique;
+		desc.cache_size = usize_from_f64(capacity_mb * 1024.0 * 1024.0)?;
+	}
+
+	if let Some(size_mb) = column.write_buffer_size_mb {
+		desc.write_size = usize_from_f64(size_mb * 1024.0 * 1024.0)?;
+	}
+
+	desc.bloom_filter_bits = column.bloom_filter_bits;
+
+	Ok(())
 }
 
 fn descriptor_cf_options(
@@ -54,17 +82,27 @@ fn descriptor_cf_options(
 		.map(|val| val.unwrap_or(CompressionType::None))
 		.collect();
 
+	let dict_bytes: i32 = if desc.compression_dict && desc.compression == CompressionType::Zstd {
+		config.rocksdb_compression_dictionary_bytes.try_into()?
+	} else {
+		0
+	};
+
+	let train_bytes = dict_bytes.saturating_mul(TRAIN_RATIO.try_into()?);
+
 	opts.set_compression_type(desc.compression);
 	opts.set_compression_per_level(compression_shape.as_slice());
-	opts.set_compression_options(-14, desc.compression_level, 0, 0); // -14 w_bits used by zlib.
+	// -14 w_bits used by zlib.
+	opts.set_compression_options(-14, desc.compression_level, 0, dict_bytes);
+	opts.set_zstd_max_train_bytes(train_bytes);
 	if let Some(&bottommost_level) = desc.bottommost_level.as_ref() {
 		opts.set_bottommost_compression_type(desc.compression);
-		opts.set_bottommost_zstd_max_train_bytes(0, true);
+		opts.set_bottommost_zstd_max_train_bytes(train_bytes, true);
 		opts.set_bottommost_compression_options(
 			-14, // -14 w_bits is only read by zlib.
 			bottommost_level,
 			0,
-			0,
+			dict_bytes,
 			true,
 		);
 	}
@@ -112,7 +150,12 @@ fn set_table_options(opts: &mut Options, desc: &Descriptor, cache: Option<&Cache
 }
 
 fn set_compression(desc: &mut Descriptor, config: &Config) {
-	desc.compression = match config.rocksdb_compression_algo.as_ref() {
+	let column = config.rocksdb_column_options.get(desc.name);
+	let algo = column
+		.and_then(|column| column.compression.as_deref())
+		.unwrap_or(&config.rocksdb_compression_algo);
+
+	desc.compression = match algo {
 		| "snappy" => CompressionType::Snappy,
 		| "zlib" => CompressionType::Zlib,
 		| "bz2" => CompressionType::Bz2,
@@ -129,6 +172,10 @@ fn set_compression(desc: &mut Descriptor, config: &Config) {
 		desc.compression_level = config.rocksdb_compression_level;
 	}
 
+	if let Some(level) = column.and_then(|column| column.compression_level) {
+		desc.compression_level = level;
+	}
+
 	let can_override_bottom = config.rocksdb_bottommost_compression_level
 		== SENTINEL_COMPRESSION_LEVEL
 		&& desc.compression == CompressionType::Zstd;
@@ -167,6 +214,10 @@ fn table_options(desc: &Descriptor, has_cache: bool) -> BlockBasedOptions {
 	opts.set_unpartitioned_pinning_tier(BlockBasedPinningTier::None);
 	opts.set_top_level_index_pinning_tier(BlockBasedPinningTier::None);
 
+	if let Some(bits) = desc.bloom_filter_bits {
+		opts.set_bloom_filter(bits, false);
+	}
+
 	opts.set_partition_filters(true);
 	opts.set_use_delta_encoding(false);
 	opts.set_index_type(BlockBasedIndexType::TwoLevelIndexSearch);
@@ -189,6 +240,11 @@ fn get_cache(ctx: &Context, desc: &Descriptor) -> Option<Cache> {
 	// legacy-compat way
 	let config = &ctx.server.config;
 	let cap = match desc.name {
+		| name if config
+			.rocksdb_column_options
+			.get(name)
+			.is_some_and(|column| column.cache_capacity_mb.is_some()) =>
+			None,
 		| "eventid_pduid" => Some(config.eventid_pdu_cache_capacity),
 		| "eventid_shorteventid" => Some(config.eventidshort_cache_capacity),
 		| "shorteventid_eventid" => Some(config.shorteventid_cache_capacity),
@@ -42,7 +42,11 @@ impl Context {
 		let col_cache = Cache::new_lru_cache_opts(&col_cache_opts);
 		let col_cache: BTreeMap<_, _> = [("Shared".to_owned(), col_cache)].into();
 
-		let mut env = Env::new().or_else(or_else)?;
+		let mut env = match config.database_backend.as_str() {
+			| "memory" => Env::mem_env(),
+			| _ => Env::new(),
+		}
+		.or_else(or_else)?;
 
 		if config.rocksdb_compaction_prio_idle {
 			env.lower_thread_pool_cpu_priority();
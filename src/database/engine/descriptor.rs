@@ -37,8 +37,10 @@ pub(crate) struct Descriptor {
 	pub(crate) compressed_index: bool,
 	pub(crate) compression_shape: [i32; 7],
 	pub(crate) compression_level: i32,
+	pub(crate) compression_dict: bool,
 	pub(crate) bottommost_level: Option<i32>,
 	pub(crate) block_index_hashing: Option<bool>,
+	pub(crate) bloom_filter_bits: Option<f64>,
 	pub(crate) cache_shards: u32,
 	pub(crate) write_to_cache: bool,
 	pub(crate) auto_readahead_thresh: u32,
@@ -69,8 +71,10 @@ pub(crate) static BASE: Descriptor = Descriptor {
 	compressed_index: true,
 	compression_shape: [0, 0, 0, 1, 1, 1, 1],
 	compression_level: SENTINEL_COMPRESSION_LEVEL,
+	compression_dict: false,
 	bottommost_level: Some(SENTINEL_COMPRESSION_LEVEL),
 	block_index_hashing: None,
+	bloom_filter_bits: None,
 	cache_shards: 64,
 	write_to_cache: false,
 	auto_readahead_thresh: 0,
@@ -38,11 +38,20 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		repair(&db_opts, &config.database_path)?;
 	}
 
+	if config.database_backend == "memory" {
+		warn!("Using an in-memory database; nothing will be kept once the server stops.");
+	}
+
 	debug!("Opening database...");
 	let db = if config.rocksdb_read_only {
 		Db::open_cf_descriptors_read_only(&db_opts, path, cfds, false)
 	} else if config.rocksdb_secondary {
-		Db::open_cf_descriptors_as_secondary(&db_opts, path, path, cfds)
+		let secondary_path = config
+			.rocksdb_secondary_path
+			.clone()
+			.unwrap_or_else(|| path.join("secondary"));
+
+		Db::open_cf_descriptors_as_secondary(&db_opts, path, &secondary_path, cfds)
 	} else {
 		Db::open_cf_descriptors(&db_opts, path, cfds)
 	}
@@ -83,7 +92,7 @@ fn configure_cfds(
 	let missing = existing
 		.iter()
 		.filter(|&name| name != "default")
-		.filter(|&name| !desc.iter().any(|desc| desc.name == name));
+		.filter(|name| !desc.iter().any(|desc| desc.name == name.as_str()));
 
 	debug!(
 		existing = existing.len(),
@@ -97,6 +106,14 @@ fn configure_cfds(
 		debug!("Found unrecognized column {name:?} in existing database.");
 	});
 
+	config
+		.rocksdb_column_options
+		.keys()
+		.filter(|name| !desc.iter().any(|desc| desc.name == name.as_str()))
+		.for_each(|name| {
+			warn!("rocksdb_column_options has options for unknown column {name:?}.");
+		});
+
 	creating.map(|desc| desc.name).for_each(|name| {
 		debug!("Creating new column {name:?} not previously found in existing database.");
 	});
@@ -1,6 +1,7 @@
 pub mod compact;
 mod contains;
 mod count;
+pub mod dictionary;
 mod get;
 mod get_batch;
 mod insert;
@@ -76,6 +77,14 @@ impl Map {
 		self.watchers.watch(prefix.as_ref())
 	}
 
+	/// Wakes everything watching this map, when it may have changed behind
+	/// our back.
+	#[inline]
+	pub(crate) fn wake_all(&self) { self.watchers.wake_all(); }
+
+	#[inline]
+	pub(crate) fn wake(&self, key: &[u8]) { self.watchers.wake(key); }
+
 	#[inline]
 	pub fn property_integer(&self, name: &CStr) -> Result<u64> {
 		self.db.property_integer(&self.cf(), name)
@@ -0,0 +1,152 @@
+use conduwuit::{err, implement, Err, Result};
+
+use crate::util::map_err;
+
+/// Amount of data RocksDB samples to train each dictionary, as a multiple of
+/// the dictionary size; zstd recommends about 100x.
+pub(crate) const TRAIN_RATIO: usize = 100;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+	/// Maximum number of values to sample from the column. Half are used to
+	/// train the dictionary and the other half to measure it.
+	pub samples: usize,
+
+	/// Maximum size of the trained dictionary in bytes.
+	pub dict_bytes: usize,
+
+	/// zstd compression level used to measure the savings.
+	pub level: i32,
+}
+
+/// Results of training a dictionary against a sample of the column.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+	/// Number of values sampled for training.
+	pub trained: usize,
+
+	/// Number of values sampled for measurement.
+	pub measured: usize,
+
+	/// Size of the trained dictionary.
+	pub dict_bytes: usize,
+
+	/// Total size of the measured values uncompressed.
+	pub raw_bytes: usize,
+
+	/// Total size of the measured values compressed without the dictionary.
+	pub plain_bytes: usize,
+
+	/// Total size of the measured values compressed with the dictionary.
+	pub dict_compressed_bytes: usize,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			samples: 16384,
+			dict_bytes: 1024 * 64,
+			level: zstd::DEFAULT_COMPRESSION_LEVEL,
+		}
+	}
+}
+
+/// Train a zstd dictionary from values sampled evenly across the column and
+/// report the savings it would yield over plain zstd compression. The
+/// dictionary is discarded: RocksDB cannot be given one, it trains its own per
+/// file once `set_compression_dictionary` is used.
+#[implement(super::Map)]
+#[tracing::instrument(
+	name = "train",
+	level = "info"
+	skip(self),
+	fields(%self),
+)]
+pub fn train_dictionary_blocking(&self, opts: &Options) -> Result<Report> {
+	let samples = self.sample_values_blocking(opts.samples)?;
+	let (train, measure): (Vec<_>, Vec<_>) = samples
+		.into_iter()
+		.enumerate()
+		.partition(|(i, _)| i & 1 == 0);
+
+	let train: Vec<_> = train.into_iter().map(|(_, val)| val).collect();
+	let dictionary = zstd::dict::from_samples(&train, opts.dict_bytes)
+		.map_err(|e| err!("Failed to train dictionary from {} samples: {e}", train.len()))?;
+
+	let mut compressor = zstd::bulk::Compressor::with_dictionary(opts.level, &dictionary)?;
+	let mut report = Report {
+		trained: train.len(),
+		measured: measure.len(),
+		dict_bytes: dictionary.len(),
+		..Report::default()
+	};
+
+	for (_, val) in &measure {
+		report.raw_bytes = report.raw_bytes.saturating_add(val.len());
+		report.plain_bytes = report
+			.plain_bytes
+			.saturating_add(zstd::bulk::compress(val, opts.level)?.len());
+		report.dict_compressed_bytes = report
+			.dict_compressed_bytes
+			.saturating_add(compressor.compress(val)?.len());
+	}
+
+	Ok(report)
+}
+
+/// Enable dictionary compression for new files written to the column. The
+/// dictionary itself is trained by RocksDB from the data of each file as it is
+/// written; this only takes effect until restart, see the
+/// `rocksdb_compression_dictionary_bytes` config option.
+#[implement(super::Map)]
+#[tracing::instrument(level = "info", skip(self), fields(%self))]
+pub fn set_compression_dictionary(&self, dict_bytes: usize) -> Result {
+	let train_bytes = dict_bytes.saturating_mul(TRAIN_RATIO);
+	let opts = format!("{{max_dict_bytes={dict_bytes};zstd_max_train_bytes={train_bytes};}}");
+
+	self.db()
+		.db
+		.set_options_cf(&self.cf(), &[
+			("compression_opts", opts.as_str()),
+			("bottommost_compression_opts", opts.as_str()),
+		])
+		.map_err(map_err)
+}
+
+/// Collect up to `count` values spaced evenly across the column.
+#[implement(super::Map)]
+fn sample_values_blocking(&self, count: usize) -> Result<Vec<Vec<u8>>> {
+	if count == 0 {
+		return Err!("Cannot sample zero values.");
+	}
+
+	let estimate = self
+		.property_integer(c"rocksdb.estimate-num-keys")
+		.unwrap_or(0);
+
+	let stride = usize::try_from(estimate)
+		.unwrap_or(usize::MAX)
+		.checked_div(count)
+		.unwrap_or(1)
+		.max(1);
+
+	let opts = super::iter_options_default(self.db());
+	let mut iter = self.db().db.raw_iterator_cf_opt(&self.cf(), opts);
+	let mut samples = Vec::with_capacity(count);
+	let mut skip: usize = 0;
+
+	iter.seek_to_first();
+	while iter.valid() && samples.len() < count {
+		if skip == 0 {
+			samples.extend(iter.value().map(<[u8]>::to_vec));
+			skip = stride;
+		}
+
+		skip = skip.saturating_sub(1);
+		iter.next();
+	}
+
+	iter.status().map_err(map_err)?;
+
+	Ok(samples)
+}
@@ -6,7 +6,7 @@
 use std::{convert::AsRef, fmt::Debug, io::Write};
 
 use arrayvec::ArrayVec;
-use conduwuit::implement;
+use conduwuit::{debug_warn, implement};
 use rocksdb::WriteBatchWithTransaction;
 use serde::Serialize;
 
@@ -198,6 +198,13 @@ where
 	K: AsRef<[u8]> + ?Sized,
 	V: AsRef<[u8]>,
 {
+	// Writes made while a read-only or secondary database serves reads are
+	// dropped; what must not be lost goes through a Batch, which fails instead.
+	if self.db.is_read_only() {
+		debug_warn!(map = %self, "Dropped write to read-only database");
+		return;
+	}
+
 	let write_options = &self.write_options;
 	self.db
 		.db
@@ -220,6 +227,11 @@ where
 	K: AsRef<[u8]> + Sized + Debug + 'a,
 	V: AsRef<[u8]> + Sized + 'a,
 {
+	if self.db.is_read_only() {
+		debug_warn!(map = %self, "Dropped write to read-only database");
+		return;
+	}
+
 	let mut batch = WriteBatchWithTransaction::<false>::default();
 	for (key, val) in iter {
 		batch.put_cf(&self.cf(), key.as_ref(), val.as_ref());
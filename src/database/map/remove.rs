@@ -1,7 +1,7 @@
 use std::{convert::AsRef, fmt::Debug, io::Write};
 
 use arrayvec::ArrayVec;
-use conduwuit::implement;
+use conduwuit::{debug_warn, implement};
 use serde::Serialize;
 
 use crate::{keyval::KeyBuf, ser, util::or_else};
@@ -43,6 +43,11 @@ pub fn remove<K>(&self, key: &K)
 where
 	K: AsRef<[u8]> + ?Sized + Debug,
 {
+	if self.db.is_read_only() {
+		debug_warn!(map = %self, "Dropped removal from read-only database");
+		return;
+	}
+
 	let write_options = &self.write_options;
 	self.db
 		.db
@@ -33,6 +33,11 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "aliasid_alias",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "authchainhash_authchain",
+		key_size_hint: Some(32),
+		..descriptor::RANDOM
+	},
 	Descriptor {
 		name: "backupid_algorithm",
 		..descriptor::RANDOM_SMALL
@@ -49,6 +54,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "bannedroomids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "clientsecretsessionid_threepidsession",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "dataexportid_userid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "disabledroomids",
 		..descriptor::RANDOM_SMALL
@@ -60,6 +73,7 @@ pub(super) static MAPS: &[Descriptor] = &[
 		val_size_hint: Some(1488),
 		block_size: 1024,
 		index_size: 512,
+		compression_dict: true,
 		..descriptor::RANDOM
 	},
 	Descriptor {
@@ -88,6 +102,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "id_appserviceregistrations",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "ipban_info",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "keychangeid_userid",
 		..descriptor::RANDOM
@@ -108,6 +126,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "mediaid_user",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "oidcsubject_userid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "onetimekeyid_onetimekeys",
 		..descriptor::RANDOM_SMALL
@@ -119,6 +141,7 @@ pub(super) static MAPS: &[Descriptor] = &[
 		val_size_hint: Some(1520),
 		block_size: 2048,
 		index_size: 512,
+		compression_dict: true,
 		..descriptor::SEQUENTIAL
 	},
 	Descriptor {
@@ -129,14 +152,42 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "publicroomids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "quarantinedmediaids",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "quarantinedroomids",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "readreceiptid_readreceipt",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "receivedat_count",
+		..descriptor::SEQUENTIAL_SMALL
+	},
 	Descriptor {
 		name: "referencedevents",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "refreshtoken_userdeviceid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "registrationtoken_info",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "renewaltoken_userid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomid_emptysince",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_invitedcount",
 		..descriptor::RANDOM_SMALL
@@ -153,6 +204,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "roomid_pduleaves",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomid_publishrequest",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_shortroomid",
 		val_size_hint: Some(8),
@@ -213,6 +268,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "senderkey_pusher",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "senderkey_pusherstate",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "server_signingkeys",
 		..descriptor::RANDOM
@@ -229,6 +288,18 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "servername_educount",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "appserviceid_devicelistcount",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "appserviceid_txnfailures",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "appserviceid_txnsuccess",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servername_override",
 		..descriptor::RANDOM_SMALL
@@ -239,6 +310,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		val_size_hint: Some(128),
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "servernoticeid_notice",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "servernoticeid_userid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "serverroomids",
 		..descriptor::RANDOM_SMALL
@@ -297,6 +376,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "threadid_userids",
 		..descriptor::SEQUENTIAL_SMALL
 	},
+	Descriptor {
+		name: "threepid_userid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "todeviceid_events",
 		..descriptor::RANDOM
@@ -316,18 +399,34 @@ pub(super) static MAPS: &[Descriptor] = &[
 		block_size: 512,
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "unfederatedroomids",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "url_previews",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userdeviceid_adminloginexpiresat",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdeviceid_metadata",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userdeviceid_refreshtoken",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdeviceid_token",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userdeviceid_tokenexpiresat",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdevicesessionid_uiaainfo",
 		..descriptor::RANDOM_SMALL
@@ -348,6 +447,18 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_blurhash",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_createdat",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_crosssigningresetexpiresat",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_dataexport",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_devicelistversion",
 		..descriptor::RANDOM_SMALL
@@ -356,6 +467,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_displayname",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_erased",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_expiresat",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_lastonetimekeyupdate",
 		..descriptor::RANDOM_SMALL
@@ -372,10 +491,22 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_presenceid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_renewaltoken",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_selfsigningkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_servernoticeroomid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_suspended",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_usersigningkeyid",
 		..descriptor::RANDOM_SMALL
@@ -384,6 +515,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "useridprofilekey_value",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "useridthreepid_metadata",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "openidtoken_expiresatuserid",
 		..descriptor::RANDOM_SMALL
@@ -416,4 +551,12 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userroomid_notificationcount",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userroomthreadid_highlightcount",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userroomthreadid_notificationcount",
+		..descriptor::RANDOM_SMALL
+	},
 ];
@@ -5,6 +5,7 @@ conduwuit::mod_ctor! {}
 conduwuit::mod_dtor! {}
 conduwuit::rustc_flags_capture! {}
 
+mod batch;
 mod cork;
 mod de;
 mod deserialized;
@@ -26,11 +27,12 @@ use std::{ops::Index, sync::Arc};
 use conduwuit::{err, Result, Server};
 
 pub use self::{
+	batch::Batch,
 	de::{Ignore, IgnoreAll},
 	deserialized::Deserialized,
 	handle::Handle,
 	keyval::{serialize_key, serialize_val, KeyVal, Slice},
-	map::{compact, Get, Map, Qry},
+	map::{compact, dictionary, Get, Map, Qry},
 	ser::{serialize, serialize_to, serialize_to_vec, Cbor, Interfix, Json, Separator, SEP},
 };
 pub(crate) use self::{
@@ -79,6 +81,21 @@ impl Database {
 	#[inline]
 	#[must_use]
 	pub fn is_secondary(&self) -> bool { self.db.is_secondary() }
+
+	/// Catches a secondary up with the writes of its primary, then wakes
+	/// everything watching the database if there were any. Returns whether
+	/// there were.
+	pub fn catch_up(&self) -> Result<bool> {
+		let sequence = self.db.current_sequence();
+		self.db.update()?;
+		if self.db.current_sequence() == sequence {
+			return Ok(false);
+		}
+
+		self.maps.values().for_each(|map| map.wake_all());
+
+		Ok(true)
+	}
 }
 
 impl Index<&str> for Database {
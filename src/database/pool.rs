@@ -8,11 +8,12 @@ use std::{
 	},
 	thread,
 	thread::JoinHandle,
+	time::Instant,
 };
 
 use async_channel::{QueueStrategy, Receiver, RecvError, Sender};
 use conduwuit::{
-	debug, debug_warn, err, error, implement,
+	debug, debug_warn, err, error, implement, metrics,
 	result::{DebugInspect, LogDebugErr},
 	trace,
 	utils::sys::compute::{get_affinity, nth_core_available, set_affinity},
@@ -200,13 +201,18 @@ pub(crate) async fn execute_get(self: &Arc<Self>, mut cmd: Get) -> Result<BatchR
 	let (send, recv) = oneshot::channel();
 	_ = cmd.res.insert(send);
 
+	let timer = Instant::now();
 	let queue = self.select_queue();
-	self.execute(queue, Cmd::Get(cmd))
+	let result = self
+		.execute(queue, Cmd::Get(cmd))
 		.and_then(move |()| {
 			recv.map_ok(into_recv_get)
 				.map_err(|e| err!(error!("recv failed {e:?}")))
 		})
-		.await
+		.await;
+
+	metrics::request::add_db(timer.elapsed());
+	result
 }
 
 #[implement(Pool)]
@@ -215,13 +221,18 @@ pub(crate) async fn execute_iter(self: &Arc<Self>, mut cmd: Seek) -> Result<stre
 	let (send, recv) = oneshot::channel();
 	_ = cmd.res.insert(send);
 
+	let timer = Instant::now();
 	let queue = self.select_queue();
-	self.execute(queue, Cmd::Iter(cmd))
+	let result = self
+		.execute(queue, Cmd::Iter(cmd))
 		.and_then(|()| {
 			recv.map_ok(into_recv_seek)
 				.map_err(|e| err!(error!("recv failed {e:?}")))
 		})
-		.await
+		.await;
+
+	metrics::request::add_db(timer.elapsed());
+	result
 }
 
 #[implement(Pool)]
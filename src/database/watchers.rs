@@ -55,4 +55,11 @@ impl Watchers {
 			}
 		};
 	}
+
+	pub(crate) fn wake_all(&self) {
+		let watchers = std::mem::take(&mut *self.watchers.write().unwrap());
+		for (tx, _) in watchers.into_values() {
+			tx.send(()).expect("channel should still be open");
+		}
+	}
 }
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use conduwuit::{
 	config::Config,
 	debug_warn, err,
-	log::{capture, fmt_span, ConsoleFormat, LogLevelReloadHandles},
+	log::{capture, fmt_span, ConsoleFormat, LogLevelReloadHandles, RollingFile, Sampler},
 	result::UnwrapOrErr,
 	Result,
 };
@@ -39,8 +39,27 @@ pub(crate) fn init(
 	let cap_state = Arc::new(capture::State::new());
 	let cap_layer = capture::Layer::new(&cap_state);
 
+	let file_layer = config
+		.log_file
+		.as_deref()
+		.map(|path| -> Result<_> {
+			let file_filter = EnvFilter::builder()
+				.with_regex(config.log_filter_regex)
+				.parse(&config.log_file_filter)
+				.map_err(|e| err!(Config("log_file_filter", "{e}.")))?;
+			let file_layer = fmt::Layer::new()
+				.with_ansi(false)
+				.with_thread_ids(config.log_thread_ids)
+				.with_writer(RollingFile::new(config, path)?);
+			let (file_reload_filter, file_reload_handle) = reload::Layer::new(file_filter);
+			reload_handles.add("file", Box::new(file_reload_handle));
+			Ok(file_layer.with_filter(file_reload_filter))
+		})
+		.transpose()?;
+
 	let subscriber = Registry::default()
 		.with(console_layer.with_filter(console_reload_filter))
+		.with(file_layer)
 		.with(cap_layer);
 
 	#[cfg(feature = "sentry_telemetry")]
@@ -71,6 +90,8 @@ pub(crate) fn init(
 
 		let jaeger_filter = EnvFilter::try_new(&config.jaeger_filter)
 			.map_err(|e| err!(Config("jaeger_filter", "{e}.")))?;
+		let jaeger_sampler = Sampler::new(&config.jaeger_sampling)
+			.map_err(|e| err!(Config("jaeger_sampling", "{e}.")))?;
 		let jaeger_layer = config.allow_jaeger.then(|| {
 			opentelemetry::global::set_text_map_propagator(
 				opentelemetry_jaeger::Propagator::new(),
@@ -84,10 +105,56 @@ pub(crate) fn init(
 			let (jaeger_reload_filter, jaeger_reload_handle) =
 				reload::Layer::new(jaeger_filter.clone());
 			reload_handles.add("jaeger", Box::new(jaeger_reload_handle));
-			Some(telemetry.with_filter(jaeger_reload_filter))
+			let jaeger_sampler = (!jaeger_sampler.is_empty()).then_some(jaeger_sampler);
+			Some(
+				telemetry
+					.with_filter(jaeger_sampler)
+					.with_filter(jaeger_reload_filter),
+			)
 		});
 
-		let subscriber = subscriber.with(flame_layer).with(jaeger_layer);
+		let otlp_filter = EnvFilter::try_new(&config.otlp_filter)
+			.map_err(|e| err!(Config("otlp_filter", "{e}.")))?;
+		let otlp_sampler = Sampler::new(&config.otlp_sampling)
+			.map_err(|e| err!(Config("otlp_sampling", "{e}.")))?;
+		let otlp_layer = config
+			.otlp_endpoint
+			.as_ref()
+			.map(|endpoint| -> Result<_> {
+				use opentelemetry_otlp::WithExportConfig;
+
+				opentelemetry::global::set_text_map_propagator(
+					opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+				);
+				let exporter = opentelemetry_otlp::new_exporter()
+					.http()
+					.with_endpoint(endpoint.as_str())
+					.with_headers(config.otlp_headers.clone().into_iter().collect());
+				let resource = opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new(
+					"service.name",
+					config.otlp_service_name.clone(),
+				)]);
+				let tracer = opentelemetry_otlp::new_pipeline()
+					.tracing()
+					.with_exporter(exporter)
+					.with_trace_config(opentelemetry_sdk::trace::config().with_resource(resource))
+					.install_batch(opentelemetry_sdk::runtime::Tokio)
+					.map_err(|e| err!(Config("otlp_endpoint", "{e}.")))?;
+				let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+				let (otlp_reload_filter, otlp_reload_handle) =
+					reload::Layer::new(otlp_filter.clone());
+				reload_handles.add("otlp", Box::new(otlp_reload_handle));
+				let otlp_sampler = (!otlp_sampler.is_empty()).then_some(otlp_sampler);
+				Ok(telemetry
+					.with_filter(otlp_sampler)
+					.with_filter(otlp_reload_filter))
+			})
+			.transpose()?;
+
+		let subscriber = subscriber
+			.with(flame_layer)
+			.with(jaeger_layer)
+			.with(otlp_layer);
 		(subscriber, flame_guard)
 	};
 
@@ -50,6 +50,7 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 
 	let layers = layers
 		.layer(SetSensitiveHeadersLayer::new([header::AUTHORIZATION]))
+		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::id))
 		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::spawn))
 		.layer(
 			TraceLayer::new_for_http()
@@ -60,6 +61,8 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 		)
 		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::handle))
 		.layer(SecureClientIpSource::ConnectInfo.into_extension())
+		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::ipban))
+		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::ratelimit))
 		.layer(SetResponseHeaderLayer::if_not_present(
 			HeaderName::from_static("origin-agent-cluster"), // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Origin-Agent-Cluster
 			HeaderValue::from_static("?1"),
@@ -153,6 +156,7 @@ fn cors_layer(_server: &Server) -> CorsLayer {
 		.allow_origin(cors::Any)
 		.allow_methods(METHODS)
 		.allow_headers(headers)
+		.expose_headers([HeaderName::from_static(request::X_REQUEST_ID)])
 		.max_age(Duration::from_secs(86400))
 }
 
@@ -208,11 +212,15 @@ fn tracing_span<T>(request: &http::Request<T>) -> tracing::Span {
 	);
 
 	let method = request.method();
+	let request_id = request
+		.headers()
+		.get(request::X_REQUEST_ID)
+		.and_then(|id| id.to_str().ok());
 
-	tracing::debug_span!(parent: None, "router", %method, %path)
+	tracing::debug_span!(parent: None, "router", %method, %path, request_id)
 }
 
-fn truncated_matched_path(path: &MatchedPath) -> &str {
+pub(crate) fn truncated_matched_path(path: &MatchedPath) -> &str {
 	path.as_str()
 		.rsplit_once(':')
 		.map_or(path.as_str(), |path| path.0.strip_suffix('/').unwrap_or(path.0))
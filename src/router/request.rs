@@ -1,12 +1,211 @@
-use std::sync::{atomic::Ordering, Arc};
+use std::{
+	net::{IpAddr, SocketAddr},
+	sync::{atomic::Ordering, Arc},
+	time::Instant,
+};
 
 use axum::{
-	extract::State,
+	body::HttpBody,
+	extract::{ConnectInfo, MatchedPath, State},
 	response::{IntoResponse, Response},
 };
-use conduwuit::{debug, debug_error, debug_warn, err, error, trace, Result};
+use conduwuit::{
+	debug, debug_error, debug_warn, err, error, metrics::request::Timing, trace, utils, warn,
+	Result,
+};
 use conduwuit_service::Services;
-use http::{Method, StatusCode, Uri};
+use http::{header, HeaderValue, Method, StatusCode, Uri};
+
+use crate::layers;
+
+pub(crate) const X_REQUEST_ID: &str = "x-request-id";
+
+/// Longest `X-Request-Id` accepted from a trusted proxy.
+const REQUEST_ID_MAX_LEN: usize = 128;
+
+/// Field of JSON error responses carrying the request ID, to be quoted when
+/// reporting the error.
+const REQUEST_ID_ERROR_FIELD: &str = "org.conduwuit.request_id";
+
+/// Largest error response the request ID is added to.
+const ERROR_BODY_MAX_LEN: usize = 64 * 1024;
+
+/// Routes which only read despite being POSTed to, to send a filter or a list
+/// of users, so a read-only server still serves them.
+const READ_ONLY_POST_ROUTES: &[&str] =
+	&["/search", "/keys/query", "/publicRooms", "/user_directory/search"];
+
+/// Assigns the request its ID before anything else sees it, and echoes that ID
+/// on the response and in the body of JSON error responses. The ID given by a
+/// trusted proxy is kept.
+pub(crate) async fn id(
+	State(services): State<Arc<Services>>,
+	mut req: http::Request<axum::body::Body>,
+	next: axum::middleware::Next,
+) -> Response {
+	let from_trusted_proxy = req
+		.extensions()
+		.get::<ConnectInfo<SocketAddr>>()
+		.is_some_and(|ConnectInfo(addr)| services.ratelimit.is_trusted_proxy(addr.ip()));
+
+	let request_id = req
+		.headers()
+		.get(X_REQUEST_ID)
+		.filter(|_| from_trusted_proxy)
+		.filter(|id| !id.is_empty() && id.len() <= REQUEST_ID_MAX_LEN)
+		.cloned()
+		.unwrap_or_else(|| {
+			HeaderValue::from_str(&utils::rand::string(16))
+				.expect("alphanumeric string is a valid header value")
+		});
+
+	req.headers_mut().insert(X_REQUEST_ID, request_id.clone());
+
+	let response = next.run(req).await;
+	let mut response =
+		if response.status().is_client_error() || response.status().is_server_error() {
+			with_request_id(response, &request_id).await
+		} else {
+			response
+		};
+
+	response.headers_mut().insert(X_REQUEST_ID, request_id);
+
+	response
+}
+
+/// Adds the request ID to a JSON error response, leaving other responses as
+/// they are.
+async fn with_request_id(response: Response, request_id: &HeaderValue) -> Response {
+	let is_json = response
+		.headers()
+		.get(header::CONTENT_TYPE)
+		.is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+
+	let is_small = response
+		.body()
+		.size_hint()
+		.upper()
+		.and_then(|len| usize::try_from(len).ok())
+		.is_some_and(|len| len <= ERROR_BODY_MAX_LEN);
+
+	let Ok(request_id) = request_id.to_str() else {
+		return response;
+	};
+
+	if !is_json || !is_small {
+		return response;
+	}
+
+	let (mut parts, body) = response.into_parts();
+	let Ok(body) = axum::body::to_bytes(body, ERROR_BODY_MAX_LEN).await else {
+		return Response::from_parts(parts, axum::body::Body::empty());
+	};
+
+	let Ok(serde_json::Value::Object(mut error)) = serde_json::from_slice(&body) else {
+		return Response::from_parts(parts, body.into());
+	};
+
+	error.insert(REQUEST_ID_ERROR_FIELD.to_owned(), request_id.into());
+	let body = serde_json::to_vec(&error).expect("JSON object serializes");
+	parts.headers.remove(header::CONTENT_LENGTH);
+
+	Response::from_parts(parts, body.into())
+}
+
+/// Refuses requests to client endpoints from IP addresses banned with
+/// `!admin ip ban` or `ip_autoban_failures`.
+pub(crate) async fn ipban(
+	State(services): State<Arc<Services>>,
+	req: http::Request<axum::body::Body>,
+	next: axum::middleware::Next,
+) -> Response {
+	let path = req.uri().path();
+	if !path.starts_with("/_matrix/client/") && !path.starts_with("/_matrix/media/") {
+		return next.run(req).await;
+	}
+
+	let (parts, body) = req.into_parts();
+	let Some(ip) = client_ip(&services, &parts) else {
+		return next.run(http::Request::from_parts(parts, body)).await;
+	};
+
+	if services.ipban.banned(ip).is_some() {
+		debug_warn!(%ip, path = parts.uri.path(), "IP address is banned");
+		return err!(Request(Forbidden("Your IP address is banned from this homeserver.")))
+			.into_response();
+	}
+
+	next.run(http::Request::from_parts(parts, body)).await
+}
+
+/// Address of the client making a request: the peer, or the address it
+/// forwarded the request for if it is one of `trusted_proxies`.
+fn client_ip(services: &Services, parts: &http::request::Parts) -> Option<IpAddr> {
+	parts
+		.extensions
+		.get::<ConnectInfo<SocketAddr>>()
+		.map(|ConnectInfo(addr)| services.ratelimit.client_ip(addr.ip(), &parts.headers))
+}
+
+/// Enforces `rate_limits` and the registration limits on requests to matched
+/// routes.
+pub(crate) async fn ratelimit(
+	State(services): State<Arc<Services>>,
+	req: http::Request<axum::body::Body>,
+	next: axum::middleware::Next,
+) -> Response {
+	let config = &services.server.config;
+	if config.rate_limits.is_empty()
+		&& config.appservice_rate_limits.is_empty()
+		&& config.registrations_per_ip == 0
+		&& config.register_available_per_ip == 0
+	{
+		return next.run(req).await;
+	}
+
+	let Some(path) = req.extensions().get::<MatchedPath>().cloned() else {
+		return next.run(req).await;
+	};
+
+	let (parts, body) = req.into_parts();
+	let Some(ip) = client_ip(&services, &parts) else {
+		return next.run(http::Request::from_parts(parts, body)).await;
+	};
+
+	let access_token = parts
+		.headers
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.or_else(|| {
+			parts.uri.query().and_then(|query| {
+				query
+					.split('&')
+					.find_map(|pair| pair.strip_prefix("access_token="))
+			})
+		});
+
+	if let Err(e) = services
+		.ratelimit
+		.check_registration(&parts.method, path.as_str(), access_token, ip)
+		.await
+	{
+		debug_warn!(%ip, path = path.as_str(), "registration rate limited");
+		return e.into_response();
+	}
+
+	if let Err(e) = services
+		.ratelimit
+		.check(&parts.method, path.as_str(), access_token, ip)
+		.await
+	{
+		debug_warn!(%ip, path = path.as_str(), "rate limited");
+		return e.into_response();
+	}
+
+	next.run(http::Request::from_parts(parts, body)).await
+}
 
 #[tracing::instrument(
 	parent = None,
@@ -91,24 +290,84 @@ pub(crate) async fn handle(
 		return Err(StatusCode::SERVICE_UNAVAILABLE);
 	}
 
+	if services.db.is_read_only() && !is_read_only_request(&req) {
+		debug_warn!(
+			method = %req.method(),
+			uri = %req.uri(),
+			"refused by read-only database"
+		);
+
+		return Ok(err!(Request(Forbidden(
+			"This server is read-only; send requests which make changes to its primary."
+		)))
+		.into_response());
+	}
+
 	let uri = req.uri().clone();
 	let method = req.method().clone();
-	let result = next.run(req).await;
-	handle_result(&method, &uri, result)
+	let request_id = req.headers().get(X_REQUEST_ID).cloned();
+	let route = req
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or("unmatched", layers::truncated_matched_path);
+
+	let pending = server.metrics.routes.begin(route);
+	let timing = Arc::new(Timing::default());
+	let timer = Instant::now();
+	let result = timing.scope(next.run(req)).await;
+	let elapsed = timer.elapsed();
+	pending.finish(result.status().as_u16(), elapsed);
+	let threshold = server.config.slow_request_threshold_ms;
+	if threshold > 0 && elapsed.as_millis() >= threshold.into() {
+		warn!(
+			method = %method,
+			path = %uri.path(),
+			sender = timing.sender().unwrap_or_default(),
+			?request_id,
+			status = result.status().as_u16(),
+			auth = ?timing.auth(),
+			handler = ?elapsed.saturating_sub(timing.auth()),
+			db = ?timing.db(),
+			"slow request took {elapsed:?}"
+		);
+	}
+
+	handle_result(&method, &uri, request_id.as_ref(), result)
+}
+
+/// Whether a request to a route can be served without changing anything.
+fn is_read_only_request(req: &http::Request<axum::body::Body>) -> bool {
+	if req.method().is_safe() {
+		return true;
+	}
+
+	let Some(route) = req.extensions().get::<MatchedPath>() else {
+		return false;
+	};
+
+	req.method() == Method::POST
+		&& READ_ONLY_POST_ROUTES
+			.iter()
+			.any(|suffix| route.as_str().ends_with(suffix))
 }
 
-fn handle_result(method: &Method, uri: &Uri, result: Response) -> Result<Response, StatusCode> {
+fn handle_result(
+	method: &Method,
+	uri: &Uri,
+	request_id: Option<&HeaderValue>,
+	result: Response,
+) -> Result<Response, StatusCode> {
 	let status = result.status();
 	let reason = status.canonical_reason().unwrap_or("Unknown Reason");
 	let code = status.as_u16();
 	if status.is_server_error() {
-		error!(method = ?method, uri = ?uri, "{code} {reason}");
+		error!(method = ?method, uri = ?uri, ?request_id, "{code} {reason}");
 	} else if status.is_client_error() {
-		debug_error!(method = ?method, uri = ?uri, "{code} {reason}");
+		debug_error!(method = ?method, uri = ?uri, ?request_id, "{code} {reason}");
 	} else if status.is_redirection() {
-		debug!(method = ?method, uri = ?uri, "{code} {reason}");
+		debug!(method = ?method, uri = ?uri, ?request_id, "{code} {reason}");
 	} else {
-		trace!(method = ?method, uri = ?uri, "{code} {reason}");
+		trace!(method = ?method, uri = ?uri, ?request_id, "{code} {reason}");
 	}
 
 	if status == StatusCode::METHOD_NOT_ALLOWED {
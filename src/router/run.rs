@@ -8,7 +8,9 @@ use std::{
 };
 
 use axum_server::Handle as ServerHandle;
-use conduwuit::{debug, debug_error, debug_info, error, info, Error, Result, Server};
+use conduwuit::{
+	debug, debug_error, debug_info, error, info, utils::sys::systemd, Error, Result, Server,
+};
 use futures::FutureExt;
 use service::Services;
 use tokio::{
@@ -39,6 +41,10 @@ pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
 			.runtime()
 			.spawn(serve::serve(services.clone(), handle.clone(), tx.subscribe()));
 
+	// Keep the service manager's watchdog fed while we're running
+	let watchdog = systemd::watchdog_interval()
+		.map(|interval| server.runtime().spawn(watchdog(server.clone(), interval)));
+
 	// Focal point
 	debug!("Running");
 	let res = tokio::select! {
@@ -46,9 +52,13 @@ pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
 		res = services.poll() => handle_services_poll(server, res, listener).await,
 	};
 
-	// Join the signal handler before we leave.
+	// Join the signal handler and watchdog before we leave.
 	sigs.abort();
 	_ = sigs.await;
+	if let Some(watchdog) = watchdog {
+		watchdog.abort();
+		_ = watchdog.await;
+	}
 
 	// Remove the admin room callback
 	admin::fini(&services.admin).await;
@@ -64,9 +74,7 @@ pub(crate) async fn start(server: Arc<Server>) -> Result<Arc<Services>> {
 
 	let services = Services::build(server).await?.start().await?;
 
-	#[cfg(all(feature = "systemd", target_os = "linux"))]
-	sd_notify::notify(true, &[sd_notify::NotifyState::Ready])
-		.expect("failed to notify systemd of ready state");
+	systemd::ready();
 
 	debug!("Started");
 	Ok(services)
@@ -76,6 +84,7 @@ pub(crate) async fn start(server: Arc<Server>) -> Result<Arc<Services>> {
 #[tracing::instrument(skip_all)]
 pub(crate) async fn stop(services: Arc<Services>) -> Result<()> {
 	debug!("Shutting down...");
+	systemd::status("Shutting down");
 
 	// Wait for all completions before dropping or we'll lose them to the module
 	// unload and explode.
@@ -100,9 +109,7 @@ pub(crate) async fn stop(services: Arc<Services>) -> Result<()> {
 		);
 	}
 
-	#[cfg(all(feature = "systemd", target_os = "linux"))]
-	sd_notify::notify(true, &[sd_notify::NotifyState::Stopping])
-		.expect("failed to notify systemd of stopping state");
+	systemd::stopping();
 
 	info!("Shutdown complete.");
 	Ok(())
@@ -117,6 +124,17 @@ async fn signal(server: Arc<Server>, tx: Sender<()>, handle: axum_server::Handle
 		.await;
 }
 
+/// Pings the service manager's watchdog at half the required interval for as
+/// long as the server is running.
+#[tracing::instrument(skip_all)]
+async fn watchdog(server: Arc<Server>, interval: Duration) {
+	let mut ticks = tokio::time::interval(interval.checked_div(2).unwrap_or(interval));
+	while server.running() {
+		ticks.tick().await;
+		systemd::watchdog();
+	}
+}
+
 async fn handle_shutdown(server: Arc<Server>, tx: Sender<()>, handle: axum_server::Handle) {
 	if let Err(e) = tx.send(()) {
 		error!("failed sending shutdown transaction to channel: {e}");
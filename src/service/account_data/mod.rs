@@ -17,7 +17,7 @@ use ruma::{
 };
 use serde::Deserialize;
 
-use crate::{globals, Dep};
+use crate::{globals, pusher, Dep};
 
 pub struct Service {
 	services: Services,
@@ -31,6 +31,7 @@ struct Data {
 
 struct Services {
 	globals: Dep<globals::Service>,
+	pusher: Dep<pusher::Service>,
 }
 
 impl crate::Service for Service {
@@ -38,6 +39,7 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
+				pusher: args.depend::<pusher::Service>("pusher"),
 			},
 			db: Data {
 				roomuserdataid_accountdata: args.db["roomuserdataid_accountdata"].clone(),
@@ -79,9 +81,38 @@ pub async fn update(
 		self.db.roomuserdataid_accountdata.remove(&prev);
 	}
 
+	if room_id.is_none()
+		&& event_type.to_string() == GlobalAccountDataEventType::PushRules.to_string()
+	{
+		self.services.pusher.invalidate_user_push_index(user_id);
+	}
+
 	Ok(())
 }
 
+/// Removes all of the user's account data, in a room or globally.
+#[implement(Service)]
+pub async fn delete_all(&self, room_id: Option<&RoomId>, user_id: &UserId) {
+	let prefix = (room_id, user_id, Interfix);
+	self.db
+		.roomuserdataid_accountdata
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| self.db.roomuserdataid_accountdata.remove(key))
+		.await;
+
+	self.db
+		.roomusertype_roomuserdataid
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| self.db.roomusertype_roomuserdataid.remove(key))
+		.await;
+
+	if room_id.is_none() {
+		self.services.pusher.invalidate_user_push_index(user_id);
+	}
+}
+
 /// Searches the room account data for a specific kind.
 #[implement(Service)]
 pub async fn get_global<T>(&self, user_id: &UserId, kind: GlobalAccountDataEventType) -> Result<T>
@@ -0,0 +1,347 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, debug_warn, err, implement, info,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+	},
+	Err, Result, Server,
+};
+use database::{Deserialized, Map};
+use futures::StreamExt;
+use ruma::{thirdparty::Medium, OwnedUserId, UserId};
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+use url::Url;
+
+use crate::{email, globals, threepid, users, Dep};
+
+/// Expires accounts a configured time after registration unless they are
+/// renewed, by the user through a link emailed to them or by an admin.
+pub struct Service {
+	interrupt: Notify,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	email: Dep<email::Service>,
+	globals: Dep<globals::Service>,
+	threepid: Dep<threepid::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	renewaltoken_userid: Arc<Map>,
+	userid_expiresat: Arc<Map>,
+	userid_renewaltoken: Arc<Map>,
+}
+
+/// Path of the conduwuit-specific endpoint renewal links point to.
+pub const RENEW_PATH: &str = "/_conduwuit/client/account_validity/renew";
+
+const RENEWAL_TOKEN_LENGTH: usize = 32;
+
+/// Stored expiry of accounts which never expire, telling them apart from
+/// accounts which were never given an expiry.
+const NEVER: u64 = u64::MAX;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				email: args.depend::<email::Service>("email"),
+				globals: args.depend::<globals::Service>("globals"),
+				threepid: args.depend::<threepid::Service>("threepid"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				renewaltoken_userid: args.db["renewaltoken_userid"].clone(),
+				userid_expiresat: args.db["userid_expiresat"].clone(),
+				userid_renewaltoken: args.db["userid_renewaltoken"].clone(),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "account_validity", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		if !self.is_enabled() || self.services.globals.is_read_only() {
+			return Ok(());
+		}
+
+		self.start_existing().await;
+
+		if !self.services.email.is_enabled() {
+			debug!("Account renewal emails are disabled");
+			return Ok(());
+		}
+
+		let mut i = interval(CHECK_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.send_due_renewal_emails().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether accounts expire, see `account_validity_period`.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn is_enabled(&self) -> bool {
+	self.services
+		.server
+		.config
+		.account_validity_period
+		.is_some()
+}
+
+/// Starts the validity period of a newly registered account.
+#[implement(Service)]
+pub async fn start(&self, user_id: &UserId) {
+	if let Some(expires_at) = self.next_expiry() {
+		self.set_expires_at(user_id, Some(expires_at)).await;
+	}
+}
+
+/// Starts the validity period of the local accounts which were registered
+/// while account expiry was disabled, so they expire one
+/// `account_validity_period` from now.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+async fn start_existing(&self) {
+	let users: Vec<OwnedUserId> = self
+		.services
+		.users
+		.list_local_users()
+		.ready_filter(|user_id| *user_id != self.services.globals.server_user)
+		.filter_map(|user_id| async move {
+			(!self.db.userid_expiresat.contains(user_id).await).then(|| user_id.to_owned())
+		})
+		.collect()
+		.await;
+
+	for user_id in &users {
+		self.start(user_id).await;
+	}
+
+	if !users.is_empty() {
+		info!("Started the validity period of {} existing accounts", users.len());
+	}
+}
+
+/// When the account expires, in milliseconds since the unix epoch. None for
+/// accounts which never expire.
+#[implement(Service)]
+pub async fn expires_at(&self, user_id: &UserId) -> Option<u64> {
+	self.db
+		.userid_expiresat
+		.get(user_id)
+		.await
+		.deserialized()
+		.ok()
+		.filter(|expires_at| *expires_at != NEVER)
+}
+
+/// Whether the account has expired and may only log out or ask for a renewal
+/// email until it is renewed.
+#[implement(Service)]
+pub async fn is_expired(&self, user_id: &UserId) -> bool {
+	self.is_enabled()
+		&& self
+			.expires_at(user_id)
+			.await
+			.is_some_and(|expires_at| expires_at <= utils::millis_since_unix_epoch())
+}
+
+/// Sets when the account expires, or makes it never expire. Any pending
+/// renewal link is invalidated.
+#[implement(Service)]
+pub async fn set_expires_at(&self, user_id: &UserId, expires_at: Option<u64>) {
+	self.remove_renewal_token(user_id).await;
+
+	self.db
+		.userid_expiresat
+		.raw_put(user_id, expires_at.unwrap_or(NEVER));
+}
+
+/// Renews the account for another `account_validity_period` from now,
+/// returning when it now expires.
+#[implement(Service)]
+pub async fn renew(&self, user_id: &UserId) -> Result<u64> {
+	let Some(expires_at) = self.next_expiry() else {
+		return Err!(FeatureDisabled("account_validity"));
+	};
+
+	self.set_expires_at(user_id, Some(expires_at)).await;
+	debug!(%user_id, expires_at, "Account renewed");
+
+	Ok(expires_at)
+}
+
+/// The account the renewal link with this token was sent for.
+#[implement(Service)]
+pub async fn renewal_user(&self, token: &str) -> Result<OwnedUserId> {
+	self.db
+		.renewaltoken_userid
+		.get(token)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(Forbidden("Invalid or already used renewal link."))))
+}
+
+/// Renews the account the renewal link with this token was sent for.
+#[implement(Service)]
+pub async fn renew_with_token(&self, token: &str) -> Result<(OwnedUserId, u64)> {
+	let user_id = self.renewal_user(token).await?;
+	let expires_at = self.renew(&user_id).await?;
+
+	Ok((user_id, expires_at))
+}
+
+/// Emails a renewal link to the user's email addresses, returning how many
+/// it was sent to.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn send_renewal_email(&self, user_id: &UserId) -> Result<usize> {
+	if !self.is_enabled() || !self.services.email.is_enabled() {
+		return Err!(FeatureDisabled("account_validity"));
+	}
+
+	let addresses: Vec<String> = self
+		.services
+		.threepid
+		.get_threepids(user_id)
+		.filter_map(|threepid| async move {
+			(threepid.medium == Medium::Email).then_some(threepid.address)
+		})
+		.collect()
+		.await;
+
+	if addresses.is_empty() {
+		return Err!(Request(NotFound(
+			"The account has no email address to send a renewal link to."
+		)));
+	}
+
+	let token = match self.db.userid_renewaltoken.get(user_id).await {
+		| Ok(token) => String::from_utf8_lossy(&token).into_owned(),
+		| Err(_) => {
+			let token = utils::random_string(RENEWAL_TOKEN_LENGTH);
+			self.db.userid_renewaltoken.insert(user_id, &token);
+			self.db.renewaltoken_userid.insert(&token, user_id);
+			token
+		},
+	};
+
+	let link = self.renewal_link(&token)?;
+	let body = format!(
+		"Hello,\n\nYour account {user_id} on {server_name} expires soon or has expired. To keep \
+		 using it, open the following link to renew it:\n\n{link}\n\nIf you no longer need the \
+		 account, you can safely ignore this email.\n",
+		server_name = self.services.server.name,
+	);
+
+	let mut sent: usize = 0;
+	for address in &addresses {
+		match self
+			.services
+			.email
+			.send(address, "Renew your account", body.clone())
+			.await
+		{
+			| Ok(()) => sent = sent.saturating_add(1),
+			| Err(e) => debug_warn!(%user_id, "Failed to send renewal email: {e}"),
+		}
+	}
+
+	if sent == 0 {
+		return Err!("Failed to send the renewal email to any of the account's addresses.");
+	}
+
+	Ok(sent)
+}
+
+/// Emails a renewal link to the users whose accounts expire within
+/// `account_validity_renew_at` and who were not sent one yet.
+#[implement(Service)]
+async fn send_due_renewal_emails(&self) {
+	let renew_at = self
+		.services
+		.server
+		.config
+		.account_validity_renew_at
+		.saturating_mul(1000);
+
+	let now = utils::millis_since_unix_epoch();
+	let due: Vec<OwnedUserId> = self
+		.db
+		.userid_expiresat
+		.stream()
+		.ignore_err()
+		.filter_map(|(user_id, expires_at): (&UserId, u64)| async move {
+			let due = expires_at > now && expires_at.saturating_sub(now) <= renew_at;
+			(due && !self.db.userid_renewaltoken.contains(user_id).await)
+				.then(|| user_id.to_owned())
+		})
+		.collect()
+		.await;
+
+	for user_id in due {
+		if let Err(e) = self.send_renewal_email(&user_id).await {
+			debug_warn!(%user_id, "Could not send account renewal email: {e}");
+		}
+	}
+}
+
+#[implement(Service)]
+fn next_expiry(&self) -> Option<u64> {
+	let period = self.services.server.config.account_validity_period?;
+
+	Some(utils::millis_since_unix_epoch().saturating_add(period.saturating_mul(1000)))
+}
+
+#[implement(Service)]
+async fn remove_renewal_token(&self, user_id: &UserId) {
+	if let Ok(token) = self.db.userid_renewaltoken.get(user_id).await {
+		self.db.renewaltoken_userid.remove(&*token);
+	}
+
+	self.db.userid_renewaltoken.remove(user_id);
+}
+
+#[implement(Service)]
+fn renewal_link(&self, token: &str) -> Result<Url> {
+	let config = &self.services.server.config;
+	let mut link = match config.well_known.client.clone() {
+		| Some(url) => url,
+		| None => Url::parse(&format!("https://{}", self.services.server.name))
+			.map_err(|e| err!("Failed to build renewal link: {e}"))?,
+	};
+
+	link.set_path(RENEW_PATH);
+	link.query_pairs_mut().append_pair("token", token);
+
+	Ok(link)
+}
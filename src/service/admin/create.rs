@@ -14,7 +14,7 @@ use ruma::{
 		preview_url::RoomPreviewUrlsEventContent,
 		topic::RoomTopicEventContent,
 	},
-	RoomId, RoomVersionId,
+	RoomVersionId,
 };
 
 use crate::Services;
@@ -24,7 +24,7 @@ use crate::Services;
 /// Users in this room are considered admins by conduit, and the room can be
 /// used to issue admin commands by talking to the server user inside it.
 pub async fn create_admin_room(services: &Services) -> Result<()> {
-	let room_id = RoomId::new(services.globals.server_name());
+	let room_id = services.rooms.metadata.generate_room_id().await?;
 	let room_version = &services.server.config.default_room_version;
 
 	let _short_id = services
@@ -0,0 +1,190 @@
+use std::fmt::{self, Display, Write};
+
+use conduwuit::{implement, Result};
+use futures::{future::ready, pin_mut, StreamExt};
+use ruma::{api::appservice::Registration, OwnedUserId, UserId};
+
+use super::RegistrationInfo;
+
+/// How many of the matching local users or aliases are named in a warning.
+const EXAMPLES: usize = 5;
+
+/// Problems with a registration's namespaces. Conflicts route the same users,
+/// aliases or tokens to two appservices and refuse the registration; warnings
+/// are only reported.
+#[derive(Debug, Default)]
+pub struct NamespaceReport {
+	pub conflicts: Vec<String>,
+	pub warnings: Vec<String>,
+}
+
+impl NamespaceReport {
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.conflicts.is_empty() && self.warnings.is_empty() }
+}
+
+impl Display for NamespaceReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for conflict in &self.conflicts {
+			writeln!(f, "- Conflict: {conflict}")?;
+		}
+
+		for warning in &self.warnings {
+			writeln!(f, "- Warning: {warning}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Checks the registration against the other registered appservices and the
+/// existing local users and aliases. The registration being replaced, if
+/// any, is not compared with.
+#[implement(super::Service)]
+pub async fn check_namespaces(&self, registration: &Registration) -> Result<NamespaceReport> {
+	let new = RegistrationInfo::try_from(registration.clone())?;
+	let new_sender = self.sender_user(&new)?;
+	let server_name = &self.services.server.name;
+
+	let mut report = NamespaceReport::default();
+	let others: Vec<RegistrationInfo> = self
+		.read()
+		.await
+		.values()
+		.filter(|other| other.registration.id != registration.id)
+		.cloned()
+		.collect();
+
+	for other in &others {
+		let id = &other.registration.id;
+		if other.registration.as_token == registration.as_token {
+			report
+				.conflicts
+				.push(format!("Uses the same as_token as appservice {id:?}."));
+		}
+
+		let other_sender = self.sender_user(other)?;
+		if other_sender == new_sender {
+			report
+				.conflicts
+				.push(format!("Uses the same sender_localpart as appservice {id:?}."));
+		}
+
+		if other.users.is_exclusive_match(new_sender.as_str()) {
+			report.conflicts.push(format!(
+				"Sender {new_sender} is in the exclusive user namespace of appservice {id:?}."
+			));
+		}
+
+		if new.users.is_exclusive_match(other_sender.as_str()) {
+			report.conflicts.push(format!(
+				"Exclusive user namespace claims {other_sender}, the sender of appservice \
+				 {id:?}."
+			));
+		}
+
+		let namespaces = [
+			("user", &registration.namespaces.users, &other.registration.namespaces.users),
+			(
+				"alias",
+				&registration.namespaces.aliases,
+				&other.registration.namespaces.aliases,
+			),
+			("room", &registration.namespaces.rooms, &other.registration.namespaces.rooms),
+		];
+
+		for (kind, ours, theirs) in namespaces {
+			for namespace in ours {
+				let overlaps = theirs.iter().any(|their| {
+					their.regex == namespace.regex && (their.exclusive || namespace.exclusive)
+				});
+
+				if overlaps {
+					report.conflicts.push(format!(
+						"The {kind} namespace {:?} is also registered by appservice {id:?}, \
+						 exclusively by at least one of them.",
+						namespace.regex
+					));
+				}
+			}
+		}
+	}
+
+	let mut users = Vec::new();
+	let mut shared_users = Vec::new();
+	let local_users = self.services.users.list_local_users();
+	pin_mut!(local_users);
+	while let Some(user_id) = local_users.next().await {
+		if user_id == new_sender || !new.users.is_exclusive_match(user_id.as_str()) {
+			continue;
+		}
+
+		if let Some(other) = others.iter().find(|other| other.is_user_match(user_id)) {
+			shared_users.push((user_id.to_owned(), other.registration.id.clone()));
+		} else {
+			users.push(user_id.to_owned());
+		}
+	}
+
+	for (user_id, id) in shared_users.iter().take(EXAMPLES) {
+		report.conflicts.push(format!(
+			"Local user {user_id} is in the exclusive user namespace and also belongs to \
+			 appservice {id:?}."
+		));
+	}
+
+	if !users.is_empty() {
+		report.warnings.push(format!(
+			"{} existing local user(s) are in the exclusive user namespace, and can only be \
+			 used through the appservice from now on: {}",
+			users.len(),
+			examples(users.iter())
+		));
+	}
+
+	let aliases: Vec<String> = self
+		.services
+		.alias
+		.all_local_aliases()
+		.map(|(_, localpart)| format!("#{localpart}:{server_name}"))
+		.filter(|alias| ready(new.aliases.is_exclusive_match(alias)))
+		.collect()
+		.await;
+
+	if !aliases.is_empty() {
+		report.warnings.push(format!(
+			"{} existing local alias(es) are in the exclusive alias namespace: {}",
+			aliases.len(),
+			examples(aliases.iter())
+		));
+	}
+
+	Ok(report)
+}
+
+#[implement(super::Service)]
+fn sender_user(&self, info: &RegistrationInfo) -> Result<OwnedUserId> {
+	UserId::parse_with_server_name(
+		info.registration.sender_localpart.as_str(),
+		&self.services.server.name,
+	)
+	.map_err(Into::into)
+}
+
+fn examples<T: Display>(items: impl Iterator<Item = T>) -> String {
+	let mut out = String::new();
+	for (i, item) in items.enumerate() {
+		if i == EXAMPLES {
+			out.push_str(", ...");
+			break;
+		}
+
+		if i > 0 {
+			out.push_str(", ");
+		}
+
+		write!(out, "{item}").expect("write to string");
+	}
+
+	out
+}
@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use conduwuit::{debug_warn, implement};
+use ruma::{
+	api::appservice::keys::claim_keys::{self, v1::OneTimeKeys},
+	OneTimeKeyAlgorithm, OwnedDeviceId, OwnedUserId,
+};
+
+use super::RegistrationInfo;
+
+/// Asks the appservices which serve their users' one-time keys (MSC3983) for
+/// keys of the requested devices. Only local users are expected; devices an
+/// appservice returned nothing for are left to be claimed from the uploaded
+/// keys by the caller.
+#[implement(super::Service)]
+pub async fn claim_one_time_keys(
+	&self,
+	one_time_keys: &BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, OneTimeKeyAlgorithm>>,
+) -> OneTimeKeys {
+	let appservices: Vec<RegistrationInfo> = self
+		.read()
+		.await
+		.values()
+		.filter(|appservice| appservice.claim_one_time_keys)
+		.cloned()
+		.collect();
+
+	let mut claimed = OneTimeKeys::new();
+	for appservice in &appservices {
+		let request: BTreeMap<_, _> = one_time_keys
+			.iter()
+			.filter(|(user_id, _)| appservice.is_exclusive_user_match(user_id))
+			.map(|(user_id, devices)| {
+				let devices = devices
+					.iter()
+					.map(|(device_id, algorithm)| (device_id.clone(), vec![algorithm.clone()]))
+					.collect();
+
+				(user_id.clone(), devices)
+			})
+			.collect();
+
+		if request.is_empty() {
+			continue;
+		}
+
+		match self
+			.services
+			.sending
+			.send_appservice_request(appservice.registration.clone(), claim_keys::v1::Request {
+				one_time_keys: request,
+			})
+			.await
+		{
+			| Ok(Some(response)) => claimed.extend(response.one_time_keys),
+			| Ok(None) => {},
+			| Err(e) => debug_warn!(
+				id = %appservice.registration.id,
+				"Failed to claim one-time keys from appservice: {e}"
+			),
+		}
+	}
+
+	claimed
+}
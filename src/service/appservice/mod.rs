@@ -1,26 +1,41 @@
+mod conflicts;
+mod keys;
 mod namespace_regex;
 mod registration_info;
+mod reload;
+mod users;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use conduwuit::{err, utils::stream::TryIgnore, Result};
+use conduwuit::{err, error, info, utils::stream::TryIgnore, Result, Server};
 use database::Map;
 use futures::{Future, StreamExt, TryStreamExt};
 use ruma::{api::appservice::Registration, RoomAliasId, RoomId, UserId};
-use tokio::sync::RwLock;
+use tokio::{
+	sync::{Notify, RwLock},
+	time::{interval, MissedTickBehavior},
+};
 
-pub use self::{namespace_regex::NamespaceRegex, registration_info::RegistrationInfo};
-use crate::{sending, Dep};
+pub use self::{
+	conflicts::NamespaceReport, namespace_regex::NamespaceRegex,
+	registration_info::RegistrationInfo, reload::ReloadSummary,
+};
+use crate::{rooms, sending, users, Dep};
 
 pub struct Service {
 	registration_info: RwLock<BTreeMap<String, RegistrationInfo>>,
+	interrupt: Notify,
 	services: Services,
 	db: Data,
 }
 
 struct Services {
+	server: Arc<Server>,
+	alias: Dep<rooms::alias::Service>,
 	sending: Dep<sending::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	users: Dep<users::Service>,
 }
 
 struct Data {
@@ -32,8 +47,13 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			registration_info: RwLock::new(BTreeMap::new()),
+			interrupt: Notify::new(),
 			services: Services {
+				server: args.server.clone(),
+				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
 				sending: args.depend::<sending::Service>("sending"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				users: args.depend::<users::Service>("users"),
 			},
 			db: Data {
 				id_appserviceregistrations: args.db["id_appserviceregistrations"].clone(),
@@ -43,19 +63,42 @@ impl crate::Service for Service {
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
 		// Inserting registrations into cache
-		for appservice in self.iter_db_ids().await? {
-			self.registration_info.write().await.insert(
-				appservice.0,
-				appservice
-					.1
-					.try_into()
-					.expect("Should be validated on registration"),
-			);
+		self.reload().await?;
+
+		let config = &self.services.server.config;
+		let period = config.appservice_registration_watch_interval;
+		if period == 0 || config.appservice_registration_dir.is_none() {
+			return Ok(());
+		}
+
+		let period = Duration::from_secs(period);
+		let mut i = interval(period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.reset_after(period);
+		let mut modified = self.registration_files_modified();
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			let current = self.registration_files_modified();
+			if current == modified {
+				continue;
+			}
+
+			modified = current;
+			match self.reload().await {
+				| Ok(summary) => info!(?summary, "Reloaded appservice registrations"),
+				| Err(e) => error!("Failed to reload appservice registrations: {e}"),
+			}
 		}
 
 		Ok(())
 	}
 
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -67,15 +110,22 @@ impl Service {
 		appservice_config_body: &str,
 	) -> Result {
 		//TODO: Check for collisions between exclusive appservice namespaces
+		let info = RegistrationInfo::try_from(registration.clone())?
+			.with_config_body(appservice_config_body.as_bytes());
+
 		self.registration_info
 			.write()
 			.await
-			.insert(registration.id.clone(), registration.clone().try_into()?);
+			.insert(registration.id.clone(), info);
 
 		self.db
 			.id_appserviceregistrations
 			.insert(&registration.id, appservice_config_body);
 
+		self.services
+			.sending
+			.reset_appservice_device_lists(&registration.id);
+
 		Ok(())
 	}
 
@@ -94,6 +144,9 @@ impl Service {
 
 		// remove the appservice from the database
 		self.db.id_appserviceregistrations.del(appservice_id);
+		self.services
+			.sending
+			.reset_appservice_device_lists(appservice_id);
 
 		// deletes all active requests for the appservice if there are any so we stop
 		// sending to the URL
@@ -112,6 +165,10 @@ impl Service {
 			.map(|info| info.registration)
 	}
 
+	pub async fn get_registration_info(&self, id: &str) -> Option<RegistrationInfo> {
+		self.registration_info.read().await.get(id).cloned()
+	}
+
 	pub async fn iter_ids(&self) -> Vec<String> {
 		self.registration_info
 			.read()
@@ -1,5 +1,6 @@
 use conduwuit::Result;
 use ruma::{api::appservice::Registration, UserId};
+use serde::Deserialize;
 
 use super::NamespaceRegex;
 
@@ -10,6 +11,36 @@ pub struct RegistrationInfo {
 	pub users: NamespaceRegex,
 	pub aliases: NamespaceRegex,
 	pub rooms: NamespaceRegex,
+	/// Whether receipts, presence and typing are pushed to the appservice in
+	/// its transactions (MSC2409).
+	pub receive_ephemeral: bool,
+	/// Whether device list changes and one-time key counts of the
+	/// appservice's users are included in its transactions (MSC3202).
+	pub receive_device_keys: bool,
+	/// Whether one-time keys of the appservice's users are claimed from the
+	/// appservice itself (MSC3983).
+	pub claim_one_time_keys: bool,
+}
+
+/// The registration keys which opt an appservice into ephemeral events and
+/// encryption support. Older bridges still use the names from before MSC2409
+/// was stabilised.
+#[derive(Default, Deserialize)]
+struct ExtensionKeys {
+	#[serde(default)]
+	receive_ephemeral: bool,
+
+	#[serde(default)]
+	push_ephemeral: bool,
+
+	#[serde(default, rename = "de.sorunome.msc2409.push_ephemeral")]
+	msc2409_push_ephemeral: bool,
+
+	#[serde(default, rename = "org.matrix.msc3202")]
+	msc3202: bool,
+
+	#[serde(default, rename = "org.matrix.msc3983")]
+	msc3983: bool,
 }
 
 impl RegistrationInfo {
@@ -25,6 +56,19 @@ impl RegistrationInfo {
 		self.users.is_exclusive_match(user_id.as_str())
 			|| self.registration.sender_localpart == user_id.localpart()
 	}
+
+	/// Also honors the opt-in keys from the registration file which aren't
+	/// part of the parsed registration.
+	#[must_use]
+	pub(super) fn with_config_body(mut self, body: &[u8]) -> Self {
+		let keys: ExtensionKeys = serde_yaml::from_slice(body).unwrap_or_default();
+		self.receive_ephemeral |=
+			keys.receive_ephemeral || keys.push_ephemeral || keys.msc2409_push_ephemeral;
+		self.receive_device_keys = keys.msc3202;
+		self.claim_one_time_keys = keys.msc3983;
+
+		self
+	}
 }
 
 impl TryFrom<Registration> for RegistrationInfo {
@@ -35,6 +79,9 @@ impl TryFrom<Registration> for RegistrationInfo {
 			users: value.namespaces.users.clone().try_into()?,
 			aliases: value.namespaces.aliases.clone().try_into()?,
 			rooms: value.namespaces.rooms.clone().try_into()?,
+			receive_ephemeral: value.receive_ephemeral,
+			receive_device_keys: false,
+			claim_one_time_keys: false,
 			registration: value,
 		})
 	}
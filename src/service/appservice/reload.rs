@@ -0,0 +1,156 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use conduwuit::{implement, warn, Result};
+use ruma::api::appservice::Registration;
+
+use super::RegistrationInfo;
+
+/// The appservices which reloading the registrations added, removed or
+/// changed.
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+	pub changed: Vec<String>,
+}
+
+impl ReloadSummary {
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+	}
+}
+
+/// Re-reads the registrations from the database and from
+/// `appservice_registration_dir`, recompiling their namespaces, and applies
+/// the differences to the registered appservices.
+#[implement(super::Service)]
+pub async fn reload(&self) -> Result<ReloadSummary> {
+	let mut registrations = BTreeMap::new();
+	for (id, registration) in self.iter_db_ids().await? {
+		let body = self.db.id_appserviceregistrations.get(&id).await?;
+		let info = RegistrationInfo::try_from(registration)?.with_config_body(&body);
+		registrations.insert(id, info);
+	}
+
+	for (path, body) in self.registration_files()? {
+		let info = match parse_registration(&body) {
+			| Ok(info) => info,
+			| Err(e) => {
+				warn!("Skipping appservice registration {path:?}: {e}");
+				continue;
+			},
+		};
+
+		let id = info.registration.id.clone();
+		if registrations.contains_key(&id) {
+			warn!("Skipping appservice registration {path:?}: {id:?} is already registered");
+			continue;
+		}
+
+		registrations.insert(id, info);
+	}
+
+	let mut summary = ReloadSummary::default();
+	{
+		let mut current = self.registration_info.write().await;
+		for (id, info) in &registrations {
+			match current.get(id) {
+				| None => summary.added.push(id.clone()),
+				| Some(old) if !same_registration(old, info) => summary.changed.push(id.clone()),
+				| Some(_) => {},
+			}
+		}
+
+		summary.removed = current
+			.keys()
+			.filter(|id| !registrations.contains_key(*id))
+			.cloned()
+			.collect();
+
+		*current = registrations;
+	}
+
+	for id in &summary.removed {
+		self.services
+			.sending
+			.cleanup_events(Some(id), None, None)
+			.await?;
+	}
+
+	for id in summary.changed.iter().chain(&summary.removed) {
+		self.services.sending.reset_appservice_device_lists(id);
+	}
+
+	if !summary.is_empty() {
+		self.services.state_cache.clear_appservice_in_room_cache();
+	}
+
+	Ok(summary)
+}
+
+/// The modification times of the files in `appservice_registration_dir`, to
+/// notice when they change.
+#[implement(super::Service)]
+pub(super) fn registration_files_modified(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+	let Some(dir) = &self.services.server.config.appservice_registration_dir else {
+		return Vec::new();
+	};
+
+	registration_paths(dir)
+		.unwrap_or_default()
+		.into_iter()
+		.map(|path| {
+			let modified = fs::metadata(&path)
+				.and_then(|metadata| metadata.modified())
+				.ok();
+
+			(path, modified)
+		})
+		.collect()
+}
+
+#[implement(super::Service)]
+fn registration_files(&self) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+	let Some(dir) = &self.services.server.config.appservice_registration_dir else {
+		return Ok(Vec::new());
+	};
+
+	registration_paths(dir)?
+		.into_iter()
+		.map(|path| Ok((path.clone(), fs::read(&path)?)))
+		.collect()
+}
+
+fn registration_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+	let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| {
+			path.extension()
+				.is_some_and(|extension| extension == "yaml" || extension == "yml")
+		})
+		.collect();
+
+	paths.sort();
+	Ok(paths)
+}
+
+fn parse_registration(body: &[u8]) -> Result<RegistrationInfo> {
+	let registration: Registration = serde_yaml::from_slice(body)?;
+	let info = RegistrationInfo::try_from(registration)?.with_config_body(body);
+
+	Ok(info)
+}
+
+fn same_registration(a: &RegistrationInfo, b: &RegistrationInfo) -> bool {
+	a.receive_ephemeral == b.receive_ephemeral
+		&& a.receive_device_keys == b.receive_device_keys
+		&& serde_yaml::to_string(&a.registration).ok()
+			== serde_yaml::to_string(&b.registration).ok()
+}
@@ -0,0 +1,24 @@
+use conduwuit::{err, implement, utils::ReadyExt, Result};
+use futures::StreamExt;
+use ruma::OwnedUserId;
+
+/// The local users in the appservice's exclusive user namespace, such as a
+/// bridge's ghost users, including its sender user.
+#[implement(super::Service)]
+pub async fn exclusive_users(&self, appservice_id: &str) -> Result<Vec<OwnedUserId>> {
+	let info = self
+		.get_registration_info(appservice_id)
+		.await
+		.ok_or_else(|| err!(Request(NotFound("Appservice not found"))))?;
+
+	let users = self
+		.services
+		.users
+		.stream()
+		.ready_filter(|user_id| info.is_exclusive_user_match(user_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	Ok(users)
+}
@@ -1,9 +1,10 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use conduwuit::{err, implement, trace, Config, Result};
+use conduwuit::{config::FederationDestinationOptions, err, implement, trace, Config, Result};
 use either::Either;
 use ipaddress::IPAddress;
 use reqwest::redirect;
+use ruma::{OwnedServerName, ServerName};
 
 use crate::{resolver, service};
 
@@ -18,6 +19,11 @@ pub struct Service {
 	pub appservice: reqwest::Client,
 	pub pusher: reqwest::Client,
 
+	/// Clients with their own connection pools for the servers configured in
+	/// `federation_destination_options`.
+	pub federation_destinations: HashMap<OwnedServerName, reqwest::Client>,
+	pub sender_destinations: HashMap<OwnedServerName, reqwest::Client>,
+
 	pub cidr_range_denylist: Vec<IPAddress>,
 }
 
@@ -64,13 +70,7 @@ impl crate::Service for Service {
 				.redirect(redirect::Policy::limited(4))
 				.build()?,
 
-			federation: base(config)?
-				.dns_resolver(resolver.resolver.hooked.clone())
-				.read_timeout(Duration::from_secs(config.federation_timeout))
-				.pool_max_idle_per_host(config.federation_idle_per_host.into())
-				.pool_idle_timeout(Duration::from_secs(config.federation_idle_timeout))
-				.redirect(redirect::Policy::limited(3))
-				.build()?,
+			federation: federation(config, &resolver, &FederationDestinationOptions::default())?,
 
 			synapse: base(config)?
 				.dns_resolver(resolver.resolver.hooked.clone())
@@ -79,14 +79,7 @@ impl crate::Service for Service {
 				.redirect(redirect::Policy::limited(3))
 				.build()?,
 
-			sender: base(config)?
-				.dns_resolver(resolver.resolver.hooked.clone())
-				.read_timeout(Duration::from_secs(config.sender_timeout))
-				.timeout(Duration::from_secs(config.sender_timeout))
-				.pool_max_idle_per_host(1)
-				.pool_idle_timeout(Duration::from_secs(config.sender_idle_timeout))
-				.redirect(redirect::Policy::limited(2))
-				.build()?,
+			sender: sender(config, &resolver, &FederationDestinationOptions::default())?,
 
 			appservice: base(config)?
 				.dns_resolver(resolver.resolver.clone())
@@ -105,6 +98,20 @@ impl crate::Service for Service {
 				.redirect(redirect::Policy::limited(2))
 				.build()?,
 
+			federation_destinations: config
+				.federation_destination_options
+				.iter()
+				.map(|(dest, options)| {
+					Ok((dest.clone(), federation(config, &resolver, options)?))
+				})
+				.collect::<Result<_>>()?,
+
+			sender_destinations: config
+				.federation_destination_options
+				.iter()
+				.map(|(dest, options)| Ok((dest.clone(), sender(config, &resolver, options)?)))
+				.collect::<Result<_>>()?,
+
 			cidr_range_denylist: config
 				.ip_range_denylist
 				.iter()
@@ -118,6 +125,63 @@ impl crate::Service for Service {
 	fn name(&self) -> &str { service::make_name(std::module_path!()) }
 }
 
+impl Service {
+	/// The client for federation requests to `dest`.
+	#[inline]
+	#[must_use]
+	pub fn federation_for(&self, dest: &ServerName) -> &reqwest::Client {
+		self.federation_destinations
+			.get(dest)
+			.unwrap_or(&self.federation)
+	}
+
+	/// The client for sending transactions to `dest`.
+	#[inline]
+	#[must_use]
+	pub fn sender_for(&self, dest: &ServerName) -> &reqwest::Client {
+		self.sender_destinations.get(dest).unwrap_or(&self.sender)
+	}
+}
+
+fn federation(
+	config: &Config,
+	resolver: &resolver::Service,
+	options: &FederationDestinationOptions,
+) -> Result<reqwest::Client> {
+	let idle_per_host = options
+		.idle_per_host
+		.unwrap_or(config.federation_idle_per_host);
+	let idle_timeout = options
+		.idle_timeout
+		.unwrap_or(config.federation_idle_timeout);
+
+	Ok(base(config)?
+		.dns_resolver(resolver.resolver.hooked.clone())
+		.read_timeout(Duration::from_secs(config.federation_timeout))
+		.pool_max_idle_per_host(idle_per_host.into())
+		.pool_idle_timeout(Duration::from_secs(idle_timeout))
+		.redirect(redirect::Policy::limited(3))
+		.build()?)
+}
+
+fn sender(
+	config: &Config,
+	resolver: &resolver::Service,
+	options: &FederationDestinationOptions,
+) -> Result<reqwest::Client> {
+	let idle_per_host = options.idle_per_host.unwrap_or(1);
+	let idle_timeout = options.idle_timeout.unwrap_or(config.sender_idle_timeout);
+
+	Ok(base(config)?
+		.dns_resolver(resolver.resolver.hooked.clone())
+		.read_timeout(Duration::from_secs(config.sender_timeout))
+		.timeout(Duration::from_secs(config.sender_timeout))
+		.pool_max_idle_per_host(idle_per_host.into())
+		.pool_idle_timeout(Duration::from_secs(idle_timeout))
+		.redirect(redirect::Policy::limited(2))
+		.build()?)
+}
+
 fn base(config: &Config) -> Result<reqwest::ClientBuilder> {
 	let mut builder = reqwest::Client::builder()
 		.hickory_dns(true)
@@ -0,0 +1,337 @@
+use std::{
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{debug, debug_info, err, implement, utils, Err, Result, Server};
+use lru_cache::LruCache;
+use ruma::{
+	events::{
+		push_rules::{PushRulesEvent, PushRulesEventContent},
+		GlobalAccountDataEventType,
+	},
+	OwnedDeviceId, OwnedUserId, UserId,
+};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::{account_data, client, globals, pusher, users, Dep};
+
+pub struct Service {
+	services: Services,
+	introspection_endpoint: RwLock<Option<Url>>,
+	cache: Mutex<LruCache<String, Introspected>>,
+	rejected: Mutex<LruCache<String, Instant>>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	client: Dep<client::Service>,
+	globals: Dep<globals::Service>,
+	pusher: Dep<pusher::Service>,
+	users: Dep<users::Service>,
+}
+
+#[derive(Clone)]
+struct Introspected {
+	user_id: OwnedUserId,
+	device_id: OwnedDeviceId,
+	expires_at: Instant,
+}
+
+/// Subset of an OAuth 2.0 token introspection response (RFC 7662).
+#[derive(Deserialize)]
+struct Introspection {
+	active: bool,
+
+	#[serde(default)]
+	scope: String,
+
+	username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+	introspection_endpoint: Url,
+}
+
+/// Scope granting access to the client-server API.
+const API_SCOPE: &str = "urn:matrix:org.matrix.msc2967.client:api:*";
+
+/// Scope prefix carrying the device ID a token was issued for.
+const DEVICE_SCOPE_PREFIX: &str = "urn:matrix:org.matrix.msc2967.client:device:";
+
+const CACHE_CAPACITY: usize = 1024;
+
+/// How long tokens the provider did not accept are refused without asking it
+/// again, so unknown tokens cannot be used to flood the provider.
+const REJECTED_CACHE_TTL: Duration = Duration::from_secs(60);
+
+const DEVICE_TOKEN_LENGTH: usize = 32;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				client: args.depend::<client::Service>("client"),
+				globals: args.depend::<globals::Service>("globals"),
+				pusher: args.depend::<pusher::Service>("pusher"),
+				users: args.depend::<users::Service>("users"),
+			},
+			introspection_endpoint: RwLock::new(
+				args.server
+					.config
+					.delegated_auth
+					.introspection_endpoint
+					.clone(),
+			),
+			cache: Mutex::new(LruCache::new(CACHE_CAPACITY)),
+			rejected: Mutex::new(LruCache::new(CACHE_CAPACITY)),
+		}))
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let cache = self.cache.lock()?;
+		writeln!(out, "introspection_cache: {} / {}", cache.len(), cache.capacity())?;
+
+		let rejected = self.rejected.lock()?;
+		writeln!(out, "rejected_token_cache: {} / {}", rejected.len(), rejected.capacity())?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether authentication is delegated to an OpenID Connect provider
+/// (MSC3861).
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.services.server.config.delegated_auth.is_enabled() }
+
+/// Validates an access token issued by the provider, returning the user and
+/// device it belongs to, or None if the token is not active. The user and
+/// device are created locally on first use. Tokens of deactivated or suspended
+/// accounts are refused.
+#[implement(Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn introspect(&self, token: &str) -> Result<Option<(OwnedUserId, OwnedDeviceId)>> {
+	let now = Instant::now();
+	let cached = self
+		.cache
+		.lock()
+		.expect("locked")
+		.get_mut(token)
+		.filter(|cached| cached.expires_at > now)
+		.cloned();
+
+	if let Some(cached) = cached {
+		self.check_user(&cached.user_id).await?;
+		return Ok(Some((cached.user_id, cached.device_id)));
+	}
+
+	if self
+		.rejected
+		.lock()
+		.expect("locked")
+		.get_mut(token)
+		.is_some_and(|expires_at| *expires_at > now)
+	{
+		return Ok(None);
+	}
+
+	let Some((user_id, device_id)) = self.request_introspection(token).await? else {
+		if let Some(expires_at) = now.checked_add(REJECTED_CACHE_TTL) {
+			self.rejected
+				.lock()
+				.expect("locked")
+				.insert(token.to_owned(), expires_at);
+		}
+
+		return Ok(None);
+	};
+
+	self.check_user(&user_id).await?;
+	self.provision(&user_id, &device_id).await?;
+
+	let ttl = Duration::from_secs(
+		self.services
+			.server
+			.config
+			.delegated_auth
+			.introspection_cache_ttl,
+	);
+
+	if let Some(expires_at) = now.checked_add(ttl) {
+		self.cache
+			.lock()
+			.expect("locked")
+			.insert(token.to_owned(), Introspected {
+				user_id: user_id.clone(),
+				device_id: device_id.clone(),
+				expires_at,
+			});
+	}
+
+	Ok(Some((user_id, device_id)))
+}
+
+/// Asks the provider about an access token, returning the user and device it
+/// was issued for or None if it is not active for the client-server API.
+#[implement(Service)]
+async fn request_introspection(
+	&self,
+	token: &str,
+) -> Result<Option<(OwnedUserId, OwnedDeviceId)>> {
+	let config = &self.services.server.config.delegated_auth;
+	let (Some(client_id), Some(client_secret)) =
+		(config.client_id.as_deref(), config.client_secret.as_deref())
+	else {
+		return Ok(None);
+	};
+
+	let body = self
+		.services
+		.client
+		.default
+		.post(self.introspection_endpoint().await?)
+		.basic_auth(client_id, Some(client_secret))
+		.form(&[("token", token), ("token_type_hint", "access_token")])
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let introspection: Introspection = serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid token introspection response: {e}")))?;
+
+	if !introspection.active {
+		return Ok(None);
+	}
+
+	let scopes: Vec<&str> = introspection.scope.split(' ').collect();
+	if !scopes.contains(&API_SCOPE) {
+		debug!("Token is not scoped for the client-server API");
+		return Ok(None);
+	}
+
+	let Some(device_id) = scopes
+		.iter()
+		.find_map(|scope| scope.strip_prefix(DEVICE_SCOPE_PREFIX))
+		.filter(|device_id| !device_id.is_empty())
+	else {
+		debug!("Token is not scoped for a device");
+		return Ok(None);
+	};
+
+	let Some(username) = introspection.username else {
+		return Err!(BadServerResponse("Token introspection response has no username."));
+	};
+
+	let user_id = UserId::parse_with_server_name(username, self.services.globals.server_name())
+		.map_err(|e| {
+		err!(BadServerResponse("Invalid username in token introspection: {e}"))
+	})?;
+
+	Ok(Some((user_id, device_id.into())))
+}
+
+/// Refuses tokens of local accounts which were deactivated or suspended.
+#[implement(Service)]
+async fn check_user(&self, user_id: &UserId) -> Result {
+	let users = &self.services.users;
+	if users.exists(user_id).await && users.is_deactivated(user_id).await? {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	if users.is_suspended(user_id).await {
+		return Err!(Request(UserSuspended("Your account has been suspended.")));
+	}
+
+	Ok(())
+}
+
+/// Creates the local user and device for a token from the provider if they do
+/// not exist yet.
+#[implement(Service)]
+async fn provision(&self, user_id: &UserId, device_id: &OwnedDeviceId) -> Result {
+	let users = &self.services.users;
+	if !users.exists(user_id).await {
+		users.create_external(user_id);
+		users.set_displayname(user_id, Some(user_id.localpart().to_owned()));
+		self.services
+			.account_data
+			.update(
+				None,
+				user_id,
+				GlobalAccountDataEventType::PushRules.to_string().into(),
+				&serde_json::to_value(PushRulesEvent {
+					content: PushRulesEventContent {
+						global: self.services.pusher.server_default_rules(user_id),
+					},
+				})
+				.expect("to json always works"),
+			)
+			.await?;
+
+		debug_info!(%user_id, "Created user for delegated authentication");
+	}
+
+	if users.get_device_metadata(user_id, device_id).await.is_err() {
+		// The device's own token is never handed out; requests authenticate with
+		// the provider's tokens.
+		let token = utils::random_string(DEVICE_TOKEN_LENGTH);
+		users
+			.create_device(user_id, device_id, &token, None, None)
+			.await?;
+
+		debug_info!(%user_id, %device_id, "Created device for delegated authentication");
+	}
+
+	Ok(())
+}
+
+/// The configured introspection endpoint, or the one discovered from the
+/// issuer, which is cached once found.
+#[implement(Service)]
+async fn introspection_endpoint(&self) -> Result<Url> {
+	if let Some(endpoint) = self.introspection_endpoint.read().await.as_ref() {
+		return Ok(endpoint.clone());
+	}
+
+	let issuer = self
+		.services
+		.server
+		.config
+		.delegated_auth
+		.issuer
+		.as_ref()
+		.ok_or_else(|| err!(Request(Unrecognized("Authentication is not delegated."))))?;
+
+	let url =
+		format!("{}/.well-known/openid-configuration", issuer.as_str().trim_end_matches('/'));
+	let body = self
+		.services
+		.client
+		.default
+		.get(url)
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let discovery: Discovery = serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid OpenID Connect discovery document: {e}")))?;
+
+	*self.introspection_endpoint.write().await = Some(discovery.introspection_endpoint.clone());
+
+	Ok(discovery.introspection_endpoint)
+}
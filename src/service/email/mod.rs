@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use conduwuit::{debug, err, implement, Err, Result};
+use lettre::{
+	message::{header::ContentType, Mailbox},
+	AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+pub struct Service {
+	transport: Option<Transport>,
+	sender: Option<Mailbox>,
+}
+
+type Transport = AsyncSmtpTransport<Tokio1Executor>;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config.smtp;
+
+		let transport = config
+			.connection_uri
+			.as_deref()
+			.map(Transport::from_url)
+			.transpose()
+			.map_err(|e| err!(Config("smtp.connection_uri", "Invalid SMTP URI: {e}")))?
+			.map(|builder| builder.build());
+
+		let sender = config
+			.sender
+			.as_deref()
+			.map(str::parse::<Mailbox>)
+			.transpose()
+			.map_err(|e| err!(Config("smtp.sender", "Invalid sender mailbox: {e}")))?;
+
+		Ok(Arc::new(Self { transport, sender }))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether an SMTP server is configured and emails can be sent.
+#[implement(Service)]
+#[inline]
+pub fn is_enabled(&self) -> bool { self.transport.is_some() && self.sender.is_some() }
+
+/// Sends a plain-text email to a single recipient.
+#[implement(Service)]
+#[tracing::instrument(skip(self, body), level = "debug")]
+pub async fn send(&self, to: &str, subject: &str, body: String) -> Result {
+	let (Some(transport), Some(sender)) = (&self.transport, &self.sender) else {
+		return Err!(FeatureDisabled("email"));
+	};
+
+	let to: Mailbox = to
+		.parse()
+		.map_err(|e| err!(Request(InvalidParam("Invalid email address: {e}"))))?;
+
+	let message = Message::builder()
+		.from(sender.clone())
+		.to(to)
+		.subject(subject)
+		.header(ContentType::TEXT_PLAIN)
+		.body(body)
+		.map_err(|e| err!("Failed to build email message: {e}"))?;
+
+	let response = transport
+		.send(message)
+		.await
+		.map_err(|e| err!(error!("Failed to send email: {e}")))?;
+
+	debug!(code = %response.code(), "Email sent");
+
+	Ok(())
+}
@@ -0,0 +1,428 @@
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use conduwuit::{
+	debug_info, err, extract_variant, implement,
+	result::LogErr,
+	utils::{self, ReadyExt},
+	warn, Err, Result,
+};
+use futures::StreamExt;
+use ruma::{
+	api::client::{
+		backup::{BackupAlgorithm, RoomKeyBackup},
+		device::Device,
+	},
+	events::{
+		AnyGlobalAccountDataEvent, AnyRawAccountDataEvent, AnyRoomAccountDataEvent,
+		RoomAccountDataEventType,
+	},
+	serde::Raw,
+	Mxc, OwnedMxcUri, OwnedRoomId, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	fs::File,
+	io::{AsyncWriteExt, BufReader, BufWriter},
+};
+
+use super::tar;
+use crate::media::MXC_LENGTH;
+
+/// Bumped when the layout of the archive changes incompatibly.
+const ACCOUNT_ARCHIVE_VERSION: u64 = 2;
+
+/// The largest `account.json` read from an archive, as it holds the user's
+/// whole key backup.
+const MAX_ACCOUNT_SIZE: usize = 1024 * 1024 * 1024;
+
+/// A local user's account as stored in `account.json` in an account archive.
+#[derive(Debug, Deserialize, Serialize)]
+struct Account {
+	version: u64,
+	user_id: OwnedUserId,
+	exported_at: u64,
+	displayname: Option<String>,
+	avatar_url: Option<OwnedMxcUri>,
+	blurhash: Option<String>,
+	profile_keys: BTreeMap<String, serde_json::Value>,
+	devices: Vec<Device>,
+	account_data: Vec<Raw<AnyGlobalAccountDataEvent>>,
+	rooms: Vec<AccountRoom>,
+	key_backup: Option<KeyBackup>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AccountRoom {
+	room_id: OwnedRoomId,
+	membership: String,
+	account_data: Vec<Raw<AnyRoomAccountDataEvent>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct KeyBackup {
+	version: String,
+	algorithm: Raw<BackupAlgorithm>,
+	rooms: BTreeMap<OwnedRoomId, RoomKeyBackup>,
+}
+
+/// An uploaded file, stored in the archive as `media/<index>.json` followed by
+/// its content as `media/<index>`.
+#[derive(Debug, Deserialize, Serialize)]
+struct MediaEntry {
+	mxc: OwnedMxcUri,
+	content_type: Option<String>,
+	content_disposition: Option<String>,
+}
+
+/// A finished account archive.
+#[derive(Clone, Debug)]
+pub struct AccountExport {
+	pub path: PathBuf,
+	pub size: u64,
+	pub media: usize,
+}
+
+/// An account restored from an archive.
+#[derive(Clone, Debug)]
+pub struct AccountImport {
+	pub user_id: OwnedUserId,
+	pub media: usize,
+	pub backup_keys: usize,
+	/// Rooms the user was joined to, which they have to rejoin.
+	pub rooms: Vec<OwnedRoomId>,
+}
+
+/// Writes a local user's profile, devices, account data, latest key backup,
+/// room memberships and uploaded media to a tar archive in `export_path`, to
+/// be imported on another server.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn export_account(&self, user_id: &UserId) -> Result<AccountExport> {
+	let users = &self.services.users;
+	if !users.exists(user_id).await {
+		return Err!(Request(NotFound("User does not exist.")));
+	}
+
+	let mut path = self.dir()?;
+	tokio::fs::create_dir_all(&path).await?;
+	path.push(format!(
+		"account-{}-{}.tar",
+		user_id.localpart(),
+		utils::millis_since_unix_epoch()
+	));
+
+	let mut out = BufWriter::new(File::create(&path).await?);
+	let media = match self.write_account_archive(user_id, &mut out).await {
+		| Ok(media) => media,
+		| Err(e) => {
+			tokio::fs::remove_file(&path).await.ok();
+			return Err(e);
+		},
+	};
+
+	out.flush().await?;
+	let size = out.get_ref().metadata().await?.len();
+
+	Ok(AccountExport { path, size, media })
+}
+
+#[implement(super::Service)]
+async fn write_account_archive(
+	&self,
+	user_id: &UserId,
+	out: &mut BufWriter<File>,
+) -> Result<usize> {
+	let users = &self.services.users;
+	let account_data = self
+		.services
+		.account_data
+		.changes_since(None, user_id, 0)
+		.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Global))
+		.collect()
+		.await;
+
+	let state_cache = &self.services.state_cache;
+	let joined = state_cache
+		.rooms_joined(user_id)
+		.map(|room_id| (room_id.to_owned(), "join"));
+	let invited = state_cache
+		.rooms_invited(user_id)
+		.map(|(room_id, _)| (room_id, "invite"));
+	let memberships: Vec<(OwnedRoomId, &str)> = joined.chain(invited).collect().await;
+
+	let mut rooms = Vec::with_capacity(memberships.len());
+	for (room_id, membership) in memberships {
+		let account_data = self
+			.services
+			.account_data
+			.changes_since(Some(&room_id), user_id, 0)
+			.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Room))
+			.collect()
+			.await;
+
+		rooms.push(AccountRoom {
+			room_id,
+			membership: membership.to_owned(),
+			account_data,
+		});
+	}
+
+	let key_backups = &self.services.key_backups;
+	let key_backup = match key_backups.get_latest_backup(user_id).await {
+		| Err(_) => None,
+		| Ok((version, algorithm)) => Some(KeyBackup {
+			rooms: key_backups.get_all(user_id, &version).await,
+			version,
+			algorithm,
+		}),
+	};
+
+	let account = Account {
+		version: ACCOUNT_ARCHIVE_VERSION,
+		user_id: user_id.to_owned(),
+		exported_at: utils::millis_since_unix_epoch(),
+		displayname: users.displayname(user_id).await.ok(),
+		avatar_url: users.avatar_url(user_id).await.ok(),
+		blurhash: users.blurhash(user_id).await.ok(),
+		profile_keys: users.all_profile_keys(user_id).collect().await,
+		devices: users.all_devices_metadata(user_id).collect().await,
+		account_data,
+		rooms,
+		key_backup,
+	};
+
+	tar::write_entry(out, "account.json", &serde_json::to_vec_pretty(&account)?).await?;
+
+	let media = &self.services.media;
+	let mut count: usize = 0;
+	for mxc in media.get_all_user_mxcs(user_id).await {
+		let Ok(parsed) = Mxc::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		let Ok(Some(file)) = media.get(&parsed).await else {
+			continue;
+		};
+
+		let Some(content) = file.content else {
+			continue;
+		};
+
+		let entry = MediaEntry {
+			mxc,
+			content_type: file.content_type,
+			content_disposition: file.content_disposition.map(|cd| cd.to_string()),
+		};
+
+		tar::write_entry(out, &format!("media/{count}.json"), &serde_json::to_vec(&entry)?)
+			.await?;
+		tar::write_entry(out, &format!("media/{count}"), &content).await?;
+		count = count.saturating_add(1);
+	}
+
+	tar::finish(out).await?;
+
+	Ok(count)
+}
+
+/// Creates a local user from an account archive written by
+/// [`export_account`](Self::export_account), re-uploading their media under
+/// new MXC URIs. The user must not exist yet and gets no password; devices
+/// and room memberships are not restored. The archive is checked and its
+/// media uploaded before the user is created, and the media is deleted again
+/// if the archive turns out to be broken.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn import_account(&self, path: &Path, user_id: &UserId) -> Result<AccountImport> {
+	let users = &self.services.users;
+	if !self.services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Accounts can only be imported as local users.")));
+	}
+
+	if users.exists(user_id).await {
+		return Err!(Request(UserInUse("User {user_id} already exists.")));
+	}
+
+	let mut input = BufReader::new(File::open(path).await?);
+	let account: Account = match tar::read_entry(&mut input, MAX_ACCOUNT_SIZE).await? {
+		| Some((name, data)) if name == "account.json" => serde_json::from_slice(&data)?,
+		| _ => return Err!(Request(InvalidParam("Archive does not start with account.json."))),
+	};
+
+	check_account(&account)?;
+
+	let mut mxcs: BTreeMap<OwnedMxcUri, OwnedMxcUri> = BTreeMap::new();
+	if let Err(e) = self.import_media(&mut input, user_id, &mut mxcs).await {
+		for mxc in mxcs.values() {
+			if let Ok(mxc) = Mxc::try_from(mxc.as_str()) {
+				self.services.media.delete(&mxc).await.log_err().ok();
+			}
+		}
+
+		return Err(e);
+	}
+
+	users.create(user_id, None)?;
+
+	users.set_displayname(user_id, account.displayname);
+	users.set_avatar_url(
+		user_id,
+		account
+			.avatar_url
+			.map(|mxc| mxcs.get(&mxc).cloned().unwrap_or(mxc)),
+	);
+	users.set_blurhash(user_id, account.blurhash);
+	for (key, value) in account.profile_keys {
+		users.set_profile_key(user_id, &key, Some(value));
+	}
+
+	let account_data = &self.services.account_data;
+	for event in &account.account_data {
+		let event = rewrite_mxcs(event.json().get(), &mxcs)?;
+		let kind: RoomAccountDataEventType = event_type(&event)?;
+		account_data.update(None, user_id, kind, &event).await?;
+	}
+
+	let mut rooms = Vec::new();
+	for room in &account.rooms {
+		for event in &room.account_data {
+			let event = rewrite_mxcs(event.json().get(), &mxcs)?;
+			let kind = event_type(&event)?;
+			account_data
+				.update(Some(&room.room_id), user_id, kind, &event)
+				.await?;
+		}
+
+		if room.membership == "join" {
+			rooms.push(room.room_id.clone());
+		}
+	}
+
+	let mut backup_keys: usize = 0;
+	if let Some(backup) = &account.key_backup {
+		let key_backups = &self.services.key_backups;
+		let version = key_backups.create_backup(user_id, &backup.algorithm)?;
+		for (room_id, room) in &backup.rooms {
+			for (session_id, key_data) in &room.sessions {
+				key_backups
+					.add_key(user_id, &version, room_id, session_id, key_data)
+					.await?;
+
+				backup_keys = backup_keys.saturating_add(1);
+			}
+		}
+	}
+
+	debug_info!(
+		%user_id,
+		from = %account.user_id,
+		media = mxcs.len(),
+		backup_keys,
+		"Imported account"
+	);
+
+	Ok(AccountImport {
+		user_id: user_id.to_owned(),
+		media: mxcs.len(),
+		backup_keys,
+		rooms,
+	})
+}
+
+/// Re-uploads the media following `account.json` in the archive one file at a
+/// time, mapping their old MXC URIs to the new ones.
+#[implement(super::Service)]
+async fn import_media(
+	&self,
+	input: &mut BufReader<File>,
+	user_id: &UserId,
+	mxcs: &mut BTreeMap<OwnedMxcUri, OwnedMxcUri>,
+) -> Result {
+	let server_name = self.services.globals.server_name();
+	let max_size = self.services.server.config.max_request_size;
+	let mut entry: Option<MediaEntry> = None;
+	while let Some((name, data)) = tar::read_entry(input, max_size).await? {
+		if !name.starts_with("media/") {
+			warn!("Ignoring unknown archive entry {name:?}");
+			continue;
+		}
+
+		if name.ends_with(".json") {
+			entry = Some(serde_json::from_slice(&data)?);
+			continue;
+		}
+
+		let Some(entry) = entry.take() else {
+			warn!("Ignoring archive entry {name:?} without metadata");
+			continue;
+		};
+
+		let media_id = utils::random_string(MXC_LENGTH);
+		let mxc = Mxc { server_name, media_id: &media_id };
+		let content_disposition = entry
+			.content_disposition
+			.as_deref()
+			.and_then(|cd| cd.parse().ok());
+
+		self.services
+			.media
+			.create(
+				&mxc,
+				Some(user_id),
+				content_disposition.as_ref(),
+				entry.content_type.as_deref(),
+				&data,
+			)
+			.await?;
+
+		mxcs.insert(entry.mxc, OwnedMxcUri::from(mxc.to_string()));
+	}
+
+	Ok(())
+}
+
+/// Refuses an archive which can't be fully imported, before anything is
+/// created from it.
+fn check_account(account: &Account) -> Result {
+	if account.version != ACCOUNT_ARCHIVE_VERSION {
+		return Err!(Request(InvalidParam(
+			"Unsupported account archive version {}.",
+			account.version
+		)));
+	}
+
+	let global = account.account_data.iter().map(Raw::json);
+	let rooms = account
+		.rooms
+		.iter()
+		.flat_map(|room| room.account_data.iter().map(Raw::json));
+
+	for event in global.chain(rooms) {
+		event_type(&serde_json::from_str(event.get())?)?;
+	}
+
+	Ok(())
+}
+
+/// Points MXC URIs of re-uploaded media at their new location.
+fn rewrite_mxcs(
+	json: &str,
+	mxcs: &BTreeMap<OwnedMxcUri, OwnedMxcUri>,
+) -> Result<serde_json::Value> {
+	let json = mxcs
+		.iter()
+		.fold(json.to_owned(), |json, (old, new)| json.replace(old.as_str(), new.as_str()));
+
+	Ok(serde_json::from_str(&json)?)
+}
+
+fn event_type(event: &serde_json::Value) -> Result<RoomAccountDataEventType> {
+	event
+		.get("type")
+		.and_then(serde_json::Value::as_str)
+		.map(Into::into)
+		.ok_or_else(|| err!(Request(InvalidParam("Account data event has no type."))))
+}
@@ -0,0 +1,343 @@
+mod account;
+mod room;
+mod tar;
+mod tests;
+mod user;
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, debug_info, err, error, implement,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+	},
+	Err, Error, Result, Server,
+};
+use database::{Deserialized, Json, Map};
+use futures::StreamExt;
+use http::StatusCode;
+use loole::{Receiver, Sender};
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, time::interval};
+
+pub use self::{
+	account::{AccountExport, AccountImport},
+	room::{RoomExport, RoomExportFilter, RoomExportFormat},
+};
+use crate::{account_data, globals, key_backups, media, rooms, threepid, users, Dep};
+
+pub struct Service {
+	channel: (Sender<OwnedUserId>, Receiver<OwnedUserId>),
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	globals: Dep<globals::Service>,
+	key_backups: Dep<key_backups::Service>,
+	media: Dep<media::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	threepid: Dep<threepid::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	dataexportid_userid: Arc<Map>,
+	userid_dataexport: Arc<Map>,
+}
+
+/// An export of a local user's data requested by that user. The ID doubles as
+/// the secret in the download URL.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Export {
+	pub id: String,
+	pub requested_at: u64,
+	pub completed_at: Option<u64>,
+	pub expires_at: Option<u64>,
+	pub error: Option<String>,
+	pub size: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+	Pending,
+	Complete,
+	Failed,
+	Expired,
+}
+
+const EXPORT_ID_LENGTH: usize = 32;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			channel: loole::unbounded(),
+			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				globals: args.depend::<globals::Service>("globals"),
+				key_backups: args.depend::<key_backups::Service>("key_backups"),
+				media: args.depend::<media::Service>("media"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				threepid: args.depend::<threepid::Service>("threepid"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				dataexportid_userid: args.db["dataexportid_userid"].clone(),
+				userid_dataexport: args.db["userid_dataexport"].clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		if self.services.globals.is_read_only() {
+			return Ok(());
+		}
+
+		// Exports interrupted by a restart are started over.
+		self.db
+			.userid_dataexport
+			.stream()
+			.ignore_err()
+			.ready_for_each(|(user_id, export): (&UserId, Export)| {
+				if export.status() == Status::Pending {
+					self.channel.0.send(user_id.to_owned()).ok();
+				}
+			})
+			.await;
+
+		let receiver = self.channel.1.clone();
+		let mut cleanup = interval(CLEANUP_INTERVAL);
+		while !receiver.is_closed() {
+			tokio::select! {
+				_ = cleanup.tick() => self.remove_expired().await,
+				user_id = receiver.recv_async() => match user_id {
+					Err(_) => break,
+					Ok(user_id) => self.run(&user_id).await,
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (sender, _) = &self.channel;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Requests an export of a local user's data, replacing any previous export.
+/// Users may only request an export once per `user_data_export_interval`.
+#[implement(Service)]
+pub async fn request(&self, user_id: &UserId) -> Result<Export> {
+	let config = &self.services.server.config;
+	if !config.allow_user_data_export || config.export_path.is_none() {
+		return Err!(Request(Forbidden("Data export is disabled on this server.")));
+	}
+
+	let now = utils::millis_since_unix_epoch();
+	if let Ok(previous) = self.get(user_id).await {
+		let next = previous
+			.requested_at
+			.saturating_add(config.user_data_export_interval.saturating_mul(1000));
+
+		if next > now {
+			return Err(Error::Request(
+				ErrorKind::LimitExceeded {
+					retry_after: Some(RetryAfter::Delay(Duration::from_millis(
+						next.saturating_sub(now),
+					))),
+				},
+				"A data export was requested too recently.".into(),
+				StatusCode::TOO_MANY_REQUESTS,
+			));
+		}
+
+		self.remove(user_id, &previous).await;
+	}
+
+	let export = Export {
+		id: utils::random_string(EXPORT_ID_LENGTH),
+		requested_at: now,
+		..Export::default()
+	};
+
+	self.db.userid_dataexport.put(user_id, Json(&export));
+	self.db.dataexportid_userid.put(&export.id, user_id);
+	self.channel
+		.0
+		.send(user_id.to_owned())
+		.map_err(|e| err!("Failed to queue data export: {e}"))?;
+
+	debug_info!(%user_id, "Data export requested");
+
+	Ok(export)
+}
+
+/// Gets the latest export requested by a local user.
+#[implement(Service)]
+pub async fn get(&self, user_id: &UserId) -> Result<Export> {
+	self.db.userid_dataexport.get(user_id).await.deserialized()
+}
+
+/// Opens a completed export for download.
+#[implement(Service)]
+pub async fn download(&self, id: &str) -> Result<fs::File> {
+	let user_id: OwnedUserId = self
+		.db
+		.dataexportid_userid
+		.get(id)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("Data export not found."))))?;
+
+	let export = self.get(&user_id).await?;
+	if export.id != id || export.status() != Status::Complete {
+		return Err!(Request(NotFound("Data export not found.")));
+	}
+
+	Ok(fs::File::open(self.path(id)?).await?)
+}
+
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "info")]
+async fn run(&self, user_id: &UserId) {
+	let Ok(mut export) = self.get(user_id).await else {
+		return;
+	};
+
+	if export.status() != Status::Pending {
+		return;
+	}
+
+	let result = async {
+		let path = self.path(&export.id)?;
+		fs::create_dir_all(self.dir()?).await?;
+		let result = self.write_user_export(user_id, &path).await;
+		if result.is_err() {
+			fs::remove_file(&path).await.ok();
+		}
+
+		result
+	};
+
+	match result.await {
+		| Ok(size) => {
+			let now = utils::millis_since_unix_epoch();
+			let ttl = self
+				.services
+				.server
+				.config
+				.user_data_export_ttl
+				.saturating_mul(1000);
+
+			export.size = size;
+			export.completed_at = Some(now);
+			export.expires_at = Some(now.saturating_add(ttl));
+			debug_info!(%user_id, size, "Data export complete");
+		},
+		| Err(e) => {
+			error!(%user_id, "Data export failed: {e}");
+			export.error = Some(e.to_string());
+		},
+	}
+
+	// The export may have been replaced while it was running.
+	if self
+		.get(user_id)
+		.await
+		.is_ok_and(|current| current.id == export.id)
+	{
+		self.db.userid_dataexport.put(user_id, Json(&export));
+	}
+}
+
+/// Deletes the files of exports which can no longer be downloaded.
+#[implement(Service)]
+async fn remove_expired(&self) {
+	let expired: Vec<(OwnedUserId, Export)> = self
+		.db
+		.userid_dataexport
+		.stream()
+		.ignore_err()
+		.ready_filter(|(_, export): &(&UserId, Export)| export.status() == Status::Expired)
+		.map(|(user_id, export)| (user_id.to_owned(), export))
+		.collect()
+		.await;
+
+	for (user_id, export) in expired {
+		debug!(%user_id, id = %export.id, "Removing expired data export");
+		if let Ok(path) = self.path(&export.id) {
+			fs::remove_file(path).await.ok();
+		}
+
+		self.db.dataexportid_userid.remove(&export.id);
+	}
+}
+
+#[implement(Service)]
+async fn remove(&self, user_id: &UserId, export: &Export) {
+	if let Ok(path) = self.path(&export.id) {
+		fs::remove_file(path).await.ok();
+	}
+
+	self.db.dataexportid_userid.remove(&export.id);
+	self.db.userid_dataexport.remove(user_id);
+}
+
+#[implement(Service)]
+fn path(&self, id: &str) -> Result<PathBuf> {
+	let mut path = self.dir()?;
+	path.push(format!("{id}.ndjson"));
+	Ok(path)
+}
+
+/// The configured `export_path`.
+#[implement(Service)]
+fn dir(&self) -> Result<PathBuf> {
+	self.services
+		.server
+		.config
+		.export_path
+		.clone()
+		.ok_or_else(|| err!(Config("export_path", "Configure export_path to enable exports")))
+}
+
+impl Export {
+	#[must_use]
+	pub fn status(&self) -> Status {
+		if self.error.is_some() {
+			Status::Failed
+		} else if self
+			.expires_at
+			.is_some_and(|expires_at| expires_at <= utils::millis_since_unix_epoch())
+		{
+			Status::Expired
+		} else if self.completed_at.is_some() {
+			Status::Complete
+		} else {
+			Status::Pending
+		}
+	}
+}
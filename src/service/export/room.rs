@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use conduwuit::{
+	implement,
+	utils::{self, stream::TryIgnore},
+	PduEvent, Result,
+};
+use futures::StreamExt;
+use ruma::{EventId, OwnedEventId, OwnedUserId, RoomId};
+use serde::Serialize;
+use serde_json::json;
+use tokio::{
+	fs::{self, File},
+	io::{AsyncWriteExt, BufWriter},
+};
+
+/// Layout of a room export file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RoomExportFormat {
+	/// One `{"type", "content"}` object per line.
+	#[default]
+	Ndjson,
+	/// A single array of `{"type", "content"}` objects.
+	Json,
+}
+
+/// Which of a room's events go into an export. Empty lists match everything;
+/// timestamps are milliseconds since the unix epoch, `until` being exclusive.
+#[derive(Clone, Debug, Default)]
+pub struct RoomExportFilter {
+	pub since: Option<u64>,
+	pub until: Option<u64>,
+	pub senders: Vec<OwnedUserId>,
+	pub types: Vec<String>,
+	/// Also write the full room state at the first and last exported events.
+	pub state: bool,
+}
+
+/// A finished room export.
+#[derive(Clone, Debug)]
+pub struct RoomExport {
+	pub path: PathBuf,
+	pub events: usize,
+	pub size: u64,
+}
+
+struct Writer {
+	out: BufWriter<File>,
+	format: RoomExportFormat,
+	records: usize,
+}
+
+/// Writes a room's events, in timeline order, to a new file in `export_path`.
+/// Events are written as full PDUs so they can be verified later.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, filter), level = "info")]
+pub async fn export_room(
+	&self,
+	room_id: &RoomId,
+	filter: &RoomExportFilter,
+	format: RoomExportFormat,
+) -> Result<RoomExport> {
+	let extension = match format {
+		| RoomExportFormat::Ndjson => "ndjson",
+		| RoomExportFormat::Json => "json",
+	};
+
+	let name: String = room_id
+		.as_str()
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+		.collect();
+
+	let mut path = self.dir()?;
+	fs::create_dir_all(&path).await?;
+	path.push(format!("room{name}-{}.{extension}", utils::millis_since_unix_epoch()));
+
+	let result = self.write_room_export(room_id, filter, format, &path).await;
+	match result {
+		| Ok((events, size)) => Ok(RoomExport { path, events, size }),
+		| Err(e) => {
+			fs::remove_file(&path).await.ok();
+			Err(e)
+		},
+	}
+}
+
+#[implement(super::Service)]
+async fn write_room_export(
+	&self,
+	room_id: &RoomId,
+	filter: &RoomExportFilter,
+	format: RoomExportFormat,
+	path: &Path,
+) -> Result<(usize, u64)> {
+	let mut out = Writer {
+		out: BufWriter::new(File::create(path).await?),
+		format,
+		records: 0,
+	};
+
+	if format == RoomExportFormat::Json {
+		out.out.write_all(b"[\n").await?;
+	}
+
+	out.write(
+		"room",
+		&json!({
+			"room_id": room_id,
+			"exported_at": utils::millis_since_unix_epoch(),
+			"since": filter.since,
+			"until": filter.until,
+		}),
+	)
+	.await?;
+
+	let mut pdus = self
+		.services
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err()
+		.boxed();
+
+	let mut events: usize = 0;
+	let mut last: Option<OwnedEventId> = None;
+	while let Some((_, pdu)) = pdus.next().await {
+		if !filter.matches(&pdu) {
+			continue;
+		}
+
+		if filter.state && last.is_none() {
+			self.write_state(&mut out, &pdu.event_id).await?;
+		}
+
+		out.write("event", &pdu).await?;
+		last = Some(pdu.event_id.clone());
+		events = events.saturating_add(1);
+	}
+
+	if filter.state {
+		if let Some(event_id) = last {
+			self.write_state(&mut out, &event_id).await?;
+		}
+	}
+
+	if format == RoomExportFormat::Json {
+		out.out.write_all(b"\n]\n").await?;
+	}
+
+	out.out.flush().await?;
+
+	Ok((events, out.out.get_ref().metadata().await?.len()))
+}
+
+/// Writes the room state at an event as a single `state` record.
+#[implement(super::Service)]
+async fn write_state(&self, out: &mut Writer, event_id: &EventId) -> Result {
+	let state_accessor = &self.services.state_accessor;
+	let Ok(shortstatehash) = state_accessor.pdu_shortstatehash(event_id).await else {
+		return Ok(());
+	};
+
+	let state: Vec<PduEvent> = state_accessor
+		.state_full_pdus(shortstatehash)
+		.collect()
+		.await;
+
+	out.write(
+		"state",
+		&json!({
+			"event_id": event_id,
+			"events": state,
+		}),
+	)
+	.await
+}
+
+impl RoomExportFilter {
+	fn matches(&self, pdu: &PduEvent) -> bool {
+		let ts: u64 = pdu.origin_server_ts.into();
+
+		self.since.is_none_or(|since| ts >= since)
+			&& self.until.is_none_or(|until| ts < until)
+			&& (self.senders.is_empty() || self.senders.contains(&pdu.sender))
+			&& (self.types.is_empty() || self.types.contains(&pdu.kind.to_string()))
+	}
+}
+
+impl Writer {
+	async fn write<T>(&mut self, kind: &str, content: &T) -> Result
+	where
+		T: Serialize + ?Sized,
+	{
+		let record = serde_json::to_vec(&json!({
+			"type": kind,
+			"content": content,
+		}))?;
+
+		match self.format {
+			| RoomExportFormat::Ndjson => {
+				self.out.write_all(&record).await?;
+				self.out.write_all(b"\n").await?;
+			},
+			| RoomExportFormat::Json => {
+				if self.records > 0 {
+					self.out.write_all(b",\n").await?;
+				}
+
+				self.out.write_all(&record).await?;
+			},
+		}
+
+		self.records = self.records.saturating_add(1);
+
+		Ok(())
+	}
+}
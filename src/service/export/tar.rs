@@ -0,0 +1,145 @@
+//! Just enough of the ustar format to write and read account archives.
+
+use conduwuit::{err, utils, Err, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const BLOCK: usize = 512;
+
+const NAME_LEN: usize = 100;
+
+/// Appends a regular file to the archive.
+pub(super) async fn write_entry<W>(out: &mut W, name: &str, data: &[u8]) -> Result
+where
+	W: AsyncWrite + Unpin + Send,
+{
+	out.write_all(&header(name, data.len())?).await?;
+	out.write_all(data).await?;
+	out.write_all(&[0; BLOCK][..padding(data.len())]).await?;
+
+	Ok(())
+}
+
+/// Writes the two empty blocks marking the end of the archive.
+pub(super) async fn finish<W>(out: &mut W) -> Result
+where
+	W: AsyncWrite + Unpin + Send,
+{
+	out.write_all(&[0; BLOCK * 2]).await?;
+
+	Ok(())
+}
+
+/// Reads the next regular file from the archive, skipping any other kind of
+/// entry. Returns None at the end of the archive. Files larger than
+/// `max_size` are refused before they are read.
+pub(super) async fn read_entry<R>(
+	input: &mut R,
+	max_size: usize,
+) -> Result<Option<(String, Vec<u8>)>>
+where
+	R: AsyncRead + Unpin + Send,
+{
+	loop {
+		let mut header = [0; BLOCK];
+		input.read_exact(&mut header).await?;
+		if header.iter().all(|&b| b == 0) {
+			return Ok(None);
+		}
+
+		if &header[257..262] != b"ustar" {
+			return Err!(Request(InvalidParam("Not a ustar archive.")));
+		}
+
+		let name = field_str(&header[..NAME_LEN])?.to_owned();
+		let size = u64::from_str_radix(field_str(&header[124..136])?, 8).map_err(|e| {
+			err!(Request(InvalidParam("Bad size for archive entry {name:?}: {e}")))
+		})?;
+
+		if !matches!(header[156], b'0' | 0) {
+			skip(input, size).await?;
+			continue;
+		}
+
+		let size: usize = size.try_into()?;
+		if size > max_size {
+			return Err!(Request(TooLarge(
+				"Archive entry {name:?} is larger than {max_size} bytes."
+			)));
+		}
+
+		let mut data = vec![0; size];
+		input.read_exact(&mut data).await?;
+
+		let mut pad = [0; BLOCK];
+		input.read_exact(&mut pad[..padding(size)]).await?;
+
+		return Ok(Some((name, data)));
+	}
+}
+
+/// Skips an entry's data and padding without buffering it.
+async fn skip<R>(input: &mut R, size: u64) -> Result
+where
+	R: AsyncRead + Unpin + Send,
+{
+	let block: u64 = BLOCK.try_into()?;
+	let padded = size.div_ceil(block).saturating_mul(block);
+	let skipped = tokio::io::copy(&mut input.take(padded), &mut tokio::io::sink()).await?;
+	if skipped != padded {
+		return Err!(Request(InvalidParam("Archive is truncated.")));
+	}
+
+	Ok(())
+}
+
+fn header(name: &str, size: usize) -> Result<[u8; BLOCK]> {
+	if name.len() > NAME_LEN {
+		return Err!("Archive entry name {name:?} is too long");
+	}
+
+	let mut header = [0; BLOCK];
+	header[..name.len()].copy_from_slice(name.as_bytes());
+	octal(&mut header[100..108], 0o644)?;
+	octal(&mut header[108..116], 0)?;
+	octal(&mut header[116..124], 0)?;
+	octal(&mut header[124..136], size.try_into()?)?;
+	octal(&mut header[136..148], utils::millis_since_unix_epoch() / 1000)?;
+	header[156] = b'0';
+	header[257..263].copy_from_slice(b"ustar\0");
+	header[263..265].copy_from_slice(b"00");
+
+	// The checksum is computed with its own field filled with spaces.
+	header[148..156].fill(b' ');
+	let checksum = header
+		.iter()
+		.fold(0_u64, |sum, &b| sum.saturating_add(b.into()));
+
+	octal(&mut header[148..155], checksum)?;
+
+	Ok(header)
+}
+
+/// Writes a NUL-terminated, zero-padded octal number filling the field.
+fn octal(field: &mut [u8], value: u64) -> Result {
+	let digits = field.len().saturating_sub(1);
+	let value = format!("{value:0digits$o}");
+	if value.len() > digits {
+		return Err!("Archive entry is too large");
+	}
+
+	field[..digits].copy_from_slice(value.as_bytes());
+	field[digits] = 0;
+
+	Ok(())
+}
+
+fn field_str(field: &[u8]) -> Result<&str> {
+	let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+	let value = std::str::from_utf8(&field[..end])
+		.map_err(|e| err!(Request(InvalidParam("Bad archive header: {e}"))))?;
+
+	Ok(value.trim())
+}
+
+#[inline]
+fn padding(size: usize) -> usize { BLOCK.saturating_sub(size % BLOCK) % BLOCK }
@@ -0,0 +1,65 @@
+#![cfg(test)]
+
+use super::tar;
+
+async fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+	let mut out = Vec::new();
+	for (name, data) in entries {
+		tar::write_entry(&mut out, name, data).await.unwrap();
+	}
+
+	tar::finish(&mut out).await.unwrap();
+	out
+}
+
+#[tokio::test]
+async fn tar_round_trip() {
+	let media = vec![7_u8; 1500];
+	let out = archive(&[
+		("account.json", b"{}".as_slice()),
+		("media/0", media.as_slice()),
+		("empty", b"".as_slice()),
+	])
+	.await;
+	assert_eq!(out.len() % 512, 0, "archive is made of whole blocks");
+
+	let mut input = out.as_slice();
+	let (name, data) = tar::read_entry(&mut input, 2048).await.unwrap().unwrap();
+	assert_eq!((name.as_str(), data.as_slice()), ("account.json", b"{}".as_slice()));
+
+	let (name, data) = tar::read_entry(&mut input, 2048).await.unwrap().unwrap();
+	assert_eq!((name.as_str(), data), ("media/0", media));
+
+	let (name, data) = tar::read_entry(&mut input, 2048).await.unwrap().unwrap();
+	assert_eq!((name.as_str(), data.len()), ("empty", 0));
+
+	assert!(tar::read_entry(&mut input, 2048).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn tar_refuses_large_entry() {
+	let out = archive(&[("media/0", [0; 1024].as_slice())]).await;
+
+	let mut input = out.as_slice();
+	assert!(tar::read_entry(&mut input, 1023).await.is_err());
+}
+
+#[tokio::test]
+async fn tar_skips_other_entries() {
+	let mut out = archive(&[("dir", [1; 600].as_slice()), ("media/0", b"data".as_slice())]).await;
+
+	// Turn the first entry into a directory
+	out[156] = b'5';
+
+	let mut input = out.as_slice();
+	let (name, data) = tar::read_entry(&mut input, 16).await.unwrap().unwrap();
+	assert_eq!((name.as_str(), data.as_slice()), ("media/0", b"data".as_slice()));
+}
+
+#[tokio::test]
+async fn tar_refuses_truncated_archive() {
+	let out = archive(&[("media/0", [0; 1024].as_slice())]).await;
+
+	let mut input = &out[..600];
+	assert!(tar::read_entry(&mut input, 2048).await.is_err());
+}
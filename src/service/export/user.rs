@@ -0,0 +1,119 @@
+use std::{collections::BTreeMap, path::Path};
+
+use conduwuit::{
+	extract_variant, implement,
+	utils::stream::{ReadyExt, TryIgnore},
+	Result,
+};
+use futures::StreamExt;
+use ruma::{events::AnyRawAccountDataEvent, OwnedRoomId, UserId};
+use serde::Serialize;
+use serde_json::json;
+use tokio::{
+	fs::File,
+	io::{AsyncWriteExt, BufWriter},
+};
+
+/// Writes everything stored about a local user, along with the events they
+/// sent, as newline-delimited JSON objects with a `type` and `content`.
+#[implement(super::Service)]
+pub(super) async fn write_user_export(&self, user_id: &UserId, path: &Path) -> Result<u64> {
+	let mut out = BufWriter::new(File::create(path).await?);
+	let users = &self.services.users;
+
+	let profile_keys: BTreeMap<_, _> = users.all_profile_keys(user_id).collect().await;
+	write_line(
+		&mut out,
+		"profile",
+		&json!({
+			"user_id": user_id,
+			"displayname": users.displayname(user_id).await.ok(),
+			"avatar_url": users.avatar_url(user_id).await.ok(),
+			"blurhash": users.blurhash(user_id).await.ok(),
+			"timezone": users.timezone(user_id).await.ok(),
+			"keys": profile_keys,
+		}),
+	)
+	.await?;
+
+	let threepids: Vec<_> = self
+		.services
+		.threepid
+		.get_threepids(user_id)
+		.collect()
+		.await;
+	write_line(&mut out, "threepids", &threepids).await?;
+
+	let devices: Vec<_> = users.all_devices_metadata(user_id).collect().await;
+	write_line(&mut out, "devices", &devices).await?;
+
+	let account_data: Vec<_> = self
+		.services
+		.account_data
+		.changes_since(None, user_id, 0)
+		.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Global))
+		.collect()
+		.await;
+	write_line(&mut out, "account_data", &account_data).await?;
+
+	let state_cache = &self.services.state_cache;
+	let joined = state_cache
+		.rooms_joined(user_id)
+		.map(|room_id| (room_id.to_owned(), "join"));
+	let left = state_cache
+		.rooms_left(user_id)
+		.map(|(room_id, _)| (room_id, "leave"));
+	let rooms: Vec<(OwnedRoomId, &str)> = joined.chain(left).collect().await;
+
+	for (room_id, membership) in &rooms {
+		let account_data: Vec<_> = self
+			.services
+			.account_data
+			.changes_since(Some(room_id), user_id, 0)
+			.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Room))
+			.collect()
+			.await;
+
+		write_line(
+			&mut out,
+			"room",
+			&json!({
+				"room_id": room_id,
+				"membership": membership,
+				"account_data": account_data,
+			}),
+		)
+		.await?;
+
+		let mut pdus = self
+			.services
+			.timeline
+			.pdus(Some(user_id), room_id, None)
+			.ignore_err()
+			.ready_filter(|(_, pdu)| pdu.sender == user_id)
+			.boxed();
+
+		while let Some((_, pdu)) = pdus.next().await {
+			write_line(&mut out, "event", &pdu.to_room_event()).await?;
+		}
+	}
+
+	out.flush().await?;
+
+	Ok(out.get_ref().metadata().await?.len())
+}
+
+async fn write_line<T>(out: &mut BufWriter<File>, kind: &str, content: &T) -> Result
+where
+	T: Serialize + ?Sized,
+{
+	let mut line = serde_json::to_vec(&json!({
+		"type": kind,
+		"content": content,
+	}))?;
+
+	line.push(b'\n');
+	out.write_all(&line).await?;
+
+	Ok(())
+}
@@ -0,0 +1,131 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use conduwuit::{implement, utils::time::now_millis, Result};
+use ruma::{OwnedServerName, ServerName};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Request statistics and limits for one remote server.
+#[derive(Debug, Default)]
+pub struct Destination {
+	/// Requests currently awaiting a response.
+	pub in_flight: AtomicU64,
+
+	/// Requests waiting for `max_connections` to allow them through.
+	pub queued: AtomicU64,
+
+	/// Requests completed, successfully or not.
+	pub requests: AtomicU64,
+
+	/// Requests which failed to connect or timed out.
+	pub failures: AtomicU64,
+
+	/// Time of the last request, in milliseconds since the epoch.
+	pub last_request: AtomicU64,
+
+	/// Configured limit of requests in flight at once.
+	pub max_connections: Option<usize>,
+
+	limit: Option<Arc<Semaphore>>,
+}
+
+pub(super) type Destinations = Mutex<HashMap<OwnedServerName, Arc<Destination>>>;
+
+/// Held for the duration of a request; updates the destination's statistics
+/// when the request ends or is abandoned.
+pub(super) struct Request {
+	destination: Arc<Destination>,
+	_permit: Option<OwnedSemaphorePermit>,
+	failed: bool,
+}
+
+/// Statistics of every remote server we've made requests to.
+#[implement(super::Service)]
+#[must_use]
+pub fn destinations(&self) -> Vec<(OwnedServerName, Arc<Destination>)> {
+	self.destinations
+		.lock()
+		.expect("locked")
+		.iter()
+		.map(|(dest, destination)| (dest.clone(), destination.clone()))
+		.collect()
+}
+
+#[implement(super::Service)]
+pub(super) async fn begin_request(&self, dest: &ServerName) -> Request {
+	let destination = self.destination(dest);
+
+	let permit = if let Some(limit) = destination.limit.clone() {
+		destination.queued.fetch_add(1, Ordering::Relaxed);
+		let permit = limit.acquire_owned().await.ok();
+		destination.queued.fetch_sub(1, Ordering::Relaxed);
+		permit
+	} else {
+		None
+	};
+
+	destination.in_flight.fetch_add(1, Ordering::Relaxed);
+	destination
+		.last_request
+		.store(now_millis(), Ordering::Relaxed);
+
+	Request {
+		destination,
+		_permit: permit,
+		failed: false,
+	}
+}
+
+#[implement(super::Service)]
+fn destination(&self, dest: &ServerName) -> Arc<Destination> {
+	self.destinations
+		.lock()
+		.expect("locked")
+		.entry(dest.to_owned())
+		.or_insert_with(|| {
+			let max_connections = self
+				.services
+				.server
+				.config
+				.federation_destination_options
+				.get(dest)
+				.and_then(|options| options.max_connections)
+				.map(|max| max.max(1));
+
+			Arc::new(Destination {
+				max_connections,
+				limit: max_connections.map(Semaphore::new).map(Arc::new),
+				..Default::default()
+			})
+		})
+		.clone()
+}
+
+#[implement(super::Service)]
+pub(super) fn destinations_memory_usage(&self, out: &mut dyn Write) -> Result {
+	let destinations = self.destinations.lock()?.len();
+	writeln!(out, "destinations: {destinations}")?;
+
+	Ok(())
+}
+
+impl Request {
+	#[inline]
+	pub(super) fn failed(&mut self) { self.failed = true; }
+}
+
+impl Drop for Request {
+	fn drop(&mut self) {
+		self.destination.in_flight.fetch_sub(1, Ordering::Relaxed);
+		self.destination.requests.fetch_add(1, Ordering::Relaxed);
+		if self.failed {
+			self.destination.failures.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+}
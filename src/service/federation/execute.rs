@@ -27,7 +27,7 @@ pub async fn execute<T>(&self, dest: &ServerName, request: T) -> Result<T::Incom
 where
 	T: OutgoingRequest + Debug + Send,
 {
-	let client = &self.services.client.federation;
+	let client = self.services.client.federation_for(dest);
 	self.execute_on(client, dest, request).await
 }
 
@@ -50,7 +50,8 @@ where
 #[tracing::instrument(
 		name = "fed",
 		level = INFO_SPAN_LEVEL,
-		skip(self, client, request),
+		skip(self, client, dest, request),
+		fields(destination = %dest),
 	)]
 pub async fn execute_on<T>(
 	&self,
@@ -95,11 +96,15 @@ where
 	let url = request.url().clone();
 	let method = request.method().clone();
 
+	let mut tracked = self.begin_request(dest).await;
+
 	debug!(?method, ?url, "Sending request");
 	match client.execute(request).await {
 		| Ok(response) => handle_response::<T>(dest, actual, &method, &url, response).await,
-		| Err(error) =>
-			Err(handle_error(actual, &method, &url, error).expect_err("always returns error")),
+		| Err(error) => {
+			tracked.failed();
+			Err(handle_error(actual, &method, &url, error).expect_err("always returns error"))
+		},
 	}
 }
 
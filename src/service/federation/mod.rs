@@ -1,13 +1,17 @@
+mod destinations;
 mod execute;
 
-use std::sync::Arc;
+use std::{fmt::Write, sync::Arc};
 
 use conduwuit::{Result, Server};
 
+pub use self::destinations::Destination;
+use self::destinations::Destinations;
 use crate::{client, resolver, server_keys, Dep};
 
 pub struct Service {
 	services: Services,
+	destinations: Destinations,
 }
 
 struct Services {
@@ -26,8 +30,11 @@ impl crate::Service for Service {
 				resolver: args.depend::<resolver::Service>("resolver"),
 				server_keys: args.depend::<server_keys::Service>("server_keys"),
 			},
+			destinations: Destinations::default(),
 		}))
 	}
 
+	fn memory_usage(&self, out: &mut dyn Write) -> Result { self.destinations_memory_usage(out) }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
@@ -1,6 +1,9 @@
-use std::sync::{Arc, RwLock};
+use std::{
+	path::Path,
+	sync::{Arc, RwLock},
+};
 
-use conduwuit::{utils, Result};
+use conduwuit::{utils, Err, Result};
 use database::{Database, Deserialized, Map};
 
 pub struct Data {
@@ -68,6 +71,25 @@ impl Data {
 			.unwrap_or(0)
 	}
 
+	/// Verifies the database is usable without writing to it: a value is read
+	/// and RocksDB must not have hit a background error, after which it would
+	/// refuse writes.
+	pub async fn health_check(&self) -> Result {
+		if let Err(e) = self.global.get(COUNTER).await {
+			if !e.is_not_found() {
+				return Err(e);
+			}
+		}
+
+		let errors = self.global.property_integer(c"rocksdb.background-errors")?;
+
+		if errors > 0 {
+			return Err!(Database("{errors} background errors occurred"));
+		}
+
+		Ok(())
+	}
+
 	#[inline]
 	pub fn bump_database_version(&self, new_version: u64) -> Result<()> {
 		self.global.raw_put(b"version", new_version);
@@ -80,6 +102,11 @@ impl Data {
 	#[inline]
 	pub fn backup_list(&self) -> Result<String> { self.db.db.backup_list() }
 
+	#[inline]
+	pub fn backup_restore(&self, backup_id: u32, target: &Path) -> Result {
+		self.db.db.backup_restore(backup_id, target)
+	}
+
 	#[inline]
 	pub fn file_list(&self) -> Result<String> { self.db.db.file_list() }
 }
@@ -0,0 +1,237 @@
+use std::{
+	collections::{BTreeMap, HashMap, VecDeque},
+	fmt::Write,
+	net::IpAddr,
+	sync::{Arc, Mutex, RwLock},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug_info, implement,
+	utils::{self, stream::TryIgnore},
+	warn, Err, Result, Server,
+};
+use database::{Json, Map};
+use futures::StreamExt;
+use ipaddress::IPAddress;
+use serde::{Deserialize, Serialize};
+
+pub struct Service {
+	server: Arc<Server>,
+	db: Data,
+
+	/// Bans by CIDR range as stored, loaded from the database at startup.
+	bans: RwLock<BTreeMap<String, (IPAddress, Ban)>>,
+
+	/// Times of recent failed password attempts from each address, oldest
+	/// first.
+	failures: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+struct Data {
+	ipban_info: Arc<Map>,
+}
+
+/// A banned IP address or CIDR range stored in the database.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Ban {
+	pub reason: Option<String>,
+	pub banned_at: u64,
+	pub expires_at: Option<u64>,
+
+	/// Whether the ban was made by `ip_autoban_failures` rather than an admin.
+	#[serde(default)]
+	pub automatic: bool,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			server: args.server.clone(),
+			db: Data {
+				ipban_info: args.db["ipban_info"].clone(),
+			},
+			bans: RwLock::default(),
+			failures: Mutex::default(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let now = utils::millis_since_unix_epoch();
+		let stored: Vec<(String, Ban)> = self
+			.db
+			.ipban_info
+			.stream()
+			.ignore_err()
+			.map(|(cidr, ban): (&str, Ban)| (cidr.to_owned(), ban))
+			.collect()
+			.await;
+
+		// Expired bans are left for the primary to remove.
+		let config = &self.server.config;
+		let read_only = config.rocksdb_read_only || config.rocksdb_secondary;
+		let mut bans = self.bans.write()?;
+		for (cidr, ban) in stored {
+			if ban.is_expired(now) {
+				if !read_only {
+					self.db.ipban_info.remove(&cidr);
+				}
+
+				continue;
+			}
+
+			match IPAddress::parse(cidr.as_str()) {
+				| Ok(range) => _ = bans.insert(cidr, (range, ban)),
+				| Err(e) => warn!(%cidr, "Ignoring IP ban with an invalid range: {e}"),
+			}
+		}
+
+		debug_info!("Loaded {} IP bans", bans.len());
+
+		Ok(())
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		writeln!(out, "ipban_bans: {}", self.bans.read()?.len())?;
+		writeln!(out, "ipban_failures: {}", self.failures.lock()?.len())?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Bans an IP address or CIDR range, for `duration` or until unbanned.
+/// Replaces any ban of the same range.
+#[implement(Service)]
+pub fn ban(
+	&self,
+	cidr: &str,
+	duration: Option<Duration>,
+	reason: Option<String>,
+	automatic: bool,
+) -> Result {
+	let cidr = cidr.trim();
+	let range = match IPAddress::parse(cidr) {
+		| Ok(range) => range,
+		| Err(e) => return Err!(Request(InvalidParam("Invalid IP address or CIDR range: {e}"))),
+	};
+
+	let banned_at = utils::millis_since_unix_epoch();
+	let expires_at = duration
+		.map(|duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+		.map(|duration| banned_at.saturating_add(duration));
+
+	let ban = Ban { reason, banned_at, expires_at, automatic };
+	self.db.ipban_info.put(cidr, Json(&ban));
+	self.bans
+		.write()
+		.expect("locked for writing")
+		.insert(cidr.to_owned(), (range, ban));
+
+	debug_info!(%cidr, ?duration, automatic, "Banned IP range");
+
+	Ok(())
+}
+
+/// Lifts the ban of an IP address or CIDR range, as it was given when banned.
+/// Returns whether it was banned.
+#[implement(Service)]
+pub fn unban(&self, cidr: &str) -> bool {
+	let cidr = cidr.trim();
+	self.db.ipban_info.remove(cidr);
+	self.bans
+		.write()
+		.expect("locked for writing")
+		.remove(cidr)
+		.is_some()
+}
+
+/// The ban covering `ip`, if any.
+#[implement(Service)]
+#[must_use]
+pub fn banned(&self, ip: IpAddr) -> Option<Ban> {
+	let bans = self.bans.read().expect("locked for reading");
+	if bans.is_empty() {
+		return None;
+	}
+
+	let ip = IPAddress::parse(ip.to_string()).ok()?;
+	let now = utils::millis_since_unix_epoch();
+	bans.values()
+		.find(|(range, ban)| !ban.is_expired(now) && range.includes(&ip))
+		.map(|(_, ban)| ban.clone())
+}
+
+/// Bans that haven't expired, by CIDR range. Expired bans are removed.
+#[implement(Service)]
+#[must_use]
+pub fn list(&self) -> Vec<(String, Ban)> {
+	let now = utils::millis_since_unix_epoch();
+	let mut bans = self.bans.write().expect("locked for writing");
+	bans.retain(|cidr, (_, ban)| {
+		let expired = ban.is_expired(now);
+		if expired {
+			self.db.ipban_info.remove(cidr);
+		}
+
+		!expired
+	});
+
+	bans.iter()
+		.map(|(cidr, (_, ban))| (cidr.clone(), ban.clone()))
+		.collect()
+}
+
+/// Counts a failed password attempt from `ip`, banning the address for
+/// `ip_autoban_duration` once it has failed `ip_autoban_failures` times
+/// within `ip_autoban_window`.
+#[implement(Service)]
+pub fn record_auth_failure(&self, ip: IpAddr) {
+	let config = &self.server.config;
+	let limit = usize::try_from(config.ip_autoban_failures).unwrap_or(usize::MAX);
+	if limit == 0 {
+		return;
+	}
+
+	let window = Duration::from_secs(config.ip_autoban_window);
+	let now = Instant::now();
+	let mut failures = self.failures.lock().expect("locked");
+	failures.retain(|_, times| {
+		times
+			.back()
+			.is_some_and(|&at| now.duration_since(at) < window)
+	});
+
+	let times = failures.entry(ip).or_default();
+	while times
+		.front()
+		.is_some_and(|&at| now.duration_since(at) >= window)
+	{
+		times.pop_front();
+	}
+
+	times.push_back(now);
+	if times.len() < limit {
+		return;
+	}
+
+	failures.remove(&ip);
+	drop(failures);
+
+	let duration = Duration::from_secs(config.ip_autoban_duration);
+	warn!(%ip, "Banning IP address for {duration:?} after {limit} failed login attempts");
+	let reason = format!("{limit} failed login attempts");
+	if let Err(e) = self.ban(&ip.to_string(), Some(duration), Some(reason), true) {
+		warn!(%ip, "Failed to ban IP address: {e}");
+	}
+}
+
+impl Ban {
+	#[must_use]
+	pub fn is_expired(&self, now: u64) -> bool {
+		self.expires_at.is_some_and(|expires_at| expires_at <= now)
+	}
+}
@@ -0,0 +1,206 @@
+use std::{
+	borrow::Cow,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{debug, err, implement, warn, Err, Result, Server};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::{Map as JsonObject, Value as JsonValue};
+use tokio::sync::RwLock;
+
+use crate::{client, Dep};
+
+pub struct Service {
+	services: Services,
+	algorithm: Algorithm,
+	key: Option<DecodingKey>,
+	jwks: RwLock<Option<KeySet>>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+}
+
+struct KeySet {
+	keys: JwkSet,
+	fetched_at: Instant,
+}
+
+/// The user a valid login token was issued for.
+#[derive(Debug)]
+pub struct Claims {
+	/// Localpart or full user ID from the configured claim.
+	pub localpart: String,
+
+	pub displayname: Option<String>,
+}
+
+/// Shortest time between fetches of the key set for tokens signed with a key
+/// it does not contain.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config.jwt;
+		let algorithm: Algorithm = config
+			.algorithm
+			.parse()
+			.map_err(|e| err!(Config("jwt.algorithm", "Invalid JWT algorithm: {e}")))?;
+
+		let key = if let Some(secret) = &config.secret {
+			Some(DecodingKey::from_secret(secret.as_bytes()))
+		} else if let Some(path) = &config.key_file {
+			let pem = std::fs::read(path)
+				.map_err(|e| err!(Config("jwt.key_file", "Failed to read JWT key: {e}")))?;
+
+			let key = match algorithm {
+				| Algorithm::ES256 => DecodingKey::from_ec_pem(&pem),
+				| _ => DecodingKey::from_rsa_pem(&pem),
+			}
+			.map_err(|e| err!(Config("jwt.key_file", "Invalid JWT key: {e}")))?;
+
+			Some(key)
+		} else {
+			None
+		};
+
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+			},
+			algorithm,
+			key,
+			jwks: RwLock::new(None),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether users may log in with a JSON Web Token.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.services.server.config.jwt.is_enabled() }
+
+/// Verifies a login token's signature, expiry, issuer and audience, returning
+/// the user it was issued for.
+#[implement(Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn validate(&self, token: &str) -> Result<Claims> {
+	let config = &self.services.server.config.jwt;
+	let header = jsonwebtoken::decode_header(token)
+		.map_err(|e| err!(Request(Forbidden("Invalid login token: {e}"))))?;
+
+	if header.alg != self.algorithm {
+		return Err!(Request(Forbidden("Login token is not signed with {:?}.", self.algorithm)));
+	}
+
+	let key = match &self.key {
+		| Some(key) => Cow::Borrowed(key),
+		| None => Cow::Owned(self.jwk(header.kid.as_deref()).await?),
+	};
+
+	let mut validation = Validation::new(self.algorithm);
+	if let Some(issuer) = &config.issuer {
+		validation.set_issuer(&[issuer]);
+	}
+
+	if config.audience.is_empty() {
+		validation.validate_aud = false;
+	} else {
+		validation.set_audience(&config.audience);
+	}
+
+	let claims = jsonwebtoken::decode::<JsonObject<String, JsonValue>>(token, &key, &validation)
+		.map_err(|e| err!(Request(Forbidden("Invalid login token: {e}"))))?
+		.claims;
+
+	let claim = |name: &str| {
+		claims
+			.get(name)
+			.and_then(JsonValue::as_str)
+			.filter(|value| !value.is_empty())
+			.map(ToOwned::to_owned)
+	};
+
+	let localpart = claim(&config.localpart_claim).ok_or_else(|| {
+		err!(Request(Forbidden("Login token has no {:?} claim.", config.localpart_claim)))
+	})?;
+
+	debug!(%localpart, "Validated login token");
+
+	Ok(Claims {
+		localpart,
+		displayname: claim(&config.displayname_claim),
+	})
+}
+
+/// Finds the key a token was signed with in the key set, which is fetched
+/// again once stale or when it does not contain the key.
+#[implement(Service)]
+async fn jwk(&self, kid: Option<&str>) -> Result<DecodingKey> {
+	let interval = Duration::from_secs(self.services.server.config.jwt.jwks_refresh_interval);
+	let (found, age) = match self.jwks.read().await.as_ref() {
+		| Some(set) => (find_jwk(&set.keys, kid), Some(set.fetched_at.elapsed())),
+		| None => (None, None),
+	};
+
+	let refetch =
+		age.is_none_or(|age| age >= interval || (found.is_none() && age >= MIN_REFETCH_INTERVAL));
+
+	if refetch {
+		match self.fetch_jwks().await {
+			| Ok(keys) => {
+				let key = find_jwk(&keys, kid);
+				*self.jwks.write().await = Some(KeySet { keys, fetched_at: Instant::now() });
+				return key.ok_or_else(|| {
+					err!(Request(Forbidden("Login token is signed with an unknown key.")))
+				});
+			},
+			| Err(e) if found.is_some() => warn!("Failed to refresh JWT key set: {e}"),
+			| Err(e) => return Err(e),
+		}
+	}
+
+	found.ok_or_else(|| err!(Request(Forbidden("Login token is signed with an unknown key."))))
+}
+
+#[implement(Service)]
+async fn fetch_jwks(&self) -> Result<JwkSet> {
+	let url = self
+		.services
+		.server
+		.config
+		.jwt
+		.jwks_url
+		.clone()
+		.ok_or_else(|| err!(FeatureDisabled("jwt.jwks_url")))?;
+
+	let body = self
+		.services
+		.client
+		.default
+		.get(url)
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid JSON Web Key Set: {e}")))
+}
+
+/// Finds a key by ID, or the only key in the set if the token names none.
+fn find_jwk(keys: &JwkSet, kid: Option<&str>) -> Option<DecodingKey> {
+	let jwk = match kid {
+		| Some(kid) => keys.find(kid),
+		| None => keys.keys.first().filter(|_| keys.keys.len() == 1),
+	};
+
+	jwk.and_then(|jwk| DecodingKey::from_jwk(jwk).ok())
+}
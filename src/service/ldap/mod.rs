@@ -0,0 +1,198 @@
+mod tests;
+
+use std::{sync::Arc, time::Duration};
+
+use conduwuit::{debug, debug_warn, err, implement, Err, Result, Server};
+use ldap3::{dn_escape, ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use ruma::UserId;
+
+pub struct Service {
+	server: Arc<Server>,
+}
+
+/// A user's directory entry, after their credentials were accepted.
+#[derive(Debug)]
+pub struct Entry {
+	pub dn: String,
+	pub displayname: Option<String>,
+	pub email: Option<String>,
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// LDAP result code for a bind with wrong credentials.
+const INVALID_CREDENTIALS: u32 = 49;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self { server: args.server.clone() }))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether password logins are checked against an LDAP server.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.server.config.ldap.uri.is_some() }
+
+/// Checks a user's password with the LDAP server, returning their directory
+/// entry if it was accepted or None if the user was not found or the password
+/// was wrong.
+#[implement(Service)]
+#[tracing::instrument(skip(self, password), level = "debug")]
+pub async fn authenticate(&self, username: &str, password: &str) -> Result<Option<Entry>> {
+	let config = &self.server.config.ldap;
+
+	// An empty password makes an unauthenticated bind, which servers accept
+	if password.is_empty() {
+		return Ok(None);
+	}
+
+	let mut ldap = self.connect().await?;
+	let entry = if let Some(template) = &config.bind_dn_template {
+		let dn = template.replace("{username}", &dn_escape(username));
+		if !bind(&mut ldap, &dn, password).await? {
+			return Ok(None);
+		}
+
+		// The user may not be allowed to read their own entry; they are still
+		// authenticated.
+		self.search(&mut ldap, &dn, Scope::Base, "(objectClass=*)")
+			.await
+			.ok()
+			.flatten()
+			.unwrap_or_else(|| Entry { dn, displayname: None, email: None })
+	} else {
+		if let Some(bind_dn) = &config.bind_dn {
+			let bind_password = config.bind_password.as_deref().unwrap_or_default();
+			if !bind(&mut ldap, bind_dn, bind_password).await? {
+				return Err!("LDAP service account credentials were rejected.");
+			}
+		}
+
+		let base_dn = config.base_dn.as_deref().unwrap_or_default();
+		let filter = config.filter.replace("{username}", &ldap_escape(username));
+		let Some(entry) = self
+			.search(&mut ldap, base_dn, Scope::Subtree, &filter)
+			.await?
+		else {
+			debug!("User not found in LDAP directory");
+			return Ok(None);
+		};
+
+		if !bind(&mut ldap, &entry.dn, password).await? {
+			return Ok(None);
+		}
+
+		entry
+	};
+
+	ldap.unbind().await.ok();
+
+	Ok(Some(entry))
+}
+
+/// Checks the password of a local account, such as for user-interactive
+/// authentication. Returns whether LDAP accepted it, or None if the local
+/// password should be checked instead.
+#[implement(Service)]
+pub async fn check_password(&self, user_id: &UserId, password: &str) -> Result<Option<bool>> {
+	if !self.is_enabled() || user_id.server_name() != self.server.name {
+		return Ok(None);
+	}
+
+	let accepted = self
+		.authenticate(user_id.localpart(), password)
+		.await?
+		.is_some();
+
+	Ok(password_outcome(accepted, self.server.config.ldap.allow_local_password))
+}
+
+/// Whether a password was accepted given LDAP's answer, or None if the local
+/// password may still be checked.
+#[must_use]
+pub fn password_outcome(accepted: bool, allow_local_password: bool) -> Option<bool> {
+	(accepted || !allow_local_password).then_some(accepted)
+}
+
+#[implement(Service)]
+async fn connect(&self) -> Result<Ldap> {
+	let config = &self.server.config.ldap;
+	let uri = config
+		.uri
+		.as_ref()
+		.ok_or_else(|| err!(FeatureDisabled("ldap")))?;
+
+	let settings = LdapConnSettings::new()
+		.set_conn_timeout(CONNECT_TIMEOUT)
+		.set_starttls(config.starttls)
+		.set_no_tls_verify(!config.verify_tls);
+
+	let (conn, ldap) = LdapConnAsync::with_settings(settings, uri.as_str())
+		.await
+		.map_err(|e| err!("Failed to connect to LDAP server: {e}"))?;
+
+	self.server.runtime().spawn(async move {
+		if let Err(e) = conn.drive().await {
+			debug_warn!("LDAP connection failed: {e}");
+		}
+	});
+
+	Ok(ldap)
+}
+
+#[implement(Service)]
+async fn search(
+	&self,
+	ldap: &mut Ldap,
+	base: &str,
+	scope: Scope,
+	filter: &str,
+) -> Result<Option<Entry>> {
+	let config = &self.server.config.ldap;
+	let attrs = [config.displayname_attribute.as_str(), config.email_attribute.as_str()];
+
+	let (entries, _) = ldap
+		.search(base, scope, filter, attrs)
+		.await
+		.and_then(ldap3::SearchResult::success)
+		.map_err(|e| err!("LDAP search failed: {e}"))?;
+
+	if entries.len() > 1 {
+		return Err!("LDAP search for {filter:?} matched more than one entry.");
+	}
+
+	Ok(entries.into_iter().next().map(|entry| {
+		let mut entry = SearchEntry::construct(entry);
+		let mut attr = |name: &str| {
+			entry
+				.attrs
+				.remove(name)
+				.and_then(|values| values.into_iter().next())
+				.filter(|value| !value.is_empty())
+		};
+
+		Entry {
+			displayname: attr(&config.displayname_attribute),
+			email: attr(&config.email_attribute),
+			dn: entry.dn,
+		}
+	}))
+}
+
+/// Binds to the server, returning whether the credentials were accepted.
+async fn bind(ldap: &mut Ldap, dn: &str, password: &str) -> Result<bool> {
+	let result = ldap
+		.simple_bind(dn, password)
+		.await
+		.map_err(|e| err!("LDAP bind failed: {e}"))?;
+
+	match result.rc {
+		| 0 => Ok(true),
+		| INVALID_CREDENTIALS => Ok(false),
+		| _ => Err!("LDAP bind failed: {result}"),
+	}
+}
@@ -0,0 +1,19 @@
+#![cfg(test)]
+
+use super::password_outcome;
+
+#[test]
+fn returning_user_accepted_by_ldap() {
+	assert_eq!(password_outcome(true, false), Some(true));
+	assert_eq!(password_outcome(true, true), Some(true));
+}
+
+#[test]
+fn rejected_by_ldap_without_local_password() {
+	assert_eq!(password_outcome(false, false), Some(false));
+}
+
+#[test]
+fn rejected_by_ldap_falls_back_to_local_password() {
+	assert_eq!(password_outcome(false, true), None);
+}
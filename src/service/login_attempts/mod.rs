@@ -0,0 +1,197 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt,
+	net::IpAddr,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{implement, warn, Error, Result, Server};
+use http::StatusCode;
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	OwnedUserId, UserId,
+};
+
+pub struct Service {
+	server: Arc<Server>,
+	state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+	/// Times of recent failed attempts, oldest first.
+	failures: HashMap<Target, VecDeque<Instant>>,
+
+	/// When each lockout ends.
+	lockouts: HashMap<Target, Instant>,
+}
+
+/// What failed password attempts are counted against.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Target {
+	User(OwnedUserId),
+	Ip(IpAddr),
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			server: args.server.clone(),
+			state: Mutex::default(),
+		}))
+	}
+
+	fn memory_usage(&self, out: &mut dyn fmt::Write) -> Result {
+		let state = self.state.lock()?;
+		writeln!(out, "login_attempt_failures: {}", state.failures.len())?;
+		writeln!(out, "login_attempt_lockouts: {}", state.lockouts.len())?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Refuses a password attempt if the account or client IP address is
+/// locked out after too many failed attempts.
+#[implement(Service)]
+pub fn check(&self, user_id: Option<&UserId>, ip: Option<IpAddr>) -> Result {
+	let mut state = self.state.lock().expect("locked");
+	let now = Instant::now();
+	state.lockouts.retain(|_, until| *until > now);
+
+	let remaining = targets(user_id, ip)
+		.filter_map(|target| state.lockouts.get(&target))
+		.map(|until| until.saturating_duration_since(now))
+		.max();
+
+	if let Some(remaining) = remaining {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded {
+				retry_after: Some(RetryAfter::Delay(remaining)),
+			},
+			"Too many failed login attempts; try again later.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	Ok(())
+}
+
+/// Counts a failed password attempt, locking out the account or client IP
+/// address once it has failed too often within the window.
+#[implement(Service)]
+pub fn record_failure(&self, user_id: Option<&UserId>, ip: Option<IpAddr>) {
+	let config = &self.server.config;
+	let window = Duration::from_secs(config.login_failure_window);
+	let lockout = Duration::from_secs(config.login_lockout_duration);
+
+	let mut state = self.state.lock().expect("locked");
+	let now = Instant::now();
+	state.failures.retain(|_, failures| {
+		failures
+			.back()
+			.is_some_and(|&at| now.duration_since(at) < window)
+	});
+
+	for target in targets(user_id, ip) {
+		let limit = match target {
+			| Target::User(_) => config.login_failures_per_account,
+			| Target::Ip(_) => config.login_failures_per_ip,
+		};
+
+		if limit == 0 {
+			continue;
+		}
+
+		let failures = state.failures.entry(target.clone()).or_default();
+		while failures
+			.front()
+			.is_some_and(|&at| now.duration_since(at) >= window)
+		{
+			failures.pop_front();
+		}
+
+		failures.push_back(now);
+		if failures.len() < usize::try_from(limit).unwrap_or(usize::MAX) {
+			continue;
+		}
+
+		state.failures.remove(&target);
+		if let Some(until) = now.checked_add(lockout) {
+			warn!(%target, "Locked out for {lockout:?} after {limit} failed login attempts");
+			state.lockouts.insert(target, until);
+		}
+	}
+}
+
+/// Forgets an account's failed attempts once it logs in successfully.
+#[implement(Service)]
+pub fn record_success(&self, user_id: &UserId) {
+	self.state
+		.lock()
+		.expect("locked")
+		.failures
+		.remove(&Target::User(user_id.to_owned()));
+}
+
+/// Active lockouts with the time remaining on each.
+#[implement(Service)]
+#[must_use]
+pub fn lockouts(&self) -> Vec<(Target, Duration)> {
+	let now = Instant::now();
+	self.state
+		.lock()
+		.expect("locked")
+		.lockouts
+		.iter()
+		.filter(|(_, until)| **until > now)
+		.map(|(target, until)| (target.clone(), until.saturating_duration_since(now)))
+		.collect()
+}
+
+/// Lifts a lockout and forgets the failed attempts of an account or IP
+/// address, returning whether it was locked out.
+#[implement(Service)]
+pub fn clear(&self, target: &Target) -> bool {
+	let mut state = self.state.lock().expect("locked");
+	state.failures.remove(target);
+	state
+		.lockouts
+		.remove(target)
+		.is_some_and(|until| until > Instant::now())
+}
+
+/// Lifts all lockouts and forgets all failed attempts, returning the
+/// number of lockouts lifted.
+#[implement(Service)]
+pub fn clear_all(&self) -> usize {
+	let mut state = self.state.lock().expect("locked");
+	let now = Instant::now();
+	let lifted = state
+		.lockouts
+		.values()
+		.filter(|until| **until > now)
+		.count();
+	state.failures.clear();
+	state.lockouts.clear();
+
+	lifted
+}
+
+impl fmt::Display for Target {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::User(user_id) => write!(f, "{user_id}"),
+			| Self::Ip(ip) => write!(f, "{ip}"),
+		}
+	}
+}
+
+fn targets(user_id: Option<&UserId>, ip: Option<IpAddr>) -> impl Iterator<Item = Target> {
+	user_id
+		.map(|user_id| Target::User(user_id.to_owned()))
+		.into_iter()
+		.chain(ip.map(Target::Ip))
+}
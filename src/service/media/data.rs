@@ -14,6 +14,7 @@ use super::{preview::UrlPreviewData, thumbnail::Dim};
 pub(crate) struct Data {
 	mediaid_file: Arc<Map>,
 	mediaid_user: Arc<Map>,
+	quarantinedmediaids: Arc<Map>,
 	url_previews: Arc<Map>,
 }
 
@@ -29,6 +30,7 @@ impl Data {
 		Self {
 			mediaid_file: db["mediaid_file"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
+			quarantinedmediaids: db["quarantinedmediaids"].clone(),
 			url_previews: db["url_previews"].clone(),
 		}
 	}
@@ -80,6 +82,18 @@ impl Data {
 			.await;
 	}
 
+	pub(super) fn set_quarantined(&self, mxc: &Mxc<'_>, quarantined: bool) {
+		if quarantined {
+			self.quarantinedmediaids.put_raw(mxc, []);
+		} else {
+			self.quarantinedmediaids.del(mxc);
+		}
+	}
+
+	pub(super) async fn is_quarantined(&self, mxc: &Mxc<'_>) -> bool {
+		self.quarantinedmediaids.qry(mxc).await.is_ok()
+	}
+
 	/// Searches for all files with the given MXC
 	pub(super) async fn search_mxc_metadata_prefix(&self, mxc: &Mxc<'_>) -> Result<Vec<Vec<u8>>> {
 		debug!("MXC URI: {mxc}");
@@ -39,7 +39,9 @@ pub(crate) async fn migrate_sha256_media(services: &Services) -> Result<()> {
 		.await;
 
 	// move the file to the new location
-	for (old_path, path) in changes {
+	let total = changes.len();
+	for (i, (old_path, path)) in changes.into_iter().enumerate() {
+		migrations::progress("sha256_media", i, Some(total));
 		if old_path.exists() {
 			tokio::fs::rename(&old_path, &path).await?;
 			if config.media_compat_file_link {
@@ -55,7 +57,7 @@ pub(crate) async fn migrate_sha256_media(services: &Services) -> Result<()> {
 	}
 
 	db["global"].insert(b"feat_sha256_media", []);
-	info!("Finished applying sha256_media");
+	info!("Finished applying sha256_media to {total} media files");
 	Ok(())
 }
 
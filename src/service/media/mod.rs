@@ -15,6 +15,7 @@ use conduwuit::{
 	warn, Err, Result, Server,
 };
 use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, UserId};
+use serde_json::Value as JsonValue;
 use tokio::{
 	fs,
 	io::{AsyncReadExt, AsyncWriteExt, BufReader},
@@ -152,8 +153,35 @@ impl Service {
 		Ok(deletion_count)
 	}
 
+	/// Gets the MXC URIs of all media uploaded by a user
+	pub async fn get_all_user_mxcs(&self, user: &UserId) -> Vec<OwnedMxcUri> {
+		self.db.get_all_user_mxcs(user).await
+	}
+
+	/// Gets the filesystem metadata, such as the size, of a stored file
+	pub async fn get_file_stat(&self, mxc: &Mxc<'_>) -> Result<std::fs::Metadata> {
+		let Metadata { key, .. } = self.db.search_file_metadata(mxc, &Dim::default()).await?;
+
+		Ok(fs::metadata(self.get_media_file(&key)).await?)
+	}
+
+	/// Quarantines a file, or lifts its quarantine. Quarantined files are
+	/// kept, but refused to anyone asking for them, including other servers.
+	pub fn quarantine(&self, mxc: &Mxc<'_>, quarantined: bool) {
+		self.db.set_quarantined(mxc, quarantined);
+	}
+
+	#[inline]
+	pub async fn is_quarantined(&self, mxc: &Mxc<'_>) -> bool {
+		self.db.is_quarantined(mxc).await
+	}
+
 	/// Downloads a file.
 	pub async fn get(&self, mxc: &Mxc<'_>) -> Result<Option<FileMeta>> {
+		if self.is_quarantined(mxc).await {
+			return Err!(Request(NotFound("Media not found")));
+		}
+
 		if let Ok(Metadata { content_disposition, content_type, key }) =
 			self.db.search_file_metadata(mxc, &Dim::default()).await
 		{
@@ -427,3 +455,23 @@ impl Service {
 #[inline]
 #[must_use]
 pub fn encode_key(key: &[u8]) -> String { general_purpose::URL_SAFE_NO_PAD.encode(key) }
+
+/// MXC URIs of the files an event's content refers to, including encrypted
+/// files and thumbnails.
+#[must_use]
+pub fn content_mxcs(content: &JsonValue) -> Vec<OwnedMxcUri> {
+	let info = content.get("info");
+	[
+		content.get("url"),
+		content.get("file").and_then(|file| file.get("url")),
+		info.and_then(|info| info.get("thumbnail_url")),
+		info.and_then(|info| info.get("thumbnail_file"))
+			.and_then(|file| file.get("url")),
+	]
+	.into_iter()
+	.flatten()
+	.filter_map(JsonValue::as_str)
+	.map(OwnedMxcUri::from)
+	.filter(|mxc| mxc.is_valid())
+	.collect()
+}
@@ -1,10 +1,11 @@
-use std::cmp;
+use std::{cmp, time::Duration};
 
 use conduwuit::{
 	debug, debug_info, debug_warn, error, info,
 	result::NotFound,
 	utils::{
 		stream::{TryExpect, TryIgnore},
+		sys::systemd,
 		IterStream, ReadyExt,
 	},
 	warn, Err, Result,
@@ -15,7 +16,6 @@ use ruma::{
 	events::{
 		push_rules::PushRulesEvent, room::member::MembershipState, GlobalAccountDataEventType,
 	},
-	push::Ruleset,
 	OwnedUserId, RoomId, UserId,
 };
 
@@ -37,6 +37,16 @@ pub(crate) const DATABASE_VERSION: u64 = 13;
 /// compatibility we'll check for both versions.
 pub(crate) const CONDUIT_DATABASE_VERSION: u64 = 16;
 
+/// Time the service manager is asked to wait for startup to make progress
+/// while a migration is running; renewed each time progress is reported.
+const MIGRATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Number of records between progress reports for migrations of unknown size.
+const PROGRESS_INTERVAL: usize = 65536;
+
+/// Key of the push rule configuration last applied to every user's push rules.
+const PUSH_RULES_CONFIG_KEY: &[u8] = b"server_default_push_rules";
+
 pub(crate) async fn migrations(services: &Services) -> Result<()> {
 	let users_count = services.users.count().await;
 
@@ -53,6 +63,7 @@ pub(crate) async fn migrations(services: &Services) -> Result<()> {
 		}
 	}
 
+	systemd::status("Running database migrations");
 	if users_count > 0 {
 		migrate(services).await
 	} else {
@@ -60,6 +71,28 @@ pub(crate) async fn migrations(services: &Services) -> Result<()> {
 	}
 }
 
+/// Logs the progress of a long-running migration and reports it to the service
+/// manager, extending the startup timeout so it isn't killed mid-migration.
+/// Reports are made at each whole percent when the total is known, or
+/// periodically otherwise.
+pub(crate) fn progress(migration: &str, done: usize, total: Option<usize>) {
+	let percent =
+		|done: usize, total: usize| done.saturating_mul(100).checked_div(total).unwrap_or(100);
+
+	let status = match total {
+		| Some(total)
+			if done == 0 || percent(done, total) != percent(done.saturating_sub(1), total) =>
+			format!("Running migration {migration} at {}%", percent(done, total)),
+		| None if done % PROGRESS_INTERVAL == 0 =>
+			format!("Running migration {migration} ({done} records processed)"),
+		| _ => return,
+	};
+
+	info!("{status}");
+	systemd::status(&status);
+	systemd::extend_timeout(MIGRATION_TIMEOUT);
+}
+
 async fn fresh(services: &Services) -> Result<()> {
 	let db = &services.db;
 
@@ -73,6 +106,7 @@ async fn fresh(services: &Services) -> Result<()> {
 	db["global"].insert(b"retroactively_fix_bad_data_from_roomuserid_joined", []);
 	db["global"].insert(b"fix_referencedevents_missing_sep", []);
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
+	db["global"].insert(PUSH_RULES_CONFIG_KEY, push_rules_config(services));
 
 	// Create the admin room and server user on first run
 	crate::admin::create_admin_room(services).boxed().await?;
@@ -104,9 +138,13 @@ async fn migrate(services: &Services) -> Result<()> {
 		db_lt_13(services).await?;
 	}
 
+	apply_push_rules_config(services).await?;
+
 	if db["global"].get(b"feat_sha256_media").await.is_not_found() {
+		progress("sha256_media", 0, None);
 		media::migrations::migrate_sha256_media(services).await?;
 	} else if config.media_startup_check {
+		progress("media_startup_check", 0, None);
 		media::migrations::checkup_sha256_media(services).await?;
 	}
 
@@ -218,13 +256,15 @@ async fn migrate(services: &Services) -> Result<()> {
 }
 
 async fn db_lt_12(services: &Services) -> Result<()> {
-	for username in &services
+	let usernames: Vec<_> = services
 		.users
 		.list_local_users()
 		.map(UserId::to_owned)
-		.collect::<Vec<_>>()
-		.await
-	{
+		.collect()
+		.await;
+
+	for (i, username) in usernames.iter().enumerate() {
+		progress("11 -> 12", i, Some(usernames.len()));
 		let user = match UserId::parse_with_server_name(username.as_str(), &services.server.name)
 		{
 			| Ok(u) => u,
@@ -296,13 +336,15 @@ async fn db_lt_12(services: &Services) -> Result<()> {
 }
 
 async fn db_lt_13(services: &Services) -> Result<()> {
-	for username in &services
+	let usernames: Vec<_> = services
 		.users
 		.list_local_users()
 		.map(UserId::to_owned)
-		.collect::<Vec<_>>()
-		.await
-	{
+		.collect()
+		.await;
+
+	for (i, username) in usernames.iter().enumerate() {
+		progress("12 -> 13", i, Some(usernames.len()));
 		let user = match UserId::parse_with_server_name(username.as_str(), &services.server.name)
 		{
 			| Ok(u) => u,
@@ -318,7 +360,7 @@ async fn db_lt_13(services: &Services) -> Result<()> {
 			.await
 			.expect("Username is invalid");
 
-		let user_default_rules = Ruleset::server_default(&user);
+		let user_default_rules = services.pusher.server_default_rules(&user);
 		account_data
 			.content
 			.global
@@ -340,6 +382,68 @@ async fn db_lt_13(services: &Services) -> Result<()> {
 	Ok(())
 }
 
+fn push_rules_config(services: &Services) -> Vec<u8> {
+	let config = &services.server.config;
+	serde_json::to_vec(&(&config.push_rules_remove_defaults, &config.push_rules_keywords))
+		.expect("push rule config can be serialized")
+}
+
+/// Brings every local user's server-default push rules up to date when
+/// `push_rules_remove_defaults` or `push_rules_keywords` have changed.
+async fn apply_push_rules_config(services: &Services) -> Result<()> {
+	let db = &services.db;
+	let config = &services.server.config;
+	let current = push_rules_config(services);
+
+	let unchanged = match db["global"].get(PUSH_RULES_CONFIG_KEY).await {
+		| Ok(applied) => *applied == *current,
+		// Never configured before; everyone already has the stock rules
+		| Err(_) =>
+			config.push_rules_remove_defaults.is_empty() && config.push_rules_keywords.is_empty(),
+	};
+
+	if !unchanged {
+		let users: Vec<OwnedUserId> = services
+			.users
+			.list_local_users()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for (i, user) in users.iter().enumerate() {
+			progress("push_rules_config", i, Some(users.len()));
+			let Ok(mut account_data) = services
+				.account_data
+				.get_global::<PushRulesEvent>(user, GlobalAccountDataEventType::PushRules)
+				.await
+			else {
+				continue;
+			};
+
+			account_data
+				.content
+				.global
+				.update_with_server_default(services.pusher.server_default_rules(user));
+
+			services
+				.account_data
+				.update(
+					None,
+					user,
+					GlobalAccountDataEventType::PushRules.to_string().into(),
+					&serde_json::to_value(account_data).expect("to json value always works"),
+				)
+				.await?;
+		}
+
+		info!("Applied server-default push rule changes to {} users", users.len());
+	}
+
+	db["global"].insert(PUSH_RULES_CONFIG_KEY, current);
+
+	Ok(())
+}
+
 async fn fix_bad_double_separator_in_state_cache(services: &Services) -> Result<()> {
 	warn!("Fixing bad double separator in state_cache roomuserid_joined");
 
@@ -355,6 +459,7 @@ async fn fix_bad_double_separator_in_state_cache(services: &Services) -> Result<
 			let mut key = key.to_vec();
 			iter_count = iter_count.saturating_add(1);
 			debug_info!(%iter_count);
+			progress("fix_bad_double_separator_in_state_cache", iter_count, None);
 			let first_sep_index = key
 				.iter()
 				.position(|&i| i == 0xFF)
@@ -398,8 +503,9 @@ async fn retroactively_fix_bad_data_from_roomuserid_joined(services: &Services)
 		.collect::<Vec<_>>()
 		.await;
 
-	for room_id in &room_ids {
+	for (i, room_id) in room_ids.iter().enumerate() {
 		debug_info!("Fixing room {room_id}");
+		progress("retroactively_fix_bad_data_from_roomuserid_joined", i, Some(room_ids.len()));
 
 		let users_in_room: Vec<OwnedUserId> = services
 			.rooms
@@ -485,6 +591,7 @@ async fn fix_referencedevents_missing_sep(services: &Services) -> Result {
 		.enumerate()
 		.ready_fold(totals, |mut a, (i, (key, val))| {
 			debug_assert!(val.is_empty(), "expected no value");
+			progress("fix_referencedevents_missing_sep", i, None);
 
 			let has_sep = key.contains(&database::SEP);
 
@@ -552,6 +659,7 @@ async fn fix_readreceiptid_readreceipt_duplicates(services: &Services) -> Result
 
 			fixed = fixed.saturating_add(is_dup.into());
 			total = total.saturating_add(1);
+			progress("fix_readreceiptid_readreceipt_duplicates", total, None);
 		})
 		.await;
 
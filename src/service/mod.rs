@@ -6,22 +6,39 @@ mod service;
 pub mod services;
 
 pub mod account_data;
+pub mod account_validity;
 pub mod admin;
 pub mod appservice;
 pub mod client;
 pub mod config;
+pub mod delegated_auth;
+pub mod email;
 pub mod emergency;
+pub mod export;
 pub mod federation;
 pub mod globals;
+pub mod ipban;
+pub mod jwt;
 pub mod key_backups;
+pub mod ldap;
+pub mod login_attempts;
 pub mod media;
+pub mod oidc;
 pub mod presence;
 pub mod pusher;
+pub mod ratelimit;
+pub mod registration_tokens;
+pub mod rendezvous;
+pub mod replica;
 pub mod resolver;
 pub mod rooms;
+pub mod scheduler;
 pub mod sending;
 pub mod server_keys;
+pub mod server_notices;
+pub mod spam_checker;
 pub mod sync;
+pub mod threepid;
 pub mod transaction_ids;
 pub mod uiaa;
 pub mod updates;
@@ -0,0 +1,308 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use conduwuit::{debug, debug_info, err, implement, utils, Err, Result, Server};
+use database::{Deserialized, Map};
+use ruma::{OwnedUserId, UserId};
+use serde::Deserialize;
+use serde_json::{Map as JsonObject, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::{client, Dep};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	provider: RwLock<Option<Arc<Provider>>>,
+	sessions: Mutex<HashMap<String, Session>>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+}
+
+struct Data {
+	oidcsubject_userid: Arc<Map>,
+}
+
+/// Endpoints of the provider from its discovery document.
+#[derive(Debug, Deserialize)]
+struct Provider {
+	issuer: String,
+	authorization_endpoint: Url,
+	token_endpoint: Url,
+	userinfo_endpoint: Url,
+}
+
+/// A login in progress at the provider, keyed by its `state` parameter.
+struct Session {
+	redirect_url: String,
+	callback_url: Url,
+	code_verifier: String,
+	expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	access_token: String,
+}
+
+/// The identity asserted by the provider once a login completes.
+#[derive(Debug)]
+pub struct Identity {
+	/// The provider's stable identifier for the user (`sub` claim).
+	pub subject: String,
+
+	/// Localpart for a new account from the configured claim, sanitized.
+	pub localpart: Option<String>,
+
+	pub displayname: Option<String>,
+
+	/// Where the client asked to be sent once logged in.
+	pub redirect_url: String,
+}
+
+const STATE_LENGTH: usize = 32;
+
+const CODE_VERIFIER_LENGTH: usize = 64;
+
+const SESSION_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+			},
+			db: Data {
+				oidcsubject_userid: args.db["oidcsubject_userid"].clone(),
+			},
+			provider: RwLock::new(None),
+			sessions: Mutex::new(HashMap::new()),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether single sign-on through an OpenID Connect provider is enabled.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.services.server.config.oidc.is_enabled() }
+
+/// Starts a login at the provider, returning the `state` identifying it and
+/// the URL to send the user's browser to. The provider redirects back to
+/// `callback_url` once the user has authenticated.
+#[implement(Service)]
+pub async fn authorization_url(
+	&self,
+	redirect_url: &str,
+	callback_url: Url,
+) -> Result<(String, Url)> {
+	let config = &self.services.server.config.oidc;
+	let client_id = config
+		.client_id
+		.as_deref()
+		.ok_or_else(|| err!(Request(Unrecognized("SSO login is not enabled."))))?;
+
+	let provider = self.provider().await?;
+	let state = utils::random_string(STATE_LENGTH);
+	let code_verifier = utils::random_string(CODE_VERIFIER_LENGTH);
+	let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(&code_verifier));
+
+	let mut url = provider.authorization_endpoint.clone();
+	url.query_pairs_mut()
+		.append_pair("response_type", "code")
+		.append_pair("client_id", client_id)
+		.append_pair("redirect_uri", callback_url.as_str())
+		.append_pair("scope", &config.scopes.join(" "))
+		.append_pair("state", &state)
+		.append_pair("code_challenge", &code_challenge)
+		.append_pair("code_challenge_method", "S256");
+
+	let session = Session {
+		redirect_url: redirect_url.to_owned(),
+		callback_url,
+		code_verifier,
+		expires_at: Instant::now()
+			.checked_add(SESSION_LIFETIME)
+			.expect("session expiry does not overflow"),
+	};
+
+	let mut sessions = self.sessions.lock().expect("locked");
+	sessions.retain(|_, session| session.expires_at > Instant::now());
+	sessions.insert(state.clone(), session);
+
+	Ok((state, url))
+}
+
+/// Completes a login when the provider redirects back with an authorization
+/// code, exchanging it for the user's claims. Claims are requested from the
+/// userinfo endpoint over TLS, so the ID token is not inspected.
+#[implement(Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn complete(&self, state: &str, code: &str) -> Result<Identity> {
+	let config = &self.services.server.config.oidc;
+	let (Some(client_id), Some(client_secret)) =
+		(config.client_id.as_deref(), config.client_secret.as_deref())
+	else {
+		return Err!(Request(Unrecognized("SSO login is not enabled.")));
+	};
+
+	let session = self
+		.sessions
+		.lock()
+		.expect("locked")
+		.remove(state)
+		.filter(|session| session.expires_at > Instant::now())
+		.ok_or_else(|| {
+			err!(Request(Forbidden("SSO login session is unknown or has expired.")))
+		})?;
+
+	let provider = self.provider().await?;
+	let body = self
+		.services
+		.client
+		.default
+		.post(provider.token_endpoint.clone())
+		.basic_auth(client_id, Some(client_secret))
+		.form(&[
+			("grant_type", "authorization_code"),
+			("code", code),
+			("redirect_uri", session.callback_url.as_str()),
+			("code_verifier", &session.code_verifier),
+		])
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let token: TokenResponse = serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid OpenID Connect token response: {e}")))?;
+
+	let body = self
+		.services
+		.client
+		.default
+		.get(provider.userinfo_endpoint.clone())
+		.bearer_auth(&token.access_token)
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let claims: JsonObject<String, JsonValue> = serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid OpenID Connect userinfo response: {e}")))?;
+
+	let claim = |name: &str| {
+		claims
+			.get(name)
+			.and_then(JsonValue::as_str)
+			.filter(|value| !value.is_empty())
+			.map(ToOwned::to_owned)
+	};
+
+	let subject = claim("sub").ok_or_else(|| {
+		err!(BadServerResponse("OpenID Connect userinfo response has no subject."))
+	})?;
+
+	debug!(%subject, "OpenID Connect login completed");
+
+	Ok(Identity {
+		localpart: claim(&config.localpart_claim)
+			.as_deref()
+			.and_then(sanitize_localpart),
+		displayname: claim(&config.displayname_claim),
+		redirect_url: session.redirect_url,
+		subject,
+	})
+}
+
+/// Finds the local user previously provisioned for a subject at the provider.
+#[implement(Service)]
+pub async fn find_user(&self, subject: &str) -> Result<OwnedUserId> {
+	let key = (self.issuer()?, subject);
+	self.db.oidcsubject_userid.qry(&key).await.deserialized()
+}
+
+/// Links a subject at the provider to a local user, so later logins with it
+/// sign in as that user.
+#[implement(Service)]
+pub fn link_user(&self, subject: &str, user_id: &UserId) -> Result {
+	let key = (self.issuer()?, subject);
+	self.db.oidcsubject_userid.put(key, user_id);
+
+	debug_info!(%subject, %user_id, "Linked OpenID Connect subject to user");
+
+	Ok(())
+}
+
+/// Discovers the provider's endpoints, which are cached once found.
+#[implement(Service)]
+async fn provider(&self) -> Result<Arc<Provider>> {
+	if let Some(provider) = self.provider.read().await.as_ref() {
+		return Ok(provider.clone());
+	}
+
+	let issuer = self.issuer()?;
+	let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+	let body = self
+		.services
+		.client
+		.default
+		.get(url)
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let provider: Provider = serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid OpenID Connect discovery document: {e}")))?;
+
+	if provider.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+		return Err!(BadServerResponse(
+			"OpenID Connect discovery document is for issuer {:?}, not {issuer:?}.",
+			provider.issuer
+		));
+	}
+
+	let provider = Arc::new(provider);
+	*self.provider.write().await = Some(provider.clone());
+
+	Ok(provider)
+}
+
+#[implement(Service)]
+fn issuer(&self) -> Result<&str> {
+	self.services
+		.server
+		.config
+		.oidc
+		.issuer
+		.as_ref()
+		.map(Url::as_str)
+		.ok_or_else(|| err!(Request(Unrecognized("SSO login is not enabled."))))
+}
+
+/// Reduces a claim to the characters allowed in a user ID localpart.
+fn sanitize_localpart(claim: &str) -> Option<String> {
+	let localpart: String = claim
+		.to_lowercase()
+		.chars()
+		.filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._=-/".contains(*c))
+		.collect();
+
+	(!localpart.is_empty()).then_some(localpart)
+}
@@ -6,8 +6,8 @@ use conduwuit::{
 	Result,
 };
 use database::{Deserialized, Json, Map};
-use futures::Stream;
-use ruma::{events::presence::PresenceEvent, presence::PresenceState, UInt, UserId};
+use futures::{Stream, StreamExt};
+use ruma::{events::presence::PresenceEvent, presence::PresenceState, OwnedUserId, UInt, UserId};
 
 use super::Presence;
 use crate::{globals, users, Dep};
@@ -52,6 +52,8 @@ impl Data {
 		Ok((count, event))
 	}
 
+	/// Returns whether the presence was stored, rather than dropped as
+	/// unchanged.
 	pub(super) async fn set_presence(
 		&self,
 		user_id: &UserId,
@@ -59,7 +61,7 @@ impl Data {
 		currently_active: Option<bool>,
 		last_active_ago: Option<UInt>,
 		status_msg: Option<String>,
-	) -> Result<()> {
+	) -> Result<bool> {
 		let last_presence = self.get_presence(user_id).await;
 		let state_changed = match last_presence {
 			| Err(_) => true,
@@ -100,7 +102,7 @@ impl Data {
 				"presence spam {user_id:?} last_active_ts:{last_active_ts:?} < \
 				 {last_last_active_ts:?}",
 			);
-			return Ok(());
+			return Ok(false);
 		}
 
 		let status_msg = if status_msg.as_ref().is_some_and(String::is_empty) {
@@ -127,7 +129,7 @@ impl Data {
 			self.presenceid_presence.remove(&key);
 		}
 
-		Ok(())
+		Ok(true)
 	}
 
 	pub(super) async fn remove_presence(&self, user_id: &UserId) {
@@ -145,6 +147,42 @@ impl Data {
 		self.userid_presenceid.remove(user_id);
 	}
 
+	/// Deletes the presence of remote users who were last active before
+	/// `cutoff`, returning how many were deleted.
+	pub(super) async fn expire_remote(&self, cutoff: u64) -> usize {
+		let expired: Vec<(Vec<u8>, u64, OwnedUserId)> = self
+			.presenceid_presence
+			.raw_stream()
+			.ignore_err()
+			.ready_filter_map(|(key, presence)| {
+				let (count, user_id) = presenceid_parse(key).ok()?;
+				if self.services.globals.user_is_local(user_id) {
+					return None;
+				}
+
+				let presence = Presence::from_json_bytes(presence).ok()?;
+				(presence.last_active_ts() < cutoff)
+					.then(|| (key.to_vec(), count, user_id.to_owned()))
+			})
+			.collect()
+			.await;
+
+		for (key, count, user_id) in &expired {
+			self.presenceid_presence.remove(key);
+			let current = self
+				.userid_presenceid
+				.get(user_id)
+				.await
+				.deserialized::<u64>();
+
+			if current.is_ok_and(|current| current == *count) {
+				self.userid_presenceid.remove(user_id);
+			}
+		}
+
+		expired.len()
+	}
+
 	#[inline]
 	pub(super) fn presence_since(
 		&self,
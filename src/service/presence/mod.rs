@@ -5,16 +5,23 @@ use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use conduwuit::{
-	checked, debug, debug_warn, error, result::LogErr, trace, Error, Result, Server,
+	checked, debug, debug_info, debug_warn, error, result::LogErr, trace, utils, Error, Result,
+	Server,
 };
 use database::Database;
 use futures::{stream::FuturesUnordered, Stream, StreamExt, TryFutureExt};
 use loole::{Receiver, Sender};
-use ruma::{events::presence::PresenceEvent, presence::PresenceState, OwnedUserId, UInt, UserId};
-use tokio::time::sleep;
+use ruma::{
+	api::appservice::event::push_events::v1::EphemeralData,
+	events::{presence::PresenceEvent, GlobalAccountDataEventType},
+	presence::PresenceState,
+	OwnedUserId, UInt, UserId,
+};
+use serde::Deserialize;
+use tokio::time::{interval, sleep, MissedTickBehavior};
 
 use self::{data::Data, presence::Presence};
-use crate::{globals, users, Dep};
+use crate::{account_data, globals, sending, sending::EduBuf, users, Dep};
 
 pub struct Service {
 	timer_channel: (Sender<TimerType>, Receiver<TimerType>),
@@ -28,12 +35,31 @@ pub struct Service {
 struct Services {
 	server: Arc<Server>,
 	db: Arc<Database>,
+	account_data: Dep<account_data::Service>,
 	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
 	users: Dep<users::Service>,
 }
 
 type TimerType = (OwnedUserId, Duration);
 
+/// How often remote presence older than `presence_remote_ttl_s` is deleted.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Global account data event in which a user sets the presence they always
+/// appear with, whatever their clients do.
+#[derive(Deserialize)]
+struct PresenceOverrideEvent {
+	content: PresenceOverride,
+}
+
+#[derive(Deserialize)]
+struct PresenceOverride {
+	presence: PresenceState,
+}
+
+pub const PRESENCE_OVERRIDE_EVENT_TYPE: &str = "org.conduwuit.presence_override";
+
 #[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
@@ -49,7 +75,9 @@ impl crate::Service for Service {
 			services: Services {
 				server: args.server.clone(),
 				db: args.db.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
 				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
 				users: args.depend::<users::Service>("users"),
 			},
 		}))
@@ -57,6 +85,10 @@ impl crate::Service for Service {
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
 		let receiver = self.timer_channel.1.clone();
+		let remote_ttl = self.services.server.config.presence_remote_ttl_s;
+		let mut expire = interval(EXPIRE_INTERVAL);
+		expire.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		expire.reset_after(EXPIRE_INTERVAL);
 
 		let mut presence_timers = FuturesUnordered::new();
 		while !receiver.is_closed() {
@@ -64,6 +96,9 @@ impl crate::Service for Service {
 				Some(user_id) = presence_timers.next() => {
 					self.process_presence_timer(&user_id).await.log_err().ok();
 				},
+				_ = expire.tick(), if remote_ttl > 0 && !self.services.globals.is_read_only() => {
+					self.expire_remote_presence(remote_ttl).await;
+				},
 				event = receiver.recv_async() => match event {
 					Err(_) => break,
 					Ok((user_id, timeout)) => {
@@ -98,11 +133,33 @@ impl Service {
 	}
 
 	/// Pings the presence of the given user in the given room, setting the
-	/// specified state.
+	/// specified state. Pings to a read-only replica are ignored.
 	pub async fn ping_presence(&self, user_id: &UserId, new_state: &PresenceState) -> Result<()> {
+		if self.services.globals.is_read_only() {
+			return Ok(());
+		}
+
 		const REFRESH_TIMEOUT: u64 = 60 * 1000;
 
 		let last_presence = self.db.get_presence(user_id).await;
+		if let Some(presence_override) = self.presence_override(user_id).await {
+			let applied = last_presence
+				.as_ref()
+				.is_ok_and(|(_, presence)| presence.content.presence == presence_override);
+
+			if applied {
+				return Ok(());
+			}
+
+			let status_msg = last_presence
+				.ok()
+				.and_then(|(_, presence)| presence.content.status_msg);
+
+			return self
+				.set_presence(user_id, &presence_override, None, None, status_msg)
+				.await;
+		}
+
 		let state_changed = match last_presence {
 			| Err(_) => true,
 			| Ok((_, ref presence)) => presence.content.presence != *new_state,
@@ -143,10 +200,23 @@ impl Service {
 			| &_ => state,
 		};
 
-		self.db
+		// Users appearing offline or away never show activity, so their last
+		// active time is left at the epoch.
+		let presence_override = self.presence_override(user_id).await;
+		let (presence_state, currently_active, last_active_ago) = match presence_override {
+			| Some(ref presence_override) => (presence_override, Some(false), Some(UInt::MAX)),
+			| None => (presence_state, currently_active, last_active_ago),
+		};
+
+		let stored = self
+			.db
 			.set_presence(user_id, presence_state, currently_active, last_active_ago, status_msg)
 			.await?;
 
+		if stored {
+			_ = self.appservice_send(user_id).await.log_err();
+		}
+
 		if (self.timeout_remote_users || self.services.globals.user_is_local(user_id))
 			&& user_id != self.services.globals.server_user
 		{
@@ -167,6 +237,40 @@ impl Service {
 		Ok(())
 	}
 
+	/// Pushes the user's current presence to the appservices interested in
+	/// them.
+	async fn appservice_send(&self, user_id: &UserId) -> Result<()> {
+		let (_, event) = self.db.get_presence(user_id).await?;
+		let edu = EphemeralData::Presence(event);
+
+		let mut buf = EduBuf::new();
+		serde_json::to_writer(&mut buf, &edu).expect("Serialized EphemeralData::Presence");
+
+		self.services
+			.sending
+			.send_edu_appservice_user(user_id, buf)
+			.await
+	}
+
+	/// The presence a local user always appears with, set in their
+	/// `org.conduwuit.presence_override` account data. Only "offline" and
+	/// "unavailable" are honored.
+	pub async fn presence_override(&self, user_id: &UserId) -> Option<PresenceState> {
+		if !self.services.globals.user_is_local(user_id) {
+			return None;
+		}
+
+		let event: PresenceOverrideEvent = self
+			.services
+			.account_data
+			.get_global(user_id, GlobalAccountDataEventType::from(PRESENCE_OVERRIDE_EVENT_TYPE))
+			.await
+			.ok()?;
+
+		matches!(event.content.presence, PresenceState::Offline | PresenceState::Unavailable)
+			.then_some(event.content.presence)
+	}
+
 	/// Removes the presence record for the given user from the database.
 	///
 	/// TODO: Why is this not used?
@@ -246,6 +350,14 @@ impl Service {
 		Ok(event)
 	}
 
+	async fn expire_remote_presence(&self, ttl: u64) {
+		let cutoff = utils::millis_since_unix_epoch().saturating_sub(ttl.saturating_mul(1000));
+		let expired = self.db.expire_remote(cutoff).await;
+		if expired > 0 {
+			debug_info!(expired, "Deleted expired presence of remote users");
+		}
+	}
+
 	async fn process_presence_timer(&self, user_id: &OwnedUserId) -> Result<()> {
 		let mut presence_state = PresenceState::Offline;
 		let mut last_active_ago = None;
@@ -34,6 +34,9 @@ impl Presence {
 		}
 	}
 
+	#[inline]
+	pub(super) fn last_active_ts(&self) -> u64 { self.last_active_ts }
+
 	pub(super) fn from_json_bytes(bytes: &[u8]) -> Result<Self> {
 		serde_json::from_slice(bytes)
 			.map_err(|_| Error::bad_database("Invalid presence data in database"))
@@ -0,0 +1,377 @@
+use std::{
+	collections::{HashMap, HashSet},
+	sync::{atomic::Ordering, Arc},
+};
+
+use conduwuit::implement;
+use futures::StreamExt;
+use ruma::{
+	events::{
+		push_rules::PushRulesEvent, room::power_levels::RoomPowerLevelsEventContent,
+		AnySyncTimelineEvent, GlobalAccountDataEventType,
+	},
+	push::{Action, PushConditionPowerLevelsCtx, PushConditionRoomCtx, Ruleset, Tweak},
+	serde::Raw,
+	uint, OwnedUserId, RoomId, UserId,
+};
+use serde_json::Value;
+
+/// The parsed push rules and display names of a room's active local members,
+/// so events in large rooms don't load every member's rules from the
+/// database. Dropped when a member's push rules or the room's membership
+/// change.
+///
+/// Members whose push rules are the server's defaults apart from their
+/// keywords are only pushed differently from each other when an event
+/// mentions them: by their display name or a keyword in its body, in its
+/// `m.mentions`, or as its state key. Their display names and keywords are
+/// indexed by word, so only the members an event may mention have their rules
+/// evaluated, and the others share the actions of one of them.
+pub(super) struct RoomPushIndex {
+	members: Vec<IndexedMember>,
+
+	/// A word of each indexed member's display name and keywords, to the
+	/// members with it.
+	words: HashMap<String, Vec<usize>>,
+}
+
+pub(super) struct IndexedMember {
+	user_id: OwnedUserId,
+	display_name: String,
+	ruleset: Ruleset,
+
+	/// Whether the member's push rules are the server's defaults apart from
+	/// their keywords, and their display name and keywords could be indexed.
+	indexed: bool,
+}
+
+/// Whether the event notifies and highlights each active local member of the
+/// room other than the sender.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, power_levels, pdu), level = "debug")]
+pub async fn room_push_actions(
+	&self,
+	room_id: &RoomId,
+	sender: &UserId,
+	power_levels: &RoomPowerLevelsEventContent,
+	pdu: &Raw<AnySyncTimelineEvent>,
+) -> Vec<(OwnedUserId, bool, bool)> {
+	let index = self.room_push_index(room_id).await;
+	if index.members.iter().all(|member| member.user_id == sender) {
+		return Vec::new();
+	}
+
+	let ctx = self.room_push_ctx(room_id, power_levels).await;
+	index.push_actions(sender, pdu, &ctx)
+}
+
+/// Drops the cached push index of the room, e.g. after a membership change.
+#[implement(super::Service)]
+pub fn invalidate_room_push_index(&self, room_id: &RoomId) {
+	let mut cache = self.room_push_index.lock().expect("locked");
+	self.room_push_index_generation
+		.fetch_add(1, Ordering::AcqRel);
+	cache.remove(room_id);
+}
+
+/// Drops the cached push index of every room the user is indexed in, e.g.
+/// after their push rules changed.
+#[implement(super::Service)]
+pub fn invalidate_user_push_index(&self, user_id: &UserId) {
+	let mut cache = self.room_push_index.lock().expect("locked");
+	self.room_push_index_generation
+		.fetch_add(1, Ordering::AcqRel);
+	let rooms: Vec<_> = cache
+		.iter()
+		.filter(|(_, index)| index.members.iter().any(|member| member.user_id == user_id))
+		.map(|(room_id, _)| room_id.clone())
+		.collect();
+
+	for room_id in &rooms {
+		cache.remove(room_id);
+	}
+}
+
+/// The room's push index, built if it isn't cached. An index built while its
+/// room or one of its members was invalidated isn't cached, as it may have
+/// been built from what was invalidated.
+#[implement(super::Service)]
+async fn room_push_index(&self, room_id: &RoomId) -> Arc<RoomPushIndex> {
+	if let Some(index) = self
+		.room_push_index
+		.lock()
+		.expect("locked")
+		.get_mut(room_id)
+	{
+		return index.clone();
+	}
+
+	let generation = self.room_push_index_generation.load(Ordering::Acquire);
+	let users: Vec<OwnedUserId> = self
+		.services
+		.state_cache
+		.active_local_users_in_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut members = Vec::with_capacity(users.len());
+	for user_id in users {
+		let defaults = self.server_default_rules(&user_id);
+		let ruleset = self
+			.services
+			.account_data
+			.get_global(&user_id, GlobalAccountDataEventType::PushRules)
+			.await
+			.map_or_else(|_| defaults.clone(), |ev: PushRulesEvent| ev.content.global);
+
+		let display_name = self
+			.services
+			.users
+			.displayname(&user_id)
+			.await
+			.unwrap_or_else(|_| user_id.localpart().to_owned());
+
+		members.push(IndexedMember::new(user_id, display_name, ruleset, &defaults));
+	}
+
+	let index = Arc::new(RoomPushIndex::new(members));
+	let mut cache = self.room_push_index.lock().expect("locked");
+	if self.room_push_index_generation.load(Ordering::Acquire) == generation {
+		cache.insert(room_id.to_owned(), index.clone());
+	}
+
+	index
+}
+
+/// The parts of the push condition context which are the same for every
+/// member of the room.
+#[implement(super::Service)]
+async fn room_push_ctx(
+	&self,
+	room_id: &RoomId,
+	power_levels: &RoomPowerLevelsEventContent,
+) -> PushConditionRoomCtx {
+	let power_levels = PushConditionPowerLevelsCtx {
+		users: power_levels.users.clone(),
+		users_default: power_levels.users_default,
+		notifications: power_levels.notifications.clone(),
+	};
+
+	let room_joined_count = self
+		.services
+		.state_cache
+		.room_joined_count(room_id)
+		.await
+		.unwrap_or(1)
+		.try_into()
+		.unwrap_or_else(|_| uint!(0));
+
+	PushConditionRoomCtx {
+		room_id: room_id.to_owned(),
+		member_count: room_joined_count,
+		// Filled in for each member
+		user_id: self.services.globals.server_user.clone(),
+		user_display_name: String::new(),
+		power_levels: Some(power_levels),
+	}
+}
+
+impl RoomPushIndex {
+	pub(super) fn new(mut members: Vec<IndexedMember>) -> Self {
+		let mut words: HashMap<String, Vec<usize>> = HashMap::new();
+		for (i, member) in members.iter_mut().enumerate() {
+			if !member.indexed {
+				continue;
+			}
+
+			let Some(member_words) = member.words() else {
+				member.indexed = false;
+				continue;
+			};
+
+			for word in member_words {
+				let indices = words.entry(word).or_default();
+				if indices.last() != Some(&i) {
+					indices.push(i);
+				}
+			}
+		}
+
+		Self { members, words }
+	}
+
+	/// Whether the event notifies and highlights each member other than the
+	/// sender. `ctx` is filled in with each member's user ID and display name.
+	pub(super) fn push_actions(
+		&self,
+		sender: &UserId,
+		pdu: &Raw<AnySyncTimelineEvent>,
+		ctx: &PushConditionRoomCtx,
+	) -> Vec<(OwnedUserId, bool, bool)> {
+		let mentioned = self.mentioned(pdu);
+		let state_key = pdu
+			.get_field::<String>("state_key")
+			.ok()
+			.flatten()
+			.map(|state_key| state_key.to_lowercase());
+
+		let mut shared = None;
+		self.members
+			.iter()
+			.enumerate()
+			.filter(|(_, member)| member.user_id != sender)
+			.map(|(i, member)| {
+				let evaluate = !member.indexed
+					|| mentioned.contains(&i)
+					|| state_key
+						.as_ref()
+						.is_some_and(|key| *key == member.user_id.as_str().to_lowercase());
+
+				let (notify, highlight) = if evaluate {
+					member.push_actions(pdu, ctx)
+				} else {
+					*shared.get_or_insert_with(|| member.push_actions(pdu, ctx))
+				};
+
+				(member.user_id.clone(), notify, highlight)
+			})
+			.collect()
+	}
+
+	/// The indexed members the event may mention, by a word of its body or in
+	/// its `m.mentions`.
+	fn mentioned(&self, pdu: &Raw<AnySyncTimelineEvent>) -> HashSet<usize> {
+		let Ok(Some(content)) = pdu.get_field::<Value>("content") else {
+			return HashSet::new();
+		};
+
+		let body = content
+			.get("body")
+			.and_then(Value::as_str)
+			.map(str::to_lowercase)
+			.unwrap_or_default();
+
+		let mut mentioned: HashSet<usize> = words(&body)
+			.filter_map(|word| self.words.get(&word))
+			.flatten()
+			.copied()
+			.collect();
+
+		let user_ids: HashSet<&str> = content
+			.get("m.mentions")
+			.and_then(|mentions| mentions.get("user_ids"))
+			.and_then(Value::as_array)
+			.into_iter()
+			.flatten()
+			.filter_map(Value::as_str)
+			.collect();
+
+		if !user_ids.is_empty() {
+			mentioned.extend(
+				self.members
+					.iter()
+					.enumerate()
+					.filter(|(_, member)| user_ids.contains(member.user_id.as_str()))
+					.map(|(i, _)| i),
+			);
+		}
+
+		mentioned
+	}
+}
+
+impl IndexedMember {
+	pub(super) fn new(
+		user_id: OwnedUserId,
+		display_name: String,
+		ruleset: Ruleset,
+		defaults: &Ruleset,
+	) -> Self {
+		let indexed = same_rules(&ruleset.override_, &defaults.override_)
+			&& same_rules(&ruleset.room, &defaults.room)
+			&& same_rules(&ruleset.sender, &defaults.sender)
+			&& same_rules(&ruleset.underride, &defaults.underride);
+
+		Self { user_id, display_name, ruleset, indexed }
+	}
+
+	fn push_actions(
+		&self,
+		pdu: &Raw<AnySyncTimelineEvent>,
+		ctx: &PushConditionRoomCtx,
+	) -> (bool, bool) {
+		let ctx = PushConditionRoomCtx {
+			user_id: self.user_id.clone(),
+			user_display_name: self.display_name.clone(),
+			..ctx.clone()
+		};
+
+		notifies_and_highlights(self.ruleset.get_actions(pdu, &ctx))
+	}
+
+	/// The longest word of the display name and of each enabled keyword; a body
+	/// can only match them if it has these words. `None` if one of them
+	/// isn't plain ASCII text, as bodies are matched case-insensitively with
+	/// Unicode word boundaries.
+	fn words(&self) -> Option<Vec<String>> {
+		let keywords = self
+			.ruleset
+			.content
+			.iter()
+			.filter(|rule| rule.enabled)
+			.map(|rule| rule.pattern.as_str());
+
+		std::iter::once(self.display_name.as_str())
+			.chain(keywords)
+			.map(|pattern| {
+				if !pattern.is_ascii() || pattern.contains(['*', '?']) {
+					return None;
+				}
+
+				words(pattern).max_by_key(String::len)
+			})
+			.collect()
+	}
+}
+
+/// Whether two kinds of push rules are the same, as ruma's rules can't be
+/// compared directly.
+fn same_rules<T: serde::Serialize>(rules: &T, defaults: &T) -> bool {
+	match (serde_json::to_value(rules), serde_json::to_value(defaults)) {
+		| (Ok(rules), Ok(defaults)) => rules == defaults,
+		| _ => false,
+	}
+}
+
+/// The lowercased runs of ASCII letters and digits of the text. Any word
+/// boundary of a match is between two of these, so a body matching an ASCII
+/// pattern has each of the pattern's words.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+	text.split(|c: char| !c.is_ascii_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(str::to_ascii_lowercase)
+}
+
+/// Whether the actions notify, and whether they highlight.
+pub(super) fn notifies_and_highlights(actions: &[Action]) -> (bool, bool) {
+	let mut highlight = false;
+	let mut notify = false;
+
+	for action in actions {
+		match action {
+			| Action::Notify => notify = true,
+			| Action::SetTweak(Tweak::Highlight(true)) => {
+				highlight = true;
+			},
+			| _ => {},
+		};
+
+		// Break early if both conditions are true
+		if notify && highlight {
+			break;
+		}
+	}
+
+	(notify, highlight)
+}
@@ -1,14 +1,25 @@
-use std::{fmt::Debug, mem, sync::Arc};
+mod index;
+mod tests;
+mod web_push;
+
+use std::{
+	collections::HashMap,
+	fmt::{Debug, Write},
+	mem,
+	sync::{atomic::AtomicU64, Arc, Mutex},
+	time::SystemTime,
+};
 
 use bytes::BytesMut;
 use conduwuit::{
 	debug_warn, err, trace,
 	utils::{stream::TryIgnore, string_from_bytes},
-	warn, Err, PduEvent, Result,
+	warn, Err, Error, PduEvent, Result, Server,
 };
 use database::{Deserialized, Ignore, Interfix, Json, Map};
 use futures::{Stream, StreamExt};
 use ipaddress::IPAddress;
+use lru_cache::LruCache;
 use ruma::{
 	api::{
 		client::push::{set_pusher, Pusher, PusherKind},
@@ -22,21 +33,65 @@ use ruma::{
 		room::power_levels::RoomPowerLevelsEventContent, AnySyncTimelineEvent, StateEventType,
 		TimelineEventType,
 	},
-	push::{
-		Action, PushConditionPowerLevelsCtx, PushConditionRoomCtx, PushFormat, Ruleset, Tweak,
-	},
-	serde::Raw,
-	uint, RoomId, UInt, UserId,
+	push::{Action, PatternedPushRuleInit, PushConditionRoomCtx, PushFormat, Ruleset, Tweak},
+	serde::{JsonObject, Raw},
+	uint, OwnedDeviceId, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
 };
+use serde::{Deserialize, Serialize};
 
-use crate::{client, globals, rooms, sending, users, Dep};
+use self::{
+	index::{notifies_and_highlights, RoomPushIndex},
+	web_push::{web_push_data, Vapid},
+};
+use crate::{account_data, client, globals, rooms, sending, users, Dep};
 
 pub struct Service {
 	db: Data,
 	services: Services,
+	failures: Mutex<HashMap<(OwnedUserId, String), PushFailures>>,
+	gateway_stats: Mutex<HashMap<String, GatewayStats>>,
+	room_push_index: Mutex<RoomPushIndexCache>,
+	room_push_index_generation: AtomicU64,
+	vapid: Option<Vapid>,
+}
+
+type RoomPushIndexCache = LruCache<OwnedRoomId, Arc<RoomPushIndex>>;
+
+const ROOM_PUSH_INDEX_CAPACITY: usize = 1024;
+
+/// Consecutive failed deliveries to a pusher.
+#[derive(Clone, Debug)]
+pub struct PushFailures {
+	pub count: u32,
+	pub last_failure: SystemTime,
+	pub last_error: String,
+}
+
+/// Deliveries to a push gateway since startup.
+#[derive(Clone, Debug, Default)]
+pub struct GatewayStats {
+	pub delivered: u64,
+	pub failed: u64,
+	pub last_failure: Option<(SystemTime, String)>,
+}
+
+/// Remotely toggleable pusher state (MSC3881)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PusherState {
+	/// Whether notifications are sent to the pusher
+	pub enabled: bool,
+
+	/// The device which created the pusher
+	pub device_id: Option<OwnedDeviceId>,
+}
+
+impl Default for PusherState {
+	fn default() -> Self { Self { enabled: true, device_id: None } }
 }
 
 struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
 	globals: Dep<globals::Service>,
 	client: Dep<client::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
@@ -47,6 +102,7 @@ struct Services {
 
 struct Data {
 	senderkey_pusher: Arc<Map>,
+	senderkey_pusherstate: Arc<Map>,
 }
 
 impl crate::Service for Service {
@@ -54,8 +110,11 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			db: Data {
 				senderkey_pusher: args.db["senderkey_pusher"].clone(),
+				senderkey_pusherstate: args.db["senderkey_pusherstate"].clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
 				globals: args.depend::<globals::Service>("globals"),
 				client: args.depend::<client::Service>("client"),
 				state_accessor: args
@@ -64,17 +123,83 @@ impl crate::Service for Service {
 				users: args.depend::<users::Service>("users"),
 				sending: args.depend::<sending::Service>("sending"),
 			},
+			failures: Mutex::default(),
+			gateway_stats: Mutex::default(),
+			room_push_index: Mutex::new(RoomPushIndexCache::new(ROOM_PUSH_INDEX_CAPACITY)),
+			room_push_index_generation: AtomicU64::new(0),
+			vapid: Vapid::new(&args.server.config)?,
 		}))
 	}
 
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let failures = self.failures.lock()?.len();
+		writeln!(out, "push_failures: {failures}")?;
+
+		let gateway_stats = self.gateway_stats.lock()?.len();
+		writeln!(out, "push_gateway_stats: {gateway_stats}")?;
+
+		let room_push_index = self.room_push_index.lock()?;
+		writeln!(
+			out,
+			"room_push_index: {} / {}",
+			room_push_index.len(),
+			room_push_index.capacity()
+		)?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.room_push_index.lock().expect("locked").clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 impl Service {
+	/// The server-default push rules for the user, with the changes configured
+	/// in `push_rules_remove_defaults` and `push_rules_keywords` applied.
+	#[must_use]
+	pub fn server_default_rules(&self, user_id: &UserId) -> Ruleset {
+		let config = &self.services.server.config;
+		let mut ruleset = Ruleset::server_default(user_id);
+
+		for rule_id in &config.push_rules_remove_defaults {
+			ruleset.override_.shift_remove(rule_id.as_str());
+			ruleset.content.shift_remove(rule_id.as_str());
+			ruleset.underride.shift_remove(rule_id.as_str());
+		}
+
+		for keyword in &config.push_rules_keywords {
+			ruleset.content.insert(
+				PatternedPushRuleInit {
+					actions: vec![
+						Action::Notify,
+						Action::SetTweak(Tweak::Sound("default".into())),
+						Action::SetTweak(Tweak::Highlight(true)),
+					],
+					default: true,
+					enabled: true,
+					rule_id: format!(".org.conduwuit.rule.keyword.{keyword}"),
+					pattern: keyword.clone(),
+				}
+				.into(),
+			);
+		}
+
+		ruleset
+	}
+
+	/// The public key web clients subscribe to Web Push with, when it is
+	/// enabled.
+	#[must_use]
+	pub fn web_push_public_key(&self) -> Option<&str> {
+		self.vapid.as_ref().map(Vapid::public_key)
+	}
+
 	pub async fn set_pusher(
 		&self,
 		sender: &UserId,
 		pusher: &set_pusher::v3::PusherAction,
+		state: &PusherState,
 	) -> Result {
 		match pusher {
 			| set_pusher::v3::PusherAction::Post(data) => {
@@ -93,53 +218,126 @@ impl Service {
 				}
 
 				// add some validation to the pusher URL
-				let pusher_kind = &data.pusher.kind;
-				if let PusherKind::Http(http) = pusher_kind {
-					let url = &http.url;
-					let url = url::Url::parse(&http.url).map_err(|e| {
-						err!(Request(InvalidParam(
-							warn!(%url, "HTTP pusher URL is not a valid URL: {e}")
-						)))
-					})?;
-
-					if ["http", "https"]
-						.iter()
-						.all(|&scheme| scheme != url.scheme().to_lowercase())
-					{
-						return Err!(Request(InvalidParam(
-							warn!(%url, "HTTP pusher URL is not a valid HTTP/HTTPS URL")
-						)));
-					}
-
-					if let Ok(ip) =
-						IPAddress::parse(url.host_str().expect("URL previously validated"))
-					{
-						if !self.services.client.valid_cidr_range(&ip) {
-							return Err!(Request(InvalidParam(
-								warn!(%url, "HTTP pusher URL is a forbidden remote address")
-							)));
-						}
-					}
+				if let PusherKind::Http(http) = &data.pusher.kind {
+					self.check_push_url(&http.url)?;
 				}
 
+				self.check_web_push_pusher(&data.pusher)?;
+
 				let key = (sender, data.pusher.ids.pushkey.as_str());
 				self.db.senderkey_pusher.put(key, Json(pusher));
+				self.db.senderkey_pusherstate.put(key, Json(state));
 			},
 			| set_pusher::v3::PusherAction::Delete(ids) => {
-				let key = (sender, ids.pushkey.as_str());
-				self.db.senderkey_pusher.del(key);
-
-				self.services
-					.sending
-					.cleanup_events(None, Some(sender), Some(ids.pushkey.as_str()))
-					.await
-					.ok();
+				self.delete_pusher(sender, ids.pushkey.as_str()).await;
 			},
 		}
 
 		Ok(())
 	}
 
+	async fn delete_pusher(&self, sender: &UserId, pushkey: &str) {
+		let key = (sender, pushkey);
+		self.db.senderkey_pusher.del(key);
+		self.db.senderkey_pusherstate.del(key);
+
+		self.failures
+			.lock()
+			.expect("locked")
+			.remove(&(sender.to_owned(), pushkey.to_owned()));
+
+		self.services
+			.sending
+			.cleanup_events(None, Some(sender), Some(pushkey))
+			.await
+			.ok();
+	}
+
+	/// Forgets the failures of a pusher after a successful delivery.
+	pub fn note_push_success(&self, sender: &UserId, pusher: &Pusher) {
+		{
+			let mut gateway_stats = self.gateway_stats.lock().expect("locked");
+			let stats = gateway_stats.entry(pusher_gateway(pusher)).or_default();
+			stats.delivered = stats.delivered.saturating_add(1);
+		}
+
+		let mut failures = self.failures.lock().expect("locked");
+		if !failures.is_empty() {
+			failures.remove(&(sender.to_owned(), pusher.ids.pushkey.clone()));
+		}
+	}
+
+	/// Records a failed delivery to a pusher. Returns true when the pusher has
+	/// now failed `pusher_failure_limit` times in a row and was removed.
+	pub async fn note_push_failure(
+		&self,
+		sender: &UserId,
+		pusher: &Pusher,
+		error: &Error,
+	) -> bool {
+		{
+			let mut gateway_stats = self.gateway_stats.lock().expect("locked");
+			let stats = gateway_stats.entry(pusher_gateway(pusher)).or_default();
+			stats.failed = stats.failed.saturating_add(1);
+			stats.last_failure = Some((SystemTime::now(), error.to_string()));
+		}
+
+		let pushkey = pusher.ids.pushkey.as_str();
+		let count = {
+			let mut failures = self.failures.lock().expect("locked");
+			let failure = failures
+				.entry((sender.to_owned(), pushkey.to_owned()))
+				.or_insert_with(|| PushFailures {
+					count: 0,
+					last_failure: SystemTime::now(),
+					last_error: String::new(),
+				});
+
+			failure.count = failure.count.saturating_add(1);
+			failure.last_failure = SystemTime::now();
+			failure.last_error = error.to_string();
+			failure.count
+		};
+
+		let limit = self.services.server.config.pusher_failure_limit;
+		if limit == 0 || count < limit {
+			return false;
+		}
+
+		warn!(%sender, ?pushkey, "Removing pusher after {count} failed deliveries: {error}");
+		self.delete_pusher(sender, pushkey).await;
+
+		true
+	}
+
+	/// Pushers of the user which are currently failing.
+	pub fn get_push_failures(&self, sender: &UserId) -> Vec<(String, PushFailures)> {
+		self.failures
+			.lock()
+			.expect("locked")
+			.iter()
+			.filter(|((user_id, _), _)| user_id == sender)
+			.map(|((_, pushkey), failures)| (pushkey.clone(), failures.clone()))
+			.collect()
+	}
+
+	/// Deliveries to each push gateway since startup.
+	pub fn get_gateway_stats(&self) -> Vec<(String, GatewayStats)> {
+		self.gateway_stats
+			.lock()
+			.expect("locked")
+			.iter()
+			.map(|(gateway, stats)| (gateway.clone(), stats.clone()))
+			.collect()
+	}
+
+	/// Every pusher of every user.
+	pub fn all_pushers(&self) -> impl Stream<Item = (OwnedUserId, Pusher)> + Send + '_ {
+		self.db.senderkey_pusher.stream().ignore_err().map(
+			|((user_id, _), pusher): ((&UserId, Ignore), Pusher)| (user_id.to_owned(), pusher),
+		)
+	}
+
 	pub async fn get_pusher(&self, sender: &UserId, pushkey: &str) -> Result<Pusher> {
 		let senderkey = (sender, pushkey);
 		self.db
@@ -160,6 +358,22 @@ impl Service {
 			.await
 	}
 
+	/// The MSC3881 state of the pusher; pushers created before it was tracked
+	/// are enabled and not associated with a device.
+	pub async fn get_pusher_state(&self, sender: &UserId, pushkey: &str) -> PusherState {
+		let senderkey = (sender, pushkey);
+		self.db
+			.senderkey_pusherstate
+			.qry(&senderkey)
+			.await
+			.deserialized()
+			.unwrap_or_default()
+	}
+
+	pub async fn is_pusher_enabled(&self, sender: &UserId, pushkey: &str) -> bool {
+		self.get_pusher_state(sender, pushkey).await.enabled
+	}
+
 	pub fn get_pushkeys<'a>(
 		&'a self,
 		sender: &'a UserId,
@@ -304,13 +518,28 @@ impl Service {
 		}
 
 		if notify == Some(true) {
-			self.send_notice(unread, pusher, tweaks, pdu).await?;
+			self.send_notice(user, unread, pusher, tweaks, pdu).await?;
 		}
 		// Else the event triggered no actions
 
 		Ok(())
 	}
 
+	/// Whether the event notifies the user, and whether it highlights.
+	pub async fn notify_and_highlight(
+		&self,
+		user: &UserId,
+		ruleset: &Ruleset,
+		power_levels: &RoomPowerLevelsEventContent,
+		pdu: &Raw<AnySyncTimelineEvent>,
+		room_id: &RoomId,
+	) -> (bool, bool) {
+		notifies_and_highlights(
+			self.get_actions(user, ruleset, power_levels, pdu, room_id)
+				.await,
+		)
+	}
+
 	#[tracing::instrument(skip(self, user, ruleset, pdu), level = "debug")]
 	pub async fn get_actions<'a>(
 		&self,
@@ -320,21 +549,6 @@ impl Service {
 		pdu: &Raw<AnySyncTimelineEvent>,
 		room_id: &RoomId,
 	) -> &'a [Action] {
-		let power_levels = PushConditionPowerLevelsCtx {
-			users: power_levels.users.clone(),
-			users_default: power_levels.users_default,
-			notifications: power_levels.notifications.clone(),
-		};
-
-		let room_joined_count = self
-			.services
-			.state_cache
-			.room_joined_count(room_id)
-			.await
-			.unwrap_or(1)
-			.try_into()
-			.unwrap_or_else(|_| uint!(0));
-
 		let user_display_name = self
 			.services
 			.users
@@ -343,137 +557,223 @@ impl Service {
 			.unwrap_or_else(|_| user.localpart().to_owned());
 
 		let ctx = PushConditionRoomCtx {
-			room_id: room_id.to_owned(),
-			member_count: room_joined_count,
 			user_id: user.to_owned(),
 			user_display_name,
-			power_levels: Some(power_levels),
+			..self.room_push_ctx(room_id, power_levels).await
 		};
 
 		ruleset.get_actions(pdu, &ctx)
 	}
 
-	#[tracing::instrument(skip(self, unread, pusher, tweaks, event))]
-	async fn send_notice(
+	/// Adds the details of the event to a notification, except for the fields
+	/// configured in `push_omit_fields`.
+	async fn add_event_fields(&self, notifi: &mut Notification, event: &PduEvent) {
+		let omit = &self.services.server.config.push_omit_fields;
+		let include = |field: &str| !omit.iter().any(|omitted| omitted == field);
+
+		if include("sender") {
+			notifi.sender = Some(event.sender.clone());
+		}
+
+		if include("type") {
+			notifi.event_type = Some(event.kind.clone());
+		}
+
+		if include("content") {
+			notifi.content = serde_json::value::to_raw_value(&event.content).ok();
+		}
+
+		if include("user_is_target") && event.kind == TimelineEventType::RoomMember {
+			notifi.user_is_target = event.state_key.as_deref() == Some(event.sender.as_str());
+		}
+
+		if include("sender_display_name") {
+			notifi.sender_display_name =
+				self.services.users.displayname(&event.sender).await.ok();
+		}
+
+		if include("room_name") {
+			notifi.room_name = self
+				.services
+				.state_accessor
+				.get_name(&event.room_id)
+				.await
+				.ok();
+		}
+
+		if include("room_alias") {
+			notifi.room_alias = self
+				.services
+				.state_accessor
+				.get_canonical_alias(&event.room_id)
+				.await
+				.ok();
+		}
+	}
+
+	/// Validates the URL of a pusher, which must be an HTTP(S) URL outside the
+	/// forbidden address ranges.
+	fn check_push_url(&self, url: &str) -> Result<url::Url> {
+		let url = url::Url::parse(url).map_err(|e| {
+			err!(Request(InvalidParam(warn!(%url, "HTTP pusher URL is not a valid URL: {e}"))))
+		})?;
+
+		if ["http", "https"]
+			.iter()
+			.all(|&scheme| scheme != url.scheme().to_lowercase())
+		{
+			return Err!(Request(InvalidParam(
+				warn!(%url, "HTTP pusher URL is not a valid HTTP/HTTPS URL")
+			)));
+		}
+
+		if let Ok(ip) = IPAddress::parse(url.host_str().expect("URL previously validated")) {
+			if !self.services.client.valid_cidr_range(&ip) {
+				return Err!(Request(InvalidParam(
+					warn!(%url, "HTTP pusher URL is a forbidden remote address")
+				)));
+			}
+		}
+
+		Ok(url)
+	}
+
+	/// The notification about the event for the pusher, in the format it asked
+	/// for.
+	async fn notification(
 		&self,
-		unread: UInt,
 		pusher: &Pusher,
-		tweaks: Vec<Tweak>,
+		format: Option<&PushFormat>,
+		data: &JsonObject,
+		unread: UInt,
+		tweaks: &[Tweak],
 		event: &PduEvent,
-	) -> Result {
-		// TODO: email
-		match &pusher.kind {
-			| PusherKind::Http(http) => {
-				let url = &http.url;
-				let url = url::Url::parse(&http.url).map_err(|e| {
-					err!(Request(InvalidParam(
-						warn!(%url, "HTTP pusher URL is not a valid URL: {e}")
-					)))
-				})?;
+	) -> Notification {
+		// TODO (timo): can pusher/devices have conflicting formats
+		let config = &self.services.server.config;
+		let event_id_only = config.push_event_id_only || format == Some(&PushFormat::EventIdOnly);
+
+		let mut device = Device::new(pusher.ids.app_id.clone(), pusher.ids.pushkey.clone());
+		device.data.data.clone_from(data);
+		device.data.format = if event_id_only {
+			Some(PushFormat::EventIdOnly)
+		} else {
+			format.cloned()
+		};
 
-				if ["http", "https"]
-					.iter()
-					.all(|&scheme| scheme != url.scheme().to_lowercase())
-				{
-					return Err!(Request(InvalidParam(
-						warn!(%url, "HTTP pusher URL is not a valid HTTP/HTTPS URL")
-					)));
-				}
+		// Tweaks are only added if the format is NOT event_id_only
+		if !event_id_only {
+			device.tweaks = tweaks.to_vec();
+		}
 
-				if let Ok(ip) =
-					IPAddress::parse(url.host_str().expect("URL previously validated"))
-				{
-					if !self.services.client.valid_cidr_range(&ip) {
-						return Err!(Request(InvalidParam(
-							warn!(%url, "HTTP pusher URL is a forbidden remote address")
-						)));
-					}
-				}
+		let d = vec![device];
+		let mut notifi = Notification::new(d);
 
-				// TODO (timo): can pusher/devices have conflicting formats
-				let event_id_only = http.format == Some(PushFormat::EventIdOnly);
+		notifi.event_id = Some((*event.event_id).to_owned());
+		notifi.room_id = Some((*event.room_id).to_owned());
+		if data.get("org.matrix.msc4076.disable_badge_count").is_none()
+			&& data.get("disable_badge_count").is_none()
+		{
+			notifi.counts = NotificationCounts::new(unread, uint!(0));
+		} else {
+			// counts will not be serialised if it's the default (0, 0)
+			// skip_serializing_if = "NotificationCounts::is_default"
+			notifi.counts = NotificationCounts::default();
+		}
 
-				let mut device =
-					Device::new(pusher.ids.app_id.clone(), pusher.ids.pushkey.clone());
-				device.data.data.clone_from(&http.data);
-				device.data.format.clone_from(&http.format);
+		// The event_id_only format carries nothing more about the event
+		if !event_id_only {
+			if event.kind == TimelineEventType::RoomEncrypted
+				|| tweaks
+					.iter()
+					.any(|t| matches!(t, Tweak::Highlight(true) | Tweak::Sound(_)))
+			{
+				notifi.prio = NotificationPriority::High;
+			} else {
+				notifi.prio = NotificationPriority::Low;
+			}
 
-				// Tweaks are only added if the format is NOT event_id_only
-				if !event_id_only {
-					device.tweaks.clone_from(&tweaks);
-				}
+			self.add_event_fields(&mut notifi, event).await;
+		}
 
-				let d = vec![device];
-				let mut notifi = Notification::new(d);
-
-				notifi.event_id = Some((*event.event_id).to_owned());
-				notifi.room_id = Some((*event.room_id).to_owned());
-				if http
-					.data
-					.get("org.matrix.msc4076.disable_badge_count")
-					.is_none() && http.data.get("disable_badge_count").is_none()
-				{
-					notifi.counts = NotificationCounts::new(unread, uint!(0));
-				} else {
-					// counts will not be serialised if it's the default (0, 0)
-					// skip_serializing_if = "NotificationCounts::is_default"
-					notifi.counts = NotificationCounts::default();
-				}
+		notifi
+	}
 
-				if event_id_only {
-					self.send_request(
-						&http.url,
-						send_event_notification::v1::Request::new(notifi),
+	#[tracing::instrument(skip(self, user, unread, pusher, tweaks, event))]
+	async fn send_notice(
+		&self,
+		user: &UserId,
+		unread: UInt,
+		pusher: &Pusher,
+		tweaks: Vec<Tweak>,
+		event: &PduEvent,
+	) -> Result {
+		match &pusher.kind {
+			| PusherKind::Http(http) => {
+				self.check_push_url(&http.url)?;
+
+				let notifi = self
+					.notification(
+						pusher,
+						http.format.as_ref(),
+						&http.data,
+						unread,
+						&tweaks,
+						event,
 					)
-					.await?;
-				} else {
-					if event.kind == TimelineEventType::RoomEncrypted
-						|| tweaks
-							.iter()
-							.any(|t| matches!(t, Tweak::Highlight(true) | Tweak::Sound(_)))
-					{
-						notifi.prio = NotificationPriority::High;
-					} else {
-						notifi.prio = NotificationPriority::Low;
-					}
-					notifi.sender = Some(event.sender.clone());
-					notifi.event_type = Some(event.kind.clone());
-					notifi.content = serde_json::value::to_raw_value(&event.content).ok();
+					.await;
 
-					if event.kind == TimelineEventType::RoomMember {
-						notifi.user_is_target =
-							event.state_key.as_deref() == Some(event.sender.as_str());
-					}
-
-					notifi.sender_display_name =
-						self.services.users.displayname(&event.sender).await.ok();
-
-					notifi.room_name = self
-						.services
-						.state_accessor
-						.get_name(&event.room_id)
-						.await
-						.ok();
-
-					notifi.room_alias = self
-						.services
-						.state_accessor
-						.get_canonical_alias(&event.room_id)
-						.await
-						.ok();
-
-					self.send_request(
-						&http.url,
-						send_event_notification::v1::Request::new(notifi),
-					)
+				let response = self
+					.send_request(&http.url, send_event_notification::v1::Request::new(notifi))
 					.await?;
+
+				// The gateway no longer accepts this pushkey; stop pushing to it
+				if response.rejected.contains(&pusher.ids.pushkey) {
+					warn!(%user, pushkey = ?pusher.ids.pushkey, "Removing pusher rejected by push gateway");
+					self.delete_pusher(user, &pusher.ids.pushkey).await;
 				}
 
 				Ok(())
 			},
-			// TODO: Handle email
-			//PusherKind::Email(_) => Ok(()),
-			| _ => Ok(()),
+			| kind => match web_push_data(kind) {
+				| Some(web_push) => {
+					let notifi = self
+						.notification(
+							pusher,
+							web_push.format.as_ref(),
+							&web_push.data,
+							unread,
+							&tweaks,
+							event,
+						)
+						.await;
+
+					self.send_web_push(user, pusher, &web_push, notifi).await
+				},
+				// TODO: Handle email
+				| None => Ok(()),
+			},
 		}
 	}
 }
+
+/// Where a pusher's notifications are delivered: the origin of the push
+/// gateway or Web Push service, or the kind of the pusher.
+#[must_use]
+pub fn pusher_gateway(pusher: &Pusher) -> String {
+	let url = match &pusher.kind {
+		| PusherKind::Http(http) => Some(http.url.clone()),
+		| kind => web_push_data(kind).map(|web_push| web_push.url),
+	};
+
+	let Some(url) = url else {
+		return serde_json::to_value(&pusher.kind)
+			.ok()
+			.and_then(|kind| kind.get("kind")?.as_str().map(ToOwned::to_owned))
+			.unwrap_or_default();
+	};
+
+	url::Url::parse(&url)
+		.map(|url| url.origin().ascii_serialization())
+		.unwrap_or(url)
+}
@@ -0,0 +1,272 @@
+#![cfg(test)]
+
+use ring::{aead, agreement, hkdf, rand::SystemRandom};
+use ruma::{
+	events::AnySyncTimelineEvent,
+	owned_room_id, owned_user_id,
+	push::{Action, PatternedPushRuleInit, PushConditionRoomCtx, RuleKind, Ruleset, Tweak},
+	serde::Raw,
+	uint, user_id, OwnedUserId,
+};
+use serde_json::{json, value::to_raw_value};
+
+use super::{
+	index::{notifies_and_highlights, IndexedMember, RoomPushIndex},
+	web_push::encrypt,
+};
+
+const AUTH_SECRET: [u8; 16] = *b"0123456789abcdef";
+
+/// A push subscription's keys, as held by the user agent.
+struct Subscription {
+	private_key: agreement::EphemeralPrivateKey,
+	public_key: Vec<u8>,
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+	fn len(&self) -> usize { self.0 }
+}
+
+fn subscription() -> Subscription {
+	let private_key =
+		agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &SystemRandom::new())
+			.unwrap();
+	let public_key = private_key.compute_public_key().unwrap().as_ref().to_vec();
+
+	Subscription { private_key, public_key }
+}
+
+fn expand(prk: &hkdf::Prk, info: &[u8], out: &mut [u8]) {
+	prk.expand(&[info], Len(out.len()))
+		.unwrap()
+		.fill(out)
+		.unwrap();
+}
+
+/// Decrypts an aes128gcm body (RFC 8188) the way a user agent does (RFC 8291
+/// section 3.4), returning the record size and the unpadded plaintext.
+fn decrypt(
+	subscription: Subscription,
+	auth_secret: &[u8],
+	body: &[u8],
+) -> Option<(u32, Vec<u8>)> {
+	let (salt, rest) = body.split_at(16);
+	let (record_size, rest) = rest.split_at(4);
+	let (key_id_len, rest) = rest.split_first()?;
+	let (server_key, record) = rest.split_at(usize::from(*key_id_len));
+
+	let server_key = agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, server_key);
+	let ikm = agreement::agree_ephemeral(subscription.private_key, &server_key, |secret| {
+		let mut info = b"WebPush: info\0".to_vec();
+		info.extend_from_slice(&subscription.public_key);
+		info.extend_from_slice(server_key.bytes());
+
+		let mut ikm = [0_u8; 32];
+		let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, auth_secret).extract(secret);
+		expand(&prk, &info, &mut ikm);
+		ikm
+	})
+	.ok()?;
+
+	let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(&ikm);
+	let mut content_key = [0_u8; 16];
+	expand(&prk, b"Content-Encoding: aes128gcm\0", &mut content_key);
+	let mut nonce = [0_u8; aead::NONCE_LEN];
+	expand(&prk, b"Content-Encoding: nonce\0", &mut nonce);
+
+	let key = aead::UnboundKey::new(&aead::AES_128_GCM, &content_key).ok()?;
+	let mut record = record.to_vec();
+	let plaintext = aead::LessSafeKey::new(key)
+		.open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut record)
+		.ok()?;
+
+	// The last record ends with a 2 delimiter, optionally followed by zeroes
+	let end = plaintext.iter().rposition(|byte| *byte != 0)?;
+	(plaintext[end] == 2).then_some(())?;
+
+	let record_size = u32::from_be_bytes(record_size.try_into().ok()?);
+	Some((record_size, plaintext[..end].to_vec()))
+}
+
+#[test]
+fn round_trip() {
+	let subscription = subscription();
+	let body = encrypt(&subscription.public_key, &AUTH_SECRET, b"{\"prio\":\"high\"}").unwrap();
+
+	let (record_size, plaintext) = decrypt(subscription, &AUTH_SECRET, &body).unwrap();
+	assert_eq!(record_size, 4096);
+	assert_eq!(plaintext, b"{\"prio\":\"high\"}");
+}
+
+#[test]
+fn header_carries_server_key() {
+	let subscription = subscription();
+	let body = encrypt(&subscription.public_key, &AUTH_SECRET, b"").unwrap();
+
+	assert_eq!(body[20], 65, "key ID is an uncompressed P-256 public key");
+	assert_eq!(body[21], 0x04, "uncompressed point");
+	assert_eq!(body.len(), 16 + 4 + 1 + 65 + 1 + 16);
+}
+
+#[test]
+fn fresh_salt_and_key() {
+	let subscription = subscription();
+	let first = encrypt(&subscription.public_key, &AUTH_SECRET, b"payload").unwrap();
+	let second = encrypt(&subscription.public_key, &AUTH_SECRET, b"payload").unwrap();
+
+	assert_ne!(first[..16], second[..16]);
+	assert_ne!(first[21..86], second[21..86]);
+}
+
+#[test]
+fn wrong_auth_secret() {
+	let subscription = subscription();
+	let body = encrypt(&subscription.public_key, &AUTH_SECRET, b"payload").unwrap();
+
+	assert!(decrypt(subscription, b"fedcba9876543210", &body).is_none());
+}
+
+/// Alice and Bob have the default rules, Carol a keyword, and Dave has turned
+/// off notifications for messages.
+fn members() -> Vec<(OwnedUserId, String, Ruleset)> {
+	let mut carol = Ruleset::server_default(user_id!("@carol:example.org"));
+	carol.content.insert(
+		PatternedPushRuleInit {
+			actions: vec![Action::Notify, Action::SetTweak(Tweak::Highlight(true))],
+			default: false,
+			enabled: true,
+			rule_id: "deploy".into(),
+			pattern: "deploy".into(),
+		}
+		.into(),
+	);
+
+	let mut dave = Ruleset::server_default(user_id!("@dave:example.org"));
+	dave.set_enabled(RuleKind::Underride, ".m.rule.message", false)
+		.unwrap();
+
+	vec![
+		(
+			owned_user_id!("@alice:example.org"),
+			"alice".into(),
+			Ruleset::server_default(user_id!("@alice:example.org")),
+		),
+		(
+			owned_user_id!("@bob:example.org"),
+			"Bob Smith".into(),
+			Ruleset::server_default(user_id!("@bob:example.org")),
+		),
+		(owned_user_id!("@carol:example.org"), "carol".into(), carol),
+		(owned_user_id!("@dave:example.org"), "dave".into(), dave),
+	]
+}
+
+fn ctx() -> PushConditionRoomCtx {
+	PushConditionRoomCtx {
+		room_id: owned_room_id!("!room:example.org"),
+		member_count: uint!(5),
+		user_id: owned_user_id!("@conduit:example.org"),
+		user_display_name: String::new(),
+		power_levels: None,
+	}
+}
+
+fn event(
+	event_type: &str,
+	state_key: Option<&str>,
+	content: serde_json::Value,
+) -> Raw<AnySyncTimelineEvent> {
+	let mut event = json!({
+		"event_id": "$event:example.org",
+		"origin_server_ts": 1,
+		"sender": "@eve:example.org",
+		"type": event_type,
+		"content": content,
+	});
+
+	if let Some(state_key) = state_key {
+		event["state_key"] = state_key.into();
+	}
+
+	Raw::from_json(to_raw_value(&event).unwrap())
+}
+
+/// The members the event highlights, after checking the index pushes each
+/// member as their own rules do.
+fn highlighted(event: &Raw<AnySyncTimelineEvent>) -> Vec<String> {
+	let sender = user_id!("@eve:example.org");
+	let index = RoomPushIndex::new(
+		members()
+			.into_iter()
+			.map(|(user_id, display_name, ruleset)| {
+				let defaults = Ruleset::server_default(&user_id);
+				IndexedMember::new(user_id, display_name, ruleset, &defaults)
+			})
+			.collect(),
+	);
+
+	let actions = index.push_actions(sender, event, &ctx());
+	let expected: Vec<_> = members()
+		.into_iter()
+		.map(|(user_id, display_name, ruleset)| {
+			let ctx = PushConditionRoomCtx {
+				user_id: user_id.clone(),
+				user_display_name: display_name,
+				..ctx()
+			};
+
+			let (notify, highlight) = notifies_and_highlights(ruleset.get_actions(event, &ctx));
+			(user_id, notify, highlight)
+		})
+		.collect();
+
+	assert_eq!(actions, expected);
+	actions
+		.into_iter()
+		.filter(|(_, _, highlight)| *highlight)
+		.map(|(user_id, ..)| user_id.localpart().to_owned())
+		.collect()
+}
+
+#[test]
+fn push_index_plain_message() {
+	let event = event("m.room.message", None, json!({"msgtype": "m.text", "body": "hello"}));
+	assert!(highlighted(&event).is_empty());
+}
+
+#[test]
+fn push_index_display_name() {
+	let event =
+		event("m.room.message", None, json!({"msgtype": "m.text", "body": "hi bob smith!"}));
+	assert_eq!(highlighted(&event), ["bob"]);
+}
+
+#[test]
+fn push_index_keyword() {
+	let event =
+		event("m.room.message", None, json!({"msgtype": "m.text", "body": "Deploy at noon"}));
+	assert_eq!(highlighted(&event), ["carol"]);
+}
+
+#[test]
+fn push_index_mentions() {
+	let event = event(
+		"m.room.message",
+		None,
+		json!({
+			"msgtype": "m.text",
+			"body": "hi",
+			"m.mentions": {"user_ids": ["@alice:example.org", "@dave:example.org"]},
+		}),
+	);
+	assert_eq!(highlighted(&event), ["alice", "dave"]);
+}
+
+#[test]
+fn push_index_invite() {
+	let event =
+		event("m.room.member", Some("@alice:example.org"), json!({"membership": "invite"}));
+	assert!(highlighted(&event).is_empty());
+}
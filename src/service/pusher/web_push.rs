@@ -0,0 +1,368 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use conduwuit::{debug_warn, err, implement, warn, Config, Err, Result};
+use ipaddress::IPAddress;
+use reqwest::StatusCode;
+use ring::{
+	aead, agreement, hkdf,
+	rand::{SecureRandom, SystemRandom},
+	signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use ruma::{
+	api::{
+		client::push::{Pusher, PusherKind},
+		push_gateway::send_event_notification::v1::{Notification, NotificationPriority},
+	},
+	push::PushFormat,
+	serde::JsonObject,
+	UserId,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Kind of the pushers delivered to with the Web Push protocol (MSC4174)
+const WEB_PUSH_KIND: &str = "webpush";
+
+/// Bytes of an uncompressed P-256 public key
+const PUBLIC_KEY_LEN: usize = 65;
+
+/// Bytes of a subscription's authentication secret
+const AUTH_SECRET_LEN: usize = 16;
+
+const SALT_LEN: usize = 16;
+
+/// The whole message is sent as a single record (RFC 8188)
+const RECORD_SIZE: u32 = 4096;
+
+/// The largest plaintext fitting the 4096 bytes push services must accept
+/// (RFC 8030), less the aes128gcm header, padding delimiter and tag.
+const MAX_PLAINTEXT_LEN: usize = 4096 - (SALT_LEN + 4 + 1 + PUBLIC_KEY_LEN) - 1 - 16;
+
+/// How long push services keep notifications for browsers which are offline
+const TIME_TO_LIVE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long the VAPID tokens sent with each notification are valid
+const VAPID_TOKEN_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// The server's key and contact for identifying itself to push services
+/// (RFC 8292).
+pub(super) struct Vapid {
+	key_pair: EcdsaKeyPair,
+	public_key: String,
+	subject: String,
+}
+
+/// The `data` of a Web Push pusher: the subscription of a browser.
+#[derive(Deserialize)]
+pub(super) struct WebPushData {
+	/// Endpoint of the subscription at the browser's push service
+	pub(super) url: String,
+
+	/// Authentication secret of the subscription, base64url-encoded
+	auth: String,
+
+	#[serde(default)]
+	pub(super) format: Option<PushFormat>,
+
+	/// The remaining fields, handed back to the client in notifications
+	#[serde(flatten)]
+	pub(super) data: JsonObject,
+}
+
+impl Vapid {
+	/// The VAPID key configured in `web_push_vapid_private_key`, if any.
+	pub(super) fn new(config: &Config) -> Result<Option<Self>> {
+		let Some(private_key) = &config.web_push_vapid_private_key else {
+			return Ok(None);
+		};
+
+		let pkcs8 = URL_SAFE_NO_PAD
+			.decode(private_key.trim().trim_end_matches('='))
+			.map_err(|e| {
+				err!(Config(
+					"web_push_vapid_private_key",
+					"VAPID private key is not valid base64url: {e}"
+				))
+			})?;
+
+		let key_pair = EcdsaKeyPair::from_pkcs8(
+			&ECDSA_P256_SHA256_FIXED_SIGNING,
+			&pkcs8,
+			&SystemRandom::new(),
+		)
+		.map_err(|e| {
+			err!(Config(
+				"web_push_vapid_private_key",
+				"VAPID private key is not a PKCS#8 P-256 key: {e}"
+			))
+		})?;
+
+		let public_key = URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref());
+		let subject = config
+			.web_push_vapid_subject
+			.clone()
+			.unwrap_or_else(|| format!("https://{}", config.server_name));
+
+		Ok(Some(Self { key_pair, public_key, subject }))
+	}
+
+	/// The public key clients subscribe with, base64url-encoded.
+	pub(super) fn public_key(&self) -> &str { &self.public_key }
+
+	/// The `Authorization` header for a request to the push service.
+	fn authorization(&self, endpoint: &url::Url) -> Result<String> {
+		let expires = SystemTime::now()
+			.checked_add(VAPID_TOKEN_LIFETIME)
+			.and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+			.map_or(0, |expires| expires.as_secs());
+
+		let header = URL_SAFE_NO_PAD.encode(json!({"typ": "JWT", "alg": "ES256"}).to_string());
+		let claims = URL_SAFE_NO_PAD.encode(
+			json!({
+				"aud": endpoint.origin().ascii_serialization(),
+				"exp": expires,
+				"sub": self.subject,
+			})
+			.to_string(),
+		);
+
+		let message = format!("{header}.{claims}");
+		let signature = self
+			.key_pair
+			.sign(&SystemRandom::new(), message.as_bytes())
+			.map_err(|e| err!("Failed to sign VAPID token: {e}"))?;
+
+		let signature = URL_SAFE_NO_PAD.encode(signature.as_ref());
+		Ok(format!("vapid t={message}.{signature}, k={}", self.public_key))
+	}
+}
+
+/// The subscription of a Web Push pusher, or None for other kinds of pushers.
+pub(super) fn web_push_data(kind: &PusherKind) -> Option<WebPushData> {
+	let kind = custom_kind(kind)?;
+	if kind.get("kind")?.as_str()? != WEB_PUSH_KIND {
+		return None;
+	}
+
+	serde_json::from_value(kind.get("data")?.clone()).ok()
+}
+
+/// Validates a Web Push pusher being set by a client.
+#[implement(super::Service)]
+pub(super) fn check_web_push_pusher(&self, pusher: &Pusher) -> Result {
+	let is_web_push = custom_kind(&pusher.kind).is_some_and(|kind| {
+		kind.get("kind").and_then(|kind| kind.as_str()) == Some(WEB_PUSH_KIND)
+	});
+
+	if !is_web_push {
+		return Ok(());
+	}
+
+	if self.vapid.is_none() {
+		return Err!(Request(InvalidParam("Web Push is not enabled on this server.")));
+	}
+
+	let Some(data) = web_push_data(&pusher.kind) else {
+		return Err!(Request(InvalidParam(
+			"Web Push pusher data must have a url and an auth secret."
+		)));
+	};
+
+	self.check_push_url(&data.url)?;
+	subscription_keys(&pusher.ids.pushkey, &data.auth)?;
+
+	Ok(())
+}
+
+/// Sends the notification to the browser of a Web Push pusher, encrypted to
+/// its subscription (RFC 8291). Subscriptions which expired are removed.
+#[implement(super::Service)]
+pub(super) async fn send_web_push(
+	&self,
+	user: &UserId,
+	pusher: &Pusher,
+	data: &WebPushData,
+	mut notifi: Notification,
+) -> Result {
+	let Some(vapid) = &self.vapid else {
+		debug_warn!(%user, "Web Push was disabled; not sending to Web Push pusher");
+		return Ok(());
+	};
+
+	let url = self.check_push_url(&data.url)?;
+	let (user_agent_key, auth_secret) = subscription_keys(&pusher.ids.pushkey, &data.auth)?;
+
+	let urgency = match notifi.prio {
+		| NotificationPriority::High => "high",
+		| _ => "normal",
+	};
+
+	let mut payload = serde_json::to_vec(&json!({ "notification": notifi }))?;
+	if payload.len() > MAX_PLAINTEXT_LEN {
+		// The client fetches what didn't fit by the event ID
+		notifi.content = None;
+		payload = serde_json::to_vec(&json!({ "notification": notifi }))?;
+	}
+
+	if payload.len() > MAX_PLAINTEXT_LEN {
+		payload = serde_json::to_vec(&json!({
+			"notification": {
+				"event_id": notifi.event_id,
+				"room_id": notifi.room_id,
+				"counts": notifi.counts,
+			}
+		}))?;
+	}
+
+	let body = encrypt(&user_agent_key, &auth_secret, &payload)?;
+	let response = self
+		.services
+		.client
+		.pusher
+		.post(url.clone())
+		.header("Authorization", vapid.authorization(&url)?)
+		.header("Content-Encoding", "aes128gcm")
+		.header("Content-Type", "application/octet-stream")
+		.header("TTL", TIME_TO_LIVE.as_secs())
+		.header("Urgency", urgency)
+		.body(body)
+		.send()
+		.await?;
+
+	if let Some(remote_addr) = response.remote_addr() {
+		if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
+			if !self.services.client.valid_cidr_range(&ip) {
+				return Err!(BadServerResponse("Not allowed to send requests to this IP"));
+			}
+		}
+	}
+
+	let status = response.status();
+	if status.is_success() {
+		return Ok(());
+	}
+
+	// The browser unsubscribed or the subscription expired
+	if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+		warn!(%user, pushkey = ?pusher.ids.pushkey, "Removing expired Web Push subscription");
+		self.delete_pusher(user, &pusher.ids.pushkey).await;
+		return Ok(());
+	}
+
+	Err!(BadServerResponse(warn!(
+		%url,
+		"Web Push service returned {status}: {}",
+		response.text().await.unwrap_or_default()
+	)))
+}
+
+/// The kind and data of a pusher of a kind ruma doesn't know.
+fn custom_kind(kind: &PusherKind) -> Option<serde_json::Value> {
+	let PusherKind::_Custom(_) = kind else {
+		return None;
+	};
+
+	serde_json::to_value(kind).ok()
+}
+
+/// The public key (the pushkey) and authentication secret of a subscription.
+fn subscription_keys(pushkey: &str, auth: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+	let decode = |value: &str| URL_SAFE_NO_PAD.decode(value.trim_end_matches('='));
+
+	let user_agent_key = decode(pushkey)
+		.ok()
+		.filter(|key| key.len() == PUBLIC_KEY_LEN)
+		.ok_or_else(|| {
+			err!(Request(InvalidParam(
+				"Web Push pushkey must be the subscription's base64url-encoded P-256 public key."
+			)))
+		})?;
+
+	let auth_secret = decode(auth)
+		.ok()
+		.filter(|auth| auth.len() == AUTH_SECRET_LEN)
+		.ok_or_else(|| {
+			err!(Request(InvalidParam(
+				"Web Push auth must be the subscription's base64url-encoded authentication \
+				 secret."
+			)))
+		})?;
+
+	Ok((user_agent_key, auth_secret))
+}
+
+/// Encrypts the payload for a subscription with the aes128gcm content coding
+/// (RFC 8188), keyed as described in RFC 8291.
+pub(super) fn encrypt(user_agent_key: &[u8], auth_secret: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+	let rng = SystemRandom::new();
+	let crypto_err = |e| err!("Failed to encrypt Web Push payload: {e}");
+
+	let private_key = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)
+		.map_err(crypto_err)?;
+	let public_key = private_key.compute_public_key().map_err(crypto_err)?;
+
+	let mut salt = [0_u8; SALT_LEN];
+	rng.fill(&mut salt).map_err(crypto_err)?;
+
+	let peer_key = agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, user_agent_key);
+	let ikm = agreement::agree_ephemeral(private_key, &peer_key, |shared_secret| {
+		let key_info: [&[u8]; 3] = [b"WebPush: info\0", user_agent_key, public_key.as_ref()];
+		let mut ikm = [0_u8; 32];
+		hkdf::Salt::new(hkdf::HKDF_SHA256, auth_secret)
+			.extract(shared_secret)
+			.expand(&key_info, Len(ikm.len()))
+			.and_then(|okm| okm.fill(&mut ikm))
+			.map(|()| ikm)
+	})
+	.map_err(crypto_err)?
+	.map_err(crypto_err)?;
+
+	let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &salt).extract(&ikm);
+
+	let mut content_key = [0_u8; 16];
+	prk.expand(&[b"Content-Encoding: aes128gcm\0".as_slice()], Len(content_key.len()))
+		.and_then(|okm| okm.fill(&mut content_key))
+		.map_err(crypto_err)?;
+
+	let mut nonce = [0_u8; aead::NONCE_LEN];
+	prk.expand(&[b"Content-Encoding: nonce\0".as_slice()], Len(nonce.len()))
+		.and_then(|okm| okm.fill(&mut nonce))
+		.map_err(crypto_err)?;
+
+	// A single record, ended by the last record's padding delimiter
+	let mut record = Vec::with_capacity(payload.len().saturating_add(17));
+	record.extend_from_slice(payload);
+	record.push(2);
+
+	let key = aead::UnboundKey::new(&aead::AES_128_GCM, &content_key).map_err(crypto_err)?;
+	aead::LessSafeKey::new(key)
+		.seal_in_place_append_tag(
+			aead::Nonce::assume_unique_for_key(nonce),
+			aead::Aad::empty(),
+			&mut record,
+		)
+		.map_err(crypto_err)?;
+
+	let public_key = public_key.as_ref();
+	let mut body = Vec::with_capacity(
+		SALT_LEN
+			.saturating_add(5)
+			.saturating_add(public_key.len())
+			.saturating_add(record.len()),
+	);
+	body.extend_from_slice(&salt);
+	body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+	body.push(u8::try_from(public_key.len())?);
+	body.extend_from_slice(public_key);
+	body.extend_from_slice(&record);
+
+	Ok(body)
+}
+
+/// Output length for HKDF expansion.
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+	fn len(&self) -> usize { self.0 }
+}
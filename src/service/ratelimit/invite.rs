@@ -0,0 +1,133 @@
+//! Hourly caps on invites sent per user and per room.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	hash::Hash,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{implement, warn, Result};
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+
+use super::limit_exceeded;
+
+const WINDOW: Duration = Duration::from_secs(3600);
+
+#[derive(Default)]
+pub(super) struct Invites {
+	/// Times of invites sent by each user within the last hour, oldest first.
+	pub(super) senders: HashMap<OwnedUserId, VecDeque<Instant>>,
+
+	/// Times of invites sent to each room within the last hour, oldest first.
+	pub(super) rooms: HashMap<OwnedRoomId, VecDeque<Instant>>,
+}
+
+/// Counts an invite from `sender` into `room_id`, refusing it when either has
+/// reached `invites_per_sender_per_hour` or `invites_per_room_per_hour`.
+#[implement(super::Service)]
+pub fn check_invite(&self, sender: &UserId, room_id: &RoomId) -> Result {
+	let config = &self.server.config;
+	let sender_limit = usize::try_from(config.invites_per_sender_per_hour).unwrap_or(usize::MAX);
+	let room_limit = usize::try_from(config.invites_per_room_per_hour).unwrap_or(usize::MAX);
+	if sender_limit == 0 && room_limit == 0 {
+		return Ok(());
+	}
+
+	let now = Instant::now();
+	let mut invites = self.invites.lock()?;
+	prune(&mut invites.senders, now);
+	prune(&mut invites.rooms, now);
+
+	if let Some(retry_after) = over(invites.senders.get(sender), sender_limit, now) {
+		warn!(%sender, %room_id, "Refused invite; sender reached {sender_limit} invites per hour");
+		return Err(limit_exceeded(
+			retry_after,
+			"You have sent too many invites; try again later.",
+		));
+	}
+
+	if let Some(retry_after) = over(invites.rooms.get(room_id), room_limit, now) {
+		warn!(%sender, %room_id, "Refused invite; room reached {room_limit} invites per hour");
+		return Err(limit_exceeded(
+			retry_after,
+			"Too many invites have been sent to this room; try again later.",
+		));
+	}
+
+	invites
+		.senders
+		.entry(sender.to_owned())
+		.or_default()
+		.push_back(now);
+
+	invites
+		.rooms
+		.entry(room_id.to_owned())
+		.or_default()
+		.push_back(now);
+
+	Ok(())
+}
+
+/// Invites counted within the last hour, per sender and per room, most first.
+#[implement(super::Service)]
+#[must_use]
+pub fn invite_counters(&self) -> (Vec<(OwnedUserId, usize)>, Vec<(OwnedRoomId, usize)>) {
+	let now = Instant::now();
+	let mut invites = self.invites.lock().expect("locked");
+	prune(&mut invites.senders, now);
+	prune(&mut invites.rooms, now);
+
+	(counts(&invites.senders), counts(&invites.rooms))
+}
+
+/// Forgets the invites counted against a sender or room, or all of them.
+#[implement(super::Service)]
+pub fn reset_invite_counters(&self, sender: Option<&UserId>, room_id: Option<&RoomId>) {
+	let mut invites = self.invites.lock().expect("locked");
+	if sender.is_none() && room_id.is_none() {
+		invites.senders.clear();
+		invites.rooms.clear();
+		return;
+	}
+
+	if let Some(sender) = sender {
+		invites.senders.remove(sender);
+	}
+
+	if let Some(room_id) = room_id {
+		invites.rooms.remove(room_id);
+	}
+}
+
+/// When `limit` has been reached, how long until the oldest counted invite
+/// leaves the window.
+fn over(times: Option<&VecDeque<Instant>>, limit: usize, now: Instant) -> Option<Duration> {
+	let times = times.filter(|times| limit > 0 && times.len() >= limit)?;
+	let oldest = times.front()?;
+
+	Some(WINDOW.saturating_sub(now.duration_since(*oldest)))
+}
+
+fn prune<K: Eq + Hash>(map: &mut HashMap<K, VecDeque<Instant>>, now: Instant) {
+	map.retain(|_, times| {
+		while times
+			.front()
+			.is_some_and(|&at| now.duration_since(at) >= WINDOW)
+		{
+			times.pop_front();
+		}
+
+		!times.is_empty()
+	});
+}
+
+fn counts<K: Clone + Ord>(map: &HashMap<K, VecDeque<Instant>>) -> Vec<(K, usize)> {
+	let mut counts: Vec<_> = map
+		.iter()
+		.map(|(key, times)| (key.clone(), times.len()))
+		.collect();
+
+	counts.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+	counts
+}
@@ -0,0 +1,154 @@
+//! Remote join rate limiting and "raid mode", under which joins to a room
+//! need approval from a server admin.
+
+use std::{
+	collections::{BTreeSet, HashMap},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{
+	config::{RateLimitKey, RateLimitRule},
+	implement, info, warn, Err, Result,
+};
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+
+use super::{limit_exceeded, Key};
+
+pub(super) type Raids = HashMap<OwnedRoomId, Raid>;
+
+pub(super) struct Raid {
+	/// When raid mode ends by itself, if ever.
+	until: Option<Instant>,
+
+	/// Users refused while raid mode was on, awaiting approval.
+	pending: BTreeSet<OwnedUserId>,
+
+	/// Users an admin approved to join.
+	approved: BTreeSet<OwnedUserId>,
+}
+
+/// State of raid mode in a room, as shown to admins.
+#[derive(Clone, Debug)]
+pub struct RaidMode {
+	pub room_id: OwnedRoomId,
+
+	/// Time left until raid mode ends by itself.
+	pub remaining: Option<Duration>,
+
+	pub pending: Vec<OwnedUserId>,
+
+	pub approved: Vec<OwnedUserId>,
+}
+
+/// Counts a join from another server into `room_id` against
+/// `remote_join_rate_per_room`.
+#[implement(super::Service)]
+pub fn check_remote_join(&self, room_id: &RoomId) -> Result {
+	let config = &self.server.config;
+	if config.remote_join_rate_per_room <= 0.0 {
+		return Ok(());
+	}
+
+	let rule = RateLimitRule {
+		per_second: config.remote_join_rate_per_room,
+		burst: config.remote_join_burst_per_room.max(1),
+		per: RateLimitKey::Ip,
+	};
+
+	self.take("remote_join", Key::Room(room_id.to_owned()), &rule)
+		.map_err(|retry_after| {
+			warn!(%room_id, "Refused remote join; room is over its join rate");
+			limit_exceeded(retry_after, "Too many users are joining this room; try again later.")
+		})
+}
+
+/// Refuses a join to a room in raid mode unless an admin approved the user,
+/// noting the user as pending approval.
+#[implement(super::Service)]
+pub fn check_raid_join(&self, room_id: &RoomId, user_id: &UserId) -> Result {
+	let mut raids = self.raids.lock()?;
+	prune(&mut raids);
+
+	let Some(raid) = raids.get_mut(room_id) else {
+		return Ok(());
+	};
+
+	if raid.approved.contains(user_id) {
+		return Ok(());
+	}
+
+	if raid.pending.insert(user_id.to_owned()) {
+		info!(%room_id, %user_id, "Join awaits approval under raid mode");
+	}
+
+	Err!(Request(Forbidden(
+		"This room is only accepting joins approved by the server admins; your request has been \
+		 noted."
+	)))
+}
+
+/// Turns on raid mode for `room_id`, for `duration` or until turned off.
+#[implement(super::Service)]
+pub fn enable_raid_mode(&self, room_id: &RoomId, duration: Option<Duration>) {
+	let until = duration.and_then(|duration| Instant::now().checked_add(duration));
+	let mut raids = self.raids.lock().expect("locked");
+	raids
+		.entry(room_id.to_owned())
+		.and_modify(|raid| raid.until = until)
+		.or_insert_with(|| Raid {
+			until,
+			pending: BTreeSet::new(),
+			approved: BTreeSet::new(),
+		});
+}
+
+/// Turns off raid mode for `room_id`. Returns whether it was on.
+#[implement(super::Service)]
+pub fn disable_raid_mode(&self, room_id: &RoomId) -> bool {
+	let mut raids = self.raids.lock().expect("locked");
+	prune(&mut raids);
+	raids.remove(room_id).is_some()
+}
+
+/// Allows `user_id` to join `room_id` while it's in raid mode. Returns
+/// whether the room is in raid mode.
+#[implement(super::Service)]
+pub fn approve_join(&self, room_id: &RoomId, user_id: &UserId) -> bool {
+	let mut raids = self.raids.lock().expect("locked");
+	prune(&mut raids);
+
+	let Some(raid) = raids.get_mut(room_id) else {
+		return false;
+	};
+
+	raid.pending.remove(user_id);
+	raid.approved.insert(user_id.to_owned());
+	true
+}
+
+/// Rooms in raid mode.
+#[implement(super::Service)]
+#[must_use]
+pub fn raid_modes(&self) -> Vec<RaidMode> {
+	let mut raids = self.raids.lock().expect("locked");
+	prune(&mut raids);
+
+	let now = Instant::now();
+	let mut modes: Vec<_> = raids
+		.iter()
+		.map(|(room_id, raid)| RaidMode {
+			room_id: room_id.clone(),
+			remaining: raid.until.map(|until| until.saturating_duration_since(now)),
+			pending: raid.pending.iter().cloned().collect(),
+			approved: raid.approved.iter().cloned().collect(),
+		})
+		.collect();
+
+	modes.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+	modes
+}
+
+fn prune(raids: &mut Raids) {
+	let now = Instant::now();
+	raids.retain(|_, raid| raid.until.is_none_or(|until| until > now));
+}
@@ -0,0 +1,359 @@
+mod invite;
+mod join;
+mod register;
+mod tests;
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	net::IpAddr,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{
+	config::{RateLimitKey, RateLimitRule},
+	err, implement, Error, Result, Server,
+};
+use http::{HeaderMap, Method, StatusCode};
+use ipaddress::IPAddress;
+use lru_cache::LruCache;
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	OwnedRoomId, OwnedUserId,
+};
+
+pub use self::join::RaidMode;
+use self::{invite::Invites, join::Raids, register::Registrations};
+use crate::{appservice, users, Dep};
+
+pub struct Service {
+	server: Arc<Server>,
+	services: Services,
+	buckets: Mutex<HashMap<(&'static str, Key), Bucket>>,
+	identities: Mutex<LruCache<String, (Identity, Instant)>>,
+	invites: Mutex<Invites>,
+	raids: Mutex<Raids>,
+	registrations: Mutex<Registrations>,
+	trusted_proxies: Vec<IPAddress>,
+	registration_allowlist: Vec<IPAddress>,
+}
+
+struct Services {
+	appservice: Dep<appservice::Service>,
+	users: Dep<users::Service>,
+}
+
+/// What requests are counted against.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Key {
+	Appservice(String),
+	User(OwnedUserId),
+	Ip(IpAddr),
+	Room(OwnedRoomId),
+}
+
+/// Who an access token belongs to, as far as rate limiting is concerned.
+#[derive(Clone)]
+enum Identity {
+	Exempt,
+	/// An appservice by ID, and whether its registration opts into
+	/// `rate_limits`.
+	Appservice(String, bool),
+	User(OwnedUserId),
+	Unknown,
+}
+
+/// Token bucket; refilled at the rule's sustained rate up to its burst.
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// How long the owner of an access token is remembered for.
+const IDENTITY_TTL: Duration = Duration::from_secs(60);
+const IDENTITY_CACHE_CAPACITY: usize = 4096;
+
+/// Buckets are pruned of idle entries once there are this many.
+const BUCKETS_PRUNE_LEN: usize = 65536;
+const BUCKET_IDLE: Duration = Duration::from_secs(3600);
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		Ok(Arc::new(Self {
+			server: args.server.clone(),
+			services: Services {
+				appservice: args.depend::<appservice::Service>("appservice"),
+				users: args.depend::<users::Service>("users"),
+			},
+			buckets: Mutex::default(),
+			identities: Mutex::new(LruCache::new(IDENTITY_CACHE_CAPACITY)),
+			invites: Mutex::default(),
+			raids: Mutex::default(),
+			registrations: Mutex::default(),
+			trusted_proxies: config
+				.trusted_proxies
+				.iter()
+				.map(IPAddress::parse)
+				.collect::<Result<_, String>>()
+				.map_err(|e| err!(Config("trusted_proxies", e)))?,
+			registration_allowlist: config
+				.registration_rate_limit_allowlist
+				.iter()
+				.map(IPAddress::parse)
+				.collect::<Result<_, String>>()
+				.map_err(|e| err!(Config("registration_rate_limit_allowlist", e)))?,
+		}))
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let buckets = self.buckets.lock()?.len();
+		let identities = self.identities.lock()?.len();
+		writeln!(out, "ratelimit_buckets: {buckets}")?;
+		writeln!(out, "ratelimit_identities: {identities}")?;
+
+		let invites = self.invites.lock()?;
+		writeln!(out, "ratelimit_invite_senders: {}", invites.senders.len())?;
+		writeln!(out, "ratelimit_invite_rooms: {}", invites.rooms.len())?;
+
+		let raids = self.raids.lock()?.len();
+		writeln!(out, "ratelimit_raid_rooms: {raids}")?;
+
+		let registrations = self.registrations.lock()?;
+		writeln!(out, "ratelimit_register_addresses: {}", registrations.register.len())?;
+		writeln!(
+			out,
+			"ratelimit_register_available_addresses: {}",
+			registrations.available.len()
+		)?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.identities.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Counts a request to the route `path` against the rate limit configured
+/// for its endpoint class, refusing it with M_LIMIT_EXCEEDED when over.
+#[implement(Service)]
+pub async fn check(
+	&self,
+	method: &Method,
+	path: &str,
+	access_token: Option<&str>,
+	ip: IpAddr,
+) -> Result {
+	let Some(class) = classify(method, path) else {
+		return Ok(());
+	};
+
+	let config = &self.server.config;
+	let rule = config.rate_limits.get(class);
+	if rule.is_none() && config.appservice_rate_limits.is_empty() {
+		return Ok(());
+	}
+
+	let identity = match access_token {
+		| Some(access_token) => self.identify(access_token).await,
+		| None => Identity::Unknown,
+	};
+
+	if let Identity::Appservice(id, _) = &identity {
+		if let Some(ceiling) = config.appservice_rate_limits.get(id) {
+			self.take("appservice", Key::Appservice(id.clone()), ceiling)
+				.map_err(|retry_after| {
+					limit_exceeded(retry_after, "Appservice is over its request limit.")
+				})?;
+		}
+	}
+
+	let Some(rule) = rule else {
+		return Ok(());
+	};
+
+	let Some(key) = key(identity, rule.per, ip) else {
+		return Ok(());
+	};
+
+	self.take(class, key, rule)
+		.map_err(|retry_after| limit_exceeded(retry_after, "Too many requests; try again later."))
+}
+
+/// Address of the client behind a request from `peer`: the last address in
+/// X-Forwarded-For which isn't a trusted proxy when `peer` is one, otherwise
+/// `peer` itself.
+#[implement(Service)]
+#[must_use]
+pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+	forwarded_client(&self.trusted_proxies, peer, headers)
+}
+
+/// Whether `ip` is one of `trusted_proxies`, whose forwarded headers are
+/// believed.
+#[implement(Service)]
+#[must_use]
+pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool { is_trusted(&self.trusted_proxies, ip) }
+
+fn forwarded_client(trusted: &[IPAddress], peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+	if !is_trusted(trusted, peer) {
+		return peer;
+	}
+
+	let forwarded: Vec<&str> = headers
+		.get_all("x-forwarded-for")
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.collect();
+
+	// Each trusted proxy appends the address it received the request from, so
+	// walk back from the nearest hop until one isn't a trusted proxy.
+	let mut client = peer;
+	for addr in forwarded.into_iter().rev() {
+		let Ok(addr) = addr.trim().parse() else {
+			break;
+		};
+
+		client = addr;
+		if !is_trusted(trusted, addr) {
+			break;
+		}
+	}
+
+	client
+}
+
+fn is_trusted(trusted: &[IPAddress], ip: IpAddr) -> bool {
+	let Ok(ip) = IPAddress::parse(ip.to_string()) else {
+		return false;
+	};
+
+	trusted.iter().any(|cidr| cidr.includes(&ip))
+}
+
+/// Takes a token from the bucket, or returns how long until one is
+/// available.
+#[implement(Service)]
+fn take(&self, class: &'static str, key: Key, rule: &RateLimitRule) -> Result<(), Duration> {
+	let now = Instant::now();
+	let burst = f64::from(rule.burst);
+	let mut buckets = self.buckets.lock().expect("locked");
+	if buckets.len() >= BUCKETS_PRUNE_LEN {
+		buckets.retain(|_, bucket| now.duration_since(bucket.updated) < BUCKET_IDLE);
+	}
+
+	let bucket = buckets
+		.entry((class, key))
+		.or_insert(Bucket { tokens: burst, updated: now });
+
+	let refill = now.duration_since(bucket.updated).as_secs_f64() * rule.per_second;
+	bucket.tokens = (bucket.tokens + refill).min(burst);
+	bucket.updated = now;
+
+	if bucket.tokens >= 1.0 {
+		bucket.tokens -= 1.0;
+		return Ok(());
+	}
+
+	let wait = (1.0 - bucket.tokens) / rule.per_second;
+	Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+}
+
+#[implement(Service)]
+async fn identify(&self, access_token: &str) -> Identity {
+	let cached = self
+		.identities
+		.lock()
+		.expect("locked")
+		.get_mut(access_token)
+		.filter(|(_, at)| at.elapsed() < IDENTITY_TTL)
+		.map(|(identity, _)| identity.clone());
+
+	if let Some(identity) = cached {
+		return identity;
+	}
+
+	let identity =
+		if let Some(info) = self.services.appservice.find_from_token(access_token).await {
+			let rate_limited = info.registration.rate_limited == Some(true);
+			Identity::Appservice(info.registration.id, rate_limited)
+		} else if let Ok((user_id, _)) = self.services.users.find_from_token(access_token).await {
+			if self.server.config.rate_limit_exempt_admins
+				&& self.services.users.is_admin(&user_id).await
+			{
+				Identity::Exempt
+			} else {
+				Identity::User(user_id)
+			}
+		} else {
+			Identity::Unknown
+		};
+
+	self.identities
+		.lock()
+		.expect("locked")
+		.insert(access_token.to_owned(), (identity.clone(), Instant::now()));
+
+	identity
+}
+
+/// What a request is counted against under a rule keyed `per`, or None if its
+/// sender is exempt. `ip` must come from `client_ip`, never from headers the
+/// client controls, or rotating them would evade the limit.
+fn key(identity: Identity, per: RateLimitKey, ip: IpAddr) -> Option<Key> {
+	match (identity, per) {
+		| (Identity::Exempt | Identity::Appservice(_, false), _) => None,
+		| (Identity::Appservice(id, true), RateLimitKey::User) => Some(Key::Appservice(id)),
+		| (Identity::User(user_id), RateLimitKey::User) => Some(Key::User(user_id)),
+		| (_, RateLimitKey::Ip | RateLimitKey::User) => Some(Key::Ip(ip)),
+	}
+}
+
+/// M_LIMIT_EXCEEDED error telling the client when to retry.
+fn limit_exceeded(retry_after: Duration, message: &'static str) -> Error {
+	Error::Request(
+		ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(retry_after)),
+		},
+		message.into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	)
+}
+
+/// Endpoint class of the route `path`, as named in `rate_limits`. Requests
+/// which don't change anything only ever count as "client".
+fn classify(method: &Method, path: &str) -> Option<&'static str> {
+	if path.starts_with("/_matrix/federation/") || path.starts_with("/_matrix/key/") {
+		return Some("federation");
+	}
+
+	let is_media = path.starts_with("/_matrix/media/") || path.contains("/media/");
+	if is_media && (path.contains("/upload") || path.contains("/create")) {
+		return Some("media_upload");
+	}
+
+	if !path.starts_with("/_matrix/client/") && !is_media {
+		return None;
+	}
+
+	let class = if method.is_safe() {
+		"client"
+	} else if path.ends_with("/login") {
+		"login"
+	} else if path.ends_with("/register") {
+		"register"
+	} else if path.contains("/send/") || path.contains("/state/") || path.contains("/redact/") {
+		"message"
+	} else if path.contains("/join") || path.contains("/knock/") {
+		"join"
+	} else if path.ends_with("/invite") {
+		"invite"
+	} else {
+		"client"
+	};
+
+	Some(class)
+}
@@ -0,0 +1,108 @@
+//! Per-address caps on registration attempts and username availability
+//! checks.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	net::IpAddr,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{implement, warn, Result};
+use http::Method;
+use ipaddress::IPAddress;
+
+use super::{limit_exceeded, Identity};
+
+#[derive(Default)]
+pub(super) struct Registrations {
+	/// Times of `/register` requests from each address within the window,
+	/// oldest first.
+	pub(super) register: HashMap<IpAddr, VecDeque<Instant>>,
+
+	/// Times of `/register/available` requests from each address within the
+	/// window, oldest first.
+	pub(super) available: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+/// Counts a request to `/register` or `/register/available` from `ip`,
+/// refusing it when the address has reached `registrations_per_ip` or
+/// `register_available_per_ip`.
+#[implement(super::Service)]
+pub async fn check_registration(
+	&self,
+	method: &Method,
+	path: &str,
+	access_token: Option<&str>,
+	ip: IpAddr,
+) -> Result {
+	let config = &self.server.config;
+	let available = path.ends_with("/register/available");
+	let (limit, window) = if available {
+		(config.register_available_per_ip, config.register_available_window)
+	} else if method == Method::POST && path.ends_with("/register") {
+		(config.registrations_per_ip, config.registration_rate_limit_window)
+	} else {
+		return Ok(());
+	};
+
+	let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+	if limit == 0 || self.registration_allowed(ip) {
+		return Ok(());
+	}
+
+	if let Some(access_token) = access_token {
+		if matches!(
+			self.identify(access_token).await,
+			Identity::Exempt | Identity::Appservice(..)
+		) {
+			return Ok(());
+		}
+	}
+
+	let now = Instant::now();
+	let window = Duration::from_secs(window);
+	let mut registrations = self.registrations.lock()?;
+	let counted = if available {
+		&mut registrations.available
+	} else {
+		&mut registrations.register
+	};
+
+	counted.retain(|_, times| {
+		while times
+			.front()
+			.is_some_and(|&at| now.duration_since(at) >= window)
+		{
+			times.pop_front();
+		}
+
+		!times.is_empty()
+	});
+
+	let times = counted.entry(ip).or_default();
+	if times.len() >= limit {
+		let oldest = times.front().copied().unwrap_or(now);
+		let retry_after = window.saturating_sub(now.duration_since(oldest));
+		warn!(%ip, path, "Refused registration request; address reached {limit} requests");
+		return Err(limit_exceeded(
+			retry_after,
+			"Too many registration requests; try again later.",
+		));
+	}
+
+	times.push_back(now);
+
+	Ok(())
+}
+
+/// Whether `ip` is in `registration_rate_limit_allowlist`.
+#[implement(super::Service)]
+fn registration_allowed(&self, ip: IpAddr) -> bool {
+	let Ok(ip) = IPAddress::parse(ip.to_string()) else {
+		return false;
+	};
+
+	self.registration_allowlist
+		.iter()
+		.any(|cidr| cidr.includes(&ip))
+}
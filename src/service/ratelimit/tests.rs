@@ -0,0 +1,140 @@
+#![cfg(test)]
+
+use std::net::IpAddr;
+
+use conduwuit::config::RateLimitKey;
+use http::HeaderMap;
+use ipaddress::IPAddress;
+use ruma::owned_user_id;
+
+use super::{forwarded_client, is_trusted, key, Identity, Key};
+
+fn ip() -> IpAddr { "192.0.2.1".parse().expect("valid address") }
+
+#[test]
+fn key_user_per_user() {
+	let user_id = owned_user_id!("@alice:example.com");
+
+	assert_eq!(
+		key(Identity::User(user_id.clone()), RateLimitKey::User, ip()),
+		Some(Key::User(user_id))
+	);
+}
+
+#[test]
+fn key_user_per_ip() {
+	let user_id = owned_user_id!("@alice:example.com");
+
+	assert_eq!(key(Identity::User(user_id), RateLimitKey::Ip, ip()), Some(Key::Ip(ip())));
+}
+
+#[test]
+fn key_unknown_is_ip() {
+	assert_eq!(key(Identity::Unknown, RateLimitKey::User, ip()), Some(Key::Ip(ip())));
+	assert_eq!(key(Identity::Unknown, RateLimitKey::Ip, ip()), Some(Key::Ip(ip())));
+}
+
+#[test]
+fn key_exempt() {
+	assert_eq!(key(Identity::Exempt, RateLimitKey::User, ip()), None);
+	assert_eq!(key(Identity::Exempt, RateLimitKey::Ip, ip()), None);
+}
+
+#[test]
+fn key_appservice() {
+	let id = "bridge".to_owned();
+
+	assert_eq!(key(Identity::Appservice(id.clone(), false), RateLimitKey::User, ip()), None);
+	assert_eq!(
+		key(Identity::Appservice(id.clone(), true), RateLimitKey::User, ip()),
+		Some(Key::Appservice(id.clone()))
+	);
+	assert_eq!(key(Identity::Appservice(id, true), RateLimitKey::Ip, ip()), Some(Key::Ip(ip())));
+}
+
+fn trusted(cidrs: &[&str]) -> Vec<IPAddress> {
+	cidrs
+		.iter()
+		.map(|cidr| IPAddress::parse(*cidr).expect("valid CIDR"))
+		.collect()
+}
+
+fn forwarded_for(values: &[&str]) -> HeaderMap {
+	let mut headers = HeaderMap::new();
+	for value in values {
+		headers.append("x-forwarded-for", value.parse().expect("valid header value"));
+	}
+
+	headers
+}
+
+fn addr(ip: &str) -> IpAddr { ip.parse().expect("valid address") }
+
+#[test]
+fn untrusted_peer_ignores_forwarded_for() {
+	let headers = forwarded_for(&["198.51.100.7"]);
+
+	assert_eq!(forwarded_client(&[], addr("192.0.2.1"), &headers), addr("192.0.2.1"));
+	assert_eq!(
+		forwarded_client(&trusted(&["10.0.0.0/8"]), addr("192.0.2.1"), &headers),
+		addr("192.0.2.1")
+	);
+}
+
+#[test]
+fn trusted_peer_uses_forwarded_for() {
+	let headers = forwarded_for(&["198.51.100.7"]);
+
+	assert_eq!(
+		forwarded_client(&trusted(&["127.0.0.1/32"]), addr("127.0.0.1"), &headers),
+		addr("198.51.100.7")
+	);
+}
+
+#[test]
+fn trusted_peer_without_forwarded_for() {
+	assert_eq!(
+		forwarded_client(&trusted(&["127.0.0.1/32"]), addr("127.0.0.1"), &HeaderMap::new()),
+		addr("127.0.0.1")
+	);
+}
+
+#[test]
+fn multi_hop_stops_at_first_untrusted() {
+	// The client forged the first entry; 203.0.113.5 is the address the outer
+	// trusted proxy received the request from.
+	let headers = forwarded_for(&["1.2.3.4, 203.0.113.5, 10.0.0.2"]);
+	let proxies = trusted(&["10.0.0.0/8"]);
+
+	assert_eq!(forwarded_client(&proxies, addr("10.0.0.1"), &headers), addr("203.0.113.5"));
+}
+
+#[test]
+fn multi_hop_across_headers() {
+	let headers = forwarded_for(&["1.2.3.4", "203.0.113.5", "10.0.0.2"]);
+	let proxies = trusted(&["10.0.0.0/8"]);
+
+	assert_eq!(forwarded_client(&proxies, addr("10.0.0.1"), &headers), addr("203.0.113.5"));
+}
+
+#[test]
+fn unparsable_hop_stops_walk() {
+	let headers = forwarded_for(&["203.0.113.5, garbage, 10.0.0.2"]);
+	let proxies = trusted(&["10.0.0.0/8"]);
+
+	assert_eq!(forwarded_client(&proxies, addr("10.0.0.1"), &headers), addr("10.0.0.2"));
+}
+
+#[test]
+fn cidr_matching() {
+	let proxies = trusted(&["10.0.0.0/8", "192.168.1.0/24", "fd00::/8", "::1/128"]);
+
+	assert!(is_trusted(&proxies, addr("10.255.0.1")));
+	assert!(is_trusted(&proxies, addr("192.168.1.200")));
+	assert!(is_trusted(&proxies, addr("fd12:3456::1")));
+	assert!(is_trusted(&proxies, addr("::1")));
+	assert!(!is_trusted(&proxies, addr("11.0.0.1")));
+	assert!(!is_trusted(&proxies, addr("192.168.2.1")));
+	assert!(!is_trusted(&proxies, addr("fe80::1")));
+	assert!(!is_trusted(&[], addr("10.0.0.1")));
+}
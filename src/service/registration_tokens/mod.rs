@@ -0,0 +1,191 @@
+mod tests;
+
+use std::sync::Arc;
+
+use conduwuit::{
+	debug_info, error, implement,
+	utils::{self, stream::TryIgnore, MutexMap},
+	Err, Result,
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+pub struct Service {
+	db: Data,
+	static_token: Option<String>,
+	token_mutex: MutexMap<String, ()>,
+}
+
+struct Data {
+	registrationtoken_info: Arc<Map>,
+}
+
+/// A registration token stored in the database, with usage counts following
+/// MSC3231. Only completed registrations count as uses; one which passed the
+/// UIAA stage but was abandoned leaves the token as it was.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenInfo {
+	pub uses_allowed: Option<u64>,
+	pub completed: u64,
+	pub expiry_time: Option<u64>,
+}
+
+const TOKEN_LENGTH: usize = 16;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let static_token = config.registration_token_file.as_ref().map_or(
+			config.registration_token.clone(),
+			|path| {
+				let Ok(token) = std::fs::read_to_string(path).inspect_err(|e| {
+					error!("Failed to read the registration token file: {e}");
+				}) else {
+					return config.registration_token.clone();
+				};
+
+				Some(token)
+			},
+		);
+
+		Ok(Arc::new(Self {
+			db: Data {
+				registrationtoken_info: args.db["registrationtoken_info"].clone(),
+			},
+			static_token,
+			token_mutex: MutexMap::new(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether a registration token is set in the config. It is valid without
+/// limit in addition to those in the database.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn has_static_token(&self) -> bool { self.static_token.is_some() }
+
+/// Creates a new registration token, generating one if not given.
+#[implement(Service)]
+pub async fn create(
+	&self,
+	token: Option<String>,
+	uses_allowed: Option<u64>,
+	expiry_time: Option<u64>,
+) -> Result<String> {
+	let token = token.unwrap_or_else(|| utils::random_string(TOKEN_LENGTH));
+	if token.is_empty()
+		|| token.len() > 64
+		|| !token
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || "._~-".contains(c))
+	{
+		return Err!(Request(InvalidParam(
+			"Registration tokens must be 1 to 64 characters from [A-Za-z0-9._~-]."
+		)));
+	}
+
+	let _lock = self.token_mutex.lock(&token).await;
+	if self.is_static(&token) || self.get(&token).await.is_ok() {
+		return Err!(Request(InvalidParam("Registration token already exists.")));
+	}
+
+	let info = TokenInfo {
+		uses_allowed,
+		expiry_time,
+		..TokenInfo::default()
+	};
+	self.db.registrationtoken_info.put(&token, Json(&info));
+
+	debug_info!(?uses_allowed, ?expiry_time, "Created registration token");
+
+	Ok(token)
+}
+
+/// Removes a registration token from the database. Registrations which have
+/// already passed the token stage with it can no longer complete.
+#[implement(Service)]
+pub async fn revoke(&self, token: &str) -> Result {
+	let _lock = self.token_mutex.lock(token).await;
+	if self.get(token).await.is_err() {
+		return Err!(Request(NotFound("Registration token not found.")));
+	}
+
+	self.db.registrationtoken_info.remove(token);
+
+	debug_info!("Revoked registration token");
+
+	Ok(())
+}
+
+#[implement(Service)]
+pub async fn get(&self, token: &str) -> Result<TokenInfo> {
+	self.db
+		.registrationtoken_info
+		.get(token)
+		.await
+		.deserialized()
+}
+
+/// Lists the registration tokens in the database.
+#[implement(Service)]
+pub fn list(&self) -> impl Stream<Item = (String, TokenInfo)> + Send + '_ {
+	self.db
+		.registrationtoken_info
+		.stream()
+		.ignore_err()
+		.map(|(token, info): (&str, TokenInfo)| (token.to_owned(), info))
+}
+
+/// Whether a registration token can currently be used to register.
+#[implement(Service)]
+pub async fn is_valid(&self, token: &str) -> bool {
+	self.is_static(token)
+		|| self
+			.get(token)
+			.await
+			.is_ok_and(|info| info.is_usable(utils::millis_since_unix_epoch()))
+}
+
+/// Counts a use of a registration token by a registration about to complete.
+/// The token is checked again, as other registrations which passed the UIAA
+/// stage with it may have used it up in the meantime.
+#[implement(Service)]
+pub async fn complete(&self, token: &str) -> Result {
+	if self.is_static(token) {
+		return Ok(());
+	}
+
+	let _lock = self.token_mutex.lock(token).await;
+	let Ok(mut info) = self.get(token).await else {
+		return Err!(Request(Forbidden("Registration token was revoked.")));
+	};
+
+	if !info.is_usable(utils::millis_since_unix_epoch()) {
+		return Err!(Request(Forbidden("Registration token is expired or used up.")));
+	}
+
+	info.completed = info.completed.saturating_add(1);
+	self.db.registrationtoken_info.put(token, Json(&info));
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn is_static(&self, token: &str) -> bool {
+	self.static_token
+		.as_deref()
+		.is_some_and(|static_token| static_token.trim() == token.trim())
+}
+
+impl TokenInfo {
+	#[must_use]
+	pub fn is_usable(&self, now: u64) -> bool {
+		self.uses_allowed
+			.is_none_or(|allowed| self.completed < allowed)
+			&& self.expiry_time.is_none_or(|expiry| now < expiry)
+	}
+}
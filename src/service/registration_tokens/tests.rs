@@ -0,0 +1,32 @@
+#![cfg(test)]
+
+use super::TokenInfo;
+
+#[test]
+fn unlimited() {
+	let info = TokenInfo { completed: 1000, ..TokenInfo::default() };
+	assert!(info.is_usable(0));
+}
+
+#[test]
+fn used_up_by_completed_registrations() {
+	let info = TokenInfo {
+		uses_allowed: Some(2),
+		completed: 1,
+		..TokenInfo::default()
+	};
+	assert!(info.is_usable(0));
+
+	let info = TokenInfo { completed: 2, ..info };
+	assert!(!info.is_usable(0));
+}
+
+#[test]
+fn expired() {
+	let info = TokenInfo {
+		expiry_time: Some(1000),
+		..TokenInfo::default()
+	};
+	assert!(info.is_usable(999));
+	assert!(!info.is_usable(1000));
+}
@@ -0,0 +1,163 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
+
+use conduwuit::{debug, err, implement, utils, Err, Error, Result, Server};
+use http::StatusCode;
+use ruma::api::client::error::ErrorKind;
+
+pub struct Service {
+	server: Arc<Server>,
+	sessions: Mutex<HashMap<String, Session>>,
+}
+
+/// A rendezvous session (MSC4108) through which two devices exchange the
+/// messages of their secure channel when signing in with a QR code. The
+/// payload is opaque to the server.
+#[derive(Clone, Debug)]
+pub struct Session {
+	pub content: String,
+	pub etag: String,
+	pub last_modified: SystemTime,
+	pub expires: SystemTime,
+}
+
+const SESSION_ID_LENGTH: usize = 32;
+
+const ETAG_LENGTH: usize = 16;
+
+const SESSION_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Largest payload accepted, in bytes.
+const MAX_CONTENT_LENGTH: usize = 4096;
+
+/// Most sessions held at once, bounding the memory clients can claim.
+const MAX_SESSIONS: usize = 1024;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			server: args.server.clone(),
+			sessions: Mutex::new(HashMap::new()),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether devices may sign in by scanning a QR code from an existing
+/// session.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.server.config.allow_qr_code_login }
+
+/// Creates a session holding the initial payload, returning its ID.
+#[implement(Service)]
+pub fn create(&self, content: String) -> Result<(String, Session)> {
+	self.check_enabled()?;
+	check_content(&content)?;
+
+	let mut sessions = self.sessions.lock().expect("locked");
+	let now = SystemTime::now();
+	sessions.retain(|_, session| session.expires > now);
+	if sessions.len() >= MAX_SESSIONS {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			"Too many rendezvous sessions in progress.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let id = utils::random_string(SESSION_ID_LENGTH);
+	let session = Session {
+		content,
+		etag: new_etag(),
+		last_modified: now,
+		expires: now
+			.checked_add(SESSION_LIFETIME)
+			.expect("session expiry does not overflow"),
+	};
+
+	sessions.insert(id.clone(), session.clone());
+	debug!(%id, "Created rendezvous session");
+
+	Ok((id, session))
+}
+
+/// Gets the current payload of a session.
+#[implement(Service)]
+pub fn get(&self, id: &str) -> Result<Session> {
+	self.check_enabled()?;
+
+	self.sessions
+		.lock()
+		.expect("locked")
+		.get(id)
+		.filter(|session| session.expires > SystemTime::now())
+		.cloned()
+		.ok_or_else(|| err!(Request(NotFound("Rendezvous session not found or expired."))))
+}
+
+/// Replaces the payload of a session, provided it has not changed since the
+/// caller last read it. The session's expiry is unchanged.
+#[implement(Service)]
+pub fn update(&self, id: &str, etag: &str, content: String) -> Result<Session> {
+	self.check_enabled()?;
+	check_content(&content)?;
+
+	let mut sessions = self.sessions.lock().expect("locked");
+	let session = sessions
+		.get_mut(id)
+		.filter(|session| session.expires > SystemTime::now())
+		.ok_or_else(|| err!(Request(NotFound("Rendezvous session not found or expired."))))?;
+
+	if session.etag != etag {
+		return Err(Error::Request(
+			ErrorKind::Unknown,
+			"Rendezvous session was modified concurrently.".into(),
+			StatusCode::PRECONDITION_FAILED,
+		));
+	}
+
+	session.content = content;
+	session.etag = new_etag();
+	session.last_modified = SystemTime::now();
+
+	Ok(session.clone())
+}
+
+/// Ends a session before it expires.
+#[implement(Service)]
+pub fn delete(&self, id: &str) -> Result {
+	self.check_enabled()?;
+
+	if self.sessions.lock().expect("locked").remove(id).is_none() {
+		return Err!(Request(NotFound("Rendezvous session not found or expired.")));
+	}
+
+	debug!(%id, "Deleted rendezvous session");
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn check_enabled(&self) -> Result {
+	if !self.is_enabled() {
+		return Err!(Request(Unrecognized("QR code login is not enabled.")));
+	}
+
+	Ok(())
+}
+
+fn check_content(content: &str) -> Result {
+	if content.len() > MAX_CONTENT_LENGTH {
+		return Err!(Request(TooLarge("Rendezvous payload is too large.")));
+	}
+
+	Ok(())
+}
+
+fn new_etag() -> String { format!("\"{}\"", utils::random_string(ETAG_LENGTH)) }
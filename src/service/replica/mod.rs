@@ -0,0 +1,77 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{debug, warn, Result, Server};
+use database::Database;
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+
+/// Keeps a read replica (a database opened as a RocksDB secondary) caught up
+/// with its primary.
+pub struct Service {
+	interrupt: Notify,
+	db: Arc<Database>,
+	server: Arc<Server>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			db: args.db.clone(),
+			server: args.server.clone(),
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "replica", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		if !self.db.is_secondary() {
+			return Ok(());
+		}
+
+		let period = Duration::from_millis(
+			self.server
+				.config
+				.rocksdb_secondary_catchup_interval_ms
+				.max(1),
+		);
+
+		let mut i = interval(period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.catch_up().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Catches up with the primary now rather than on the next interval.
+	pub async fn catch_up(&self) {
+		let db = self.db.clone();
+		match self
+			.server
+			.runtime()
+			.spawn_blocking(move || db.catch_up())
+			.await
+		{
+			| Ok(Ok(true)) => debug!("Caught up with the primary"),
+			| Ok(Ok(false)) => (),
+			| Ok(Err(e)) => warn!("Failed to catch up with the primary: {e}"),
+			| Err(e) => warn!("Failed to catch up with the primary: {e}"),
+		}
+	}
+}
@@ -7,7 +7,7 @@ mod tests;
 use std::sync::Arc;
 
 use arrayvec::ArrayString;
-use conduwuit::{utils::MutexMap, Result, Server};
+use conduwuit::{err, utils::MutexMap, Result, Server};
 
 use self::{cache::Cache, dns::Resolver};
 use crate::{client, Dep};
@@ -44,3 +44,15 @@ impl crate::Service for Service {
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
+
+impl Service {
+	/// Resolves `hostname` without the cache, verifying outbound DNS works.
+	pub async fn health_check(&self, hostname: &str) -> Result {
+		self.resolver
+			.resolver
+			.lookup_ip(hostname)
+			.await
+			.map(|_| ())
+			.map_err(|e| err!("Failed to resolve {hostname:?}: {e}"))
+	}
+}
@@ -3,7 +3,11 @@ use std::{
 	sync::{Arc, Mutex},
 };
 
-use conduwuit::{err, utils, utils::math::usize_from_f64, Err, Result};
+use conduwuit::{
+	err, utils,
+	utils::{hash::sha256, math::usize_from_f64, stream::TryIgnore, ReadyExt},
+	Result,
+};
 use database::Map;
 use lru_cache::LruCache;
 
@@ -11,6 +15,7 @@ use crate::rooms::short::ShortEventId;
 
 pub(super) struct Data {
 	shorteventid_authchain: Arc<Map>,
+	authchainhash_authchain: Arc<Map>,
 	pub(super) auth_chain_cache: Mutex<LruCache<Vec<u64>, Arc<[ShortEventId]>>>,
 }
 
@@ -23,6 +28,7 @@ impl Data {
 			.expect("valid cache size");
 		Self {
 			shorteventid_authchain: db["shorteventid_authchain"].clone(),
+			authchainhash_authchain: db["authchainhash_authchain"].clone(),
 			auth_chain_cache: Mutex::new(LruCache::new(cache_size)),
 		}
 	}
@@ -43,17 +49,14 @@ impl Data {
 			return Ok(Arc::clone(result));
 		}
 
-		// We only save auth chains for single events in the db
-		if key.len() != 1 {
-			return Err!(Request(NotFound("auth_chain not cached")));
+		// Check database; auth chains of several events are stored by the hash
+		// of their short IDs
+		let chain = if key.len() == 1 {
+			self.shorteventid_authchain.qry(&key[0]).await
+		} else {
+			self.authchainhash_authchain.get(&key_hash(key)).await
 		}
-
-		// Check database
-		let chain = self
-			.shorteventid_authchain
-			.qry(&key[0])
-			.await
-			.map_err(|_| err!(Request(NotFound("auth_chain not found"))))?;
+		.map_err(|_| err!(Request(NotFound("auth_chain not found"))))?;
 
 		let chain = chain
 			.chunks_exact(size_of::<u64>())
@@ -64,7 +67,7 @@ impl Data {
 		self.auth_chain_cache
 			.lock()
 			.expect("cache locked")
-			.insert(vec![key[0]], Arc::clone(&chain));
+			.insert(key.to_vec(), Arc::clone(&chain));
 
 		Ok(chain)
 	}
@@ -72,15 +75,16 @@ impl Data {
 	pub(super) fn cache_auth_chain(&self, key: Vec<u64>, auth_chain: Arc<[ShortEventId]>) {
 		debug_assert!(!key.is_empty(), "auth_chain key must not be empty");
 
-		// Only persist single events in db
-		if key.len() == 1 {
-			let key = key[0].to_be_bytes();
-			let val = auth_chain
-				.iter()
-				.flat_map(|s| s.to_be_bytes().to_vec())
-				.collect::<Vec<u8>>();
+		let val = auth_chain
+			.iter()
+			.flat_map(|s| s.to_be_bytes().to_vec())
+			.collect::<Vec<u8>>();
 
-			self.shorteventid_authchain.insert(&key, &val);
+		if key.len() == 1 {
+			self.shorteventid_authchain
+				.insert(&key[0].to_be_bytes(), &val);
+		} else {
+			self.authchainhash_authchain.insert(&key_hash(&key), &val);
 		}
 
 		// Cache in RAM
@@ -89,4 +93,19 @@ impl Data {
 			.expect("cache locked")
 			.insert(key, auth_chain);
 	}
+
+	/// Forgets every stored auth chain, in memory and in the database.
+	pub(super) async fn clear(&self) {
+		self.auth_chain_cache.lock().expect("cache locked").clear();
+		for map in [&self.shorteventid_authchain, &self.authchainhash_authchain] {
+			map.raw_keys()
+				.ignore_err()
+				.ready_for_each(|key| map.remove(key))
+				.await;
+		}
+	}
+}
+
+fn key_hash(key: &[ShortEventId]) -> sha256::Digest {
+	sha256::concat(key.iter().map(|short| short.to_be_bytes()))
 }
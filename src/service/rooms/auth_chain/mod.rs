@@ -9,13 +9,13 @@ use std::{
 use conduwuit::{
 	at, debug, debug_error, implement, trace,
 	utils::{
-		stream::{ReadyExt, TryBroadbandExt},
+		stream::{ReadyExt, TryBroadbandExt, TryIgnore},
 		IterStream,
 	},
 	validated, warn, Err, Result,
 };
 use futures::{Stream, StreamExt, TryFutureExt, TryStreamExt};
-use ruma::{EventId, OwnedEventId, RoomId};
+use ruma::{EventId, OwnedEventId, OwnedRoomId, RoomId};
 
 use self::data::Data;
 use crate::{rooms, rooms::short::ShortEventId, Dep};
@@ -26,6 +26,7 @@ pub struct Service {
 }
 
 struct Services {
+	metadata: Dep<rooms::metadata::Service>,
 	short: Dep<rooms::short::Service>,
 	timeline: Dep<rooms::timeline::Service>,
 }
@@ -34,6 +35,7 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: Services {
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 			},
@@ -185,6 +187,57 @@ where
 	Ok(full_auth_chain)
 }
 
+/// Recomputes and stores the auth chain of every event in the room's
+/// timeline, or in every room's after forgetting all stored auth chains.
+/// Returns the number of events.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn rebuild(&self, room_id: Option<&RoomId>) -> Result<usize> {
+	let rooms: Vec<OwnedRoomId> = match room_id {
+		| Some(room_id) => {
+			self.clear_cache();
+			vec![room_id.to_owned()]
+		},
+		| None => {
+			self.db.clear().await;
+			self.services
+				.metadata
+				.iter_ids()
+				.map(ToOwned::to_owned)
+				.collect()
+				.await
+		},
+	};
+
+	let mut count: usize = 0;
+	for room_id in &rooms {
+		let event_ids: Vec<OwnedEventId> = self
+			.services
+			.timeline
+			.pdus(None, room_id, None)
+			.ignore_err()
+			.map(|(_, pdu)| pdu.event_id)
+			.collect()
+			.await;
+
+		for event_id in &event_ids {
+			let shortid = self
+				.services
+				.short
+				.get_or_create_shorteventid(event_id)
+				.await;
+
+			let auth_chain = self.get_auth_chain_inner(room_id, event_id).await?;
+			self.cache_auth_chain_vec(vec![shortid], auth_chain.as_slice());
+			count = count.saturating_add(1);
+		}
+
+		debug!(%room_id, events = event_ids.len(), "Rebuilt auth chains");
+	}
+
+	Ok(count)
+}
+
 #[implement(Service)]
 #[tracing::instrument(name = "inner", level = "trace", skip(self, room_id))]
 async fn get_auth_chain_inner(
@@ -198,7 +251,7 @@ async fn get_auth_chain_inner(
 	while let Some(event_id) = todo.pop_front() {
 		trace!(?event_id, "processing auth event");
 
-		match self.services.timeline.get_pdu(&event_id).await {
+		match self.services.timeline.get_shared_pdu(&event_id).await {
 			| Err(e) => {
 				debug_error!(?event_id, ?e, "Could not find pdu mentioned in auth events");
 			},
@@ -0,0 +1,224 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, info,
+	utils::{self, ReadyExt},
+	warn, Result, Server,
+};
+use database::{Deserialized, Map};
+use futures::StreamExt;
+use ruma::{OwnedRoomId, RoomId};
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+
+use crate::{admin, globals, rooms, Dep};
+
+/// Purges rooms which every local user has left and forgotten.
+pub struct Service {
+	interrupt: Notify,
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	roomid_emptysince: Arc<Map>,
+}
+
+struct Services {
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	purge: Dep<rooms::purge::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	server: Arc<Server>,
+}
+
+/// A room no local user is in or remembers.
+#[derive(Debug)]
+pub struct AbandonedRoom {
+	pub room_id: OwnedRoomId,
+
+	/// When the room was first seen abandoned, in milliseconds since the unix
+	/// epoch.
+	pub empty_since: u64,
+
+	/// Whether the grace period has passed, so the room is (or would be)
+	/// purged.
+	pub expired: bool,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			db: Data {
+				roomid_emptysince: args.db["roomid_emptysince"].clone(),
+			},
+			services: Services {
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				purge: args.depend::<rooms::purge::Service>("rooms::purge"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				server: args.server.clone(),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "cleanup", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let config = &self.services.server.config;
+		if !config.empty_room_cleanup || self.services.globals.is_read_only() {
+			debug!("Empty room cleanup is disabled");
+			return Ok(());
+		}
+
+		let period = Duration::from_secs(config.empty_room_cleanup_interval.max(60));
+		let mut i = interval(period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.reset_after(period);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			if let Err(e) = self.cleanup(false).await {
+				warn!("Failed to clean up empty rooms: {e}");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Finds abandoned rooms, recording when each was first seen that way,
+	/// and purges those abandoned for longer than "empty_room_grace_period".
+	/// With `dry_run` nothing is recorded or purged. Returns the abandoned
+	/// rooms.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn cleanup(&self, dry_run: bool) -> Result<Vec<AbandonedRoom>> {
+		let grace = self
+			.services
+			.server
+			.config
+			.empty_room_grace_period
+			.saturating_mul(1000);
+
+		let admin_room = self.services.admin.get_admin_room().await.ok();
+		let rooms: Vec<OwnedRoomId> = self
+			.services
+			.metadata
+			.iter_ids()
+			.ready_filter(|room_id| admin_room.as_deref() != Some(*room_id))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let mut abandoned = Vec::new();
+		for room_id in rooms {
+			if !self.services.server.running() {
+				break;
+			}
+
+			if !self.is_abandoned(&room_id).await {
+				if !dry_run {
+					self.db.roomid_emptysince.remove(&room_id);
+				}
+
+				continue;
+			}
+
+			let now = utils::millis_since_unix_epoch();
+			let empty_since = match self.empty_since(&room_id).await {
+				| Some(empty_since) => empty_since,
+				| None => {
+					if !dry_run {
+						self.db.roomid_emptysince.raw_put(&room_id, now);
+					}
+
+					now
+				},
+			};
+
+			let expired = now.saturating_sub(empty_since) >= grace;
+			if expired && !dry_run {
+				self.purge_room(&room_id).await?;
+			}
+
+			abandoned.push(AbandonedRoom { room_id, empty_since, expired });
+		}
+
+		Ok(abandoned)
+	}
+
+	/// Whether no local user is joined, invited or knocking, and every local
+	/// user who was ever joined has forgotten the room.
+	pub async fn is_abandoned(&self, room_id: &RoomId) -> bool {
+		let state_cache = &self.services.state_cache;
+		let globals = &self.services.globals;
+
+		if state_cache
+			.room_members(room_id)
+			.ready_any(|user_id| globals.user_is_local(user_id))
+			.await
+		{
+			return false;
+		}
+
+		if state_cache
+			.room_members_invited(room_id)
+			.ready_any(|user_id| globals.user_is_local(user_id))
+			.await
+		{
+			return false;
+		}
+
+		if state_cache
+			.room_members_knocked(room_id)
+			.ready_any(|user_id| globals.user_is_local(user_id))
+			.await
+		{
+			return false;
+		}
+
+		!state_cache
+			.room_useroncejoined(room_id)
+			.ready_filter(|user_id| globals.user_is_local(user_id))
+			.any(|user_id| state_cache.is_left(user_id, room_id))
+			.await
+	}
+
+	/// When the room was first seen abandoned, if it still is.
+	pub async fn empty_since(&self, room_id: &RoomId) -> Option<u64> {
+		self.db
+			.roomid_emptysince
+			.get(room_id)
+			.await
+			.deserialized()
+			.ok()
+	}
+
+	async fn purge_room(&self, room_id: &RoomId) -> Result {
+		let purge = &self.services.purge;
+		let aliases = purge.purge_aliases(room_id).await;
+		let media = purge.purge_remote_media(room_id).await;
+		let state = purge.purge_state(room_id).await?;
+		let members = purge.purge_members(room_id).await;
+		let events = purge.purge_events(room_id).await?;
+		self.db.roomid_emptysince.remove(room_id);
+
+		info!(%room_id, aliases, media, state, members, events, "Purged abandoned room");
+
+		Ok(())
+	}
+}
@@ -1,35 +1,91 @@
-use std::sync::Arc;
+use std::{
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
 
-use conduwuit::{implement, utils::stream::TryIgnore, Result};
-use database::Map;
-use futures::Stream;
-use ruma::{api::client::room::Visibility, RoomId};
+use conduwuit::{implement, utils, utils::stream::TryIgnore, Result, Server};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use lru_cache::LruCache;
+use ruma::{
+	api::client::{directory::get_public_rooms_filtered, room::Visibility},
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UInt, UserId,
+};
+use serde::{Deserialize, Serialize};
 
 pub struct Service {
 	db: Data,
+	server: Arc<Server>,
+	remote_pages: Mutex<LruCache<RemotePage, CachedPage>>,
 }
 
 struct Data {
 	publicroomids: Arc<Map>,
+	roomid_publishrequest: Arc<Map>,
 }
 
+/// A request by a non-admin to publish a room to the room directory, awaiting
+/// review by an admin; see `room_directory_review`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublishRequest {
+	pub user_id: OwnedUserId,
+	pub requested_at: u64,
+}
+
+/// A page of another server's room directory, as requested from it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RemotePage {
+	pub server: OwnedServerName,
+	pub limit: Option<UInt>,
+	pub since: Option<String>,
+
+	/// The request's filter, serialized
+	pub filter: String,
+}
+
+struct CachedPage {
+	response: get_public_rooms_filtered::v3::Response,
+	expires_at: Instant,
+}
+
+const REMOTE_PAGES_CAPACITY: usize = 256;
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
 				publicroomids: args.db["publicroomids"].clone(),
+				roomid_publishrequest: args.db["roomid_publishrequest"].clone(),
 			},
+			server: args.server.clone(),
+			remote_pages: Mutex::new(LruCache::new(REMOTE_PAGES_CAPACITY)),
 		}))
 	}
 
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let remote_pages = self.remote_pages.lock()?;
+		writeln!(out, "remote_pages: {} / {}", remote_pages.len(), remote_pages.capacity())?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.remote_pages.lock().expect("locked").clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 #[implement(Service)]
-pub fn set_public(&self, room_id: &RoomId) { self.db.publicroomids.insert(room_id, []); }
+pub fn set_public(&self, room_id: &RoomId) {
+	self.db.roomid_publishrequest.remove(room_id);
+	self.db.publicroomids.insert(room_id, []);
+}
 
 #[implement(Service)]
-pub fn set_not_public(&self, room_id: &RoomId) { self.db.publicroomids.remove(room_id); }
+pub fn set_not_public(&self, room_id: &RoomId) {
+	self.db.roomid_publishrequest.remove(room_id);
+	self.db.publicroomids.remove(room_id);
+}
 
 #[implement(Service)]
 pub fn public_rooms(&self) -> impl Stream<Item = &RoomId> + Send {
@@ -49,3 +105,91 @@ pub async fn visibility(&self, room_id: &RoomId) -> Visibility {
 		Visibility::Private
 	}
 }
+
+/// Queues a room for review before it is published to the room directory,
+/// replacing any earlier request for it.
+#[implement(Service)]
+pub fn request_publish(&self, room_id: &RoomId, user_id: &UserId) {
+	let request = PublishRequest {
+		user_id: user_id.to_owned(),
+		requested_at: utils::millis_since_unix_epoch(),
+	};
+
+	self.db.roomid_publishrequest.put(room_id, Json(request));
+}
+
+/// Requests to publish rooms awaiting review.
+#[implement(Service)]
+pub fn publish_requests(&self) -> impl Stream<Item = (OwnedRoomId, PublishRequest)> + Send + '_ {
+	self.db
+		.roomid_publishrequest
+		.stream()
+		.ignore_err()
+		.map(|(room_id, request): (&RoomId, PublishRequest)| (room_id.to_owned(), request))
+}
+
+#[implement(Service)]
+pub async fn publish_request(&self, room_id: &RoomId) -> Result<PublishRequest> {
+	self.db
+		.roomid_publishrequest
+		.get(room_id)
+		.await
+		.deserialized()
+}
+
+/// Drops the request to publish a room without publishing it.
+#[implement(Service)]
+pub fn reject_publish(&self, room_id: &RoomId) { self.db.roomid_publishrequest.remove(room_id); }
+
+/// Whether our users may browse the room directory of another server, per
+/// `forbidden_remote_room_directory_server_names` and
+/// `allowed_remote_room_directory_server_names`.
+#[implement(Service)]
+pub fn remote_directory_allowed(&self, server: &ServerName) -> bool {
+	let config = &self.server.config;
+	if config
+		.forbidden_remote_room_directory_server_names
+		.contains(server)
+	{
+		return false;
+	}
+
+	config.allowed_remote_room_directory_server_names.is_empty()
+		|| config
+			.allowed_remote_room_directory_server_names
+			.contains(server)
+}
+
+/// A page of another server's room directory fetched within
+/// `remote_room_directory_cache_ttl`.
+#[implement(Service)]
+pub fn cached_remote_page(
+	&self,
+	page: &RemotePage,
+) -> Option<get_public_rooms_filtered::v3::Response> {
+	self.remote_pages
+		.lock()
+		.expect("locked")
+		.get_mut(page)
+		.filter(|cached| cached.expires_at > Instant::now())
+		.map(|cached| cached.response.clone())
+}
+
+#[implement(Service)]
+pub fn cache_remote_page(
+	&self,
+	page: RemotePage,
+	response: &get_public_rooms_filtered::v3::Response,
+) {
+	let ttl = Duration::from_secs(self.server.config.remote_room_directory_cache_ttl);
+	if ttl.is_zero() {
+		return;
+	}
+
+	if let Some(expires_at) = Instant::now().checked_add(ttl) {
+		self.remote_pages
+			.lock()
+			.expect("locked")
+			.insert(page, CachedPage { response: response.clone(), expires_at });
+	}
+}
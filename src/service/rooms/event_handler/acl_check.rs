@@ -1,31 +1,64 @@
+use std::sync::Arc;
+
 use conduwuit::{debug, implement, trace, warn, Err, Result};
+use regex::RegexSet;
 use ruma::{
 	events::{room::server_acl::RoomServerAclEventContent, StateEventType},
-	RoomId, ServerName,
+	OwnedEventId, RoomId, ServerName,
 };
 
-/// Returns Ok if the acl allows the server
+/// A room's `m.room.server_acl` compiled for matching, along with the ACL
+/// event it was compiled from.
+pub(super) struct AclMatcher {
+	event_id: OwnedEventId,
+	rules: AclRules,
+}
+
+enum AclRules {
+	Compiled {
+		allow_ip_literals: bool,
+		allow: RegexSet,
+		deny: RegexSet,
+	},
+
+	/// An ACL which can't be compiled, e.g. as it is too large, is matched
+	/// glob by glob instead.
+	Content(RoomServerAclEventContent),
+}
+
+/// Returns Ok if the acl allows the server. Other servers are never allowed in
+/// rooms whose federation was cut off by an admin.
 #[implement(super::Service)]
 #[tracing::instrument(skip_all, level = "debug")]
 pub async fn acl_check(&self, server_name: &ServerName, room_id: &RoomId) -> Result {
-	let Ok(acl_event_content) = self
+	if !self.services.globals.server_is_ours(server_name)
+		&& self.services.metadata.is_unfederated(room_id).await
+	{
+		debug!("Server {server_name} was denied as {room_id} does not federate");
+		return Err!(Request(Forbidden("Room does not federate")));
+	}
+
+	let Ok(acl_event) = self
 		.services
 		.state_accessor
-		.room_state_get_content(room_id, &StateEventType::RoomServerAcl, "")
+		.room_state_get(room_id, &StateEventType::RoomServerAcl, "")
 		.await
-		.map(|c: RoomServerAclEventContent| c)
-		.inspect(|acl| trace!("ACL content found: {acl:?}"))
-		.inspect_err(|e| trace!("No ACL content found: {e:?}"))
 	else {
+		trace!("No ACL event found in {room_id}");
 		return Ok(());
 	};
 
-	if acl_event_content.allow.is_empty() {
-		warn!("Ignoring broken ACL event (allow key is empty)");
+	let Some(matcher) = self.acl_matcher(room_id, &acl_event.event_id, || {
+		acl_event
+			.get_content::<RoomServerAclEventContent>()
+			.inspect(|acl| trace!("ACL content found: {acl:?}"))
+			.inspect_err(|e| trace!("Invalid ACL content: {e:?}"))
+			.ok()
+	}) else {
 		return Ok(());
-	}
+	};
 
-	if acl_event_content.is_allowed(server_name) {
+	if matcher.is_allowed(server_name) {
 		trace!("server {server_name} is allowed by ACL");
 		Ok(())
 	} else {
@@ -33,3 +66,94 @@ pub async fn acl_check(&self, server_name: &ServerName, room_id: &RoomId) -> Res
 		Err!(Request(Forbidden("Server was denied by room ACL")))
 	}
 }
+
+/// Returns the compiled ACL for the room, compiling and caching it when the
+/// room's ACL event has changed. None when the ACL is to be ignored.
+#[implement(super::Service)]
+fn acl_matcher<F>(
+	&self,
+	room_id: &RoomId,
+	event_id: &OwnedEventId,
+	content: F,
+) -> Option<Arc<AclMatcher>>
+where
+	F: FnOnce() -> Option<RoomServerAclEventContent>,
+{
+	if let Some(matcher) = self
+		.acl_cache
+		.lock()
+		.expect("locked")
+		.get_mut(room_id)
+		.filter(|matcher| matcher.event_id == *event_id)
+	{
+		return Some(matcher.clone());
+	}
+
+	let content = content()?;
+	if content.allow.is_empty() {
+		warn!("Ignoring broken ACL event (allow key is empty)");
+		return None;
+	}
+
+	let matcher = Arc::new(AclMatcher::new(event_id.clone(), content));
+
+	self.acl_cache
+		.lock()
+		.expect("locked")
+		.insert(room_id.to_owned(), matcher.clone());
+
+	Some(matcher)
+}
+
+impl AclMatcher {
+	pub(super) fn new(event_id: OwnedEventId, content: RoomServerAclEventContent) -> Self {
+		let rules = AclRules::compile(&content).unwrap_or_else(|e| {
+			warn!("Matching ACL event {event_id} without compiling it: {e}");
+			AclRules::Content(content)
+		});
+
+		Self { event_id, rules }
+	}
+
+	/// Same result as [`RoomServerAclEventContent::is_allowed`].
+	pub(super) fn is_allowed(&self, server_name: &ServerName) -> bool {
+		match &self.rules {
+			| AclRules::Compiled { allow_ip_literals, allow, deny } => {
+				if !allow_ip_literals && server_name.is_ip_literal() {
+					return false;
+				}
+
+				let host = server_name.host();
+
+				!deny.is_match(host) && allow.is_match(host)
+			},
+			| AclRules::Content(content) => content.is_allowed(server_name),
+		}
+	}
+}
+
+impl AclRules {
+	fn compile(content: &RoomServerAclEventContent) -> Result<Self> {
+		Ok(Self::Compiled {
+			allow_ip_literals: content.allow_ip_literals,
+			allow: RegexSet::new(content.allow.iter().map(String::as_str).map(glob_to_regex))?,
+			deny: RegexSet::new(content.deny.iter().map(String::as_str).map(glob_to_regex))?,
+		})
+	}
+}
+
+/// Translates an ACL glob, where `*` matches any run of characters and `?`
+/// matches a single character, into an anchored regular expression.
+fn glob_to_regex(glob: &str) -> String {
+	let mut re = String::with_capacity(glob.len().saturating_add(2));
+	re.push('^');
+	for c in glob.chars() {
+		match c {
+			| '*' => re.push_str(".*"),
+			| '?' => re.push('.'),
+			| c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+		}
+	}
+	re.push('$');
+	re
+}
@@ -8,7 +8,6 @@ use conduwuit::{
 	debug, debug_error, debug_warn, implement, pdu, trace,
 	utils::continue_exponential_backoff_secs, warn, PduEvent,
 };
-use futures::TryFutureExt;
 use ruma::{
 	api::federation::event::get_event, CanonicalJsonValue, OwnedEventId, RoomId, ServerName,
 };
@@ -53,7 +52,7 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 		// a. Look in the main timeline (pduid_pdu tree)
 		// b. Look at outlier pdu tree
 		// (get_pdu_json checks both)
-		if let Ok(local_pdu) = self.services.timeline.get_pdu(id).map_ok(Arc::new).await {
+		if let Ok(local_pdu) = self.services.timeline.get_shared_pdu(id).await {
 			trace!("Found {id} in db");
 			events_with_auth_events.push((id, Some(local_pdu), vec![]));
 			continue;
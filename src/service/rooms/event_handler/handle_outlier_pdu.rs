@@ -4,7 +4,7 @@ use std::{
 };
 
 use conduwuit::{debug, debug_info, err, implement, trace, warn, Err, Error, PduEvent, Result};
-use futures::{future::ready, TryFutureExt};
+use futures::future::ready;
 use ruma::{
 	api::client::error::ErrorKind,
 	events::StateEventType,
@@ -94,7 +94,7 @@ pub(super) async fn handle_outlier_pdu<'a>(
 	// Build map of auth events
 	let mut auth_events = HashMap::with_capacity(incoming_pdu.auth_events.len());
 	for id in &incoming_pdu.auth_events {
-		let Ok(auth_event) = self.services.timeline.get_pdu(id).map_ok(Arc::new).await else {
+		let Ok(auth_event) = self.services.timeline.get_shared_pdu(id).await else {
 			warn!("Could not find auth event {id}");
 			continue;
 		};
@@ -138,10 +138,14 @@ pub(super) async fn handle_outlier_pdu<'a>(
 		ready(auth_events.get(&key))
 	};
 
+	let third_party_invite = incoming_pdu.third_party_invite_token().and_then(|token| {
+		auth_events.get(&StateEventType::RoomThirdPartyInvite.with_state_key(token.as_str()))
+	});
+
 	let auth_check = state_res::event_auth::auth_check(
 		&to_room_version(&room_version_id),
 		&incoming_pdu,
-		None, // TODO: third party invite
+		third_party_invite,
 		state_fetch,
 	)
 	.await
@@ -8,36 +8,42 @@ mod handle_prev_pdu;
 mod parse_incoming_pdu;
 mod resolve_state;
 mod state_at_incoming;
+mod tests;
 mod upgrade_outlier_pdu;
 
 use std::{
 	collections::HashMap,
 	fmt::Write,
-	sync::{Arc, RwLock as StdRwLock},
+	sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
 	time::Instant,
 };
 
 use conduwuit::{
-	utils::{MutexMap, TryFutureExtExt},
+	err,
+	utils::{sys::available_parallelism, MutexMap, TryFutureExtExt},
 	Err, PduEvent, Result, Server,
 };
-use futures::TryFutureExt;
+use lru_cache::LruCache;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use ruma::{
 	events::room::create::RoomCreateEventContent, state_res::RoomVersion, OwnedEventId,
 	OwnedRoomId, RoomId, RoomVersionId,
 };
 
-use crate::{globals, rooms, sending, server_keys, Dep};
+use crate::{globals, rooms, sending, server_keys, spam_checker, Dep};
 
 pub struct Service {
 	pub mutex_federation: RoomMutexMap,
 	pub federation_handletime: StdRwLock<HandleTimeMap>,
+	acl_cache: StdMutex<AclCache>,
+	resolver: ThreadPool,
 	services: Services,
 }
 
 struct Services {
 	globals: Dep<globals::Service>,
 	sending: Dep<sending::Service>,
+	spam_checker: Dep<spam_checker::Service>,
 	auth_chain: Dep<rooms::auth_chain::Service>,
 	metadata: Dep<rooms::metadata::Service>,
 	outlier: Dep<rooms::outlier::Service>,
@@ -53,15 +59,27 @@ struct Services {
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
 type HandleTimeMap = HashMap<OwnedRoomId, (OwnedEventId, Instant)>;
+type AclCache = LruCache<OwnedRoomId, Arc<acl_check::AclMatcher>>;
+
+const ACL_CACHE_CAPACITY: usize = 4096;
+
+const RESOLVER_NAME: &str = "conduwuit:resolver";
 
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			mutex_federation: RoomMutexMap::new(),
 			federation_handletime: HandleTimeMap::new().into(),
+			acl_cache: StdMutex::new(AclCache::new(ACL_CACHE_CAPACITY)),
+			resolver: ThreadPoolBuilder::new()
+				.num_threads(available_parallelism())
+				.thread_name(|_| RESOLVER_NAME.into())
+				.build()
+				.map_err(|e| err!("Failed to start the state resolution pool: {e}"))?,
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
+				spam_checker: args.depend::<spam_checker::Service>("spam_checker"),
 				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
 				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				outlier: args.depend::<rooms::outlier::Service>("rooms::outlier"),
@@ -90,9 +108,14 @@ impl crate::Service for Service {
 			.len();
 		writeln!(out, "federation_handletime: {federation_handletime}")?;
 
+		let acl_cache = self.acl_cache.lock()?;
+		writeln!(out, "acl_cache: {} / {}", acl_cache.len(), acl_cache.capacity())?;
+
 		Ok(())
 	}
 
+	fn clear_cache(&self) { self.acl_cache.lock().expect("locked").clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -102,12 +125,7 @@ impl Service {
 	}
 
 	async fn event_fetch(&self, event_id: OwnedEventId) -> Option<Arc<PduEvent>> {
-		self.services
-			.timeline
-			.get_pdu(&event_id)
-			.map_ok(Arc::new)
-			.ok()
-			.await
+		self.services.timeline.get_shared_pdu(&event_id).ok().await
 	}
 }
 
@@ -7,16 +7,22 @@ use std::{
 use conduwuit::{
 	debug, err, implement,
 	utils::stream::{automatic_width, IterStream, ReadyExt, TryWidebandExt, WidebandExt},
-	Result,
+	PduEvent, Result,
 };
 use futures::{FutureExt, StreamExt, TryStreamExt};
+use rayon::prelude::*;
 use ruma::{
 	state_res::{self, StateMap},
 	OwnedEventId, RoomId, RoomVersionId,
 };
+use tokio::{runtime::Handle, task};
 
 use crate::rooms::state_compressor::CompressedStateEvent;
 
+/// The most conflicted events loaded ahead of state resolution, bounding the
+/// memory a huge state reset takes.
+const MAX_PREFETCH_EVENTS: usize = 32_768;
+
 #[implement(super::Service)]
 #[tracing::instrument(name = "resolve", level = "debug", skip_all)]
 pub async fn resolve_state(
@@ -122,14 +128,103 @@ pub async fn state_resolution(
 	state_sets: &[StateMap<OwnedEventId>],
 	auth_chain_sets: &[HashSet<OwnedEventId>],
 ) -> Result<StateMap<OwnedEventId>> {
-	state_res::resolve(
-		room_version,
-		state_sets.iter(),
-		auth_chain_sets,
-		&|event_id| self.event_fetch(event_id),
-		&|event_id| self.event_exists(event_id),
-		automatic_width(),
-	)
-	.await
+	let conflicted =
+		self.on_resolver(|| conflicted_events(state_sets, auth_chain_sets, MAX_PREFETCH_EVENTS));
+	let events = self.prefetch_conflicted(conflicted).await;
+	debug!(prefetched = events.len(), "Prefetched conflicted events");
+
+	let event_fetch = |event_id: OwnedEventId| {
+		let event = events.get(&event_id).cloned();
+		async move {
+			match event {
+				| Some(event) => Some(event),
+				| None => self.event_fetch(event_id).await,
+			}
+		}
+	};
+
+	let event_exists = |event_id: OwnedEventId| {
+		let prefetched = events.contains_key(&event_id);
+		async move { prefetched || self.event_exists(event_id).await }
+	};
+
+	// Sorting and auth checking the conflicted events is done by ruma in one go,
+	// so it is run on a thread of the pool, fetching events through the runtime.
+	let handle = Handle::current();
+	self.on_resolver(|| {
+		handle.block_on(state_res::resolve(
+			room_version,
+			state_sets.iter(),
+			auth_chain_sets,
+			&event_fetch,
+			&event_exists,
+			automatic_width(),
+		))
+	})
 	.map_err(|e| err!(error!("State resolution failed: {e:?}")))
 }
+
+/// Runs CPU-bound state resolution work on the resolver pool, so big state
+/// resets take turns on its threads instead of stalling the runtime's workers.
+#[implement(super::Service)]
+fn on_resolver<F, R>(&self, f: F) -> R
+where
+	F: FnOnce() -> R + Send,
+	R: Send,
+{
+	task::block_in_place(|| self.resolver.install(f))
+}
+
+/// Loads the events state resolution spends its time on, which are found by
+/// [`conflicted_events`]. Resolution sorts and auth checks these one at a
+/// time, fetching each of them repeatedly, so they're loaded concurrently up
+/// front and each deserialized only once.
+#[implement(super::Service)]
+async fn prefetch_conflicted(
+	&self,
+	event_ids: Vec<&OwnedEventId>,
+) -> HashMap<OwnedEventId, Arc<PduEvent>> {
+	event_ids
+		.into_iter()
+		.stream()
+		.wide_filter_map(|event_id| {
+			self.event_fetch(event_id.clone())
+				.map(move |event| Some((event_id.clone(), event?)))
+		})
+		.collect()
+		.await
+}
+
+/// The events the forks disagree about, followed by the difference of their
+/// auth chains, up to `limit` of them; the rest are fetched as resolution
+/// needs them. Both are computed in parallel chunks on the current rayon pool.
+pub(super) fn conflicted_events<'a>(
+	state_sets: &'a [StateMap<OwnedEventId>],
+	auth_chain_sets: &'a [HashSet<OwnedEventId>],
+	limit: usize,
+) -> Vec<&'a OwnedEventId> {
+	let keys: HashSet<_> = state_sets.iter().flat_map(StateMap::keys).collect();
+	let conflicted: HashSet<&OwnedEventId> = keys
+		.par_iter()
+		.flat_map_iter(|&key| {
+			let event_ids: HashSet<_> = state_sets.iter().map(|state| state.get(key)).collect();
+			let conflicted = event_ids.len() > 1;
+			event_ids.into_iter().flatten().filter(move |_| conflicted)
+		})
+		.collect();
+
+	let auth_difference: HashSet<&OwnedEventId> = auth_chain_sets
+		.par_iter()
+		.flat_map(|set| {
+			set.par_iter()
+				.filter(|&event_id| !auth_chain_sets.iter().all(|other| other.contains(event_id)))
+		})
+		.filter(|event_id| !conflicted.contains(event_id))
+		.collect();
+
+	conflicted
+		.into_iter()
+		.chain(auth_difference)
+		.take(limit)
+		.collect()
+}
@@ -7,12 +7,14 @@ use std::{
 use conduwuit::{
 	debug, err, implement,
 	result::LogErr,
-	utils::stream::{BroadbandExt, IterStream},
+	utils::stream::{BroadbandExt, IterStream, ReadyExt, TryWidebandExt},
 	PduEvent, Result,
 };
-use futures::{FutureExt, StreamExt};
+use futures::{FutureExt, StreamExt, TryStreamExt};
 use ruma::{state_res::StateMap, OwnedEventId, RoomId, RoomVersionId};
 
+use crate::rooms::short::ShortStateHash;
+
 // TODO: if we know the prev_events of the incoming event we can avoid the
 #[implement(super::Service)]
 // request and build the state from a known point and resolve if > 1 prev_event
@@ -97,57 +99,16 @@ pub(super) async fn state_at_incoming_resolved(
 		return Ok(None);
 	}
 
-	let mut fork_states = Vec::with_capacity(extremity_sstatehashes.len());
-	let mut auth_chain_sets = Vec::with_capacity(extremity_sstatehashes.len());
-	for (sstatehash, prev_event) in extremity_sstatehashes {
-		let mut leaf_state: HashMap<_, _> = self
-			.services
-			.state_accessor
-			.state_full_ids(sstatehash)
-			.collect()
-			.await;
-
-		if let Some(state_key) = &prev_event.state_key {
-			let shortstatekey = self
-				.services
-				.short
-				.get_or_create_shortstatekey(&prev_event.kind.to_string().into(), state_key)
-				.await;
-
-			let event_id = &prev_event.event_id;
-			leaf_state.insert(shortstatekey, event_id.clone());
-			// Now it's the state after the pdu
-		}
-
-		let mut state = StateMap::with_capacity(leaf_state.len());
-		let mut starting_events = Vec::with_capacity(leaf_state.len());
-		for (k, id) in &leaf_state {
-			if let Ok((ty, st_key)) = self
-				.services
-				.short
-				.get_statekey_from_short(*k)
-				.await
-				.log_err()
-			{
-				// FIXME: Undo .to_string().into() when StateMap
-				//        is updated to use StateEventType
-				state.insert((ty.to_string().into(), st_key), id.clone());
-			}
-
-			starting_events.push(id.borrow());
-		}
-
-		let auth_chain: HashSet<OwnedEventId> = self
-			.services
-			.auth_chain
-			.get_event_ids(room_id, starting_events.into_iter())
-			.await?
-			.into_iter()
-			.collect();
-
-		auth_chain_sets.push(auth_chain);
-		fork_states.push(state);
-	}
+	let (fork_states, auth_chain_sets): (Vec<_>, Vec<_>) = extremity_sstatehashes
+		.into_iter()
+		.try_stream()
+		.wide_and_then(|(sstatehash, prev_event)| {
+			self.fork_state(room_id, sstatehash, prev_event)
+		})
+		.try_collect::<Vec<_>>()
+		.await?
+		.into_iter()
+		.unzip();
 
 	let Ok(new_state) = self
 		.state_resolution(room_version_id, &fork_states, &auth_chain_sets)
@@ -171,3 +132,54 @@ pub(super) async fn state_at_incoming_resolved(
 		.map(Ok)
 		.await
 }
+
+/// The state after one of the incoming event's prev_events, with the auth
+/// chain of that state.
+#[implement(super::Service)]
+async fn fork_state(
+	&self,
+	room_id: &RoomId,
+	sstatehash: ShortStateHash,
+	prev_event: PduEvent,
+) -> Result<(StateMap<OwnedEventId>, HashSet<OwnedEventId>)> {
+	let mut leaf_state: HashMap<_, _> = self
+		.services
+		.state_accessor
+		.state_full_ids(sstatehash)
+		.collect()
+		.await;
+
+	if let Some(state_key) = &prev_event.state_key {
+		let shortstatekey = self
+			.services
+			.short
+			.get_or_create_shortstatekey(&prev_event.kind.to_string().into(), state_key)
+			.await;
+
+		let event_id = &prev_event.event_id;
+		leaf_state.insert(shortstatekey, event_id.clone());
+		// Now it's the state after the pdu
+	}
+
+	let starting_events = leaf_state.values().map(Borrow::borrow);
+	let auth_chain: HashSet<OwnedEventId> = self
+		.services
+		.auth_chain
+		.get_event_ids(room_id, starting_events)
+		.await?
+		.into_iter()
+		.collect();
+
+	let shortstatekeys = leaf_state.keys().copied().stream();
+	let event_ids = leaf_state.values().cloned().stream();
+	let state = self
+		.services
+		.short
+		.multi_get_statekey_from_short(shortstatekeys)
+		.zip(event_ids)
+		.ready_filter_map(|(ty_sk, id)| Some((ty_sk.log_err().ok()?, id)))
+		.collect()
+		.await;
+
+	Ok((state, auth_chain))
+}
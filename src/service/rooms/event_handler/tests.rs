@@ -0,0 +1,141 @@
+#![cfg(test)]
+
+use std::collections::HashSet;
+
+use ruma::{
+	events::{room::server_acl::RoomServerAclEventContent, StateEventType},
+	owned_event_id, server_name,
+	state_res::StateMap,
+	OwnedEventId, ServerName,
+};
+
+use super::{acl_check::AclMatcher, resolve_state::conflicted_events};
+
+fn acl(allow_ip_literals: bool, allow: &[&str], deny: &[&str]) -> RoomServerAclEventContent {
+	RoomServerAclEventContent::new(
+		allow_ip_literals,
+		allow.iter().map(ToString::to_string).collect(),
+		deny.iter().map(ToString::to_string).collect(),
+	)
+}
+
+fn assert_matches_ruma(content: &RoomServerAclEventContent) {
+	let matcher = AclMatcher::new(owned_event_id!("$acl:example.org"), content.clone());
+
+	for server in servers() {
+		assert_eq!(
+			matcher.is_allowed(server),
+			content.is_allowed(server),
+			"{server} with {content:?}"
+		);
+	}
+}
+
+fn servers() -> [&'static ServerName; 9] {
+	[
+		server_name!("example.org"),
+		server_name!("matrix.example.org"),
+		server_name!("evil.example.org"),
+		server_name!("evil.example.org:8448"),
+		server_name!("example.com"),
+		server_name!("exampleXorg"),
+		server_name!("1.2.3.4"),
+		server_name!("1.2.3.4:8448"),
+		server_name!("[::1]"),
+	]
+}
+
+#[test]
+fn acl_allow_all() { assert_matches_ruma(&acl(true, &["*"], &[])); }
+
+#[test]
+fn acl_deny_subdomain() {
+	assert_matches_ruma(&acl(true, &["*"], &["evil.example.org"]));
+	assert_matches_ruma(&acl(false, &["*"], &["*.example.org"]));
+}
+
+#[test]
+fn acl_literal_dots() {
+	// `.` is not a wildcard, so example.org must not match exampleXorg
+	assert_matches_ruma(&acl(true, &["example.org"], &[]));
+}
+
+#[test]
+fn acl_single_char_wildcard() {
+	assert_matches_ruma(&acl(true, &["example.co?", "?.2.3.4"], &[]));
+}
+
+#[test]
+fn acl_ip_literals() {
+	assert_matches_ruma(&acl(false, &["*"], &[]));
+	assert_matches_ruma(&acl(true, &["1.2.3.*"], &[]));
+}
+
+#[test]
+fn acl_regex_metacharacters() {
+	assert_matches_ruma(&acl(true, &["example.(org|com)", "[::1]", "*"], &["+*"]));
+}
+
+#[test]
+fn acl_too_large_to_compile() {
+	let glob = "?".repeat(1 << 20);
+	let content = acl(false, &[glob.as_str(), "*.example.org"], &["evil.example.org"]);
+	assert_matches_ruma(&content);
+
+	let matcher = AclMatcher::new(owned_event_id!("$acl:example.org"), content);
+	assert!(matcher.is_allowed(server_name!("matrix.example.org")));
+	assert!(!matcher.is_allowed(server_name!("evil.example.org")));
+	assert!(!matcher.is_allowed(server_name!("example.com")));
+}
+
+fn fork(events: &[(&str, &str)]) -> StateMap<OwnedEventId> {
+	events
+		.iter()
+		.map(|&(state_key, event_id)| {
+			let key = (StateEventType::RoomMember, state_key.to_owned());
+			(key, event_id.try_into().unwrap())
+		})
+		.collect()
+}
+
+fn auth_chain(events: &[&str]) -> HashSet<OwnedEventId> {
+	events
+		.iter()
+		.map(|&event_id| event_id.try_into().unwrap())
+		.collect()
+}
+
+#[test]
+fn conflicted_events_before_auth_difference() {
+	let state_sets = [
+		fork(&[("@a:x", "$same"), ("@b:x", "$b1"), ("@c:x", "$c")]),
+		fork(&[("@a:x", "$same"), ("@b:x", "$b2")]),
+	];
+	let auth_chain_sets = [auth_chain(&["$create", "$b1", "$auth1"]), auth_chain(&["$create"])];
+
+	let events = conflicted_events(&state_sets, &auth_chain_sets, usize::MAX);
+	let (conflicted, auth_difference) = events.split_at(3);
+
+	let conflicted: HashSet<_> = conflicted
+		.iter()
+		.map(|event_id| event_id.as_str())
+		.collect();
+	assert_eq!(conflicted, HashSet::from(["$b1", "$b2", "$c"]));
+	assert_eq!(auth_difference.len(), 1);
+	assert_eq!(auth_difference[0].as_str(), "$auth1");
+}
+
+#[test]
+fn conflicted_events_limit() {
+	let state_sets = [fork(&[("@a:x", "$a1")]), fork(&[("@a:x", "$a2")])];
+	let auth_chain_sets = [auth_chain(&["$auth1", "$auth2"]), auth_chain(&[])];
+
+	let events = conflicted_events(&state_sets, &auth_chain_sets, 3);
+	assert_eq!(events.len(), 3);
+
+	let events: HashSet<_> = events[..2]
+		.iter()
+		.map(|event_id| event_id.as_str())
+		.collect();
+	assert_eq!(events, HashSet::from(["$a1", "$a2"]));
+}
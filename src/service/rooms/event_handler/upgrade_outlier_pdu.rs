@@ -6,7 +6,10 @@ use std::{
 };
 
 use conduwuit::{debug, debug_info, err, implement, trace, warn, Err, Error, PduEvent, Result};
-use futures::{future::ready, StreamExt};
+use futures::{
+	future::{ready, OptionFuture},
+	StreamExt,
+};
 use ruma::{
 	api::client::error::ErrorKind,
 	events::{room::redaction::RoomRedactionEventContent, StateEventType, TimelineEventType},
@@ -81,10 +84,26 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 		self.services.timeline.get_pdu(event_id).await.ok()
 	};
 
+	let third_party_invite: OptionFuture<_> = incoming_pdu
+		.third_party_invite_token()
+		.map(|token| async move {
+			let shortstatekey = self
+				.services
+				.short
+				.get_shortstatekey(&StateEventType::RoomThirdPartyInvite, &token)
+				.await
+				.ok()?;
+
+			let event_id = state_fetch_state.get(&shortstatekey)?;
+			self.services.timeline.get_pdu(event_id).await.ok()
+		})
+		.into();
+
+	let third_party_invite = third_party_invite.await.flatten();
 	let auth_check = state_res::event_auth::auth_check(
 		&room_version,
 		&incoming_pdu,
-		None, // TODO: third party invite
+		third_party_invite.as_ref(),
 		|k, s| state_fetch(k, s.to_owned()),
 	)
 	.await
@@ -112,10 +131,14 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 		ready(auth_events.get(&key).cloned())
 	};
 
+	let third_party_invite = incoming_pdu.third_party_invite_token().and_then(|token| {
+		auth_events.get(&StateEventType::RoomThirdPartyInvite.with_state_key(token.as_str()))
+	});
+
 	let auth_check = state_res::event_auth::auth_check(
 		&room_version,
 		&incoming_pdu,
-		None, // third-party invite
+		third_party_invite,
 		state_fetch,
 	)
 	.await
@@ -165,6 +188,16 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 				}
 	};
 
+	// Soft fail messages refused by the spam checker; state events are kept so
+	// the room's state doesn't diverge from other servers.
+	let soft_fail = soft_fail
+		|| (incoming_pdu.state_key.is_none()
+			&& self
+				.services
+				.spam_checker
+				.federated_event_is_spam(&val)
+				.await);
+
 	// 13. Use state resolution to find new room state
 
 	// We start looking at current room state now, so lets lock the room
@@ -247,7 +280,7 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 		let HashSetCompressStateEvent { shortstatehash, added, removed } = self
 			.services
 			.state_compressor
-			.save_state(room_id, new_room_state)
+			.save_state(room_id, new_room_state, &state_lock)
 			.await?;
 
 		self.services
@@ -59,6 +59,10 @@ impl crate::Service for Service {
 #[implement(Service)]
 #[tracing::instrument(skip(self), level = "debug")]
 pub async fn reset(&self, ctx: &Context<'_>) {
+	if self.db.db.is_read_only() {
+		return;
+	}
+
 	let prefix = (ctx.user_id, ctx.device_id, ctx.room_id, Interfix);
 	self.db
 		.lazyloadedids
@@ -115,6 +119,10 @@ where
 	let make_key =
 		|sender: &'a UserId| -> Key<'a> { (ctx.user_id, ctx.device_id, ctx.room_id, sender) };
 
+	// A read replica can't record what it sent, so it keeps sending the members
+	// until the client syncs with the primary.
+	let read_only = self.db.db.is_read_only();
+
 	senders
 		.clone()
 		.stream()
@@ -123,6 +131,10 @@ where
 		.map(into_status)
 		.zip(senders.stream())
 		.map(move |(status, sender)| {
+			if read_only {
+				return status;
+			}
+
 			if matches!(status, Status::Unseen) {
 				self.db
 					.lazyloadedids
@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
-use conduwuit::{implement, utils::stream::TryIgnore, Result};
+use conduwuit::{implement, utils::stream::TryIgnore, Err, Result, Server};
 use database::Map;
 use futures::{Stream, StreamExt};
-use ruma::RoomId;
+use ruma::{OwnedRoomId, RoomId};
 
 use crate::{rooms, Dep};
 
+/// Room IDs generated before giving up on finding one which is free.
+const GENERATE_ROOM_ID_ATTEMPTS: usize = 32;
+
 pub struct Service {
 	db: Data,
 	services: Services,
@@ -15,11 +18,14 @@ pub struct Service {
 struct Data {
 	disabledroomids: Arc<Map>,
 	bannedroomids: Arc<Map>,
+	quarantinedroomids: Arc<Map>,
+	unfederatedroomids: Arc<Map>,
 	roomid_shortroomid: Arc<Map>,
 	pduid_pdu: Arc<Map>,
 }
 
 struct Services {
+	server: Arc<Server>,
 	short: Dep<rooms::short::Service>,
 }
 
@@ -29,10 +35,13 @@ impl crate::Service for Service {
 			db: Data {
 				disabledroomids: args.db["disabledroomids"].clone(),
 				bannedroomids: args.db["bannedroomids"].clone(),
+				quarantinedroomids: args.db["quarantinedroomids"].clone(),
+				unfederatedroomids: args.db["unfederatedroomids"].clone(),
 				roomid_shortroomid: args.db["roomid_shortroomid"].clone(),
 				pduid_pdu: args.db["pduid_pdu"].clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 			},
 		}))
@@ -57,6 +66,48 @@ pub async fn exists(&self, room_id: &RoomId) -> bool {
 		.is_some()
 }
 
+/// Generates the ID of a new room, which neither collides with a room known to
+/// this server nor starts with a prefix of `appservice_room_id_prefixes`.
+#[implement(Service)]
+pub async fn generate_room_id(&self) -> Result<OwnedRoomId> {
+	for _ in 0..GENERATE_ROOM_ID_ATTEMPTS {
+		let room_id = RoomId::new(&self.services.server.name);
+		if self
+			.room_id_reserved_for(room_id_localpart(&room_id))
+			.is_none()
+			&& self.services.short.get_shortroomid(&room_id).await.is_err()
+			&& !self.is_banned(&room_id).await
+		{
+			return Ok(room_id);
+		}
+	}
+
+	Err!(
+		"Failed to generate a room ID which is free and not reserved by \
+		 appservice_room_id_prefixes."
+	)
+}
+
+/// The appservice which a room ID localpart is reserved for by
+/// `appservice_room_id_prefixes`, if any.
+#[implement(Service)]
+pub fn room_id_reserved_for(&self, localpart: &str) -> Option<&str> {
+	self.services
+		.server
+		.config
+		.appservice_room_id_prefixes
+		.iter()
+		.find(|(_, prefixes)| prefixes.iter().any(|prefix| localpart.starts_with(prefix)))
+		.map(|(appservice_id, _)| appservice_id.as_str())
+}
+
+fn room_id_localpart(room_id: &RoomId) -> &str {
+	let room_id = room_id.as_str().trim_start_matches('!');
+	room_id
+		.split_once(':')
+		.map_or(room_id, |(localpart, _)| localpart)
+}
+
 #[implement(Service)]
 pub fn iter_ids(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
 	self.db.roomid_shortroomid.keys().ignore_err()
@@ -82,11 +133,46 @@ pub fn ban_room(&self, room_id: &RoomId, banned: bool) {
 	}
 }
 
+/// Quarantined rooms can't be joined by local users other than admins and are
+/// hidden from the room directory, but are otherwise left as they are.
+#[implement(Service)]
+#[inline]
+pub fn quarantine_room(&self, room_id: &RoomId, quarantined: bool) {
+	if quarantined {
+		self.db.quarantinedroomids.insert(room_id, []);
+	} else {
+		self.db.quarantinedroomids.remove(room_id);
+	}
+}
+
+/// Unfederated rooms stop exchanging events with other servers, as if they had
+/// been created with `m.federate: false`. Servers already in the room keep
+/// what they have so far.
+#[implement(Service)]
+#[inline]
+pub fn unfederate_room(&self, room_id: &RoomId, unfederated: bool) {
+	if unfederated {
+		self.db.unfederatedroomids.insert(room_id, []);
+	} else {
+		self.db.unfederatedroomids.remove(room_id);
+	}
+}
+
+#[implement(Service)]
+pub fn list_quarantined_rooms(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
+	self.db.quarantinedroomids.keys().ignore_err()
+}
+
 #[implement(Service)]
 pub fn list_banned_rooms(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
 	self.db.bannedroomids.keys().ignore_err()
 }
 
+#[implement(Service)]
+pub fn list_unfederated_rooms(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
+	self.db.unfederatedroomids.keys().ignore_err()
+}
+
 #[implement(Service)]
 #[inline]
 pub async fn is_disabled(&self, room_id: &RoomId) -> bool {
@@ -98,3 +184,15 @@ pub async fn is_disabled(&self, room_id: &RoomId) -> bool {
 pub async fn is_banned(&self, room_id: &RoomId) -> bool {
 	self.db.bannedroomids.get(room_id).await.is_ok()
 }
+
+#[implement(Service)]
+#[inline]
+pub async fn is_quarantined(&self, room_id: &RoomId) -> bool {
+	self.db.quarantinedroomids.get(room_id).await.is_ok()
+}
+
+#[implement(Service)]
+#[inline]
+pub async fn is_unfederated(&self, room_id: &RoomId) -> bool {
+	self.db.unfederatedroomids.get(room_id).await.is_ok()
+}
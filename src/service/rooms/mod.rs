@@ -1,12 +1,15 @@
 pub mod alias;
 pub mod auth_chain;
+pub mod cleanup;
 pub mod directory;
 pub mod event_handler;
 pub mod lazy_loading;
 pub mod metadata;
 pub mod outlier;
 pub mod pdu_metadata;
+pub mod purge;
 pub mod read_receipt;
+pub mod retention;
 pub mod search;
 pub mod short;
 pub mod spaces;
@@ -16,6 +19,7 @@ pub mod state_cache;
 pub mod state_compressor;
 pub mod threads;
 pub mod timeline;
+pub mod tombstone;
 pub mod typing;
 pub mod user;
 
@@ -24,13 +28,16 @@ use std::sync::Arc;
 pub struct Service {
 	pub alias: Arc<alias::Service>,
 	pub auth_chain: Arc<auth_chain::Service>,
+	pub cleanup: Arc<cleanup::Service>,
 	pub directory: Arc<directory::Service>,
 	pub event_handler: Arc<event_handler::Service>,
 	pub lazy_loading: Arc<lazy_loading::Service>,
 	pub metadata: Arc<metadata::Service>,
 	pub outlier: Arc<outlier::Service>,
 	pub pdu_metadata: Arc<pdu_metadata::Service>,
+	pub purge: Arc<purge::Service>,
 	pub read_receipt: Arc<read_receipt::Service>,
+	pub retention: Arc<retention::Service>,
 	pub search: Arc<search::Service>,
 	pub short: Arc<short::Service>,
 	pub spaces: Arc<spaces::Service>,
@@ -40,6 +47,7 @@ pub struct Service {
 	pub state_compressor: Arc<state_compressor::Service>,
 	pub threads: Arc<threads::Service>,
 	pub timeline: Arc<timeline::Service>,
+	pub tombstone: Arc<tombstone::Service>,
 	pub typing: Arc<typing::Service>,
 	pub user: Arc<user::Service>,
 }
@@ -0,0 +1,436 @@
+use std::{collections::BTreeSet, fmt::Debug, sync::Arc};
+
+use conduwuit::{
+	implement,
+	utils::{stream::TryIgnore, ReadyExt},
+	PduCount, PduEvent, RawPduId, Result,
+};
+use database::{Deserialized, Ignore, Interfix, Map};
+use futures::{stream, Stream, StreamExt};
+use ruma::{
+	Mxc, OwnedEventId, OwnedMxcUri, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	globals, media, rooms,
+	rooms::short::{ShortRoomId, ShortStateHash},
+	Dep,
+};
+
+/// Removes everything stored locally about a room, to reclaim the storage of
+/// rooms that were banned or abandoned.
+///
+/// Outliers only fetched to authorize the room's events are not indexed by
+/// room and are left behind.
+pub struct Service {
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	eventid_outlierpdu: Arc<Map>,
+	eventid_pduid: Arc<Map>,
+	eventid_shorteventid: Arc<Map>,
+	lazyloadedids: Arc<Map>,
+	pduid_pdu: Arc<Map>,
+	readreceiptid_readreceipt: Arc<Map>,
+	referencedevents: Arc<Map>,
+	roomid_invitedcount: Arc<Map>,
+	roomid_inviteviaservers: Arc<Map>,
+	roomid_joinedcount: Arc<Map>,
+	roomid_pduleaves: Arc<Map>,
+	roomid_shortroomid: Arc<Map>,
+	roomid_shortstatehash: Arc<Map>,
+	roomserverids: Arc<Map>,
+	roomsynctoken_shortstatehash: Arc<Map>,
+	roomuserdataid_accountdata: Arc<Map>,
+	roomuserid_invitecount: Arc<Map>,
+	roomuserid_joined: Arc<Map>,
+	roomuserid_knockedcount: Arc<Map>,
+	roomuserid_lastprivatereadupdate: Arc<Map>,
+	roomuserid_leftcount: Arc<Map>,
+	roomuserid_privateread: Arc<Map>,
+	roomuseroncejoinedids: Arc<Map>,
+	roomusertype_roomuserdataid: Arc<Map>,
+	serverroomids: Arc<Map>,
+	shorteventid_authchain: Arc<Map>,
+	shorteventid_eventid: Arc<Map>,
+	shorteventid_shortstatehash: Arc<Map>,
+	shortstatehash_statediff: Arc<Map>,
+	softfailedeventids: Arc<Map>,
+	threadid_userids: Arc<Map>,
+	tofrom_relation: Arc<Map>,
+	tokenids: Arc<Map>,
+	userroomid_highlightcount: Arc<Map>,
+	userroomid_invitestate: Arc<Map>,
+	userroomid_joined: Arc<Map>,
+	userroomid_knockedstate: Arc<Map>,
+	userroomid_leftstate: Arc<Map>,
+	userroomid_notificationcount: Arc<Map>,
+	userroomthreadid_highlightcount: Arc<Map>,
+	userroomthreadid_notificationcount: Arc<Map>,
+}
+
+struct Services {
+	alias: Dep<rooms::alias::Service>,
+	directory: Dep<rooms::directory::Service>,
+	globals: Dep<globals::Service>,
+	media: Dep<media::Service>,
+	search: Dep<rooms::search::Service>,
+	short: Dep<rooms::short::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+#[derive(Deserialize)]
+struct ExtractEventId {
+	event_id: OwnedEventId,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				eventid_outlierpdu: args.db["eventid_outlierpdu"].clone(),
+				eventid_pduid: args.db["eventid_pduid"].clone(),
+				eventid_shorteventid: args.db["eventid_shorteventid"].clone(),
+				lazyloadedids: args.db["lazyloadedids"].clone(),
+				pduid_pdu: args.db["pduid_pdu"].clone(),
+				readreceiptid_readreceipt: args.db["readreceiptid_readreceipt"].clone(),
+				referencedevents: args.db["referencedevents"].clone(),
+				roomid_invitedcount: args.db["roomid_invitedcount"].clone(),
+				roomid_inviteviaservers: args.db["roomid_inviteviaservers"].clone(),
+				roomid_joinedcount: args.db["roomid_joinedcount"].clone(),
+				roomid_pduleaves: args.db["roomid_pduleaves"].clone(),
+				roomid_shortroomid: args.db["roomid_shortroomid"].clone(),
+				roomid_shortstatehash: args.db["roomid_shortstatehash"].clone(),
+				roomserverids: args.db["roomserverids"].clone(),
+				roomsynctoken_shortstatehash: args.db["roomsynctoken_shortstatehash"].clone(),
+				roomuserdataid_accountdata: args.db["roomuserdataid_accountdata"].clone(),
+				roomuserid_invitecount: args.db["roomuserid_invitecount"].clone(),
+				roomuserid_joined: args.db["roomuserid_joined"].clone(),
+				roomuserid_knockedcount: args.db["roomuserid_knockedcount"].clone(),
+				roomuserid_lastprivatereadupdate: args.db["roomuserid_lastprivatereadupdate"]
+					.clone(),
+				roomuserid_leftcount: args.db["roomuserid_leftcount"].clone(),
+				roomuserid_privateread: args.db["roomuserid_privateread"].clone(),
+				roomuseroncejoinedids: args.db["roomuseroncejoinedids"].clone(),
+				roomusertype_roomuserdataid: args.db["roomusertype_roomuserdataid"].clone(),
+				serverroomids: args.db["serverroomids"].clone(),
+				shorteventid_authchain: args.db["shorteventid_authchain"].clone(),
+				shorteventid_eventid: args.db["shorteventid_eventid"].clone(),
+				shorteventid_shortstatehash: args.db["shorteventid_shortstatehash"].clone(),
+				shortstatehash_statediff: args.db["shortstatehash_statediff"].clone(),
+				softfailedeventids: args.db["softfailedeventids"].clone(),
+				threadid_userids: args.db["threadid_userids"].clone(),
+				tofrom_relation: args.db["tofrom_relation"].clone(),
+				tokenids: args.db["tokenids"].clone(),
+				userroomid_highlightcount: args.db["userroomid_highlightcount"].clone(),
+				userroomid_invitestate: args.db["userroomid_invitestate"].clone(),
+				userroomid_joined: args.db["userroomid_joined"].clone(),
+				userroomid_knockedstate: args.db["userroomid_knockedstate"].clone(),
+				userroomid_leftstate: args.db["userroomid_leftstate"].clone(),
+				userroomid_notificationcount: args.db["userroomid_notificationcount"].clone(),
+				userroomthreadid_highlightcount: args.db["userroomthreadid_highlightcount"]
+					.clone(),
+				userroomthreadid_notificationcount: args.db["userroomthreadid_notificationcount"]
+					.clone(),
+			},
+			services: Services {
+				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
+				globals: args.depend::<globals::Service>("globals"),
+				media: args.depend::<media::Service>("media"),
+				search: args.depend::<rooms::search::Service>("rooms::search"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Removes the room's local aliases and its room directory entry. Returns
+/// the number of aliases removed.
+#[implement(Service)]
+pub async fn purge_aliases(&self, room_id: &RoomId) -> usize {
+	self.services.directory.set_not_public(room_id);
+
+	let aliases: Vec<_> = self
+		.services
+		.alias
+		.local_aliases_for_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let server_user = &self.services.globals.server_user;
+	stream::iter(&aliases)
+		.filter_map(|alias| async move {
+			self.services
+				.alias
+				.remove_alias(alias, server_user)
+				.await
+				.ok()
+		})
+		.count()
+		.await
+}
+
+/// Deletes the cached copies of remote media referenced by the room's
+/// events. Media uploaded to this server may be shared into other rooms and
+/// is kept. Returns the number of files deleted.
+#[implement(Service)]
+pub async fn purge_remote_media(&self, room_id: &RoomId) -> usize {
+	let mxcs: BTreeSet<OwnedMxcUri> = self
+		.services
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err()
+		.flat_map(|(_, pdu)| stream::iter(media::content_mxcs(&pdu.get_content_as_value())))
+		.ready_filter(|mxc| {
+			mxc.server_name()
+				.is_ok_and(|server_name| !self.services.globals.server_is_ours(server_name))
+		})
+		.collect()
+		.await;
+
+	stream::iter(&mxcs)
+		.filter_map(|mxc| async move {
+			let mxc = Mxc::try_from(mxc.as_str()).ok()?;
+			self.services.media.delete(&mxc).await.ok()
+		})
+		.count()
+		.await
+}
+
+/// Removes the room's state groups, forward extremities and the state
+/// snapshots kept for sync tokens. Returns the number of state groups
+/// removed.
+#[implement(Service)]
+pub async fn purge_state(&self, room_id: &RoomId) -> Result<usize> {
+	let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+	let prefix = shortroomid.to_be_bytes();
+
+	let mut shortstatehashes: BTreeSet<ShortStateHash> = self
+		.room_events(&prefix)
+		.filter_map(|(_, event_id)| async move {
+			let shorteventid = self.services.short.get_shorteventid(&event_id).await.ok()?;
+			self.db
+				.shorteventid_shortstatehash
+				.qry(&shorteventid)
+				.await
+				.deserialized()
+				.ok()
+		})
+		.collect()
+		.await;
+
+	if let Ok(shortstatehash) = self
+		.db
+		.roomid_shortstatehash
+		.get(room_id)
+		.await
+		.deserialized()
+	{
+		shortstatehashes.insert(shortstatehash);
+	}
+
+	self.db
+		.roomsynctoken_shortstatehash
+		.stream_prefix(&shortroomid)
+		.ignore_err()
+		.ready_for_each(|(_, shortstatehash): (Ignore, ShortStateHash)| {
+			shortstatehashes.insert(shortstatehash);
+		})
+		.await;
+
+	for shortstatehash in &shortstatehashes {
+		self.db.shortstatehash_statediff.del(shortstatehash);
+	}
+
+	remove_prefix(&self.db.roomsynctoken_shortstatehash, &shortroomid).await;
+	remove_prefix(&self.db.roomid_pduleaves, &(room_id, Interfix)).await;
+	self.db.roomid_shortstatehash.remove(room_id);
+
+	Ok(shortstatehashes.len())
+}
+
+/// Removes the room's memberships and everything users keep about it:
+/// account data, read receipts, notification counts and lazy-loading
+/// records. Returns the number of users the room was removed for.
+#[implement(Service)]
+pub async fn purge_members(&self, room_id: &RoomId) -> usize {
+	let prefix = (room_id, Interfix);
+
+	let mut users = BTreeSet::<OwnedUserId>::new();
+	for map in [
+		&self.db.roomuseroncejoinedids,
+		&self.db.roomuserid_joined,
+		&self.db.roomuserid_invitecount,
+		&self.db.roomuserid_knockedcount,
+		&self.db.roomuserid_leftcount,
+	] {
+		map.keys_prefix(&prefix)
+			.ignore_err()
+			.ready_for_each(|(_, user_id): (Ignore, &UserId)| {
+				users.insert(user_id.to_owned());
+			})
+			.await;
+	}
+
+	for user_id in &users {
+		let userroom_id = (user_id, room_id);
+		self.db.userroomid_joined.del(userroom_id);
+		self.db.userroomid_invitestate.del(userroom_id);
+		self.db.userroomid_knockedstate.del(userroom_id);
+		self.db.userroomid_leftstate.del(userroom_id);
+		self.db.userroomid_highlightcount.del(userroom_id);
+		self.db.userroomid_notificationcount.del(userroom_id);
+
+		let userroom_prefix = (user_id, room_id, Interfix);
+		remove_prefix(&self.db.userroomthreadid_highlightcount, &userroom_prefix).await;
+		remove_prefix(&self.db.userroomthreadid_notificationcount, &userroom_prefix).await;
+	}
+
+	let servers: Vec<OwnedServerName> = self
+		.db
+		.roomserverids
+		.keys_prefix(&prefix)
+		.ignore_err()
+		.map(|(_, server): (Ignore, &ServerName)| server.to_owned())
+		.collect()
+		.await;
+
+	for server in &servers {
+		self.db.serverroomids.del((server, room_id));
+	}
+
+	for map in [
+		&self.db.roomserverids,
+		&self.db.roomuseroncejoinedids,
+		&self.db.roomuserid_joined,
+		&self.db.roomuserid_invitecount,
+		&self.db.roomuserid_knockedcount,
+		&self.db.roomuserid_leftcount,
+		&self.db.roomuserid_privateread,
+		&self.db.roomuserid_lastprivatereadupdate,
+		&self.db.roomuserdataid_accountdata,
+		&self.db.roomusertype_roomuserdataid,
+		&self.db.readreceiptid_readreceipt,
+	] {
+		remove_prefix(map, &prefix).await;
+	}
+
+	self.db.roomid_joinedcount.remove(room_id);
+	self.db.roomid_invitedcount.remove(room_id);
+	self.db.roomid_inviteviaservers.remove(room_id);
+
+	// Lazy-loading records are keyed by user and device first, so every record
+	// has to be checked for the room.
+	self.db
+		.lazyloadedids
+		.raw_keys()
+		.ignore_err()
+		.ready_filter(|key| key.split(|&b| b == 0xFF).nth(2) == Some(room_id.as_bytes()))
+		.ready_for_each(|key| self.db.lazyloadedids.remove(key))
+		.await;
+
+	users.len()
+}
+
+/// Removes the room's events and everything indexed by them, then forgets
+/// the room's short ID. This must run last as the other steps find the
+/// room's data through its events. Returns the number of events removed.
+#[implement(Service)]
+pub async fn purge_events(&self, room_id: &RoomId) -> Result<usize> {
+	let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+	let prefix = shortroomid.to_be_bytes();
+
+	let events = self
+		.room_events(&prefix)
+		.then(|(pdu_id, event_id)| async move {
+			if let Ok(shorteventid) = self.services.short.get_shorteventid(&event_id).await {
+				self.db.shorteventid_eventid.del(shorteventid);
+				self.db.shorteventid_shortstatehash.del(shorteventid);
+				self.db.shorteventid_authchain.del(shorteventid);
+			}
+
+			if let PduCount::Normal(count) = pdu_id.pdu_count() {
+				remove_prefix(&self.db.tofrom_relation, &count).await;
+			}
+
+			self.db.eventid_shorteventid.remove(&event_id);
+			self.db.eventid_pduid.remove(&event_id);
+			self.db.eventid_outlierpdu.remove(&event_id);
+			self.db.softfailedeventids.remove(&event_id);
+			self.db.pduid_pdu.remove(&pdu_id);
+			self.services.timeline.evict_cached_pdu(&event_id);
+		})
+		.count()
+		.await;
+
+	remove_prefix(&self.db.tokenids, &shortroomid).await;
+	remove_prefix(&self.db.threadid_userids, &shortroomid).await;
+	remove_prefix(&self.db.referencedevents, &(room_id, Interfix)).await;
+	self.db.roomid_shortroomid.remove(room_id);
+
+	Ok(events)
+}
+
+/// Removes a single timeline event, its search index entries and the
+/// relations pointing at it. Its short IDs are kept so state which refers to
+/// it still resolves. Returns the MXC URIs the event referred to.
+#[implement(Service)]
+pub async fn purge_event(
+	&self,
+	shortroomid: ShortRoomId,
+	pdu_id: &RawPduId,
+	pdu: &PduEvent,
+) -> Vec<OwnedMxcUri> {
+	let content = pdu.get_content_as_value();
+	if let Some(body) = content.get("body").and_then(serde_json::Value::as_str) {
+		self.services.search.deindex_pdu(shortroomid, pdu_id, body);
+	}
+
+	if let PduCount::Normal(count) = pdu_id.pdu_count() {
+		remove_prefix(&self.db.tofrom_relation, &count).await;
+	}
+
+	self.db.eventid_pduid.remove(&pdu.event_id);
+	self.db.pduid_pdu.remove(pdu_id);
+	self.services.timeline.evict_cached_pdu(&pdu.event_id);
+
+	media::content_mxcs(&content)
+}
+
+/// The IDs of the room's timeline events, by the room's short ID.
+#[implement(Service)]
+fn room_events<'a>(
+	&'a self,
+	prefix: &'a [u8; 8],
+) -> impl Stream<Item = (RawPduId, OwnedEventId)> + Send + 'a {
+	self.db
+		.pduid_pdu
+		.raw_stream_prefix(prefix)
+		.ignore_err()
+		.ready_filter_map(|(pdu_id, pdu)| {
+			let ExtractEventId { event_id } = serde_json::from_slice(pdu).ok()?;
+			Some((pdu_id.into(), event_id))
+		})
+}
+
+/// Removes every entry under the prefix, returning how many there were.
+async fn remove_prefix<P>(map: &Arc<Map>, prefix: &P) -> usize
+where
+	P: Serialize + ?Sized + Debug,
+{
+	map.keys_prefix_raw(prefix)
+		.ignore_err()
+		.ready_fold(0_usize, |count, key| {
+			map.remove(key);
+			count.saturating_add(1)
+		})
+		.await
+}
@@ -7,7 +7,10 @@ use conduwuit::{
 use database::{Deserialized, Json, Map};
 use futures::{Stream, StreamExt};
 use ruma::{
-	events::{receipt::ReceiptEvent, AnySyncEphemeralRoomEvent},
+	events::{
+		receipt::{ReceiptEvent, ReceiptThread, ReceiptType},
+		AnySyncEphemeralRoomEvent,
+	},
 	serde::Raw,
 	CanonicalJsonObject, RoomId, UserId,
 };
@@ -46,14 +49,21 @@ impl Data {
 		room_id: &RoomId,
 		event: &ReceiptEvent,
 	) {
-		// Remove old entry
+		// Remove old entry for the same thread; receipts in other threads are kept
+		let thread = receipt_thread(event, user_id);
 		let last_possible_key = (room_id, u64::MAX);
 		self.readreceiptid_readreceipt
-			.rev_keys_from_raw(&last_possible_key)
+			.rev_stream_from_raw(&last_possible_key)
 			.ignore_err()
-			.ready_take_while(|key| key.starts_with(room_id.as_bytes()))
-			.ready_filter_map(|key| key.ends_with(user_id.as_bytes()).then_some(key))
-			.ready_for_each(|key| self.readreceiptid_readreceipt.del(key))
+			.ready_take_while(|(key, _)| key.starts_with(room_id.as_bytes()))
+			.ready_filter_map(|(key, val)| {
+				key.ends_with(user_id.as_bytes()).then_some((key, val))
+			})
+			.ready_filter(|(_, val)| {
+				serde_json::from_slice::<ReceiptEvent>(val)
+					.map_or(true, |old| receipt_thread(&old, user_id) == thread)
+			})
+			.ready_for_each(|(key, _)| self.readreceiptid_readreceipt.del(key))
 			.await;
 
 		let count = self.services.globals.next_count().unwrap();
@@ -116,3 +126,13 @@ impl Data {
 			.unwrap_or(0)
 	}
 }
+
+/// The thread of the user's read receipt carried by a receipt event.
+fn receipt_thread<'a>(event: &'a ReceiptEvent, user_id: &UserId) -> Option<&'a ReceiptThread> {
+	event
+		.content
+		.values()
+		.filter_map(|receipts| receipts.get(&ReceiptType::Read))
+		.find_map(|users| users.get(user_id))
+		.map(|receipt| &receipt.thread)
+}
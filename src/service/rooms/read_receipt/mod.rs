@@ -58,7 +58,7 @@ impl Service {
 		self.db.readreceipt_update(user_id, room_id, &event).await;
 		self.services
 			.sending
-			.flush_room(room_id)
+			.flush_room_receipts(room_id)
 			.await
 			.expect("room flush failed");
 		// update appservices
@@ -161,8 +161,11 @@ where
 			value.json().get(),
 		);
 		if let Ok(value) = receipt {
-			for (event, receipt) in value.content {
-				json.insert(event, receipt);
+			for (event, receipts) in value.content {
+				let event: &mut Receipts = json.entry(event).or_default();
+				for (receipt_type, users) in receipts {
+					event.entry(receipt_type).or_default().extend(users);
+				}
 			}
 		} else {
 			debug!("failed to parse receipt: {:?}", receipt);
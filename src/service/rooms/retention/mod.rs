@@ -0,0 +1,331 @@
+use std::{
+	collections::BTreeSet,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, debug_info, info,
+	result::LogErr,
+	utils::{self, stream::TryIgnore, ReadyExt},
+	warn, PduCount, PduEvent, PduId, RawPduId, Result, Server,
+};
+use database::Map;
+use futures::StreamExt;
+use ruma::{events::StateEventType, Mxc, OwnedMxcUri, OwnedRoomId, RoomId};
+use serde::Deserialize;
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+
+use crate::{admin, globals, media, rooms, Dep};
+
+/// Deletes events which have outlived their room's retention policy
+/// (MSC1763).
+pub struct Service {
+	interrupt: Notify,
+	stats: Stats,
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	receivedat_count: Arc<Map>,
+}
+
+struct Services {
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+	media: Dep<media::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	purge: Dep<rooms::purge::Service>,
+	short: Dep<rooms::short::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	server: Arc<Server>,
+}
+
+/// Totals since startup.
+#[derive(Default)]
+pub struct Stats {
+	pub runs: AtomicU64,
+	pub events: AtomicU64,
+	pub media: AtomicU64,
+	pub last_run: AtomicU64,
+}
+
+/// Content of an `m.room.retention` state event; lifetimes are in
+/// milliseconds.
+#[derive(Debug, Default, Deserialize)]
+struct RoomRetentionEventContent {
+	max_lifetime: Option<u64>,
+}
+
+const RETENTION_EVENT_TYPE: &str = "m.room.retention";
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			stats: Stats::default(),
+			db: Data {
+				receivedat_count: args.db["receivedat_count"].clone(),
+			},
+			services: Services {
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+				media: args.depend::<media::Service>("media"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				purge: args.depend::<rooms::purge::Service>("rooms::purge"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				server: args.server.clone(),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "retention", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let config = &self.services.server.config;
+		if !config.retention_enabled || self.services.globals.is_read_only() {
+			debug!("Message retention is disabled");
+			return Ok(());
+		}
+
+		let period = Duration::from_secs(config.retention_purge_interval.max(60));
+		let mut i = interval(period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.reset_after(period);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.purge_expired().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Totals of what the purger removed since startup.
+	#[inline]
+	#[must_use]
+	pub fn stats(&self) -> &Stats { &self.stats }
+
+	/// How long the room's events are kept: its `m.room.retention`
+	/// "max_lifetime" bounded by the configured minimum and maximum, or the
+	/// configured default. None keeps them forever.
+	pub async fn max_lifetime(&self, room_id: &RoomId) -> Option<Duration> {
+		let config = &self.services.server.config;
+		let content: RoomRetentionEventContent = self
+			.services
+			.state_accessor
+			.room_state_get_content(room_id, &StateEventType::from(RETENTION_EVENT_TYPE), "")
+			.await
+			.unwrap_or_default();
+
+		let lifetime = content.max_lifetime.map(Duration::from_millis).or(config
+			.retention_default_max_lifetime
+			.map(Duration::from_secs))?;
+
+		let lifetime = config
+			.retention_min_lifetime
+			.map(Duration::from_secs)
+			.map_or(lifetime, |min| lifetime.max(min));
+
+		let lifetime = config
+			.retention_max_lifetime
+			.map(Duration::from_secs)
+			.map_or(lifetime, |max| lifetime.min(max));
+
+		Some(lifetime)
+	}
+
+	/// Purges expired events in every room with a retention policy.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn purge_expired(&self) {
+		let now = utils::millis_since_unix_epoch();
+		if let Ok(count) = self.services.globals.current_count() {
+			self.db.receivedat_count.put(now, count);
+		}
+
+		let admin_room = self.services.admin.get_admin_room().await.ok();
+		let rooms: Vec<OwnedRoomId> = self
+			.services
+			.metadata
+			.iter_ids()
+			.ready_filter(|room_id| admin_room.as_deref() != Some(*room_id))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let (mut events, mut media) = (0_usize, 0_usize);
+		let mut longest = Duration::ZERO;
+		for room_id in &rooms {
+			if !self.services.server.running() {
+				break;
+			}
+
+			let Some(lifetime) = self.max_lifetime(room_id).await else {
+				continue;
+			};
+
+			longest = longest.max(lifetime);
+
+			match self.purge_room(room_id, lifetime).await {
+				| Ok((room_events, room_media)) => {
+					events = events.saturating_add(room_events);
+					media = media.saturating_add(room_media);
+				},
+				| Err(e) => warn!(%room_id, "Failed to purge expired events: {e}"),
+			}
+		}
+
+		let stats = &self.stats;
+		stats.runs.fetch_add(1, Ordering::Relaxed);
+		stats
+			.events
+			.fetch_add(events.try_into().unwrap_or(0), Ordering::Relaxed);
+		stats
+			.media
+			.fetch_add(media.try_into().unwrap_or(0), Ordering::Relaxed);
+		stats
+			.last_run
+			.store(utils::millis_since_unix_epoch(), Ordering::Relaxed);
+
+		if events > 0 {
+			info!(events, media, "Purged expired events");
+		}
+
+		self.prune_received_at(cutoff(now, longest)).await;
+	}
+
+	/// The last event count this server had assigned by `time`, as recorded on
+	/// a run of the purger at or before it. None if there was no run yet.
+	async fn count_received_by(&self, time: u64) -> Option<u64> {
+		self.db
+			.receivedat_count
+			.rev_stream_from(&time)
+			.ignore_err()
+			.map(|(_, count): (u64, u64)| count)
+			.next()
+			.await
+	}
+
+	/// Forgets the counts recorded before `time`, except the newest of them,
+	/// which is still needed to look up `time` itself.
+	async fn prune_received_at(&self, time: u64) {
+		let mut older: Vec<u64> = self
+			.db
+			.receivedat_count
+			.keys()
+			.ignore_err()
+			.ready_take_while(|received_at: &u64| *received_at <= time)
+			.collect()
+			.await;
+
+		older.pop();
+		for received_at in older {
+			self.db.receivedat_count.del(received_at);
+		}
+	}
+
+	/// Deletes the room's timeline events this server received before
+	/// `lifetime` ago, except state events and the room's newest event, then
+	/// the cached remote media they referred to. Returns how many events and
+	/// files were deleted.
+	///
+	/// When an event was received is known from the counts recorded on each
+	/// run, so events are purged up to one `retention_purge_interval` late.
+	/// Their `origin_server_ts` is not used, as the sender chooses it.
+	async fn purge_room(&self, room_id: &RoomId, lifetime: Duration) -> Result<(usize, usize)> {
+		let cutoff = cutoff(utils::millis_since_unix_epoch(), lifetime);
+		let Some(received_by) = self.count_received_by(cutoff).await else {
+			return Ok((0, 0));
+		};
+
+		let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+		let latest = self.services.timeline.latest_pdu_in_room(room_id).await?;
+
+		// Backfilled events come first but were received whenever they were
+		// fetched, so every event is checked.
+		let expired: Vec<(RawPduId, PduEvent)> = self
+			.services
+			.timeline
+			.pdus(None, room_id, None)
+			.ignore_err()
+			.ready_filter(|(count, pdu)| {
+				received_count(*count) <= received_by
+					&& pdu.state_key.is_none()
+					&& pdu.event_id != latest.event_id
+			})
+			.map(|(shorteventid, pdu)| (PduId { shortroomid, shorteventid }.into(), pdu))
+			.collect()
+			.await;
+
+		let mut mxcs = BTreeSet::<OwnedMxcUri>::new();
+		for (pdu_id, pdu) in &expired {
+			mxcs.extend(
+				self.services
+					.purge
+					.purge_event(shortroomid, pdu_id, pdu)
+					.await,
+			);
+		}
+
+		let mut media: usize = 0;
+		for mxc in &mxcs {
+			let Ok(mxc) = Mxc::try_from(mxc.as_str()) else {
+				continue;
+			};
+
+			if !self.services.globals.server_is_ours(mxc.server_name)
+				&& self.services.media.delete(&mxc).await.is_ok()
+			{
+				media = media.saturating_add(1);
+			}
+		}
+
+		if !expired.is_empty() {
+			debug_info!(%room_id, events = expired.len(), media, "Purged expired events");
+
+			// Purged events may still be counted as unread
+			self.services
+				.timeline
+				.recount_room_notifications(room_id)
+				.await
+				.log_err()
+				.ok();
+		}
+
+		Ok((expired.len(), media))
+	}
+}
+
+fn cutoff(now: u64, lifetime: Duration) -> u64 {
+	now.saturating_sub(lifetime.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+/// Position of an event in the order this server received it in; backfilled
+/// events are counted down from zero by the count at the time.
+fn received_count(count: PduCount) -> u64 {
+	match count {
+		| PduCount::Normal(count) => count,
+		| PduCount::Backfilled(count) => count.unsigned_abs(),
+	}
+}
@@ -2,7 +2,7 @@ use std::{borrow::Borrow, fmt::Debug, mem::size_of_val, sync::Arc};
 
 pub use conduwuit::pdu::{ShortEventId, ShortId, ShortRoomId};
 use conduwuit::{err, implement, utils, utils::IterStream, Result};
-use database::{Deserialized, Get, Map, Qry};
+use database::{Batch, Deserialized, Get, Map, Qry};
 use futures::{Stream, StreamExt};
 use ruma::{events::StateEventType, EventId, RoomId};
 use serde::Deserialize;
@@ -209,9 +209,17 @@ where
 		.map(Deserialized::deserialized)
 }
 
-/// Returns (shortstatehash, already_existed)
+/// Returns (shortstatehash, already_existed). A new shortstatehash is queued on
+/// `batch`, so it is committed together with the state it stands for. Until
+/// then another caller would allocate a second one for the same state, so
+/// callers hold the room's state mutex; a room's state hashes are its own, as
+/// they cover its events.
 #[implement(Service)]
-pub async fn get_or_create_shortstatehash(&self, state_hash: &[u8]) -> (ShortStateHash, bool) {
+pub async fn get_or_create_shortstatehash<'a>(
+	&'a self,
+	state_hash: &[u8],
+	batch: &mut Batch<'a>,
+) -> (ShortStateHash, bool) {
 	const BUFSIZE: usize = size_of::<ShortStateHash>();
 
 	if let Ok(shortstatehash) = self
@@ -227,9 +235,7 @@ pub async fn get_or_create_shortstatehash(&self, state_hash: &[u8]) -> (ShortSta
 	let shortstatehash = self.services.globals.next_count().unwrap();
 	debug_assert!(size_of_val(&shortstatehash) == BUFSIZE, "buffer requirement changed");
 
-	self.db
-		.statehash_shortstatehash
-		.raw_aput::<BUFSIZE, _, _>(state_hash, shortstatehash);
+	batch.insert(&self.db.statehash_shortstatehash, state_hash, shortstatehash.to_be_bytes());
 
 	(shortstatehash, false)
 }
@@ -15,7 +15,7 @@ use conduwuit::{
 	},
 	warn, PduEvent, Result,
 };
-use database::{Deserialized, Ignore, Interfix, Map};
+use database::{Database, Deserialized, Ignore, Interfix, Map};
 use futures::{
 	future::join_all, pin_mut, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt,
 };
@@ -32,7 +32,7 @@ use ruma::{
 use crate::{
 	globals, rooms,
 	rooms::{
-		short::{ShortEventId, ShortStateHash},
+		short::ShortStateHash,
 		state_compressor::{parse_compressed_state_event, CompressedStateEvent},
 	},
 	Dep,
@@ -58,6 +58,7 @@ struct Data {
 	shorteventid_shortstatehash: Arc<Map>,
 	roomid_shortstatehash: Arc<Map>,
 	roomid_pduleaves: Arc<Map>,
+	db: Arc<Database>,
 }
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
@@ -82,6 +83,7 @@ impl crate::Service for Service {
 				shorteventid_shortstatehash: args.db["shorteventid_shortstatehash"].clone(),
 				roomid_shortstatehash: args.db["roomid_shortstatehash"].clone(),
 				roomid_pduleaves: args.db["roomid_pduleaves"].clone(),
+				db: args.db.clone(),
 			},
 		}))
 	}
@@ -171,16 +173,17 @@ impl Service {
 	///
 	/// This adds all current state events (not including the incoming event)
 	/// to `stateid_pduid` and adds the incoming event to `eventid_statehash`.
-	#[tracing::instrument(skip(self, state_ids_compressed), level = "debug")]
+	/// The new StateHash, its diff and the association are committed
+	/// atomically.
+	#[tracing::instrument(skip(self, state_ids_compressed, _state_lock), level = "debug")]
 	pub async fn set_event_state(
 		&self,
 		event_id: &EventId,
 		room_id: &RoomId,
 		state_ids_compressed: Arc<HashSet<CompressedStateEvent>>,
+		_state_lock: &RoomMutexGuard, /* Take mutex guard to make sure users get the room
+		                               * state mutex */
 	) -> Result<ShortStateHash> {
-		const KEY_LEN: usize = size_of::<ShortEventId>();
-		const VAL_LEN: usize = size_of::<ShortStateHash>();
-
 		let shorteventid = self
 			.services
 			.short
@@ -191,10 +194,11 @@ impl Service {
 
 		let state_hash = calculate_hash(state_ids_compressed.iter().map(|s| &s[..]));
 
+		let mut batch = self.db.db.batch();
 		let (shortstatehash, already_existed) = self
 			.services
 			.short
-			.get_or_create_shortstatehash(&state_hash)
+			.get_or_create_shortstatehash(&state_hash, &mut batch)
 			.await;
 
 		if !already_existed {
@@ -225,6 +229,7 @@ impl Service {
 					(state_ids_compressed, Arc::new(HashSet::new()))
 				};
 			self.services.state_compressor.save_state_from_diff(
+				&mut batch,
 				shortstatehash,
 				statediffnew,
 				statediffremoved,
@@ -233,9 +238,12 @@ impl Service {
 			)?;
 		}
 
-		self.db
-			.shorteventid_shortstatehash
-			.aput::<KEY_LEN, VAL_LEN, _, _>(shorteventid, shortstatehash);
+		batch.insert(
+			&self.db.shorteventid_shortstatehash,
+			&shorteventid.to_be_bytes(),
+			shortstatehash.to_be_bytes(),
+		);
+		batch.commit()?;
 
 		Ok(shortstatehash)
 	}
@@ -308,13 +316,16 @@ impl Service {
 				statediffremoved.insert(*replaces);
 			}
 
+			let mut batch = self.db.db.batch();
 			self.services.state_compressor.save_state_from_diff(
+				&mut batch,
 				shortstatehash,
 				Arc::new(statediffnew),
 				Arc::new(statediffremoved),
 				2,
 				states_parents,
 			)?;
+			batch.commit()?;
 
 			Ok(shortstatehash)
 		} else {
@@ -453,7 +464,7 @@ impl Service {
 		let (state_keys, event_ids): (Vec<_>, Vec<_>) = self
 			.services
 			.state_accessor
-			.state_full_shortids(shortstatehash)
+			.state_get_shortids(shortstatehash, sauthevents.keys().copied().collect::<Vec<_>>())
 			.await
 			.map_err(|e| err!(Database(error!(?room_id, ?shortstatehash, "{e:?}"))))?
 			.into_iter()
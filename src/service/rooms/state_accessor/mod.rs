@@ -1,5 +1,6 @@
 use std::{
 	borrow::Borrow,
+	collections::HashSet,
 	fmt::Write,
 	sync::{Arc, Mutex as StdMutex, Mutex},
 };
@@ -16,7 +17,7 @@ use conduwuit::{
 	Err, Error, PduEvent, Result,
 };
 use database::{Deserialized, Map};
-use futures::{FutureExt, Stream, StreamExt, TryFutureExt};
+use futures::{future::try_join, FutureExt, Stream, StreamExt, TryFutureExt};
 use lru_cache::LruCache;
 use ruma::{
 	events::{
@@ -276,6 +277,108 @@ impl Service {
 		Ok(shortids)
 	}
 
+	/// Looks up only the given keys in the state at `shortstatehash`, without
+	/// materializing the rest of it. Keys absent from the state are omitted.
+	pub async fn state_get_shortids<I>(
+		&self,
+		shortstatehash: ShortStateHash,
+		shortstatekeys: I,
+	) -> Result<Vec<(ShortStateKey, ShortEventId)>>
+	where
+		I: IntoIterator<Item = ShortStateKey> + Send,
+	{
+		let mut wanted: HashSet<_> = shortstatekeys.into_iter().collect();
+		let full_state = self
+			.services
+			.state_compressor
+			.load_shortstatehash_info(shortstatehash)
+			.await
+			.map_err(|e| err!(Database("Missing state IDs: {e}")))?
+			.pop()
+			.expect("there is always one layer")
+			.full_state;
+
+		let mut shortids = Vec::with_capacity(wanted.len());
+		for compressed in full_state.iter() {
+			if wanted.is_empty() {
+				break;
+			}
+
+			let (shortstatekey, shorteventid) = parse_compressed_state_event(*compressed);
+			if wanted.remove(&shortstatekey) {
+				shortids.push((shortstatekey, shorteventid));
+			}
+		}
+
+		Ok(shortids)
+	}
+
+	/// Returns the entries of the state at `shortstatehash` which are not in
+	/// the state at `since`: everything set or replaced in between. Neither
+	/// state is materialized; the difference is taken on the compressed
+	/// states.
+	pub async fn state_added_shortids(
+		&self,
+		since: ShortStateHash,
+		shortstatehash: ShortStateHash,
+	) -> Result<Vec<(ShortStateKey, ShortEventId)>> {
+		let load = |shortstatehash| {
+			self.services
+				.state_compressor
+				.load_shortstatehash_info(shortstatehash)
+				.map_ok(|mut info| info.pop().expect("there is always one layer").full_state)
+				.map_err(|e| err!(Database("Missing state IDs: {e}")))
+		};
+
+		let (since, current) = try_join(load(since), load(shortstatehash)).await?;
+		let shortids = current
+			.difference(&*since)
+			.copied()
+			.map(parse_compressed_state_event)
+			.collect();
+
+		Ok(shortids)
+	}
+
+	/// Like state_full_ids() restricted to the entries added since another
+	/// state; see state_added_shortids().
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub fn state_added_ids<'a, Id>(
+		&'a self,
+		since: ShortStateHash,
+		shortstatehash: ShortStateHash,
+	) -> impl Stream<Item = (ShortStateKey, Id)> + Send + 'a
+	where
+		Id: for<'de> Deserialize<'de> + Send + Sized + ToOwned + 'a,
+		<Id as ToOwned>::Owned: Borrow<EventId>,
+	{
+		let shortids = self
+			.state_added_shortids(since, shortstatehash)
+			.map(|result| result.expect("missing shortstatehash"))
+			.map(|vec| vec.into_iter().unzip())
+			.boxed()
+			.shared();
+
+		let shortstatekeys = shortids
+			.clone()
+			.map(at!(0))
+			.map(Vec::into_iter)
+			.map(IterStream::stream)
+			.flatten_stream();
+
+		let shorteventids = shortids
+			.map(at!(1))
+			.map(Vec::into_iter)
+			.map(IterStream::stream)
+			.flatten_stream();
+
+		self.services
+			.short
+			.multi_get_eventid_from_short(shorteventids)
+			.zip(shortstatekeys)
+			.ready_filter_map(|(event_id, shortstatekey)| Some((shortstatekey, event_id.ok()?)))
+	}
+
 	/// Returns a single PDU from `room_id` with key (`event_type`,
 	/// `state_key`).
 	pub async fn state_get(
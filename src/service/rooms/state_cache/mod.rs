@@ -1,5 +1,6 @@
 use std::{
 	collections::{HashMap, HashSet},
+	fmt::Write,
 	sync::{Arc, RwLock},
 };
 
@@ -25,10 +26,10 @@ use ruma::{
 	},
 	int,
 	serde::Raw,
-	OwnedRoomId, OwnedServerName, RoomId, ServerName, UserId,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
 };
 
-use crate::{account_data, appservice::RegistrationInfo, globals, rooms, users, Dep};
+use crate::{account_data, appservice::RegistrationInfo, globals, pusher, rooms, users, Dep};
 
 pub struct Service {
 	appservice_in_room_cache: AppServiceInRoomCache,
@@ -39,6 +40,7 @@ pub struct Service {
 struct Services {
 	account_data: Dep<account_data::Service>,
 	globals: Dep<globals::Service>,
+	pusher: Dep<pusher::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	users: Dep<users::Service>,
 }
@@ -71,6 +73,7 @@ impl crate::Service for Service {
 			services: Services {
 				account_data: args.depend::<account_data::Service>("account_data"),
 				globals: args.depend::<globals::Service>("globals"),
+				pusher: args.depend::<pusher::Service>("pusher"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				users: args.depend::<users::Service>("users"),
@@ -94,6 +97,13 @@ impl crate::Service for Service {
 		}))
 	}
 
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let appservice_in_room = self.appservice_in_room_cache.read()?.len();
+		writeln!(out, "appservice_in_room_cache: {appservice_in_room}")?;
+
+		Ok(())
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -157,6 +167,11 @@ impl Service {
 			*/
 		}
 
+		// Local members' push rules are indexed per room
+		if self.services.globals.user_is_local(user_id) {
+			self.services.pusher.invalidate_room_push_index(room_id);
+		}
+
 		match &membership {
 			| MembershipState::Join => {
 				// Check if the user never joined this room
@@ -646,6 +661,24 @@ impl Service {
 			.ignore_err()
 	}
 
+	/// Returns an iterator over the rooms a user is invited to, leaving out
+	/// invites sent by users they ignore, which are not shown to them.
+	#[tracing::instrument(skip(self), level = "trace")]
+	pub fn rooms_invited_unignored<'a>(
+		&'a self,
+		user_id: &'a UserId,
+	) -> impl Stream<Item = StrippedStateEventItem> + Send + 'a {
+		self.rooms_invited(user_id)
+			.filter_map(move |(room_id, invite_state)| async move {
+				let ignored = match invite_sender(user_id, &invite_state) {
+					| Some(sender) => self.services.users.user_is_ignored(&sender, user_id).await,
+					| None => false,
+				};
+
+				(!ignored).then_some((room_id, invite_state))
+			})
+	}
+
 	/// Returns an iterator over all rooms a user is currently knocking.
 	#[tracing::instrument(skip(self), level = "trace")]
 	pub fn rooms_knocked<'a>(
@@ -987,3 +1020,25 @@ impl Service {
 			.insert(room_id.as_bytes(), &servers);
 	}
 }
+
+/// The sender of the user's invite, from the member event in its stripped
+/// state.
+fn invite_sender(
+	user_id: &UserId,
+	invite_state: &[Raw<AnyStrippedStateEvent>],
+) -> Option<OwnedUserId> {
+	invite_state
+		.iter()
+		.filter(|event| {
+			event.get_field::<StateEventType>("type").ok().flatten()
+				== Some(StateEventType::RoomMember)
+		})
+		.filter(|event| {
+			event
+				.get_field::<String>("state_key")
+				.ok()
+				.flatten()
+				.is_some_and(|state_key| state_key == user_id.as_str())
+		})
+		.find_map(|event| event.get_field("sender").ok().flatten())
+}
@@ -1,3 +1,5 @@
+mod recompress;
+
 use std::{
 	collections::{HashMap, HashSet},
 	fmt::{Debug, Write},
@@ -9,16 +11,20 @@ use arrayvec::ArrayVec;
 use conduwuit::{
 	at, checked, err, expected, utils,
 	utils::{bytes, math::usize_from_f64, stream::IterStream},
-	Result,
+	Result, Server,
 };
-use database::Map;
+use database::{Batch, Database, Map};
 use futures::{Stream, StreamExt};
 use lru_cache::LruCache;
 use ruma::{EventId, RoomId};
 
+pub use self::recompress::Recompression;
 use crate::{
 	rooms,
-	rooms::short::{ShortEventId, ShortId, ShortStateHash, ShortStateKey},
+	rooms::{
+		short::{ShortEventId, ShortId, ShortStateHash, ShortStateKey},
+		state::RoomMutexGuard,
+	},
 	Dep,
 };
 
@@ -29,12 +35,17 @@ pub struct Service {
 }
 
 struct Services {
+	metadata: Dep<rooms::metadata::Service>,
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	server: Arc<Server>,
 }
 
 struct Data {
 	shortstatehash_statediff: Arc<Map>,
+	db: Arc<Database>,
 }
 
 #[derive(Clone)]
@@ -75,10 +86,16 @@ impl crate::Service for Service {
 			stateinfo_cache: LruCache::new(usize_from_f64(cache_capacity)?).into(),
 			db: Data {
 				shortstatehash_statediff: args.db["shortstatehash_statediff"].clone(),
+				db: args.db.clone(),
 			},
 			services: Services {
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				server: args.server.clone(),
 			},
 		}))
 	}
@@ -246,8 +263,11 @@ impl Service {
 	///   for this layer
 	/// * `parent_states` - A stack with info on shortstatehash, full state,
 	///   added diff and removed diff for each parent layer
-	pub fn save_state_from_diff(
-		&self,
+	///
+	/// The diff is queued on `batch`; it is only readable once committed.
+	pub fn save_state_from_diff<'a>(
+		&'a self,
+		batch: &mut Batch<'a>,
 		shortstatehash: ShortStateHash,
 		statediffnew: Arc<HashSet<CompressedStateEvent>>,
 		statediffremoved: Arc<HashSet<CompressedStateEvent>>,
@@ -285,6 +305,7 @@ impl Service {
 			}
 
 			self.save_state_from_diff(
+				batch,
 				shortstatehash,
 				Arc::new(parent_new),
 				Arc::new(parent_removed),
@@ -297,7 +318,7 @@ impl Service {
 
 		if parent_states.is_empty() {
 			// There is no parent layer, create a new state
-			self.save_statediff(shortstatehash, &StateDiff {
+			self.save_statediff(batch, shortstatehash, &StateDiff {
 				parent: None,
 				added: statediffnew,
 				removed: statediffremoved,
@@ -339,6 +360,7 @@ impl Service {
 			}
 
 			self.save_state_from_diff(
+				batch,
 				shortstatehash,
 				Arc::new(parent_new),
 				Arc::new(parent_removed),
@@ -347,7 +369,7 @@ impl Service {
 			)?;
 		} else {
 			// Diff small enough, we add diff as layer on top of parent
-			self.save_statediff(shortstatehash, &StateDiff {
+			self.save_statediff(batch, shortstatehash, &StateDiff {
 				parent: Some(parent.shortstatehash),
 				added: statediffnew,
 				removed: statediffremoved,
@@ -358,12 +380,15 @@ impl Service {
 	}
 
 	/// Returns the new shortstatehash, and the state diff from the previous
-	/// room state
-	#[tracing::instrument(skip(self, new_state_ids_compressed), level = "debug")]
+	/// room state. A new shortstatehash and its diff are committed atomically,
+	/// so a crash can't leave a shortstatehash without its state.
+	#[tracing::instrument(skip(self, new_state_ids_compressed, _state_lock), level = "debug")]
 	pub async fn save_state(
 		&self,
 		room_id: &RoomId,
 		new_state_ids_compressed: Arc<HashSet<CompressedStateEvent>>,
+		_state_lock: &RoomMutexGuard, /* Take mutex guard to make sure users get the room
+		                               * state mutex */
 	) -> Result<HashSetCompressStateEvent> {
 		let previous_shortstatehash = self
 			.services
@@ -375,10 +400,11 @@ impl Service {
 		let state_hash =
 			utils::calculate_hash(new_state_ids_compressed.iter().map(|bytes| &bytes[..]));
 
+		let mut batch = self.db.db.batch();
 		let (new_shortstatehash, already_existed) = self
 			.services
 			.short
-			.get_or_create_shortstatehash(&state_hash)
+			.get_or_create_shortstatehash(&state_hash, &mut batch)
 			.await;
 
 		if Some(new_shortstatehash) == previous_shortstatehash {
@@ -414,6 +440,7 @@ impl Service {
 
 		if !already_existed {
 			self.save_state_from_diff(
+				&mut batch,
 				new_shortstatehash,
 				statediffnew.clone(),
 				statediffremoved.clone(),
@@ -422,6 +449,8 @@ impl Service {
 			)?;
 		};
 
+		batch.commit()?;
+
 		Ok(HashSetCompressStateEvent {
 			shortstatehash: new_shortstatehash,
 			added: statediffnew,
@@ -478,7 +507,12 @@ impl Service {
 		})
 	}
 
-	fn save_statediff(&self, shortstatehash: ShortStateHash, diff: &StateDiff) {
+	fn save_statediff<'a>(
+		&'a self,
+		batch: &mut Batch<'a>,
+		shortstatehash: ShortStateHash,
+		diff: &StateDiff,
+	) {
 		let mut value = Vec::<u8>::with_capacity(
 			2_usize
 				.saturating_add(diff.added.len())
@@ -499,9 +533,7 @@ impl Service {
 			}
 		}
 
-		self.db
-			.shortstatehash_statediff
-			.insert(&shortstatehash.to_be_bytes(), &value);
+		batch.insert(&self.db.shortstatehash_statediff, &shortstatehash.to_be_bytes(), value);
 	}
 }
 
@@ -0,0 +1,210 @@
+use std::{collections::HashSet, sync::Arc};
+
+use conduwuit::{debug_info, implement, utils::stream::TryIgnore, Result};
+use futures::StreamExt;
+use ruma::{OwnedEventId, OwnedRoomId, RoomId};
+
+use super::{ShortStateInfo, ShortStateInfoVec, StateDiff};
+use crate::rooms::short::ShortStateHash;
+
+/// Outcome of rebuilding a room's compression layers.
+#[derive(Clone, Debug, Default)]
+pub struct Recompression {
+	/// Number of state snapshots rewritten.
+	pub states: usize,
+
+	/// Bytes taken by their diffs before and after.
+	pub bytes_before: usize,
+	pub bytes_after: usize,
+}
+
+/// A room's current state is considered stale once the diffs stacked on top
+/// of its base layer hold this many times as many entries as the state itself.
+const STALE_RATIO: usize = 1;
+
+/// Whether the compression layers under the room's current state have grown
+/// deep enough to be worth rebuilding with [`recompress_room`].
+///
+/// [`recompress_room`]: super::Service::recompress_room
+#[implement(super::Service)]
+pub async fn needs_recompression(&self, room_id: &RoomId) -> Result<bool> {
+	let shortstatehash = self.services.state.get_room_shortstatehash(room_id).await?;
+	let stack = self.load_uncached(shortstatehash).await?;
+	let Some(top) = stack.last() else {
+		return Ok(false);
+	};
+
+	let layered = stack
+		.iter()
+		.skip(1)
+		.map(|layer| layer.added.len().saturating_add(layer.removed.len()))
+		.fold(0_usize, usize::saturating_add);
+
+	Ok(stack.len() > 1 && layered > top.full_state.len().saturating_mul(STALE_RATIO))
+}
+
+/// Recompresses every room whose compression layers are stale. Returns the
+/// rooms rewritten with their outcomes.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn recompress_stale_rooms(&self) -> Result<Vec<(OwnedRoomId, Recompression)>> {
+	let rooms: Vec<OwnedRoomId> = self
+		.services
+		.metadata
+		.iter_ids()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut done = Vec::new();
+	for room_id in rooms {
+		if !self.services.server.running() {
+			break;
+		}
+
+		if self.needs_recompression(&room_id).await.unwrap_or(false) {
+			let recompression = self.recompress_room(&room_id).await?;
+			done.push((room_id, recompression));
+		}
+	}
+
+	Ok(done)
+}
+
+/// Rebuilds the compression layers of every state snapshot in the room's
+/// timeline, in timeline order, as if each had just been saved on top of the
+/// previous one. A snapshot's full state never changes, so snapshots outside
+/// the timeline which are based on a rewritten one stay valid.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn recompress_room(&self, room_id: &RoomId) -> Result<Recompression> {
+	let event_ids: Vec<OwnedEventId> = self
+		.services
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err()
+		.map(|(_, pdu)| pdu.event_id)
+		.collect()
+		.await;
+
+	let mut seen = HashSet::new();
+	let mut hashes = Vec::new();
+	for event_id in &event_ids {
+		if let Ok(shortstatehash) = self
+			.services
+			.state_accessor
+			.pdu_shortstatehash(event_id)
+			.await
+		{
+			if seen.insert(shortstatehash) {
+				hashes.push(shortstatehash);
+			}
+		}
+	}
+
+	let mut recompression = Recompression::default();
+	let mut previous: Option<ShortStateHash> = None;
+	for shortstatehash in hashes {
+		let Some(full_state) = self
+			.load_uncached(shortstatehash)
+			.await?
+			.pop()
+			.map(|info| info.full_state)
+		else {
+			continue;
+		};
+
+		recompression.bytes_before = recompression
+			.bytes_before
+			.saturating_add(self.statediff_len(shortstatehash).await);
+
+		// Only snapshots rewritten earlier in this run are used as parents, so
+		// no snapshot can end up based on itself.
+		let parent_states = match previous {
+			| Some(previous) => self.load_uncached(previous).await?,
+			| None => ShortStateInfoVec::new(),
+		};
+
+		let (added, removed) = match parent_states.last() {
+			| Some(parent) => (
+				full_state.difference(&parent.full_state).copied().collect(),
+				parent.full_state.difference(&full_state).copied().collect(),
+			),
+			| None => ((*full_state).clone(), HashSet::new()),
+		};
+
+		let mut batch = self.db.db.batch();
+		self.save_state_from_diff(
+			&mut batch,
+			shortstatehash,
+			Arc::new(added),
+			Arc::new(removed),
+			2,
+			parent_states,
+		)?;
+		batch.commit()?;
+
+		self.stateinfo_cache.lock()?.remove(&shortstatehash);
+
+		recompression.states = recompression.states.saturating_add(1);
+		recompression.bytes_after = recompression
+			.bytes_after
+			.saturating_add(self.statediff_len(shortstatehash).await);
+
+		previous = Some(shortstatehash);
+	}
+
+	debug_info!(
+		%room_id,
+		states = recompression.states,
+		before = recompression.bytes_before,
+		after = recompression.bytes_after,
+		"Recompressed room state"
+	);
+
+	Ok(recompression)
+}
+
+/// Like `load_shortstatehash_info`, but always reads the layers from the
+/// database, so it sees layers rewritten since they were cached.
+#[implement(super::Service)]
+async fn load_uncached(&self, shortstatehash: ShortStateHash) -> Result<ShortStateInfoVec> {
+	let mut diffs = Vec::new();
+	let mut next = Some(shortstatehash);
+	while let Some(shortstatehash) = next {
+		let diff = self.get_statediff(shortstatehash).await?;
+		next = diff.parent;
+		diffs.push((shortstatehash, diff));
+	}
+
+	let mut stack = ShortStateInfoVec::with_capacity(diffs.len());
+	for (shortstatehash, StateDiff { added, removed, .. }) in diffs.into_iter().rev() {
+		let mut full_state = stack
+			.last()
+			.map(|top: &ShortStateInfo| (*top.full_state).clone())
+			.unwrap_or_default();
+
+		full_state.extend(added.iter().copied());
+		for r in removed.iter() {
+			full_state.remove(r);
+		}
+
+		stack.push(ShortStateInfo {
+			shortstatehash,
+			full_state: Arc::new(full_state),
+			added,
+			removed,
+		});
+	}
+
+	Ok(stack)
+}
+
+#[implement(super::Service)]
+async fn statediff_len(&self, shortstatehash: ShortStateHash) -> usize {
+	self.db
+		.shortstatehash_statediff
+		.get(&shortstatehash.to_be_bytes())
+		.await
+		.map_or(0, |value| value.len())
+}
@@ -0,0 +1,173 @@
+use std::{
+	fmt::Write,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use conduwuit::{
+	debug_warn,
+	utils::{bytes::pretty, math::usize_from_f64},
+	Config, PduEvent, Result,
+};
+use lru_cache::LruCache;
+use ruma::{EventId, OwnedEventId};
+
+/// Two-tier cache of PduEvents by event ID. A small hot tier keeps recently
+/// used events deserialized; a larger warm tier keeps them as compressed
+/// JSON, which is far cheaper in memory than the event and still avoids the
+/// trip to the database.
+///
+/// Both tiers are locked hot first. Removals bump a generation under both
+/// locks, and events read from the database before a removal are not cached
+/// after it, so a fill racing a redaction can't bring back the old event.
+pub(super) struct PduCache {
+	hot: Mutex<LruCache<OwnedEventId, Arc<PduEvent>>>,
+	warm: Mutex<LruCache<OwnedEventId, Box<[u8]>>>,
+	generation: AtomicU64,
+	hot_hits: AtomicU64,
+	warm_hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+const COMPRESSION_LEVEL: i32 = 1;
+
+impl PduCache {
+	pub(super) fn new(config: &Config) -> Result<Self> {
+		let capacity = |capacity: u32| -> Result<usize> {
+			usize_from_f64(f64::from(capacity) * config.cache_capacity_modifier)
+		};
+
+		Ok(Self::with_capacity(
+			capacity(config.pdu_hot_cache_capacity)?,
+			capacity(config.pdu_cache_capacity)?,
+		))
+	}
+
+	pub(super) fn with_capacity(hot: usize, warm: usize) -> Self {
+		Self {
+			hot: LruCache::new(hot).into(),
+			warm: LruCache::new(warm).into(),
+			generation: AtomicU64::new(0),
+			hot_hits: AtomicU64::new(0),
+			warm_hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+		}
+	}
+
+	/// Taken before reading an event from the database, to be given back to
+	/// `insert`.
+	pub(super) fn generation(&self) -> u64 { self.generation.load(Ordering::Acquire) }
+
+	pub(super) fn get(&self, event_id: &EventId) -> Option<Arc<PduEvent>> {
+		let generation = self.generation();
+		if let Some(pdu) = self.hot.lock().expect("locked").get_mut(event_id) {
+			self.hot_hits.fetch_add(1, Ordering::Relaxed);
+			return Some(pdu.clone());
+		}
+
+		let compressed = self
+			.warm
+			.lock()
+			.expect("locked")
+			.get_mut(event_id)
+			.map(|compressed| compressed.to_vec());
+
+		let Some(pdu) = compressed.and_then(|compressed| decompress(&compressed)) else {
+			self.misses.fetch_add(1, Ordering::Relaxed);
+			return None;
+		};
+
+		self.warm_hits.fetch_add(1, Ordering::Relaxed);
+		let pdu = Arc::new(pdu);
+		let mut hot = self.hot.lock().expect("locked");
+		if self.generation() == generation {
+			hot.insert(event_id.to_owned(), pdu.clone());
+		}
+
+		Some(pdu)
+	}
+
+	/// Caches an event read from the database, unless an event was removed
+	/// since `generation` was taken.
+	pub(super) fn insert(&self, pdu: &Arc<PduEvent>, generation: u64) {
+		let compressed = compress(pdu);
+
+		let mut hot = self.hot.lock().expect("locked");
+		let mut warm = self.warm.lock().expect("locked");
+		if self.generation() != generation {
+			return;
+		}
+
+		if let Some(compressed) = compressed {
+			warm.insert(pdu.event_id.clone(), compressed);
+		}
+
+		hot.insert(pdu.event_id.clone(), pdu.clone());
+	}
+
+	pub(super) fn remove(&self, event_id: &EventId) {
+		let mut hot = self.hot.lock().expect("locked");
+		let mut warm = self.warm.lock().expect("locked");
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		hot.remove(event_id);
+		warm.remove(event_id);
+	}
+
+	pub(super) fn clear(&self) {
+		let mut hot = self.hot.lock().expect("locked");
+		let mut warm = self.warm.lock().expect("locked");
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		hot.clear();
+		warm.clear();
+	}
+
+	pub(super) fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let hot = self.hot.lock()?.len();
+		let (warm, warm_bytes) = self.warm.lock()?.iter().fold(
+			(0_usize, 0_usize),
+			|(count, bytes), (_, compressed)| {
+				(count.saturating_add(1), bytes.saturating_add(compressed.len()))
+			},
+		);
+
+		let hot_hits = self.hot_hits.load(Ordering::Relaxed);
+		let warm_hits = self.warm_hits.load(Ordering::Relaxed);
+		let misses = self.misses.load(Ordering::Relaxed);
+		let lookups = hot_hits.saturating_add(warm_hits).saturating_add(misses);
+
+		writeln!(out, "pdu_cache_hot: {hot} (hits: {})", ratio(hot_hits, lookups))?;
+		writeln!(
+			out,
+			"pdu_cache_warm: {warm} ({}) (hits: {})",
+			pretty(warm_bytes),
+			ratio(warm_hits, lookups)
+		)?;
+		writeln!(out, "pdu_cache_misses: {}", ratio(misses, lookups))?;
+
+		Ok(())
+	}
+}
+
+fn compress(pdu: &PduEvent) -> Option<Box<[u8]>> {
+	let json = serde_json::to_vec(pdu).ok()?;
+	zstd::bulk::compress(&json, COMPRESSION_LEVEL)
+		.map(Vec::into_boxed_slice)
+		.map_err(|e| debug_warn!("Failed to compress {}: {e}", pdu.event_id))
+		.ok()
+}
+
+fn decompress(compressed: &[u8]) -> Option<PduEvent> {
+	let json = zstd::stream::decode_all(compressed).ok()?;
+	serde_json::from_slice(&json).ok()
+}
+
+#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+fn ratio(hits: u64, lookups: u64) -> String {
+	if lookups == 0 {
+		return "0".to_owned();
+	}
+
+	format!("{hits} {:.1}%", hits as f64 * 100.0 / lookups as f64)
+}
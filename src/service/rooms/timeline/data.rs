@@ -20,6 +20,8 @@ pub(super) struct Data {
 	pduid_pdu: Arc<Map>,
 	userroomid_highlightcount: Arc<Map>,
 	userroomid_notificationcount: Arc<Map>,
+	userroomthreadid_highlightcount: Arc<Map>,
+	userroomthreadid_notificationcount: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Services,
 }
@@ -39,6 +41,8 @@ impl Data {
 			pduid_pdu: db["pduid_pdu"].clone(),
 			userroomid_highlightcount: db["userroomid_highlightcount"].clone(),
 			userroomid_notificationcount: db["userroomid_notificationcount"].clone(),
+			userroomthreadid_highlightcount: db["userroomthreadid_highlightcount"].clone(),
+			userroomthreadid_notificationcount: db["userroomthreadid_notificationcount"].clone(),
 			db: args.db.clone(),
 			services: Services {
 				short: args.depend::<rooms::short::Service>("rooms::short"),
@@ -184,12 +188,10 @@ impl Data {
 		pdu: &PduEvent,
 		json: &CanonicalJsonObject,
 		count: PduCount,
-	) {
+	) -> Result {
 		debug_assert!(matches!(count, PduCount::Normal(_)), "PduCount not Normal");
 
-		self.pduid_pdu.raw_put(pdu_id, Json(json));
-		self.eventid_pduid.insert(pdu.event_id.as_bytes(), pdu_id);
-		self.eventid_outlierpdu.remove(pdu.event_id.as_bytes());
+		self.insert_pdu(pdu_id, &pdu.event_id, json)
 	}
 
 	pub(super) fn prepend_backfill_pdu(
@@ -197,10 +199,25 @@ impl Data {
 		pdu_id: &RawPduId,
 		event_id: &EventId,
 		json: &CanonicalJsonObject,
-	) {
-		self.pduid_pdu.raw_put(pdu_id, Json(json));
-		self.eventid_pduid.insert(event_id, pdu_id);
-		self.eventid_outlierpdu.remove(event_id);
+	) -> Result {
+		self.insert_pdu(pdu_id, event_id, json)
+	}
+
+	/// Moves the pdu out of the outliers and into the timeline in one atomic
+	/// write, so a crash can't leave it half-indexed.
+	fn insert_pdu(
+		&self,
+		pdu_id: &RawPduId,
+		event_id: &EventId,
+		json: &CanonicalJsonObject,
+	) -> Result {
+		let json = database::serialize_to_vec(Json(json))?;
+
+		let mut batch = self.db.batch();
+		batch.insert(&self.pduid_pdu, pdu_id, json);
+		batch.insert(&self.eventid_pduid, event_id, pdu_id);
+		batch.remove(&self.eventid_outlierpdu, event_id);
+		batch.commit()
 	}
 
 	/// Removes a pdu and creates a new one with the same id.
@@ -273,6 +290,7 @@ impl Data {
 	pub(super) fn increment_notification_counts(
 		&self,
 		room_id: &RoomId,
+		thread_id: Option<&EventId>,
 		notifies: Vec<OwnedUserId>,
 		highlights: Vec<OwnedUserId>,
 	) {
@@ -283,6 +301,12 @@ impl Data {
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
 			increment(&self.userroomid_notificationcount, &userroom_id);
+
+			if let Some(thread_id) = thread_id {
+				userroom_id.push(0xFF);
+				userroom_id.extend_from_slice(thread_id.as_bytes());
+				increment(&self.userroomthreadid_notificationcount, &userroom_id);
+			}
 		}
 
 		for user in highlights {
@@ -290,6 +314,12 @@ impl Data {
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
 			increment(&self.userroomid_highlightcount, &userroom_id);
+
+			if let Some(thread_id) = thread_id {
+				userroom_id.push(0xFF);
+				userroom_id.extend_from_slice(thread_id.as_bytes());
+				increment(&self.userroomthreadid_highlightcount, &userroom_id);
+			}
 		}
 	}
 
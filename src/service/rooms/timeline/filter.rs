@@ -0,0 +1,60 @@
+use conduwuit::{implement, info, pdu::PduBuilder, warn, Err, Result};
+use ruma::{RoomId, UserId};
+use serde_json::{value::to_raw_value, Value as JsonValue};
+
+/// Applies `content_filter` to an event from a local user before it is built,
+/// refusing it or removing its content as `content_filter_action` says.
+#[implement(super::Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub(super) async fn filter_content(
+	&self,
+	pdu_builder: &mut PduBuilder,
+	sender: &UserId,
+	room_id: &RoomId,
+) -> Result {
+	let config = &self.services.server.config;
+	if config.content_filter.is_empty()
+		|| sender == self.services.globals.server_user
+		|| !self.services.globals.user_is_local(sender)
+	{
+		return Ok(());
+	}
+
+	let Ok(content) = serde_json::from_str::<JsonValue>(pdu_builder.content.get()) else {
+		return Ok(());
+	};
+
+	if !any_string(&content, &|string| config.content_filter.is_match(string))
+		|| self.services.users.is_admin(sender).await
+	{
+		return Ok(());
+	}
+
+	let event_type = &pdu_builder.event_type;
+	if config.content_filter_action != "redact" || pdu_builder.state_key.is_some() {
+		info!(%sender, %room_id, %event_type, "Refused event matching content_filter");
+		return Err!(Request(Forbidden("This content is not allowed on this server.")));
+	}
+
+	warn!(%sender, %room_id, %event_type, "Removed content of event matching content_filter");
+	pdu_builder.content = to_raw_value(&serde_json::json!({}))?;
+
+	self.services
+		.admin
+		.send_text(&format!(
+			"Removed the content of a {event_type} event from {sender} in {room_id} which \
+			 matched `content_filter`."
+		))
+		.await;
+
+	Ok(())
+}
+
+fn any_string(value: &JsonValue, matches: &dyn Fn(&str) -> bool) -> bool {
+	match value {
+		| JsonValue::String(string) => matches(string),
+		| JsonValue::Array(values) => values.iter().any(|value| any_string(value, matches)),
+		| JsonValue::Object(object) => object.values().any(|value| any_string(value, matches)),
+		| _ => false,
+	}
+}
@@ -1,4 +1,9 @@
+mod cache;
 mod data;
+mod filter;
+mod profile;
+mod recount;
+mod tests;
 
 use std::{
 	cmp,
@@ -6,6 +11,7 @@ use std::{
 	fmt::Write,
 	iter::once,
 	sync::Arc,
+	time::{Duration, Instant},
 };
 
 use conduwuit::{
@@ -18,7 +24,8 @@ use conduwuit::{
 };
 pub use conduwuit::{PduId, RawPduId};
 use futures::{
-	future, future::ready, pin_mut, Future, FutureExt, Stream, StreamExt, TryStreamExt,
+	future, future::ready, pin_mut, stream::FuturesUnordered, Future, FutureExt, Stream,
+	StreamExt, TryStreamExt,
 };
 use ruma::{
 	api::federation,
@@ -34,20 +41,21 @@ use ruma::{
 		},
 		GlobalAccountDataEventType, StateEventType, TimelineEventType,
 	},
-	push::{Action, Ruleset, Tweak},
+	push::Ruleset,
 	state_res::{self, Event, RoomVersion},
 	uint, CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, OwnedRoomId,
 	OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UserId,
 };
 use serde::Deserialize;
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
+use tokio::time::sleep;
 
-use self::data::Data;
 pub use self::data::PdusIterItem;
+use self::{cache::PduCache, data::Data};
 use crate::{
 	account_data, admin, appservice,
 	appservice::NamespaceRegex,
-	globals, pusher, rooms,
+	globals, media, pusher, rooms,
 	rooms::{short::ShortRoomId, state_compressor::CompressedStateEvent},
 	sending, server_keys, users, Dep,
 };
@@ -77,6 +85,7 @@ struct ExtractBody {
 pub struct Service {
 	services: Services,
 	db: Data,
+	pdu_cache: PduCache,
 	pub mutex_insert: RoomMutexMap,
 }
 
@@ -87,6 +96,8 @@ struct Services {
 	admin: Dep<admin::Service>,
 	alias: Dep<rooms::alias::Service>,
 	globals: Dep<globals::Service>,
+	media: Dep<media::Service>,
+	metadata: Dep<rooms::metadata::Service>,
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
@@ -99,6 +110,7 @@ struct Services {
 	users: Dep<users::Service>,
 	pusher: Dep<pusher::Service>,
 	threads: Dep<rooms::threads::Service>,
+	tombstone: Dep<rooms::tombstone::Service>,
 	search: Dep<rooms::search::Service>,
 	spaces: Dep<rooms::spaces::Service>,
 	event_handler: Dep<rooms::event_handler::Service>,
@@ -117,6 +129,8 @@ impl crate::Service for Service {
 				admin: args.depend::<admin::Service>("admin"),
 				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
 				globals: args.depend::<globals::Service>("globals"),
+				media: args.depend::<media::Service>("media"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
@@ -130,12 +144,14 @@ impl crate::Service for Service {
 				users: args.depend::<users::Service>("users"),
 				pusher: args.depend::<pusher::Service>("pusher"),
 				threads: args.depend::<rooms::threads::Service>("rooms::threads"),
+				tombstone: args.depend::<rooms::tombstone::Service>("rooms::tombstone"),
 				search: args.depend::<rooms::search::Service>("rooms::search"),
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
 				event_handler: args
 					.depend::<rooms::event_handler::Service>("rooms::event_handler"),
 			},
 			db: Data::new(&args),
+			pdu_cache: PduCache::new(&args.server.config)?,
 			mutex_insert: RoomMutexMap::new(),
 		}))
 	}
@@ -144,9 +160,11 @@ impl crate::Service for Service {
 		let mutex_insert = self.mutex_insert.len();
 		writeln!(out, "insert_mutex: {mutex_insert}")?;
 
-		Ok(())
+		self.pdu_cache.memory_usage(out)
 	}
 
+	fn clear_cache(&self) { self.pdu_cache.clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -217,9 +235,31 @@ impl Service {
 	///
 	/// Checks the `eventid_outlierpdu` Tree if not found in the timeline.
 	pub async fn get_pdu(&self, event_id: &EventId) -> Result<PduEvent> {
-		self.db.get_pdu(event_id).await
+		self.get_shared_pdu(event_id)
+			.await
+			.map(Arc::unwrap_or_clone)
+	}
+
+	/// Returns the pdu as held by the cache, without copying it.
+	///
+	/// Checks the `eventid_outlierpdu` Tree if not found in the timeline.
+	pub async fn get_shared_pdu(&self, event_id: &EventId) -> Result<Arc<PduEvent>> {
+		if let Some(pdu) = self.pdu_cache.get(event_id) {
+			return Ok(pdu);
+		}
+
+		let generation = self.pdu_cache.generation();
+		let pdu = Arc::new(self.db.get_pdu(event_id).await?);
+		self.pdu_cache.insert(&pdu, generation);
+
+		Ok(pdu)
 	}
 
+	/// Drops the pdu from the cache after it was changed or removed outside
+	/// of this service.
+	#[inline]
+	pub fn evict_cached_pdu(&self, event_id: &EventId) { self.pdu_cache.remove(event_id); }
+
 	/// Returns the pdu.
 	///
 	/// This does __NOT__ check the outliers `Tree`.
@@ -250,7 +290,10 @@ impl Service {
 		pdu_json: &CanonicalJsonObject,
 		pdu: &PduEvent,
 	) -> Result<()> {
-		self.db.replace_pdu(pdu_id, pdu_json, pdu).await
+		self.db.replace_pdu(pdu_id, pdu_json, pdu).await?;
+		self.pdu_cache.remove(&pdu.event_id);
+
+		Ok(())
 	}
 
 	/// Creates a new persisted data unit and adds it to a room.
@@ -348,13 +391,15 @@ impl Service {
 			.private_read_set(&pdu.room_id, &pdu.sender, count1);
 		self.services
 			.user
-			.reset_notification_counts(&pdu.sender, &pdu.room_id);
+			.reset_notification_counts(&pdu.sender, &pdu.room_id)
+			.await;
 
 		let count2 = PduCount::Normal(self.services.globals.next_count().unwrap());
 		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: count2 }.into();
 
 		// Insert pdu
-		self.db.append_pdu(&pdu_id, pdu, &pdu_json, count2).await;
+		self.db.append_pdu(&pdu_id, pdu, &pdu_json, count2).await?;
+		self.pdu_cache.remove(&pdu.event_id);
 
 		drop(insert_lock);
 
@@ -369,75 +414,59 @@ impl Service {
 
 		let sync_pdu = pdu.to_sync_room_event();
 
-		let mut push_target: HashSet<_> = self
+		let mut push_actions = self
 			.services
-			.state_cache
-			.active_local_users_in_room(&pdu.room_id)
-			// Don't notify the sender of their own events
-			.ready_filter(|user| user != &pdu.sender)
-			.map(ToOwned::to_owned)
-			.collect()
+			.pusher
+			.room_push_actions(&pdu.room_id, &pdu.sender, &power_levels, &sync_pdu)
 			.await;
 
-		let mut notifies = Vec::with_capacity(push_target.len().saturating_add(1));
-		let mut highlights = Vec::with_capacity(push_target.len().saturating_add(1));
-
 		if pdu.kind == TimelineEventType::RoomMember {
 			if let Some(state_key) = &pdu.state_key {
 				let target_user_id = OwnedUserId::parse(state_key)?;
 
-				if self.services.users.is_active_local(&target_user_id).await {
-					push_target.insert(target_user_id);
+				if self.services.users.is_active_local(&target_user_id).await
+					&& !push_actions
+						.iter()
+						.any(|(user, ..)| *user == target_user_id)
+				{
+					let rules_for_user = self.push_rules(&target_user_id).await;
+					let (notify, highlight) = self
+						.services
+						.pusher
+						.notify_and_highlight(
+							&target_user_id,
+							&rules_for_user,
+							&power_levels,
+							&sync_pdu,
+							&pdu.room_id,
+						)
+						.await;
+
+					push_actions.push((target_user_id, notify, highlight));
 				}
 			}
 		}
 
-		for user in &push_target {
-			let rules_for_user = self
-				.services
-				.account_data
-				.get_global(user, GlobalAccountDataEventType::PushRules)
-				.await
-				.map_or_else(
-					|_| Ruleset::server_default(user),
-					|ev: PushRulesEvent| ev.content.global,
-				);
-
-			let mut highlight = false;
-			let mut notify = false;
+		let mut notifies = Vec::with_capacity(push_actions.len());
+		let mut highlights = Vec::with_capacity(push_actions.len());
 
-			for action in self
-				.services
-				.pusher
-				.get_actions(user, &rules_for_user, &power_levels, &sync_pdu, &pdu.room_id)
-				.await
-			{
-				match action {
-					| Action::Notify => notify = true,
-					| Action::SetTweak(Tweak::Highlight(true)) => {
-						highlight = true;
-					},
-					| _ => {},
-				};
-
-				// Break early if both conditions are true
-				if notify && highlight {
-					break;
-				}
-			}
-
-			if notify {
+		for (user, notify, highlight) in &push_actions {
+			if *notify {
 				notifies.push(user.clone());
 			}
 
-			if highlight {
+			if *highlight {
 				highlights.push(user.clone());
 			}
 
 			self.services
 				.pusher
 				.get_pushkeys(user)
-				.ready_for_each(|push_key| {
+				.for_each(|push_key| async move {
+					if !self.services.pusher.is_pusher_enabled(user, push_key).await {
+						return;
+					}
+
 					self.services
 						.sending
 						.send_pdu_push(&pdu_id, user, push_key.to_owned())
@@ -446,8 +475,16 @@ impl Service {
 				.await;
 		}
 
-		self.db
-			.increment_notification_counts(&pdu.room_id, notifies, highlights);
+		// Notifications in a thread are also counted against the thread root so that
+		// threaded read receipts can clear them separately (MSC3771).
+		let thread_id = thread_root(pdu);
+
+		self.db.increment_notification_counts(
+			&pdu.room_id,
+			thread_id.as_deref(),
+			notifies,
+			highlights,
+		);
 
 		match pdu.kind {
 			| TimelineEventType::RoomRedaction => {
@@ -521,6 +558,9 @@ impl Service {
 						.await?;
 				}
 			},
+			| TimelineEventType::RoomTombstone => {
+				self.services.tombstone.handle_tombstone(pdu)?;
+			},
 			| TimelineEventType::RoomMessage => {
 				let content: ExtractBody = pdu.get_content()?;
 				if let Some(body) = content.body {
@@ -536,6 +576,15 @@ impl Service {
 			| _ => {},
 		}
 
+		// Media shared into a quarantined room is quarantined along with it
+		if self.services.metadata.is_quarantined(&pdu.room_id).await {
+			for mxc in media::content_mxcs(&pdu.get_content_as_value()) {
+				if let Ok(mxc) = mxc.as_str().try_into() {
+					self.services.media.quarantine(&mxc, true);
+				}
+			}
+		}
+
 		if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>() {
 			if let Ok(related_pducount) = self.get_pdu_count(&content.relates_to.event_id).await {
 				self.services
@@ -642,6 +691,20 @@ impl Service {
 			timestamp,
 		} = pdu_builder;
 
+		if self.services.globals.user_is_local(sender)
+			&& self.services.users.is_suspended(sender).await
+		{
+			// Suspended users may still leave rooms and redact their events
+			let leaving = event_type == TimelineEventType::RoomMember
+				&& state_key.as_deref() == Some(sender.as_str())
+				&& serde_json::from_str::<RoomMemberEventContent>(content.get())
+					.is_ok_and(|content| content.membership == MembershipState::Leave);
+
+			if !leaving && event_type != TimelineEventType::RoomRedaction {
+				return Err!(Request(UserSuspended("Your account has been suspended.")));
+			}
+		}
+
 		let prev_events: Vec<OwnedEventId> = self
 			.services
 			.state
@@ -748,10 +811,14 @@ impl Service {
 			ready(auth_events.get(&key))
 		};
 
+		let third_party_invite = pdu.third_party_invite_token().and_then(|token| {
+			auth_events.get(&(StateEventType::RoomThirdPartyInvite, token))
+		});
+
 		let auth_check = state_res::auth_check(
 			&room_version,
 			&pdu,
-			None, // TODO: third_party_invite
+			third_party_invite,
 			auth_fetch,
 		)
 		.await
@@ -816,12 +883,19 @@ impl Service {
 	#[tracing::instrument(skip(self, state_lock), level = "debug")]
 	pub async fn build_and_append_pdu(
 		&self,
-		pdu_builder: PduBuilder,
+		mut pdu_builder: PduBuilder,
 		sender: &UserId,
 		room_id: &RoomId,
 		state_lock: &RoomMutexGuard, /* Take mutex guard to make sure users get the room state
 		                              * mutex */
 	) -> Result<OwnedEventId> {
+		self.filter_content(&mut pdu_builder, sender, room_id)
+			.boxed()
+			.await?;
+
+		self.filter_member_profile(&mut pdu_builder, room_id)
+			.await?;
+
 		let (pdu, pdu_json) = self
 			.create_hash_and_sign_event(pdu_builder, sender, room_id, state_lock)
 			.await?;
@@ -932,9 +1006,21 @@ impl Service {
 		// room_servers() and/or the if statement above
 		servers.remove(self.services.globals.server_name());
 
+		// Servers denied by the room's server ACL are not sent its events
+		let servers = servers
+			.iter()
+			.map(AsRef::as_ref)
+			.stream()
+			.filter(|server_name| {
+				self.services
+					.event_handler
+					.acl_check(server_name, &pdu.room_id)
+					.map(|result| result.is_ok())
+			});
+
 		self.services
 			.sending
-			.send_pdu_servers(servers.iter().map(AsRef::as_ref).stream(), &pdu_id)
+			.send_pdu_servers(servers, &pdu_id)
 			.await?;
 
 		Ok(pdu.event_id)
@@ -958,7 +1044,7 @@ impl Service {
 		// fail.
 		self.services
 			.state
-			.set_event_state(&pdu.event_id, &pdu.room_id, state_ids_compressed)
+			.set_event_state(&pdu.event_id, &pdu.room_id, state_ids_compressed, state_lock)
 			.await?;
 
 		if soft_fail {
@@ -1108,7 +1194,7 @@ impl Service {
 		.map(|alias| alias.server_name().to_owned())
 		.stream();
 
-		let mut servers = room_mods
+		let mut servers: Vec<OwnedServerName> = room_mods
 			.stream()
 			.map(ToOwned::to_owned)
 			.chain(canonical_room_alias_server)
@@ -1129,9 +1215,48 @@ impl Service {
 					.await
 					.then_some(server_name)
 			})
-			.boxed();
+			.collect()
+			.await;
+
+		let mut seen = HashSet::new();
+		servers.retain(|server_name| seen.insert(server_name.clone()));
+
+		let pdus = self
+			.fetch_backfill(room_id, &first_pdu.1.event_id, &servers)
+			.await;
+
+		if pdus.is_empty() {
+			info!("No servers could backfill, but backfill was needed in room {room_id}");
+			return Ok(());
+		}
+
+		// Servers answering together return mostly the same events; those we
+		// already have are skipped by backfill_pdu().
+		for (backfill_server, pdu) in pdus {
+			if let Err(e) = self.backfill_pdu(backfill_server, pdu).boxed().await {
+				debug_warn!("Failed to add backfilled pdu in room {room_id}: {e}");
+			}
+		}
+
+		Ok(())
+	}
 
-		while let Some(ref backfill_server) = servers.next().await {
+	/// Requests backfill from the candidate servers in order, hedging a slow
+	/// server by asking the next one after `backfill_hedge_delay_ms` with at
+	/// most `backfill_concurrency` requests in flight. Once a server answers,
+	/// the others in flight get one more hedging delay before their answers
+	/// are merged and the rest are abandoned.
+	async fn fetch_backfill<'a>(
+		&self,
+		room_id: &RoomId,
+		event_id: &EventId,
+		servers: &'a [OwnedServerName],
+	) -> Vec<(&'a ServerName, Box<RawJsonValue>)> {
+		let config = &self.services.server.config;
+		let concurrency = config.backfill_concurrency.max(1);
+		let hedge = Duration::from_millis(config.backfill_hedge_delay_ms);
+
+		let request = |backfill_server: &'a ServerName| async move {
 			info!("Asking {backfill_server} for backfill");
 			let response = self
 				.services
@@ -1140,28 +1265,56 @@ impl Service {
 					backfill_server,
 					federation::backfill::get_backfill::v1::Request {
 						room_id: room_id.to_owned(),
-						v: vec![first_pdu.1.event_id.clone()],
+						v: vec![event_id.to_owned()],
 						limit: uint!(100),
 					},
 				)
 				.await;
-			match response {
-				| Ok(response) => {
-					for pdu in response.pdus {
-						if let Err(e) = self.backfill_pdu(backfill_server, pdu).boxed().await {
-							debug_warn!("Failed to add backfilled pdu in room {room_id}: {e}");
+
+			(backfill_server, response)
+		};
+
+		let mut candidates = servers.iter().map(|server_name| &**server_name);
+		let mut requests = FuturesUnordered::new();
+		let mut answered: Option<Instant> = None;
+		let mut pdus = Vec::new();
+		loop {
+			if answered.is_none() && requests.len() < concurrency {
+				if let Some(backfill_server) = candidates.next() {
+					requests.push(request(backfill_server));
+				}
+			}
+
+			if requests.is_empty() {
+				break;
+			}
+
+			let wait =
+				answered.map_or(hedge, |answered| hedge.saturating_sub(answered.elapsed()));
+
+			tokio::select! {
+				Some((backfill_server, response)) = requests.next() => match response {
+					| Ok(response) => {
+						pdus.extend(response.pdus.into_iter().map(|pdu| (backfill_server, pdu)));
+						if hedge.is_zero() {
+							break;
 						}
-					}
-					return Ok(());
+
+						answered.get_or_insert_with(Instant::now);
+					},
+					| Err(e) => {
+						warn!("{backfill_server} failed to provide backfill for room {room_id}: {e}");
+					},
 				},
-				| Err(e) => {
-					warn!("{backfill_server} failed to provide backfill for room {room_id}: {e}");
+				() = sleep(wait), if !hedge.is_zero() => {
+					if answered.is_some() {
+						break;
+					}
 				},
 			}
 		}
 
-		info!("No servers could backfill, but backfill was needed in room {room_id}");
-		Ok(())
+		pdus
 	}
 
 	#[tracing::instrument(skip(self, pdu), level = "debug")]
@@ -1206,7 +1359,8 @@ impl Service {
 		.into();
 
 		// Insert pdu
-		self.db.prepend_backfill_pdu(&pdu_id, &event_id, &value);
+		self.db.prepend_backfill_pdu(&pdu_id, &event_id, &value)?;
+		self.pdu_cache.remove(&event_id);
 
 		drop(insert_lock);
 
@@ -1295,3 +1449,26 @@ async fn check_pdu_for_admin_room(&self, pdu: &PduEvent, sender: &UserId) -> Res
 
 	Ok(())
 }
+
+/// The user's push rules, or the server defaults when they have none.
+#[implement(Service)]
+async fn push_rules(&self, user_id: &UserId) -> Ruleset {
+	self.services
+		.account_data
+		.get_global(user_id, GlobalAccountDataEventType::PushRules)
+		.await
+		.map_or_else(
+			|_| self.services.pusher.server_default_rules(user_id),
+			|ev: PushRulesEvent| ev.content.global,
+		)
+}
+
+/// The root of the thread the event is part of, if any.
+fn thread_root(pdu: &PduEvent) -> Option<OwnedEventId> {
+	pdu.get_content::<ExtractRelatesTo>()
+		.ok()
+		.and_then(|content| match content.relates_to {
+			| Relation::Thread(thread) => Some(thread.event_id),
+			| _ => None,
+		})
+}
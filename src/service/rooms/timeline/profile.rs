@@ -0,0 +1,101 @@
+use conduwuit::{implement, pdu::PduBuilder, utils::ReadyExt, Result};
+use ruma::{
+	events::TimelineEventType, CanonicalJsonObject, CanonicalJsonValue, RoomId, ServerName,
+	UserId,
+};
+use serde_json::{value::to_raw_value, Value as JsonValue};
+
+/// Member event content fields carrying profile data.
+const PROFILE_FIELDS: [&str; 3] = ["displayname", "avatar_url", "xyz.amorgan.blurhash"];
+
+/// Whether local users' membership events in the room are sent without
+/// profile data under `strip_membership_profile` or
+/// `strip_membership_profile_servers`. `via` is a server the event is sent
+/// through which may not be in the room yet.
+#[implement(super::Service)]
+pub async fn strips_member_profile(&self, room_id: &RoomId, via: Option<&ServerName>) -> bool {
+	let config = &self.services.server.config;
+	if config.strip_membership_profile {
+		return true;
+	}
+
+	let servers = &config.strip_membership_profile_servers;
+	if servers.is_empty() {
+		return false;
+	}
+
+	if via.is_some_and(|via| servers.contains(via))
+		|| room_id
+			.server_name()
+			.is_some_and(|server_name| servers.contains(server_name))
+	{
+		return true;
+	}
+
+	self.services
+		.state_cache
+		.room_servers(room_id)
+		.ready_any(|server_name| servers.contains(server_name))
+		.await
+}
+
+/// Removes profile data from a local user's membership event built from a
+/// remote server's template, when `strips_member_profile` says so.
+#[implement(super::Service)]
+pub async fn strip_member_profile(
+	&self,
+	event: &mut CanonicalJsonObject,
+	room_id: &RoomId,
+	via: &ServerName,
+) {
+	if !self.strips_member_profile(room_id, Some(via)).await {
+		return;
+	}
+
+	if let Some(CanonicalJsonValue::Object(content)) = event.get_mut("content") {
+		for field in PROFILE_FIELDS {
+			content.remove(field);
+		}
+	}
+}
+
+/// Removes profile data from a local user's membership event before it is
+/// built, when `strips_member_profile` says so.
+#[implement(super::Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub(super) async fn filter_member_profile(
+	&self,
+	pdu_builder: &mut PduBuilder,
+	room_id: &RoomId,
+) -> Result {
+	if pdu_builder.event_type != TimelineEventType::RoomMember {
+		return Ok(());
+	}
+
+	let Some(user_id) = pdu_builder
+		.state_key
+		.as_deref()
+		.and_then(|state_key| UserId::parse(state_key).ok())
+	else {
+		return Ok(());
+	};
+
+	if !self.services.globals.user_is_local(&user_id)
+		|| !self.strips_member_profile(room_id, None).await
+	{
+		return Ok(());
+	}
+
+	let Ok(JsonValue::Object(mut content)) = serde_json::from_str(pdu_builder.content.get())
+	else {
+		return Ok(());
+	};
+
+	for field in PROFILE_FIELDS {
+		content.remove(field);
+	}
+
+	pdu_builder.content = to_raw_value(&content)?;
+
+	Ok(())
+}
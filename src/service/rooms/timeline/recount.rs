@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use conduwuit::{implement, pdu::PduCount, utils::stream::TryIgnore, Result};
+use futures::{pin_mut, StreamExt};
+use ruma::{
+	events::{
+		receipt::{ReceiptThread, ReceiptType},
+		room::power_levels::RoomPowerLevelsEventContent,
+		AnySyncEphemeralRoomEvent, StateEventType,
+	},
+	OwnedEventId, RoomId, UserId,
+};
+
+/// Recomputes the user's notification and highlight counts in the room from
+/// the events after their latest read receipt, replacing the stored counts
+/// which may have drifted after purges or redactions. Returns the new counts.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn recount_notifications(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+) -> Result<(u64, u64)> {
+	let power_levels: RoomPowerLevelsEventContent = self
+		.services
+		.state_accessor
+		.room_state_get_content(room_id, &StateEventType::RoomPowerLevels, "")
+		.await
+		.unwrap_or_default();
+
+	let rules = self.push_rules(user_id).await;
+	let since = self.read_position(user_id, room_id).await;
+
+	let mut counts = (0_u64, 0_u64);
+	let mut threads = BTreeMap::<OwnedEventId, (u64, u64)>::new();
+
+	let pdus = self.pdus(None, room_id, Some(since)).ignore_err();
+	pin_mut!(pdus);
+	while let Some((_, pdu)) = pdus.next().await {
+		// Sending an event resets the user's counts like a read receipt does
+		if pdu.sender == user_id {
+			counts = (0, 0);
+			threads.clear();
+			continue;
+		}
+
+		let (notify, highlight) = self
+			.services
+			.pusher
+			.notify_and_highlight(
+				user_id,
+				&rules,
+				&power_levels,
+				&pdu.to_sync_room_event(),
+				room_id,
+			)
+			.await;
+
+		let increment = (u64::from(notify), u64::from(highlight));
+		add_counts(&mut counts, increment);
+		if let Some(thread_id) = super::thread_root(&pdu) {
+			add_counts(threads.entry(thread_id).or_default(), increment);
+		}
+	}
+
+	self.services
+		.user
+		.set_notification_counts(user_id, room_id, counts, &threads)
+		.await;
+
+	Ok(counts)
+}
+
+/// Recomputes the notification counts of every local user in the room.
+/// Returns how many users were recounted.
+#[implement(super::Service)]
+pub async fn recount_room_notifications(&self, room_id: &RoomId) -> Result<usize> {
+	let users: Vec<_> = self
+		.services
+		.state_cache
+		.local_users_in_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for user_id in &users {
+		self.recount_notifications(user_id, room_id).await?;
+	}
+
+	Ok(users.len())
+}
+
+/// The position in the room's timeline up to which the user has read, from
+/// their latest unthreaded or main timeline receipt, public or private.
+#[implement(super::Service)]
+async fn read_position(&self, user_id: &UserId, room_id: &RoomId) -> PduCount {
+	let private = self
+		.services
+		.read_receipt
+		.private_read_get_count(room_id, user_id)
+		.await
+		.map_or_else(|_| PduCount::min(), PduCount::Normal);
+
+	let receipts = self.services.read_receipt.readreceipts_since(room_id, 0);
+
+	pin_mut!(receipts);
+	let mut position = private;
+	while let Some((receipt_user, _, receipt)) = receipts.next().await {
+		if receipt_user != user_id {
+			continue;
+		}
+
+		let Ok(AnySyncEphemeralRoomEvent::Receipt(receipt)) = receipt.deserialize() else {
+			continue;
+		};
+
+		for (event_id, receipts) in receipt.content.0 {
+			let is_read = receipts
+				.get(&ReceiptType::Read)
+				.and_then(|receipts| receipts.get(user_id))
+				.is_some_and(|receipt| !matches!(receipt.thread, ReceiptThread::Thread(_)));
+
+			if !is_read {
+				continue;
+			}
+
+			if let Ok(count) = self.get_pdu_count(&event_id).await {
+				position = position.max(count);
+			}
+		}
+	}
+
+	position
+}
+
+fn add_counts(counts: &mut (u64, u64), (notifications, highlights): (u64, u64)) {
+	counts.0 = counts.0.saturating_add(notifications);
+	counts.1 = counts.1.saturating_add(highlights);
+}
@@ -0,0 +1,78 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+
+use conduwuit::PduEvent;
+use serde_json::json;
+
+use super::cache::PduCache;
+
+fn pdu(body: &str) -> Arc<PduEvent> {
+	let pdu = serde_json::from_value(json!({
+		"event_id": "$event:example.com",
+		"room_id": "!room:example.com",
+		"sender": "@alice:example.com",
+		"origin_server_ts": 1,
+		"type": "m.room.message",
+		"content": { "msgtype": "m.text", "body": body },
+		"prev_events": [],
+		"depth": 1,
+		"auth_events": [],
+		"hashes": { "sha256": "" },
+	}))
+	.expect("valid pdu");
+
+	Arc::new(pdu)
+}
+
+#[test]
+fn hot_hits_share_the_event() {
+	let cache = PduCache::with_capacity(4, 4);
+	let pdu = pdu("hello");
+	cache.insert(&pdu, cache.generation());
+
+	let hit = cache.get(&pdu.event_id).expect("cached");
+	assert!(Arc::ptr_eq(&hit, &pdu));
+}
+
+#[test]
+fn warm_hits_are_promoted() {
+	let cache = PduCache::with_capacity(0, 4);
+	let pdu = pdu("hello");
+	cache.insert(&pdu, cache.generation());
+
+	let hit = cache.get(&pdu.event_id).expect("cached");
+	assert_eq!(hit.content.get(), pdu.content.get());
+}
+
+#[test]
+fn fill_racing_a_removal_is_dropped() {
+	let cache = PduCache::with_capacity(4, 4);
+	let stale = pdu("hello");
+
+	// A reader takes the generation and reads the event from the database...
+	let generation = cache.generation();
+
+	// ...while it is redacted and evicted...
+	cache.remove(&stale.event_id);
+
+	// ...so the old event it read must not be cached afterwards.
+	cache.insert(&stale, generation);
+	assert!(cache.get(&stale.event_id).is_none());
+
+	let redacted = pdu("");
+	cache.insert(&redacted, cache.generation());
+	let hit = cache.get(&redacted.event_id).expect("cached");
+	assert!(Arc::ptr_eq(&hit, &redacted));
+}
+
+#[test]
+fn clear_drops_pending_fills() {
+	let cache = PduCache::with_capacity(4, 4);
+	let pdu = pdu("hello");
+
+	let generation = cache.generation();
+	cache.clear();
+	cache.insert(&pdu, generation);
+	assert!(cache.get(&pdu.event_id).is_none());
+}
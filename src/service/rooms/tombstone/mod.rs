@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, debug_info, utils::stream::IterStream, PduBuilder, PduEvent, Result, Server,
+};
+use futures::StreamExt;
+use ruma::{
+	events::{
+		room::{
+			join_rules::{AllowRule, JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			tombstone::RoomTombstoneEventContent,
+		},
+		GlobalAccountDataEventType, StateEventType,
+	},
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use serde::Deserialize;
+
+use crate::{account_data, globals, rooms, rooms::state::RoomMutexGuard, users, Dep};
+
+/// Joins local users to the replacement room of a room they were in when it
+/// gets a tombstone, if they opted in.
+pub struct Service {
+	channel: (loole::Sender<Tombstone>, loole::Receiver<Tombstone>),
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	globals: Dep<globals::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	users: Dep<users::Service>,
+}
+
+/// The tombstoned room and its replacement.
+type Tombstone = (OwnedRoomId, OwnedRoomId);
+
+/// Global account data overriding `auto_follow_tombstones` for the user.
+#[derive(Deserialize)]
+struct AutoFollowEvent {
+	content: AutoFollow,
+}
+
+#[derive(Deserialize)]
+struct AutoFollow {
+	enabled: bool,
+}
+
+pub const AUTO_FOLLOW_EVENT_TYPE: &str = "org.conduwuit.auto_follow_tombstones";
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			channel: loole::unbounded(),
+			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				globals: args.depend::<globals::Service>("globals"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let receiver = self.channel.1.clone();
+		while let Ok((room_id, replacement_room)) = receiver.recv_async().await {
+			self.follow_tombstone(&room_id, &replacement_room).await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (sender, _) = &self.channel;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Queues following the tombstone for the room's local users. The joins
+	/// happen in the background, outside of the room's state lock.
+	pub fn handle_tombstone(&self, pdu: &PduEvent) -> Result {
+		if pdu.state_key.as_deref() != Some("") {
+			return Ok(());
+		}
+
+		let content: RoomTombstoneEventContent = pdu.get_content()?;
+		if content.replacement_room == pdu.room_id {
+			return Ok(());
+		}
+
+		self.channel
+			.0
+			.send((pdu.room_id.clone(), content.replacement_room))
+			.ok();
+
+		Ok(())
+	}
+
+	/// Whether the user wants to be joined to replacement rooms, from their
+	/// account data or else the server default.
+	pub async fn auto_follow(&self, user_id: &UserId) -> bool {
+		self.services
+			.account_data
+			.get_global(user_id, GlobalAccountDataEventType::from(AUTO_FOLLOW_EVENT_TYPE))
+			.await
+			.map(|event: AutoFollowEvent| event.content.enabled)
+			.unwrap_or(self.services.server.config.auto_follow_tombstones)
+	}
+
+	#[tracing::instrument(skip(self), level = "debug")]
+	async fn follow_tombstone(&self, room_id: &RoomId, replacement_room: &RoomId) {
+		// Held from the start, so a replacement room still being created by a local
+		// upgrade is complete before it's looked at.
+		let state_lock = self.services.state.mutex.lock(replacement_room).await;
+
+		// Only rooms this server takes part in can be joined locally
+		if !self
+			.services
+			.state_cache
+			.server_in_room(self.services.globals.server_name(), replacement_room)
+			.await
+		{
+			debug!("Not following tombstone to a room this server is not in");
+			return;
+		}
+
+		let users: Vec<OwnedUserId> = self
+			.services
+			.state_cache
+			.active_local_users_in_room(room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for user_id in &users {
+			if self
+				.services
+				.state_cache
+				.is_joined(user_id, replacement_room)
+				.await
+			{
+				continue;
+			}
+
+			if !self.auto_follow(user_id).await {
+				continue;
+			}
+
+			match self.join(user_id, replacement_room, &state_lock).await {
+				| Ok(()) => debug_info!(%user_id, "Followed tombstone to {replacement_room}"),
+				| Err(e) => debug!(%user_id, "Could not follow tombstone: {e}"),
+			}
+		}
+	}
+
+	async fn join(
+		&self,
+		user_id: &UserId,
+		room_id: &RoomId,
+		state_lock: &RoomMutexGuard,
+	) -> Result {
+		let restriction_rooms: Vec<OwnedRoomId> = match self
+			.services
+			.state_accessor
+			.room_state_get_content(room_id, &StateEventType::RoomJoinRules, "")
+			.await
+		{
+			| Ok(RoomJoinRulesEventContent {
+				join_rule:
+					JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted),
+			}) => restricted
+				.allow
+				.into_iter()
+				.filter_map(|rule| match rule {
+					| AllowRule::RoomMembership(rule) => Some(rule.room_id),
+					| _ => None,
+				})
+				.collect(),
+			| _ => Vec::new(),
+		};
+
+		let join_authorized_via_users_server = if restriction_rooms
+			.iter()
+			.stream()
+			.any(|restriction_room| {
+				self.services
+					.state_cache
+					.is_joined(user_id, restriction_room)
+			})
+			.await
+		{
+			self.services
+				.state_cache
+				.local_users_in_room(room_id)
+				.filter(|authorizer| {
+					self.services
+						.state_accessor
+						.user_can_invite(room_id, authorizer, user_id, state_lock)
+				})
+				.boxed()
+				.next()
+				.await
+				.map(ToOwned::to_owned)
+		} else {
+			None
+		};
+
+		let content = RoomMemberEventContent {
+			displayname: self.services.users.displayname(user_id).await.ok(),
+			avatar_url: self.services.users.avatar_url(user_id).await.ok(),
+			blurhash: self.services.users.blurhash(user_id).await.ok(),
+			join_authorized_via_users_server,
+			..RoomMemberEventContent::new(MembershipState::Join)
+		};
+
+		self.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder::state(user_id.to_string(), &content),
+				user_id,
+				room_id,
+				state_lock,
+			)
+			.await
+			.map(|_| ())
+	}
+}
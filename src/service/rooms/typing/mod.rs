@@ -1,9 +1,14 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
+use async_trait::async_trait;
 use conduwuit::{
 	debug_info, trace,
 	utils::{self, IterStream},
-	Result, Server,
+	warn, Result, Server,
 };
 use futures::StreamExt;
 use ruma::{
@@ -14,7 +19,10 @@ use ruma::{
 	events::{typing::TypingEventContent, EphemeralRoomEvent, SyncEphemeralRoomEvent},
 	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
-use tokio::sync::{broadcast, RwLock};
+use tokio::{
+	sync::{broadcast, Notify, RwLock},
+	time::{interval, MissedTickBehavior},
+};
 
 use crate::{globals, sending, sending::EduBuf, users, Dep};
 
@@ -26,6 +34,12 @@ pub struct Service {
 	/// timestamp of the last change to typing users
 	pub last_typing_update: RwLock<BTreeMap<OwnedRoomId, u64>>,
 	pub typing_update_sender: broadcast::Sender<OwnedRoomId>,
+	/// rooms with typing changes held back by `typing_sync_debounce_ms`
+	pending: Mutex<BTreeSet<OwnedRoomId>>,
+	/// typing users last reported in each room under
+	/// `typing_sync_debounce_ms`
+	reported: Mutex<BTreeMap<OwnedRoomId, Vec<OwnedUserId>>>,
+	interrupt: Notify,
 }
 
 struct Services {
@@ -34,6 +48,7 @@ struct Services {
 	users: Dep<users::Service>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
@@ -46,9 +61,35 @@ impl crate::Service for Service {
 			typing: RwLock::new(BTreeMap::new()),
 			last_typing_update: RwLock::new(BTreeMap::new()),
 			typing_update_sender: broadcast::channel(100).0,
+			pending: Mutex::default(),
+			reported: Mutex::default(),
+			interrupt: Notify::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		let Some(debounce) = self.debounce() else {
+			return Ok(());
+		};
+
+		let mut i = interval(debounce);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			if let Err(e) = self.report_pending().await {
+				warn!("Failed to report typing changes: {e}");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -70,17 +111,7 @@ impl Service {
 			.or_default()
 			.insert(user_id.to_owned(), timeout);
 
-		self.last_typing_update
-			.write()
-			.await
-			.insert(room_id.to_owned(), self.services.globals.next_count()?);
-
-		if self.typing_update_sender.send(room_id.to_owned()).is_err() {
-			trace!("receiver found what it was looking for and is no longer interested");
-		}
-
-		// update appservices
-		self.appservice_send(room_id).await?;
+		self.typing_changed(room_id).await?;
 
 		// update federation
 		if self.services.globals.user_is_local(user_id) {
@@ -101,6 +132,64 @@ impl Service {
 			.or_default()
 			.remove(user_id);
 
+		self.typing_changed(room_id).await?;
+
+		// update federation
+		if self.services.globals.user_is_local(user_id) {
+			self.federation_send(room_id, user_id, false).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Reports a change of typing users to clients and appservices, right
+	/// away or with the room's other changes after `typing_sync_debounce_ms`.
+	async fn typing_changed(&self, room_id: &RoomId) -> Result<()> {
+		if self.debounce().is_some() {
+			self.pending.lock()?.insert(room_id.to_owned());
+			return Ok(());
+		}
+
+		self.report(room_id).await
+	}
+
+	/// Reports the held back changes of each room whose typing users differ
+	/// from the ones last reported.
+	async fn report_pending(&self) -> Result<()> {
+		let pending = std::mem::take(&mut *self.pending.lock()?);
+		for room_id in pending {
+			let user_ids: Vec<_> = self
+				.typing
+				.read()
+				.await
+				.get(&room_id)
+				.map(|room| room.keys().cloned().collect())
+				.unwrap_or_default();
+
+			{
+				let mut reported = self.reported.lock()?;
+				if reported
+					.get(&room_id)
+					.map_or(user_ids.is_empty(), |last| *last == user_ids)
+				{
+					continue;
+				}
+
+				if user_ids.is_empty() {
+					reported.remove(&room_id);
+				} else {
+					reported.insert(room_id.clone(), user_ids);
+				}
+			}
+
+			self.report(&room_id).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn report(&self, room_id: &RoomId) -> Result<()> {
+		// update clients
 		self.last_typing_update
 			.write()
 			.await
@@ -111,14 +200,12 @@ impl Service {
 		}
 
 		// update appservices
-		self.appservice_send(room_id).await?;
-
-		// update federation
-		if self.services.globals.user_is_local(user_id) {
-			self.federation_send(room_id, user_id, false).await?;
-		}
+		self.appservice_send(room_id).await
+	}
 
-		Ok(())
+	fn debounce(&self) -> Option<Duration> {
+		let debounce = Duration::from_millis(self.server.config.typing_sync_debounce_ms);
+		(!debounce.is_zero()).then_some(debounce)
 	}
 
 	pub async fn wait_for_update(&self, room_id: &RoomId) {
@@ -151,25 +238,15 @@ impl Service {
 		if removable.is_empty() {
 			return Ok(());
 		}
-		let typing = &mut self.typing.write().await;
+		let mut typing = self.typing.write().await;
 		let room = typing.entry(room_id.to_owned()).or_default();
 		for user in &removable {
 			debug_info!("typing timeout {user:?} in {room_id:?}");
 			room.remove(user);
 		}
 
-		// update clients
-		self.last_typing_update
-			.write()
-			.await
-			.insert(room_id.to_owned(), self.services.globals.next_count()?);
-
-		if self.typing_update_sender.send(room_id.to_owned()).is_err() {
-			trace!("receiver found what it was looking for and is no longer interested");
-		}
-
-		// update appservices
-		self.appservice_send(room_id).await?;
+		drop(typing);
+		self.typing_changed(room_id).await?;
 
 		// update federation
 		for user in &removable {
@@ -258,7 +335,7 @@ impl Service {
 		let mut buf = EduBuf::new();
 		serde_json::to_writer(&mut buf, &edu).expect("Serialized Edu::Typing");
 
-		self.services.sending.send_edu_room(room_id, buf).await?;
+		self.services.sending.send_typing_room(room_id, buf).await?;
 
 		Ok(())
 	}
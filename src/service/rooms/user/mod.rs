@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
-use conduwuit::{implement, Result};
-use database::{Database, Deserialized, Map};
-use ruma::{RoomId, UserId};
+use conduwuit::{
+	implement,
+	utils::stream::{ReadyExt, TryIgnore},
+	Result,
+};
+use database::{Database, Deserialized, Ignore, Interfix, Map};
+use ruma::{events::receipt::ReceiptThread, EventId, OwnedEventId, RoomId, UserId};
 
 use crate::{globals, rooms, rooms::short::ShortStateHash, Dep};
 
@@ -15,6 +19,8 @@ struct Data {
 	db: Arc<Database>,
 	userroomid_notificationcount: Arc<Map>,
 	userroomid_highlightcount: Arc<Map>,
+	userroomthreadid_notificationcount: Arc<Map>,
+	userroomthreadid_highlightcount: Arc<Map>,
 	roomuserid_lastnotificationread: Arc<Map>,
 	roomsynctoken_shortstatehash: Arc<Map>,
 }
@@ -31,6 +37,10 @@ impl crate::Service for Service {
 				db: args.db.clone(),
 				userroomid_notificationcount: args.db["userroomid_notificationcount"].clone(),
 				userroomid_highlightcount: args.db["userroomid_highlightcount"].clone(),
+				userroomthreadid_notificationcount: args.db["userroomthreadid_notificationcount"]
+					.clone(),
+				userroomthreadid_highlightcount: args.db["userroomthreadid_highlightcount"]
+					.clone(),
 				roomuserid_lastnotificationread: args.db["userroomid_highlightcount"].clone(),
 				roomsynctoken_shortstatehash: args.db["roomsynctoken_shortstatehash"].clone(),
 			},
@@ -46,11 +56,151 @@ impl crate::Service for Service {
 }
 
 #[implement(Service)]
-pub fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
+pub async fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
 	let userroom_id = (user_id, room_id);
 	self.db.userroomid_highlightcount.put(userroom_id, 0_u64);
 	self.db.userroomid_notificationcount.put(userroom_id, 0_u64);
 
+	self.clear_thread_notification_counts(user_id, room_id)
+		.await;
+	self.mark_notifications_read(user_id, room_id);
+}
+
+/// Clears the notification counts covered by a read receipt for the given
+/// thread (MSC3771). Unthreaded receipts clear the whole room; receipts for
+/// the main timeline leave only the counts of unread threads; receipts for
+/// a thread clear only that thread's share of the room counts.
+#[implement(Service)]
+pub async fn reset_notification_counts_for(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	thread: &ReceiptThread,
+) {
+	match thread {
+		| ReceiptThread::Main => {
+			let notifications = self
+				.thread_counts_sum(&self.db.userroomthreadid_notificationcount, user_id, room_id)
+				.await;
+			let highlights = self
+				.thread_counts_sum(&self.db.userroomthreadid_highlightcount, user_id, room_id)
+				.await;
+
+			let userroom_id = (user_id, room_id);
+			self.db
+				.userroomid_notificationcount
+				.put(userroom_id, notifications);
+			self.db
+				.userroomid_highlightcount
+				.put(userroom_id, highlights);
+			self.mark_notifications_read(user_id, room_id);
+		},
+		| ReceiptThread::Thread(thread_id) => {
+			self.reset_thread_count(
+				&self.db.userroomthreadid_notificationcount,
+				&self.db.userroomid_notificationcount,
+				(user_id, room_id, thread_id),
+			)
+			.await;
+			self.reset_thread_count(
+				&self.db.userroomthreadid_highlightcount,
+				&self.db.userroomid_highlightcount,
+				(user_id, room_id, thread_id),
+			)
+			.await;
+			self.mark_notifications_read(user_id, room_id);
+		},
+		| _ => self.reset_notification_counts(user_id, room_id).await,
+	}
+}
+
+/// Replaces the user's notification and highlight counts in the room, and
+/// those of each thread, with counts recomputed from the timeline.
+#[implement(Service)]
+pub async fn set_notification_counts(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	(notifications, highlights): (u64, u64),
+	threads: &BTreeMap<OwnedEventId, (u64, u64)>,
+) {
+	self.clear_thread_notification_counts(user_id, room_id)
+		.await;
+
+	let userroom_id = (user_id, room_id);
+	self.db
+		.userroomid_notificationcount
+		.put(userroom_id, notifications);
+	self.db
+		.userroomid_highlightcount
+		.put(userroom_id, highlights);
+
+	for (thread_id, (notifications, highlights)) in threads {
+		let userroomthread_id = (user_id, room_id, thread_id);
+		if *notifications > 0 {
+			self.db
+				.userroomthreadid_notificationcount
+				.put(userroomthread_id, *notifications);
+		}
+
+		if *highlights > 0 {
+			self.db
+				.userroomthreadid_highlightcount
+				.put(userroomthread_id, *highlights);
+		}
+	}
+}
+
+#[implement(Service)]
+async fn reset_thread_count(
+	&self,
+	thread_map: &Arc<Map>,
+	room_map: &Arc<Map>,
+	(user_id, room_id, thread_id): (&UserId, &RoomId, &EventId),
+) {
+	let userroomthread_id = (user_id, room_id, thread_id);
+	let thread_count: u64 = thread_map
+		.qry(&userroomthread_id)
+		.await
+		.deserialized()
+		.unwrap_or(0);
+
+	if thread_count == 0 {
+		return;
+	}
+
+	let userroom_id = (user_id, room_id);
+	let room_count: u64 = room_map.qry(&userroom_id).await.deserialized().unwrap_or(0);
+
+	room_map.put(userroom_id, room_count.saturating_sub(thread_count));
+	thread_map.del(userroomthread_id);
+}
+
+#[implement(Service)]
+async fn thread_counts_sum(&self, map: &Arc<Map>, user_id: &UserId, room_id: &RoomId) -> u64 {
+	let prefix = (user_id, room_id, Interfix);
+	map.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_fold(0_u64, |sum, (_, count): (Ignore, u64)| sum.saturating_add(count))
+		.await
+}
+
+#[implement(Service)]
+async fn clear_thread_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
+	let prefix = (user_id, room_id, Interfix);
+	for map in [
+		&self.db.userroomthreadid_notificationcount,
+		&self.db.userroomthreadid_highlightcount,
+	] {
+		map.keys_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_for_each(|key| map.del(key))
+			.await;
+	}
+}
+
+#[implement(Service)]
+fn mark_notifications_read(&self, user_id: &UserId, room_id: &RoomId) {
 	let roomuser_id = (room_id, user_id);
 	let count = self.services.globals.next_count().unwrap();
 	self.db
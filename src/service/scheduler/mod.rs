@@ -0,0 +1,193 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	config::ScheduledTask, debug, error, info, utils::time::cron::Schedule, warn, Result, Server,
+};
+use tokio::{sync::Notify, time::sleep};
+
+use crate::{admin, Dep};
+
+pub struct Service {
+	interrupt: Notify,
+	tasks: Mutex<Vec<Task>>,
+	services: Services,
+}
+
+struct Services {
+	admin: Dep<admin::Service>,
+	server: Arc<Server>,
+}
+
+/// A configured scheduled task and how it last went.
+#[derive(Clone, Debug)]
+pub struct Task {
+	pub schedule: String,
+	pub admin_command: String,
+	pub next_run: Option<SystemTime>,
+	pub last_run: Option<LastRun>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LastRun {
+	pub started: SystemTime,
+	pub elapsed: Duration,
+	pub success: bool,
+	pub output: String,
+}
+
+/// Run on `database_backup_schedule`, after the configured tasks.
+const BACKUP_COMMAND: &str = "db backup create";
+
+/// Run on `state_recompression_schedule`, after the backups.
+const RECOMPRESS_COMMAND: &str = "db recompress-state";
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			tasks: Mutex::default(),
+			services: Services {
+				admin: args.depend::<admin::Service>("admin"),
+				server: args.server.clone(),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "scheduler", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let config = &self.services.server.config;
+		let backup = config
+			.database_backup_schedule
+			.as_ref()
+			.map(|schedule| ScheduledTask {
+				schedule: schedule.clone(),
+				admin_command: BACKUP_COMMAND.to_owned(),
+			});
+
+		let recompress = config
+			.state_recompression_schedule
+			.as_ref()
+			.map(|schedule| ScheduledTask {
+				schedule: schedule.clone(),
+				admin_command: RECOMPRESS_COMMAND.to_owned(),
+			});
+
+		let configured: Vec<ScheduledTask> = config
+			.scheduled_tasks
+			.iter()
+			.cloned()
+			.chain(backup)
+			.chain(recompress)
+			.collect();
+
+		let schedules: Vec<Schedule> = configured
+			.iter()
+			.map(|task| task.schedule.parse())
+			.collect::<Result<_>>()?;
+
+		let now = SystemTime::now();
+		*self.tasks.lock().expect("locked") = configured
+			.iter()
+			.zip(&schedules)
+			.map(|(task, schedule)| Task {
+				schedule: task.schedule.clone(),
+				admin_command: task.admin_command.clone(),
+				next_run: schedule.next_after(now),
+				last_run: None,
+			})
+			.collect();
+
+		if schedules.is_empty() {
+			debug!("No scheduled tasks configured");
+			return Ok(());
+		}
+
+		if config.rocksdb_secondary || config.rocksdb_read_only {
+			debug!("Scheduled tasks only run on the primary");
+			return Ok(());
+		}
+
+		loop {
+			let next = self
+				.tasks
+				.lock()
+				.expect("locked")
+				.iter()
+				.filter_map(|task| task.next_run)
+				.min();
+
+			let Some(next) = next else {
+				warn!("None of the scheduled tasks will run again");
+				break;
+			};
+
+			let wait = next.duration_since(SystemTime::now()).unwrap_or_default();
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = sleep(wait) => (),
+			}
+
+			for (i, schedule) in schedules.iter().enumerate() {
+				let due = self.tasks.lock().expect("locked")[i]
+					.next_run
+					.is_some_and(|next_run| next_run <= SystemTime::now());
+
+				if due {
+					self.run(i).await;
+					self.tasks.lock().expect("locked")[i].next_run =
+						schedule.next_after(SystemTime::now());
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// The configured tasks with their next and last runs, in config order.
+	#[must_use]
+	pub fn tasks(&self) -> Vec<Task> { self.tasks.lock().expect("locked").clone() }
+
+	#[tracing::instrument(skip(self), level = "debug")]
+	async fn run(&self, i: usize) {
+		let command = self.tasks.lock().expect("locked")[i].admin_command.clone();
+
+		info!("Running scheduled task #{i}: {command:?}");
+
+		let started = SystemTime::now();
+		let timer = Instant::now();
+		let (success, output) = match self.services.admin.command_in_place(command, None).await {
+			| Ok(output) => (
+				true,
+				output
+					.map(|output| output.body().to_owned())
+					.unwrap_or_default(),
+			),
+			| Err(output) => (false, output.body().to_owned()),
+		};
+
+		if success {
+			debug!("Scheduled task #{i} completed:\n{output}");
+		} else {
+			error!("Scheduled task #{i} failed:\n{output}");
+		}
+
+		self.tasks.lock().expect("locked")[i].last_run = Some(LastRun {
+			started,
+			elapsed: timer.elapsed(),
+			success,
+			output,
+		});
+	}
+}
@@ -0,0 +1,91 @@
+//! Where EDU selection for each destination resumes, so rooms left out by the
+//! selection limits are served first next time.
+
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Mutex,
+	},
+};
+
+use conduwuit::implement;
+use futures::{FutureExt, StreamExt};
+use ruma::{OwnedRoomId, OwnedServerName, ServerName};
+
+#[derive(Default)]
+pub(super) struct Cursors {
+	rooms: Mutex<HashMap<OwnedServerName, OwnedRoomId>>,
+}
+
+/// The rooms shared with a destination, starting from its cursor.
+pub(super) struct EduRooms {
+	rooms: Vec<OwnedRoomId>,
+
+	/// Index of the first room updates were left out in.
+	deferred: AtomicUsize,
+}
+
+/// The rooms shared with `server_name` in the order EDUs are selected from
+/// them.
+#[implement(super::Service)]
+pub(super) async fn edu_rooms(&self, server_name: &ServerName) -> EduRooms {
+	let mut rooms: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.server_rooms(server_name)
+		.filter(|room_id| {
+			self.services
+				.event_handler
+				.acl_check(server_name, room_id)
+				.map(|result| result.is_ok())
+		})
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let cursor = self
+		.cursors
+		.rooms
+		.lock()
+		.expect("locked")
+		.get(server_name)
+		.cloned();
+
+	if let Some(cursor) = cursor {
+		let start = rooms.partition_point(|room_id| *room_id < cursor);
+		rooms.rotate_left(start);
+	}
+
+	EduRooms {
+		rooms,
+		deferred: AtomicUsize::new(usize::MAX),
+	}
+}
+
+/// Sets the room EDU selection for `server_name` resumes from, or clears it
+/// when everything was selected.
+#[implement(super::Service)]
+pub(super) fn set_edu_cursor(&self, server_name: &ServerName, cursor: Option<OwnedRoomId>) {
+	let mut rooms = self.cursors.rooms.lock().expect("locked");
+	match cursor {
+		| Some(cursor) => _ = rooms.insert(server_name.to_owned(), cursor),
+		| None => _ = rooms.remove(server_name),
+	}
+}
+
+impl EduRooms {
+	#[inline]
+	pub(super) fn iter(&self) -> impl Iterator<Item = &OwnedRoomId> + '_ { self.rooms.iter() }
+
+	/// Notes that updates in the `i`th room were left out.
+	#[inline]
+	pub(super) fn defer(&self, i: usize) { self.deferred.fetch_min(i, Ordering::Relaxed); }
+
+	/// The first room updates were left out in.
+	pub(super) fn deferred(&self) -> Option<OwnedRoomId> {
+		self.rooms
+			.get(self.deferred.load(Ordering::Relaxed))
+			.cloned()
+	}
+}
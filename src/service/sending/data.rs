@@ -5,9 +5,10 @@ use conduwuit::{
 	utils::{stream::TryIgnore, ReadyExt},
 	Error, Result,
 };
-use database::{Database, Deserialized, Map};
+use database::{Database, Deserialized, Json, Map};
 use futures::{Stream, StreamExt};
 use ruma::{OwnedServerName, ServerName, UserId};
+use serde::{Deserialize, Serialize};
 
 use super::{Destination, SendingEvent};
 use crate::{globals, Dep};
@@ -17,10 +18,23 @@ pub(super) type SendingItem = (Key, SendingEvent);
 pub(super) type QueueItem = (Key, SendingEvent);
 pub(super) type Key = Vec<u8>;
 
+/// Transactions to an appservice which failed in a row, kept across restarts
+/// so the backoff carries on where it left off.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AppserviceFailures {
+	pub tries: u32,
+	/// Milliseconds since the unix epoch
+	pub last_failure: u64,
+	pub last_error: String,
+}
+
 pub struct Data {
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
 	servername_educount: Arc<Map>,
+	appserviceid_devicelistcount: Arc<Map>,
+	appserviceid_txnfailures: Arc<Map>,
+	appserviceid_txnsuccess: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Services,
 }
@@ -36,6 +50,9 @@ impl Data {
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
 			servername_educount: db["servername_educount"].clone(),
+			appserviceid_devicelistcount: db["appserviceid_devicelistcount"].clone(),
+			appserviceid_txnfailures: db["appserviceid_txnfailures"].clone(),
+			appserviceid_txnsuccess: db["appserviceid_txnsuccess"].clone(),
 			db: args.db.clone(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
@@ -181,6 +198,63 @@ impl Data {
 			.deserialized()
 			.unwrap_or(0)
 	}
+
+	pub(super) fn set_appservice_devicelist_count(&self, appservice_id: &str, count: u64) {
+		self.appserviceid_devicelistcount
+			.raw_put(appservice_id, count);
+	}
+
+	/// The position up to which device list changes were sent to the
+	/// appservice.
+	pub async fn get_appservice_devicelist_count(&self, appservice_id: &str) -> u64 {
+		self.appserviceid_devicelistcount
+			.get(appservice_id)
+			.await
+			.deserialized()
+			.unwrap_or(0)
+	}
+
+	pub(super) fn set_appservice_failures(
+		&self,
+		appservice_id: &str,
+		failures: &AppserviceFailures,
+	) {
+		self.appserviceid_txnfailures
+			.raw_put(appservice_id, Json(failures));
+	}
+
+	pub(super) fn del_appservice_failures(&self, appservice_id: &str) {
+		self.appserviceid_txnfailures.del(appservice_id);
+	}
+
+	pub async fn get_appservice_failures(
+		&self,
+		appservice_id: &str,
+	) -> Option<AppserviceFailures> {
+		self.appserviceid_txnfailures
+			.get(appservice_id)
+			.await
+			.deserialized()
+			.ok()
+	}
+
+	pub(super) fn set_appservice_last_success(&self, appservice_id: &str, millis: u64) {
+		self.appserviceid_txnsuccess.raw_put(appservice_id, millis);
+	}
+
+	pub(super) fn del_appservice_last_success(&self, appservice_id: &str) {
+		self.appserviceid_txnsuccess.del(appservice_id);
+	}
+
+	/// When a transaction was last delivered to the appservice, in
+	/// milliseconds since the unix epoch.
+	pub async fn get_appservice_last_success(&self, appservice_id: &str) -> Option<u64> {
+		self.appserviceid_txnsuccess
+			.get(appservice_id)
+			.await
+			.deserialized()
+			.ok()
+	}
 }
 
 fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, SendingEvent)> {
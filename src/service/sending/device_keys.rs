@@ -0,0 +1,373 @@
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	sync::{Arc, Mutex},
+};
+
+use conduwuit::{
+	utils::{stream::IterStream, ReadyExt},
+	Result,
+};
+use futures::StreamExt;
+use ruma::{
+	api::appservice::event::push_events::v1::DeviceLists, OneTimeKeyAlgorithm, OwnedDeviceId,
+	OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
+};
+
+use super::{Destination, Msg, SendingEvent, Service};
+use crate::appservice::RegistrationInfo;
+
+/// One-time key counts of each device of each of the appservice's users.
+pub(super) type OneTimeKeyCounts =
+	BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, BTreeMap<OneTimeKeyAlgorithm, UInt>>>;
+
+/// Rooms whose membership or encryption an appservice transaction changed,
+/// with the users whose membership changed in each.
+pub(super) type RoomChanges = BTreeMap<OwnedRoomId, BTreeSet<OwnedUserId>>;
+
+/// What is kept in memory about the appservices receiving device keys
+/// (MSC3202), so a transaction doesn't have to scan every user and room.
+#[derive(Default)]
+pub(super) struct DeviceKeys {
+	/// The users and rooms tracked for each appservice; built on its first
+	/// transaction and then kept up to date from the membership events sent to
+	/// it.
+	tracked: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Tracked>>>>>,
+
+	/// Users of each appservice whose one-time keys were claimed since its
+	/// previous transaction.
+	claimed: Mutex<HashMap<String, BTreeSet<OwnedUserId>>>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Tracked {
+	/// The appservice's local users.
+	pub(super) users: BTreeSet<OwnedUserId>,
+
+	/// Encrypted rooms which any of the appservice's users is joined to.
+	pub(super) rooms: BTreeSet<OwnedRoomId>,
+}
+
+impl DeviceKeys {
+	/// Records that one of the user's one-time keys was claimed.
+	pub(super) fn claim(&self, appservice_id: &str, user_id: &UserId) {
+		self.claimed
+			.lock()
+			.expect("locked")
+			.entry(appservice_id.to_owned())
+			.or_default()
+			.insert(user_id.to_owned());
+	}
+
+	/// Takes the users of the appservice whose one-time keys were claimed since
+	/// its previous transaction.
+	pub(super) fn take_claimed(&self, appservice_id: &str) -> BTreeSet<OwnedUserId> {
+		self.claimed
+			.lock()
+			.expect("locked")
+			.remove(appservice_id)
+			.unwrap_or_default()
+	}
+
+	/// Puts back the users taken for a transaction which failed, so their
+	/// counts are sent with the next one.
+	pub(super) fn restore_claimed(&self, appservice_id: &str, users: BTreeSet<OwnedUserId>) {
+		if users.is_empty() {
+			return;
+		}
+
+		self.claimed
+			.lock()
+			.expect("locked")
+			.entry(appservice_id.to_owned())
+			.or_default()
+			.extend(users);
+	}
+}
+
+impl Tracked {
+	/// Starts or stops tracking the room, returning whether it was tracked
+	/// before.
+	pub(super) fn set_room(&mut self, room_id: &RoomId, shared: bool) -> bool {
+		if shared {
+			!self.rooms.insert(room_id.to_owned())
+		} else {
+			self.rooms.remove(room_id)
+		}
+	}
+}
+
+impl Service {
+	/// The users whose device lists changed since the appservice's previous
+	/// transaction, out of its own users and those sharing an encrypted room
+	/// with them, and those who no longer share any (MSC3202). Also returns
+	/// the position they were selected up to, which is stored once the
+	/// transaction is delivered.
+	#[tracing::instrument(skip_all, fields(id = %appservice.registration.id), level = "debug")]
+	pub(super) async fn appservice_device_lists(
+		&self,
+		appservice: &RegistrationInfo,
+		room_changes: &RoomChanges,
+	) -> (DeviceLists, u64) {
+		let since = self
+			.db
+			.get_appservice_devicelist_count(&appservice.registration.id)
+			.await;
+
+		let until = self.services.globals.current_count().unwrap_or(since);
+
+		let entry = self.tracked_appservice(&appservice.registration.id);
+		let mut guard = entry.lock().await;
+		if guard.is_none() {
+			*guard = Some(self.track_appservice(appservice).await);
+		}
+
+		let tracked = guard.as_mut().expect("tracked appservice");
+		let (mut changed, left) = self.update_tracked(appservice, tracked, room_changes).await;
+
+		for user_id in &tracked.users {
+			self.services
+				.users
+				.keys_changed(user_id, since, Some(until))
+				.ready_for_each(|user_id| {
+					changed.insert(user_id.to_owned());
+				})
+				.await;
+		}
+
+		for room_id in &tracked.rooms {
+			self.services
+				.users
+				.room_keys_changed(room_id, since, Some(until))
+				.ready_for_each(|(user_id, _)| {
+					changed.insert(user_id.to_owned());
+				})
+				.await;
+		}
+
+		(device_lists(changed, left), until)
+	}
+
+	/// Forgets the users and rooms tracked for the appservice, e.g. after its
+	/// namespaces changed; they are gathered again on its next transaction.
+	pub fn reset_appservice_device_lists(&self, appservice_id: &str) {
+		self.device_keys
+			.tracked
+			.lock()
+			.expect("locked")
+			.remove(appservice_id);
+	}
+
+	/// Sends the one-time key counts of the user's devices to the appservices
+	/// receiving device keys whose namespaces cover the user, after one of
+	/// their keys was claimed.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn appservice_keys_claimed(&self, user_id: &UserId) -> Result {
+		let appservices: Vec<String> = self
+			.services
+			.appservice
+			.read()
+			.await
+			.values()
+			.filter(|appservice| appservice.receive_device_keys)
+			.filter(|appservice| appservice.is_user_match(user_id))
+			.map(|appservice| appservice.registration.id.clone())
+			.collect();
+
+		for id in appservices {
+			self.device_keys.claim(&id, user_id);
+			self.dispatch(Msg {
+				dest: Destination::Appservice(id),
+				event: SendingEvent::Flush,
+				queue_id: Vec::new(),
+			})?;
+		}
+
+		Ok(())
+	}
+
+	/// The one-time key counts of every device of the users.
+	pub(super) async fn appservice_one_time_key_counts(
+		&self,
+		users: &BTreeSet<OwnedUserId>,
+	) -> OneTimeKeyCounts {
+		users
+			.iter()
+			.stream()
+			.then(|user_id| async move {
+				let devices: Vec<OwnedDeviceId> = self
+					.services
+					.users
+					.all_device_ids(user_id)
+					.map(ToOwned::to_owned)
+					.collect()
+					.await;
+
+				let mut counts = BTreeMap::new();
+				for device_id in devices {
+					let count = self
+						.services
+						.users
+						.count_one_time_keys(user_id, &device_id)
+						.await;
+
+					counts.insert(device_id, count);
+				}
+
+				(user_id.clone(), counts)
+			})
+			.collect()
+			.await
+	}
+
+	fn tracked_appservice(
+		&self,
+		appservice_id: &str,
+	) -> Arc<tokio::sync::Mutex<Option<Tracked>>> {
+		self.device_keys
+			.tracked
+			.lock()
+			.expect("locked")
+			.entry(appservice_id.to_owned())
+			.or_default()
+			.clone()
+	}
+
+	/// Gathers the appservice's users and the encrypted rooms they're in. This
+	/// scans every user, so it is only done once per appservice.
+	async fn track_appservice(&self, appservice: &RegistrationInfo) -> Tracked {
+		let users: BTreeSet<OwnedUserId> = self
+			.services
+			.users
+			.stream()
+			.ready_filter(|user_id| appservice.is_user_match(user_id))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let mut joined = BTreeSet::new();
+		for user_id in &users {
+			self.services
+				.state_cache
+				.rooms_joined(user_id)
+				.ready_for_each(|room_id| {
+					joined.insert(room_id.to_owned());
+				})
+				.await;
+		}
+
+		let mut rooms = BTreeSet::new();
+		for room_id in joined {
+			if self
+				.services
+				.state_accessor
+				.is_encrypted_room(&room_id)
+				.await
+			{
+				rooms.insert(room_id);
+			}
+		}
+
+		Tracked { users, rooms }
+	}
+
+	/// Applies the rooms changed by the transaction to what is tracked for the
+	/// appservice, returning the users who started sharing an encrypted room
+	/// with its users and those who no longer share any.
+	async fn update_tracked(
+		&self,
+		appservice: &RegistrationInfo,
+		tracked: &mut Tracked,
+		room_changes: &RoomChanges,
+	) -> (BTreeSet<OwnedUserId>, BTreeSet<OwnedUserId>) {
+		let mut joined = BTreeSet::new();
+		let mut candidates = BTreeSet::new();
+		for (room_id, users) in room_changes {
+			tracked.users.extend(
+				users
+					.iter()
+					.filter(|user_id| self.services.globals.user_is_local(user_id))
+					.filter(|user_id| appservice.is_user_match(user_id))
+					.cloned(),
+			);
+
+			let encrypted = self
+				.services
+				.state_accessor
+				.is_encrypted_room(room_id)
+				.await;
+
+			let shared = encrypted
+				&& self
+					.services
+					.state_cache
+					.appservice_in_room(room_id, appservice)
+					.await;
+
+			match (tracked.set_room(room_id, shared), shared) {
+				| (false, true) => {
+					// One of the appservice's users joined the room
+					self.services
+						.state_cache
+						.room_members(room_id)
+						.ready_for_each(|user_id| {
+							joined.insert(user_id.to_owned());
+						})
+						.await;
+				},
+				| (true, false) => {
+					// The last of the appservice's users left the room
+					self.services
+						.state_cache
+						.room_members(room_id)
+						.ready_for_each(|user_id| {
+							candidates.insert(user_id.to_owned());
+						})
+						.await;
+				},
+				| (true, true) =>
+					for user_id in users {
+						if self.services.state_cache.is_joined(user_id, room_id).await {
+							joined.insert(user_id.clone());
+						} else {
+							candidates.insert(user_id.clone());
+						}
+					},
+				| (false, false) => {},
+			}
+		}
+
+		let rooms = &tracked.rooms;
+		let left = candidates
+			.into_iter()
+			.stream()
+			.ready_filter(|user_id| !appservice.is_user_match(user_id))
+			.filter_map(|user_id| async move {
+				let shared = self
+					.services
+					.state_cache
+					.rooms_joined(&user_id)
+					.ready_any(|room_id| rooms.contains(room_id))
+					.await;
+
+				(!shared).then_some(user_id)
+			})
+			.collect()
+			.await;
+
+		(joined, left)
+	}
+}
+
+/// The device lists of a transaction; users who left aren't also reported as
+/// changed.
+pub(super) fn device_lists(
+	mut changed: BTreeSet<OwnedUserId>,
+	left: BTreeSet<OwnedUserId>,
+) -> DeviceLists {
+	changed.retain(|user_id| !left.contains(user_id));
+
+	let mut device_lists = DeviceLists::new();
+	device_lists.changed = changed.into_iter().collect();
+	device_lists.left = left.into_iter().collect();
+	device_lists
+}
@@ -0,0 +1,117 @@
+//! Rolling statistics of transactions sent to each destination.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::Mutex,
+	time::{Duration, SystemTime},
+};
+
+use conduwuit::implement;
+use ruma::{OwnedServerName, ServerName};
+
+/// Number of recent transactions the success rate and latency cover.
+const WINDOW: usize = 64;
+
+#[derive(Default)]
+pub(super) struct Health {
+	servers: Mutex<HashMap<OwnedServerName, Window>>,
+}
+
+#[derive(Default)]
+struct Window {
+	/// Outcome and latency of the most recent transactions.
+	recent: VecDeque<(bool, Duration)>,
+	consecutive_failures: u32,
+	last_success: Option<SystemTime>,
+	last_failure: Option<SystemTime>,
+}
+
+/// Snapshot of a destination's recent transactions.
+#[derive(Clone, Debug)]
+pub struct DestinationHealth {
+	/// Transactions the statistics below cover.
+	pub transactions: usize,
+
+	/// Fraction of those transactions which succeeded.
+	pub success_rate: f64,
+
+	/// Median latency of those transactions.
+	pub median_latency: Duration,
+
+	/// Transactions which failed since the last success.
+	pub consecutive_failures: u32,
+
+	pub last_success: Option<SystemTime>,
+
+	pub last_failure: Option<SystemTime>,
+}
+
+/// Health of every destination we've sent transactions to, least healthy
+/// first.
+#[implement(super::Service)]
+#[must_use]
+pub fn federation_health(&self) -> Vec<(OwnedServerName, DestinationHealth)> {
+	let mut health: Vec<_> = self
+		.health
+		.servers
+		.lock()
+		.expect("locked")
+		.iter()
+		.map(|(server, window)| (server.clone(), window.snapshot()))
+		.collect();
+
+	health.sort_by(|(a_server, a), (b_server, b)| {
+		b.consecutive_failures
+			.cmp(&a.consecutive_failures)
+			.then(a.success_rate.total_cmp(&b.success_rate))
+			.then(a_server.cmp(b_server))
+	});
+
+	health
+}
+
+#[implement(super::Service)]
+pub(super) fn record_transaction(&self, server: &ServerName, success: bool, latency: Duration) {
+	let mut servers = self.health.servers.lock().expect("locked");
+	let window = servers.entry(server.to_owned()).or_default();
+
+	if window.recent.len() >= WINDOW {
+		window.recent.pop_front();
+	}
+
+	window.recent.push_back((success, latency));
+	if success {
+		window.consecutive_failures = 0;
+		window.last_success = Some(SystemTime::now());
+	} else {
+		window.consecutive_failures = window.consecutive_failures.saturating_add(1);
+		window.last_failure = Some(SystemTime::now());
+	}
+}
+
+impl Window {
+	#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+	fn snapshot(&self) -> DestinationHealth {
+		let transactions = self.recent.len();
+		let successes = self.recent.iter().filter(|(success, _)| *success).count();
+		let success_rate = if transactions > 0 {
+			successes as f64 / transactions as f64
+		} else {
+			0.0
+		};
+
+		let mut latencies: Vec<_> = self.recent.iter().map(|(_, latency)| *latency).collect();
+
+		latencies.sort_unstable();
+		let median_latency = latencies.get(transactions / 2).copied().unwrap_or_default();
+
+		DestinationHealth {
+			transactions,
+			success_rate,
+			median_latency,
+			consecutive_failures: self.consecutive_failures,
+			last_success: self.last_success,
+			last_failure: self.last_failure,
+		}
+	}
+}
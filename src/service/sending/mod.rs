@@ -1,37 +1,51 @@
 mod appservice;
+mod cursor;
 mod data;
 mod dest;
+mod device_keys;
+mod health;
 mod sender;
+mod suppress;
+mod tests;
 
 use std::{
 	fmt::Debug,
 	hash::{DefaultHasher, Hash, Hasher},
 	iter::once,
-	sync::Arc,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
 };
 
 use async_trait::async_trait;
 use conduwuit::{
 	debug, debug_warn, err, error,
-	utils::{available_parallelism, math::usize_from_u64_truncated, ReadyExt, TryReadyExt},
+	utils::{
+		available_parallelism, math::usize_from_u64_truncated, IterStream, ReadyExt, TryReadyExt,
+	},
 	warn, Result, Server,
 };
 use futures::{FutureExt, Stream, StreamExt};
 use ruma::{
 	api::{appservice::Registration, OutgoingRequest},
-	RoomId, ServerName, UserId,
+	OwnedRoomId, RoomId, ServerName, UserId,
 };
 use smallvec::SmallVec;
 use tokio::task::JoinSet;
 
-use self::data::Data;
+use self::{
+	cursor::Cursors, data::Data, device_keys::DeviceKeys, health::Health, suppress::Suppress,
+};
 pub use self::{
+	data::AppserviceFailures,
 	dest::Destination,
+	health::DestinationHealth,
 	sender::{EDU_LIMIT, PDU_LIMIT},
 };
 use crate::{
 	account_data,
-	appservice::NamespaceRegex,
+	appservice::{NamespaceRegex, RegistrationInfo},
 	client, federation, globals, presence, pusher,
 	rooms::{self, timeline::RawPduId},
 	users, Dep,
@@ -42,13 +56,20 @@ pub struct Service {
 	server: Arc<Server>,
 	services: Services,
 	channels: Vec<(loole::Sender<Msg>, loole::Receiver<Msg>)>,
+	suppress: Suppress,
+	health: Health,
+	cursors: Cursors,
+	device_keys: DeviceKeys,
+	senders_running: AtomicUsize,
 }
 
 struct Services {
 	alias: Dep<rooms::alias::Service>,
 	client: Dep<client::Service>,
+	event_handler: Dep<rooms::event_handler::Service>,
 	globals: Dep<globals::Service>,
 	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
 	user: Dep<rooms::user::Service>,
 	users: Dep<users::Service>,
@@ -92,8 +113,12 @@ impl crate::Service for Service {
 			services: Services {
 				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
 				client: args.depend::<client::Service>("client"),
+				event_handler: args
+					.depend::<rooms::event_handler::Service>("rooms::event_handler"),
 				globals: args.depend::<globals::Service>("globals"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				user: args.depend::<rooms::user::Service>("rooms::user"),
 				users: args.depend::<users::Service>("users"),
@@ -106,10 +131,20 @@ impl crate::Service for Service {
 				federation: args.depend::<federation::Service>("federation"),
 			},
 			channels: (0..num_senders).map(|_| loole::unbounded()).collect(),
+			suppress: Suppress::default(),
+			health: Health::default(),
+			cursors: Cursors::default(),
+			device_keys: DeviceKeys::default(),
+			senders_running: AtomicUsize::new(0),
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result {
+		// The primary sends everything; a replica would only send it again.
+		if self.services.globals.is_read_only() {
+			return Ok(());
+		}
+
 		let mut senders =
 			self.channels
 				.iter()
@@ -147,6 +182,19 @@ impl crate::Service for Service {
 }
 
 impl Service {
+	/// Number of sender workers which are running and the number expected to
+	/// be. No workers run on a read-only replica.
+	#[must_use]
+	pub fn senders_alive(&self) -> (usize, usize) {
+		let expected = if self.services.globals.is_read_only() {
+			0
+		} else {
+			self.channels.len()
+		};
+
+		(self.senders_running.load(Ordering::Relaxed), expected)
+	}
+
 	#[tracing::instrument(skip(self, pdu_id, user, pushkey), level = "debug")]
 	pub fn send_pdu_push(&self, pdu_id: &RawPduId, user: &UserId, pushkey: String) -> Result {
 		let dest = Destination::Push(user.to_owned(), pushkey);
@@ -175,11 +223,7 @@ impl Service {
 
 	#[tracing::instrument(skip(self, room_id, pdu_id), level = "debug")]
 	pub async fn send_pdu_room(&self, room_id: &RoomId, pdu_id: &RawPduId) -> Result {
-		let servers = self
-			.services
-			.state_cache
-			.room_servers(room_id)
-			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name));
+		let servers = self.room_remote_servers(room_id);
 
 		self.send_pdu_servers(servers, pdu_id).await
 	}
@@ -239,19 +283,9 @@ impl Service {
 		serialized: EduBuf,
 	) -> Result<()> {
 		for appservice in self.services.appservice.read().await.values() {
-			let matching_aliases = |aliases: NamespaceRegex| {
-				self.services
-					.alias
-					.local_aliases_for_room(room_id)
-					.ready_any(move |room_alias| aliases.is_match(room_alias.as_str()))
-			};
-
-			if appservice.rooms.is_match(room_id.as_str())
-				|| matching_aliases(appservice.aliases.clone()).await
-				|| self
-					.services
-					.state_cache
-					.appservice_in_room(room_id, appservice)
+			if appservice.receive_ephemeral
+				&& self
+					.appservice_interested_in_room(room_id, appservice)
 					.await
 			{
 				self.send_edu_appservice(&appservice.registration.id, serialized.clone())?;
@@ -260,17 +294,97 @@ impl Service {
 		Ok(())
 	}
 
-	#[tracing::instrument(skip(self, room_id, serialized), level = "debug")]
-	pub async fn send_edu_room(&self, room_id: &RoomId, serialized: EduBuf) -> Result {
-		let servers = self
+	/// Sends the EDU about the user, e.g. their presence, to the appservices
+	/// which receive ephemeral events and whose namespaces cover the user or
+	/// any room the user is joined to.
+	#[tracing::instrument(skip(self, serialized), level = "debug")]
+	pub async fn send_edu_appservice_user(
+		&self,
+		user_id: &UserId,
+		serialized: EduBuf,
+	) -> Result<()> {
+		let appservices: Vec<RegistrationInfo> = self
+			.services
+			.appservice
+			.read()
+			.await
+			.values()
+			.filter(|appservice| appservice.receive_ephemeral)
+			.cloned()
+			.collect();
+
+		if appservices.is_empty() {
+			return Ok(());
+		}
+
+		let rooms: Vec<OwnedRoomId> = self
 			.services
 			.state_cache
-			.room_servers(room_id)
-			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name));
+			.rooms_joined(user_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for appservice in &appservices {
+			let interested = appservice.is_user_match(user_id)
+				|| rooms
+					.iter()
+					.stream()
+					.any(|room_id| self.appservice_interested_in_room(room_id, appservice))
+					.await;
+
+			if interested {
+				self.send_edu_appservice(&appservice.registration.id, serialized.clone())?;
+			}
+		}
+		Ok(())
+	}
+
+	async fn appservice_interested_in_room(
+		&self,
+		room_id: &RoomId,
+		appservice: &RegistrationInfo,
+	) -> bool {
+		let matching_aliases = |aliases: NamespaceRegex| {
+			self.services
+				.alias
+				.local_aliases_for_room(room_id)
+				.ready_any(move |room_alias| aliases.is_match(room_alias.as_str()))
+		};
+
+		appservice.rooms.is_match(room_id.as_str())
+			|| matching_aliases(appservice.aliases.clone()).await
+			|| self
+				.services
+				.state_cache
+				.appservice_in_room(room_id, appservice)
+				.await
+	}
+
+	#[tracing::instrument(skip(self, room_id, serialized), level = "debug")]
+	pub async fn send_edu_room(&self, room_id: &RoomId, serialized: EduBuf) -> Result {
+		let servers = self.room_remote_servers(room_id);
 
 		self.send_edu_servers(servers, serialized).await
 	}
 
+	/// The other servers in the room which the room's server ACL allows.
+	pub(super) fn room_remote_servers<'a>(
+		&'a self,
+		room_id: &'a RoomId,
+	) -> impl Stream<Item = &'a ServerName> + Send + 'a {
+		self.services
+			.state_cache
+			.room_servers(room_id)
+			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name))
+			.filter(move |server_name| {
+				self.services
+					.event_handler
+					.acl_check(server_name, room_id)
+					.map(|result| result.is_ok())
+			})
+	}
+
 	#[tracing::instrument(skip(self, servers, serialized), level = "debug")]
 	pub async fn send_edu_servers<'a, S>(&self, servers: S, serialized: EduBuf) -> Result
 	where
@@ -298,11 +412,7 @@ impl Service {
 
 	#[tracing::instrument(skip(self, room_id), level = "debug")]
 	pub async fn flush_room(&self, room_id: &RoomId) -> Result<()> {
-		let servers = self
-			.services
-			.state_cache
-			.room_servers(room_id)
-			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name));
+		let servers = self.room_remote_servers(room_id);
 
 		self.flush_servers(servers).await
 	}
@@ -398,6 +508,9 @@ impl Service {
 					.delete_all_requests_for(&Destination::Appservice(appservice_id.to_owned()))
 					.await;
 
+				self.db.del_appservice_failures(appservice_id);
+				self.db.del_appservice_last_success(appservice_id);
+
 				Ok(())
 			},
 			| _ => {
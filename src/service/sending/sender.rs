@@ -1,6 +1,7 @@
 use std::{
-	collections::{BTreeMap, HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	fmt::Debug,
+	iter::once,
 	sync::{
 		atomic::{AtomicU64, AtomicUsize, Ordering},
 		Arc,
@@ -10,10 +11,13 @@ use std::{
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use conduwuit::{
-	debug, err, error,
+	at, debug, debug_warn, defer, err, error,
 	result::LogErr,
 	trace,
-	utils::{calculate_hash, continue_exponential_backoff_secs, stream::IterStream, ReadyExt},
+	utils::{
+		calculate_hash, continue_exponential_backoff_secs, millis_since_unix_epoch,
+		stream::IterStream, sys::systemd, ReadyExt,
+	},
 	warn, Error, Result,
 };
 use futures::{
@@ -24,7 +28,7 @@ use futures::{
 };
 use ruma::{
 	api::{
-		appservice::event::push_events::v1::EphemeralData,
+		appservice::event::push_events::v1::{DeviceLists, EphemeralData},
 		federation::transactions::{
 			edu::{
 				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent,
@@ -36,17 +40,18 @@ use ruma::{
 	device_id,
 	events::{
 		push_rules::PushRulesEvent, receipt::ReceiptType, AnySyncEphemeralRoomEvent,
-		GlobalAccountDataEventType,
+		GlobalAccountDataEventType, TimelineEventType,
 	},
-	push,
 	serde::Raw,
 	uint, CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName,
-	OwnedUserId, RoomId, RoomVersionId, ServerName, UInt,
+	OwnedUserId, RoomId, RoomVersionId, ServerName, UInt, UserId,
 };
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
+use tokio::time::{interval, MissedTickBehavior};
 
 use super::{
-	appservice, data::QueueItem, Destination, EduBuf, EduVec, Msg, SendingEvent, Service,
+	appservice, cursor::EduRooms, data::QueueItem, device_keys::RoomChanges, AppserviceFailures,
+	Destination, EduBuf, EduVec, Msg, SendingEvent, Service,
 };
 
 #[derive(Debug)]
@@ -69,12 +74,25 @@ const SELECT_RECEIPT_LIMIT: usize = 256;
 const SELECT_EDU_LIMIT: usize = EDU_LIMIT - 2;
 const DEQUEUE_LIMIT: usize = 48;
 
+/// Bounds of how often held back receipts are checked for being due.
+const RECEIPT_TICK_MIN: Duration = Duration::from_millis(100);
+const RECEIPT_TICK_MAX: Duration = Duration::from_secs(1);
+
+/// How often appservices whose transactions failed are checked for being due
+/// a retry.
+const APPSERVICE_RETRY_TICK: Duration = Duration::from_secs(1);
+
 pub const PDU_LIMIT: usize = 50;
 pub const EDU_LIMIT: usize = 100;
 
 impl Service {
 	#[tracing::instrument(skip(self), level = "debug")]
 	pub(super) async fn sender(self: Arc<Self>, id: usize) -> Result {
+		self.senders_running.fetch_add(1, Ordering::Relaxed);
+		defer! {{
+			self.senders_running.fetch_sub(1, Ordering::Relaxed);
+		}};
+
 		let mut statuses: CurTransactionStatus = CurTransactionStatus::new();
 		let mut futures: SendingFutures<'_> = FuturesUnordered::new();
 
@@ -112,6 +130,17 @@ impl Service {
 			.map(|(_, receiver)| receiver.clone())
 			.expect("Missing channel for sender worker");
 
+		let receipt_delay = self.receipt_coalesce_delay();
+		let mut receipts = interval(
+			receipt_delay
+				.unwrap_or(RECEIPT_TICK_MAX)
+				.clamp(RECEIPT_TICK_MIN, RECEIPT_TICK_MAX),
+		);
+		receipts.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		let mut appservice_retries = interval(APPSERVICE_RETRY_TICK);
+		appservice_retries.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
 		while !receiver.is_closed() {
 			tokio::select! {
 				Some(response) = futures.next() => {
@@ -121,10 +150,58 @@ impl Service {
 					Ok(request) => self.handle_request(request, futures, statuses).await,
 					Err(_) => return,
 				},
+				_ = receipts.tick(), if receipt_delay.is_some() => {
+					for server_name in self.receipts_due(id) {
+						let msg = Msg {
+							dest: Destination::Federation(server_name),
+							event: SendingEvent::Flush,
+							queue_id: Vec::new(),
+						};
+
+						self.handle_request(msg, futures, statuses).await;
+					}
+				},
+				_ = appservice_retries.tick() => {
+					for dest in self.appservice_retries_due(statuses) {
+						let msg = Msg {
+							dest,
+							event: SendingEvent::Flush,
+							queue_id: Vec::new(),
+						};
+
+						self.handle_request(msg, futures, statuses).await;
+					}
+				},
 			}
 		}
 	}
 
+	/// Appservices with failed transactions whose backoff has expired, which
+	/// are retried without waiting for new events.
+	fn appservice_retries_due(&self, statuses: &CurTransactionStatus) -> Vec<Destination> {
+		statuses
+			.iter()
+			.filter(|(dest, _)| matches!(dest, Destination::Appservice(_)))
+			.filter_map(|(dest, status)| match status {
+				| TransactionStatus::Failed(tries, time)
+					if !self.continue_backoff(dest, time.elapsed(), *tries) =>
+					Some(dest.clone()),
+				| _ => None,
+			})
+			.collect()
+	}
+
+	fn continue_backoff(&self, dest: &Destination, elapsed: Duration, tries: u32) -> bool {
+		let config = &self.server.config;
+		let (min, max) = match dest {
+			| Destination::Appservice(_) =>
+				(config.appservice_retry_backoff_min, config.appservice_retry_backoff_limit),
+			| _ => (config.sender_timeout, config.sender_retry_backoff_limit),
+		};
+
+		continue_exponential_backoff_secs(min, max, elapsed, tries)
+	}
+
 	#[tracing::instrument(name = "response", level = "debug", skip_all)]
 	async fn handle_response<'a>(
 		&'a self,
@@ -134,22 +211,40 @@ impl Service {
 	) {
 		match response {
 			| Ok(dest) => self.handle_response_ok(&dest, futures, statuses).await,
-			| Err((dest, e)) => Self::handle_response_err(dest, statuses, &e),
+			| Err((dest, e)) => self.handle_response_err(dest, statuses, &e),
 		};
 	}
 
-	fn handle_response_err(dest: Destination, statuses: &mut CurTransactionStatus, e: &Error) {
+	fn handle_response_err(
+		&self,
+		dest: Destination,
+		statuses: &mut CurTransactionStatus,
+		e: &Error,
+	) {
 		debug!(dest = ?dest, "{e:?}");
-		statuses.entry(dest).and_modify(|e| {
-			*e = match e {
+		let mut tries = 0;
+		statuses.entry(dest.clone()).and_modify(|status| {
+			*status = match status {
 				| TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
 				| TransactionStatus::Retrying(ref n) =>
 					TransactionStatus::Failed(n.saturating_add(1), Instant::now()),
 				| TransactionStatus::Failed(..) => {
 					panic!("Request that was not even running failed?!")
 				},
+			};
+
+			if let TransactionStatus::Failed(n, _) = status {
+				tries = *n;
 			}
 		});
+
+		if let Destination::Appservice(id) = &dest {
+			self.db.set_appservice_failures(id, &AppserviceFailures {
+				tries,
+				last_failure: millis_since_unix_epoch(),
+				last_error: e.to_string(),
+			});
+		}
 	}
 
 	#[allow(clippy::needless_pass_by_ref_mut)]
@@ -161,12 +256,22 @@ impl Service {
 	) {
 		let _cork = self.db.db.cork();
 		self.db.delete_all_active_requests_for(dest).await;
+		if let Destination::Appservice(id) = dest {
+			self.db.del_appservice_failures(id);
+			self.db
+				.set_appservice_last_success(id, millis_since_unix_epoch());
+		}
 
 		// Find events that have been added since starting the last request
+		let limit = match dest {
+			| Destination::Appservice(_) => self.server.config.appservice_transaction_limit,
+			| _ => DEQUEUE_LIMIT,
+		};
+
 		let new_events = self
 			.db
 			.queued_requests(dest)
-			.take(DEQUEUE_LIMIT)
+			.take(limit)
 			.collect::<Vec<_>>()
 			.await;
 
@@ -259,7 +364,30 @@ impl Service {
 			}
 		}
 
+		if self.server.config.startup_netburst && !txns.is_empty() {
+			systemd::status(&format!(
+				"Sending queued transactions to {} destinations (netburst)",
+				txns.len()
+			));
+		}
+
 		for (dest, events) in txns {
+			// Appservices which were failing keep backing off where they left off,
+			// and are retried once it expires.
+			if let Destination::Appservice(id) = &dest {
+				if let Some(failures) = self.db.get_appservice_failures(id).await {
+					let elapsed = Duration::from_millis(
+						millis_since_unix_epoch().saturating_sub(failures.last_failure),
+					);
+
+					let failed_at = Instant::now()
+						.checked_sub(elapsed)
+						.unwrap_or_else(Instant::now);
+					statuses.insert(dest, TransactionStatus::Failed(failures.tries, failed_at));
+					continue;
+				}
+			}
+
 			if self.server.config.startup_netburst && !events.is_empty() {
 				statuses.insert(dest.clone(), TransactionStatus::Running);
 				futures.push(self.send_events(dest.clone(), events));
@@ -293,12 +421,27 @@ impl Service {
 		let mut events = Vec::new();
 
 		// Must retry any previous transaction for this remote.
-		if retry {
+		if let Some(tries) = retry {
+			let drop_edus = self.drop_edus_after(tries);
 			self.db
 				.active_requests_for(dest)
-				.ready_for_each(|(_, e)| events.push(e))
+				.ready_for_each(|(key, e)| {
+					if drop_edus && matches!(e, SendingEvent::Edu(_)) {
+						self.db.delete_active_request(&key);
+					} else {
+						events.push(e);
+					}
+				})
 				.await;
 
+			// Only EDUs were left; don't wake the remote up for them and keep
+			// backing off until there's something worth sending.
+			if drop_edus && events.is_empty() {
+				debug!(?dest, tries, "Dropped EDUs to unreachable destination");
+				statuses.insert(dest.clone(), TransactionStatus::Failed(tries, Instant::now()));
+				return Ok(None);
+			}
+
 			return Ok(Some(events));
 		}
 
@@ -332,21 +475,17 @@ impl Service {
 		&self,
 		dest: &Destination,
 		statuses: &mut CurTransactionStatus,
-	) -> Result<(bool, bool)> {
-		let (mut allow, mut retry) = (true, false);
+	) -> Result<(bool, Option<u32>)> {
+		let (mut allow, mut retry) = (true, None);
 		statuses
 			.entry(dest.clone()) // TODO: can we avoid cloning?
 			.and_modify(|e| match e {
 				TransactionStatus::Failed(tries, time) => {
 					// Fail if a request has failed recently (exponential backoff)
-					let min = self.server.config.sender_timeout;
-					let max = self.server.config.sender_retry_backoff_limit;
-					if continue_exponential_backoff_secs(min, max, time.elapsed(), *tries)
-						&& !matches!(dest, Destination::Appservice(_))
-					{
+					if self.continue_backoff(dest, time.elapsed(), *tries) {
 						allow = false;
 					} else {
-						retry = true;
+						retry = Some(*tries);
 						*e = TransactionStatus::Retrying(*tries);
 					}
 				},
@@ -373,62 +512,89 @@ impl Service {
 
 		let events_len = AtomicUsize::default();
 		let max_edu_count = AtomicU64::new(since);
-
-		let device_changes =
-			self.select_edus_device_changes(server_name, batch, &max_edu_count, &events_len);
+		let deferred_count = AtomicU64::new(u64::MAX);
+		let rooms = self.edu_rooms(server_name).await;
+
+		let device_changes = self.select_edus_device_changes(
+			&rooms,
+			batch,
+			&max_edu_count,
+			&deferred_count,
+			&events_len,
+		);
 
 		let receipts: OptionFuture<_> = self
 			.server
 			.config
 			.allow_outgoing_read_receipts
-			.then(|| self.select_edus_receipts(server_name, batch, &max_edu_count))
+			.then(|| self.select_edus_receipts(&rooms, batch, &max_edu_count, &deferred_count))
 			.into();
 
-		let presence: OptionFuture<_> = self
-			.server
-			.config
-			.allow_outgoing_presence
+		let allow_presence =
+			self.server.config.allow_outgoing_presence && self.presence_due(server_name);
+
+		let presence: OptionFuture<_> = allow_presence
 			.then(|| self.select_edus_presence(server_name, batch, &max_edu_count))
 			.into();
 
 		let (device_changes, receipts, presence) = join!(device_changes, receipts, presence);
 
 		let mut events = device_changes;
-		events.extend(presence.into_iter().flatten());
+		if let Some(presence) = presence.flatten() {
+			self.presence_sent(server_name);
+			events.push(presence);
+		}
+
 		events.extend(receipts.into_iter().flatten());
 
-		Ok((events, max_edu_count.load(Ordering::Acquire)))
+		// Updates left out by the selection limits are picked up again next
+		// time, starting with the room they were left out in.
+		let deferred_count = deferred_count.load(Ordering::Acquire);
+		let last_count = max_edu_count
+			.load(Ordering::Acquire)
+			.min(deferred_count.saturating_sub(1));
+
+		let cursor = (deferred_count != u64::MAX)
+			.then(|| rooms.deferred())
+			.flatten();
+
+		self.set_edu_cursor(server_name, cursor);
+
+		Ok((events, last_count))
 	}
 
 	/// Look for device changes
 	#[tracing::instrument(
 		name = "device_changes",
 		level = "trace",
-		skip(self, server_name, max_edu_count)
+		skip(self, rooms, max_edu_count, deferred_count)
 	)]
 	async fn select_edus_device_changes(
 		&self,
-		server_name: &ServerName,
+		rooms: &EduRooms,
 		since: (u64, u64),
 		max_edu_count: &AtomicU64,
+		deferred_count: &AtomicU64,
 		events_len: &AtomicUsize,
 	) -> EduVec {
 		let mut events = EduVec::new();
-		let server_rooms = self.services.state_cache.server_rooms(server_name);
-
-		pin_mut!(server_rooms);
 		let mut device_list_changes = HashSet::<OwnedUserId>::new();
-		while let Some(room_id) = server_rooms.next().await {
+		for (i, room_id) in rooms.iter().enumerate() {
 			let keys_changed = self
 				.services
 				.users
-				.room_keys_changed(room_id, since.0, None)
+				.room_keys_changed(room_id, since.0, Some(since.1))
 				.ready_filter(|(user_id, _)| self.services.globals.user_is_local(user_id));
 
 			pin_mut!(keys_changed);
 			while let Some((user_id, count)) = keys_changed.next().await {
-				if count > since.1 {
-					break;
+				if events_len.load(Ordering::Relaxed) >= SELECT_EDU_LIMIT {
+					rooms.defer(i);
+					deferred_count.fetch_min(count, Ordering::Relaxed);
+					self.defer_device_changes(rooms, i, since, deferred_count)
+						.await;
+
+					return events;
 				}
 
 				max_edu_count.fetch_max(count, Ordering::Relaxed);
@@ -453,39 +619,72 @@ impl Service {
 					.expect("failed to serialize device list update to JSON");
 
 				events.push(buf);
-				if events_len.fetch_add(1, Ordering::Relaxed) >= SELECT_EDU_LIMIT - 1 {
-					return events;
-				}
+				events_len.fetch_add(1, Ordering::Relaxed);
 			}
 		}
 
 		events
 	}
 
+	/// Notes the oldest device change in each room after the `i`th, which
+	/// were left out of the selection.
+	async fn defer_device_changes(
+		&self,
+		rooms: &EduRooms,
+		i: usize,
+		since: (u64, u64),
+		deferred_count: &AtomicU64,
+	) {
+		for room_id in rooms.iter().skip(i.saturating_add(1)) {
+			let oldest = self
+				.services
+				.users
+				.room_keys_changed(room_id, since.0, Some(since.1))
+				.ready_filter(|(user_id, _)| self.services.globals.user_is_local(user_id))
+				.map(at!(1))
+				.boxed()
+				.next()
+				.await;
+
+			if let Some(count) = oldest {
+				deferred_count.fetch_min(count, Ordering::Relaxed);
+			}
+		}
+	}
+
 	/// Look for read receipts in this room
 	#[tracing::instrument(
 		name = "receipts",
 		level = "trace",
-		skip(self, server_name, max_edu_count)
+		skip(self, rooms, max_edu_count, deferred_count)
 	)]
 	async fn select_edus_receipts(
 		&self,
-		server_name: &ServerName,
+		rooms: &EduRooms,
 		since: (u64, u64),
 		max_edu_count: &AtomicU64,
+		deferred_count: &AtomicU64,
 	) -> Option<EduBuf> {
-		let server_rooms = self.services.state_cache.server_rooms(server_name);
-
-		pin_mut!(server_rooms);
 		let mut num = 0;
 		let mut receipts = BTreeMap::<OwnedRoomId, ReceiptMap>::new();
-		while let Some(room_id) = server_rooms.next().await {
+		for (i, room_id) in rooms.iter().enumerate() {
+			let full = num >= SELECT_RECEIPT_LIMIT;
 			let receipt_map = self
-				.select_edus_receipts_room(room_id, since, max_edu_count, &mut num)
+				.select_edus_receipts_room(
+					room_id,
+					since,
+					max_edu_count,
+					deferred_count,
+					&mut num,
+				)
 				.await;
 
+			if !full && num >= SELECT_RECEIPT_LIMIT {
+				rooms.defer(i);
+			}
+
 			if !receipt_map.read.is_empty() {
-				receipts.insert(room_id.into(), receipt_map);
+				receipts.insert(room_id.clone(), receipt_map);
 			}
 		}
 
@@ -502,13 +701,19 @@ impl Service {
 		Some(buf)
 	}
 
-	/// Look for read receipts in this room
-	#[tracing::instrument(name = "receipts", level = "trace", skip(self, since, max_edu_count))]
+	/// Look for read receipts in this room. Once `num` reaches the limit the
+	/// oldest receipt left out is noted in `deferred_count` instead.
+	#[tracing::instrument(
+		name = "receipts",
+		level = "trace",
+		skip(self, since, max_edu_count, deferred_count)
+	)]
 	async fn select_edus_receipts_room(
 		&self,
 		room_id: &RoomId,
 		since: (u64, u64),
 		max_edu_count: &AtomicU64,
+		deferred_count: &AtomicU64,
 		num: &mut usize,
 	) -> ReceiptMap {
 		let receipts = self
@@ -523,11 +728,17 @@ impl Service {
 				break;
 			}
 
-			max_edu_count.fetch_max(count, Ordering::Relaxed);
 			if !self.services.globals.user_is_local(user_id) {
+				max_edu_count.fetch_max(count, Ordering::Relaxed);
 				continue;
 			}
 
+			if *num >= SELECT_RECEIPT_LIMIT {
+				deferred_count.fetch_min(count, Ordering::Relaxed);
+				break;
+			}
+
+			max_edu_count.fetch_max(count, Ordering::Relaxed);
 			let Ok(event) = serde_json::from_str(read_receipt.json().get()) else {
 				error!(?user_id, ?count, ?read_receipt, "Invalid edu event in read_receipts.");
 				continue;
@@ -551,6 +762,16 @@ impl Service {
 				.remove(user_id)
 				.expect("our read receipts always have the user here");
 
+			// An EDU carries one receipt per user per room, so a receipt for another
+			// thread has to wait for the next transaction.
+			if read
+				.get(user_id)
+				.is_some_and(|prev| prev.data.thread != receipt.thread)
+			{
+				deferred_count.fetch_min(count, Ordering::Relaxed);
+				break;
+			}
+
 			let receipt_data = ReceiptData {
 				data: receipt,
 				event_ids: vec![event_id.clone()],
@@ -558,9 +779,6 @@ impl Service {
 
 			if read.insert(user_id.to_owned(), receipt_data).is_none() {
 				*num = num.saturating_add(1);
-				if *num >= SELECT_RECEIPT_LIMIT {
-					break;
-				}
 			}
 		}
 
@@ -593,6 +811,10 @@ impl Service {
 				continue;
 			}
 
+			if !self.presence_user_due(server_name, user_id) {
+				continue;
+			}
+
 			if !self
 				.services
 				.state_cache
@@ -629,13 +851,14 @@ impl Service {
 			}
 		}
 
-		if presence_updates.is_empty() {
+		let mut push: Vec<_> = presence_updates.into_values().collect();
+		push.truncate(self.take_presence_budget(push.len()));
+		if push.is_empty() {
 			return None;
 		}
 
-		let presence_content = Edu::Presence(PresenceContent {
-			push: presence_updates.into_values().collect(),
-		});
+		self.presence_users_sent(server_name, push.iter().map(|update| &update.user_id));
+		let presence_content = Edu::Presence(PresenceContent { push });
 
 		let mut buf = EduBuf::new();
 		serde_json::to_writer(&mut buf, &presence_content)
@@ -668,7 +891,7 @@ impl Service {
 		id: String,
 		events: Vec<SendingEvent>,
 	) -> SendingResult {
-		let Some(appservice) = self.services.appservice.get_registration(&id).await else {
+		let Some(appservice) = self.services.appservice.get_registration_info(&id).await else {
 			return Err((
 				Destination::Appservice(id.clone()),
 				err!(Database(warn!(?id, "Missing appservice registration"))),
@@ -687,10 +910,34 @@ impl Service {
 				.filter(|event| matches!(event, SendingEvent::Edu(_)))
 				.count(),
 		);
+		// The appservice's users the transaction is about, whose one-time key
+		// counts are included
+		let mut key_count_users = BTreeSet::<OwnedUserId>::new();
+		// Rooms whose membership or encryption changed, for the device lists
+		let mut room_changes = RoomChanges::new();
 		for event in &events {
 			match event {
 				| SendingEvent::Pdu(pdu_id) => {
 					if let Ok(pdu) = self.services.timeline.get_pdu_from_id(pdu_id).await {
+						let member = (pdu.kind == TimelineEventType::RoomMember)
+							.then(|| pdu.state_key.as_deref().map(UserId::parse))
+							.flatten()
+							.and_then(Result::ok);
+
+						if pdu.kind == TimelineEventType::RoomEncryption || member.is_some() {
+							room_changes
+								.entry(pdu.room_id.clone())
+								.or_default()
+								.extend(member.clone());
+						}
+
+						key_count_users.extend(
+							once(pdu.sender.clone())
+								.chain(member)
+								.filter(|user_id| self.services.globals.user_is_local(user_id))
+								.filter(|user_id| appservice.is_user_match(user_id)),
+						);
+
 						pdu_jsons.push(pdu.to_room_event());
 					}
 				},
@@ -712,23 +959,55 @@ impl Service {
 
 		let txn_id = &*URL_SAFE_NO_PAD.encode(txn_hash);
 
+		let claimed = if appservice.receive_device_keys {
+			self.device_keys.take_claimed(&id)
+		} else {
+			BTreeSet::new()
+		};
+
+		let (device_lists, devicelist_count, one_time_key_counts) =
+			if appservice.receive_device_keys {
+				let (device_lists, count) = self
+					.appservice_device_lists(&appservice, &room_changes)
+					.await;
+
+				key_count_users.extend(claimed.iter().cloned());
+				let counts = self.appservice_one_time_key_counts(&key_count_users).await;
+				(device_lists, Some(count), counts)
+			} else {
+				(DeviceLists::new(), None, BTreeMap::new())
+			};
+
 		//debug_assert!(pdu_jsons.len() + edu_jsons.len() > 0, "sending empty
 		// transaction");
 		let client = &self.services.client.appservice;
 		match appservice::send_request(
 			client,
-			appservice,
+			appservice.registration,
 			ruma::api::appservice::event::push_events::v1::Request {
 				events: pdu_jsons,
 				txn_id: txn_id.into(),
 				ephemeral: edu_jsons,
 				to_device: Vec::new(), // TODO
+				device_lists,
+				device_one_time_keys_count: one_time_key_counts,
+				// Fallback keys are not yet supported
+				device_unused_fallback_key_types: BTreeMap::new(),
 			},
 		)
 		.await
 		{
-			| Ok(_) => Ok(Destination::Appservice(id)),
-			| Err(e) => Err((Destination::Appservice(id), e)),
+			| Ok(_) => {
+				if let Some(count) = devicelist_count {
+					self.db.set_appservice_devicelist_count(&id, count);
+				}
+
+				Ok(Destination::Appservice(id))
+			},
+			| Err(e) => {
+				self.device_keys.restore_claimed(&id, claimed);
+				Err((Destination::Appservice(id), e))
+			},
 		}
 	}
 
@@ -747,12 +1026,22 @@ impl Service {
 		events: Vec<SendingEvent>,
 	) -> SendingResult {
 		let Ok(pusher) = self.services.pusher.get_pusher(&user_id, &pushkey).await else {
-			return Err((
-				Destination::Push(user_id.clone(), pushkey.clone()),
-				err!(Database(error!(?user_id, ?pushkey, "Missing pusher"))),
-			));
+			// The pusher was removed; there is nobody left to deliver these to
+			debug_warn!(?user_id, ?pushkey, "Dropping events for missing pusher");
+			return Ok(Destination::Push(user_id, pushkey));
 		};
 
+		if !self
+			.services
+			.pusher
+			.is_pusher_enabled(&user_id, &pushkey)
+			.await
+		{
+			// The pusher was disabled remotely (MSC3881) after these were queued
+			debug!(?user_id, ?pushkey, "Dropping events for disabled pusher");
+			return Ok(Destination::Push(user_id, pushkey));
+		}
+
 		let mut pdus = Vec::with_capacity(
 			events
 				.iter()
@@ -785,7 +1074,7 @@ impl Service {
 				.get_global(&user_id, GlobalAccountDataEventType::PushRules)
 				.await
 				.map_or_else(
-					|_| push::Ruleset::server_default(&user_id),
+					|_| self.services.pusher.server_default_rules(&user_id),
 					|ev: PushRulesEvent| ev.content.global,
 				);
 
@@ -797,17 +1086,39 @@ impl Service {
 				.try_into()
 				.expect("notification count can't go that high");
 
-			let _response = self
+			let result = self
 				.services
 				.pusher
 				.send_push_notice(&user_id, unread, &pusher, rules_for_user, &pdu)
-				.await
-				.map_err(|e| (Destination::Push(user_id.clone(), pushkey.clone()), e));
+				.await;
+
+			if let Err(e) = result {
+				// Once the pusher is given up on its events go with it; otherwise the
+				// whole batch is retried after backing off.
+				if self
+					.services
+					.pusher
+					.note_push_failure(&user_id, &pusher, &e)
+					.await
+				{
+					break;
+				}
+
+				return Err((Destination::Push(user_id, pushkey), e));
+			}
+
+			self.services.pusher.note_push_success(&user_id, &pusher);
 		}
 
 		Ok(Destination::Push(user_id, pushkey))
 	}
 
+	#[tracing::instrument(
+		name = "federation",
+		level = "debug",
+		skip_all,
+		fields(destination = %server, events = events.len()),
+	)]
 	async fn send_events_dest_federation(
 		&self,
 		server: OwnedServerName,
@@ -855,12 +1166,15 @@ impl Service {
 			edus,
 		};
 
+		let timer = Instant::now();
 		let result = self
 			.services
 			.federation
-			.execute_on(&self.services.client.sender, &server, request)
+			.execute_on(self.services.client.sender_for(&server), &server, request)
 			.await;
 
+		self.record_transaction(&server, result.is_ok(), timer.elapsed());
+
 		for (event_id, result) in result.iter().flat_map(|resp| resp.pdus.iter()) {
 			if let Err(e) = result {
 				warn!(
@@ -0,0 +1,239 @@
+//! Heuristics which skip sending EDUs a destination is unlikely to need.
+
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{implement, utils::ReadyExt, Result};
+use futures::StreamExt;
+use ruma::{OwnedServerName, OwnedUserId, RoomId, ServerName, UserId};
+
+use super::{Destination, EduBuf};
+
+#[derive(Default)]
+pub(super) struct Suppress {
+	/// When each server last sent us a transaction.
+	inbound: Mutex<HashMap<OwnedServerName, Instant>>,
+
+	/// When presence was last sent to each server.
+	presence: Mutex<HashMap<OwnedServerName, Instant>>,
+
+	/// When each local user's presence was last sent to each server.
+	presence_users: Mutex<HashMap<(OwnedServerName, OwnedUserId), Instant>>,
+
+	/// Presence updates which may still be sent under
+	/// `presence_federation_max_per_second`.
+	presence_budget: Mutex<Option<(f64, Instant)>>,
+
+	/// When receipts held back by `receipt_federation_coalesce_ms` are due to
+	/// be sent to each server.
+	receipts: Mutex<HashMap<OwnedServerName, Instant>>,
+}
+
+/// Per-user presence send times are pruned of stale entries once there are
+/// this many.
+const PRESENCE_USERS_PRUNE_LEN: usize = 65536;
+
+/// Records that `origin` sent us a transaction, which is taken as a sign its
+/// users are active.
+#[implement(super::Service)]
+pub fn note_inbound(&self, origin: &ServerName) {
+	if self.server.config.typing_federation_active_window_s == 0 {
+		return;
+	}
+
+	self.suppress
+		.inbound
+		.lock()
+		.expect("locked")
+		.insert(origin.to_owned(), Instant::now());
+}
+
+/// Sends a typing EDU to the servers in the room which have been active
+/// within `typing_federation_active_window_s`.
+#[implement(super::Service)]
+pub async fn send_typing_room(&self, room_id: &RoomId, serialized: EduBuf) -> Result {
+	let window = Duration::from_secs(self.server.config.typing_federation_active_window_s);
+	if window.is_zero() {
+		return self.send_edu_room(room_id, serialized).await;
+	}
+
+	let servers = self
+		.room_remote_servers(room_id)
+		.ready_filter(move |server_name| self.recently_active(server_name, window));
+
+	self.send_edu_servers(servers, serialized).await
+}
+
+#[implement(super::Service)]
+fn recently_active(&self, server_name: &ServerName, window: Duration) -> bool {
+	self.suppress
+		.inbound
+		.lock()
+		.expect("locked")
+		.get(server_name)
+		.is_some_and(|last| last.elapsed() < window)
+}
+
+/// Whether presence may be sent to `server_name` again under
+/// `presence_federation_min_interval_s`.
+#[implement(super::Service)]
+pub(super) fn presence_due(&self, server_name: &ServerName) -> bool {
+	let interval = Duration::from_secs(self.server.config.presence_federation_min_interval_s);
+	interval.is_zero()
+		|| !self
+			.suppress
+			.presence
+			.lock()
+			.expect("locked")
+			.get(server_name)
+			.is_some_and(|last| last.elapsed() < interval)
+}
+
+/// Records that presence was just sent to `server_name`.
+#[implement(super::Service)]
+pub(super) fn presence_sent(&self, server_name: &ServerName) {
+	if self.server.config.presence_federation_min_interval_s == 0 {
+		return;
+	}
+
+	self.suppress
+		.presence
+		.lock()
+		.expect("locked")
+		.insert(server_name.to_owned(), Instant::now());
+}
+
+/// Whether `user_id`'s presence may be sent to `server_name` again under
+/// `presence_federation_user_min_interval_s`.
+#[implement(super::Service)]
+pub(super) fn presence_user_due(&self, server_name: &ServerName, user_id: &UserId) -> bool {
+	let interval =
+		Duration::from_secs(self.server.config.presence_federation_user_min_interval_s);
+	if interval.is_zero() {
+		return true;
+	}
+
+	let key = (server_name.to_owned(), user_id.to_owned());
+	!self
+		.suppress
+		.presence_users
+		.lock()
+		.expect("locked")
+		.get(&key)
+		.is_some_and(|last| last.elapsed() < interval)
+}
+
+/// Records that the presence of `user_ids` was just sent to `server_name`.
+#[implement(super::Service)]
+pub(super) fn presence_users_sent<'a, I>(&self, server_name: &ServerName, user_ids: I)
+where
+	I: Iterator<Item = &'a OwnedUserId>,
+{
+	let interval =
+		Duration::from_secs(self.server.config.presence_federation_user_min_interval_s);
+	if interval.is_zero() {
+		return;
+	}
+
+	let now = Instant::now();
+	let mut sent = self.suppress.presence_users.lock().expect("locked");
+	if sent.len() >= PRESENCE_USERS_PRUNE_LEN {
+		sent.retain(|_, last| now.duration_since(*last) < interval);
+	}
+
+	for user_id in user_ids {
+		sent.insert((server_name.to_owned(), user_id.clone()), now);
+	}
+}
+
+/// Takes up to `wanted` presence updates from the budget refilled at
+/// `presence_federation_max_per_second`, returning how many may be sent.
+#[implement(super::Service)]
+pub(super) fn take_presence_budget(&self, wanted: usize) -> usize {
+	let rate = self.server.config.presence_federation_max_per_second;
+	if rate == 0 {
+		return wanted;
+	}
+
+	let rate = f64::from(rate);
+	let now = Instant::now();
+	let mut budget = self.suppress.presence_budget.lock().expect("locked");
+	let (tokens, updated) = budget.get_or_insert((rate, now));
+	let refill = now.duration_since(*updated).as_secs_f64() * rate;
+	*tokens = (*tokens + refill).min(rate);
+	*updated = now;
+
+	let mut allowed: usize = 0;
+	while allowed < wanted && *tokens >= 1.0 {
+		*tokens -= 1.0;
+		allowed = allowed.saturating_add(1);
+	}
+
+	allowed
+}
+
+/// Sends read receipts to the servers in the room, after
+/// `receipt_federation_coalesce_ms` so later receipts are sent along with
+/// them.
+#[implement(super::Service)]
+pub async fn flush_room_receipts(&self, room_id: &RoomId) -> Result {
+	let Some(delay) = self.receipt_coalesce_delay() else {
+		return self.flush_room(room_id).await;
+	};
+
+	let due = Instant::now()
+		.checked_add(delay)
+		.unwrap_or_else(Instant::now);
+	let servers: Vec<OwnedServerName> = self
+		.room_remote_servers(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut receipts = self.suppress.receipts.lock()?;
+	for server_name in servers {
+		receipts.entry(server_name).or_insert(due);
+	}
+
+	Ok(())
+}
+
+/// How long receipts are held back for, if they are.
+#[implement(super::Service)]
+pub(super) fn receipt_coalesce_delay(&self) -> Option<Duration> {
+	let delay = Duration::from_millis(self.server.config.receipt_federation_coalesce_ms);
+	(!delay.is_zero()).then_some(delay)
+}
+
+/// Takes the servers handled by sender `id` whose held back receipts are due.
+#[implement(super::Service)]
+pub(super) fn receipts_due(&self, id: usize) -> Vec<OwnedServerName> {
+	let now = Instant::now();
+	let mut due = Vec::new();
+	self.suppress
+		.receipts
+		.lock()
+		.expect("locked")
+		.retain(|server_name, at| {
+			let dest = Destination::Federation(server_name.clone());
+			if *at > now || self.shard_id(&dest) != id {
+				return true;
+			}
+
+			due.push(server_name.clone());
+			false
+		});
+
+	due
+}
+
+/// Whether queued EDUs to a destination which has failed `tries` times in a
+/// row should be dropped rather than retried.
+#[implement(super::Service)]
+pub(super) fn drop_edus_after(&self, tries: u32) -> bool {
+	let attempts = self.server.config.sender_edu_backoff_drop_attempts;
+	attempts > 0 && tries >= attempts
+}
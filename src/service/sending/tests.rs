@@ -0,0 +1,61 @@
+#![cfg(test)]
+
+use std::collections::BTreeSet;
+
+use ruma::{owned_room_id, owned_user_id, user_id, OwnedUserId};
+
+use super::device_keys::{device_lists, DeviceKeys, Tracked};
+
+#[test]
+fn tracked_room_transitions() {
+	let room_id = owned_room_id!("!room:example.com");
+	let mut tracked = Tracked::default();
+
+	assert!(!tracked.set_room(&room_id, true), "newly tracked");
+	assert!(tracked.set_room(&room_id, true), "already tracked");
+	assert!(tracked.set_room(&room_id, false), "was tracked before it stopped");
+	assert!(!tracked.set_room(&room_id, false), "already untracked");
+	assert!(tracked.rooms.is_empty());
+}
+
+#[test]
+fn left_users_are_not_changed() {
+	let alice = owned_user_id!("@alice:example.com");
+	let bob = owned_user_id!("@bob:example.com");
+
+	let changed = BTreeSet::from([alice.clone(), bob.clone()]);
+	let left = BTreeSet::from([bob.clone()]);
+	let device_lists = device_lists(changed, left);
+
+	assert_eq!(device_lists.changed, vec![alice]);
+	assert_eq!(device_lists.left, vec![bob]);
+}
+
+#[test]
+fn claimed_keys_are_taken_once() {
+	let keys = DeviceKeys::default();
+	keys.claim("bridge", user_id!("@bridge_alice:example.com"));
+	keys.claim("bridge", user_id!("@bridge_alice:example.com"));
+	keys.claim("other", user_id!("@other_bob:example.com"));
+
+	let claimed = keys.take_claimed("bridge");
+	assert_eq!(claimed.len(), 1);
+	assert!(keys.take_claimed("bridge").is_empty());
+	assert_eq!(keys.take_claimed("other").len(), 1);
+}
+
+#[test]
+fn claimed_keys_are_restored_after_failure() {
+	let keys = DeviceKeys::default();
+	keys.claim("bridge", user_id!("@bridge_alice:example.com"));
+
+	let claimed = keys.take_claimed("bridge");
+	keys.claim("bridge", user_id!("@bridge_bob:example.com"));
+	keys.restore_claimed("bridge", claimed);
+
+	let users: Vec<OwnedUserId> = keys.take_claimed("bridge").into_iter().collect();
+	assert_eq!(users, vec![
+		owned_user_id!("@bridge_alice:example.com"),
+		owned_user_id!("@bridge_bob:example.com"),
+	]);
+}
@@ -0,0 +1,298 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use conduwuit::{
+	debug_info, implement,
+	pdu::PduBuilder,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+	},
+	warn, Result, Server,
+};
+use database::{Deserialized, Ignore, Interfix, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{
+	events::{
+		room::{
+			create::RoomCreateEventContent,
+			guest_access::{GuestAccess, RoomGuestAccessEventContent},
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			message::RoomMessageEventContent,
+			name::RoomNameEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		tag::{TagEvent, TagEventContent, TagInfo},
+		RoomAccountDataEventType,
+	},
+	OwnedEventId, OwnedRoomId, OwnedUserId, RoomVersionId, UserId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{account_data, globals, rooms, users, Dep};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	globals: Dep<globals::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	servernoticeid_notice: Arc<Map>,
+	servernoticeid_userid: Arc<Map>,
+	userid_servernoticeroomid: Arc<Map>,
+}
+
+/// A notice broadcast to local users, with how many of them it reached.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Notice {
+	pub id: String,
+	pub body: String,
+	pub sent_at: u64,
+	pub reached: usize,
+	pub failed: usize,
+}
+
+const NOTICE_ID_LENGTH: usize = 16;
+
+const NOTICE_ROOM_TAG: &str = "m.server_notice";
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				globals: args.depend::<globals::Service>("globals"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				servernoticeid_notice: args.db["servernoticeid_notice"].clone(),
+				servernoticeid_userid: args.db["servernoticeid_userid"].clone(),
+				userid_servernoticeroomid: args.db["userid_servernoticeroomid"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Sends a markdown notice to every local user with a password, recording which
+/// of them it reached.
+#[implement(Service)]
+pub async fn broadcast(&self, body: &str) -> Result<Notice> {
+	let server_user = &self.services.globals.server_user;
+	let users: Vec<OwnedUserId> = self
+		.services
+		.users
+		.list_local_users()
+		.ready_filter(|user_id| *user_id != *server_user)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut notice = Notice {
+		id: utils::random_string(NOTICE_ID_LENGTH),
+		body: body.to_owned(),
+		sent_at: utils::millis_since_unix_epoch(),
+		..Notice::default()
+	};
+
+	let content = RoomMessageEventContent::notice_markdown(body);
+	for user_id in &users {
+		match self.send(user_id, content.clone()).await {
+			| Ok(event_id) => {
+				let key = (notice.id.as_str(), user_id);
+				self.db.servernoticeid_userid.put(key, &*event_id);
+				notice.reached = notice.reached.saturating_add(1);
+			},
+			| Err(e) => {
+				warn!(%user_id, "Failed to send server notice: {e}");
+				notice.failed = notice.failed.saturating_add(1);
+			},
+		}
+	}
+
+	self.db.servernoticeid_notice.put(&notice.id, Json(&notice));
+
+	debug_info!(id = %notice.id, reached = notice.reached, failed = notice.failed, "Server notice sent");
+
+	Ok(notice)
+}
+
+/// Sends a notice to a local user in their server notice room, creating the
+/// room first if they don't have one.
+#[implement(Service)]
+pub async fn send(
+	&self,
+	user_id: &UserId,
+	content: RoomMessageEventContent,
+) -> Result<OwnedEventId> {
+	let room_id = self.notice_room(user_id).await?;
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::timeline(&content),
+			&self.services.globals.server_user,
+			&room_id,
+			&state_lock,
+		)
+		.await
+}
+
+/// Gets a broadcast notice by its ID.
+#[implement(Service)]
+pub async fn get_notice(&self, id: &str) -> Result<Notice> {
+	self.db.servernoticeid_notice.get(id).await.deserialized()
+}
+
+/// Returns the users a broadcast notice was delivered to.
+#[implement(Service)]
+pub fn reached_users<'a>(&'a self, id: &'a str) -> impl Stream<Item = &UserId> + Send + 'a {
+	let prefix = (id, Interfix);
+	self.db
+		.servernoticeid_userid
+		.keys_prefix(&prefix)
+		.ignore_err()
+		.map(|(_, user_id): (Ignore, &UserId)| user_id)
+}
+
+/// Gets the user's server notice room, creating a new one if they never had
+/// one or have left it.
+#[implement(Service)]
+async fn notice_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	if let Ok(room_id) = self
+		.db
+		.userid_servernoticeroomid
+		.get(user_id)
+		.await
+		.deserialized::<OwnedRoomId>()
+	{
+		let state_cache = &self.services.state_cache;
+		if state_cache.is_joined(user_id, &room_id).await
+			|| state_cache.is_invited(user_id, &room_id).await
+		{
+			return Ok(room_id);
+		}
+	}
+
+	let room_id = self.create_notice_room(user_id).await?;
+	self.db.userid_servernoticeroomid.put(user_id, &room_id);
+
+	Ok(room_id)
+}
+
+/// Creates a room which only the server user can send to and joins the user
+/// to it, tagging it so clients show it as a system alert.
+#[implement(Service)]
+async fn create_notice_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	let room_id = self.services.metadata.generate_room_id().await?;
+	let room_version = &self.services.server.config.default_room_version;
+	let server_user = &self.services.globals.server_user;
+
+	let _short_id = self
+		.services
+		.short
+		.get_or_create_shortroomid(&room_id)
+		.await;
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	let create_content = {
+		use RoomVersionId::*;
+		match room_version {
+			| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 =>
+				RoomCreateEventContent::new_v1(server_user.clone()),
+			| _ => RoomCreateEventContent::new_v11(),
+		}
+	};
+
+	let users = BTreeMap::from_iter([(server_user.clone(), 100.into())]);
+	let events = [
+		PduBuilder::state(String::new(), &RoomCreateEventContent {
+			federate: false,
+			predecessor: None,
+			room_version: room_version.clone(),
+			..create_content
+		}),
+		PduBuilder::state(
+			server_user.to_string(),
+			&RoomMemberEventContent::new(MembershipState::Join),
+		),
+		PduBuilder::state(String::new(), &RoomPowerLevelsEventContent {
+			users,
+			events_default: 100.into(),
+			..Default::default()
+		}),
+		PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(JoinRule::Invite)),
+		PduBuilder::state(
+			String::new(),
+			&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomGuestAccessEventContent::new(GuestAccess::Forbidden),
+		),
+		PduBuilder::state(String::new(), &RoomNameEventContent::new("Server Notices".to_owned())),
+		PduBuilder::state(
+			user_id.to_string(),
+			&RoomMemberEventContent::new(MembershipState::Invite),
+		),
+	];
+
+	for pdu in events {
+		self.services
+			.timeline
+			.build_and_append_pdu(pdu, server_user, &room_id, &state_lock)
+			.await?;
+	}
+
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(
+				user_id.to_string(),
+				&RoomMemberEventContent::new(MembershipState::Join),
+			),
+			user_id,
+			&room_id,
+			&state_lock,
+		)
+		.await?;
+
+	let tags = BTreeMap::from_iter([(NOTICE_ROOM_TAG.into(), TagInfo::new())]);
+	let event = TagEvent { content: TagEventContent { tags } };
+
+	self.services
+		.account_data
+		.update(
+			Some(&room_id),
+			user_id,
+			RoomAccountDataEventType::Tag,
+			&serde_json::to_value(event)?,
+		)
+		.await?;
+
+	debug_info!(%user_id, %room_id, "Created server notice room");
+
+	Ok(room_id)
+}
@@ -5,36 +5,55 @@ use std::{
 	sync::{Arc, RwLock},
 };
 
-use conduwuit::{debug, debug_info, info, trace, Result, Server};
+use conduwuit::{debug, debug_info, info, trace, utils::sys::systemd, Result, Server};
 use database::Database;
 use tokio::sync::Mutex;
 
 use crate::{
-	account_data, admin, appservice, client, config, emergency, federation, globals, key_backups,
+	account_data, account_validity, admin, appservice, client, config, delegated_auth, email,
+	emergency, export, federation, globals, ipban, jwt, key_backups, ldap, login_attempts,
 	manager::Manager,
-	media, presence, pusher, resolver, rooms, sending, server_keys, service,
+	media, oidc, presence, pusher, ratelimit, registration_tokens, rendezvous, replica, resolver,
+	rooms, scheduler, sending, server_keys, server_notices, service,
 	service::{Args, Map, Service},
-	sync, transaction_ids, uiaa, updates, users,
+	spam_checker, sync, threepid, transaction_ids, uiaa, updates, users,
 };
 
 pub struct Services {
 	pub account_data: Arc<account_data::Service>,
+	pub account_validity: Arc<account_validity::Service>,
 	pub admin: Arc<admin::Service>,
 	pub appservice: Arc<appservice::Service>,
 	pub config: Arc<config::Service>,
+	pub delegated_auth: Arc<delegated_auth::Service>,
 	pub client: Arc<client::Service>,
+	pub email: Arc<email::Service>,
 	pub emergency: Arc<emergency::Service>,
+	pub export: Arc<export::Service>,
 	pub globals: Arc<globals::Service>,
+	pub ipban: Arc<ipban::Service>,
+	pub jwt: Arc<jwt::Service>,
 	pub key_backups: Arc<key_backups::Service>,
+	pub ldap: Arc<ldap::Service>,
+	pub login_attempts: Arc<login_attempts::Service>,
 	pub media: Arc<media::Service>,
+	pub oidc: Arc<oidc::Service>,
 	pub presence: Arc<presence::Service>,
 	pub pusher: Arc<pusher::Service>,
+	pub ratelimit: Arc<ratelimit::Service>,
+	pub registration_tokens: Arc<registration_tokens::Service>,
+	pub rendezvous: Arc<rendezvous::Service>,
+	pub replica: Arc<replica::Service>,
 	pub resolver: Arc<resolver::Service>,
 	pub rooms: rooms::Service,
+	pub scheduler: Arc<scheduler::Service>,
 	pub federation: Arc<federation::Service>,
 	pub sending: Arc<sending::Service>,
 	pub server_keys: Arc<server_keys::Service>,
+	pub server_notices: Arc<server_notices::Service>,
+	pub spam_checker: Arc<spam_checker::Service>,
 	pub sync: Arc<sync::Service>,
+	pub threepid: Arc<threepid::Service>,
 	pub transaction_ids: Arc<transaction_ids::Service>,
 	pub uiaa: Arc<uiaa::Service>,
 	pub updates: Arc<updates::Service>,
@@ -49,6 +68,7 @@ pub struct Services {
 impl Services {
 	#[allow(clippy::cognitive_complexity)]
 	pub async fn build(server: Arc<Server>) -> Result<Arc<Self>> {
+		systemd::status("Loading database");
 		let db = Database::open(&server).await?;
 		let service: Arc<Map> = Arc::new(RwLock::new(BTreeMap::new()));
 		macro_rules! build {
@@ -65,27 +85,43 @@ impl Services {
 
 		Ok(Arc::new(Self {
 			account_data: build!(account_data::Service),
+			account_validity: build!(account_validity::Service),
 			admin: build!(admin::Service),
 			appservice: build!(appservice::Service),
 			resolver: build!(resolver::Service),
 			client: build!(client::Service),
 			config: build!(config::Service),
+			delegated_auth: build!(delegated_auth::Service),
+			email: build!(email::Service),
 			emergency: build!(emergency::Service),
+			export: build!(export::Service),
 			globals: build!(globals::Service),
+			ipban: build!(ipban::Service),
+			jwt: build!(jwt::Service),
 			key_backups: build!(key_backups::Service),
+			ldap: build!(ldap::Service),
+			login_attempts: build!(login_attempts::Service),
 			media: build!(media::Service),
+			oidc: build!(oidc::Service),
 			presence: build!(presence::Service),
 			pusher: build!(pusher::Service),
+			ratelimit: build!(ratelimit::Service),
+			registration_tokens: build!(registration_tokens::Service),
+			rendezvous: build!(rendezvous::Service),
+			replica: build!(replica::Service),
 			rooms: rooms::Service {
 				alias: build!(rooms::alias::Service),
 				auth_chain: build!(rooms::auth_chain::Service),
+				cleanup: build!(rooms::cleanup::Service),
 				directory: build!(rooms::directory::Service),
 				event_handler: build!(rooms::event_handler::Service),
 				lazy_loading: build!(rooms::lazy_loading::Service),
 				metadata: build!(rooms::metadata::Service),
 				outlier: build!(rooms::outlier::Service),
 				pdu_metadata: build!(rooms::pdu_metadata::Service),
+				purge: build!(rooms::purge::Service),
 				read_receipt: build!(rooms::read_receipt::Service),
+				retention: build!(rooms::retention::Service),
 				search: build!(rooms::search::Service),
 				short: build!(rooms::short::Service),
 				spaces: build!(rooms::spaces::Service),
@@ -95,13 +131,18 @@ impl Services {
 				state_compressor: build!(rooms::state_compressor::Service),
 				threads: build!(rooms::threads::Service),
 				timeline: build!(rooms::timeline::Service),
+				tombstone: build!(rooms::tombstone::Service),
 				typing: build!(rooms::typing::Service),
 				user: build!(rooms::user::Service),
 			},
+			scheduler: build!(scheduler::Service),
 			federation: build!(federation::Service),
 			sending: build!(sending::Service),
 			server_keys: build!(server_keys::Service),
+			server_notices: build!(server_notices::Service),
+			spam_checker: build!(spam_checker::Service),
 			sync: build!(sync::Service),
+			threepid: build!(threepid::Service),
 			transaction_ids: build!(transaction_ids::Service),
 			uiaa: build!(uiaa::Service),
 			updates: build!(updates::Service),
@@ -119,6 +160,7 @@ impl Services {
 
 		self.admin.set_services(Some(Arc::clone(self)).as_ref());
 		super::migrations::migrations(self).await?;
+		systemd::status("Starting services");
 		self.manager
 			.lock()
 			.await
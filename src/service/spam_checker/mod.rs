@@ -0,0 +1,184 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use conduwuit::{debug_info, err, implement, warn, Err, Result, Server};
+use http::header::CONTENT_TYPE;
+use ruma::{RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{client, Dep};
+
+/// Asks an external HTTP service whether users may take certain actions, in
+/// the manner of Synapse's spam checker modules.
+pub struct Service {
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+}
+
+/// Response of the spam checker to a callback.
+#[derive(Deserialize)]
+struct Verdict {
+	allow: bool,
+
+	#[serde(default)]
+	reason: Option<String>,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether `inviter` may invite `invitee` to `room_id`.
+#[implement(Service)]
+pub async fn user_may_invite(
+	&self,
+	inviter: &UserId,
+	invitee: &UserId,
+	room_id: &RoomId,
+) -> Result {
+	self.check(
+		"user_may_invite",
+		json!({
+			"inviter": inviter,
+			"invitee": invitee,
+			"room_id": room_id,
+		}),
+	)
+	.await
+}
+
+/// Whether `user_id` may create a room.
+#[implement(Service)]
+pub async fn user_may_create_room(&self, user_id: &UserId) -> Result {
+	self.check("user_may_create_room", json!({ "user_id": user_id }))
+		.await
+}
+
+/// Whether `event` may be sent. Events from local users are given before
+/// they are built, with only their room, sender, type, state key and content.
+#[implement(Service)]
+pub async fn check_event_for_spam<E>(&self, event: &E) -> Result
+where
+	E: Serialize + Send + Sync,
+{
+	self.check("check_event_for_spam", json!({ "event": event }))
+		.await
+}
+
+/// Whether a federated `event` was explicitly refused by the spam checker.
+/// Other servers may already have accepted the event, so when the spam
+/// checker can't be asked it is accepted regardless of `fail_open`.
+#[implement(Service)]
+pub async fn federated_event_is_spam<E>(&self, event: &E) -> bool
+where
+	E: Serialize + Send + Sync,
+{
+	let Some(url) = self.url("check_event_for_spam") else {
+		return false;
+	};
+
+	let body = json!({ "event": event });
+	match self.call(&url, &body).await {
+		| Ok(Verdict { allow, reason }) => {
+			if !allow {
+				debug_info!(?reason, %body, "Federated event refused by spam checker");
+			}
+
+			!allow
+		},
+		| Err(e) => {
+			warn!("Spam checker failed, accepting federated event: {e}");
+			false
+		},
+	}
+}
+
+/// Whether an account may be registered with the localpart `username` from
+/// `ip`.
+#[implement(Service)]
+pub async fn check_registration(
+	&self,
+	username: &str,
+	ip: IpAddr,
+	appservice_id: Option<&str>,
+) -> Result {
+	self.check(
+		"check_registration",
+		json!({
+			"username": username,
+			"ip": ip,
+			"appservice_id": appservice_id,
+		}),
+	)
+	.await
+}
+
+#[implement(Service)]
+#[tracing::instrument(skip(self, body), level = "debug")]
+async fn check(&self, callback: &str, body: JsonValue) -> Result {
+	let config = &self.services.server.config.spam_checker;
+	let Some(url) = self.url(callback) else {
+		return Ok(());
+	};
+
+	match self.call(&url, &body).await {
+		| Ok(Verdict { allow: true, .. }) => Ok(()),
+		| Ok(Verdict { allow: false, reason }) => {
+			debug_info!(?reason, %body, "Refused by spam checker");
+			let reason = reason
+				.as_deref()
+				.unwrap_or("This request has been refused as spam.");
+			Err!(Request(Forbidden("{reason}")))
+		},
+		| Err(e) if config.fail_open => {
+			warn!("Spam checker failed, allowing {callback}: {e}");
+			Ok(())
+		},
+		| Err(e) => {
+			warn!("Spam checker failed, refusing {callback}: {e}");
+			Err!(Request(Unknown("Unable to check this request for spam; try again later.")))
+		},
+	}
+}
+
+#[implement(Service)]
+fn url(&self, callback: &str) -> Option<String> {
+	let url = self.services.server.config.spam_checker.url.as_ref()?;
+
+	Some(format!("{}/{callback}", url.as_str().trim_end_matches('/')))
+}
+
+#[implement(Service)]
+async fn call(&self, url: &str, body: &JsonValue) -> Result<Verdict> {
+	let config = &self.services.server.config.spam_checker;
+	let mut request = self
+		.services
+		.client
+		.default
+		.post(url)
+		.timeout(Duration::from_millis(config.timeout))
+		.header(CONTENT_TYPE, "application/json")
+		.body(body.to_string());
+
+	if let Some(token) = &config.token {
+		request = request.bearer_auth(token);
+	}
+
+	let response = request.send().await?.error_for_status()?.bytes().await?;
+
+	serde_json::from_slice(&response)
+		.map_err(|e| err!(BadServerResponse("Invalid spam checker response: {e}")))
+}
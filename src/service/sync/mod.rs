@@ -2,6 +2,7 @@ mod watch;
 
 use std::{
 	collections::{BTreeMap, BTreeSet},
+	fmt::Write,
 	sync::{Arc, Mutex, Mutex as StdMutex},
 };
 
@@ -95,6 +96,15 @@ impl crate::Service for Service {
 		}))
 	}
 
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let connections = self.connections.lock()?.len();
+		let snake_connections = self.snake_connections.lock()?.len();
+		writeln!(out, "sliding_sync_connections: {connections}")?;
+		writeln!(out, "simplified_sliding_sync_connections: {snake_connections}")?;
+
+		Ok(())
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
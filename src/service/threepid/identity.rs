@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use conduwuit::{err, implement, Err, Result};
+use ruma::{
+	events::room::{member::SignedContent, third_party_invite::RoomThirdPartyInviteEventContent},
+	signatures::{PublicKeyMap, PublicKeySet},
+	thirdparty::Medium,
+	CanonicalJsonObject, CanonicalJsonValue, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId,
+	OwnedUserId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// What an identity server is told about a room when it stores an invite for
+/// a third-party identifier, to put in the invite it sends.
+#[derive(Debug, Serialize)]
+pub struct InviteDetails {
+	pub medium: Medium,
+	pub address: String,
+	pub room_id: OwnedRoomId,
+	pub sender: OwnedUserId,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub room_alias: Option<OwnedRoomAliasId>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub room_avatar_url: Option<OwnedMxcUri>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub room_join_rules: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub room_name: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sender_display_name: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sender_avatar_url: Option<OwnedMxcUri>,
+}
+
+/// An invite stored by an identity server, which is sent into the room as an
+/// `m.room.third_party_invite` event.
+#[derive(Debug, Deserialize)]
+pub struct StoredInvite {
+	pub token: String,
+	pub public_keys: Vec<ruma::events::room::third_party_invite::PublicKey>,
+	pub display_name: String,
+}
+
+#[derive(Deserialize)]
+struct HashDetails {
+	algorithms: Vec<String>,
+	lookup_pepper: String,
+}
+
+#[derive(Serialize)]
+struct LookupRequest<'a> {
+	addresses: Vec<String>,
+	algorithm: &'a str,
+	pepper: &'a str,
+}
+
+#[derive(Deserialize)]
+struct LookupResponse {
+	#[serde(default)]
+	mappings: BTreeMap<String, OwnedUserId>,
+}
+
+/// Whether local users may invite by third-party identifier through the
+/// identity server; see `trusted_identity_servers`.
+#[implement(super::Service)]
+#[must_use]
+pub fn is_trusted_identity_server(&self, id_server: &str) -> bool {
+	self.services
+		.server
+		.config
+		.trusted_identity_servers
+		.contains(id_server)
+}
+
+/// Looks up the Matrix user a third-party identifier is bound to at an
+/// identity server, with the hashed lookup of the identity service API v2.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, id_access_token, address), level = "debug")]
+pub async fn lookup_threepid(
+	&self,
+	id_server: &str,
+	id_access_token: &str,
+	medium: &Medium,
+	address: &str,
+) -> Result<Option<OwnedUserId>> {
+	let details: HashDetails = self
+		.identity_request(id_server, id_access_token, "hash_details", None::<&()>)
+		.await?;
+
+	if !details
+		.algorithms
+		.iter()
+		.any(|algorithm| algorithm == "sha256")
+	{
+		return Err!(BadServerResponse(
+			"Identity server {id_server} does not support sha256 lookups."
+		));
+	}
+
+	let hash = URL_SAFE_NO_PAD.encode(Sha256::digest(format!(
+		"{address} {} {}",
+		medium.as_str(),
+		details.lookup_pepper
+	)));
+
+	let request = LookupRequest {
+		addresses: vec![hash.clone()],
+		algorithm: "sha256",
+		pepper: &details.lookup_pepper,
+	};
+
+	let response: LookupResponse = self
+		.identity_request(id_server, id_access_token, "lookup", Some(&request))
+		.await?;
+
+	Ok(response.mappings.get(&hash).cloned())
+}
+
+/// Has an identity server store an invite for a third-party identifier not
+/// bound to a Matrix user yet, which it sends to the identifier.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, id_access_token, invite), level = "debug")]
+pub async fn store_invite(
+	&self,
+	id_server: &str,
+	id_access_token: &str,
+	invite: &InviteDetails,
+) -> Result<StoredInvite> {
+	let stored: StoredInvite = self
+		.identity_request(id_server, id_access_token, "store-invite", Some(invite))
+		.await?;
+
+	if stored.public_keys.is_empty() {
+		return Err!(BadServerResponse(
+			"Identity server {id_server} stored an invite without public keys."
+		));
+	}
+
+	Ok(stored)
+}
+
+#[implement(super::Service)]
+async fn identity_request<B, T>(
+	&self,
+	id_server: &str,
+	id_access_token: &str,
+	endpoint: &str,
+	body: Option<&B>,
+) -> Result<T>
+where
+	B: Serialize + Sync,
+	T: DeserializeOwned,
+{
+	let url = Url::parse(&format!("https://{id_server}/_matrix/identity/v2/{endpoint}"))
+		.map_err(|e| err!(Request(InvalidParam("Invalid identity server {id_server}: {e}"))))?;
+
+	let client = &self.services.client.default;
+	let request = match body {
+		| Some(body) => client.post(url).json(body),
+		| None => client.get(url),
+	};
+
+	let body = request
+		.bearer_auth(id_access_token)
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	serde_json::from_slice(&body).map_err(|e| {
+		err!(BadServerResponse("Invalid response from identity server {id_server}: {e}"))
+	})
+}
+
+/// Checks that the `signed` object of a third-party invite was signed by the
+/// identity server with one of the public keys of the room's
+/// `m.room.third_party_invite` event.
+pub fn verify_third_party_signed(
+	signed: &SignedContent,
+	third_party_invite: &RoomThirdPartyInviteEventContent,
+) -> Result {
+	let public_keys = third_party_invite
+		.public_keys
+		.iter()
+		.flatten()
+		.map(|public_key| &public_key.public_key)
+		.chain(Some(&third_party_invite.public_key));
+
+	let mut object: CanonicalJsonObject = serde_json::to_value(signed)
+		.and_then(serde_json::from_value)
+		.map_err(|e| err!(Request(BadJson("Invalid third-party invite signature: {e}"))))?;
+
+	object.remove("signatures");
+
+	for public_key in public_keys {
+		for (entity, signatures) in &signed.signatures {
+			for (key_id, signature) in signatures {
+				let keys: PublicKeySet = [(key_id.to_string(), public_key.encode())].into();
+				let keys: PublicKeyMap = [(entity.to_string(), keys)].into();
+
+				let signatures = CanonicalJsonObject::from([(
+					key_id.to_string(),
+					CanonicalJsonValue::String(signature.clone()),
+				)]);
+
+				let mut object = object.clone();
+				object.insert(
+					"signatures".to_owned(),
+					CanonicalJsonValue::Object(CanonicalJsonObject::from([(
+						entity.to_string(),
+						CanonicalJsonValue::Object(signatures),
+					)])),
+				);
+
+				if ruma::signatures::verify_json(&keys, &object).is_ok() {
+					return Ok(());
+				}
+			}
+		}
+	}
+
+	Err!(Request(Forbidden(
+		"Third-party invite is not signed by the identity server of the room's invite."
+	)))
+}
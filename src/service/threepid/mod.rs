@@ -0,0 +1,365 @@
+mod identity;
+mod tests;
+
+use std::sync::Arc;
+
+use conduwuit::{
+	debug_info, err, implement,
+	utils::{self, stream::TryIgnore, ReadyExt},
+	Err, Result, Server,
+};
+use database::{Deserialized, Ignore, Interfix, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, MilliSecondsSinceUnixEpoch, OwnedSessionId, OwnedUserId, SessionId, UInt,
+	UserId,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+pub use self::identity::{verify_third_party_signed, InviteDetails, StoredInvite};
+use crate::{client, email, Dep};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+	email: Dep<email::Service>,
+}
+
+struct Data {
+	clientsecretsessionid_threepidsession: Arc<Map>,
+	threepid_userid: Arc<Map>,
+	useridthreepid_metadata: Arc<Map>,
+}
+
+/// A pending or completed validation of a third-party identifier, created when
+/// a client requests a validation token.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Session {
+	pub medium: Medium,
+	pub address: String,
+	pub send_attempt: UInt,
+	pub next_link: Option<String>,
+	pub expires_at: u64,
+	pub validated_at: Option<u64>,
+	token: String,
+}
+
+/// Path of the conduwuit-specific endpoint validation tokens are submitted to.
+pub const SUBMIT_TOKEN_PATH: &str = "/_conduwuit/client/3pid/email/submit_token";
+
+const SESSION_ID_LENGTH: usize = 32;
+
+const TOKEN_LENGTH: usize = 32;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+				email: args.depend::<email::Service>("email"),
+			},
+			db: Data {
+				clientsecretsessionid_threepidsession: args.db
+					["clientsecretsessionid_threepidsession"]
+					.clone(),
+				threepid_userid: args.db["threepid_userid"].clone(),
+				useridthreepid_metadata: args.db["useridthreepid_metadata"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Starts (or resumes) the validation of an email address, emailing a
+/// validation token to it. Retries with a `send_attempt` no greater than a
+/// previous attempt for the same client secret and address do not send
+/// another email, per the spec.
+#[implement(Service)]
+#[tracing::instrument(skip(self, client_secret, next_link), level = "debug")]
+pub async fn request_email_token(
+	&self,
+	client_secret: &ClientSecret,
+	address: &str,
+	send_attempt: UInt,
+	next_link: Option<String>,
+) -> Result<OwnedSessionId> {
+	if !self.services.email.is_enabled() {
+		return Err!(Request(ThreepidDenied("This server does not support email addresses.")));
+	}
+
+	let address = normalize_email(address)?;
+	let existing = self
+		.sessions(client_secret)
+		.ready_filter(|(_, session)| {
+			session.medium == Medium::Email && session.address == address
+		})
+		.boxed()
+		.next()
+		.await;
+
+	let sid = match existing {
+		| Some((sid, session)) if session.send_attempt >= send_attempt && !session.is_expired() =>
+			return Ok(sid),
+		| Some((sid, _)) => sid,
+		| None => OwnedSessionId::try_from(utils::random_string(SESSION_ID_LENGTH))
+			.expect("random string is a valid session ID"),
+	};
+
+	let expires_at = utils::millis_since_unix_epoch().saturating_add(
+		self.services
+			.server
+			.config
+			.threepid_validation_token_ttl
+			.saturating_mul(1000),
+	);
+
+	let session = Session {
+		medium: Medium::Email,
+		address: address.clone(),
+		send_attempt,
+		next_link,
+		expires_at,
+		validated_at: None,
+		token: utils::random_string(TOKEN_LENGTH),
+	};
+
+	let link = self.validation_link(client_secret, &sid, &session.token)?;
+	let body = format!(
+		"Hello,\n\nA request was made on {server_name} to verify this email address. To \
+		 confirm, open the following link:\n\n{link}\n\nIf you did not make this request, you \
+		 can safely ignore this email.\n",
+		server_name = self.services.server.name,
+	);
+
+	self.services
+		.email
+		.send(&address, "Verify your email address", body)
+		.await?;
+
+	let key = (client_secret, &sid);
+	self.db
+		.clientsecretsessionid_threepidsession
+		.put(key, Json(&session));
+
+	Ok(sid)
+}
+
+/// Marks a validation session as validated if the token matches.
+#[implement(Service)]
+#[tracing::instrument(skip(self, client_secret, token), level = "debug")]
+pub async fn submit_token(
+	&self,
+	client_secret: &ClientSecret,
+	sid: &SessionId,
+	token: &str,
+) -> Result<Session> {
+	let mut session = self.get_session(client_secret, sid).await?;
+	if session.token != token {
+		return Err!(Request(ThreepidAuthFailed("Validation token is incorrect.")));
+	}
+
+	if session.validated_at.is_none() {
+		session.validated_at = Some(utils::millis_since_unix_epoch());
+
+		let key = (client_secret, sid);
+		self.db
+			.clientsecretsessionid_threepidsession
+			.put(key, Json(&session));
+	}
+
+	Ok(session)
+}
+
+/// Gets a validation session which has been successfully validated.
+#[implement(Service)]
+pub async fn get_validated_session(
+	&self,
+	client_secret: &ClientSecret,
+	sid: &SessionId,
+) -> Result<Session> {
+	let session = self.get_session(client_secret, sid).await?;
+	if session.validated_at.is_none() {
+		return Err!(Request(ThreepidAuthFailed(
+			"Third party identifier has not been validated."
+		)));
+	}
+
+	Ok(session)
+}
+
+/// Gets a validation session, removing it instead if it has expired.
+#[implement(Service)]
+async fn get_session(&self, client_secret: &ClientSecret, sid: &SessionId) -> Result<Session> {
+	let key = (client_secret, sid);
+	let session: Session = self
+		.db
+		.clientsecretsessionid_threepidsession
+		.qry(&key)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(ThreepidNotFound("Validation session not found."))))?;
+
+	if session.is_expired() {
+		self.remove_session(client_secret, sid);
+		return Err!(Request(ThreepidAuthFailed("Validation session has expired.")));
+	}
+
+	Ok(session)
+}
+
+#[implement(Service)]
+pub fn remove_session(&self, client_secret: &ClientSecret, sid: &SessionId) {
+	let key = (client_secret, sid);
+	self.db.clientsecretsessionid_threepidsession.del(key);
+}
+
+#[implement(Service)]
+fn sessions<'a>(
+	&'a self,
+	client_secret: &'a ClientSecret,
+) -> impl Stream<Item = (OwnedSessionId, Session)> + Send + 'a {
+	type KeyVal<'a> = ((Ignore, &'a SessionId), Session);
+
+	let prefix = (client_secret, Interfix);
+	self.db
+		.clientsecretsessionid_threepidsession
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.map(|((_, sid), session): KeyVal<'_>| (sid.to_owned(), session))
+}
+
+#[implement(Service)]
+fn validation_link(
+	&self,
+	client_secret: &ClientSecret,
+	sid: &SessionId,
+	token: &str,
+) -> Result<Url> {
+	let config = &self.services.server.config;
+	let mut link = match config.well_known.client.clone() {
+		| Some(url) => url,
+		| None => Url::parse(&format!("https://{}", self.services.server.name))
+			.map_err(|e| err!("Failed to build validation link: {e}"))?,
+	};
+
+	link.set_path(SUBMIT_TOKEN_PATH);
+	link.query_pairs_mut()
+		.append_pair("sid", sid.as_str())
+		.append_pair("client_secret", client_secret.as_str())
+		.append_pair("token", token);
+
+	Ok(link)
+}
+
+/// Associates a validated third-party identifier with a local user.
+#[implement(Service)]
+pub async fn add(&self, user_id: &UserId, session: &Session) -> Result {
+	let validated_at = session.validated_at.expect("session was validated");
+
+	self.add_validated(user_id, &session.medium, &session.address, validated_at)
+		.await
+}
+
+/// Associates a third-party identifier validated by other means, such as an
+/// external directory, with a local user.
+#[implement(Service)]
+pub async fn add_validated(
+	&self,
+	user_id: &UserId,
+	medium: &Medium,
+	address: &str,
+	validated_at: u64,
+) -> Result {
+	if let Ok(owner) = self.find_user(medium, address).await {
+		if owner != user_id {
+			return Err!(Request(ThreepidInUse("Third party identifier is already in use.")));
+		}
+	}
+
+	let threepid = ThirdPartyIdentifier {
+		address: address.to_owned(),
+		medium: medium.clone(),
+		validated_at: MilliSecondsSinceUnixEpoch(UInt::new_saturating(validated_at)),
+		added_at: MilliSecondsSinceUnixEpoch::now(),
+	};
+
+	let key = (medium.as_str(), address);
+	self.db.threepid_userid.put(key, user_id);
+
+	let key = (user_id, medium.as_str(), address);
+	self.db.useridthreepid_metadata.put(key, Json(&threepid));
+
+	debug_info!(%user_id, %medium, "Added third party identifier");
+
+	Ok(())
+}
+
+/// Removes a third-party identifier from a local user.
+#[implement(Service)]
+pub async fn remove(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result {
+	let address = match medium {
+		| Medium::Email => normalize_email(address)?,
+		| _ => address.to_owned(),
+	};
+
+	if self.find_user(medium, &address).await.ok().as_deref() != Some(user_id) {
+		return Err!(Request(ThreepidNotFound("Third party identifier not found.")));
+	}
+
+	let key = (medium.as_str(), &address);
+	self.db.threepid_userid.del(key);
+
+	let key = (user_id, medium.as_str(), &address);
+	self.db.useridthreepid_metadata.del(key);
+
+	debug_info!(%user_id, %medium, "Removed third party identifier");
+
+	Ok(())
+}
+
+/// Finds the local user a third-party identifier is associated with.
+#[implement(Service)]
+pub async fn find_user(&self, medium: &Medium, address: &str) -> Result<OwnedUserId> {
+	let key = (medium.as_str(), address);
+	self.db.threepid_userid.qry(&key).await.deserialized()
+}
+
+/// Lists the third-party identifiers associated with a local user.
+#[implement(Service)]
+pub fn get_threepids<'a>(
+	&'a self,
+	user_id: &'a UserId,
+) -> impl Stream<Item = ThirdPartyIdentifier> + Send + 'a {
+	type KeyVal<'a> = ((Ignore, Ignore, Ignore), ThirdPartyIdentifier);
+
+	let prefix = (user_id, Interfix);
+	self.db
+		.useridthreepid_metadata
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.map(|(_, threepid): KeyVal<'_>| threepid)
+}
+
+impl Session {
+	#[inline]
+	#[must_use]
+	pub fn is_expired(&self) -> bool { self.expires_at < utils::millis_since_unix_epoch() }
+}
+
+/// Validates an email address, normalizing it to lowercase.
+pub fn normalize_email(address: &str) -> Result<String> {
+	address
+		.trim()
+		.parse::<lettre::Address>()
+		.map(|address| address.to_string().to_lowercase())
+		.map_err(|e| err!(Request(InvalidParam("Invalid email address: {e}"))))
+}
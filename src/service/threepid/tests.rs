@@ -0,0 +1,115 @@
+#![cfg(test)]
+
+use ruma::{
+	events::room::{member::SignedContent, third_party_invite::RoomThirdPartyInviteEventContent},
+	serde::Base64,
+	signatures::{sign_json, Ed25519KeyPair},
+	CanonicalJsonObject, CanonicalJsonValue,
+};
+use serde_json::json;
+
+use super::verify_third_party_signed;
+
+const IDENTITY_SERVER: &str = "id.example.org";
+
+fn keypair(version: &str) -> Ed25519KeyPair {
+	let der = Ed25519KeyPair::generate()
+		.expect("keypair generated")
+		.to_vec();
+
+	Ed25519KeyPair::from_der(&der, version.to_owned()).expect("keypair loaded")
+}
+
+fn public_key(key: &Ed25519KeyPair) -> String { Base64::new(key.public_key().to_vec()).encode() }
+
+fn invite(public_key: &str, public_keys: &[&str]) -> RoomThirdPartyInviteEventContent {
+	let public_keys: Vec<_> = public_keys
+		.iter()
+		.map(|public_key| json!({ "public_key": public_key }))
+		.collect();
+
+	serde_json::from_value(json!({
+		"display_name": "a...@example.org",
+		"key_validity_url": "https://id.example.org/_matrix/identity/v2/pubkey/isvalid",
+		"public_key": public_key,
+		"public_keys": public_keys,
+	}))
+	.expect("valid invite content")
+}
+
+fn signed(key: &Ed25519KeyPair, mxid: &str, token: &str) -> CanonicalJsonObject {
+	let mut object: CanonicalJsonObject =
+		serde_json::from_value(json!({ "mxid": mxid, "token": token }))
+			.expect("valid canonical JSON");
+
+	sign_json(IDENTITY_SERVER, key, &mut object).expect("object signed");
+
+	object
+}
+
+fn content(object: CanonicalJsonObject) -> SignedContent {
+	serde_json::to_value(object)
+		.and_then(serde_json::from_value)
+		.expect("valid signed content")
+}
+
+#[test]
+fn signed_with_invite_key() {
+	let key = keypair("0");
+	let invite = invite(&public_key(&key), &[]);
+	let signed = content(signed(&key, "@alice:example.com", "abc"));
+
+	verify_third_party_signed(&signed, &invite).expect("signature verified");
+}
+
+#[test]
+fn signed_with_additional_key() {
+	let key = keypair("0");
+	let other = keypair("1");
+	let invite = invite(&public_key(&other), &[&public_key(&key)]);
+	let signed = content(signed(&key, "@alice:example.com", "abc"));
+
+	verify_third_party_signed(&signed, &invite).expect("signature verified");
+}
+
+#[test]
+fn signed_with_unknown_key() {
+	let key = keypair("0");
+	let other = keypair("0");
+	let invite = invite(&public_key(&other), &[]);
+	let signed = content(signed(&key, "@alice:example.com", "abc"));
+
+	verify_third_party_signed(&signed, &invite).expect_err("signature rejected");
+}
+
+#[test]
+fn tampered_mxid() {
+	let key = keypair("0");
+	let invite = invite(&public_key(&key), &[]);
+	let mut object = signed(&key, "@alice:example.com", "abc");
+	object
+		.insert("mxid".to_owned(), CanonicalJsonValue::String("@mallory:example.com".to_owned()));
+
+	verify_third_party_signed(&content(object), &invite).expect_err("signature rejected");
+}
+
+#[test]
+fn tampered_token() {
+	let key = keypair("0");
+	let invite = invite(&public_key(&key), &[]);
+	let mut object = signed(&key, "@alice:example.com", "abc");
+	object.insert("token".to_owned(), CanonicalJsonValue::String("xyz".to_owned()));
+
+	verify_third_party_signed(&content(object), &invite).expect_err("signature rejected");
+}
+
+#[test]
+fn unsigned() {
+	let key = keypair("0");
+	let invite = invite(&public_key(&key), &[]);
+	let mut object = signed(&key, "@alice:example.com", "abc");
+	object
+		.insert("signatures".to_owned(), CanonicalJsonValue::Object(CanonicalJsonObject::new()));
+
+	verify_third_party_signed(&content(object), &invite).expect_err("signature rejected");
+}
@@ -0,0 +1,74 @@
+use conduwuit::{debug_warn, err, implement, Result};
+use serde::Deserialize;
+use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
+
+const RECAPTCHA_SITEVERIFY: &str = "https://www.google.com/recaptcha/api/siteverify";
+const HCAPTCHA_SITEVERIFY: &str = "https://api.hcaptcha.com/siteverify";
+const TURNSTILE_SITEVERIFY: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// Response of the siteverify API; common to all supported providers.
+#[derive(Deserialize)]
+struct SiteVerify {
+	success: bool,
+
+	#[serde(default, rename = "error-codes")]
+	error_codes: Vec<String>,
+}
+
+/// Parameters for clients to display the `m.login.recaptcha` stage.
+#[implement(super::Service)]
+pub fn captcha_params(&self) -> Result<Box<RawJsonValue>> {
+	let site_key = self
+		.services
+		.server
+		.config
+		.captcha
+		.site_key
+		.as_deref()
+		.ok_or_else(|| err!(FeatureDisabled("captcha")))?;
+
+	to_raw_value(&serde_json::json!({
+		"m.login.recaptcha": {
+			"public_key": site_key,
+		},
+	}))
+	.map_err(Into::into)
+}
+
+/// Verifies a CAPTCHA response from a client with the configured provider.
+#[implement(super::Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub(super) async fn verify_captcha(&self, response: &str) -> Result<bool> {
+	let config = &self.services.server.config.captcha;
+	let secret = config
+		.secret_key
+		.as_deref()
+		.ok_or_else(|| err!(FeatureDisabled("captcha")))?;
+
+	let url = match config.provider.as_str() {
+		| "hcaptcha" => HCAPTCHA_SITEVERIFY,
+		| "turnstile" => TURNSTILE_SITEVERIFY,
+		| _ => RECAPTCHA_SITEVERIFY,
+	};
+
+	let body = self
+		.services
+		.client
+		.default
+		.post(url)
+		.form(&[("secret", secret), ("response", response)])
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let result: SiteVerify = serde_json::from_slice(&body)
+		.map_err(|e| err!(BadServerResponse("Invalid CAPTCHA verification response: {e}")))?;
+
+	if !result.success {
+		debug_warn!(errors = ?result.error_codes, "CAPTCHA verification failed");
+	}
+
+	Ok(result.success)
+}
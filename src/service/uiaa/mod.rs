@@ -1,32 +1,48 @@
+mod captcha;
+
 use std::{
 	collections::BTreeMap,
+	fmt::Write,
 	sync::{Arc, RwLock},
+	time::{Duration, Instant},
 };
 
 use conduwuit::{
 	err, error, implement, utils,
 	utils::{hash, string::EMPTY},
-	Error, Result,
+	Error, Result, Server,
 };
 use database::{Deserialized, Json, Map};
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{
+			AuthData, AuthType, EmailIdentity, Password, ReCaptcha, ThirdpartyIdCredentials,
+			UiaaInfo, UserIdentifier,
+		},
 	},
+	thirdparty::Medium,
 	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
 };
 
-use crate::{globals, users, Dep};
+use crate::{client, globals, ldap, login_attempts, registration_tokens, threepid, users, Dep};
 
 pub struct Service {
 	userdevicesessionid_uiaarequest: RwLock<RequestMap>,
+	userdevicesessionid_threepidcreds: RwLock<ThreepidCredsMap>,
+	userdevicesessionid_registrationtoken: RwLock<RegistrationTokenMap>,
 	db: Data,
 	services: Services,
 }
 
 struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
 	globals: Dep<globals::Service>,
+	ldap: Dep<ldap::Service>,
+	login_attempts: Dep<login_attempts::Service>,
+	registration_tokens: Dep<registration_tokens::Service>,
+	threepid: Dep<threepid::Service>,
 	users: Dep<users::Service>,
 }
 
@@ -36,23 +52,49 @@ struct Data {
 
 type RequestMap = BTreeMap<RequestKey, CanonicalJsonValue>;
 type RequestKey = (OwnedUserId, OwnedDeviceId, String);
+type ThreepidCredsMap = BTreeMap<RequestKey, (ThirdpartyIdCredentials, Instant)>;
+type RegistrationTokenMap = BTreeMap<RequestKey, (String, Instant)>;
 
 pub const SESSION_ID_LENGTH: usize = 32;
 
+/// How long the outcome of a completed stage is kept for the request which
+/// finishes the session; sessions abandoned for longer lose it.
+const STAGE_OUTCOME_TTL: Duration = Duration::from_secs(60 * 60);
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			userdevicesessionid_uiaarequest: RwLock::new(RequestMap::new()),
+			userdevicesessionid_threepidcreds: RwLock::new(ThreepidCredsMap::new()),
+			userdevicesessionid_registrationtoken: RwLock::new(RegistrationTokenMap::new()),
 			db: Data {
 				userdevicesessionid_uiaainfo: args.db["userdevicesessionid_uiaainfo"].clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
 				globals: args.depend::<globals::Service>("globals"),
+				ldap: args.depend::<ldap::Service>("ldap"),
+				login_attempts: args.depend::<login_attempts::Service>("login_attempts"),
+				registration_tokens: args
+					.depend::<registration_tokens::Service>("registration_tokens"),
+				threepid: args.depend::<threepid::Service>("threepid"),
 				users: args.depend::<users::Service>("users"),
 			},
 		}))
 	}
 
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let uiaarequests = self.userdevicesessionid_uiaarequest.read()?.len();
+		let threepidcreds = self.userdevicesessionid_threepidcreds.read()?.len();
+		let registrationtokens = self.userdevicesessionid_registrationtoken.read()?.len();
+		writeln!(out, "uiaa_requests: {uiaarequests}")?;
+		writeln!(out, "uiaa_threepid_creds: {threepidcreds}")?;
+		writeln!(out, "uiaa_registration_tokens: {registrationtokens}")?;
+
+		Ok(())
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -136,29 +178,50 @@ pub async fn try_auth(
 			)
 			.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "User ID is invalid."))?;
 
-			// Check if password is correct
-			if let Ok(hash) = self.services.users.password_hash(&user_id).await {
-				let hash_matches = hash::verify_password(password, &hash).is_ok();
-				if !hash_matches {
-					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
-						kind: ErrorKind::forbidden(),
-						message: "Invalid username or password.".to_owned(),
-					});
-					return Ok((false, uiaainfo));
-				}
+			self.services.login_attempts.check(Some(&user_id), None)?;
+
+			// Check if password is correct, with the LDAP server if enabled since
+			// accounts it created have no local password
+			let accepted = match self
+				.services
+				.ldap
+				.check_password(&user_id, password)
+				.await?
+			{
+				| Some(accepted) => Some(accepted),
+				| None => self
+					.services
+					.users
+					.password_hash(&user_id)
+					.await
+					.ok()
+					.map(|hash| hash::verify_password(password, &hash).is_ok()),
+			};
+
+			if accepted == Some(false) {
+				self.services
+					.login_attempts
+					.record_failure(Some(&user_id), None);
+
+				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+					kind: ErrorKind::forbidden(),
+					message: "Invalid username or password.".to_owned(),
+				});
+				return Ok((false, uiaainfo));
 			}
 
 			// Password was correct! Let's add it to `completed`
 			uiaainfo.completed.push(AuthType::Password);
 		},
 		| AuthData::RegistrationToken(t) => {
-			if self
-				.services
-				.globals
-				.registration_token
-				.as_ref()
-				.is_some_and(|reg_token| t.token.trim() == reg_token)
-			{
+			let token = t.token.trim();
+			if self.services.registration_tokens.is_valid(token).await {
+				self.set_registration_token(
+					user_id,
+					device_id,
+					uiaainfo.session.as_ref().expect("session is always set"),
+					token,
+				);
 				uiaainfo.completed.push(AuthType::RegistrationToken);
 			} else {
 				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
@@ -168,6 +231,41 @@ pub async fn try_auth(
 				return Ok((false, uiaainfo));
 			}
 		},
+		| AuthData::ReCaptcha(ReCaptcha { response, .. }) => {
+			if !self.verify_captcha(response).await? {
+				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+					kind: ErrorKind::forbidden(),
+					message: "CAPTCHA verification failed.".to_owned(),
+				});
+				return Ok((false, uiaainfo));
+			}
+
+			uiaainfo.completed.push(AuthType::ReCaptcha);
+		},
+		| AuthData::EmailIdentity(EmailIdentity { thirdparty_id_creds: creds, .. }) => {
+			let validated = self
+				.services
+				.threepid
+				.get_validated_session(&creds.client_secret, &creds.sid)
+				.await
+				.is_ok_and(|session| session.medium == Medium::Email);
+
+			if !validated {
+				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+					kind: ErrorKind::ThreepidAuthFailed,
+					message: "Email address has not been validated.".to_owned(),
+				});
+				return Ok((false, uiaainfo));
+			}
+
+			self.set_threepid_creds(
+				user_id,
+				device_id,
+				uiaainfo.session.as_ref().expect("session is always set"),
+				creds,
+			);
+			uiaainfo.completed.push(AuthType::EmailIdentity);
+		},
 		| AuthData::Dummy(_) => {
 			uiaainfo.completed.push(AuthType::Dummy);
 		},
@@ -243,6 +341,78 @@ pub fn get_uiaa_request(
 		.cloned()
 }
 
+#[implement(Service)]
+fn set_threepid_creds(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	session: &str,
+	creds: &ThirdpartyIdCredentials,
+) {
+	let key = (user_id.to_owned(), device_id.to_owned(), session.to_owned());
+	let mut threepidcreds = self
+		.userdevicesessionid_threepidcreds
+		.write()
+		.expect("locked for writing");
+
+	threepidcreds.retain(|_, (_, set_at)| set_at.elapsed() < STAGE_OUTCOME_TTL);
+	threepidcreds.insert(key, (creds.to_owned(), Instant::now()));
+}
+
+/// Takes the credentials of the third party identifier validated to complete
+/// an `m.login.email.identity` stage of the session, if any.
+#[implement(Service)]
+pub fn take_threepid_creds(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	session: &str,
+) -> Option<ThirdpartyIdCredentials> {
+	let key = (user_id.to_owned(), device_id.to_owned(), session.to_owned());
+	self.userdevicesessionid_threepidcreds
+		.write()
+		.expect("locked for writing")
+		.remove(&key)
+		.filter(|(_, set_at)| set_at.elapsed() < STAGE_OUTCOME_TTL)
+		.map(|(creds, _)| creds)
+}
+
+#[implement(Service)]
+fn set_registration_token(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	session: &str,
+	token: &str,
+) {
+	let key = (user_id.to_owned(), device_id.to_owned(), session.to_owned());
+	let mut registrationtokens = self
+		.userdevicesessionid_registrationtoken
+		.write()
+		.expect("locked for writing");
+
+	registrationtokens.retain(|_, (_, set_at)| set_at.elapsed() < STAGE_OUTCOME_TTL);
+	registrationtokens.insert(key, (token.to_owned(), Instant::now()));
+}
+
+/// Takes the registration token accepted to complete an
+/// `m.login.registration_token` stage of the session, if any.
+#[implement(Service)]
+pub fn take_registration_token(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	session: &str,
+) -> Option<String> {
+	let key = (user_id.to_owned(), device_id.to_owned(), session.to_owned());
+	self.userdevicesessionid_registrationtoken
+		.write()
+		.expect("locked for writing")
+		.remove(&key)
+		.filter(|(_, set_at)| set_at.elapsed() < STAGE_OUTCOME_TTL)
+		.map(|(token, _)| token)
+}
+
 #[implement(Service)]
 fn update_uiaa_session(
 	&self,
@@ -60,7 +60,9 @@ impl crate::Service for Service {
 
 	#[tracing::instrument(skip_all, name = "updates", level = "debug")]
 	async fn worker(self: Arc<Self>) -> Result<()> {
-		if !self.services.globals.allow_check_for_updates() {
+		if !self.services.globals.allow_check_for_updates()
+			|| self.services.globals.is_read_only()
+		{
 			debug!("Disabling update check");
 			return Ok(());
 		}
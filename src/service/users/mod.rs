@@ -1,7 +1,10 @@
-use std::{collections::BTreeMap, mem, mem::size_of, sync::Arc};
+mod tests;
 
+use std::{collections::BTreeMap, mem, mem::size_of, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
 use conduwuit::{
-	debug_warn, err, trace,
+	debug, debug_info, debug_warn, err, trace,
 	utils::{self, stream::TryIgnore, string::Unquoted, ReadyExt},
 	Err, Error, Result, Server,
 };
@@ -18,10 +21,15 @@ use ruma::{
 	OneTimeKeyName, OwnedDeviceId, OwnedKeyId, OwnedMxcUri, OwnedUserId, RoomId, UInt, UserId,
 };
 use serde_json::json;
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
 
 use crate::{account_data, admin, globals, rooms, Dep};
 
 pub struct Service {
+	interrupt: Notify,
 	services: Services,
 	db: Data,
 }
@@ -36,32 +44,58 @@ struct Services {
 	state_cache: Dep<rooms::state_cache::Service>,
 }
 
+/// How long a user may replace their cross-signing keys without
+/// user-interactive authentication once an admin has allowed it.
+const CROSS_SIGNING_RESET_WINDOW: Duration = Duration::from_secs(600);
+
+const ADMIN_LOGIN_DEVICE_ID_LENGTH: usize = 10;
+
+const ADMIN_LOGIN_TOKEN_LENGTH: usize = 32;
+
+/// How often devices of expired admin logins are removed.
+const ADMIN_LOGIN_CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Stored in place of a password hash for accounts authenticated by an
+/// external provider. It is not a valid hash, so password logins always fail,
+/// and it is not empty, which would mark the account deactivated.
+pub const EXTERNAL_PASSWORD: &str = "!external";
+
 struct Data {
 	keychangeid_userid: Arc<Map>,
 	keyid_key: Arc<Map>,
 	onetimekeyid_onetimekeys: Arc<Map>,
 	openidtoken_expiresatuserid: Arc<Map>,
 	logintoken_expiresatuserid: Arc<Map>,
+	refreshtoken_userdeviceid: Arc<Map>,
 	todeviceid_events: Arc<Map>,
 	token_userdeviceid: Arc<Map>,
+	userdeviceid_adminloginexpiresat: Arc<Map>,
 	userdeviceid_metadata: Arc<Map>,
+	userdeviceid_refreshtoken: Arc<Map>,
 	userdeviceid_token: Arc<Map>,
+	userdeviceid_tokenexpiresat: Arc<Map>,
 	userfilterid_filter: Arc<Map>,
+	userid_crosssigningresetexpiresat: Arc<Map>,
 	userid_avatarurl: Arc<Map>,
 	userid_blurhash: Arc<Map>,
+	userid_createdat: Arc<Map>,
 	userid_devicelistversion: Arc<Map>,
 	userid_displayname: Arc<Map>,
+	userid_erased: Arc<Map>,
 	userid_lastonetimekeyupdate: Arc<Map>,
 	userid_masterkeyid: Arc<Map>,
 	userid_password: Arc<Map>,
 	userid_selfsigningkeyid: Arc<Map>,
+	userid_suspended: Arc<Map>,
 	userid_usersigningkeyid: Arc<Map>,
 	useridprofilekey_value: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
 			services: Services {
 				server: args.server.clone(),
 				db: args.db.clone(),
@@ -78,25 +112,57 @@ impl crate::Service for Service {
 				onetimekeyid_onetimekeys: args.db["onetimekeyid_onetimekeys"].clone(),
 				openidtoken_expiresatuserid: args.db["openidtoken_expiresatuserid"].clone(),
 				logintoken_expiresatuserid: args.db["logintoken_expiresatuserid"].clone(),
+				refreshtoken_userdeviceid: args.db["refreshtoken_userdeviceid"].clone(),
 				todeviceid_events: args.db["todeviceid_events"].clone(),
 				token_userdeviceid: args.db["token_userdeviceid"].clone(),
+				userdeviceid_adminloginexpiresat: args.db["userdeviceid_adminloginexpiresat"]
+					.clone(),
 				userdeviceid_metadata: args.db["userdeviceid_metadata"].clone(),
+				userdeviceid_refreshtoken: args.db["userdeviceid_refreshtoken"].clone(),
 				userdeviceid_token: args.db["userdeviceid_token"].clone(),
+				userdeviceid_tokenexpiresat: args.db["userdeviceid_tokenexpiresat"].clone(),
 				userfilterid_filter: args.db["userfilterid_filter"].clone(),
+				userid_crosssigningresetexpiresat: args.db["userid_crosssigningresetexpiresat"]
+					.clone(),
 				userid_avatarurl: args.db["userid_avatarurl"].clone(),
 				userid_blurhash: args.db["userid_blurhash"].clone(),
+				userid_createdat: args.db["userid_createdat"].clone(),
 				userid_devicelistversion: args.db["userid_devicelistversion"].clone(),
 				userid_displayname: args.db["userid_displayname"].clone(),
+				userid_erased: args.db["userid_erased"].clone(),
 				userid_lastonetimekeyupdate: args.db["userid_lastonetimekeyupdate"].clone(),
 				userid_masterkeyid: args.db["userid_masterkeyid"].clone(),
 				userid_password: args.db["userid_password"].clone(),
 				userid_selfsigningkeyid: args.db["userid_selfsigningkeyid"].clone(),
+				userid_suspended: args.db["userid_suspended"].clone(),
 				userid_usersigningkeyid: args.db["userid_usersigningkeyid"].clone(),
 				useridprofilekey_value: args.db["useridprofilekey_value"].clone(),
 			},
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		if self.services.globals.is_read_only() {
+			debug!("Admin login cleanup is disabled");
+			return Ok(());
+		}
+
+		let mut i = interval(ADMIN_LOGIN_CLEANUP_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.remove_expired_admin_logins().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -124,9 +190,33 @@ impl Service {
 	}
 
 	/// Create a new user account on this homeserver.
-	#[inline]
 	pub fn create(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
-		self.set_password(user_id, password)
+		self.set_password(user_id, password)?;
+		self.db
+			.userid_createdat
+			.raw_put(user_id, utils::millis_since_unix_epoch());
+
+		Ok(())
+	}
+
+	/// Create a new account for a user authenticated by an external provider,
+	/// such as SSO, LDAP or JWT, which has no local password.
+	pub fn create_external(&self, user_id: &UserId) {
+		self.db.userid_password.insert(user_id, EXTERNAL_PASSWORD);
+		self.db
+			.userid_createdat
+			.raw_put(user_id, utils::millis_since_unix_epoch());
+	}
+
+	/// When the account was created, in milliseconds since the unix epoch.
+	/// Unknown for accounts created before this was recorded.
+	pub async fn created_at(&self, user_id: &UserId) -> Option<u64> {
+		self.db
+			.userid_createdat
+			.get(user_id)
+			.await
+			.deserialized()
+			.ok()
 	}
 
 	/// Deactivate account
@@ -157,7 +247,7 @@ impl Service {
 		self.db
 			.userid_password
 			.get(user_id)
-			.map_ok(|val| val.is_empty())
+			.map_ok(|val| is_deactivated_password(&val))
 			.map_err(|_| err!(Request(NotFound("User does not exist."))))
 			.await
 	}
@@ -172,6 +262,43 @@ impl Service {
 		self.services.globals.user_is_local(user_id) && self.is_active(user_id).await
 	}
 
+	/// Suspend account (MSC3823). Unlike deactivation the account keeps its
+	/// devices and data, but may not log in or send events other than leaving
+	/// rooms and redacting until it is unsuspended.
+	pub fn suspend(&self, user_id: &UserId) {
+		self.db
+			.userid_suspended
+			.raw_put(user_id, utils::millis_since_unix_epoch());
+	}
+
+	/// Lift a suspension, returning whether the account was suspended.
+	pub async fn unsuspend(&self, user_id: &UserId) -> bool {
+		let suspended = self.is_suspended(user_id).await;
+		self.db.userid_suspended.remove(user_id);
+
+		suspended
+	}
+
+	/// Check if account is suspended
+	#[inline]
+	pub async fn is_suspended(&self, user_id: &UserId) -> bool {
+		self.db.userid_suspended.get(user_id).await.is_ok()
+	}
+
+	/// Mark a deactivated account as erased (GDPR), so no further data about
+	/// the user is accepted. This cannot be undone.
+	pub fn mark_erased(&self, user_id: &UserId) {
+		self.db
+			.userid_erased
+			.raw_put(user_id, utils::millis_since_unix_epoch());
+	}
+
+	/// Check if account was erased
+	#[inline]
+	pub async fn is_erased(&self, user_id: &UserId) -> bool {
+		self.db.userid_erased.get(user_id).await.is_ok()
+	}
+
 	/// Returns the number of users registered on this server.
 	#[inline]
 	pub async fn count(&self) -> usize { self.db.userid_password.count().await }
@@ -193,6 +320,15 @@ impl Service {
 		self.db.userid_password.keys().ignore_err()
 	}
 
+	/// Returns an iterator over the users on this homeserver whose user ID
+	/// starts with the prefix, without scanning the others.
+	pub fn stream_prefix<'a>(
+		&'a self,
+		prefix: &'a str,
+	) -> impl Stream<Item = &UserId> + Send + 'a {
+		self.db.userid_password.keys_raw_prefix(prefix).ignore_err()
+	}
+
 	/// Returns a list of local users as list of usernames.
 	///
 	/// A user account is considered `local` if the length of it's password is
@@ -309,6 +445,9 @@ impl Service {
 			self.db.token_userdeviceid.remove(&old_token);
 		}
 
+		self.remove_refresh_token(user_id, device_id).await;
+		self.db.userdeviceid_adminloginexpiresat.del(userdeviceid);
+
 		// Remove todevice events
 		let prefix = (user_id, device_id, Interfix);
 		self.db
@@ -371,9 +510,136 @@ impl Service {
 		self.db.userdeviceid_token.put_raw(key, token);
 		self.db.token_userdeviceid.raw_put(token, key);
 
+		// The new token does not expire unless a refresh token is issued with it
+		self.remove_refresh_token(user_id, device_id).await;
+
 		Ok(())
 	}
 
+	/// Issues a refresh token for a device alongside its current access token,
+	/// which then expires after `refreshable_access_token_lifetime`. Returns
+	/// the access token's lifetime.
+	pub fn set_refresh_token(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		refresh_token: &str,
+	) -> Duration {
+		let key = (user_id, device_id);
+		let lifetime = Duration::from_secs(
+			self.services
+				.server
+				.config
+				.refreshable_access_token_lifetime,
+		);
+		let expires_at = utils::millis_since_unix_epoch()
+			.saturating_add(lifetime.as_millis().try_into().unwrap_or(u64::MAX));
+
+		self.db
+			.userdeviceid_refreshtoken
+			.put_raw(key, refresh_token);
+		self.db
+			.refreshtoken_userdeviceid
+			.raw_put(refresh_token, key);
+		self.db.userdeviceid_tokenexpiresat.put(key, expires_at);
+
+		lifetime
+	}
+
+	/// Find out which device a refresh token belongs to.
+	pub async fn find_from_refresh_token(
+		&self,
+		refresh_token: &str,
+	) -> Result<(OwnedUserId, OwnedDeviceId)> {
+		self.db
+			.refreshtoken_userdeviceid
+			.get(refresh_token)
+			.await
+			.deserialized()
+			.map_err(|_| {
+				Error::BadRequest(
+					ErrorKind::UnknownToken { soft_logout: false },
+					"Refresh token is unrecognised",
+				)
+			})
+	}
+
+	/// Whether the access token of a device was issued with a refresh token
+	/// and has expired.
+	pub async fn access_token_expired(&self, user_id: &UserId, device_id: &DeviceId) -> bool {
+		let key = (user_id, device_id);
+		self.db
+			.userdeviceid_tokenexpiresat
+			.qry(&key)
+			.await
+			.deserialized::<u64>()
+			.is_ok_and(|expires_at| expires_at <= utils::millis_since_unix_epoch())
+	}
+
+	/// Creates a device with an access token for an admin to act as a local
+	/// user, which expires after `lifetime` and cannot be refreshed. The
+	/// device is named after who issued it, so the user can see it among
+	/// their devices.
+	pub async fn create_admin_login(
+		&self,
+		user_id: &UserId,
+		issued_by: &str,
+		lifetime: Duration,
+	) -> Result<(OwnedDeviceId, String)> {
+		let device_id: OwnedDeviceId = utils::random_string(ADMIN_LOGIN_DEVICE_ID_LENGTH).into();
+		let token = utils::random_string(ADMIN_LOGIN_TOKEN_LENGTH);
+		let display_name = format!("Admin login by {issued_by}");
+
+		self.create_device(user_id, &device_id, &token, Some(display_name), None)
+			.await?;
+
+		let expires_at = utils::millis_since_unix_epoch()
+			.saturating_add(lifetime.as_millis().try_into().unwrap_or(u64::MAX));
+
+		let key = (user_id, &*device_id);
+		self.db.userdeviceid_tokenexpiresat.put(key, expires_at);
+		self.db
+			.userdeviceid_adminloginexpiresat
+			.put(key, expires_at);
+
+		Ok((device_id, token))
+	}
+
+	/// Removes the devices of admin logins whose token has expired, along with
+	/// the token.
+	pub async fn remove_expired_admin_logins(&self) {
+		type KeyVal<'a> = ((&'a UserId, &'a DeviceId), u64);
+
+		let now = utils::millis_since_unix_epoch();
+		let expired: Vec<(OwnedUserId, OwnedDeviceId)> = self
+			.db
+			.userdeviceid_adminloginexpiresat
+			.stream()
+			.ignore_err()
+			.ready_filter(|(_, expires_at): &KeyVal<'_>| *expires_at <= now)
+			.map(|((user_id, device_id), _)| (user_id.to_owned(), device_id.to_owned()))
+			.collect()
+			.await;
+
+		for (user_id, device_id) in &expired {
+			self.remove_device(user_id, device_id).await;
+		}
+
+		if !expired.is_empty() {
+			debug_info!(count = expired.len(), "Removed devices of expired admin logins");
+		}
+	}
+
+	async fn remove_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) {
+		let key = (user_id, device_id);
+		if let Ok(old_token) = self.db.userdeviceid_refreshtoken.qry(&key).await {
+			self.db.refreshtoken_userdeviceid.remove(&old_token);
+			self.db.userdeviceid_refreshtoken.del(key);
+		}
+
+		self.db.userdeviceid_tokenexpiresat.del(key);
+	}
+
 	pub async fn add_one_time_key(
 		&self,
 		user_id: &UserId,
@@ -535,6 +801,9 @@ impl Service {
 			.userid_masterkeyid
 			.insert(user_id.as_bytes(), &master_key_key);
 
+		// An allowance to reset the keys is used up once they are replaced
+		self.db.userid_crosssigningresetexpiresat.remove(user_id);
+
 		// Self-signing key
 		if let Some(self_signing_key) = self_signing_key {
 			let mut self_signing_key_ids = self_signing_key
@@ -728,6 +997,37 @@ impl Service {
 		Ok(Raw::from_json(raw_value))
 	}
 
+	/// Allows the user to replace their cross-signing keys without
+	/// user-interactive authentication for a short while, e.g. after they lost
+	/// all their devices. Returns when the allowance expires, in milliseconds
+	/// since the unix epoch.
+	pub fn allow_cross_signing_reset(&self, user_id: &UserId) -> u64 {
+		let expires_at = utils::millis_since_unix_epoch().saturating_add(
+			CROSS_SIGNING_RESET_WINDOW
+				.as_millis()
+				.try_into()
+				.unwrap_or(u64::MAX),
+		);
+
+		self.db
+			.userid_crosssigningresetexpiresat
+			.raw_put(user_id, expires_at);
+
+		expires_at
+	}
+
+	/// Whether an admin has allowed the user to replace their cross-signing
+	/// keys without user-interactive authentication and the allowance has not
+	/// expired or been used.
+	pub async fn cross_signing_reset_allowed(&self, user_id: &UserId) -> bool {
+		self.db
+			.userid_crosssigningresetexpiresat
+			.get(user_id)
+			.await
+			.deserialized::<u64>()
+			.is_ok_and(|expires_at| expires_at > utils::millis_since_unix_epoch())
+	}
+
 	pub async fn get_master_key<F>(
 		&self,
 		sender_user: Option<&UserId>,
@@ -801,12 +1101,18 @@ impl Service {
 			.map(|(_, val): (Ignore, Raw<AnyToDeviceEvent>)| val)
 	}
 
+	/// Removes the to-device events a device has received up to `until`. A
+	/// read-only replica leaves them for the primary to remove.
 	pub async fn remove_to_device_events(
 		&self,
 		user_id: &UserId,
 		device_id: &DeviceId,
 		until: u64,
 	) {
+		if self.services.globals.is_read_only() {
+			return;
+		}
+
 		let mut prefix = user_id.as_bytes().to_vec();
 		prefix.push(0xFF);
 		prefix.extend_from_slice(device_id.as_bytes());
@@ -868,6 +1174,15 @@ impl Service {
 			.deserialized()
 	}
 
+	/// When any of the user's devices was last seen, in milliseconds since the
+	/// unix epoch.
+	pub async fn last_seen(&self, user_id: &UserId) -> Option<u64> {
+		self.all_devices_metadata(user_id)
+			.ready_filter_map(|device| device.last_seen_ts)
+			.ready_fold(None, |last, ts| last.max(Some(ts.get().into())))
+			.await
+	}
+
 	pub fn all_devices_metadata<'a>(
 		&'a self,
 		user_id: &'a UserId,
@@ -1109,6 +1424,10 @@ where
 	Ok(cross_signing_key)
 }
 
+/// Deactivated accounts have their password set to "", which hashes never
+/// are.
+fn is_deactivated_password(password: &[u8]) -> bool { password.is_empty() }
+
 //TODO: this is an ABA
 fn increment(db: &Arc<Map>, key: &[u8]) {
 	let old = db.get_blocking(key);
@@ -0,0 +1,30 @@
+#![cfg(test)]
+
+use conduwuit::utils::hash;
+
+use super::{is_deactivated_password, EXTERNAL_PASSWORD};
+
+#[test]
+fn external_account_is_active() {
+	assert!(!is_deactivated_password(EXTERNAL_PASSWORD.as_bytes()));
+}
+
+#[test]
+fn deactivated_account_is_deactivated() {
+	assert!(is_deactivated_password(b""));
+}
+
+#[test]
+fn external_account_rejects_passwords() {
+	for password in ["", "external", "!external", EXTERNAL_PASSWORD] {
+		assert!(hash::verify_password(password, EXTERNAL_PASSWORD).is_err(), "{password:?}");
+	}
+}
+
+#[test]
+fn local_account_is_active() {
+	let hash = hash::password("hunter2").expect("password hashes");
+
+	assert!(!is_deactivated_password(hash.as_bytes()));
+	assert!(hash::verify_password("hunter2", &hash).is_ok());
+}
@@ -0,0 +1,95 @@
+#!/usr/bin/env bash
+#
+# Starts a primary and a read replica against the same database, then checks
+# that the replica serves /sync and /messages with lazy-loaded members, which
+# write to the database on a primary.
+#
+# Usage: tests/read_replica.sh [path to conduwuit binary]
+
+set -euo pipefail
+
+BIN="${1:-target/debug/conduwuit}"
+DIR="$(mktemp -d /tmp/uwu_replica.XXXXXX)"
+PRIMARY="http://127.0.0.1:8448"
+REPLICA="http://127.0.0.1:8449"
+
+cleanup () {
+	kill $(jobs -p) 2>/dev/null || true
+	wait 2>/dev/null || true
+	rm -rf "$DIR"
+}
+
+trap cleanup EXIT
+
+config () {
+	cat <<-EOF
+	[global]
+	server_name = "localhost"
+	database_path = "$DIR/db"
+	address = ["127.0.0.1"]
+	port = $1
+	allow_registration = true
+	yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse = true
+	allow_check_for_updates = false
+	EOF
+}
+
+wait_for () {
+	for _ in $(seq 60); do
+		curl -sf "$1/_matrix/client/versions" >/dev/null && return 0
+		sleep 1
+	done
+
+	echo "$1 did not start" >&2
+	exit 1
+}
+
+config 8448 > "$DIR/primary.toml"
+{
+	config 8449
+	echo "rocksdb_secondary = true"
+	echo "rocksdb_secondary_path = \"$DIR/secondary\""
+} > "$DIR/replica.toml"
+
+"$BIN" -c "$DIR/primary.toml" > "$DIR/primary.log" 2>&1 &
+wait_for "$PRIMARY"
+
+TOKEN=$(curl -sf -X POST "$PRIMARY/_matrix/client/v3/register" \
+	-H "Content-Type: application/json" \
+	-d '{"username": "replica", "password": "replica-test-password", "auth": {"type": "m.login.dummy"}}' |
+	jq -r .access_token)
+
+ROOM=$(curl -sf -X POST "$PRIMARY/_matrix/client/v3/createRoom" \
+	-H "Authorization: Bearer $TOKEN" -H "Content-Type: application/json" -d '{}' |
+	jq -r .room_id)
+
+curl -sf -X PUT "$PRIMARY/_matrix/client/v3/rooms/$ROOM/send/m.room.message/1" \
+	-H "Authorization: Bearer $TOKEN" -H "Content-Type: application/json" \
+	-d '{"msgtype": "m.text", "body": "hello"}' >/dev/null
+
+"$BIN" -c "$DIR/replica.toml" > "$DIR/replica.log" 2>&1 &
+wait_for "$REPLICA"
+
+FILTER=$(jq -rn '{room: {state: {lazy_load_members: true}, timeline: {lazy_load_members: true}}} | @uri')
+
+for _ in 1 2; do
+	SYNC=$(curl -sf "$REPLICA/_matrix/client/v3/sync?filter=$FILTER" -H "Authorization: Bearer $TOKEN")
+	echo "$SYNC" | jq -e --arg room "$ROOM" '.rooms.join[$room].timeline.events | any(.content.body == "hello")' >/dev/null
+done
+
+MESSAGES=$(curl -sf "$REPLICA/_matrix/client/v3/rooms/$ROOM/messages?dir=b&filter=$FILTER" \
+	-H "Authorization: Bearer $TOKEN")
+echo "$MESSAGES" | jq -e '.chunk | any(.content.body == "hello")' >/dev/null
+
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X PUT \
+	"$REPLICA/_matrix/client/v3/rooms/$ROOM/send/m.room.message/2" \
+	-H "Authorization: Bearer $TOKEN" -H "Content-Type: application/json" \
+	-d '{"msgtype": "m.text", "body": "refused"}')
+test "$STATUS" = 403
+
+if grep -q "panicked" "$DIR/replica.log"; then
+	cat "$DIR/replica.log" >&2
+	exit 1
+fi
+
+echo "PASS read replica"